@@ -18,6 +18,8 @@ pub enum AdmissionControlStatus {
     Blacklisted(String),
     /// The transaction is rejected, e.g. due to incorrect signature.
     Rejected(String),
+    /// The transaction was submitted before and has since been committed.
+    Committed,
 }
 
 impl TryFrom<crate::proto::admission_control::AdmissionControlStatus> for AdmissionControlStatus {
@@ -35,6 +37,7 @@ impl TryFrom<crate::proto::admission_control::AdmissionControlStatus> for Admiss
                 let msg = proto.message;
                 AdmissionControlStatus::Rejected(msg)
             }
+            ProtoStatusCode::Committed => AdmissionControlStatus::Committed,
         };
         Ok(ret)
     }
@@ -56,6 +59,9 @@ impl From<AdmissionControlStatus> for crate::proto::admission_control::Admission
                 admission_control_status.message = msg;
                 admission_control_status.set_code(ProtoStatusCode::Rejected)
             }
+            AdmissionControlStatus::Committed => {
+                admission_control_status.set_code(ProtoStatusCode::Committed)
+            }
         }
         admission_control_status
     }
@@ -64,7 +70,8 @@ impl From<AdmissionControlStatus> for crate::proto::admission_control::Admission
 /// Rust structure for SubmitTransactionResponse protobuf definition.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SubmitTransactionResponse {
-    /// AC status returned to client if any - it can be one of: accepted, blacklisted, or rejected.
+    /// AC status returned to client if any - it can be one of: accepted, blacklisted, rejected or
+    /// committed.
     pub ac_status: Option<AdmissionControlStatus>,
     /// Mempool error status if any.
     pub mempool_error: Option<MempoolAddTransactionStatus>,
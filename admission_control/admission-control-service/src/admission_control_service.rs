@@ -5,6 +5,7 @@
 //! from external clients (such as wallets) and performs necessary processing before sending them to
 //! next step.
 
+use crate::audit_log::AuditLog;
 use admission_control_proto::proto::admission_control::{
     AdmissionControl, SubmitTransactionRequest, SubmitTransactionResponse,
 };
@@ -31,6 +32,8 @@ pub struct AdmissionControlService {
     )>,
     /// gRPC client to send read requests to Storage.
     storage_read_client: Arc<dyn StorageRead>,
+    /// Audit log the submissions of clients are recorded to, if enabled.
+    audit_log: Option<AuditLog>,
 }
 
 impl AdmissionControlService {
@@ -41,10 +44,12 @@ impl AdmissionControlService {
             oneshot::Sender<failure::Result<SubmitTransactionResponse>>,
         )>,
         storage_read_client: Arc<dyn StorageRead>,
+        audit_log: Option<AuditLog>,
     ) -> Self {
         AdmissionControlService {
             ac_sender,
             storage_read_client,
+            audit_log,
         }
     }
 
@@ -85,6 +90,7 @@ impl AdmissionControl for AdmissionControlService {
         debug!("[GRPC] AdmissionControl::submit_transaction");
         let _timer = SVC_COUNTERS.req(&ctx);
 
+        let audited_req = self.audit_log.as_ref().map(|_| req.clone());
         let (req_sender, res_receiver) = oneshot::channel();
         let sent_result = block_on(self.ac_sender.send((req, req_sender)));
         let resp = match sent_result {
@@ -103,6 +109,9 @@ impl AdmissionControl for AdmissionControlService {
             )),
         };
 
+        if let (Some(audit_log), Some(req)) = (&self.audit_log, audited_req) {
+            audit_log.record(ctx.peer(), &req, &resp);
+        }
         provide_grpc_response(resp, ctx, sink);
     }
 
@@ -0,0 +1,286 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Durable record of the transaction submissions handled by admission control, accepted or not.
+//!
+//! Records are length delimited `SubmissionAuditRecord` protos followed by their checksum, see
+//! `libra_prost_ext::delimited`. They are appended to `audit.log` in the configured directory,
+//! which is renamed to `audit.log.1` once it reaches the size cap, the older files shifting to
+//! `audit.log.2` and so on up to the configured number of files.
+//!
+//! Submissions only queue their records, a dedicated thread writes them. Records submitted while
+//! the queue is full are dropped and counted, the submission path never waits on the disk.
+
+use crate::OP_COUNTERS;
+use admission_control_proto::proto::admission_control::{
+    SubmissionAuditRecord, SubmitTransactionRequest, SubmitTransactionResponse,
+};
+use failure::prelude::*;
+use libra_crypto::{hash::CryptoHash, HashValue};
+use libra_logger::prelude::*;
+use libra_prost_ext::delimited::{write_delimited_with_crc, DelimitedError, DelimitedIter};
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{SignedTransaction, Transaction},
+};
+use serde::Serialize;
+use std::{
+    convert::TryFrom,
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, SyncSender, TrySendError},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the audit log file being written, rotated files get a numbered extension.
+pub const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+
+enum AuditCommand {
+    Record {
+        timestamp: SystemTime,
+        source: String,
+        request: SubmitTransactionRequest,
+        result: std::result::Result<SubmitTransactionResponse, String>,
+    },
+    // answered once the commands queued before are written to disk
+    Flush(mpsc::Sender<()>),
+}
+
+/// Handle to the audit log, cheap to clone. The writer thread stops once every handle is dropped.
+#[derive(Clone)]
+pub struct AuditLog {
+    sender: SyncSender<AuditCommand>,
+}
+
+impl AuditLog {
+    /// Starts writing to `dir`, created if missing. An existing `audit.log` is appended to.
+    pub fn new(
+        dir: &Path,
+        max_file_size: u64,
+        max_files: usize,
+        queue_size: usize,
+    ) -> Result<Self> {
+        let writer = RotatingWriter::open(dir, max_file_size, max_files)?;
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
+        thread::Builder::new()
+            .name("ac-audit-log".to_string())
+            .spawn(move || writer.run(receiver))?;
+        Ok(Self { sender })
+    }
+
+    /// Queues the record of `request` being answered with `result`, received from `source`.
+    pub fn record(
+        &self,
+        source: String,
+        request: &SubmitTransactionRequest,
+        result: &Result<SubmitTransactionResponse>,
+    ) {
+        let command = AuditCommand::Record {
+            timestamp: SystemTime::now(),
+            source,
+            request: request.clone(),
+            result: match result {
+                Ok(response) => Ok(response.clone()),
+                Err(e) => Err(e.to_string()),
+            },
+        };
+        match self.sender.try_send(command) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => OP_COUNTERS.inc("audit_log.dropped"),
+            Err(TrySendError::Disconnected(_)) => {
+                OP_COUNTERS.inc("audit_log.dropped");
+                error!("[admission control] audit log writer stopped, dropping record");
+            }
+        }
+    }
+
+    /// Waits for the records queued so far to be written to disk.
+    pub fn flush(&self) -> Result<()> {
+        let (sender, receiver) = mpsc::channel();
+        self.sender
+            .send(AuditCommand::Flush(sender))
+            .map_err(|_| format_err!("Audit log writer stopped"))?;
+        receiver
+            .recv()
+            .map_err(|_| format_err!("Audit log writer stopped"))
+    }
+}
+
+/// Appends records to the current audit log file, rotating it once it is full.
+struct RotatingWriter {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    file: BufWriter<File>,
+    file_size: u64,
+}
+
+impl RotatingWriter {
+    fn open(dir: &Path, max_file_size: u64, max_files: usize) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let (file, file_size) = Self::open_current(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_file_size,
+            max_files,
+            file,
+            file_size,
+        })
+    }
+
+    fn open_current(dir: &Path) -> Result<(BufWriter<File>, u64)> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(AUDIT_LOG_FILE_NAME))?;
+        let file_size = file.metadata()?.len();
+        Ok((BufWriter::new(file), file_size))
+    }
+
+    fn run(mut self, receiver: Receiver<AuditCommand>) {
+        while let Ok(command) = receiver.recv() {
+            self.handle(command);
+            // records stay buffered only while more are queued
+            while let Ok(command) = receiver.try_recv() {
+                self.handle(command);
+            }
+            if let Err(e) = self.file.flush() {
+                error!("[admission control] failed to flush audit log: {}", e);
+            }
+        }
+    }
+
+    fn handle(&mut self, command: AuditCommand) {
+        match command {
+            AuditCommand::Record {
+                timestamp,
+                source,
+                request,
+                result,
+            } => {
+                let record = to_audit_record(timestamp, source, request, result);
+                if let Err(e) = self.write(&record) {
+                    OP_COUNTERS.inc("audit_log.write_failures");
+                    error!("[admission control] failed to write audit record: {}", e);
+                }
+            }
+            AuditCommand::Flush(callback) => {
+                if let Err(e) = self.file.flush() {
+                    error!("[admission control] failed to flush audit log: {}", e);
+                }
+                let _ = callback.send(());
+            }
+        }
+    }
+
+    fn write(&mut self, record: &SubmissionAuditRecord) -> Result<()> {
+        let mut buf = vec![];
+        write_delimited_with_crc(&mut buf, record)?;
+        // a record larger than the cap still gets a file of its own
+        if self.file_size > 0 && self.file_size + buf.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+        self.file.write_all(&buf)?;
+        self.file_size += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Shifts the rotated files by one, deleting the oldest, and starts a new current file.
+    fn rotate(&mut self) -> Result<()> {
+        self.file.flush()?;
+        let current_path = self.dir.join(AUDIT_LOG_FILE_NAME);
+        if self.max_files == 0 {
+            fs::remove_file(&current_path)?;
+        } else {
+            for i in (1..self.max_files).rev() {
+                let path = rotated_path(&self.dir, i);
+                if path.exists() {
+                    fs::rename(&path, rotated_path(&self.dir, i + 1))?;
+                }
+            }
+            fs::rename(&current_path, rotated_path(&self.dir, 1))?;
+        }
+        let (file, file_size) = Self::open_current(&self.dir)?;
+        self.file = file;
+        self.file_size = file_size;
+        Ok(())
+    }
+}
+
+/// Path of the `index`-th most recently rotated audit log file of `dir`.
+pub fn rotated_path(dir: &Path, index: usize) -> PathBuf {
+    dir.join(format!("{}.{}", AUDIT_LOG_FILE_NAME, index))
+}
+
+fn to_audit_record(
+    timestamp: SystemTime,
+    source: String,
+    request: SubmitTransactionRequest,
+    result: std::result::Result<SubmitTransactionResponse, String>,
+) -> SubmissionAuditRecord {
+    let mut record = SubmissionAuditRecord::default();
+    record.timestamp_usecs = timestamp
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_micros() as u64);
+    record.source = source;
+    if let Some(transaction) = request
+        .transaction
+        .and_then(|txn| SignedTransaction::try_from(txn).ok())
+    {
+        record.sender = transaction.sender().to_vec();
+        record.transaction_hash = Transaction::UserTransaction(transaction).hash().to_vec();
+    }
+    match result {
+        Ok(response) => record.response = Some(response),
+        Err(error) => record.error = error,
+    }
+    record
+}
+
+/// Iterates over the records of the audit log file at `path`.
+pub fn read_audit_log(
+    path: &Path,
+) -> Result<impl Iterator<Item = std::result::Result<SubmissionAuditRecord, DelimitedError>>> {
+    Ok(DelimitedIter::with_crc(BufReader::new(File::open(path)?)))
+}
+
+/// Human readable form of an audit record, e.g. to dump it as JSON.
+#[derive(Debug, Serialize)]
+pub struct AuditRecordView {
+    /// When the submission was answered, in microseconds since the Unix epoch.
+    pub timestamp_usecs: u64,
+    /// Address of the gRPC client or peer id of the node the submission came from.
+    pub source: String,
+    /// Sender of the transaction, if it could be decoded.
+    pub sender: Option<String>,
+    /// Hash of the transaction, if it could be decoded.
+    pub transaction_hash: Option<String>,
+    /// The response to the submission, if it did not fail with an error.
+    pub status: Option<String>,
+    /// The error the submission failed with, if any.
+    pub error: Option<String>,
+}
+
+impl From<SubmissionAuditRecord> for AuditRecordView {
+    fn from(record: SubmissionAuditRecord) -> Self {
+        Self {
+            timestamp_usecs: record.timestamp_usecs,
+            source: record.source,
+            sender: AccountAddress::try_from(&record.sender[..])
+                .ok()
+                .map(|sender| sender.to_string()),
+            transaction_hash: HashValue::from_slice(&record.transaction_hash)
+                .ok()
+                .map(|hash| format!("{:x}", hash)),
+            status: record.response.map(|response| {
+                match admission_control_proto::SubmitTransactionResponse::try_from(response) {
+                    Ok(response) => format!("{:?}", response),
+                    Err(e) => format!("undecodable response: {}", e),
+                }
+            }),
+            error: Some(record.error).filter(|error| !error.is_empty()),
+        }
+    }
+}
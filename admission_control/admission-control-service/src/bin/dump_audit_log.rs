@@ -0,0 +1,35 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use admission_control_service::audit_log::{read_audit_log, AuditRecordView};
+use std::{path::PathBuf, process};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Prints the records of an admission control audit log file as JSON lines")]
+struct Args {
+    #[structopt(parse(from_os_str))]
+    /// Audit log file, current or rotated
+    file: PathBuf,
+}
+
+fn main() {
+    let args = Args::from_args();
+    let records = read_audit_log(&args.file).unwrap_or_else(|e| {
+        eprintln!("Failed to open {}: {}", args.file.display(), e);
+        process::exit(1);
+    });
+    for record in records {
+        match record {
+            Ok(record) => println!(
+                "{}",
+                serde_json::to_string(&AuditRecordView::from(record))
+                    .expect("Audit records serialize to JSON")
+            ),
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", args.file.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+}
@@ -0,0 +1,73 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Short-lived memory of how full mempool was for recent submissions.
+//!
+//! Before running VM validation, AC asks mempool whether it has room for the transaction. A burst
+//! of submissions from one sender would otherwise issue that request for each of them, so the
+//! response is reused for a sender until it is a few ticks old. For a sequence number other than
+//! the one it was requested for, the response may count the transaction the new one would replace,
+//! so the sender is assumed to have one transaction fewer. The cache thus only ever rejects what
+//! mempool would have rejected when it answered.
+
+use libra_mempool::proto::mempool::GetMempoolCapacityResponse;
+use libra_types::account_address::AccountAddress;
+use lru_cache::LruCache;
+use std::time::{Duration, Instant};
+
+pub(crate) struct CapacityCache {
+    /// Capacity response for each sender, with the time it was received and the sequence number
+    /// it was requested for.
+    entries: LruCache<AccountAddress, (Instant, u64, GetMempoolCapacityResponse)>,
+    ttl: Duration,
+}
+
+impl CapacityCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+            ttl,
+        }
+    }
+
+    /// Returns the response remembered for `sender`, unless it is older than the TTL. If it was
+    /// requested for another sequence number, the sender's transaction count is lowered by one.
+    pub fn get(
+        &mut self,
+        sender: AccountAddress,
+        sequence_number: u64,
+        now: Instant,
+    ) -> Option<GetMempoolCapacityResponse> {
+        let expired = match self.entries.get_mut(&sender) {
+            Some((received_at, requested_sequence_number, response)) => {
+                if now.duration_since(*received_at) < self.ttl {
+                    let mut response = response.clone();
+                    if *requested_sequence_number != sequence_number {
+                        response.sender_num_transactions =
+                            response.sender_num_transactions.saturating_sub(1);
+                    }
+                    return Some(response);
+                }
+                true
+            }
+            None => false,
+        };
+        if expired {
+            self.entries.remove(&sender);
+        }
+        None
+    }
+
+    /// Remembers `response` for `sender`, as requested for `sequence_number`, replacing any
+    /// earlier one.
+    pub fn insert(
+        &mut self,
+        sender: AccountAddress,
+        sequence_number: u64,
+        response: GetMempoolCapacityResponse,
+        now: Instant,
+    ) {
+        self.entries
+            .insert(sender, (now, sequence_number, response));
+    }
+}
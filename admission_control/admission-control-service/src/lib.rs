@@ -15,16 +15,26 @@
 #[path = "unit_tests/admission_control_service_test.rs"]
 mod admission_control_service_test;
 
+#[cfg(test)]
+#[path = "unit_tests/audit_log_test.rs"]
+mod audit_log_test;
+
 #[cfg(feature = "fuzzing")]
 /// Fuzzer for admission control
 pub mod admission_control_fuzzing;
 /// AC gRPC service.
 pub mod admission_control_service;
+/// Audit log of transaction submissions
+pub mod audit_log;
+/// Recent mempool capacity responses, to check submissions against before validation
+mod capacity_cache;
 #[cfg(feature = "fuzzing")]
 /// Useful Mocks
 pub mod mocks;
 /// AC runtime to launch gRPC and network service
 pub mod runtime;
+/// Recently returned submission responses, to answer duplicate submissions
+mod submission_cache;
 /// Handler for sending transaction write requests upstream if needed
 mod upstream_proxy;
 use lazy_static::lazy_static;
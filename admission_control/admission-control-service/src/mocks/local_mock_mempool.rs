@@ -4,7 +4,8 @@
 use libra_mempool::proto::{
     mempool::{
         AddTransactionWithValidationRequest, AddTransactionWithValidationResponse,
-        HealthCheckRequest, HealthCheckResponse,
+        GetMempoolCapacityRequest, GetMempoolCapacityResponse, HealthCheckRequest,
+        HealthCheckResponse,
     },
     mempool_client::MempoolClientTrait,
 };
@@ -13,13 +14,18 @@ use libra_mempool_shared_proto::proto::mempool_status::{
 };
 use libra_types::{account_address::ADDRESS_LENGTH, transaction::SignedTransaction};
 use std::convert::TryFrom;
-use std::time::SystemTime;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use std::time::{Duration, SystemTime};
 
 /// Define a local mempool to use for unit tests and fuzzing,
 /// ignore methods not used
 #[derive(Clone)]
 pub struct LocalMockMempool {
     created_time: SystemTime,
+    capacity_calls: Arc<AtomicUsize>,
 }
 
 impl LocalMockMempool {
@@ -27,8 +33,14 @@ impl LocalMockMempool {
     pub fn new() -> Self {
         Self {
             created_time: SystemTime::now(),
+            capacity_calls: Arc::new(AtomicUsize::new(0)),
         }
     }
+
+    /// Number of capacity requests received so far, by this mock and its clones
+    pub fn capacity_calls(&self) -> usize {
+        self.capacity_calls.load(Ordering::SeqCst)
+    }
 }
 
 impl MempoolClientTrait for LocalMockMempool {
@@ -69,4 +81,32 @@ impl MempoolClientTrait for LocalMockMempool {
         ret.is_healthy = duration_ms > 500 || duration_ms < 300;
         Ok(ret)
     }
+
+    fn get_mempool_capacity(
+        &self,
+        req: &GetMempoolCapacityRequest,
+        _timeout: Duration,
+    ) -> ::grpcio::Result<GetMempoolCapacityResponse> {
+        self.capacity_calls.fetch_add(1, Ordering::SeqCst);
+        let mempool_full = [104_u8; ADDRESS_LENGTH];
+        let too_many_txns_add = [105_u8; ADDRESS_LENGTH];
+        let capacity_error_add = [106_u8; ADDRESS_LENGTH];
+        if req.address[..] == capacity_error_add {
+            return Err(::grpcio::Error::RpcFailure(::grpcio::RpcStatus::new(
+                ::grpcio::RpcStatusCode::DEADLINE_EXCEEDED,
+                None,
+            )));
+        }
+        let mut resp = GetMempoolCapacityResponse::default();
+        resp.capacity = 100;
+        resp.capacity_per_user = 10;
+        if req.address[..] == mempool_full {
+            resp.size = resp.capacity;
+            resp.is_full = true;
+        } else if req.address[..] == too_many_txns_add {
+            resp.size = resp.capacity_per_user;
+            resp.sender_num_transactions = resp.capacity_per_user;
+        }
+        Ok(resp)
+    }
 }
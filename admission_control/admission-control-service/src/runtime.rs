@@ -3,6 +3,7 @@
 
 use crate::{
     admission_control_service::AdmissionControlService,
+    audit_log::AuditLog,
     upstream_proxy::{process_network_messages, UpstreamProxyData},
 };
 use admission_control_proto::proto::admission_control::create_admission_control;
@@ -60,8 +61,17 @@ impl AdmissionControlRuntime {
             config.storage.port,
         ));
 
+        let audit_log = config.admission_control.audit_log_dir.as_ref().map(|dir| {
+            AuditLog::new(
+                dir,
+                config.admission_control.audit_log_max_file_size,
+                config.admission_control.audit_log_max_files,
+                config.admission_control.audit_log_queue_size,
+            )
+            .expect("[admission control] failed to open audit log")
+        });
         let admission_control_service =
-            AdmissionControlService::new(ac_sender, Arc::clone(&storage_client));
+            AdmissionControlService::new(ac_sender, Arc::clone(&storage_client), audit_log.clone());
 
         let vm_validator = Arc::new(VMValidator::new(&config, Arc::clone(&storage_client)));
 
@@ -85,7 +95,7 @@ impl AdmissionControlRuntime {
             .map(|peer_id| (*peer_id, true))
             .collect();
 
-        let upstream_proxy_data = UpstreamProxyData::new(
+        let mut upstream_proxy_data = UpstreamProxyData::new(
             config.admission_control.clone(),
             network_sender,
             config.get_role(),
@@ -96,6 +106,9 @@ impl AdmissionControlRuntime {
                 .admission_control
                 .need_to_check_mempool_before_validation,
         );
+        if let Some(audit_log) = audit_log {
+            upstream_proxy_data = upstream_proxy_data.with_audit_log(audit_log);
+        }
         executor.spawn(process_network_messages(
             upstream_proxy_data,
             network_events,
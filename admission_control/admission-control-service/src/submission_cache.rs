@@ -0,0 +1,60 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Short-lived memory of the responses given to recent transaction submissions.
+//!
+//! Clients commonly retry a submission when they time out waiting for the response. Looking up
+//! the hash of the signed transaction here lets AC answer such duplicates with the status it
+//! returned the first time, instead of running VM validation and mempool insertion again.
+
+use admission_control_proto::proto::admission_control::SubmitTransactionResponse;
+use libra_crypto::HashValue;
+use lru_cache::LruCache;
+use std::time::{Duration, Instant};
+
+pub(crate) struct SubmissionCache {
+    /// Response returned for each transaction hash, with the time it was first returned.
+    entries: LruCache<HashValue, (Instant, SubmitTransactionResponse)>,
+    ttl: Duration,
+}
+
+impl SubmissionCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+            ttl,
+        }
+    }
+
+    /// Returns the response remembered for `txn_hash`, unless it is older than the TTL.
+    pub fn get(&mut self, txn_hash: &HashValue, now: Instant) -> Option<SubmitTransactionResponse> {
+        let expired = match self.entries.get_mut(txn_hash) {
+            Some((inserted_at, response)) => {
+                if now.duration_since(*inserted_at) < self.ttl {
+                    return Some(response.clone());
+                }
+                true
+            }
+            None => false,
+        };
+        if expired {
+            self.entries.remove(txn_hash);
+        }
+        None
+    }
+
+    /// Remembers `response` for `txn_hash`. If an entry already exists, its response is replaced
+    /// but it keeps expiring relative to the first submission.
+    pub fn insert(
+        &mut self,
+        txn_hash: HashValue,
+        response: SubmitTransactionResponse,
+        now: Instant,
+    ) {
+        let inserted_at = match self.entries.get_mut(&txn_hash) {
+            Some((inserted_at, _)) => *inserted_at,
+            None => now,
+        };
+        self.entries.insert(txn_hash, (inserted_at, response));
+    }
+}
@@ -1,24 +1,36 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{mocks::local_mock_mempool::LocalMockMempool, upstream_proxy};
+use crate::{mocks::local_mock_mempool::LocalMockMempool, upstream_proxy, OP_COUNTERS};
 use admission_control_proto::proto::admission_control::{
     SubmitTransactionRequest, SubmitTransactionResponse as ProtoSubmitTransactionResponse,
 };
 use admission_control_proto::{AdmissionControlStatus, SubmitTransactionResponse};
-use futures::executor::block_on;
+use failure::prelude::*;
+use futures::{executor::block_on, prelude::*};
 use libra_config::config::{AdmissionControlConfig, RoleType};
-use libra_crypto::{ed25519::*, test_utils::TEST_SEED};
+use libra_crypto::{ed25519::*, test_utils::TEST_SEED, HashValue};
 use libra_mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
 use libra_types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
+    account_state_blob::AccountStateBlob,
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof},
+    get_with_proof::{RequestItem, ResponseItem},
+    proof::{AccumulatorConsistencyProof, AccumulatorProof, SparseMerkleProof, TransactionProof},
     test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::{
+        Script, SignedTransaction, Transaction, TransactionInfo, TransactionListWithProof,
+        TransactionWithProof, Version,
+    },
     vm_error::{StatusCode, VMStatus},
 };
 use network::validator_network::AdmissionControlNetworkSender;
 use rand::SeedableRng;
 use std::convert::TryFrom;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use storage_client::StorageRead;
+use storage_proto::StartupInfo;
 use storage_service::mocks::mock_storage_client::MockStorageReadClient;
 use vm_validator::mocks::mock_vm_validator::MockVMValidator;
 
@@ -278,3 +290,369 @@ fn test_submit_txn_inner_mempool() {
         MempoolAddTransactionStatusCode::MempoolIsFull,
     );
 }
+
+/// Storage that behaves like `MockStorageReadClient`, except that it reports the transactions in
+/// `committed` as committed when looked up by sender and sequence number.
+#[derive(Default)]
+struct CommittedTxnsStorage {
+    committed: Mutex<Vec<SignedTransaction>>,
+}
+
+impl StorageRead for CommittedTxnsStorage {
+    fn update_to_latest_ledger(
+        &self,
+        client_known_version: Version,
+        request_items: Vec<RequestItem>,
+    ) -> Result<(
+        Vec<ResponseItem>,
+        LedgerInfoWithSignatures,
+        ValidatorChangeEventWithProof,
+        AccumulatorConsistencyProof,
+    )> {
+        let (mut response_items, ledger_info, validator_change_events, consistency_proof) =
+            MockStorageReadClient
+                .update_to_latest_ledger(client_known_version, request_items.clone())?;
+        let committed = self.committed.lock().unwrap();
+        for (request_item, response_item) in request_items.iter().zip(response_items.iter_mut()) {
+            if let RequestItem::GetAccountTransactionBySequenceNumber {
+                account,
+                sequence_number,
+                ..
+            } = request_item
+            {
+                if let Some(txn) = committed.iter().find(|txn| {
+                    txn.sender() == *account && txn.sequence_number() == *sequence_number
+                }) {
+                    let transaction_info = TransactionInfo::new(
+                        HashValue::zero(),
+                        HashValue::zero(),
+                        HashValue::zero(),
+                        0,
+                        StatusCode::EXECUTED,
+                    );
+                    *response_item = ResponseItem::GetAccountTransactionBySequenceNumber {
+                        transaction_with_proof: Some(TransactionWithProof {
+                            version: 0,
+                            transaction: Transaction::UserTransaction(txn.clone()),
+                            events: None,
+                            proof: TransactionProof::new(
+                                AccumulatorProof::new(vec![]),
+                                transaction_info,
+                            ),
+                        }),
+                        proof_of_current_sequence_number: None,
+                    };
+                }
+            }
+        }
+        Ok((
+            response_items,
+            ledger_info,
+            validator_change_events,
+            consistency_proof,
+        ))
+    }
+
+    fn update_to_latest_ledger_async(
+        &self,
+        client_known_version: Version,
+        request_items: Vec<RequestItem>,
+    ) -> Pin<
+        Box<
+            dyn Future<
+                    Output = Result<(
+                        Vec<ResponseItem>,
+                        LedgerInfoWithSignatures,
+                        ValidatorChangeEventWithProof,
+                        AccumulatorConsistencyProof,
+                    )>,
+                > + Send,
+        >,
+    > {
+        futures::future::ready(self.update_to_latest_ledger(client_known_version, request_items))
+            .boxed()
+    }
+
+    fn get_transactions(
+        &self,
+        _start_version: Version,
+        _batch_size: u64,
+        _ledger_version: Version,
+        _fetch_events: bool,
+    ) -> Result<TransactionListWithProof> {
+        unimplemented!()
+    }
+
+    fn get_transactions_async(
+        &self,
+        _start_version: Version,
+        _batch_size: u64,
+        _ledger_version: Version,
+        _fetch_events: bool,
+    ) -> Pin<Box<dyn Future<Output = Result<TransactionListWithProof>> + Send>> {
+        unimplemented!()
+    }
+
+    fn get_account_state_with_proof_by_version(
+        &self,
+        _address: AccountAddress,
+        _version: Version,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        unimplemented!()
+    }
+
+    fn get_account_state_with_proof_by_version_async(
+        &self,
+        _address: AccountAddress,
+        _version: Version,
+    ) -> Pin<Box<dyn Future<Output = Result<(Option<AccountStateBlob>, SparseMerkleProof)>> + Send>>
+    {
+        unimplemented!()
+    }
+
+    fn get_startup_info(&self) -> Result<Option<StartupInfo>> {
+        unimplemented!()
+    }
+
+    fn get_startup_info_async(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<StartupInfo>>> + Send>> {
+        unimplemented!()
+    }
+
+    fn get_epoch_change_ledger_infos(
+        &self,
+        _start_epoch: u64,
+        _end_epoch: u64,
+    ) -> Result<Vec<LedgerInfoWithSignatures>> {
+        unimplemented!()
+    }
+
+    fn get_epoch_change_ledger_infos_async(
+        &self,
+        _start_epoch: u64,
+        _end_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>> {
+        unimplemented!()
+    }
+}
+
+#[test]
+fn test_resubmit_txn_before_and_after_commit() {
+    let mock_upstream_proxy_data = UpstreamProxyDataMock::new();
+    let storage = Arc::new(CommittedTxnsStorage::default());
+    let upstream_proxy_data = upstream_proxy::UpstreamProxyData::new(
+        mock_upstream_proxy_data.ac_config,
+        mock_upstream_proxy_data.network_sender,
+        mock_upstream_proxy_data.role,
+        mock_upstream_proxy_data.mempool_client,
+        storage.clone(),
+        mock_upstream_proxy_data.vm_validator,
+        mock_upstream_proxy_data.need_to_check_mempool_before_validation,
+    );
+    let submit = |req: &SubmitTransactionRequest| {
+        SubmitTransactionResponse::try_from(
+            block_on(upstream_proxy::submit_transaction_to_mempool(
+                upstream_proxy_data.clone(),
+                req.clone(),
+            ))
+            .unwrap(),
+        )
+        .unwrap()
+    };
+    let dedup_hits = OP_COUNTERS.counter("submit_txn.dedup_hit");
+    let committed_hits = OP_COUNTERS.counter("submit_txn.dedup_hit.committed");
+
+    let keypair = compat::generate_keypair(None);
+    let txn = get_test_signed_txn(
+        AccountAddress::new([103; ADDRESS_LENGTH]),
+        0,
+        keypair.0.clone(),
+        keypair.1.clone(),
+        None,
+    );
+    let mut req = SubmitTransactionRequest::default();
+    req.transaction = Some(txn.clone().into());
+    assert_eq!(
+        submit(&req).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(dedup_hits.get(), 0);
+
+    // Resubmitted while still pending, the prior status comes from the cache.
+    assert_eq!(
+        submit(&req).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(dedup_hits.get(), 1);
+    assert_eq!(committed_hits.get(), 0);
+
+    // Once it is committed, duplicates are told so.
+    storage.committed.lock().unwrap().push(txn);
+    assert_eq!(
+        submit(&req).ac_status.unwrap(),
+        AdmissionControlStatus::Committed
+    );
+    assert_eq!(dedup_hits.get(), 2);
+    assert_eq!(committed_hits.get(), 1);
+
+    // A different transaction of the same sender and sequence number is not a duplicate.
+    let other_txn = get_test_signed_txn(
+        AccountAddress::new([103; ADDRESS_LENGTH]),
+        0,
+        keypair.0.clone(),
+        keypair.1.clone(),
+        Some(Script::new(vec![1], vec![])),
+    );
+    req.transaction = Some(other_txn.into());
+    assert_eq!(
+        submit(&req).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(dedup_hits.get(), 2);
+}
+
+/// Upstream proxy data for a validator with `ac_config`, along with its mock mempool.
+fn capacity_checked_proxy_data(
+    ac_config: AdmissionControlConfig,
+) -> (
+    upstream_proxy::UpstreamProxyData<LocalMockMempool, MockVMValidator>,
+    Arc<LocalMockMempool>,
+) {
+    let mock_upstream_proxy_data = UpstreamProxyDataMock::new();
+    let mempool = Arc::new(LocalMockMempool::new());
+    let upstream_proxy_data = upstream_proxy::UpstreamProxyData::new(
+        ac_config,
+        mock_upstream_proxy_data.network_sender,
+        mock_upstream_proxy_data.role,
+        Some(mempool.clone()),
+        mock_upstream_proxy_data.storage_read_client,
+        mock_upstream_proxy_data.vm_validator,
+        mock_upstream_proxy_data.need_to_check_mempool_before_validation,
+    );
+    (upstream_proxy_data, mempool)
+}
+
+fn submit_from(
+    upstream_proxy_data: &upstream_proxy::UpstreamProxyData<LocalMockMempool, MockVMValidator>,
+    sender: u8,
+    sequence_number: u64,
+) -> SubmitTransactionResponse {
+    let keypair = compat::generate_keypair(None);
+    let mut req = SubmitTransactionRequest::default();
+    req.transaction = Some(
+        get_test_signed_txn(
+            AccountAddress::new([sender; ADDRESS_LENGTH]),
+            sequence_number,
+            keypair.0,
+            keypair.1,
+            None,
+        )
+        .into(),
+    );
+    SubmitTransactionResponse::try_from(
+        block_on(upstream_proxy::submit_transaction_to_mempool(
+            upstream_proxy_data.clone(),
+            req,
+        ))
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_capacity_check_rejects_before_validation() {
+    let (upstream_proxy_data, mempool) =
+        capacity_checked_proxy_data(AdmissionControlConfig::default());
+
+    // The mock mempool would accept these, so the rejections come from the capacity check.
+    assert_eq!(
+        submit_from(&upstream_proxy_data, 105, 0)
+            .mempool_error
+            .unwrap()
+            .code,
+        MempoolAddTransactionStatusCode::TooManyTransactions
+    );
+    assert_eq!(
+        submit_from(&upstream_proxy_data, 104, 0)
+            .mempool_error
+            .unwrap()
+            .code,
+        MempoolAddTransactionStatusCode::MempoolIsFull
+    );
+    assert_eq!(
+        submit_from(&upstream_proxy_data, 103, 0).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(mempool.capacity_calls(), 3);
+
+    // Without the check, the submission goes through validation and mempool as before.
+    let mut ac_config = AdmissionControlConfig::default();
+    ac_config.mempool_capacity_check = false;
+    let (upstream_proxy_data, mempool) = capacity_checked_proxy_data(ac_config);
+    assert_eq!(
+        submit_from(&upstream_proxy_data, 105, 0).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(mempool.capacity_calls(), 0);
+}
+
+#[test]
+fn test_capacity_check_cache() {
+    let mut ac_config = AdmissionControlConfig::default();
+    ac_config.mempool_capacity_cache_ttl = std::time::Duration::from_secs(3600);
+    let (upstream_proxy_data, mempool) = capacity_checked_proxy_data(ac_config.clone());
+
+    let rejected = |sender, sequence_number| {
+        submit_from(&upstream_proxy_data, sender, sequence_number)
+            .mempool_error
+            .map(|status| status.code)
+    };
+    assert_eq!(
+        rejected(105, 0),
+        Some(MempoolAddTransactionStatusCode::TooManyTransactions)
+    );
+    assert_eq!(mempool.capacity_calls(), 1);
+    // The same sender and sequence number reuse the answer.
+    assert_eq!(
+        rejected(105, 0),
+        Some(MempoolAddTransactionStatusCode::TooManyTransactions)
+    );
+    assert_eq!(mempool.capacity_calls(), 1);
+    // The next sequence number reuses it too, assuming the sender has one transaction fewer in
+    // case the counted ones include the one it would replace.
+    assert_eq!(rejected(105, 1), None);
+    assert_eq!(mempool.capacity_calls(), 1);
+    // A burst of consecutive sequence numbers from another sender asks mempool once.
+    for sequence_number in 0..5 {
+        assert_eq!(rejected(103, sequence_number), None);
+    }
+    assert_eq!(mempool.capacity_calls(), 2);
+
+    // Expired answers are not reused.
+    ac_config.mempool_capacity_cache_ttl = std::time::Duration::from_secs(0);
+    let (upstream_proxy_data, mempool) = capacity_checked_proxy_data(ac_config);
+    submit_from(&upstream_proxy_data, 105, 0);
+    submit_from(&upstream_proxy_data, 105, 0);
+    assert_eq!(mempool.capacity_calls(), 2);
+}
+
+#[test]
+fn test_capacity_check_failure_falls_back() {
+    let (upstream_proxy_data, mempool) =
+        capacity_checked_proxy_data(AdmissionControlConfig::default());
+    let failures = OP_COUNTERS.counter("submit_txn.capacity_check.failure");
+    let failures_before = failures.get();
+
+    // The capacity request fails for this sender, which the mock mempool then accepts.
+    assert_eq!(
+        submit_from(&upstream_proxy_data, 106, 0).ac_status.unwrap(),
+        AdmissionControlStatus::Accepted
+    );
+    assert_eq!(mempool.capacity_calls(), 1);
+    assert_eq!(failures.get(), failures_before + 1);
+
+    // Failures are not cached, the next submission asks again.
+    submit_from(&upstream_proxy_data, 106, 1);
+    assert_eq!(mempool.capacity_calls(), 2);
+}
@@ -0,0 +1,173 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    audit_log::{read_audit_log, rotated_path, AuditLog, AUDIT_LOG_FILE_NAME},
+    mocks::local_mock_mempool::LocalMockMempool,
+    upstream_proxy::{process_submit_transaction_request, UpstreamProxyData},
+};
+use admission_control_proto::proto::admission_control::{
+    SubmissionAuditRecord, SubmitTransactionRequest,
+};
+use admission_control_proto::{AdmissionControlStatus, SubmitTransactionResponse};
+use futures::{channel::oneshot, executor::block_on};
+use libra_config::config::{AdmissionControlConfig, RoleType};
+use libra_crypto::{ed25519::*, hash::CryptoHash};
+use libra_tools::tempdir::TempPath;
+use libra_types::{
+    account_address::{AccountAddress, ADDRESS_LENGTH},
+    proto::types::SignedTransaction as ProtoSignedTransaction,
+    test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::{SignedTransaction, Transaction},
+    vm_error::{StatusCode, VMStatus},
+};
+use network::validator_network::AdmissionControlNetworkSender;
+use std::{convert::TryFrom, path::Path, sync::Arc};
+use storage_service::mocks::mock_storage_client::MockStorageReadClient;
+use vm_validator::mocks::mock_vm_validator::MockVMValidator;
+
+fn read_records(path: &Path) -> Vec<SubmissionAuditRecord> {
+    read_audit_log(path)
+        .unwrap()
+        .map(|record| record.unwrap())
+        .collect()
+}
+
+fn signed_txn(sender: AccountAddress) -> SignedTransaction {
+    let keypair = compat::generate_keypair(None);
+    get_test_signed_txn(sender, 0, keypair.0, keypair.1, None)
+}
+
+fn submission_request(transaction: ProtoSignedTransaction) -> SubmitTransactionRequest {
+    let mut request = SubmitTransactionRequest::default();
+    request.transaction = Some(transaction);
+    request
+}
+
+#[test]
+fn test_audit_submissions() {
+    let dir = TempPath::new();
+    let audit_log = AuditLog::new(dir.path(), 1024 * 1024, 1, 100).unwrap();
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let upstream_proxy_data = UpstreamProxyData::new(
+        AdmissionControlConfig::default(),
+        AdmissionControlNetworkSender::new(network_reqs_tx),
+        RoleType::Validator,
+        Some(Arc::new(LocalMockMempool::new())),
+        Arc::new(MockStorageReadClient),
+        Arc::new(MockVMValidator),
+        false,
+    )
+    .with_audit_log(audit_log.clone());
+
+    let accepted_txn = signed_txn(AccountAddress::new([103; ADDRESS_LENGTH]));
+    // rejected by the mock VM validator
+    let rejected_txn = signed_txn(AccountAddress::new([1; ADDRESS_LENGTH]));
+    let mut undecodable_txn = ProtoSignedTransaction::default();
+    undecodable_txn.txn_bytes = vec![0xff; 3];
+    let source_peer_id = AccountAddress::random();
+    for request in vec![
+        submission_request(accepted_txn.clone().into()),
+        submission_request(rejected_txn.clone().into()),
+        submission_request(undecodable_txn),
+    ] {
+        let (callback, _response) = oneshot::channel();
+        block_on(process_submit_transaction_request(
+            upstream_proxy_data.clone(),
+            None,
+            source_peer_id,
+            request,
+            callback,
+        ));
+    }
+    audit_log.flush().unwrap();
+
+    let records = read_records(&dir.path().join(AUDIT_LOG_FILE_NAME));
+    assert_eq!(records.len(), 3);
+    for record in &records {
+        assert_eq!(record.source, source_peer_id.to_string());
+        assert!(record.timestamp_usecs > 0);
+        assert!(record.error.is_empty());
+    }
+    let status = |record: &SubmissionAuditRecord| {
+        SubmitTransactionResponse::try_from(record.response.clone().unwrap()).unwrap()
+    };
+
+    assert_eq!(records[0].sender, accepted_txn.sender().to_vec());
+    assert_eq!(
+        records[0].transaction_hash,
+        Transaction::UserTransaction(accepted_txn).hash().to_vec()
+    );
+    assert_eq!(
+        status(&records[0]).ac_status,
+        Some(AdmissionControlStatus::Accepted)
+    );
+
+    assert_eq!(records[1].sender, rejected_txn.sender().to_vec());
+    assert_eq!(
+        records[1].transaction_hash,
+        Transaction::UserTransaction(rejected_txn).hash().to_vec()
+    );
+    assert_eq!(
+        status(&records[1]).vm_error.unwrap().major_status,
+        VMStatus::new(StatusCode::INVALID_SIGNATURE).major_status
+    );
+
+    assert!(records[2].sender.is_empty());
+    assert!(records[2].transaction_hash.is_empty());
+    match status(&records[2]).ac_status {
+        Some(AdmissionControlStatus::Rejected(_)) => {}
+        status => panic!("Unexpected status: {:?}", status),
+    }
+}
+
+#[test]
+fn test_audit_log_rotation() {
+    let request = submission_request(signed_txn(AccountAddress::random()).into());
+    // every record has the same size, as the sources have the same length
+    let record = |audit_log: &AuditLog, i: usize| {
+        audit_log.record(
+            format!("{:02}", i),
+            &request,
+            &Err(failure::format_err!("error")),
+        )
+    };
+    let record_len = {
+        let dir = TempPath::new();
+        let audit_log = AuditLog::new(dir.path(), 1024 * 1024, 1, 100).unwrap();
+        record(&audit_log, 0);
+        audit_log.flush().unwrap();
+        std::fs::metadata(dir.path().join(AUDIT_LOG_FILE_NAME))
+            .unwrap()
+            .len()
+    };
+
+    // three records per file, two rotated files kept
+    let dir = TempPath::new();
+    let audit_log = AuditLog::new(dir.path(), 3 * record_len, 2, 100).unwrap();
+    for i in 0..20 {
+        record(&audit_log, i);
+    }
+    audit_log.flush().unwrap();
+
+    let sources = |path: &Path| -> Vec<String> {
+        read_records(path)
+            .into_iter()
+            .map(|record| record.source)
+            .collect()
+    };
+    assert_eq!(
+        sources(&dir.path().join(AUDIT_LOG_FILE_NAME)),
+        vec!["18", "19"]
+    );
+    assert_eq!(
+        sources(&rotated_path(dir.path(), 1)),
+        vec!["15", "16", "17"]
+    );
+    assert_eq!(
+        sources(&rotated_path(dir.path(), 2)),
+        vec!["12", "13", "14"]
+    );
+    assert!(!rotated_path(dir.path(), 3).exists());
+    assert_eq!(read_records(&rotated_path(dir.path(), 1))[0].error, "error");
+}
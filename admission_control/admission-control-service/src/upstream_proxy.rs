@@ -1,12 +1,15 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::audit_log::AuditLog;
+use crate::capacity_cache::CapacityCache;
+use crate::submission_cache::SubmissionCache;
 use crate::PeerId;
 use crate::OP_COUNTERS;
 use admission_control_proto::proto::admission_control::{
     admission_control_msg::Message as AdmissionControlMsg_oneof,
-    submit_transaction_response::Status, AdmissionControlMsg, SubmitTransactionRequest,
-    SubmitTransactionResponse,
+    submit_transaction_response::Status, AdmissionControlMsg, AdmissionControlStatusCode,
+    SubmitTransactionRequest, SubmitTransactionResponse,
 };
 use admission_control_proto::AdmissionControlStatus;
 use bounded_executor::BoundedExecutor;
@@ -18,24 +21,29 @@ use futures::{
     stream::{select_all, StreamExt},
 };
 use libra_config::config::{AdmissionControlConfig, RoleType};
+use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_logger::prelude::*;
 use libra_mempool::proto::{
-    mempool::{AddTransactionWithValidationRequest, HealthCheckRequest},
+    mempool::{AddTransactionWithValidationRequest, GetMempoolCapacityRequest, HealthCheckRequest},
     mempool_client::MempoolClientTrait,
 };
 use libra_mempool_shared_proto::proto::mempool_status::{
     MempoolAddTransactionStatus,
-    MempoolAddTransactionStatusCode::{self, MempoolIsFull},
+    MempoolAddTransactionStatusCode::{self, MempoolIsFull, TooManyTransactions},
 };
 use libra_prost_ext::MessageExt;
-use libra_types::transaction::SignedTransaction;
+use libra_types::{
+    get_with_proof::{RequestItem, ResponseItem},
+    transaction::{SignedTransaction, Transaction},
+};
 use network::validator_network::{
     AdmissionControlNetworkEvents, AdmissionControlNetworkSender, Event, RpcError,
 };
 use rand::seq::SliceRandom;
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use storage_client::StorageRead;
 use tokio::runtime::TaskExecutor;
 use vm_validator::vm_validator::{get_account_state, TransactionValidation};
@@ -57,6 +65,12 @@ pub struct UpstreamProxyData<M, V> {
     /// Flag indicating whether we need to check mempool before validation, drop txn if check
     /// fails.
     need_to_check_mempool_before_validation: bool,
+    /// Responses recently returned to submissions, keyed by transaction hash.
+    submission_cache: Arc<Mutex<SubmissionCache>>,
+    /// Mempool capacity recently reported for a sender.
+    capacity_cache: Arc<Mutex<CapacityCache>>,
+    /// Audit log the submissions received from other nodes are recorded to, if enabled.
+    audit_log: Option<AuditLog>,
 }
 
 impl<M: 'static, V> UpstreamProxyData<M, V>
@@ -74,6 +88,14 @@ where
         vm_validator: Arc<V>,
         need_to_check_mempool_before_validation: bool,
     ) -> Self {
+        let submission_cache = Arc::new(Mutex::new(SubmissionCache::new(
+            ac_config.submission_cache_size,
+            ac_config.submission_cache_ttl,
+        )));
+        let capacity_cache = Arc::new(Mutex::new(CapacityCache::new(
+            ac_config.mempool_capacity_cache_size,
+            ac_config.mempool_capacity_cache_ttl,
+        )));
         Self {
             ac_config,
             network_sender,
@@ -82,8 +104,17 @@ where
             storage_read_client,
             vm_validator,
             need_to_check_mempool_before_validation,
+            submission_cache,
+            capacity_cache,
+            audit_log: None,
         }
     }
+
+    /// Records the submissions received from other nodes to `audit_log`.
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Self {
+        self.audit_log = Some(audit_log);
+        self
+    }
 }
 
 /// Main routine for proxying write request. Starts a coordinator that listens for AdmissionControlMsg
@@ -125,11 +156,11 @@ pub async fn process_network_messages<M, V>(
                                 debug!("[admission control] lost peer {}", peer_id);
                                 lost_peer(&mut peer_info, peer_id);
                             }
-                            Event::RpcRequest((peer_id, mut message, callback)) => {
+                            Event::RpcRequest((source_peer_id, mut message, callback)) => {
                                 if let Some(AdmissionControlMsg_oneof::SubmitTransactionRequest(request)) = message.message {
                                     let peer_id = pick_peer(&peer_info);
                                     bounded_executor
-                                        .spawn(process_submit_transaction_request(upstream_proxy_data.clone(), peer_id, request, callback))
+                                        .spawn(process_submit_transaction_request(upstream_proxy_data.clone(), peer_id, source_peer_id, request, callback))
                                         .await;
                                 }
                             }
@@ -240,42 +271,34 @@ async fn submit_transaction_upstream<M, V>(
     Err(format_err!("[admission-control] No active upstream peers"))
 }
 
-async fn process_submit_transaction_request<M, V>(
+/// Handles a submission received from `source_peer_id`, forwarded to `peer_id` on full nodes.
+pub(crate) async fn process_submit_transaction_request<M, V>(
     mut upstream_proxy_data: UpstreamProxyData<M, V>,
     peer_id: Option<PeerId>,
+    source_peer_id: PeerId,
     request: SubmitTransactionRequest,
     callback: oneshot::Sender<Result<Bytes, RpcError>>,
 ) where
     M: MempoolClientTrait,
     V: TransactionValidation,
 {
-    let mut response_msg = None;
-    match upstream_proxy_data.role {
-        RoleType::Validator => {
-            if let Ok(response) = submit_transaction_to_mempool(upstream_proxy_data, request).await
-            {
-                let ac_control_msg = AdmissionControlMsg {
-                    message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
-                        response,
-                    )),
-                };
-                response_msg = Some(ac_control_msg);
-            }
-        }
+    let audit_log = upstream_proxy_data.audit_log.clone();
+    let audited_request = audit_log.as_ref().map(|_| request.clone());
+    let result = match upstream_proxy_data.role {
+        RoleType::Validator => submit_transaction_to_mempool(upstream_proxy_data, request).await,
         RoleType::FullNode => {
             // node is not a validator, so send the transaction to upstream AC via networking stack
-            if let Ok(response) =
-                submit_transaction_upstream(request, &mut upstream_proxy_data, peer_id).await
-            {
-                let ac_control_msg = AdmissionControlMsg {
-                    message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
-                        response,
-                    )),
-                };
-                response_msg = Some(ac_control_msg);
-            }
+            submit_transaction_upstream(request, &mut upstream_proxy_data, peer_id).await
         }
     };
+    if let (Some(audit_log), Some(request)) = (audit_log, audited_request) {
+        audit_log.record(source_peer_id.to_string(), &request, &result);
+    }
+    let response_msg = result.ok().map(|response| AdmissionControlMsg {
+        message: Some(AdmissionControlMsg_oneof::SubmitTransactionResponse(
+            response,
+        )),
+    });
     if let Some(response_msg) = response_msg {
         let response_data = response_msg.to_bytes().expect("fail to serialize proto");
         if let Err(err) = callback
@@ -295,6 +318,8 @@ async fn process_submit_transaction_request<M, V>(
 }
 
 /// Validate transaction signature, then via VM, and add it to Mempool if it passes VM check.
+/// A transaction submitted again within the submission cache TTL gets the status returned the
+/// first time, or `Committed` if it has made it into the ledger since.
 pub(crate) async fn submit_transaction_to_mempool<M, V>(
     upstream_proxy_data: UpstreamProxyData<M, V>,
     req: SubmitTransactionRequest,
@@ -303,19 +328,6 @@ where
     M: MempoolClientTrait,
     V: TransactionValidation,
 {
-    // Drop requests first if mempool is full (validator is lagging behind) so not to consume
-    // unnecessary resources.
-    if !can_send_txn_to_mempool(&upstream_proxy_data)? {
-        debug!("Mempool is full");
-        OP_COUNTERS.inc_by("submit_txn.rejected.mempool_full", 1);
-        let mut response = SubmitTransactionResponse::default();
-        let mut status = MempoolAddTransactionStatus::default();
-        status.set_code(MempoolIsFull);
-        status.message = "Mempool is full".to_string();
-        response.status = Some(Status::MempoolStatus(status));
-        return Ok(response);
-    }
-
     let txn_proto = req.transaction.clone().unwrap_or_else(Default::default);
 
     let transaction = match SignedTransaction::try_from(txn_proto.clone()) {
@@ -334,6 +346,43 @@ where
         }
     };
 
+    let txn_hash = Transaction::UserTransaction(transaction.clone()).hash();
+    if let Some(response) = get_cached_submission(
+        &upstream_proxy_data.submission_cache,
+        upstream_proxy_data.storage_read_client.clone(),
+        txn_hash,
+        &transaction,
+    )
+    .await
+    {
+        return Ok(response);
+    }
+
+    // Drop requests first if mempool is full (validator is lagging behind) so not to consume
+    // unnecessary resources.
+    if !can_send_txn_to_mempool(&upstream_proxy_data)? {
+        debug!("Mempool is full");
+        OP_COUNTERS.inc_by("submit_txn.rejected.mempool_full", 1);
+        let mut response = SubmitTransactionResponse::default();
+        let mut status = MempoolAddTransactionStatus::default();
+        status.set_code(MempoolIsFull);
+        status.message = "Mempool is full".to_string();
+        response.status = Some(Status::MempoolStatus(status));
+        return Ok(response);
+    }
+
+    // Likewise, drop the transaction before validation if mempool has no room for it.
+    if let Some(status) = check_mempool_capacity(&upstream_proxy_data, &transaction) {
+        debug!(
+            "txn rejected by mempool capacity check, status: {:?}, txn: {:?}",
+            status, transaction
+        );
+        OP_COUNTERS.inc_by("submit_txn.rejected.capacity_check", 1);
+        let mut response = SubmitTransactionResponse::default();
+        response.status = Some(Status::MempoolStatus(status));
+        return Ok(response);
+    }
+
     let gas_cost = transaction.max_gas_amount();
     let validation_status = upstream_proxy_data
         .vm_validator
@@ -348,7 +397,7 @@ where
             e
         })?;
 
-    if let Some(validation_status) = validation_status {
+    let response = if let Some(validation_status) = validation_status {
         let mut response = SubmitTransactionResponse::default();
         OP_COUNTERS.inc_by("submit_txn.vm_validation.failure", 1);
         debug!(
@@ -356,21 +405,102 @@ where
             validation_status, transaction
         );
         response.status = Some(Status::VmStatus(validation_status.into()));
-        return Ok(response);
+        response
+    } else {
+        let sender = transaction.sender();
+        let account_state =
+            get_account_state(upstream_proxy_data.storage_read_client.clone(), sender).await;
+        let mut add_transaction_request = AddTransactionWithValidationRequest::default();
+        add_transaction_request.transaction = req.transaction.clone();
+        add_transaction_request.max_gas_cost = gas_cost;
+
+        if let Ok((sequence_number, balance)) = account_state {
+            add_transaction_request.account_balance = balance;
+            add_transaction_request.latest_sequence_number = sequence_number;
+        }
+
+        add_txn_to_mempool(&upstream_proxy_data, add_transaction_request)?
+    };
+
+    // A full mempool says nothing about the transaction itself, so let a retry go through.
+    let is_mempool_full = match &response.status {
+        Some(Status::MempoolStatus(status)) => status.code() == MempoolIsFull,
+        _ => false,
+    };
+    if !is_mempool_full {
+        upstream_proxy_data.submission_cache.lock().unwrap().insert(
+            txn_hash,
+            response.clone(),
+            Instant::now(),
+        );
     }
-    let sender = transaction.sender();
-    let account_state =
-        get_account_state(upstream_proxy_data.storage_read_client.clone(), sender).await;
-    let mut add_transaction_request = AddTransactionWithValidationRequest::default();
-    add_transaction_request.transaction = req.transaction.clone();
-    add_transaction_request.max_gas_cost = gas_cost;
+    Ok(response)
+}
 
-    if let Ok((sequence_number, balance)) = account_state {
-        add_transaction_request.account_balance = balance;
-        add_transaction_request.latest_sequence_number = sequence_number;
+/// Returns the response remembered for a duplicate submission of `transaction`, upgraded to
+/// `Committed` if an accepted transaction has been committed since.
+async fn get_cached_submission(
+    submission_cache: &Mutex<SubmissionCache>,
+    storage_read_client: Arc<dyn StorageRead>,
+    txn_hash: HashValue,
+    transaction: &SignedTransaction,
+) -> Option<SubmitTransactionResponse> {
+    let cached_response = submission_cache
+        .lock()
+        .unwrap()
+        .get(&txn_hash, Instant::now())?;
+    OP_COUNTERS.inc_by("submit_txn.dedup_hit", 1);
+    debug!("Duplicate submission of txn {}", txn_hash);
+
+    let was_accepted = match &cached_response.status {
+        Some(Status::AcStatus(status)) => status.code() == AdmissionControlStatusCode::Accepted,
+        _ => false,
+    };
+    if was_accepted && is_committed(storage_read_client, transaction).await {
+        OP_COUNTERS.inc_by("submit_txn.dedup_hit.committed", 1);
+        let mut response = SubmitTransactionResponse::default();
+        response.status = Some(Status::AcStatus(AdmissionControlStatus::Committed.into()));
+        submission_cache
+            .lock()
+            .unwrap()
+            .insert(txn_hash, response.clone(), Instant::now());
+        return Some(response);
     }
+    Some(cached_response)
+}
 
-    add_txn_to_mempool(&upstream_proxy_data, add_transaction_request)
+/// Checks whether `transaction` is the one committed at its sender and sequence number.
+async fn is_committed(
+    storage_read_client: Arc<dyn StorageRead>,
+    transaction: &SignedTransaction,
+) -> bool {
+    let req_item = RequestItem::GetAccountTransactionBySequenceNumber {
+        account: transaction.sender(),
+        sequence_number: transaction.sequence_number(),
+        fetch_events: false,
+    };
+    match storage_read_client
+        .update_to_latest_ledger_async(0 /* client_known_version */, vec![req_item])
+        .await
+    {
+        Ok((response_items, _, _, _)) => match response_items.into_iter().next() {
+            Some(ResponseItem::GetAccountTransactionBySequenceNumber {
+                transaction_with_proof: Some(transaction_with_proof),
+                ..
+            }) => {
+                transaction_with_proof.transaction
+                    == Transaction::UserTransaction(transaction.clone())
+            }
+            _ => false,
+        },
+        Err(e) => {
+            error!(
+                "[admission control] failed to look up committed transaction: {:?}",
+                e
+            );
+            false
+        }
+    }
 }
 
 fn can_send_txn_to_mempool<M, V>(
@@ -390,6 +520,76 @@ where
     Ok(true)
 }
 
+/// Asks mempool whether it has room for `transaction`, reusing a recent answer for the same
+/// sender. Returns the status mempool would reject the transaction with, or `None` if it has room.
+/// Failing to get an answer within the deadline is not a reason to reject the transaction, so it
+/// also returns `None`.
+fn check_mempool_capacity<M, V>(
+    upstream_proxy_data: &UpstreamProxyData<M, V>,
+    transaction: &SignedTransaction,
+) -> Option<MempoolAddTransactionStatus>
+where
+    M: MempoolClientTrait,
+{
+    if !upstream_proxy_data.ac_config.mempool_capacity_check {
+        return None;
+    }
+    let mempool_client = upstream_proxy_data.mempool_client.as_ref()?;
+    let sender = transaction.sender();
+    let sequence_number = transaction.sequence_number();
+
+    let cached = upstream_proxy_data.capacity_cache.lock().unwrap().get(
+        sender,
+        sequence_number,
+        Instant::now(),
+    );
+    let capacity = match cached {
+        Some(capacity) => capacity,
+        None => {
+            let mut req = GetMempoolCapacityRequest::default();
+            req.address = sender.as_ref().to_vec();
+            req.sequence_number = sequence_number;
+            match mempool_client.get_mempool_capacity(
+                &req,
+                upstream_proxy_data.ac_config.mempool_capacity_check_timeout,
+            ) {
+                Ok(capacity) => {
+                    upstream_proxy_data.capacity_cache.lock().unwrap().insert(
+                        sender,
+                        sequence_number,
+                        capacity.clone(),
+                        Instant::now(),
+                    );
+                    capacity
+                }
+                Err(e) => {
+                    OP_COUNTERS.inc_by("submit_txn.capacity_check.failure", 1);
+                    debug!("[admission control] mempool capacity check failed: {:?}", e);
+                    return None;
+                }
+            }
+        }
+    };
+
+    let mut status = MempoolAddTransactionStatus::default();
+    if capacity.is_full {
+        status.set_code(MempoolIsFull);
+        status.message = format!(
+            "mempool size: {}, capacity: {}",
+            capacity.size, capacity.capacity
+        );
+    } else if capacity.sender_num_transactions >= capacity.capacity_per_user {
+        status.set_code(TooManyTransactions);
+        status.message = format!(
+            "txns length: {} capacity per user: {}",
+            capacity.sender_num_transactions, capacity.capacity_per_user
+        );
+    } else {
+        return None;
+    }
+    Some(status)
+}
+
 /// Add signed transaction to mempool once it passes vm check
 fn add_txn_to_mempool<M, V>(
     upstream_proxy_data: &UpstreamProxyData<M, V>,
@@ -2,9 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use benchmark::{
-    bin_utils::{create_benchmarker_from_opt, measure_throughput, try_start_metrics_server},
+    bin_utils::{
+        create_benchmarker_from_opt, measure_throughput, measure_workload_throughput,
+        try_start_metrics_server,
+    },
     cli_opt::{RubenOpt, TransactionPattern},
     load_generator::{LoadGenerator, PairwiseTransferTxnGenerator, RingTransferTxnGenerator},
+    workload::{AccountPool, MixedWorkload},
 };
 /// To run benchmarking experiment, RuBen creates two key required components:
 /// * An object that implements LoadGenerator trait, which generates accounts and offline
@@ -40,6 +44,23 @@ fn main() {
     try_start_metrics_server(&args.bench_opt);
     let mut bm = create_benchmarker_from_opt(&args.bench_opt);
     let mut faucet_account = bm.load_faucet_account(&args.bench_opt.faucet_key_file_path);
+    if let Some(spec) = &args.workload {
+        let workload = MixedWorkload::with_default_generators(
+            spec,
+            args.module_fixtures_dir.as_ref().map(String::as_str),
+        )
+        .expect("invalid workload");
+        let mut pool = AccountPool::new(faucet_account);
+        measure_workload_throughput(
+            &mut bm,
+            &workload,
+            &mut pool,
+            args.num_accounts,
+            (args.num_accounts * args.num_rounds) as usize,
+            args.num_epochs,
+        );
+        return;
+    }
     let mut generator: Box<dyn LoadGenerator> = match args.txn_pattern {
         TransactionPattern::Ring => Box::new(RingTransferTxnGenerator::new()),
         TransactionPattern::Pairwise => Box::new(PairwiseTransferTxnGenerator::new()),
@@ -3,7 +3,8 @@
 
 use crate::{
     cli_opt::BenchOpt,
-    load_generator::{gen_repeated_requests, LoadGenerator},
+    load_generator::{gen_mint_txn_requests, gen_repeated_requests, LoadGenerator},
+    workload::{AccountPool, MixedWorkload, WorkloadReport},
     BenchSummary, Benchmarker,
 };
 use admission_control_proto::proto::admission_control::AdmissionControlClient;
@@ -122,6 +123,45 @@ pub fn measure_throughput<T: LoadGenerator + ?Sized>(
     results
 }
 
+/// Play a mixed workload for several epochs and report latency and throughput by transaction
+/// type. First `num_accounts` senders are added to the pool and minted. Then each epoch
+/// generates `num_requests` TXNs, submits them, waits for them to commit, and admits the
+/// accounts created meanwhile as senders of the next epochs.
+/// Return the reports of all epochs.
+pub fn measure_workload_throughput(
+    bm: &mut Benchmarker,
+    workload: &MixedWorkload,
+    pool: &mut AccountPool,
+    num_accounts: u64,
+    num_requests: usize,
+    num_epochs: u64,
+) -> Vec<WorkloadReport> {
+    pool.gen_accounts(num_accounts);
+    bm.register_accounts(pool.accounts());
+    let (faucet_account, senders) = pool.faucet_and_senders_mut();
+    let mint_requests = gen_mint_txn_requests(faucet_account, senders);
+    bm.mint_accounts(&mint_requests, faucet_account);
+
+    let mut rng = rand::thread_rng();
+    let mut reports = vec![];
+    for _ in 0..num_epochs {
+        let requests = workload.gen_requests(pool, num_requests, &mut rng);
+        let report = bm.submit_workload(&requests);
+        let (num_committed, _) = bm.wait_and_check_txns_committed(pool.accounts_mut());
+        let created_accounts = pool.admit_created();
+        bm.register_accounts(created_accounts);
+        info!(
+            "{} of {} TXNs committed, {} accounts created.",
+            num_committed,
+            requests.len(),
+            created_accounts.len(),
+        );
+        report.publish();
+        reports.push(report);
+    }
+    reports
+}
+
 /// Generate TXNs, submit them at constant submission rate, and measure TXN throughput.
 /// Run this process for several epochs.
 /// Aggregate each epoch's running result into a single BenchSummary and return it.
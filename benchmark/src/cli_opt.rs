@@ -1,6 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::workload::WorkloadSpec;
 use failure::prelude::*;
 use libra_config::config::{NodeConfig, PersistableConfig};
 use libra_logger::prelude::*;
@@ -86,6 +87,14 @@ pub struct RubenOpt {
         default_value = "Ring"
     )]
     pub txn_pattern: TransactionPattern,
+    /// Mixed workload to play instead of txn_pattern, as comma separated `type=weight` pairs
+    /// over transfer, create_account, mint, rotate_key and publish_module,
+    /// e.g. `transfer=80,create_account=10,mint=10`. Each round plays num_accounts TXNs.
+    #[structopt(short = "w", long)]
+    pub workload: Option<WorkloadSpec>,
+    /// Directory of compiled modules (*.mv files) published by publish_module TXNs.
+    #[structopt(long)]
+    pub module_fixtures_dir: Option<String>,
 }
 
 /// CLI options for linear search max throughput.
@@ -36,7 +36,7 @@ const QUERY_SEQUENCE_NUMBERS_INTERVAL_MS: u64 = 50;
 
 /// Return a parameter that controls how "patient" AC clients are,
 /// who are waiting the response from AC for this amount of time.
-fn get_default_grpc_call_option() -> CallOption {
+pub(crate) fn get_default_grpc_call_option() -> CallOption {
     CallOption::default()
         .wait_for_ready(true)
         .timeout(std::time::Duration::from_millis(GRPC_TIMEOUT_MS))
@@ -58,7 +58,7 @@ pub fn divide_items<T>(items: &[T], num_chunks: usize) -> Chunks<T> {
 
 /// By checking 1) ac status, 2) vm status, and 3) mempool status, decide whether the reponse
 /// from AC is accepted. If not, classify what the error type is.
-fn check_ac_response(resp: &ProtoSubmitTransactionResponse) -> bool {
+pub(crate) fn check_ac_response(resp: &ProtoSubmitTransactionResponse) -> bool {
     use admission_control_proto::proto::admission_control::submit_transaction_response::Status::*;
 
     match &resp.status {
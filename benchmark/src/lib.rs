@@ -20,11 +20,13 @@ pub mod cli_opt;
 pub mod grpc_helpers;
 pub mod load_generator;
 pub mod submit_rate;
+pub mod workload;
 
 use grpc_helpers::{
     divide_items, get_account_states, submit_and_wait_requests, sync_account_sequence_number,
 };
 use load_generator::Request;
+use workload::{submit_workload, TxnType, WorkloadReport};
 
 lazy_static! {
     pub static ref OP_COUNTER: OpMetrics = OpMetrics::new_and_registered("benchmark");
@@ -269,6 +271,29 @@ impl Benchmarker {
         (txn_resps.len(), request_duration_ms)
     }
 
+    /// Send the TXNs of a mixed workload to AC, each client submitting its share one at a time
+    /// at the submission rate. Return the latency and throughput by transaction type.
+    pub fn submit_workload(&self, requests: &[(TxnType, Request)]) -> WorkloadReport {
+        let req_chunks = divide_items(requests, self.clients.len());
+        let children: Vec<thread::JoinHandle<_>> = req_chunks
+            .zip(self.clients.iter().cycle())
+            .map(|(chunk, client)| {
+                let local_chunk = Vec::from(chunk);
+                let local_client = Arc::clone(client);
+                let submit_rate = self.submit_rate;
+                thread::spawn(move || -> WorkloadReport {
+                    submit_workload(local_client.as_ref(), local_chunk, submit_rate)
+                })
+            })
+            .collect();
+        let mut report = WorkloadReport::default();
+        for child in children {
+            let chunk_report = child.join().expect("failed to join a request thread");
+            report.merge(&chunk_report);
+        }
+        report
+    }
+
     /// Wait for accepted TXNs to commit or time out: for any account, if its sequence number
     /// (bumpped during TXN generation) equals the one synchronized from validator,
     /// denoted as sync sequence number, then all its TXNs are committed.
@@ -369,6 +394,14 @@ impl Benchmarker {
         (committed_txns_usize, uncommitted_txns_usize)
     }
 
+    /// Wait for the TXNs of senders to commit or time out, and check how many are committed.
+    /// Return (#committed TXNs, wait duration).
+    pub fn wait_and_check_txns_committed(&mut self, senders: &mut [AccountData]) -> (usize, u128) {
+        let (sync_sequence_numbers, wait_duration_ms) = self.wait_txns_committed(senders);
+        let (num_committed, _) = self.check_txn_results(senders, &sync_sequence_numbers);
+        (num_committed, wait_duration_ms)
+    }
+
     /// Implement the general way to submit requests to Libra and then
     /// wait for all accepted TXNs to become committed.
     /// Return (#accepted TXNs, #committed TXNs, submit duration, wait duration).
@@ -380,8 +413,7 @@ impl Benchmarker {
     ) -> BenchSummary {
         let rate = submit_rate.unwrap_or(self.submit_rate);
         let (num_accepted, submit_duration_ms) = self.submit_requests(requests, rate);
-        let (sync_sequence_numbers, wait_duration_ms) = self.wait_txns_committed(senders);
-        let (num_committed, _) = self.check_txn_results(senders, &sync_sequence_numbers);
+        let (num_committed, wait_duration_ms) = self.wait_and_check_txns_committed(senders);
         BenchSummary {
             num_submitted: requests.len(),
             num_accepted,
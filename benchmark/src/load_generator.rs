@@ -90,12 +90,21 @@ fn gen_submit_transaction_request<T: TransactionSigner>(
     program: Script,
     sender_account: &mut AccountData,
     signer: &T,
+) -> Result<Request> {
+    gen_submit_payload_request(TransactionPayload::Script(program), sender_account, signer)
+}
+
+/// Craft a signed transaction request with any payload, e.g. a module to publish.
+pub(crate) fn gen_submit_payload_request<T: TransactionSigner>(
+    payload: TransactionPayload,
+    sender_account: &mut AccountData,
+    signer: &T,
 ) -> Result<Request> {
     // If generation fails here, sequence number will not be increased,
     // so it is fine to continue later generation.
     let transaction = create_user_txn(
         signer,
-        TransactionPayload::Script(program),
+        payload,
         sender_account.address,
         sender_account.sequence_number,
         MAX_GAS_AMOUNT,
@@ -0,0 +1,765 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+/// ----------------------------------------------------------------------------------- ///
+///  Mixed workloads: a distribution over transaction types, each type generated by its  ///
+///  own WorkloadGenerator, with latency and throughput reported per type.               ///
+/// ----------------------------------------------------------------------------------- ///
+use crate::{
+    grpc_helpers::{check_ac_response, get_default_grpc_call_option},
+    load_generator::{gen_accounts_from_wallet, gen_submit_payload_request, Request},
+    submit_rate::ConstantRate,
+    OP_COUNTER,
+};
+use admission_control_proto::proto::admission_control::AdmissionControlClient;
+use client::AccountData;
+use failure::prelude::*;
+use libra_logger::prelude::*;
+use libra_types::{
+    account_address::AccountAddress,
+    transaction::{Module, TransactionPayload},
+};
+use libra_wallet::wallet_library::WalletLibrary;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng, RngCore,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+/// Amount of coins moved by generated transfers.
+const TRANSFER_AMOUNT: u64 = 1;
+/// Initial balance of the accounts created by create-account TXNs.
+const CREATE_ACCOUNT_BALANCE: u64 = 10;
+/// Amount of coins minted by generated mint TXNs.
+const MINT_AMOUNT: u64 = 1_000;
+
+/// Transaction types a mixed workload can be made of.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum TxnType {
+    Transfer,
+    CreateAccount,
+    Mint,
+    RotateKey,
+    PublishModule,
+}
+
+impl TxnType {
+    /// Name of the type in workload specifications and metrics.
+    pub fn name(self) -> &'static str {
+        match self {
+            TxnType::Transfer => "transfer",
+            TxnType::CreateAccount => "create_account",
+            TxnType::Mint => "mint",
+            TxnType::RotateKey => "rotate_key",
+            TxnType::PublishModule => "publish_module",
+        }
+    }
+
+    pub fn all() -> Vec<TxnType> {
+        vec![
+            TxnType::Transfer,
+            TxnType::CreateAccount,
+            TxnType::Mint,
+            TxnType::RotateKey,
+            TxnType::PublishModule,
+        ]
+    }
+}
+
+impl fmt::Display for TxnType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for TxnType {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        TxnType::all()
+            .into_iter()
+            .find(|txn_type| txn_type.name() == s)
+            .ok_or_else(|| format_err!("unknown transaction type {}", s))
+    }
+}
+
+/// A distribution over transaction types, written as comma separated `type=weight` pairs,
+/// e.g. `transfer=80,create_account=10,mint=10`. Each TXN is of a type picked at random with
+/// a probability proportional to its weight.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorkloadSpec {
+    weights: Vec<(TxnType, u32)>,
+}
+
+impl WorkloadSpec {
+    pub fn new(weights: Vec<(TxnType, u32)>) -> Result<Self> {
+        for (index, (txn_type, _)) in weights.iter().enumerate() {
+            ensure!(
+                !weights[..index].iter().any(|(other, _)| other == txn_type),
+                "transaction type {} is given more than once",
+                txn_type
+            );
+        }
+        ensure!(
+            weights.iter().any(|(_, weight)| *weight > 0),
+            "at least one transaction type must have a positive weight"
+        );
+        Ok(Self { weights })
+    }
+
+    /// Types with a positive weight.
+    pub fn txn_types(&self) -> Vec<TxnType> {
+        self.weights
+            .iter()
+            .filter(|(_, weight)| *weight > 0)
+            .map(|(txn_type, _)| *txn_type)
+            .collect()
+    }
+
+    pub fn sampler(&self) -> WorkloadSampler {
+        let weights: Vec<_> = self.weights.iter().map(|(_, weight)| *weight).collect();
+        WorkloadSampler {
+            txn_types: self.weights.iter().map(|(txn_type, _)| *txn_type).collect(),
+            index: WeightedIndex::new(&weights).expect("checked by WorkloadSpec::new"),
+        }
+    }
+}
+
+impl FromStr for WorkloadSpec {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let weights: Vec<(TxnType, u32)> = s
+            .split(',')
+            .map(|entry| {
+                let mut parts = entry.trim().splitn(2, '=');
+                let txn_type: TxnType = parts.next().unwrap_or_default().trim().parse()?;
+                let weight = parts
+                    .next()
+                    .ok_or_else(|| format_err!("missing weight of {}", txn_type))?
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|e| format_err!("invalid weight of {}: {}", txn_type, e))?;
+                Ok((txn_type, weight))
+            })
+            .collect::<Result<_>>()?;
+        Self::new(weights)
+    }
+}
+
+/// Samples transaction types according to a WorkloadSpec.
+pub struct WorkloadSampler {
+    txn_types: Vec<TxnType>,
+    index: WeightedIndex<u32>,
+}
+
+impl WorkloadSampler {
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> TxnType {
+        self.txn_types[self.index.sample(rng)]
+    }
+}
+
+/// Accounts the generated TXNs are sent by and sent to. The faucet account comes first,
+/// followed by the accounts generated from the wallet. Accounts created by create-account TXNs
+/// are recipients right away, and senders once they are admitted after the TXNs are committed.
+pub struct AccountPool {
+    wallet: WalletLibrary,
+    accounts: Vec<AccountData>,
+    recipients: Vec<AccountAddress>,
+    created: Vec<AccountData>,
+}
+
+impl AccountPool {
+    pub fn new(faucet_account: AccountData) -> Self {
+        AccountPool {
+            wallet: WalletLibrary::new(),
+            recipients: vec![faucet_account.address],
+            accounts: vec![faucet_account],
+            created: vec![],
+        }
+    }
+
+    /// Generate `num_accounts` senders, which must be minted before sending TXNs.
+    pub fn gen_accounts(&mut self, num_accounts: u64) {
+        let accounts = gen_accounts_from_wallet(&mut self.wallet, num_accounts);
+        self.recipients
+            .extend(accounts.iter().map(|account| account.address));
+        self.accounts.extend(accounts);
+    }
+
+    /// The faucet account followed by all the senders.
+    pub fn accounts(&self) -> &[AccountData] {
+        &self.accounts
+    }
+
+    pub fn accounts_mut(&mut self) -> &mut [AccountData] {
+        &mut self.accounts
+    }
+
+    /// The faucet account and the other senders, e.g. to mint the senders.
+    pub fn faucet_and_senders_mut(&mut self) -> (&mut AccountData, &mut [AccountData]) {
+        self.accounts
+            .split_first_mut()
+            .expect("the pool always has the faucet account")
+    }
+
+    pub fn num_senders(&self) -> usize {
+        self.accounts.len() - 1
+    }
+
+    pub fn recipients(&self) -> &[AccountAddress] {
+        &self.recipients
+    }
+
+    /// Generate a new account to create, recipient of later TXNs.
+    pub fn gen_created_account(&mut self) -> AccountAddress {
+        let mut accounts = gen_accounts_from_wallet(&mut self.wallet, 1);
+        let account = accounts.remove(0);
+        let address = account.address;
+        self.recipients.push(address);
+        self.created.push(account);
+        address
+    }
+
+    /// Turn the accounts created since the last call into senders, returning them.
+    /// Call once the create-account TXNs are committed.
+    pub fn admit_created(&mut self) -> &[AccountData] {
+        let start = self.accounts.len();
+        self.accounts.append(&mut self.created);
+        &self.accounts[start..]
+    }
+
+    pub fn random_recipient<R: Rng + ?Sized>(&self, rng: &mut R) -> AccountAddress {
+        self.recipients[rng.gen_range(0, self.recipients.len())]
+    }
+
+    /// Sign a TXN with `payload`, built from the sender's address, by a random sender.
+    pub fn sign_by_random_sender<R: Rng + ?Sized>(
+        &mut self,
+        rng: &mut R,
+        payload: impl FnOnce(&AccountAddress) -> TransactionPayload,
+    ) -> Result<Request> {
+        ensure!(self.num_senders() > 0, "no sender in the account pool");
+        let index = rng.gen_range(1, self.accounts.len());
+        let sender = &mut self.accounts[index];
+        let payload = payload(&sender.address);
+        gen_submit_payload_request(payload, sender, &self.wallet)
+    }
+
+    /// Sign a TXN with `payload` by the faucet account.
+    pub fn sign_by_faucet(&mut self, payload: TransactionPayload) -> Result<Request> {
+        let faucet_account = &mut self.accounts[0];
+        let signer = faucet_account
+            .key_pair
+            .as_ref()
+            .ok_or_else(|| format_err!("failed to load keypair from faucet"))?
+            .clone();
+        gen_submit_payload_request(payload, faucet_account, &signer)
+    }
+}
+
+/// Generates TXNs of one type. Implement it to add a transaction type to mixed workloads.
+pub trait WorkloadGenerator {
+    fn txn_type(&self) -> TxnType;
+    /// Generate a TXN sent by and to accounts of `pool`, bumping the sender's sequence number.
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request>;
+}
+
+/// Transfers between random accounts of the pool.
+pub struct TransferGenerator;
+
+impl WorkloadGenerator for TransferGenerator {
+    fn txn_type(&self) -> TxnType {
+        TxnType::Transfer
+    }
+
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request> {
+        let receiver = pool.random_recipient(rng);
+        pool.sign_by_random_sender(rng, |_| {
+            TransactionPayload::Script(transaction_builder::encode_transfer_script(
+                &receiver,
+                TRANSFER_AMOUNT,
+            ))
+        })
+    }
+}
+
+/// Creates new accounts, which join the pool as recipients.
+pub struct CreateAccountGenerator;
+
+impl WorkloadGenerator for CreateAccountGenerator {
+    fn txn_type(&self) -> TxnType {
+        TxnType::CreateAccount
+    }
+
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request> {
+        ensure!(pool.num_senders() > 0, "no sender in the account pool");
+        let new_account = pool.gen_created_account();
+        pool.sign_by_random_sender(rng, |_| {
+            TransactionPayload::Script(transaction_builder::encode_create_account_script(
+                &new_account,
+                CREATE_ACCOUNT_BALANCE,
+            ))
+        })
+    }
+}
+
+/// Mints coins from the faucet account to random accounts of the pool.
+pub struct MintGenerator;
+
+impl WorkloadGenerator for MintGenerator {
+    fn txn_type(&self) -> TxnType {
+        TxnType::Mint
+    }
+
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request> {
+        let receiver = pool.random_recipient(rng);
+        pool.sign_by_faucet(TransactionPayload::Script(
+            transaction_builder::encode_mint_script(&receiver, MINT_AMOUNT),
+        ))
+    }
+}
+
+/// Rotates the authentication key of random senders. The key is rotated to the hash of the
+/// current public key, i.e. the address, so that the wallet can keep signing for the sender.
+pub struct RotateKeyGenerator;
+
+impl WorkloadGenerator for RotateKeyGenerator {
+    fn txn_type(&self) -> TxnType {
+        TxnType::RotateKey
+    }
+
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request> {
+        pool.sign_by_random_sender(rng, |sender| {
+            TransactionPayload::Script(transaction_builder::rotate_authentication_key_script(
+                sender.to_vec(),
+            ))
+        })
+    }
+}
+
+/// Publishes compiled modules, loaded from the `*.mv` files of a fixtures directory, from
+/// random senders.
+pub struct PublishModuleGenerator {
+    modules: Vec<Vec<u8>>,
+}
+
+impl PublishModuleGenerator {
+    pub fn new(modules: Vec<Vec<u8>>) -> Result<Self> {
+        ensure!(!modules.is_empty(), "no module to publish");
+        Ok(Self { modules })
+    }
+
+    pub fn from_dir<P: AsRef<Path>>(fixtures_dir: P) -> Result<Self> {
+        let mut modules = vec![];
+        for entry in fs::read_dir(fixtures_dir.as_ref())? {
+            let path = entry?.path();
+            if path.is_file() && path.extension() == Some(OsStr::new("mv")) {
+                modules.push(fs::read(&path)?);
+            }
+        }
+        Self::new(modules).map_err(|e| {
+            format_err!(
+                "failed to load modules from {:?}: {}",
+                fixtures_dir.as_ref(),
+                e
+            )
+        })
+    }
+}
+
+impl WorkloadGenerator for PublishModuleGenerator {
+    fn txn_type(&self) -> TxnType {
+        TxnType::PublishModule
+    }
+
+    fn gen_request(&self, pool: &mut AccountPool, rng: &mut dyn RngCore) -> Result<Request> {
+        let code = self.modules[rng.gen_range(0, self.modules.len())].clone();
+        pool.sign_by_random_sender(rng, |_| TransactionPayload::Module(Module::new(code)))
+    }
+}
+
+/// Generates TXNs of the types of a WorkloadSpec, in its proportions.
+pub struct MixedWorkload {
+    sampler: WorkloadSampler,
+    generators: HashMap<TxnType, Box<dyn WorkloadGenerator>>,
+}
+
+impl MixedWorkload {
+    /// Fails if any type of `spec` has no generator.
+    pub fn new(spec: &WorkloadSpec, generators: Vec<Box<dyn WorkloadGenerator>>) -> Result<Self> {
+        let generators: HashMap<_, _> = generators
+            .into_iter()
+            .map(|generator| (generator.txn_type(), generator))
+            .collect();
+        for txn_type in spec.txn_types() {
+            ensure!(
+                generators.contains_key(&txn_type),
+                "no generator for transaction type {}",
+                txn_type
+            );
+        }
+        Ok(Self {
+            sampler: spec.sampler(),
+            generators,
+        })
+    }
+
+    /// Workload of `spec` with the generators of all types. Module fixtures are only needed if
+    /// `spec` publishes modules.
+    pub fn with_default_generators(
+        spec: &WorkloadSpec,
+        module_fixtures_dir: Option<&str>,
+    ) -> Result<Self> {
+        let mut generators: Vec<Box<dyn WorkloadGenerator>> = vec![
+            Box::new(TransferGenerator),
+            Box::new(CreateAccountGenerator),
+            Box::new(MintGenerator),
+            Box::new(RotateKeyGenerator),
+        ];
+        if spec.txn_types().contains(&TxnType::PublishModule) {
+            let fixtures_dir = module_fixtures_dir
+                .ok_or_else(|| format_err!("publish_module requires a module fixtures dir"))?;
+            generators.push(Box::new(PublishModuleGenerator::from_dir(fixtures_dir)?));
+        }
+        Self::new(spec, generators)
+    }
+
+    /// Generate `num_requests` TXNs of sampled types, tagged with their type. TXNs that fail
+    /// to be generated are logged and skipped.
+    pub fn gen_requests<R: RngCore>(
+        &self,
+        pool: &mut AccountPool,
+        num_requests: usize,
+        rng: &mut R,
+    ) -> Vec<(TxnType, Request)> {
+        let mut requests = vec![];
+        for _ in 0..num_requests {
+            let txn_type = self.sampler.sample(rng);
+            match self.generators[&txn_type].gen_request(pool, rng) {
+                Ok(request) => requests.push((txn_type, request)),
+                Err(e) => error!("failed to generate {} TXN: {:?}", txn_type, e),
+            }
+        }
+        requests
+    }
+}
+
+/// Submits requests one at a time, reporting whether each one is accepted.
+pub trait Submitter {
+    fn submit(&self, request: &Request) -> bool;
+}
+
+impl Submitter for AdmissionControlClient {
+    fn submit(&self, request: &Request) -> bool {
+        match request {
+            Request::WriteRequest(txn_req) => {
+                match self.submit_transaction_opt(txn_req, get_default_grpc_call_option()) {
+                    Ok(resp) => check_ac_response(&resp),
+                    Err(e) => {
+                        OP_COUNTER.inc(&format!("submit_txns.failure.grpc.{:?}", e));
+                        debug!("Failed to send gRPC request: {:?}", e);
+                        false
+                    }
+                }
+            }
+            Request::ReadRequest(read_req) => self
+                .update_to_latest_ledger_opt(read_req, get_default_grpc_call_option())
+                .is_ok(),
+        }
+    }
+}
+
+/// Submission statistics of the TXNs of one type.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TxnTypeStats {
+    pub num_submitted: usize,
+    pub num_accepted: usize,
+    /// Sum of the durations of the submissions.
+    pub total_latency: Duration,
+}
+
+impl TxnTypeStats {
+    pub fn avg_latency(&self) -> Duration {
+        if self.num_submitted == 0 {
+            return Duration::from_secs(0);
+        }
+        self.total_latency / self.num_submitted as u32
+    }
+
+    fn merge(&mut self, other: &TxnTypeStats) {
+        self.num_submitted += other.num_submitted;
+        self.num_accepted += other.num_accepted;
+        self.total_latency += other.total_latency;
+    }
+}
+
+/// Per type results of submitting a mixed workload.
+#[derive(Debug, Default)]
+pub struct WorkloadReport {
+    stats: BTreeMap<TxnType, TxnTypeStats>,
+    /// Wall clock duration of the submission.
+    duration: Duration,
+}
+
+impl WorkloadReport {
+    pub fn stats(&self, txn_type: TxnType) -> Option<&TxnTypeStats> {
+        self.stats.get(&txn_type)
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Accepted TXNs of `txn_type` per second.
+    pub fn throughput(&self, txn_type: TxnType) -> f64 {
+        let duration_secs = self.duration.as_secs_f64();
+        match self.stats.get(&txn_type) {
+            Some(stats) if duration_secs > 0.0 => stats.num_accepted as f64 / duration_secs,
+            _ => 0.0,
+        }
+    }
+
+    /// Add up the stats of a report of requests submitted concurrently, the duration being the
+    /// longest of the two.
+    pub fn merge(&mut self, other: &WorkloadReport) {
+        for (txn_type, stats) in other.stats.iter() {
+            self.stats.entry(*txn_type).or_default().merge(stats);
+        }
+        self.duration = std::cmp::max(self.duration, other.duration);
+    }
+
+    /// Log the per type results and set them as gauges, e.g. workload.transfer.throughput.
+    pub fn publish(&self) {
+        for (txn_type, stats) in self.stats.iter() {
+            let throughput = self.throughput(*txn_type);
+            info!(
+                "{}: {} submitted, {} accepted, avg latency {} us, throughput {:.2} TPS.",
+                txn_type,
+                stats.num_submitted,
+                stats.num_accepted,
+                stats.avg_latency().as_micros(),
+                throughput,
+            );
+            OP_COUNTER.set(
+                &format!("workload.{}.submitted", txn_type),
+                stats.num_submitted,
+            );
+            OP_COUNTER.set(
+                &format!("workload.{}.accepted", txn_type),
+                stats.num_accepted,
+            );
+            OP_COUNTER.set(
+                &format!("workload.{}.avg_latency_us", txn_type),
+                stats.avg_latency().as_micros() as usize,
+            );
+            OP_COUNTER.set(
+                &format!("workload.{}.throughput", txn_type),
+                throughput as usize,
+            );
+        }
+    }
+}
+
+/// Submit tagged requests at `submit_rate` per second through `submitter`, timing each
+/// submission, and bucket the results by transaction type.
+pub fn submit_workload<S: Submitter + ?Sized>(
+    submitter: &S,
+    requests: Vec<(TxnType, Request)>,
+    submit_rate: u64,
+) -> WorkloadReport {
+    let mut report = WorkloadReport::default();
+    let start = Instant::now();
+    for (txn_type, request) in ConstantRate::new(submit_rate, requests.into_iter()) {
+        let now = Instant::now();
+        let accepted = submitter.submit(&request);
+        let stats = report.stats.entry(txn_type).or_default();
+        stats.num_submitted += 1;
+        stats.total_latency += now.elapsed();
+        if accepted {
+            stats.num_accepted += 1;
+        }
+    }
+    report.duration = start.elapsed();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        load_generator::Request,
+        workload::{
+            submit_workload, AccountPool, CreateAccountGenerator, MixedWorkload, Submitter,
+            TransferGenerator, TxnType, WorkloadGenerator, WorkloadSpec,
+        },
+    };
+    use admission_control_proto::proto::admission_control::SubmitTransactionRequest;
+    use client::{AccountData, AccountStatus};
+    use libra_crypto::{test_utils::KeyPair, traits::Uniform};
+    use libra_types::{
+        account_config::association_address,
+        proto::types::UpdateToLatestLedgerRequest as ProtoUpdateToLatestLedgerRequest,
+    };
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::{collections::HashMap, thread, time::Duration};
+
+    fn faucet_account(rng: &mut StdRng) -> AccountData {
+        AccountData {
+            address: association_address(),
+            key_pair: Some(KeyPair::generate_for_testing(rng)),
+            sequence_number: 0,
+            status: AccountStatus::Persisted,
+        }
+    }
+
+    #[test]
+    fn test_parse_workload_spec() {
+        let spec: WorkloadSpec = "transfer=80, create_account=10,mint=10".parse().unwrap();
+        assert_eq!(
+            spec,
+            WorkloadSpec::new(vec![
+                (TxnType::Transfer, 80),
+                (TxnType::CreateAccount, 10),
+                (TxnType::Mint, 10),
+            ])
+            .unwrap()
+        );
+        assert!("transfer=1,teleport=1".parse::<WorkloadSpec>().is_err());
+        assert!("transfer".parse::<WorkloadSpec>().is_err());
+        assert!("transfer=-1".parse::<WorkloadSpec>().is_err());
+        assert!("transfer=1,transfer=2".parse::<WorkloadSpec>().is_err());
+        assert!("transfer=0,mint=0".parse::<WorkloadSpec>().is_err());
+    }
+
+    #[test]
+    fn test_sampling_distribution() {
+        const NUM_SAMPLES: usize = 10_000;
+
+        let spec: WorkloadSpec = "transfer=70,mint=20,rotate_key=10,create_account=0"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            spec.txn_types(),
+            vec![TxnType::Transfer, TxnType::Mint, TxnType::RotateKey]
+        );
+        let sampler = spec.sampler();
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let mut counts = HashMap::new();
+        for _ in 0..NUM_SAMPLES {
+            *counts.entry(sampler.sample(&mut rng)).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.get(&TxnType::CreateAccount), None);
+        assert_eq!(counts.get(&TxnType::PublishModule), None);
+        for (txn_type, expected_share) in &[
+            (TxnType::Transfer, 0.7),
+            (TxnType::Mint, 0.2),
+            (TxnType::RotateKey, 0.1),
+        ] {
+            let share = counts[txn_type] as f64 / NUM_SAMPLES as f64;
+            assert!(
+                (share - expected_share).abs() < 0.02,
+                "{} share is {}",
+                txn_type,
+                share
+            );
+        }
+    }
+
+    #[test]
+    fn test_mixed_workload_requires_generators() {
+        let spec: WorkloadSpec = "transfer=1,mint=1".parse().unwrap();
+        assert!(MixedWorkload::new(&spec, vec![Box::new(TransferGenerator)]).is_err());
+        assert!(MixedWorkload::with_default_generators(&spec, None).is_ok());
+        let spec: WorkloadSpec = "transfer=1,publish_module=1".parse().unwrap();
+        assert!(MixedWorkload::with_default_generators(&spec, None).is_err());
+    }
+
+    #[test]
+    fn test_create_account_grows_pool() {
+        let mut rng = StdRng::from_seed([0u8; 32]);
+        let mut pool = AccountPool::new(faucet_account(&mut rng));
+        pool.gen_accounts(2);
+        assert_eq!(pool.recipients().len(), 3);
+
+        for _ in 0..3 {
+            CreateAccountGenerator
+                .gen_request(&mut pool, &mut rng)
+                .unwrap();
+        }
+        // created accounts are recipients right away, senders once admitted
+        assert_eq!(pool.recipients().len(), 6);
+        assert_eq!(pool.num_senders(), 2);
+        let num_sent: u64 = pool.accounts()[1..]
+            .iter()
+            .map(|account| account.sequence_number)
+            .sum();
+        assert_eq!(num_sent, 3);
+
+        let admitted: Vec<_> = pool
+            .admit_created()
+            .iter()
+            .map(|account| account.address)
+            .collect();
+        assert_eq!(admitted, pool.recipients()[3..].to_vec());
+        assert_eq!(pool.num_senders(), 5);
+        assert!(pool.admit_created().is_empty());
+
+        // new senders can send right away
+        let workload =
+            MixedWorkload::with_default_generators(&"transfer=1".parse().unwrap(), None).unwrap();
+        assert_eq!(workload.gen_requests(&mut pool, 20, &mut rng).len(), 20);
+    }
+
+    /// Accepts write requests and rejects read requests, taking 1 ms for reads.
+    struct MockSubmitter;
+
+    impl Submitter for MockSubmitter {
+        fn submit(&self, request: &Request) -> bool {
+            match request {
+                Request::WriteRequest(_) => true,
+                Request::ReadRequest(_) => {
+                    thread::sleep(Duration::from_millis(1));
+                    false
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_metric_bucketing() {
+        let write = || Request::WriteRequest(SubmitTransactionRequest::default());
+        let read = || Request::ReadRequest(ProtoUpdateToLatestLedgerRequest::default());
+        let requests = vec![
+            (TxnType::Transfer, write()),
+            (TxnType::Mint, read()),
+            (TxnType::Transfer, write()),
+            (TxnType::CreateAccount, write()),
+            (TxnType::Mint, write()),
+            (TxnType::Mint, read()),
+        ];
+
+        let report = submit_workload(&MockSubmitter, requests, std::u64::MAX);
+
+        let transfer = report.stats(TxnType::Transfer).unwrap();
+        assert_eq!((transfer.num_submitted, transfer.num_accepted), (2, 2));
+        let create_account = report.stats(TxnType::CreateAccount).unwrap();
+        assert_eq!(
+            (create_account.num_submitted, create_account.num_accepted),
+            (1, 1)
+        );
+        let mint = report.stats(TxnType::Mint).unwrap();
+        assert_eq!((mint.num_submitted, mint.num_accepted), (3, 1));
+        assert!(mint.total_latency >= Duration::from_millis(2));
+        assert!(report.stats(TxnType::RotateKey).is_none());
+        assert!(report.throughput(TxnType::Transfer) > report.throughput(TxnType::Mint));
+        assert_eq!(report.throughput(TxnType::RotateKey), 0.0);
+    }
+}
@@ -17,7 +17,12 @@
 
 use byteorder::{ByteOrder, LittleEndian};
 use hmac::Hmac;
-use libra_crypto::{ed25519::*, hash::HashValue, hkdf::Hkdf, traits::SigningKey};
+use libra_crypto::{
+    ed25519::*,
+    hash::HashValue,
+    hkdf::{HkdfInfo, HkdfSalt, Sha3Hkdf},
+    traits::SigningKey,
+};
 use libra_types::account_address::AccountAddress;
 use mirai_annotations::*;
 use pbkdf2::pbkdf2;
@@ -122,12 +127,12 @@ pub struct KeyFactory {
 
 impl KeyFactory {
     const MNEMONIC_SALT_PREFIX: &'static [u8] = b"LIBRA WALLET: mnemonic salt prefix$";
-    const MASTER_KEY_SALT: &'static [u8] = b"LIBRA WALLET: master key salt$";
+    const MASTER_KEY_SALT: HkdfSalt<'static> = HkdfSalt(b"LIBRA WALLET: master key salt$");
     const INFO_PREFIX: &'static [u8] = b"LIBRA WALLET: derived key$";
     /// Instantiate a new KeyFactor from a Seed, where the [u8; 64] raw bytes of the Seed are used
     /// to derive both the Master
     pub fn new(seed: &Seed) -> Result<Self> {
-        let hkdf_extract = Hkdf::<Sha3_256>::extract(Some(KeyFactory::MASTER_KEY_SALT), &seed.0)?;
+        let hkdf_extract = Sha3Hkdf::extract_with_salt(KeyFactory::MASTER_KEY_SALT, &seed.0)?;
 
         Ok(Self {
             master: Master::from(&hkdf_extract[..32]),
@@ -149,7 +154,7 @@ impl KeyFactory {
         let mut info = KeyFactory::INFO_PREFIX.to_vec();
         info.extend_from_slice(&le_n);
 
-        let hkdf_expand = Hkdf::<Sha3_256>::expand(&self.master(), Some(&info), 32)?;
+        let hkdf_expand = Sha3Hkdf::expand_with_info(&self.master(), HkdfInfo(&info), 32)?;
         let sk = Ed25519PrivateKey::try_from(hkdf_expand.as_slice())
             .expect("Unable to convert into private key");
 
@@ -237,3 +242,34 @@ fn test_key_derivation() {
         hex::encode(&child_private_1_from_increment.private_key.to_bytes()[..])
     );
 }
+
+// The keys derived today, which any change to the derivation must keep.
+#[cfg(test)]
+#[test]
+fn test_pinned_child_keys() {
+    let master =
+        hex::decode("16274c9618ed59177ca948529c1884ba65c57984d562ec2b4e5aa1ee3e3903be").unwrap();
+    let key_factory = KeyFactory {
+        master: Master::from(&master[..]),
+    };
+    for (child, expected) in &[
+        (
+            2,
+            "3bc2a3d5abc8a9d07d78cde34db0be7b45a85db1a43a893bc6dfd17c382c9813",
+        ),
+        (
+            1 << 32,
+            "3b84b63354250601b63d9b30fe1bf2bd6bf644c0b006eb2bd3ce5510b6e54c8c",
+        ),
+        (
+            1 << 62,
+            "d9fb99957de0691a8180077870135dca24a5cb0613069dec05df3b26dbf44ba8",
+        ),
+    ] {
+        let child_private = key_factory.private_child(ChildNumber(*child)).unwrap();
+        assert_eq!(
+            *expected,
+            hex::encode(&child_private.private_key.to_bytes()[..])
+        );
+    }
+}
@@ -20,6 +20,7 @@ impl Command for AccountCommand {
             Box::new(AccountCommandRecoverWallet {}),
             Box::new(AccountCommandWriteRecovery {}),
             Box::new(AccountCommandMint {}),
+            Box::new(AccountCommandResetNonces {}),
         ];
 
         subcommand_execute(&params[0], commands, client, &params[1..]);
@@ -150,3 +151,24 @@ impl Command for AccountCommandMint {
         }
     }
 }
+
+/// Sub command to forget the sequence numbers of the transactions submitted and not committed yet.
+pub struct AccountCommandResetNonces {}
+
+impl Command for AccountCommandResetNonces {
+    fn get_aliases(&self) -> Vec<&'static str> {
+        vec!["reset-nonces", "rn"]
+    }
+    fn get_params_help(&self) -> &'static str {
+        "[<account_ref_id>|<account_address>]"
+    }
+    fn get_description(&self) -> &'static str {
+        "Forget the local sequence numbers of pending transactions, of one or all accounts"
+    }
+    fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
+        match client.reset_nonces(params) {
+            Ok(_) => println!("Reset pending sequence numbers"),
+            Err(e) => report_error("Error resetting sequence numbers", e),
+        }
+    }
+}
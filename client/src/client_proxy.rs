@@ -1,7 +1,18 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{commands::*, grpc_client::GRPCClient, AccountData, AccountStatus};
+use crate::{
+    commands::*,
+    connection::ConnectionConfig,
+    dev_flow::DevBackend,
+    grpc_client::GRPCClient,
+    nonce_tracker::NonceTracker,
+    views::{
+        AccountView, BalanceView, BlockchainStatusView, EventView, OutputFormat,
+        SequenceNumberView, TransactionView,
+    },
+    AccountData, AccountStatus,
+};
 use admission_control_proto::proto::admission_control::SubmitTransactionRequest;
 use failure::prelude::*;
 use libra_config::{config::PersistableConfig, trusted_peers::ConsensusPeersConfig};
@@ -16,12 +27,14 @@ use libra_types::{
         ACCOUNT_RECEIVED_EVENT_PATH, ACCOUNT_SENT_EVENT_PATH,
     },
     account_state_blob::{AccountStateBlob, AccountStateWithProof},
+    account_state_diff::AccountStateDiff,
     contract_event::{ContractEvent, EventWithProof},
     transaction::{
         helpers::{create_unsigned_txn, create_user_txn, TransactionSigner},
         parse_as_transaction_argument, RawTransaction, Script, SignedTransaction, Transaction,
         TransactionArgument, TransactionPayload, Version,
     },
+    vm_error::StatusCode,
 };
 use libra_wallet::{io_utils, wallet_library::WalletLibrary};
 use num_traits::{
@@ -91,6 +104,8 @@ pub struct ClientProxy {
     address_to_ref_id: HashMap<AccountAddress, usize>,
     /// Host that operates a faucet service
     faucet_server: String,
+    /// TLS and proxy settings, the latter also used for the faucet.
+    connection_config: ConnectionConfig,
     /// Account used for mint operations.
     pub faucet_account: Option<AccountData>,
     /// Wallet library managing user accounts.
@@ -99,6 +114,10 @@ pub struct ClientProxy {
     sync_on_wallet_recovery: bool,
     /// temp files (alive for duration of program)
     temp_files: Vec<PathBuf>,
+    /// How the query commands print their results.
+    output_format: OutputFormat,
+    /// Sequence numbers of the transactions submitted and not committed yet.
+    nonce_tracker: NonceTracker,
 }
 
 impl ClientProxy {
@@ -111,6 +130,31 @@ impl ClientProxy {
         sync_on_wallet_recovery: bool,
         faucet_server: Option<String>,
         mnemonic_file: Option<String>,
+    ) -> Result<Self> {
+        Self::new_with_connection_config(
+            host,
+            ac_port,
+            validator_set_file,
+            faucet_account_file,
+            sync_on_wallet_recovery,
+            faucet_server,
+            mnemonic_file,
+            ConnectionConfig::default(),
+        )
+    }
+
+    /// Construct a new TestClient connecting to the validator and the faucet as set in
+    /// `connection_config`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_with_connection_config(
+        host: &str,
+        ac_port: u16,
+        validator_set_file: &str,
+        faucet_account_file: &str,
+        sync_on_wallet_recovery: bool,
+        faucet_server: Option<String>,
+        mnemonic_file: Option<String>,
+        connection_config: ConnectionConfig,
     ) -> Result<Self> {
         let validator_verifier = Arc::new(
             ConsensusPeersConfig::load_config(validator_set_file).get_validator_verifier(),
@@ -119,7 +163,7 @@ impl ClientProxy {
             !validator_verifier.is_empty(),
             "Not able to load any validators from trusted peers config!"
         );
-        let client = GRPCClient::new(host, ac_port, validator_verifier)?;
+        let client = GRPCClient::new(host, ac_port, validator_verifier, connection_config.clone())?;
 
         let accounts = vec![];
 
@@ -157,10 +201,13 @@ impl ClientProxy {
             accounts,
             address_to_ref_id,
             faucet_server,
+            connection_config,
             faucet_account,
             wallet: Self::get_libra_wallet(mnemonic_file)?,
             sync_on_wallet_recovery,
             temp_files: vec![],
+            output_format: OutputFormat::Text,
+            nonce_tracker: NonceTracker::default(),
         })
     }
 
@@ -231,21 +278,21 @@ impl ClientProxy {
     }
 
     /// Get balance from validator for the account specified.
-    pub fn get_balance(&mut self, space_delim_strings: &[&str]) -> Result<String> {
+    pub fn get_balance(&mut self, space_delim_strings: &[&str]) -> Result<BalanceView> {
         ensure!(
             space_delim_strings.len() == 2,
             "Invalid number of arguments for getting balance"
         );
         let address = self.get_account_address_from_parameter(space_delim_strings[1])?;
-        self.get_account_resource_and_update(address).map(|res| {
-            let whole_num = res.balance() / 1_000_000;
-            let remainder = res.balance() % 1_000_000;
-            format!("{}.{:0>6}", whole_num.to_string(), remainder.to_string())
-        })
+        self.get_account_resource_and_update(address)
+            .map(|res| BalanceView::new(address, res.balance()))
     }
 
     /// Get the latest sequence number from validator for the account specified.
-    pub fn get_sequence_number(&mut self, space_delim_strings: &[&str]) -> Result<u64> {
+    pub fn get_sequence_number(
+        &mut self,
+        space_delim_strings: &[&str],
+    ) -> Result<SequenceNumberView> {
         ensure!(
             space_delim_strings.len() == 2 || space_delim_strings.len() == 3,
             "Invalid number of arguments for getting sequence number"
@@ -268,17 +315,35 @@ impl ClientProxy {
             false
         };
         if reset_sequence_number {
+            self.nonce_tracker.reset(&address);
             if let Some(faucet_account) = &mut self.faucet_account {
                 if faucet_account.address == address {
                     faucet_account.sequence_number = sequence_number;
-                    return Ok(sequence_number);
+                    return Ok(SequenceNumberView::new(address, sequence_number));
                 }
             }
             let mut account = self.mut_account_from_parameter(space_delim_strings[1])?;
             // Set sequence_number to latest one.
             account.sequence_number = sequence_number;
         }
-        Ok(sequence_number)
+        Ok(SequenceNumberView::new(address, sequence_number))
+    }
+
+    /// Forgets the sequence numbers of the transactions submitted and not committed yet, of the
+    /// account specified or of all the accounts, for their next transactions to take the sequence
+    /// numbers on chain.
+    pub fn reset_nonces(&mut self, space_delim_strings: &[&str]) -> Result<()> {
+        ensure!(
+            space_delim_strings.len() == 1 || space_delim_strings.len() == 2,
+            "Invalid number of arguments for resetting nonces"
+        );
+        if space_delim_strings.len() == 2 {
+            let address = self.get_account_address_from_parameter(space_delim_strings[1])?;
+            self.nonce_tracker.reset(&address);
+        } else {
+            self.nonce_tracker.reset_all();
+        }
+        Ok(())
     }
 
     /// Mints coins for the receiver specified.
@@ -309,6 +374,7 @@ impl ClientProxy {
                     .get_txn_by_acc_seq(account, sequence_number - 1, true)
             {
                 println!("transaction is stored!");
+                self.nonce_tracker.reconcile(account, sequence_number);
                 if events.is_empty() {
                     println!("no events emitted");
                 }
@@ -333,38 +399,22 @@ impl ClientProxy {
         max_gas_amount: Option<u64>,
         is_blocking: bool,
     ) -> Result<IndexAndSequence> {
-        let sender_address;
-        let sender_sequence;
-        {
-            let sender = self.accounts.get(sender_account_ref_id).ok_or_else(|| {
-                format_err!("Unable to find sender account: {}", sender_account_ref_id)
-            })?;
-
-            let program = transaction_builder::encode_transfer_script(&receiver_address, num_coins);
-            let req = self.create_submit_transaction_req(
-                TransactionPayload::Script(program),
-                sender,
-                max_gas_amount, /* max_gas_amount */
-                gas_unit_price, /* gas_unit_price */
-            )?;
-            let sender_mut = self
-                .accounts
-                .get_mut(sender_account_ref_id)
-                .ok_or_else(|| {
-                    format_err!("Unable to find sender account: {}", sender_account_ref_id)
-                })?;
-            self.client.submit_transaction(Some(sender_mut), &req)?;
-            sender_address = sender_mut.address;
-            sender_sequence = sender_mut.sequence_number;
-        }
+        let program = transaction_builder::encode_transfer_script(&receiver_address, num_coins);
+        let sequence_number = self.submit_tracked_transaction(
+            Some(sender_account_ref_id),
+            TransactionPayload::Script(program),
+            max_gas_amount,
+            gas_unit_price,
+        )?;
 
         if is_blocking {
-            self.wait_for_transaction(sender_address, sender_sequence);
+            let sender_address = self.accounts[sender_account_ref_id].address;
+            self.wait_for_transaction(sender_address, sequence_number + 1);
         }
 
         Ok(IndexAndSequence {
             account_index: AccountEntry::Index(sender_account_ref_id),
-            sequence_number: sender_sequence - 1,
+            sequence_number,
         })
     }
 
@@ -556,13 +606,9 @@ impl ClientProxy {
     ) -> Result<()> {
         let sender_address = self.get_account_address_from_parameter(space_delim_strings[1])?;
         let sender_ref_id = self.get_account_ref_id(&sender_address)?;
-        let sender = self.accounts.get(sender_ref_id).unwrap();
-        let sequence_number = sender.sequence_number;
 
-        let req = self.create_submit_transaction_req(program, &sender, None, None)?;
-
-        self.client
-            .submit_transaction(self.accounts.get_mut(sender_ref_id), &req)?;
+        let sequence_number =
+            self.submit_tracked_transaction(Some(sender_ref_id), program, None, None)?;
         self.wait_for_transaction(sender_address, sequence_number + 1);
 
         Ok(())
@@ -588,6 +634,32 @@ impl ClientProxy {
         )
     }
 
+    /// Runs `submit` for the next transaction of `sender` and returns the VM status and the
+    /// events of the transaction once it is committed.
+    fn submit_and_get_output<F>(
+        &mut self,
+        sender: &str,
+        submit: F,
+    ) -> Result<(StatusCode, Vec<ContractEvent>)>
+    where
+        F: FnOnce(&mut Self) -> Result<()>,
+    {
+        let address = self.get_account_address_from_parameter(sender)?;
+        let ref_id = self.get_account_ref_id(&address)?;
+        submit(self)?;
+        // the sequence number of the account follows the transaction just submitted
+        let sequence_number = self.accounts[ref_id].sequence_number - 1;
+        self.client
+            .get_txn_output_by_acc_seq(address, sequence_number)?
+            .ok_or_else(|| {
+                format_err!(
+                    "Transaction {} of {} is not committed",
+                    sequence_number,
+                    address
+                )
+            })
+    }
+
     /// Get the latest account state from validator.
     pub fn get_latest_account_state(
         &mut self,
@@ -601,6 +673,43 @@ impl ClientProxy {
         self.get_account_state_and_update(account)
     }
 
+    /// Get the latest state of an account from validator, as a view.
+    pub fn get_account_view(&mut self, space_delim_strings: &[&str]) -> Result<AccountView> {
+        let (blob, version) = self.get_latest_account_state(space_delim_strings)?;
+        let account = self.get_account_address_from_parameter(space_delim_strings[1])?;
+        Ok(AccountView::new(
+            account,
+            &get_account_resource_or_default(&blob)?,
+            version,
+        ))
+    }
+
+    /// Diff the state of an account between two versions.
+    pub fn get_account_state_diff(
+        &mut self,
+        space_delim_strings: &[&str],
+    ) -> Result<AccountStateDiff> {
+        ensure!(
+            space_delim_strings.len() == 4,
+            "Invalid number of arguments to get account state diff"
+        );
+        let account = self.get_account_address_from_parameter(space_delim_strings[1])?;
+        let parse_version = |name, input: &str| {
+            input.parse::<u64>().map_err(|error| {
+                format_parse_data_error(name, InputType::UnsignedInt, input, error)
+            })
+        };
+        let old_version = parse_version("version1", space_delim_strings[2])?;
+        let new_version = parse_version("version2", space_delim_strings[3])?;
+        let old_blob = self
+            .client
+            .get_account_blob_by_version(account, old_version)?;
+        let new_blob = self
+            .client
+            .get_account_blob_by_version(account, new_version)?;
+        AccountStateDiff::from_blobs(old_blob.as_ref(), new_blob.as_ref())
+    }
+
     /// Get committed txn by account and sequence number.
     pub fn get_committed_txn_by_acc_seq(
         &mut self,
@@ -633,6 +742,18 @@ impl ClientProxy {
             .get_txn_by_acc_seq(account, sequence_number, fetch_events)
     }
 
+    /// Get committed txn by account and sequence number, as a view.
+    pub fn get_committed_txn_view_by_acc_seq(
+        &mut self,
+        space_delim_strings: &[&str],
+    ) -> Result<Option<TransactionView>> {
+        Ok(self
+            .get_committed_txn_by_acc_seq(space_delim_strings)?
+            .map(|(txn, events)| {
+                TransactionView::new(None, &txn, events.as_ref().map(Vec::as_slice))
+            }))
+    }
+
     /// Get committed txn by account and sequence number
     pub fn get_committed_txn_by_range(
         &mut self,
@@ -671,6 +792,23 @@ impl ClientProxy {
             .get_txn_by_range(start_version, limit, fetch_events)
     }
 
+    /// Get committed txns by range, as views.
+    pub fn get_committed_txn_views_by_range(
+        &mut self,
+        space_delim_strings: &[&str],
+    ) -> Result<Vec<TransactionView>> {
+        let txns_and_events = self.get_committed_txn_by_range(space_delim_strings)?;
+        // The start version was parsed successfully by the call above.
+        let start_version = space_delim_strings[1].parse::<u64>()?;
+        Ok(txns_and_events
+            .iter()
+            .zip(start_version..)
+            .map(|((txn, events), version)| {
+                TransactionView::new(Some(version), txn, events.as_ref().map(Vec::as_slice))
+            })
+            .collect())
+    }
+
     /// Get account address from parameter. If the parameter is string of address, try to convert
     /// it to address, otherwise, try to convert to u64 and looking at TestClient::accounts.
     pub fn get_account_address_from_parameter(&self, para: &str) -> Result<AccountAddress> {
@@ -738,6 +876,46 @@ impl ClientProxy {
             .get_events_by_access_path(access_path, start_seq_number, ascending, limit)
     }
 
+    /// Get events by account and event type, as views, along with the view of the state of the
+    /// account they were read at.
+    pub fn get_event_views_by_account_and_type(
+        &mut self,
+        space_delim_strings: &[&str],
+    ) -> Result<(Vec<EventView>, AccountView)> {
+        let (events, last_event_state) =
+            self.get_events_by_account_and_type(space_delim_strings)?;
+        let account = self.get_account_address_from_parameter(space_delim_strings[1])?;
+        Ok((
+            events
+                .iter()
+                .map(|event| EventView::new(&event.event, Some(event.transaction_version)))
+                .collect(),
+            AccountView::new(
+                account,
+                &get_account_resource_or_default(&last_event_state.blob)?,
+                last_event_state.version,
+            ),
+        ))
+    }
+
+    /// Get the latest state of the ledger from validator, as a view.
+    pub fn get_blockchain_status(&self) -> Result<BlockchainStatusView> {
+        let response = self.client.get_with_proof_sync(vec![])?;
+        Ok(BlockchainStatusView::new(
+            response.ledger_info_with_sigs.ledger_info(),
+        ))
+    }
+
+    /// How the query commands print their results.
+    pub fn output_format(&self) -> OutputFormat {
+        self.output_format
+    }
+
+    /// Set how the query commands print their results.
+    pub fn set_output_format(&mut self, output_format: OutputFormat) {
+        self.output_format = output_format;
+    }
+
     /// Write mnemonic recover to the file specified.
     pub fn write_recovery(&self, space_delim_strings: &[&str]) -> Result<()> {
         ensure!(
@@ -792,7 +970,9 @@ impl ClientProxy {
 
     /// Test gRPC client connection with validator.
     pub fn test_validator_connection(&self) -> Result<()> {
-        self.client.get_with_proof_sync(vec![])?;
+        self.client
+            .get_with_proof_sync(vec![])
+            .map_err(|err| self.client.explain_error(err))?;
         Ok(())
     }
 
@@ -923,24 +1103,18 @@ impl ClientProxy {
         is_blocking: bool,
     ) -> Result<()> {
         ensure!(self.faucet_account.is_some(), "No faucet account loaded");
-        let sender = self.faucet_account.as_ref().unwrap();
-        let sender_address = sender.address;
+        let sender_address = self.faucet_account.as_ref().unwrap().address;
         let program = transaction_builder::encode_mint_script(&receiver, num_coins);
-        let req = self.create_submit_transaction_req(
+        let sequence_number = self.submit_tracked_transaction(
+            None,
             TransactionPayload::Script(program),
-            sender,
             None, /* max_gas_amount */
             None, /* gas_unit_price */
         )?;
-        let mut sender_mut = self.faucet_account.as_mut().unwrap();
-        let resp = self.client.submit_transaction(Some(&mut sender_mut), &req);
         if is_blocking {
-            self.wait_for_transaction(
-                sender_address,
-                self.faucet_account.as_ref().unwrap().sequence_number,
-            );
+            self.wait_for_transaction(sender_address, sequence_number + 1);
         }
-        resp
+        Ok(())
     }
 
     fn mint_coins_with_faucet_service(
@@ -949,7 +1123,7 @@ impl ClientProxy {
         num_coins: u64,
         is_blocking: bool,
     ) -> Result<()> {
-        let client = reqwest::ClientBuilder::new().use_sys_proxy().build()?;
+        let client = self.connection_config.http_client()?;
 
         let url = reqwest::Url::parse_with_params(
             format!("http://{}", self.faucet_server).as_str(),
@@ -1001,23 +1175,62 @@ impl ClientProxy {
         value.to_u64().ok_or_else(|| format_err!("invalid value"))
     }
 
+    /// Submits a transaction of the account at `sender_account_ref_id`, or of the faucet account
+    /// if `None`, with the next sequence number tracked for it, see `NonceTracker`. Returns the
+    /// sequence number of the transaction.
+    fn submit_tracked_transaction(
+        &mut self,
+        sender_account_ref_id: Option<usize>,
+        program: TransactionPayload,
+        max_gas_amount: Option<u64>,
+        gas_unit_price: Option<u64>,
+    ) -> Result<u64> {
+        let sender = match sender_account_ref_id {
+            Some(ref_id) => self
+                .accounts
+                .get_mut(ref_id)
+                .ok_or_else(|| format_err!("Unable to find sender account: {}", ref_id))?,
+            None => self
+                .faucet_account
+                .as_mut()
+                .ok_or_else(|| format_err!("No faucet account loaded"))?,
+        };
+        let wallet = &self.wallet;
+        let sequence_number =
+            self.nonce_tracker
+                .submit(&mut self.client, sender.address, |sequence_number| {
+                    Self::create_submit_transaction_req(
+                        wallet,
+                        program,
+                        sender,
+                        sequence_number,
+                        max_gas_amount,
+                        gas_unit_price,
+                    )
+                })?;
+        // the local sequence number is the one of the next transaction
+        sender.sequence_number = sequence_number + 1;
+        Ok(sequence_number)
+    }
+
     /// Craft a transaction request.
     fn create_submit_transaction_req(
-        &self,
+        wallet: &WalletLibrary,
         program: TransactionPayload,
         sender_account: &AccountData,
+        sequence_number: u64,
         max_gas_amount: Option<u64>,
         gas_unit_price: Option<u64>,
     ) -> Result<SubmitTransactionRequest> {
         let signer: Box<&dyn TransactionSigner> = match &sender_account.key_pair {
             Some(key_pair) => Box::new(key_pair),
-            None => Box::new(&self.wallet),
+            None => Box::new(wallet),
         };
         let transaction = create_user_txn(
             *signer,
             program,
             sender_account.address,
-            sender_account.sequence_number,
+            sequence_number,
             max_gas_amount.unwrap_or(MAX_GAS_AMOUNT),
             gas_unit_price.unwrap_or(GAS_UNIT_PRICE),
             TX_EXPIRATION,
@@ -1079,6 +1292,30 @@ fn parse_bool(para: &str) -> Result<bool> {
     Ok(para.to_lowercase().parse::<bool>()?)
 }
 
+impl DevBackend for ClientProxy {
+    fn compile(&mut self, sender: &str, file_path: &str, is_module: bool) -> Result<String> {
+        let program_type = if is_module { "module" } else { "script" };
+        self.compile_program(&["compile", sender, file_path, program_type])
+    }
+
+    fn publish(&mut self, sender: &str, path: &str) -> Result<(StatusCode, Vec<ContractEvent>)> {
+        self.submit_and_get_output(sender, |client| {
+            client.publish_module(&["publish", sender, path])
+        })
+    }
+
+    fn run(
+        &mut self,
+        sender: &str,
+        path: &str,
+        args: &[&str],
+    ) -> Result<(StatusCode, Vec<ContractEvent>)> {
+        let mut params = vec!["execute", sender, path];
+        params.extend_from_slice(args);
+        self.submit_and_get_output(sender, |client| client.execute_script(&params))
+    }
+}
+
 impl fmt::Display for AccountEntry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
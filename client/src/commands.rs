@@ -3,13 +3,14 @@
 
 use crate::{
     account_commands::AccountCommand, client_proxy::ClientProxy, dev_commands::DevCommand,
-    query_commands::QueryCommand, transfer_commands::TransferCommand,
+    query_commands::QueryCommand, transfer_commands::TransferCommand, views::OutputFormat,
 };
 
 use failure::prelude::*;
 use libra_metrics::counters::*;
 use libra_types::account_address::ADDRESS_LENGTH;
-use std::{collections::HashMap, sync::Arc};
+use serde::Serialize;
+use std::{collections::HashMap, fmt::Display, sync::Arc};
 
 /// Print the error and bump up error counter.
 pub fn report_error(msg: &str, e: Error) {
@@ -17,6 +18,40 @@ pub fn report_error(msg: &str, e: Error) {
     COUNTER_CLIENT_ERRORS.inc();
 }
 
+/// Print the result of a query in the output format of `client`.
+pub fn print_view<T: Display + Serialize>(client: &ClientProxy, view: &T) {
+    match client.output_format() {
+        OutputFormat::Text => println!("{}", view),
+        OutputFormat::Json => print_json(view),
+    }
+}
+
+/// Print the results of a query in the output format of `client`, as a JSON array in JSON mode.
+pub fn print_views<T: Display + Serialize>(client: &ClientProxy, views: &[T]) {
+    match client.output_format() {
+        OutputFormat::Text => {
+            for view in views {
+                println!("{}", view);
+            }
+        }
+        OutputFormat::Json => print_json(&views),
+    }
+}
+
+/// Print a progress message, only meant for humans, so not in JSON mode.
+pub fn print_progress(client: &ClientProxy, msg: &str) {
+    if client.output_format() == OutputFormat::Text {
+        println!(">> {}", msg);
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => println!("{}", json),
+        Err(e) => report_error("Failed to serialize result", e.into()),
+    }
+}
+
 fn pretty_format_error(e: Error) -> String {
     if let Some(grpc_error) = e.downcast_ref::<grpcio::Error>() {
         if let grpcio::Error::RpcFailure(grpc_rpc_failure) = grpc_error {
@@ -0,0 +1,362 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Settings of the connections of the client to admission control and to the faucet: TLS and an
+//! HTTP CONNECT proxy. Without a proxy set here, gRPC and the faucet requests still honor the
+//! standard `https_proxy` and `http_proxy` environment variables.
+
+use failure::prelude::*;
+use grpcio::{Channel, ChannelBuilder, ChannelCredentialsBuilder, Environment, RpcStatusCode};
+use std::{
+    ffi::CString,
+    fs,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+
+/// Channel argument of gRPC naming the HTTP CONNECT proxy to go through.
+const GRPC_ARG_HTTP_PROXY: &str = "grpc.http_proxy";
+
+/// Timeout of each step of the diagnosis of an unreachable endpoint.
+const DIAGNOSIS_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Upper bound on the size of the response head of a proxy to a CONNECT request.
+const MAX_PROXY_RESPONSE_HEAD: usize = 8192;
+
+/// Why an endpoint could not be reached.
+#[derive(Debug, Eq, Fail, PartialEq)]
+pub enum ConnectionError {
+    /// The connection to the endpoint, given first, was refused or timed out.
+    #[fail(display = "Connection to {} failed: {}", _0, _1)]
+    Refused(String, String),
+    /// The proxy, given first, could not be reached or did not open a tunnel to the endpoint.
+    #[fail(display = "Proxy {} failed to connect to {}: {}", _0, _1, _2)]
+    Proxy(String, String, String),
+    /// The endpoint accepted the connection, but the TLS handshake with it failed.
+    #[fail(
+        display = "TLS handshake with {} failed, check that it serves TLS with a certificate \
+                   issued for its name by a trusted CA (see --ca-cert-file)",
+        _0
+    )]
+    TlsHandshake(String),
+}
+
+/// How the client connects to admission control and to the faucet.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectionConfig {
+    /// Whether to connect to admission control over TLS, verifying its certificate against the
+    /// default roots of gRPC.
+    pub tls: bool,
+    /// PEM bundle of the CAs to verify the certificate of admission control with instead of the
+    /// default roots. Implies `tls`.
+    pub ca_cert_file: Option<PathBuf>,
+    /// HTTP CONNECT proxy to go through, as `host:port` or `http://host:port`.
+    pub proxy: Option<String>,
+}
+
+impl ConnectionConfig {
+    fn uses_tls(&self) -> bool {
+        self.tls || self.ca_cert_file.is_some()
+    }
+
+    /// Opens a gRPC channel to `host:port`.
+    pub(crate) fn connect(&self, env: Arc<Environment>, host: &str, port: u16) -> Result<Channel> {
+        let addr = format!("{}:{}", host, port);
+        let mut builder = ChannelBuilder::new(env);
+        if let Some(proxy) = &self.proxy {
+            builder = builder.raw_cfg_string(
+                CString::new(GRPC_ARG_HTTP_PROXY)?,
+                CString::new(proxy_url(proxy))?,
+            );
+        }
+        if !self.uses_tls() {
+            return Ok(builder.connect(&addr));
+        }
+        let mut credentials = ChannelCredentialsBuilder::new();
+        if let Some(path) = &self.ca_cert_file {
+            let ca_cert = fs::read(path)
+                .map_err(|err| format_err!("Failed to read CA bundle {:?}: {}", path, err))?;
+            credentials = credentials.root_cert(ca_cert);
+        }
+        Ok(builder.secure_connect(&addr, credentials.build()))
+    }
+
+    /// Builds an HTTP client for the faucet, going through the proxy if one is set.
+    pub(crate) fn http_client(&self) -> Result<reqwest::Client> {
+        let builder = reqwest::ClientBuilder::new();
+        let builder = match &self.proxy {
+            Some(proxy) => builder.proxy(reqwest::Proxy::all(&proxy_url(proxy))?),
+            None => builder.use_sys_proxy(),
+        };
+        Ok(builder.build()?)
+    }
+
+    /// Replaces `error`, returned by a call to `host:port`, by the reason the endpoint can't be
+    /// reached if gRPC reported it unavailable and the reason can be found.
+    pub fn explain(&self, host: &str, port: u16, error: Error) -> Error {
+        let is_unavailable = match error.downcast_ref::<grpcio::Error>() {
+            Some(grpcio::Error::RpcFailure(status)) => status.status == RpcStatusCode::UNAVAILABLE,
+            _ => false,
+        };
+        if is_unavailable {
+            if let Some(reason) = self.diagnose(host, port) {
+                return reason.into();
+            }
+        }
+        error
+    }
+
+    /// Tells whether the proxy fails, the connection to `host:port` is refused, or the TLS
+    /// handshake with it fails, in this order. `None` if the endpoint can be reached without TLS.
+    pub fn diagnose(&self, host: &str, port: u16) -> Option<ConnectionError> {
+        let endpoint = format!("{}:{}", host, port);
+        match &self.proxy {
+            Some(proxy) => {
+                if let Err(err) = open_tunnel(&proxy_address(proxy), &endpoint) {
+                    return Some(ConnectionError::Proxy(
+                        proxy.clone(),
+                        endpoint,
+                        err.to_string(),
+                    ));
+                }
+            }
+            None => {
+                if let Err(err) = connect_tcp(&endpoint) {
+                    return Some(ConnectionError::Refused(endpoint, err.to_string()));
+                }
+            }
+        }
+        if self.uses_tls() {
+            Some(ConnectionError::TlsHandshake(endpoint))
+        } else {
+            None
+        }
+    }
+}
+
+/// The proxy as a URL, defaulting the scheme to `http`.
+fn proxy_url(proxy: &str) -> String {
+    if proxy.contains("://") {
+        proxy.to_string()
+    } else {
+        format!("http://{}", proxy)
+    }
+}
+
+/// The `host:port` of the proxy.
+fn proxy_address(proxy: &str) -> String {
+    let address = proxy.splitn(2, "://").last().unwrap_or(proxy);
+    address.trim_end_matches('/').to_string()
+}
+
+fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address resolved");
+    for addr in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, DIAGNOSIS_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = err,
+        }
+    }
+    Err(last_error)
+}
+
+/// Asks the proxy at `proxy_address` to open a tunnel to `endpoint`.
+fn open_tunnel(proxy_address: &str, endpoint: &str) -> Result<()> {
+    let mut stream =
+        connect_tcp(proxy_address).map_err(|err| format_err!("proxy unreachable: {}", err))?;
+    stream.set_read_timeout(Some(DIAGNOSIS_TIMEOUT))?;
+    write!(
+        stream,
+        "CONNECT {} HTTP/1.1\r\nHost: {}\r\n\r\n",
+        endpoint, endpoint
+    )?;
+
+    let mut head = vec![];
+    let mut buf = [0u8; 512];
+    while !head.ends_with(b"\r\n\r\n") && head.len() < MAX_PROXY_RESPONSE_HEAD {
+        let num_read = stream.read(&mut buf)?;
+        ensure!(num_read > 0, "proxy closed the connection");
+        head.extend_from_slice(&buf[..num_read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let status_line = head.lines().next().unwrap_or_default();
+    ensure!(
+        status_line.split_whitespace().nth(1) == Some("200"),
+        "proxy answered \"{}\"",
+        status_line
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use admission_control_proto::proto::admission_control::{
+        create_admission_control, AdmissionControl, AdmissionControlClient,
+        SubmitTransactionRequest, SubmitTransactionResponse,
+    };
+    use futures::Future;
+    use grpcio::{
+        EnvBuilder, RpcContext, RpcStatus, Server, ServerBuilder, ServerCredentialsBuilder,
+        UnarySink,
+    };
+    use libra_types::proto::types::{UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse};
+    use std::{net::TcpListener, thread};
+
+    static CERT: &[u8] = include_bytes!("fixtures/localhost.crt");
+    static KEY: &[u8] = include_bytes!("fixtures/localhost.key");
+
+    /// Answers every call, so that a call failing otherwise tells that the connection failed.
+    #[derive(Clone)]
+    struct UnimplementedAdmissionControl;
+
+    impl AdmissionControl for UnimplementedAdmissionControl {
+        fn submit_transaction(
+            &mut self,
+            ctx: RpcContext<'_>,
+            _req: SubmitTransactionRequest,
+            sink: UnarySink<SubmitTransactionResponse>,
+        ) {
+            let status = RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None);
+            ctx.spawn(sink.fail(status).map_err(|_| ()));
+        }
+
+        fn update_to_latest_ledger(
+            &mut self,
+            ctx: RpcContext<'_>,
+            _req: UpdateToLatestLedgerRequest,
+            sink: UnarySink<UpdateToLatestLedgerResponse>,
+        ) {
+            let status = RpcStatus::new(RpcStatusCode::UNIMPLEMENTED, None);
+            ctx.spawn(sink.fail(status).map_err(|_| ()));
+        }
+    }
+
+    /// Starts an admission control serving TLS with the self-signed certificate of `localhost`.
+    fn start_tls_server() -> (Server, u16) {
+        let credentials = ServerCredentialsBuilder::new()
+            .add_cert(CERT.to_vec(), KEY.to_vec())
+            .build();
+        let mut server = ServerBuilder::new(Arc::new(EnvBuilder::new().build()))
+            .register_service(create_admission_control(UnimplementedAdmissionControl))
+            .bind_secure("localhost", 0, credentials)
+            .build()
+            .unwrap();
+        server.start();
+        let (_, port) = server.bind_addrs()[0];
+        (server, port)
+    }
+
+    /// Calls admission control at `localhost:port` through `config`.
+    fn call(config: &ConnectionConfig, port: u16) -> Error {
+        let env = Arc::new(EnvBuilder::new().build());
+        let client = AdmissionControlClient::new(config.connect(env, "localhost", port).unwrap());
+        let error = client
+            .update_to_latest_ledger(&UpdateToLatestLedgerRequest::default())
+            .unwrap_err();
+        config.explain("localhost", port, error.into())
+    }
+
+    fn ca_cert_file() -> PathBuf {
+        [
+            env!("CARGO_MANIFEST_DIR"),
+            "src",
+            "fixtures",
+            "localhost.crt",
+        ]
+        .iter()
+        .collect()
+    }
+
+    #[test]
+    fn test_tls_with_custom_ca() {
+        let (_server, port) = start_tls_server();
+        let config = ConnectionConfig {
+            ca_cert_file: Some(ca_cert_file()),
+            ..ConnectionConfig::default()
+        };
+        // the call reaches the server
+        match call(&config, port).downcast_ref::<grpcio::Error>() {
+            Some(grpcio::Error::RpcFailure(status)) => {
+                assert_eq!(status.status, RpcStatusCode::UNIMPLEMENTED)
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_tls_handshake_failure() {
+        let (_server, port) = start_tls_server();
+        // the self-signed certificate is not trusted by the default roots
+        let config = ConnectionConfig {
+            tls: true,
+            ..ConnectionConfig::default()
+        };
+        let endpoint = format!("localhost:{}", port);
+        assert_eq!(
+            call(&config, port).downcast_ref::<ConnectionError>(),
+            Some(&ConnectionError::TlsHandshake(endpoint))
+        );
+    }
+
+    #[test]
+    fn test_connection_refused() {
+        let port = TcpListener::bind("localhost:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = ConnectionConfig {
+            ca_cert_file: Some(ca_cert_file()),
+            ..ConnectionConfig::default()
+        };
+        match call(&config, port).downcast_ref::<ConnectionError>() {
+            Some(ConnectionError::Refused(endpoint, _)) => {
+                assert_eq!(endpoint, &format!("localhost:{}", port))
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_proxy_failure() {
+        let (_server, port) = start_tls_server();
+        // a proxy refusing every tunnel
+        let proxy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let proxy_address = proxy.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            for stream in proxy.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut buf = [0u8; 1024];
+                let _ = stream.read(&mut buf);
+                let _ = stream.write_all(b"HTTP/1.1 403 Forbidden\r\n\r\n");
+            }
+        });
+        let config = ConnectionConfig {
+            ca_cert_file: Some(ca_cert_file()),
+            proxy: Some(proxy_address.clone()),
+            ..ConnectionConfig::default()
+        };
+        match call(&config, port).downcast_ref::<ConnectionError>() {
+            Some(ConnectionError::Proxy(proxy, _, reason)) => {
+                assert_eq!(proxy, &proxy_address);
+                assert!(reason.contains("403"));
+            }
+            error => panic!("unexpected error {:?}", error),
+        }
+    }
+
+    #[test]
+    fn test_proxy_address() {
+        assert_eq!(proxy_url("proxy:3128"), "http://proxy:3128");
+        assert_eq!(proxy_url("http://proxy:3128"), "http://proxy:3128");
+        assert_eq!(proxy_address("proxy:3128"), "proxy:3128");
+        assert_eq!(proxy_address("http://proxy:3128/"), "proxy:3128");
+    }
+}
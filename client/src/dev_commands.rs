@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{client_proxy::ClientProxy, commands::*};
+use crate::{client_proxy::ClientProxy, commands::*, dev_flow};
 
 /// Major command for account related operations.
 pub struct DevCommand {}
@@ -18,6 +18,7 @@ impl Command for DevCommand {
             Box::new(DevCommandCompile {}),
             Box::new(DevCommandPublish {}),
             Box::new(DevCommandExecute {}),
+            Box::new(DevCommandPublishAndRun {}),
         ];
         subcommand_execute(&params[0], commands, client, &params[1..]);
     }
@@ -104,3 +105,39 @@ impl Command for DevCommandExecute {
         }
     }
 }
+
+/// Sub command to publish a move module and run a script using it
+pub struct DevCommandPublishAndRun {}
+
+impl Command for DevCommandPublishAndRun {
+    fn get_aliases(&self) -> Vec<&'static str> {
+        vec!["publish-and-run", "pr"]
+    }
+
+    fn get_params_help(&self) -> &'static str {
+        "<sender_account_address>|<sender_account_ref_id> <module_file_path> <script_file_path> [parameters]"
+    }
+
+    fn get_description(&self) -> &'static str {
+        "Compile and publish a move module, then compile and execute a script using it and print its events"
+    }
+
+    fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
+        if params.len() < 4 {
+            println!("Invalid number of arguments to publish and run");
+            return;
+        }
+        match dev_flow::publish_and_run(client, params[1], params[2], params[3], &params[4..]) {
+            Ok(events) => {
+                println!("Successfully finished execution");
+                if events.is_empty() {
+                    println!("no events emitted");
+                }
+                for event in &events {
+                    println!("{}", dev_flow::decode_event(event));
+                }
+            }
+            Err(e) => println!("{}", e),
+        }
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! The staged flow behind `dev publish-and-run`: compile and publish a module, then compile and
+//! run a script using it, and decode the events the script emitted. The steps talking to the
+//! compiler and the node are behind `DevBackend`, implemented by `ClientProxy`.
+
+use failure::prelude::*;
+use libra_types::{
+    account_config::{account_module_name, core_code_address, AccountEvent},
+    contract_event::ContractEvent,
+    language_storage::TypeTag,
+    vm_error::StatusCode,
+};
+use std::fmt;
+
+/// A stage of `dev publish-and-run`, in the order they run.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Stage {
+    CompileModule,
+    PublishModule,
+    CompileScript,
+    RunScript,
+}
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stage = match self {
+            Stage::CompileModule => "compile module",
+            Stage::PublishModule => "publish module",
+            Stage::CompileScript => "compile script",
+            Stage::RunScript => "run script",
+        };
+        write!(f, "{}", stage)
+    }
+}
+
+/// The failure of one stage of `dev publish-and-run`. The later stages are not run.
+#[derive(Debug, Fail)]
+#[fail(display = "Failed to {}: {}", stage, error)]
+pub(crate) struct StageError {
+    pub stage: Stage,
+    pub error: Error,
+}
+
+/// The steps of `dev publish-and-run` that need the compiler or the node.
+pub(crate) trait DevBackend {
+    /// Compiles the module or script at `file_path` for `sender` and returns the path of the
+    /// compiled program.
+    fn compile(&mut self, sender: &str, file_path: &str, is_module: bool) -> Result<String>;
+
+    /// Submits the compiled module at `path` and waits for it to be committed. Returns the VM
+    /// status of the transaction and the events it emitted.
+    fn publish(&mut self, sender: &str, path: &str) -> Result<(StatusCode, Vec<ContractEvent>)>;
+
+    /// Submits the compiled script at `path` with `args` and waits for it to be committed.
+    /// Returns the VM status of the transaction and the events it emitted.
+    fn run(
+        &mut self,
+        sender: &str,
+        path: &str,
+        args: &[&str],
+    ) -> Result<(StatusCode, Vec<ContractEvent>)>;
+}
+
+/// Publishes the module at `module_path` as `sender`, then runs the script at `script_path`,
+/// which may use the fresh module, with `args`. Returns the events emitted by the script.
+pub(crate) fn publish_and_run(
+    backend: &mut dyn DevBackend,
+    sender: &str,
+    module_path: &str,
+    script_path: &str,
+    args: &[&str],
+) -> std::result::Result<Vec<ContractEvent>, StageError> {
+    let compiled_module = run_stage(Stage::CompileModule, || {
+        backend.compile(sender, module_path, true /* is_module */)
+    })?;
+    run_stage(Stage::PublishModule, || {
+        check_executed(backend.publish(sender, &compiled_module)?)
+    })?;
+    let compiled_script = run_stage(Stage::CompileScript, || {
+        backend.compile(sender, script_path, false /* is_module */)
+    })?;
+    run_stage(Stage::RunScript, || {
+        check_executed(backend.run(sender, &compiled_script, args)?)
+    })
+}
+
+fn run_stage<T, F>(stage: Stage, f: F) -> std::result::Result<T, StageError>
+where
+    F: FnOnce() -> Result<T>,
+{
+    f().map_err(|error| StageError { stage, error })
+}
+
+fn check_executed(
+    (status, events): (StatusCode, Vec<ContractEvent>),
+) -> Result<Vec<ContractEvent>> {
+    ensure!(
+        status == StatusCode::EXECUTED,
+        "Transaction committed with VM status {:?}",
+        status
+    );
+    Ok(events)
+}
+
+/// Renders `event` for display. Payment events of the account module are decoded, any other
+/// event is shown with its type and the hex of its data.
+pub(crate) fn decode_event(event: &ContractEvent) -> String {
+    if let TypeTag::Struct(tag) = event.type_tag() {
+        let is_payment_event = tag.address == core_code_address()
+            && tag.module.as_ident_str() == account_module_name()
+            && (tag.name.as_str() == "SentPaymentEvent"
+                || tag.name.as_str() == "ReceivedPaymentEvent");
+        if is_payment_event {
+            if let Ok(account_event) = AccountEvent::try_from(event.event_data()) {
+                return format!(
+                    "{} {{ amount: {}, account: {} }}",
+                    tag.name,
+                    account_event.amount(),
+                    account_event.account()
+                );
+            }
+        }
+    }
+    format!(
+        "{:?} {{ data: 0x{} }}",
+        event.type_tag(),
+        hex::encode(event.event_data())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_types::{
+        account_address::AccountAddress,
+        event::{EventKey, EVENT_KEY_LENGTH},
+        identifier::Identifier,
+        language_storage::StructTag,
+    };
+
+    /// Records the steps run and fails the one of `fail_stage`, if any.
+    struct MockBackend {
+        steps: Vec<String>,
+        fail_stage: Option<Stage>,
+        run_status: StatusCode,
+    }
+
+    impl MockBackend {
+        fn new(fail_stage: Option<Stage>) -> Self {
+            Self {
+                steps: vec![],
+                fail_stage,
+                run_status: StatusCode::EXECUTED,
+            }
+        }
+
+        fn step(&mut self, stage: Stage, step: String) -> Result<()> {
+            self.steps.push(step);
+            ensure!(self.fail_stage != Some(stage), "{} broke", stage);
+            Ok(())
+        }
+    }
+
+    impl DevBackend for MockBackend {
+        fn compile(&mut self, _sender: &str, file_path: &str, is_module: bool) -> Result<String> {
+            let stage = if is_module {
+                Stage::CompileModule
+            } else {
+                Stage::CompileScript
+            };
+            self.step(stage, format!("compile {}", file_path))?;
+            Ok(format!("{}.mv", file_path))
+        }
+
+        fn publish(
+            &mut self,
+            _sender: &str,
+            path: &str,
+        ) -> Result<(StatusCode, Vec<ContractEvent>)> {
+            self.step(Stage::PublishModule, format!("publish {}", path))?;
+            Ok((StatusCode::EXECUTED, vec![]))
+        }
+
+        fn run(
+            &mut self,
+            _sender: &str,
+            path: &str,
+            args: &[&str],
+        ) -> Result<(StatusCode, Vec<ContractEvent>)> {
+            self.step(Stage::RunScript, format!("run {} {}", path, args.join(" ")))?;
+            Ok((self.run_status, vec![test_event("Ping", vec![1, 2])]))
+        }
+    }
+
+    fn test_event(name: &str, event_data: Vec<u8>) -> ContractEvent {
+        let type_tag = TypeTag::Struct(StructTag {
+            address: core_code_address(),
+            module: account_module_name().into(),
+            name: Identifier::new(name).unwrap(),
+            type_params: vec![],
+        });
+        ContractEvent::new(
+            EventKey::new([0; EVENT_KEY_LENGTH]),
+            0,
+            type_tag,
+            event_data,
+        )
+    }
+
+    #[test]
+    fn test_publish_and_run() {
+        let mut backend = MockBackend::new(None);
+        let events = publish_and_run(&mut backend, "0", "m.mvir", "s.mvir", &["1", "2"]).unwrap();
+        assert_eq!(
+            backend.steps,
+            vec![
+                "compile m.mvir",
+                "publish m.mvir.mv",
+                "compile s.mvir",
+                "run s.mvir.mv 1 2"
+            ]
+        );
+        assert_eq!(events.len(), 1);
+    }
+
+    #[test]
+    fn test_publish_and_run_stops_at_failed_stage() {
+        for (num_steps, stage) in [
+            Stage::CompileModule,
+            Stage::PublishModule,
+            Stage::CompileScript,
+            Stage::RunScript,
+        ]
+        .iter()
+        .enumerate()
+        {
+            let mut backend = MockBackend::new(Some(*stage));
+            let err = publish_and_run(&mut backend, "0", "m.mvir", "s.mvir", &[]).unwrap_err();
+            assert_eq!(err.stage, *stage);
+            assert_eq!(backend.steps.len(), num_steps + 1);
+        }
+
+        // A transaction committed with an error fails its stage too, naming the VM status.
+        let mut backend = MockBackend::new(None);
+        backend.run_status = StatusCode::MISSING_DEPENDENCY;
+        let err = publish_and_run(&mut backend, "0", "m.mvir", "s.mvir", &[]).unwrap_err();
+        assert_eq!(err.stage, Stage::RunScript);
+        assert!(err.to_string().contains("MISSING_DEPENDENCY"));
+    }
+
+    #[test]
+    fn test_decode_event() {
+        let account = AccountAddress::random();
+        let data = lcs::to_bytes(&(100u64, account)).unwrap();
+        assert_eq!(
+            decode_event(&test_event("SentPaymentEvent", data)),
+            format!("SentPaymentEvent {{ amount: 100, account: {} }}", account)
+        );
+
+        // Unknown event types, and known ones that fail to decode, fall back to hex.
+        assert!(decode_event(&test_event("Ping", vec![0xab, 0xcd])).ends_with("{ data: 0xabcd }"));
+        assert!(
+            decode_event(&test_event("ReceivedPaymentEvent", vec![0xab]))
+                .ends_with("{ data: 0xab }")
+        );
+    }
+}
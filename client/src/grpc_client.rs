@@ -1,7 +1,7 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::AccountData;
+use crate::{connection::ConnectionConfig, AccountData};
 use admission_control_proto::{
     proto::admission_control::{
         AdmissionControlClient, SubmitTransactionRequest,
@@ -11,7 +11,7 @@ use admission_control_proto::{
 };
 use failure::prelude::*;
 use futures::Future;
-use grpcio::{CallOption, ChannelBuilder, EnvBuilder};
+use grpcio::{CallOption, EnvBuilder};
 use libra_crypto::ed25519::*;
 use libra_logger::prelude::*;
 use libra_types::{
@@ -32,28 +32,48 @@ use std::sync::Arc;
 
 const MAX_GRPC_RETRY_COUNT: u64 = 1;
 
+/// A transaction admission control answered with a rejection, as opposed to one that may not have
+/// reached it.
+#[derive(Debug, Fail)]
+#[fail(display = "Transaction failed with {}", _0)]
+pub(crate) struct TransactionRejected(pub String);
+
 /// Struct holding dependencies of client.
 pub struct GRPCClient {
     client: AdmissionControlClient,
     validator_verifier: Arc<ValidatorVerifier>,
+    host: String,
+    port: u16,
+    connection_config: ConnectionConfig,
 }
 
 impl GRPCClient {
     /// Construct a new Client instance.
-    pub fn new(host: &str, port: u16, validator_verifier: Arc<ValidatorVerifier>) -> Result<Self> {
-        let conn_addr = format!("{}:{}", host, port);
-
+    pub fn new(
+        host: &str,
+        port: u16,
+        validator_verifier: Arc<ValidatorVerifier>,
+        connection_config: ConnectionConfig,
+    ) -> Result<Self> {
         // Create a GRPC client
         let env = Arc::new(EnvBuilder::new().name_prefix("grpc-client-").build());
-        let ch = ChannelBuilder::new(env).connect(&conn_addr);
+        let ch = connection_config.connect(env, host, port)?;
         let client = AdmissionControlClient::new(ch);
 
         Ok(GRPCClient {
             client,
             validator_verifier,
+            host: host.to_string(),
+            port,
+            connection_config,
         })
     }
 
+    /// Replaces `error` by the reason the validator can't be reached, if that's what it is.
+    pub(crate) fn explain_error(&self, error: Error) -> Error {
+        self.connection_config.explain(&self.host, self.port, error)
+    }
+
     /// Submits a transaction and bumps the sequence number for the sender, pass in `None` for
     /// sender_account if sender's address is not managed by the client.
     pub fn submit_transaction(
@@ -71,31 +91,32 @@ impl GRPCClient {
         let completed_resp = SubmitTransactionResponse::try_from(resp?)?;
 
         if let Some(ac_status) = completed_resp.ac_status {
-            if ac_status == AdmissionControlStatus::Accepted {
+            // A retried submission may find the transaction already committed.
+            if ac_status == AdmissionControlStatus::Accepted
+                || ac_status == AdmissionControlStatus::Committed
+            {
                 if let Some(sender_account) = sender_account_opt {
                     // Bump up sequence_number if transaction is accepted.
                     sender_account.sequence_number += 1;
                 }
             } else {
-                bail!("Transaction failed with AC status: {:?}", ac_status,);
+                return Err(TransactionRejected(format!("AC status: {:?}", ac_status)).into());
             }
         } else if let Some(vm_error) = completed_resp.vm_error {
             if vm_error.major_status == StatusCode::SEQUENCE_NUMBER_TOO_OLD {
                 if let Some(sender_account) = sender_account_opt {
                     sender_account.sequence_number =
                         self.get_sequence_number(sender_account.address)?;
-                    bail!(
-                        "Transaction failed with vm status: {:?}, please retry your transaction.",
+                    return Err(TransactionRejected(format!(
+                        "vm status: {:?}, please retry your transaction.",
                         vm_error
-                    );
+                    ))
+                    .into());
                 }
             }
-            bail!("Transaction failed with vm status: {:?}", vm_error);
+            return Err(TransactionRejected(format!("vm status: {:?}", vm_error)).into());
         } else if let Some(mempool_error) = completed_resp.mempool_error {
-            bail!(
-                "Transaction failed with mempool status: {:?}",
-                mempool_error,
-            );
+            return Err(TransactionRejected(format!("mempool status: {:?}", mempool_error)).into());
         } else {
             bail!(
                 "Malformed SubmitTransactionResponse which has no status set, {:?}",
@@ -208,6 +229,47 @@ impl GRPCClient {
         ))
     }
 
+    /// Get the latest account state blobs of `addresses` from validator, in the same order, `None`
+    /// for the accounts that don't exist. All of them are verified against a single ledger info,
+    /// whose version is returned.
+    pub fn get_account_blobs(
+        &self,
+        addresses: Vec<AccountAddress>,
+    ) -> Result<(Vec<Option<AccountStateBlob>>, Version)> {
+        let req_item = RequestItem::GetAccountStates { addresses };
+
+        let mut response = self.get_with_proof_sync(vec![req_item])?;
+        let account_states_with_proof = response
+            .response_items
+            .remove(0)
+            .into_get_account_states_response()?;
+
+        Ok((
+            account_states_with_proof
+                .into_iter()
+                .map(|account_state_with_proof| account_state_with_proof.blob)
+                .collect(),
+            response.ledger_info_with_sigs.ledger_info().version(),
+        ))
+    }
+
+    /// Get the account state blob as of `version` from validator.
+    pub(crate) fn get_account_blob_by_version(
+        &self,
+        address: AccountAddress,
+        version: Version,
+    ) -> Result<Option<AccountStateBlob>> {
+        let req_item = RequestItem::GetAccountStateByVersion { address, version };
+
+        let mut response = self.get_with_proof_sync(vec![req_item])?;
+        let account_state_with_proof = response
+            .response_items
+            .remove(0)
+            .into_get_account_state_response()?;
+
+        Ok(account_state_with_proof.blob)
+    }
+
     /// Get transaction from validator by account and sequence number.
     pub fn get_txn_by_acc_seq(
         &self,
@@ -230,6 +292,33 @@ impl GRPCClient {
         Ok(txn_with_proof.map(|t| (t.transaction, t.events)))
     }
 
+    /// Get the VM status and the events of a committed transaction by account and sequence
+    /// number, `None` if the transaction is not committed yet.
+    pub fn get_txn_output_by_acc_seq(
+        &self,
+        account: AccountAddress,
+        sequence_number: u64,
+    ) -> Result<Option<(StatusCode, Vec<ContractEvent>)>> {
+        let req_item = RequestItem::GetAccountTransactionBySequenceNumber {
+            account,
+            sequence_number,
+            fetch_events: true,
+        };
+
+        let mut response = self.get_with_proof_sync(vec![req_item])?;
+        let (txn_with_proof, _) = response
+            .response_items
+            .remove(0)
+            .into_get_account_txn_by_seq_num_response()?;
+
+        Ok(txn_with_proof.map(|t| {
+            (
+                t.proof.transaction_info().major_status(),
+                t.events.unwrap_or_default(),
+            )
+        }))
+    }
+
     /// Get transactions in range (start_version..start_version + limit - 1) from validator.
     pub fn get_txn_by_range(
         &self,
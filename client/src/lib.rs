@@ -18,11 +18,18 @@ pub(crate) mod account_commands;
 pub mod client_proxy;
 /// Command struct to interact with client.
 pub mod commands;
+/// TLS and proxy settings of the connections to validator and faucet.
+pub mod connection;
 pub(crate) mod dev_commands;
+pub(crate) mod dev_flow;
 /// gRPC client wrapper to connect to validator.
 pub(crate) mod grpc_client;
+/// Local tracking of the sequence numbers of the transactions submitted and not committed yet.
+pub(crate) mod nonce_tracker;
 pub(crate) mod query_commands;
 pub(crate) mod transfer_commands;
+/// Structured results of the query commands.
+pub mod views;
 
 /// Struct used to store data for each created account.  We track the sequence number
 /// so we can create new transactions easily
@@ -2,10 +2,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use chrono::prelude::{SecondsFormat, Utc};
-use client::{client_proxy::ClientProxy, commands::*};
+use client::{
+    client_proxy::ClientProxy, commands::*, connection::ConnectionConfig, views::OutputFormat,
+};
 use libra_logger::set_default_global_logger;
 use rustyline::{config::CompletionType, error::ReadlineError, Config, Editor};
-use std::num::NonZeroU16;
+use std::{env, num::NonZeroU16, path::PathBuf};
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -52,6 +54,27 @@ struct Args {
     /// Verbose output.
     #[structopt(short = "v", long = "verbose")]
     pub verbose: bool,
+    /// Print the results of the query commands as JSON instead of text.
+    #[structopt(long)]
+    pub json: bool,
+    /// Connect to the validator over TLS, verifying its certificate against the default CAs.
+    /// Can also be set with LIBRA_CLIENT_TLS=1.
+    #[structopt(long)]
+    pub tls: bool,
+    /// PEM bundle of the CAs to verify the certificate of the validator with. Implies --tls.
+    #[structopt(long, parse(from_os_str), env = "LIBRA_CLIENT_CA_CERT_FILE")]
+    pub ca_cert_file: Option<PathBuf>,
+    /// HTTP CONNECT proxy to reach the validator and the faucet through, as host:port. If not
+    /// passed, the https_proxy and http_proxy environment variables are honored.
+    #[structopt(long, env = "LIBRA_CLIENT_PROXY")]
+    pub proxy: Option<String>,
+}
+
+/// Whether the environment variable `name` is set to a true value.
+fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
 }
 
 fn main() -> std::io::Result<()> {
@@ -63,7 +86,13 @@ fn main() -> std::io::Result<()> {
 
     let faucet_account_file = args.faucet_account_file.unwrap_or_else(|| "".to_string());
 
-    let mut client_proxy = ClientProxy::new(
+    let connection_config = ConnectionConfig {
+        tls: args.tls || env_flag("LIBRA_CLIENT_TLS"),
+        ca_cert_file: args.ca_cert_file,
+        proxy: args.proxy,
+    };
+
+    let mut client_proxy = ClientProxy::new_with_connection_config(
         &args.host,
         args.port.get(),
         &args.validator_set_file,
@@ -71,15 +100,19 @@ fn main() -> std::io::Result<()> {
         args.sync,
         args.faucet_server,
         args.mnemonic_file,
+        connection_config,
     )
     .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, &format!("{}", e)[..]))?;
+    if args.json {
+        client_proxy.set_output_format(OutputFormat::Json);
+    }
 
     // Test connection to validator
     let test_ret = client_proxy.test_validator_connection();
 
     if let Err(e) = test_ret {
         println!(
-            "Not able to connect to validator at {}:{}, error {:?}",
+            "Not able to connect to validator at {}:{}, error: {}",
             args.host, args.port, e
         );
         return Ok(());
@@ -0,0 +1,375 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Local tracking of the sequence numbers of the transactions submitted but not committed yet, so
+//! that transactions submitted back to back from an account get consecutive sequence numbers
+//! instead of the one still on chain. Submissions go through `SubmissionBackend`, implemented by
+//! `GRPCClient`.
+
+use crate::grpc_client::{GRPCClient, TransactionRejected};
+use admission_control_proto::proto::admission_control::SubmitTransactionRequest;
+use failure::prelude::*;
+use libra_types::account_address::AccountAddress;
+use std::collections::{BTreeSet, HashMap};
+
+/// The calls to admission control the tracker needs.
+pub(crate) trait SubmissionBackend {
+    /// Gets the sequence number of `address` on chain, i.e. the one of its next transaction to
+    /// commit.
+    fn sequence_number_on_chain(&mut self, address: AccountAddress) -> Result<u64>;
+
+    /// Submits `req`. A transaction admission control turned down fails with
+    /// `TransactionRejected`, any other error leaves it unknown whether it was accepted.
+    fn submit(&mut self, req: &SubmitTransactionRequest) -> Result<()>;
+}
+
+impl SubmissionBackend for GRPCClient {
+    fn sequence_number_on_chain(&mut self, address: AccountAddress) -> Result<u64> {
+        self.get_sequence_number(address)
+    }
+
+    fn submit(&mut self, req: &SubmitTransactionRequest) -> Result<()> {
+        self.submit_transaction(None, req)
+    }
+}
+
+/// Sequence numbers handed out for an account beyond the one on chain.
+#[derive(Debug)]
+struct AccountNonces {
+    // one past the highest sequence number handed out
+    next: u64,
+    // sequence numbers below `next` given back by rejected submissions, handed out first
+    released: BTreeSet<u64>,
+}
+
+/// Sequence numbers of the transactions submitted by the accounts of the wallet and not committed
+/// yet, by account.
+#[derive(Debug, Default)]
+pub(crate) struct NonceTracker {
+    accounts: HashMap<AccountAddress, AccountNonces>,
+}
+
+impl NonceTracker {
+    /// Hands out the sequence number of the next transaction of `address`, whose sequence number
+    /// on chain is `on_chain`: one given back by a rejected submission if any, else the highest
+    /// one handed out plus one, or `on_chain` if everything handed out is committed.
+    pub fn reserve(&mut self, address: AccountAddress, on_chain: u64) -> u64 {
+        self.reconcile(address, on_chain);
+        let nonces = self
+            .accounts
+            .entry(address)
+            .or_insert_with(|| AccountNonces {
+                next: on_chain,
+                released: BTreeSet::new(),
+            });
+        if let Some(sequence_number) = nonces.released.iter().next().cloned() {
+            nonces.released.remove(&sequence_number);
+            return sequence_number;
+        }
+        nonces.next += 1;
+        nonces.next - 1
+    }
+
+    /// Gives back `sequence_number` of `address` after its transaction was rejected for good, for
+    /// the next transaction to take it.
+    pub fn release(&mut self, address: AccountAddress, sequence_number: u64) {
+        if let Some(nonces) = self.accounts.get_mut(&address) {
+            if sequence_number >= nonces.next {
+                return;
+            }
+            if sequence_number + 1 == nonces.next {
+                nonces.next = sequence_number;
+                // the numbers given back right below are not taken anymore either
+                while nonces.next > 0 && nonces.released.remove(&(nonces.next - 1)) {
+                    nonces.next -= 1;
+                }
+            } else {
+                nonces.released.insert(sequence_number);
+            }
+        }
+    }
+
+    /// Forgets what `address` committed, now that its sequence number on chain is `on_chain`.
+    pub fn reconcile(&mut self, address: AccountAddress, on_chain: u64) {
+        let is_committed = match self.accounts.get_mut(&address) {
+            Some(nonces) => {
+                nonces.released = nonces.released.split_off(&on_chain);
+                on_chain >= nonces.next
+            }
+            None => false,
+        };
+        if is_committed {
+            self.accounts.remove(&address);
+        }
+    }
+
+    /// Forgets the sequence numbers handed out for `address`, its next transaction takes the
+    /// sequence number on chain.
+    pub fn reset(&mut self, address: &AccountAddress) {
+        self.accounts.remove(address);
+    }
+
+    /// Forgets the sequence numbers handed out for all the accounts.
+    pub fn reset_all(&mut self) {
+        self.accounts.clear();
+    }
+
+    /// Submits the transaction of `address` built by `build` for the sequence number handed out
+    /// to it, and returns that sequence number. The sequence number is given back if the
+    /// transaction fails to build or is rejected, and kept if it is unknown whether it was
+    /// accepted.
+    pub fn submit<B, F>(
+        &mut self,
+        backend: &mut B,
+        address: AccountAddress,
+        build: F,
+    ) -> Result<u64>
+    where
+        B: SubmissionBackend + ?Sized,
+        F: FnOnce(u64) -> Result<SubmitTransactionRequest>,
+    {
+        let on_chain = backend.sequence_number_on_chain(address)?;
+        let sequence_number = self.reserve(address, on_chain);
+        let req = match build(sequence_number) {
+            Ok(req) => req,
+            Err(error) => {
+                self.release(address, sequence_number);
+                return Err(error);
+            }
+        };
+        if let Err(error) = backend.submit(&req) {
+            if error.downcast_ref::<TransactionRejected>().is_some() {
+                self.release(address, sequence_number);
+            }
+            return Err(error);
+        }
+        Ok(sequence_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_crypto::{
+        ed25519::{compat, Ed25519PrivateKey, Ed25519PublicKey},
+        test_utils::KeyPair,
+    };
+    use libra_types::transaction::{
+        helpers::create_user_txn, Script, SignedTransaction, TransactionPayload,
+    };
+    use std::convert::TryFrom;
+
+    /// Admission control of a single account, committing the transactions accepted once all the
+    /// ones before are.
+    struct MockAdmissionControl {
+        on_chain: u64,
+        // accepted and not committed yet
+        mempool: BTreeSet<u64>,
+        // sequence numbers whose next submission is rejected
+        rejections: BTreeSet<u64>,
+        // whether the next submission fails to reach admission control
+        unreachable: bool,
+        submitted: Vec<u64>,
+    }
+
+    impl MockAdmissionControl {
+        fn new(on_chain: u64) -> Self {
+            Self {
+                on_chain,
+                mempool: BTreeSet::new(),
+                rejections: BTreeSet::new(),
+                unreachable: false,
+                submitted: vec![],
+            }
+        }
+
+        /// Commits the transactions accepted that directly follow the ones on chain.
+        fn commit(&mut self) {
+            while self.mempool.remove(&self.on_chain) {
+                self.on_chain += 1;
+            }
+        }
+    }
+
+    impl SubmissionBackend for MockAdmissionControl {
+        fn sequence_number_on_chain(&mut self, _address: AccountAddress) -> Result<u64> {
+            Ok(self.on_chain)
+        }
+
+        fn submit(&mut self, req: &SubmitTransactionRequest) -> Result<()> {
+            ensure!(!self.unreachable, "Unreachable");
+            let txn = SignedTransaction::try_from(req.transaction.clone().unwrap())?;
+            let sequence_number = txn.sequence_number();
+            self.submitted.push(sequence_number);
+            if self.rejections.remove(&sequence_number)
+                || sequence_number < self.on_chain
+                || self.mempool.contains(&sequence_number)
+            {
+                return Err(TransactionRejected(format!("{}", sequence_number)).into());
+            }
+            self.mempool.insert(sequence_number);
+            Ok(())
+        }
+    }
+
+    fn build_req(
+        key_pair: &KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+        address: AccountAddress,
+        sequence_number: u64,
+    ) -> Result<SubmitTransactionRequest> {
+        let txn = create_user_txn(
+            key_pair,
+            TransactionPayload::Script(Script::new(vec![], vec![])),
+            address,
+            sequence_number,
+            0,
+            0,
+            100,
+        )?;
+        let mut req = SubmitTransactionRequest::default();
+        req.transaction = Some(txn.into());
+        Ok(req)
+    }
+
+    fn submit(
+        tracker: &mut NonceTracker,
+        ac: &mut MockAdmissionControl,
+        key_pair: &KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
+        address: AccountAddress,
+    ) -> Result<u64> {
+        tracker.submit(ac, address, |sequence_number| {
+            build_req(key_pair, address, sequence_number)
+        })
+    }
+
+    #[test]
+    fn test_pipelined_submissions() {
+        let key_pair = KeyPair::from(compat::generate_keypair(None).0);
+        let address = AccountAddress::random();
+        let mut tracker = NonceTracker::default();
+        let mut ac = MockAdmissionControl::new(5);
+
+        // nothing commits in between, yet every submission takes the next sequence number
+        for expected in 5..8 {
+            assert_eq!(
+                submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+                expected
+            );
+        }
+
+        // once the chain catches up, the local numbers are forgotten
+        ac.commit();
+        tracker.reconcile(address, ac.on_chain);
+        assert!(tracker.accounts.is_empty());
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            8
+        );
+
+        // the chain is ahead of the local numbers, e.g. after transactions submitted elsewhere
+        ac.commit();
+        ac.on_chain = 12;
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            12
+        );
+        assert_eq!(ac.submitted, vec![5, 6, 7, 8, 12]);
+    }
+
+    #[test]
+    fn test_rejection_mid_stream() {
+        let key_pair = KeyPair::from(compat::generate_keypair(None).0);
+        let address = AccountAddress::random();
+        let mut tracker = NonceTracker::default();
+        let mut ac = MockAdmissionControl::new(0);
+        ac.rejections.insert(1);
+
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            0
+        );
+        let error = submit(&mut tracker, &mut ac, &key_pair, address).unwrap_err();
+        assert!(error.downcast_ref::<TransactionRejected>().is_some());
+        // the rejected number is taken by the next transaction, which unblocks the ones after it
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            1
+        );
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            2
+        );
+        ac.commit();
+        assert_eq!(ac.on_chain, 3);
+
+        // a number given back below others in flight is reused before handing out new ones
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            3
+        );
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            4
+        );
+        ac.mempool.remove(&3);
+        tracker.release(address, 3);
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            3
+        );
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            5
+        );
+        ac.commit();
+        assert_eq!(ac.on_chain, 6);
+        assert_eq!(ac.submitted, vec![0, 1, 1, 2, 3, 4, 3, 5]);
+    }
+
+    #[test]
+    fn test_unknown_outcome_keeps_sequence_number() {
+        let key_pair = KeyPair::from(compat::generate_keypair(None).0);
+        let address = AccountAddress::random();
+        let mut tracker = NonceTracker::default();
+        let mut ac = MockAdmissionControl::new(0);
+
+        ac.unreachable = true;
+        let error = submit(&mut tracker, &mut ac, &key_pair, address).unwrap_err();
+        assert!(error.downcast_ref::<TransactionRejected>().is_none());
+        // the transaction may have been accepted, so its number is not reused
+        ac.unreachable = false;
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            1
+        );
+
+        // until the sequence numbers are reset
+        tracker.reset(&address);
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, address).unwrap(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_accounts_tracked_separately() {
+        let key_pair = KeyPair::from(compat::generate_keypair(None).0);
+        let addresses = [AccountAddress::random(), AccountAddress::random()];
+        let mut tracker = NonceTracker::default();
+        let mut ac = MockAdmissionControl::new(0);
+
+        for address in &addresses {
+            assert_eq!(tracker.reserve(*address, 0), 0);
+            assert_eq!(tracker.reserve(*address, 0), 1);
+        }
+        tracker.release(addresses[0], 1);
+        assert_eq!(tracker.reserve(addresses[0], 0), 1);
+        assert_eq!(tracker.reserve(addresses[1], 0), 2);
+
+        tracker.reset_all();
+        assert_eq!(
+            submit(&mut tracker, &mut ac, &key_pair, addresses[1]).unwrap(),
+            0
+        );
+    }
+}
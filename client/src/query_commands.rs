@@ -1,9 +1,8 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{client_proxy::ClientProxy, commands::*};
-use libra_types::account_config::get_account_resource_or_default;
-use transaction_builder::get_transaction_name;
+use crate::{client_proxy::ClientProxy, commands::*, views::OutputFormat};
+use serde_json::json;
 
 /// Major command for query operations.
 pub struct QueryCommand {}
@@ -20,9 +19,11 @@ impl Command for QueryCommand {
             Box::new(QueryCommandGetBalance {}),
             Box::new(QueryCommandGetSeqNum {}),
             Box::new(QueryCommandGetLatestAccountState {}),
+            Box::new(QueryCommandGetAccountDiff {}),
             Box::new(QueryCommandGetTxnByAccountSeq {}),
             Box::new(QueryCommandGetTxnByRange {}),
             Box::new(QueryCommandGetEvent {}),
+            Box::new(QueryCommandGetBlockchainStatus {}),
         ];
 
         subcommand_execute(&params[0], commands, client, &params[1..]);
@@ -48,7 +49,7 @@ impl Command for QueryCommandGetBalance {
             return;
         }
         match client.get_balance(&params) {
-            Ok(balance) => println!("Balance is: {}", balance),
+            Ok(balance) => print_view(client, &balance),
             Err(e) => report_error("Failed to get balance", e),
         }
     }
@@ -69,9 +70,9 @@ impl Command for QueryCommandGetSeqNum {
          and reset current sequence number in CLI (optional, default is false)"
     }
     fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
-        println!(">> Getting current sequence number");
+        print_progress(client, "Getting current sequence number");
         match client.get_sequence_number(&params) {
-            Ok(sn) => println!("Sequence number is: {}", sn),
+            Ok(sequence_number) => print_view(client, &sequence_number),
             Err(e) => report_error("Error getting sequence number", e),
         }
     }
@@ -91,27 +92,42 @@ impl Command for QueryCommandGetLatestAccountState {
         "Get the latest state for an account"
     }
     fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
-        println!(">> Getting latest account state");
-        match client.get_latest_account_state(&params) {
-            Ok((acc, version)) => match get_account_resource_or_default(&acc) {
-                Ok(_) => println!(
-                    "Latest account state is: \n \
-                     Account: {:#?}\n \
-                     State: {:#?}\n \
-                     Blockchain Version: {}\n",
-                    client
-                        .get_account_address_from_parameter(params[1])
-                        .expect("Unable to parse account parameter"),
-                    acc,
-                    version,
-                ),
-                Err(e) => report_error("Error converting account blob to account resource", e),
-            },
+        print_progress(client, "Getting latest account state");
+        match client.get_account_view(&params) {
+            Ok(account) => print_view(client, &account),
             Err(e) => report_error("Error getting latest account state", e),
         }
     }
 }
 
+/// Sub command to diff the state of an account between two versions.
+pub struct QueryCommandGetAccountDiff {}
+
+impl Command for QueryCommandGetAccountDiff {
+    fn get_aliases(&self) -> Vec<&'static str> {
+        vec!["account_diff", "ad"]
+    }
+    fn get_params_help(&self) -> &'static str {
+        "<account_ref_id>|<account_address> <version1> <version2>"
+    }
+    fn get_description(&self) -> &'static str {
+        "Show how the state of an account changed between two versions"
+    }
+    fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
+        print_progress(client, "Getting account state diff");
+        match client.get_account_state_diff(&params) {
+            Ok(diff) => {
+                if diff.is_empty() {
+                    println!("No changes between {} and {}", params[2], params[3]);
+                } else {
+                    print!("{}", diff);
+                }
+            }
+            Err(e) => report_error("Error getting account state diff", e),
+        }
+    }
+}
+
 /// Sub command  to get transaction by account and sequence number from validator.
 pub struct QueryCommandGetTxnByAccountSeq {}
 
@@ -127,25 +143,13 @@ impl Command for QueryCommandGetTxnByAccountSeq {
          Optionally also fetch events emitted by this transaction."
     }
     fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
-        println!(">> Getting committed transaction by account and sequence number");
-        match client.get_committed_txn_by_acc_seq(&params) {
-            Ok(txn_and_events) => {
-                match txn_and_events {
-                    Some((comm_txn, events)) => {
-                        println!(
-                            "Committed transaction: {}",
-                            comm_txn.format_for_client(get_transaction_name)
-                        );
-                        if let Some(events_inner) = &events {
-                            println!("Events: ");
-                            for event in events_inner {
-                                println!("{}", event);
-                            }
-                        }
-                    }
-                    None => println!("Transaction not available"),
-                };
-            }
+        print_progress(
+            client,
+            "Getting committed transaction by account and sequence number",
+        );
+        match client.get_committed_txn_view_by_acc_seq(&params) {
+            Ok(Some(txn)) => print_view(client, &txn),
+            Ok(None) => println!("Transaction not available"),
             Err(e) => report_error(
                 "Error getting committed transaction by account and sequence number",
                 e,
@@ -169,30 +173,9 @@ impl Command for QueryCommandGetTxnByRange {
          Optionally also fetch events emitted by these transactions."
     }
     fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
-        println!(">> Getting committed transaction by range");
-        match client.get_committed_txn_by_range(&params) {
-            Ok(comm_txns_and_events) => {
-                // Note that this should never panic because we shouldn't return items
-                // if the version wasn't able to be parsed in the first place
-                let mut cur_version = params[1].parse::<u64>().expect("Unable to parse version");
-                for (txn, opt_events) in comm_txns_and_events {
-                    println!(
-                        "Transaction at version {}: {}",
-                        cur_version,
-                        txn.format_for_client(get_transaction_name)
-                    );
-                    if let Some(events) = opt_events {
-                        if events.is_empty() {
-                            println!("No events returned");
-                        } else {
-                            for event in events {
-                                println!("{}", event);
-                            }
-                        }
-                    }
-                    cur_version += 1;
-                }
-            }
+        print_progress(client, "Getting committed transaction by range");
+        match client.get_committed_txn_views_by_range(&params) {
+            Ok(txns) => print_views(client, &txns),
             Err(e) => report_error("Error getting committed transactions by range", e),
         }
     }
@@ -212,19 +195,42 @@ impl Command for QueryCommandGetEvent {
         "Get events by account and event type (sent|received)."
     }
     fn execute(&self, client: &mut ClientProxy, params: &[&str]) {
-        println!(">> Getting events by account and event type.");
-        match client.get_events_by_account_and_type(&params) {
-            Ok((events, last_event_state)) => {
-                if events.is_empty() {
-                    println!("No events returned");
-                } else {
-                    for event in events {
-                        println!("{}", event);
+        print_progress(client, "Getting events by account and event type.");
+        match client.get_event_views_by_account_and_type(&params) {
+            Ok((events, last_event_state)) => match client.output_format() {
+                OutputFormat::Text => {
+                    if events.is_empty() {
+                        println!("No events returned");
+                    } else {
+                        print_views(client, &events);
                     }
+                    println!("Last event state: {}", last_event_state);
                 }
-                println!("Last event state: {:#?}", last_event_state);
-            }
+                OutputFormat::Json => print_view(
+                    client,
+                    &json!({ "events": events, "last_event_state": last_event_state }),
+                ),
+            },
             Err(e) => report_error("Error getting events by access path", e),
         }
     }
 }
+
+/// Sub command to query the latest state of the ledger from validator.
+pub struct QueryCommandGetBlockchainStatus {}
+
+impl Command for QueryCommandGetBlockchainStatus {
+    fn get_aliases(&self) -> Vec<&'static str> {
+        vec!["status", "st"]
+    }
+    fn get_description(&self) -> &'static str {
+        "Get the latest version of the blockchain and the block it was committed in"
+    }
+    fn execute(&self, client: &mut ClientProxy, _params: &[&str]) {
+        print_progress(client, "Getting blockchain status");
+        match client.get_blockchain_status() {
+            Ok(status) => print_view(client, &status),
+            Err(e) => report_error("Error getting blockchain status", e),
+        }
+    }
+}
@@ -0,0 +1,581 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structured results of the query commands, so that programs embedding the client don't have to
+//! parse the output meant for humans. The serde form of each view is what the JSON output mode
+//! prints, and its `Display` implementation is what the interactive CLI prints.
+
+use libra_crypto::traits::ValidKey;
+use libra_types::{
+    account_address::AccountAddress,
+    account_config::{AccountEvent, AccountResource},
+    contract_event::ContractEvent,
+    ledger_info::LedgerInfo,
+    transaction::{Transaction, TransactionPayload, Version},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use transaction_builder::get_transaction_name;
+
+/// How the query commands print their results.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OutputFormat {
+    /// Text meant to be read by humans.
+    Text,
+    /// The serde form of the views, as JSON.
+    Json,
+}
+
+/// The state of an account at a version of the ledger.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct AccountView {
+    /// Address of the account, hex encoded.
+    pub address: String,
+    /// Balance of the account in micro libras.
+    pub balance: u64,
+    /// Sequence number of the next transaction sent by the account.
+    pub sequence_number: u64,
+    /// Authentication key of the account, hex encoded.
+    pub authentication_key: String,
+    /// Whether the account has delegated its key rotation capability.
+    pub delegated_key_rotation_capability: bool,
+    /// Whether the account has delegated its withdrawal capability.
+    pub delegated_withdrawal_capability: bool,
+    /// Number of payments sent by the account.
+    pub sent_events_count: u64,
+    /// Number of payments received by the account.
+    pub received_events_count: u64,
+    /// Version of the ledger the state was read at.
+    pub version: Version,
+}
+
+impl AccountView {
+    /// Builds the view of the account at `address` holding `resource` at `version`.
+    pub fn new(address: AccountAddress, resource: &AccountResource, version: Version) -> Self {
+        Self {
+            address: hex::encode(&address),
+            balance: resource.balance(),
+            sequence_number: resource.sequence_number(),
+            authentication_key: hex::encode(resource.authentication_key().as_bytes()),
+            delegated_key_rotation_capability: resource.delegated_key_rotation_capability(),
+            delegated_withdrawal_capability: resource.delegated_withdrawal_capability(),
+            sent_events_count: resource.sent_events().count(),
+            received_events_count: resource.received_events().count(),
+            version,
+        }
+    }
+}
+
+impl fmt::Display for AccountView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Account: {}", self.address)?;
+        writeln!(f, "\tBalance: {}", format_libras(self.balance))?;
+        writeln!(f, "\tSequence number: {}", self.sequence_number)?;
+        writeln!(f, "\tAuthentication key: {}", self.authentication_key)?;
+        writeln!(
+            f,
+            "\tDelegated key rotation capability: {}",
+            self.delegated_key_rotation_capability
+        )?;
+        writeln!(
+            f,
+            "\tDelegated withdrawal capability: {}",
+            self.delegated_withdrawal_capability
+        )?;
+        writeln!(f, "\tSent events: {}", self.sent_events_count)?;
+        writeln!(f, "\tReceived events: {}", self.received_events_count)?;
+        write!(f, "\tBlockchain version: {}", self.version)
+    }
+}
+
+/// The balance of an account.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BalanceView {
+    /// Address of the account, hex encoded.
+    pub address: String,
+    /// Balance of the account in micro libras.
+    pub balance: u64,
+}
+
+impl BalanceView {
+    /// Builds the view of the account at `address` holding `balance` micro libras.
+    pub fn new(address: AccountAddress, balance: u64) -> Self {
+        Self {
+            address: hex::encode(&address),
+            balance,
+        }
+    }
+}
+
+impl fmt::Display for BalanceView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Balance is: {}", format_libras(self.balance))
+    }
+}
+
+/// The sequence number of the next transaction sent by an account.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SequenceNumberView {
+    /// Address of the account, hex encoded.
+    pub address: String,
+    /// Sequence number of the next transaction sent by the account.
+    pub sequence_number: u64,
+}
+
+impl SequenceNumberView {
+    /// Builds the view of the account at `address` whose next transaction is `sequence_number`.
+    pub fn new(address: AccountAddress, sequence_number: u64) -> Self {
+        Self {
+            address: hex::encode(&address),
+            sequence_number,
+        }
+    }
+}
+
+impl fmt::Display for SequenceNumberView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Sequence number is: {}", self.sequence_number)
+    }
+}
+
+/// A committed transaction, along with the events it emitted if they were fetched.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct TransactionView {
+    /// Version of the transaction, if known.
+    pub version: Option<Version>,
+    /// The transaction itself.
+    pub transaction: TransactionDataView,
+    /// Events emitted by the transaction, `None` if they were not fetched.
+    pub events: Option<Vec<EventView>>,
+}
+
+impl TransactionView {
+    /// Builds the view of `transaction` committed at `version`, with the `events` it emitted.
+    pub fn new(
+        version: Option<Version>,
+        transaction: &Transaction,
+        events: Option<&[ContractEvent]>,
+    ) -> Self {
+        Self {
+            version,
+            transaction: TransactionDataView::new(transaction),
+            events: events.map(|events| {
+                events
+                    .iter()
+                    .map(|event| EventView::new(event, version))
+                    .collect()
+            }),
+        }
+    }
+}
+
+impl fmt::Display for TransactionView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.version {
+            Some(version) => write!(f, "Transaction at version {}: ", version)?,
+            None => write!(f, "Committed transaction: ")?,
+        }
+        write!(f, "{}", self.transaction)?;
+        if let Some(events) = &self.events {
+            if events.is_empty() {
+                write!(f, "\nNo events returned")?;
+            } else {
+                write!(f, "\nEvents: ")?;
+                for event in events {
+                    write!(f, "\n{}", event)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The content of a transaction, by kind of transaction.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransactionDataView {
+    /// A transaction submitted by a user.
+    User {
+        /// Address of the sender, hex encoded.
+        sender: String,
+        /// Sequence number of the transaction.
+        sequence_number: u64,
+        /// Name of the script run by the transaction, or what else its payload is.
+        script: String,
+        /// Arguments of the script.
+        args: Vec<String>,
+        /// Maximum amount of gas the transaction could use.
+        max_gas_amount: u64,
+        /// Price paid per unit of gas.
+        gas_unit_price: u64,
+        /// Time after which the transaction could not be committed, in seconds since the epoch.
+        expiration_time: u64,
+        /// Public key of the sender, hex encoded.
+        public_key: String,
+    },
+    /// A transaction applying a write set, only used for genesis.
+    WriteSet,
+    /// A transaction updating the block metadata.
+    BlockMetadata,
+}
+
+impl TransactionDataView {
+    fn new(transaction: &Transaction) -> Self {
+        match transaction {
+            Transaction::UserTransaction(txn) => {
+                let (script, args) = match txn.payload() {
+                    TransactionPayload::Program => ("deprecated".to_string(), vec![]),
+                    TransactionPayload::WriteSet(_) => ("genesis".to_string(), vec![]),
+                    TransactionPayload::Script(script) => (
+                        get_transaction_name(script.code()),
+                        script
+                            .args()
+                            .iter()
+                            .map(|arg| format!("{:?}", arg))
+                            .collect(),
+                    ),
+                    TransactionPayload::Module(_) => ("module publishing".to_string(), vec![]),
+                };
+                TransactionDataView::User {
+                    sender: hex::encode(&txn.sender()),
+                    sequence_number: txn.sequence_number(),
+                    script,
+                    args,
+                    max_gas_amount: txn.max_gas_amount(),
+                    gas_unit_price: txn.gas_unit_price(),
+                    expiration_time: txn.expiration_time().as_secs(),
+                    public_key: hex::encode(txn.public_key().to_bytes()),
+                }
+            }
+            Transaction::WriteSet(_) => TransactionDataView::WriteSet,
+            Transaction::BlockMetadata(_) => TransactionDataView::BlockMetadata,
+        }
+    }
+}
+
+impl fmt::Display for TransactionDataView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransactionDataView::User {
+                sender,
+                sequence_number,
+                script,
+                args,
+                max_gas_amount,
+                gas_unit_price,
+                expiration_time,
+                public_key,
+            } => {
+                writeln!(f, "UserTransaction {{")?;
+                writeln!(f, "\tsender: {},", sender)?;
+                writeln!(f, "\tsequence_number: {},", sequence_number)?;
+                writeln!(f, "\tscript: {},", script)?;
+                writeln!(f, "\targs: [{}],", args.join(", "))?;
+                writeln!(f, "\tmax_gas_amount: {},", max_gas_amount)?;
+                writeln!(f, "\tgas_unit_price: {},", gas_unit_price)?;
+                writeln!(f, "\texpiration_time: {}s,", expiration_time)?;
+                writeln!(f, "\tpublic_key: {},", public_key)?;
+                write!(f, "}}")
+            }
+            TransactionDataView::WriteSet => write!(f, "genesis"),
+            TransactionDataView::BlockMetadata => write!(f, "block_metadata"),
+        }
+    }
+}
+
+/// An event emitted by a committed transaction.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EventView {
+    /// Key of the event stream the event belongs to, hex encoded.
+    pub key: String,
+    /// Sequence number of the event in its stream.
+    pub sequence_number: u64,
+    /// Version of the transaction that emitted the event, if known.
+    pub transaction_version: Option<Version>,
+    /// Type of the event data.
+    pub type_tag: String,
+    /// Data of the event, hex encoded.
+    pub data: String,
+    /// The payment the event is about, if its data decodes as one.
+    pub payment: Option<PaymentView>,
+}
+
+impl EventView {
+    /// Builds the view of `event`, emitted by the transaction at `transaction_version`.
+    pub fn new(event: &ContractEvent, transaction_version: Option<Version>) -> Self {
+        Self {
+            key: hex::encode(event.key().as_bytes()),
+            sequence_number: event.sequence_number(),
+            transaction_version,
+            type_tag: format!("{:?}", event.type_tag()),
+            data: hex::encode(event.event_data()),
+            payment: AccountEvent::try_from(event.event_data())
+                .ok()
+                .map(|account_event| PaymentView {
+                    account: hex::encode(&account_event.account()),
+                    amount: account_event.amount(),
+                }),
+        }
+    }
+}
+
+impl fmt::Display for EventView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Event {{ key: {}, sequence_number: {}, ",
+            self.key, self.sequence_number
+        )?;
+        if let Some(version) = self.transaction_version {
+            write!(f, "transaction_version: {}, ", version)?;
+        }
+        write!(f, "type: {}, ", self.type_tag)?;
+        match &self.payment {
+            Some(payment) => write!(
+                f,
+                "account: {}, amount: {} }}",
+                payment.account,
+                format_libras(payment.amount)
+            ),
+            None => write!(f, "data: {} }}", self.data),
+        }
+    }
+}
+
+/// The payment a sent or received event is about.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PaymentView {
+    /// Address of the receiver of a sent payment or of the sender of a received one, hex
+    /// encoded.
+    pub account: String,
+    /// Amount of the payment in micro libras.
+    pub amount: u64,
+}
+
+/// The latest state of the ledger known to the validator.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct BlockchainStatusView {
+    /// Latest committed version.
+    pub version: Version,
+    /// Epoch of the latest committed block.
+    pub epoch: u64,
+    /// Round of the latest committed block.
+    pub round: u64,
+    /// Timestamp of the latest committed block in microseconds.
+    pub timestamp_usecs: u64,
+    /// Root hash of the transaction accumulator at the latest version, hex encoded.
+    pub accumulator_root_hash: String,
+}
+
+impl BlockchainStatusView {
+    /// Builds the view of the ledger described by `ledger_info`.
+    pub fn new(ledger_info: &LedgerInfo) -> Self {
+        Self {
+            version: ledger_info.version(),
+            epoch: ledger_info.epoch(),
+            round: ledger_info.round(),
+            timestamp_usecs: ledger_info.timestamp_usecs(),
+            accumulator_root_hash: format!("{:x}", ledger_info.transaction_accumulator_hash()),
+        }
+    }
+}
+
+impl fmt::Display for BlockchainStatusView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Blockchain version: {}", self.version)?;
+        writeln!(f, "\tEpoch: {}", self.epoch)?;
+        writeln!(f, "\tRound: {}", self.round)?;
+        writeln!(f, "\tTimestamp: {}us", self.timestamp_usecs)?;
+        write!(f, "\tAccumulator root hash: {}", self.accumulator_root_hash)
+    }
+}
+
+/// Formats an amount of micro libras as libras.
+pub(crate) fn format_libras(micro_libras: u64) -> String {
+    format!(
+        "{}.{:0>6}",
+        micro_libras / 1_000_000,
+        micro_libras % 1_000_000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libra_crypto::{ed25519::*, HashValue};
+    use libra_types::{
+        block_metadata::BlockMetadata,
+        byte_array::ByteArray,
+        event::{EventHandle, EventKey},
+        language_storage::TypeTag,
+        transaction::{RawTransaction, Script, TransactionArgument},
+    };
+    use serde_json::json;
+    use std::{collections::BTreeMap, time::Duration};
+
+    #[test]
+    fn test_account_view_schema() {
+        let address = AccountAddress::new([1; 32]);
+        let resource = AccountResource::new(
+            2_500_000,
+            7,
+            ByteArray::new(vec![0xab; 2]),
+            false,
+            true,
+            EventHandle::new(EventKey::new([2; 32]), 3),
+            EventHandle::new(EventKey::new([3; 32]), 4),
+        );
+        let view = AccountView::new(address, &resource, 42);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "address": hex::encode([1; 32]),
+                "balance": 2_500_000,
+                "sequence_number": 7,
+                "authentication_key": "abab",
+                "delegated_key_rotation_capability": false,
+                "delegated_withdrawal_capability": true,
+                "sent_events_count": 3,
+                "received_events_count": 4,
+                "version": 42,
+            })
+        );
+        assert!(view.to_string().contains("Balance: 2.500000"));
+    }
+
+    #[test]
+    fn test_balance_view_schema() {
+        let view = BalanceView::new(AccountAddress::new([1; 32]), 2_500_000);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "address": hex::encode([1; 32]),
+                "balance": 2_500_000,
+            })
+        );
+        assert_eq!(view.to_string(), "Balance is: 2.500000");
+    }
+
+    #[test]
+    fn test_sequence_number_view_schema() {
+        let view = SequenceNumberView::new(AccountAddress::new([1; 32]), 7);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "address": hex::encode([1; 32]),
+                "sequence_number": 7,
+            })
+        );
+        assert_eq!(view.to_string(), "Sequence number is: 7");
+    }
+
+    #[test]
+    fn test_transaction_view_schema() {
+        let (private_key, public_key) = compat::generate_keypair(None);
+        let raw_txn = RawTransaction::new_script(
+            AccountAddress::new([1; 32]),
+            5,
+            Script::new(vec![], vec![TransactionArgument::U64(10)]),
+            1_000,
+            2,
+            Duration::from_secs(100),
+        );
+        let txn = raw_txn.sign(&private_key, public_key.clone()).unwrap();
+        let event = ContractEvent::new(EventKey::new([2; 32]), 0, TypeTag::Bool, vec![0xcd]);
+        let view = TransactionView::new(
+            Some(9),
+            &Transaction::UserTransaction(txn.into_inner()),
+            Some(&[event][..]),
+        );
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "version": 9,
+                "transaction": {
+                    "type": "user",
+                    "sender": hex::encode([1; 32]),
+                    "sequence_number": 5,
+                    "script": get_transaction_name(&[]),
+                    "args": ["{U64: 10}"],
+                    "max_gas_amount": 1_000,
+                    "gas_unit_price": 2,
+                    "expiration_time": 100,
+                    "public_key": hex::encode(public_key.to_bytes()),
+                },
+                "events": [{
+                    "key": hex::encode([2; 32]),
+                    "sequence_number": 0,
+                    "transaction_version": 9,
+                    "type_tag": "Bool",
+                    "data": "cd",
+                    "payment": null,
+                }],
+            })
+        );
+
+        let block_metadata = BlockMetadata::new(
+            HashValue::zero(),
+            0,
+            BTreeMap::new(),
+            AccountAddress::new([0; 32]),
+        );
+        let view = TransactionView::new(None, &Transaction::BlockMetadata(block_metadata), None);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "version": null,
+                "transaction": { "type": "block_metadata" },
+                "events": null,
+            })
+        );
+    }
+
+    #[test]
+    fn test_event_view_schema() {
+        let sender = AccountAddress::new([4; 32]);
+        let mut data = 1_000_000u64.to_le_bytes().to_vec();
+        data.extend_from_slice(sender.as_ref());
+        let event = ContractEvent::new(
+            EventKey::new_from_address(&AccountAddress::new([1; 32]), 1),
+            3,
+            TypeTag::Bool,
+            data.clone(),
+        );
+        let view = EventView::new(&event, None);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "key": hex::encode(event.key().as_bytes()),
+                "sequence_number": 3,
+                "transaction_version": null,
+                "type_tag": "Bool",
+                "data": hex::encode(&data),
+                "payment": {
+                    "account": hex::encode([4; 32]),
+                    "amount": 1_000_000,
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_blockchain_status_view_schema() {
+        let ledger_info = LedgerInfo::genesis();
+        let view = BlockchainStatusView::new(&ledger_info);
+        assert_eq!(
+            serde_json::to_value(&view).unwrap(),
+            json!({
+                "version": ledger_info.version(),
+                "epoch": ledger_info.epoch(),
+                "round": ledger_info.round(),
+                "timestamp_usecs": ledger_info.timestamp_usecs(),
+                "accumulator_root_hash": format!("{:x}", ledger_info.transaction_accumulator_hash()),
+            })
+        );
+        assert_eq!(
+            serde_json::from_value::<BlockchainStatusView>(serde_json::to_value(&view).unwrap())
+                .unwrap(),
+            view
+        );
+    }
+}
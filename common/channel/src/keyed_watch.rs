@@ -0,0 +1,157 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! keyed_watch provides a channel for updates of which only the latest value per key matters,
+//! e.g. commit notifications or reloaded configs. A value inserted for a key that has not been
+//! received yet replaces the pending one (the updates are coalesced), so a slow receiver never
+//! builds up a backlog of more than one value per key.
+//! The receiver gets `(key, value)` pairs with the keys in the order they first became pending.
+use failure::prelude::*;
+use futures::{
+    stream::{FusedStream, Stream},
+    task::{Context, Poll, Waker},
+};
+use libra_metrics::IntCounterVec;
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+/// SharedState is a data structure private to this module which is
+/// shared by the senders and the receiver.
+struct SharedState<K: Eq + Hash + Clone, V> {
+    /// The latest value inserted for each key that hasn't been received yet
+    pending: HashMap<K, V>,
+    /// The keys of `pending`, in the order they became pending
+    order: VecDeque<K>,
+    /// Waker of the receiver task, woken up when any key gets updated
+    waker: Option<Waker>,
+    /// Number of senders alive, the stream terminates once it drops to 0 and `pending` is empty
+    num_senders: usize,
+    /// A boolean which tracks whether the receiver has dropped
+    receiver_dropped: bool,
+    /// A boolean which tracks whether the stream has terminated
+    stream_terminated: bool,
+    /// Counts the values "inserted", "coalesced" (replaced before being received) and "received"
+    counters: Option<&'static IntCounterVec>,
+}
+
+impl<K: Eq + Hash + Clone, V> SharedState<K, V> {
+    fn inc_counter(&self, label: &str) {
+        if let Some(c) = self.counters {
+            c.with_label_values(&[label]).inc();
+        }
+    }
+}
+
+/// The sending end of the keyed_watch channel. It can be cloned to have several producers.
+pub struct Sender<K: Eq + Hash + Clone, V> {
+    shared_state: Arc<Mutex<SharedState<K, V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V> Sender<K, V> {
+    /// Sets the value of `key`, replacing the value still pending for it if any. This is a
+    /// synchronous call.
+    pub fn insert(&self, key: K, value: V) -> failure::Result<()> {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        ensure!(!shared_state.receiver_dropped, "Channel is closed");
+        shared_state.inc_counter("inserted");
+        if shared_state.pending.insert(key.clone(), value).is_some() {
+            shared_state.inc_counter("coalesced");
+        } else {
+            shared_state.order.push_back(key);
+        }
+        if let Some(w) = shared_state.waker.take() {
+            w.wake();
+        }
+        Ok(())
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Clone for Sender<K, V> {
+    fn clone(&self) -> Self {
+        self.shared_state.lock().unwrap().num_senders += 1;
+        Self {
+            shared_state: Arc::clone(&self.shared_state),
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Drop for Sender<K, V> {
+    fn drop(&mut self) {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        shared_state.num_senders -= 1;
+        if shared_state.num_senders == 0 {
+            if let Some(w) = shared_state.waker.take() {
+                w.wake();
+            }
+        }
+    }
+}
+
+/// The receiving end of the keyed_watch channel.
+pub struct Receiver<K: Eq + Hash + Clone, V> {
+    shared_state: Arc<Mutex<SharedState<K, V>>>,
+}
+
+impl<K: Eq + Hash + Clone, V> Drop for Receiver<K, V> {
+    fn drop(&mut self) {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        shared_state.receiver_dropped = true;
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> Stream for Receiver<K, V> {
+    type Item = (K, V);
+    /// poll_next returns the pending value of the key that has been waiting the longest. If no
+    /// key is pending, it sets the waker passed to it by the scheduler/executor and returns
+    /// Pending, or terminates the stream if all the senders have dropped.
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut shared_state = self.shared_state.lock().unwrap();
+        if let Some(key) = shared_state.order.pop_front() {
+            let value = shared_state
+                .pending
+                .remove(&key)
+                .expect("[keyed watch] pending key without value");
+            shared_state.inc_counter("received");
+            Poll::Ready(Some((key, value)))
+        } else if shared_state.num_senders == 0 {
+            shared_state.stream_terminated = true;
+            Poll::Ready(None)
+        } else {
+            shared_state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<K: Eq + Hash + Clone, V> FusedStream for Receiver<K, V> {
+    fn is_terminated(&self) -> bool {
+        self.shared_state.lock().unwrap().stream_terminated
+    }
+}
+
+/// Create a new keyed_watch channel and returns the two ends of the channel. `counters`, if
+/// given, needs a single label taking the values "inserted", "coalesced" and "received".
+pub fn new<K: Eq + Hash + Clone, V>(
+    counters: Option<&'static IntCounterVec>,
+) -> (Sender<K, V>, Receiver<K, V>) {
+    let shared_state = Arc::new(Mutex::new(SharedState {
+        pending: HashMap::new(),
+        order: VecDeque::new(),
+        waker: None,
+        num_senders: 1,
+        receiver_dropped: false,
+        stream_terminated: false,
+        counters,
+    }));
+    let shared_state_clone = Arc::clone(&shared_state);
+    (
+        Sender { shared_state },
+        Receiver {
+            shared_state: shared_state_clone,
+        },
+    )
+}
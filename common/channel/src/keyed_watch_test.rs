@@ -0,0 +1,157 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::keyed_watch;
+use futures::{executor::block_on, stream::FusedStream, FutureExt, StreamExt};
+use libra_metrics::IntCounterVec;
+use prometheus::Opts;
+use proptest::{collection::vec, prelude::*};
+use std::{
+    collections::{HashMap, VecDeque},
+    thread,
+};
+
+/// A fresh, unregistered counter, so that tests running in parallel don't share it
+fn test_counters() -> &'static IntCounterVec {
+    let counters = IntCounterVec::new(
+        Opts::new("keyed_watch_test", "Counter of keyed watch tests"),
+        &["state"],
+    )
+    .unwrap();
+    Box::leak(Box::new(counters))
+}
+
+fn count(counters: &IntCounterVec, label: &str) -> u64 {
+    counters.with_label_values(&[label]).get() as u64
+}
+
+#[test]
+fn test_coalesce_per_key() {
+    let (sender, mut receiver) = keyed_watch::new(None);
+    sender.insert(0, 0).unwrap();
+    sender.insert(1, 10).unwrap();
+    sender.insert(0, 1).unwrap();
+    sender.insert(0, 2).unwrap();
+    // Only the latest value of each key is received, in the order the keys became pending
+    assert_eq!(block_on(receiver.select_next_some()), (0, 2));
+    assert_eq!(block_on(receiver.select_next_some()), (1, 10));
+    assert_eq!(receiver.select_next_some().now_or_never(), None);
+    // A key becomes pending again once its value was received
+    sender.insert(0, 3).unwrap();
+    assert_eq!(block_on(receiver.select_next_some()), (0, 3));
+}
+
+#[test]
+fn test_terminates_after_senders_drop() {
+    let (sender, mut receiver) = keyed_watch::new(None);
+    let other_sender = sender.clone();
+    sender.insert(0, 0).unwrap();
+    drop(sender);
+    other_sender.insert(0, 1).unwrap();
+    drop(other_sender);
+    // Pending values are still delivered before the stream terminates
+    assert_eq!(block_on(receiver.next()), Some((0, 1)));
+    assert_eq!(block_on(receiver.next()), None);
+    assert!(receiver.is_terminated());
+}
+
+#[test]
+fn test_insert_after_receiver_drop() {
+    let (sender, receiver) = keyed_watch::new::<u8, u8>(None);
+    drop(receiver);
+    assert!(sender.insert(0, 0).is_err());
+}
+
+#[test]
+fn test_concurrent_producers() {
+    let num_keys: u64 = 4;
+    let num_values: u64 = 1000;
+    let counters = test_counters();
+    let (sender, mut receiver) = keyed_watch::new(Some(counters));
+    let producers: Vec<_> = (0..num_keys)
+        .map(|key| {
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for value in 0..num_values {
+                    sender.insert(key, value).unwrap();
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut latest = HashMap::new();
+    let mut num_received: u64 = 0;
+    while let Some((key, value)) = block_on(receiver.next()) {
+        // Values of a key are never received out of order, nor twice
+        if let Some(previous) = latest.insert(key, value) {
+            assert!(value > previous);
+        }
+        num_received += 1;
+    }
+    for producer in producers {
+        producer.join().unwrap();
+    }
+
+    // The latest value of every key makes it through, and every other value was coalesced
+    for key in 0..num_keys {
+        assert_eq!(latest[&key], num_values - 1);
+    }
+    assert_eq!(count(counters, "inserted"), num_keys * num_values);
+    assert_eq!(count(counters, "received"), num_received);
+    assert_eq!(
+        count(counters, "coalesced"),
+        num_keys * num_values - num_received
+    );
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+    Insert(u8, u32),
+    Receive,
+}
+
+fn arb_op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0u8..4, any::<u32>()).prop_map(|(key, value)| Op::Insert(key, value)),
+        Just(Op::Receive),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_matches_model(ops in vec(arb_op(), 0..100)) {
+        let counters = test_counters();
+        let (sender, mut receiver) = keyed_watch::new(Some(counters));
+        let mut pending = HashMap::new();
+        let mut order = VecDeque::new();
+        let mut num_coalesced = 0;
+
+        for op in ops {
+            match op {
+                Op::Insert(key, value) => {
+                    sender.insert(key, value).unwrap();
+                    if pending.insert(key, value).is_some() {
+                        num_coalesced += 1;
+                    } else {
+                        order.push_back(key);
+                    }
+                }
+                Op::Receive => {
+                    let expected = order
+                        .pop_front()
+                        .map(|key| (key, pending.remove(&key).unwrap()));
+                    prop_assert_eq!(receiver.select_next_some().now_or_never(), expected);
+                }
+            }
+        }
+        prop_assert_eq!(count(counters, "coalesced"), num_coalesced);
+
+        // Nothing is lost: the rest of the pending values come out in order
+        drop(sender);
+        for key in order {
+            prop_assert_eq!(block_on(receiver.next()), Some((key, pending[&key])));
+        }
+        prop_assert_eq!(block_on(receiver.next()), None);
+    }
+}
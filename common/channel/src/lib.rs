@@ -20,6 +20,10 @@ use std::{
 #[cfg(test)]
 mod test;
 
+pub mod keyed_watch;
+#[cfg(test)]
+mod keyed_watch_test;
+
 pub mod libra_channel;
 #[cfg(test)]
 mod libra_channel_test;
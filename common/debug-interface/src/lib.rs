@@ -1,7 +1,10 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::proto::{GetNodeDetailsRequest, NodeDebugInterfaceClient};
+use crate::proto::{
+    AuditStateRequest, GetDbStatsRequest, GetNodeDetailsRequest, NodeDebugInterfaceClient,
+    VersionAudit,
+};
 use failure::prelude::*;
 use grpcio::{ChannelBuilder, EnvBuilder};
 use std::{collections::HashMap, sync::Arc};
@@ -38,6 +41,26 @@ impl NodeDebugClient {
         Ok(metrics.get(metric.as_ref()).cloned())
     }
 
+    /// Compares the transaction infos at `versions` with the ones of the peer `peer_id`, see
+    /// the `AuditState` command.
+    pub fn audit_state(&self, peer_id: Vec<u8>, versions: Vec<u64>) -> Result<Vec<VersionAudit>> {
+        let request = AuditStateRequest { peer_id, versions };
+        let response = self
+            .client
+            .audit_state(&request)
+            .context("Unable to audit the node state")?;
+        Ok(response.audits)
+    }
+
+    /// Dumps the statistics RocksDB keeps on the storage DB, see the `GetDbStats` command.
+    pub fn get_db_stats(&self) -> Result<String> {
+        let response = self
+            .client
+            .get_db_stats(&GetDbStatsRequest::default())
+            .context("Unable to get the DB stats")?;
+        Ok(response.stats)
+    }
+
     pub fn get_node_metrics(&self) -> Result<HashMap<String, i64>> {
         let response = self
             .client
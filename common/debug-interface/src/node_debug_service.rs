@@ -6,21 +6,52 @@
 use crate::{
     json_log,
     proto::{
-        Event, GetEventsRequest, GetEventsResponse, GetNodeDetailsRequest, GetNodeDetailsResponse,
-        NodeDebugInterface,
+        AuditStateRequest, AuditStateResponse, Event, GetDbStatsRequest, GetDbStatsResponse,
+        GetEventsRequest, GetEventsResponse, GetNodeDetailsRequest, GetNodeDetailsResponse,
+        NodeDebugInterface, VersionAudit,
     },
 };
+use failure::prelude::*;
 use futures::Future;
+use grpcio::{RpcStatus, RpcStatusCode};
 use libra_logger::prelude::*;
 use libra_metrics::counters::COUNTER_ADMISSION_CONTROL_CANNOT_SEND_REPLY;
+use std::sync::Arc;
+
+/// Audits the state of the node against a peer, for the `AuditState` command.
+pub trait StateAuditor: Send + Sync {
+    /// Compares the transaction infos at `versions` with the ones of the peer `peer_id`.
+    fn audit_state(&self, peer_id: &[u8], versions: Vec<u64>) -> Result<Vec<VersionAudit>>;
+}
+
+/// Reads the statistics of the storage DB, for the `GetDbStats` command.
+pub trait DbStatsProvider: Send + Sync {
+    /// Returns the statistics RocksDB keeps on the DB, as text.
+    fn get_db_stats(&self) -> Result<String>;
+}
 
 #[derive(Clone, Default)]
-pub struct NodeDebugService {}
+pub struct NodeDebugService {
+    state_auditor: Option<Arc<dyn StateAuditor>>,
+    db_stats_provider: Option<Arc<dyn DbStatsProvider>>,
+}
 
 impl NodeDebugService {
     pub fn new() -> Self {
         Default::default()
     }
+
+    /// Serves the `AuditState` command with `state_auditor`. Without one, the command fails.
+    pub fn with_state_auditor(mut self, state_auditor: Arc<dyn StateAuditor>) -> Self {
+        self.state_auditor = Some(state_auditor);
+        self
+    }
+
+    /// Serves the `GetDbStats` command with `db_stats_provider`. Without one, the command fails.
+    pub fn with_db_stats_provider(mut self, db_stats_provider: Arc<dyn DbStatsProvider>) -> Self {
+        self.db_stats_provider = Some(db_stats_provider);
+        self
+    }
 }
 
 impl NodeDebugInterface for NodeDebugService {
@@ -54,6 +85,54 @@ impl NodeDebugInterface for NodeDebugService {
         }
         ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
     }
+
+    fn audit_state(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: AuditStateRequest,
+        sink: ::grpcio::UnarySink<AuditStateResponse>,
+    ) {
+        info!("[GRPC] audit_state");
+        let audits = match &self.state_auditor {
+            Some(state_auditor) => state_auditor.audit_state(&req.peer_id, req.versions),
+            None => Err(format_err!("state audits are not supported by this node")),
+        };
+        match audits {
+            Ok(audits) => {
+                let response = AuditStateResponse { audits };
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
+            }
+            Err(err) => {
+                let status =
+                    RpcStatus::new(RpcStatusCode::FAILED_PRECONDITION, Some(err.to_string()));
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger))
+            }
+        }
+    }
+
+    fn get_db_stats(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        _req: GetDbStatsRequest,
+        sink: ::grpcio::UnarySink<GetDbStatsResponse>,
+    ) {
+        info!("[GRPC] get_db_stats");
+        let stats = match &self.db_stats_provider {
+            Some(db_stats_provider) => db_stats_provider.get_db_stats(),
+            None => Err(format_err!("DB stats are not supported by this node")),
+        };
+        match stats {
+            Ok(stats) => {
+                let response = GetDbStatsResponse { stats };
+                ctx.spawn(sink.success(response).map_err(default_reply_error_logger))
+            }
+            Err(err) => {
+                let status =
+                    RpcStatus::new(RpcStatusCode::FAILED_PRECONDITION, Some(err.to_string()));
+                ctx.spawn(sink.fail(status).map_err(default_reply_error_logger))
+            }
+        }
+    }
 }
 
 fn default_reply_error_logger<T: ::std::fmt::Debug>(e: T) {
@@ -0,0 +1,224 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers to write sequences of protobuf messages to a byte stream, e.g. a file, and read them
+//! back.
+//!
+//! Each record is the varint encoded length of the message, followed by the encoded message and,
+//! if written with a checksum, the CRC32 of the encoded message in little endian. Whether records
+//! carry a checksum is not recorded in the stream, so the reader has to know it.
+//!
+//! A stream that ends in the middle of a record, e.g. because the writer crashed, yields a
+//! `DelimitedError::TruncatedRecord` for that record rather than a decoding error.
+
+use prost::{encoding::encode_varint, DecodeError, EncodeError, Message};
+use std::{
+    fmt,
+    io::{self, ErrorKind, Read, Write},
+    marker::PhantomData,
+};
+
+/// The longest varint encoding of a `u64`.
+const MAX_VARINT_LEN: usize = 10;
+const CRC_LEN: usize = 4;
+
+#[derive(Debug)]
+pub enum DelimitedError {
+    Io(io::Error),
+    Encode(EncodeError),
+    Decode(DecodeError),
+    /// The stream ended in the middle of a record.
+    TruncatedRecord,
+    /// The length prefix of a record is not a valid varint.
+    InvalidLength,
+    /// The checksum stored with a record does not match its content.
+    CrcMismatch {
+        expected: u32,
+        actual: u32,
+    },
+}
+
+impl fmt::Display for DelimitedError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DelimitedError::Io(err) => write!(f, "I/O error: {}", err),
+            DelimitedError::Encode(err) => write!(f, "failed to encode record: {}", err),
+            DelimitedError::Decode(err) => write!(f, "failed to decode record: {}", err),
+            DelimitedError::TruncatedRecord => write!(f, "truncated record"),
+            DelimitedError::InvalidLength => write!(f, "invalid record length prefix"),
+            DelimitedError::CrcMismatch { expected, actual } => write!(
+                f,
+                "record checksum mismatch: expected {:#010x}, actual {:#010x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DelimitedError {}
+
+impl From<io::Error> for DelimitedError {
+    fn from(err: io::Error) -> Self {
+        DelimitedError::Io(err)
+    }
+}
+
+impl From<EncodeError> for DelimitedError {
+    fn from(err: EncodeError) -> Self {
+        DelimitedError::Encode(err)
+    }
+}
+
+impl From<DecodeError> for DelimitedError {
+    fn from(err: DecodeError) -> Self {
+        DelimitedError::Decode(err)
+    }
+}
+
+/// Writes `msg` as one length delimited record.
+pub fn write_delimited<W: Write, M: Message>(
+    writer: &mut W,
+    msg: &M,
+) -> Result<(), DelimitedError> {
+    write_record(writer, msg, false /* with_crc */)
+}
+
+/// Writes `msg` as one length delimited record followed by its checksum.
+pub fn write_delimited_with_crc<W: Write, M: Message>(
+    writer: &mut W,
+    msg: &M,
+) -> Result<(), DelimitedError> {
+    write_record(writer, msg, true /* with_crc */)
+}
+
+/// Reads the next record written by `write_delimited`. Returns `None` if the stream ends right
+/// before the record.
+pub fn read_delimited<R: Read, M: Message + Default>(
+    reader: &mut R,
+) -> Result<Option<M>, DelimitedError> {
+    read_record(reader, false /* with_crc */)
+}
+
+/// Reads the next record written by `write_delimited_with_crc`. Returns `None` if the stream ends
+/// right before the record.
+pub fn read_delimited_with_crc<R: Read, M: Message + Default>(
+    reader: &mut R,
+) -> Result<Option<M>, DelimitedError> {
+    read_record(reader, true /* with_crc */)
+}
+
+/// Iterates over the records of a stream until it ends or the first error, which is yielded as
+/// the last item.
+pub struct DelimitedIter<R, M> {
+    reader: R,
+    with_crc: bool,
+    done: bool,
+    phantom: PhantomData<M>,
+}
+
+impl<R: Read, M: Message + Default> DelimitedIter<R, M> {
+    /// Iterates over records written by `write_delimited`.
+    pub fn new(reader: R) -> Self {
+        Self::new_impl(reader, false /* with_crc */)
+    }
+
+    /// Iterates over records written by `write_delimited_with_crc`.
+    pub fn with_crc(reader: R) -> Self {
+        Self::new_impl(reader, true /* with_crc */)
+    }
+
+    fn new_impl(reader: R, with_crc: bool) -> Self {
+        Self {
+            reader,
+            with_crc,
+            done: false,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<R: Read, M: Message + Default> Iterator for DelimitedIter<R, M> {
+    type Item = Result<M, DelimitedError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let res = read_record(&mut self.reader, self.with_crc).transpose();
+        if res.as_ref().map_or(true, Result::is_err) {
+            self.done = true;
+        }
+        res
+    }
+}
+
+fn write_record<W: Write, M: Message>(
+    writer: &mut W,
+    msg: &M,
+    with_crc: bool,
+) -> Result<(), DelimitedError> {
+    let len = msg.encoded_len();
+    let mut buf = Vec::with_capacity(MAX_VARINT_LEN + len + CRC_LEN);
+    encode_varint(len as u64, &mut buf);
+    let msg_start = buf.len();
+    msg.encode(&mut buf)?;
+    if with_crc {
+        let crc = crc32fast::hash(&buf[msg_start..]);
+        buf.extend_from_slice(&crc.to_le_bytes());
+    }
+    writer.write_all(&buf)?;
+    Ok(())
+}
+
+fn read_record<R: Read, M: Message + Default>(
+    reader: &mut R,
+    with_crc: bool,
+) -> Result<Option<M>, DelimitedError> {
+    let len = match read_length(reader)? {
+        Some(len) => len,
+        None => return Ok(None),
+    };
+    // Don't trust the length to allocate the buffer upfront, the stream may be corrupted.
+    let mut buf = vec![];
+    reader.by_ref().take(len).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < len {
+        return Err(DelimitedError::TruncatedRecord);
+    }
+    if with_crc {
+        let mut crc_bytes = [0u8; CRC_LEN];
+        reader.read_exact(&mut crc_bytes).map_err(|err| {
+            if err.kind() == ErrorKind::UnexpectedEof {
+                DelimitedError::TruncatedRecord
+            } else {
+                DelimitedError::Io(err)
+            }
+        })?;
+        let expected = u32::from_le_bytes(crc_bytes);
+        let actual = crc32fast::hash(&buf);
+        if expected != actual {
+            return Err(DelimitedError::CrcMismatch { expected, actual });
+        }
+    }
+    Ok(Some(M::decode(&buf)?))
+}
+
+/// Reads a varint length prefix byte by byte, so that nothing past it is consumed from `reader`.
+/// Returns `None` if the stream ends before the first byte.
+fn read_length<R: Read>(reader: &mut R) -> Result<Option<u64>, DelimitedError> {
+    let mut value = 0u64;
+    for i in 0..MAX_VARINT_LEN {
+        let mut byte = [0u8; 1];
+        if reader.read(&mut byte)? == 0 {
+            return if i == 0 {
+                Ok(None)
+            } else {
+                Err(DelimitedError::TruncatedRecord)
+            };
+        }
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+    Err(DelimitedError::InvalidLength)
+}
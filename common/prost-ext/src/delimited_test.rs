@@ -0,0 +1,130 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::delimited::*;
+use prost::Message;
+
+#[derive(Clone, PartialEq, Message)]
+struct TestRecord {
+    #[prost(uint64, tag = "1")]
+    id: u64,
+    #[prost(bytes, tag = "2")]
+    payload: Vec<u8>,
+}
+
+fn test_records() -> Vec<TestRecord> {
+    vec![
+        TestRecord {
+            id: 1,
+            payload: vec![1, 2, 3],
+        },
+        // encodes to zero bytes
+        TestRecord::default(),
+        TestRecord {
+            id: 3,
+            payload: vec![0xff; 300],
+        },
+    ]
+}
+
+fn write_records(records: &[TestRecord], with_crc: bool) -> Vec<u8> {
+    let mut buf = vec![];
+    for record in records {
+        if with_crc {
+            write_delimited_with_crc(&mut buf, record).unwrap();
+        } else {
+            write_delimited(&mut buf, record).unwrap();
+        }
+    }
+    buf
+}
+
+fn iter_records(buf: &[u8], with_crc: bool) -> DelimitedIter<&[u8], TestRecord> {
+    if with_crc {
+        DelimitedIter::with_crc(buf)
+    } else {
+        DelimitedIter::new(buf)
+    }
+}
+
+#[test]
+fn test_roundtrip() {
+    let records = test_records();
+    for &with_crc in &[false, true] {
+        let buf = write_records(&records, with_crc);
+        let read: Vec<_> = iter_records(&buf, with_crc)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(read, records);
+    }
+}
+
+#[test]
+fn test_read_delimited() {
+    let records = test_records();
+    let buf = write_records(&records, false /* with_crc */);
+    let mut reader = buf.as_slice();
+    for record in &records {
+        let read: TestRecord = read_delimited(&mut reader).unwrap().unwrap();
+        assert_eq!(read, *record);
+    }
+    assert!(read_delimited::<_, TestRecord>(&mut reader)
+        .unwrap()
+        .is_none());
+
+    let buf = write_records(&records, true /* with_crc */);
+    let mut reader = buf.as_slice();
+    for record in &records {
+        let read: TestRecord = read_delimited_with_crc(&mut reader).unwrap().unwrap();
+        assert_eq!(read, *record);
+    }
+    assert!(read_delimited_with_crc::<_, TestRecord>(&mut reader)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_truncated_record() {
+    let records = test_records();
+    for &with_crc in &[false, true] {
+        let complete = write_records(&records[..2], with_crc).len();
+        let buf = write_records(&records, with_crc);
+        // cutting right before the last record is a clean end of stream
+        for len in complete + 1..buf.len() {
+            let mut iter = iter_records(&buf[..len], with_crc);
+            assert_eq!(iter.next().unwrap().unwrap(), records[0]);
+            assert_eq!(iter.next().unwrap().unwrap(), records[1]);
+            match iter.next() {
+                Some(Err(DelimitedError::TruncatedRecord)) => (),
+                res => panic!("Unexpected result: {:?}", res),
+            }
+            assert!(iter.next().is_none());
+        }
+    }
+}
+
+#[test]
+fn test_crc_mismatch() {
+    let records = test_records();
+    let mut buf = write_records(&records[..1], true /* with_crc */);
+    // flip a bit of the payload, which still decodes fine
+    let last_payload_byte = buf.len() - 5;
+    buf[last_payload_byte] ^= 1;
+
+    match read_delimited_with_crc::<_, TestRecord>(&mut buf.as_slice()) {
+        Err(DelimitedError::CrcMismatch { expected, actual }) => assert_ne!(expected, actual),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+    let mut iter = iter_records(&buf, true /* with_crc */);
+    assert!(iter.next().unwrap().is_err());
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn test_invalid_length() {
+    let buf = vec![0xff; 11];
+    match read_delimited::<_, TestRecord>(&mut buf.as_slice()) {
+        Err(DelimitedError::InvalidLength) => (),
+        res => panic!("Unexpected result: {:?}", res),
+    }
+}
@@ -4,6 +4,10 @@
 use bytes::{Bytes, BytesMut};
 use prost::{EncodeError, Message};
 
+pub mod delimited;
+#[cfg(test)]
+mod delimited_test;
+
 impl<T: ?Sized> MessageExt for T where T: Message {}
 
 pub trait MessageExt: Message {
@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Convenience structs and functions for generating configuration for a swarm of libra nodes
-use crate::util::gen_genesis_transaction_bytes;
+use crate::util::{
+    derive_full_node_network_identity_keys, derive_validator_network_identity_keys,
+    gen_genesis_transaction_bytes,
+};
 use failure::prelude::*;
 use libra_config::{
     config::{
@@ -95,8 +98,12 @@ impl SwarmConfig {
             network_peers: template_network.network_peers.clone(),
             seed_peers: template_network.seed_peers.clone(),
         };
-        let (mut private_keys, mut network_peers_config) =
+        let (private_keys, network_peers_config) =
             ConfigHelpers::gen_full_nodes(num_nodes, key_seed);
+        let (mut private_keys, mut network_peers_config) = match key_seed {
+            Some(seed) => derive_full_node_network_identity_keys(&seed, private_keys),
+            None => (private_keys, network_peers_config),
+        };
         // Add upstream peer to NetworkPeersConfig.
         network_peers_config
             .peers
@@ -212,8 +219,15 @@ impl SwarmConfig {
         key_seed: Option<[u8; 32]>,
         output_dir: &Path,
     ) -> Result<Self> {
-        let (mut private_keys, consensus_peers_config, network_peers_config) =
+        let (mut private_keys, consensus_peers_config, mut network_peers_config) =
             ConfigHelpers::gen_validator_nodes(num_nodes, key_seed);
+        if let Some(seed) = &key_seed {
+            derive_validator_network_identity_keys(
+                seed,
+                &mut private_keys,
+                &mut network_peers_config,
+            );
+        }
         let mut seed_peers_config = SeedPeersConfigHelpers::get_test_config_with_ipver(
             &network_peers_config,
             None,
@@ -4,17 +4,32 @@
 use libra_config::{
     config::{NodeConfig, NodeConfigHelpers},
     trusted_peers::{
-        ConfigHelpers, ConsensusPeersConfig, ConsensusPrivateKey, NetworkPeersConfig,
-        NetworkPrivateKeys,
+        ConfigHelpers, ConsensusPeersConfig, ConsensusPrivateKey, NetworkPeerInfo,
+        NetworkPeersConfig, NetworkPrivateKeys,
     },
 };
-use libra_crypto::{ed25519::*, test_utils::KeyPair};
+use libra_crypto::{
+    ed25519::*,
+    hkdf::{HkdfInfo, HkdfSalt, Sha3Hkdf},
+    test_utils::KeyPair,
+    traits::ValidKey,
+    x25519::{X25519StaticPrivateKey, X25519StaticPublicKey, X25519_PRIVATE_KEY_LENGTH},
+};
 use libra_prost_ext::MessageExt;
 use libra_types::{account_address::AccountAddress, transaction::SignatureCheckedTransaction};
 use rand::{Rng, SeedableRng};
-use std::{collections::HashMap, fs::File, io::prelude::*};
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fs::File,
+    io::prelude::*,
+};
 use vm_genesis::encode_genesis_transaction_with_validator;
 
+const NETWORK_IDENTITY_KEY_SALT: HkdfSalt<'static> =
+    HkdfSalt(b"LIBRA SWARM: network identity key salt$");
+const NETWORK_IDENTITY_KEY_INFO_PREFIX: &[u8] = b"LIBRA SWARM: network identity key$";
+
 pub fn gen_genesis_transaction(
     faucet_account_keypair: &KeyPair<Ed25519PrivateKey, Ed25519PublicKey>,
     consensus_peers_config: &ConsensusPeersConfig,
@@ -76,3 +91,84 @@ pub fn get_test_config() -> (NodeConfig, KeyPair<Ed25519PrivateKey, Ed25519Publi
         get_test_config_with_validators();
     (config, keypair)
 }
+
+/// Derives the network identity keypair of the node labeled `node_label` from the `seed` of its
+/// swarm, with HKDF based on SHA3-256.
+pub fn derive_network_identity_keypair(
+    seed: &[u8; 32],
+    node_label: &[u8],
+) -> (X25519StaticPrivateKey, X25519StaticPublicKey) {
+    let mut info = NETWORK_IDENTITY_KEY_INFO_PREFIX.to_vec();
+    info.extend_from_slice(node_label);
+    let key_bytes = Sha3Hkdf::derive(
+        NETWORK_IDENTITY_KEY_SALT,
+        seed,
+        HkdfInfo(&info),
+        X25519_PRIVATE_KEY_LENGTH,
+    )
+    .expect("HKDF failed to derive a network identity key");
+    let private_key = X25519StaticPrivateKey::try_from(key_bytes.as_slice())
+        .expect("Unable to convert into network identity key");
+    let public_key = (&private_key).into();
+    (private_key, public_key)
+}
+
+/// Replaces the network identity keys of the validators generated by
+/// `ConfigHelpers::gen_validator_nodes` with keys derived from the `seed` of the swarm, labeled
+/// by the peer ids of the validators.
+pub fn derive_validator_network_identity_keys(
+    seed: &[u8; 32],
+    private_keys: &mut HashMap<AccountAddress, (ConsensusPrivateKey, NetworkPrivateKeys)>,
+    network_peers_config: &mut NetworkPeersConfig,
+) {
+    for (peer_id, (_, network_private_keys)) in private_keys.iter_mut() {
+        let (private_key, public_key) = derive_network_identity_keypair(seed, peer_id.as_ref());
+        network_private_keys.network_identity_private_key = private_key;
+        if let Some(peer_info) = network_peers_config.peers.get_mut(&peer_id.to_string()) {
+            peer_info.network_identity_pubkey = public_key;
+        }
+    }
+}
+
+/// Replaces the network identity keys of the full nodes generated by
+/// `ConfigHelpers::gen_full_nodes` with keys derived from the `seed` of the swarm, labeled by the
+/// position of the full nodes. The peer id of a full node is its network identity public key, so
+/// the full nodes get new peer ids as well.
+pub fn derive_full_node_network_identity_keys(
+    seed: &[u8; 32],
+    private_keys: HashMap<AccountAddress, NetworkPrivateKeys>,
+) -> (
+    HashMap<AccountAddress, NetworkPrivateKeys>,
+    NetworkPeersConfig,
+) {
+    let mut network_peers = HashMap::new();
+    let mut peers_private_keys = HashMap::new();
+    let private_keys: BTreeMap<_, _> = private_keys.into_iter().collect();
+    for (index, (_, network_private_keys)) in private_keys.into_iter().enumerate() {
+        let mut node_label = b"full node ".to_vec();
+        node_label.extend_from_slice(&(index as u64).to_le_bytes());
+        let (private_key, public_key) = derive_network_identity_keypair(seed, &node_label);
+        let peer_id = AccountAddress::try_from(public_key.to_bytes()).unwrap();
+        let signing_private_key = network_private_keys.network_signing_private_key;
+        network_peers.insert(
+            peer_id.to_string(),
+            NetworkPeerInfo {
+                network_signing_pubkey: (&signing_private_key).into(),
+                network_identity_pubkey: public_key,
+            },
+        );
+        peers_private_keys.insert(
+            peer_id,
+            NetworkPrivateKeys {
+                network_signing_private_key: signing_private_key,
+                network_identity_private_key: private_key,
+            },
+        );
+    }
+    (
+        peers_private_keys,
+        NetworkPeersConfig {
+            peers: network_peers,
+        },
+    )
+}
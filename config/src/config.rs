@@ -213,6 +213,16 @@ impl NodeConfig {
         }
     }
 
+    pub fn get_state_sync_checkpoint_file(&self) -> Option<PathBuf> {
+        self.state_sync.checkpoint_file.as_ref().map(|path| {
+            if path.is_relative() {
+                self.base.data_dir_path.join(path)
+            } else {
+                path.clone()
+            }
+        })
+    }
+
     pub fn get_metrics_dir(&self) -> Option<PathBuf> {
         let path = self.metrics.dir.as_path();
         if path.as_os_str().is_empty() {
@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default)]
@@ -12,6 +12,30 @@ pub struct AdmissionControlConfig {
     pub need_to_check_mempool_before_validation: bool,
     pub max_concurrent_inbound_syncs: usize,
     pub upstream_proxy_timeout: Duration,
+    /// Max number of recent submissions whose response is remembered, so that resubmitting the
+    /// same signed transaction returns the earlier status.
+    pub submission_cache_size: usize,
+    /// How long a remembered submission response is returned for duplicates.
+    pub submission_cache_ttl: Duration,
+    /// If set, every transaction submission, accepted or not, is recorded in the audit log files
+    /// of this directory.
+    pub audit_log_dir: Option<PathBuf>,
+    /// Size past which the current audit log file is rotated.
+    pub audit_log_max_file_size: u64,
+    /// Number of rotated audit log files kept besides the current one, the oldest is deleted.
+    pub audit_log_max_files: usize,
+    /// Max number of audit records waiting to be written. Records submitted past it are dropped
+    /// rather than holding up submissions.
+    pub audit_log_queue_size: usize,
+    /// Whether mempool is asked how full it is before VM validation, so that submissions it
+    /// would reject anyway for lack of room fail fast.
+    pub mempool_capacity_check: bool,
+    /// Deadline of the capacity request, past which the submission proceeds unchecked.
+    pub mempool_capacity_check_timeout: Duration,
+    /// Max number of capacity responses remembered, one per sender.
+    pub mempool_capacity_cache_size: usize,
+    /// How long a capacity response is reused before asking mempool again.
+    pub mempool_capacity_cache_ttl: Duration,
 }
 
 impl Default for AdmissionControlConfig {
@@ -22,6 +46,16 @@ impl Default for AdmissionControlConfig {
             need_to_check_mempool_before_validation: false,
             max_concurrent_inbound_syncs: 100,
             upstream_proxy_timeout: Duration::from_secs(1),
+            submission_cache_size: 10_000,
+            submission_cache_ttl: Duration::from_secs(30),
+            audit_log_dir: None,
+            audit_log_max_file_size: 64 * 1024 * 1024,
+            audit_log_max_files: 10,
+            audit_log_queue_size: 10_000,
+            mempool_capacity_check: true,
+            mempool_capacity_check_timeout: Duration::from_millis(50),
+            mempool_capacity_cache_size: 1_000,
+            mempool_capacity_cache_ttl: Duration::from_millis(100),
         }
     }
 }
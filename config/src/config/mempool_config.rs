@@ -15,6 +15,13 @@ pub struct MempoolConfig {
     pub capacity_per_user: usize,
     pub system_transaction_timeout_secs: u64,
     pub system_transaction_gc_interval_ms: u64,
+    // how long transactions may wait behind a sequence number gap before the gap is hinted to
+    // clients
+    pub sequence_gap_hint_timeout_secs: u64,
+    // how long transactions may wait behind a sequence number gap before they are evicted
+    pub sequence_gap_gc_timeout_secs: u64,
+    // order in which transactions are pulled into blocks for Consensus
+    pub block_ordering: BlockOrdering,
     pub mempool_service_port: u16,
     pub address: String,
 }
@@ -32,6 +39,20 @@ impl Default for MempoolConfig {
             address: "localhost".to_string(),
             mempool_service_port: 6182,
             system_transaction_gc_interval_ms: 180_000,
+            sequence_gap_hint_timeout_secs: 60,
+            sequence_gap_gc_timeout_secs: 3600,
+            block_ordering: BlockOrdering::GasPrice,
         }
     }
 }
+
+/// Order in which Mempool pulls transactions into blocks. Transactions of the same sender are
+/// always pulled in sequence number order.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockOrdering {
+    /// Oldest transactions first.
+    Timeline,
+    /// Highest gas price first, oldest first among transactions with equal gas price.
+    GasPrice,
+}
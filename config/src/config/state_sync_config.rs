@@ -3,6 +3,7 @@
 
 use crate::trusted_peers::UpstreamPeersConfig;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default)]
@@ -13,10 +14,115 @@ pub struct StateSyncConfig {
     pub tick_interval_ms: u64,
     // default timeout used for long polling to remote peer
     pub long_poll_timeout_ms: u64,
+    // if set, the long-poll timeout requested by full nodes follows the recent commit rate within
+    // these (min, max) bounds instead: shorter on a busy chain for lower latency, longer on a
+    // quiet one for fewer requests
+    pub adaptive_long_poll_timeout_ms: Option<(u64, u64)>,
+    // if set, a full node more than this many versions behind the highest version it has seen
+    // requests chunks without a timeout to catch up quickly, and only long-polls once within
+    // this many versions of it
+    pub catch_up_threshold: Option<u64>,
     // valid maximum chunk limit for sanity check
     pub max_chunk_limit: u64,
     // valid maximum timeout limit for sanity check
     pub max_timeout_ms: u64,
+    // records of chunk requests older than this multiple of the request timeout are dropped
+    pub request_expiry_timeout_multiplier: u64,
+    // if set, a chunk request still unanswered after this long is also sent to a second peer
+    pub hedge_after_ms: Option<u64>,
+    // on a request timeout, stop picking the peer with the highest latency for a while
+    pub drop_slowest_on_stuck: bool,
+    // time after which a peer dropped for being the slowest is picked again
+    pub slow_peer_cooldown_ms: u64,
+    // if set, a peer syncing fewer versions per second than this while we are behind is failed
+    // over like an unresponsive one, even though it does make some progress
+    pub min_progress_rate: Option<u64>,
+    // peers scoring within this much of the best peer are picked in turn rather than at random
+    pub peer_score_tie_epsilon: f64,
+    // if set, a request timeout only lowers the score of the peer if it timed out before within
+    // this long, so that a single hiccup of an otherwise good peer goes unpunished
+    pub timeout_penalty_grace_period_ms: Option<u64>,
+    // weight of the peers of each network of the node, in the order of the networks, e.g. to
+    // prefer the validator network over a full node network; networks not listed weigh 1
+    pub network_weights: Vec<f64>,
+    // if set, a summary of the latest ledger info is sent to the connected peers at this interval,
+    // for full nodes to learn of new versions from each other rather than only from upstream
+    pub ledger_info_gossip_interval_ms: Option<u64>,
+    // ledger info summaries of a peer arriving sooner than this after its previous one are dropped
+    pub min_ledger_info_summary_interval_ms: u64,
+    // number of committed transactions a subscriber to committed data may lag behind
+    pub committed_data_buffer_size: usize,
+    // whether a lagging subscriber to committed data holds up further commits, instead of missing
+    // the data committed meanwhile
+    pub block_on_committed_data_lag: bool,
+    // if set, chunks are served to other peers only up to this version, e.g. by an archival
+    // replica pinned at a historical version
+    pub serve_max_version: Option<u64>,
+    // if non-empty, chunks are served only to these peers, e.g. the downstream full nodes of a
+    // private network, and chunk requests of other peers are refused
+    pub serve_allowlist: Vec<String>,
+    // maximum number of chunks waiting to be applied or requested ahead of them; once reached,
+    // the next chunk is only requested after the one at hand is applied, so that requests follow
+    // the pace of execution
+    pub max_pending_chunks: u64,
+    // if set, a sync starts requesting chunks of this size, doubling it with every chunk received
+    // in order up to `max_chunk_limit`, to gently probe the capacity of peers
+    pub slow_start_chunk_limit: Option<u64>,
+    // if set, the node config file is checked for changes at this interval and the upstream
+    // peers are reloaded from it without a restart
+    pub upstream_peers_reload_interval_ms: Option<u64>,
+    // timeout of chunk requests while consensus is waiting for the sync, tighter than the two
+    // ticks used for catching up
+    pub consensus_sync_timeout_ms: u64,
+    // chunk executions taking longer than this are logged with the time spent in each stage, to
+    // tell storage write stalls apart from slow peers
+    pub slow_execution_warning_ms: u64,
+    // chunks whose execution fails on a transient storage error, e.g. when storage can't be
+    // reached, are kept and executed again up to this many times, waiting
+    // `chunk_execution_retry_backoff_ms` before the first retry and twice as long before each next
+    // one; a chunk is dropped once retrying it would take longer than
+    // `chunk_execution_retry_expiry_ms` since its first execution. Execution failures are never
+    // blamed on the peer that sent the chunk, whose proofs are verified before it is executed
+    pub max_chunk_execution_retries: u64,
+    pub chunk_execution_retry_backoff_ms: u64,
+    pub chunk_execution_retry_expiry_ms: u64,
+    // reads of an epoch proof failing transiently, e.g. when storage can't be reached, are
+    // retried up to this many times, waiting `epoch_proof_retry_backoff_ms` before the first
+    // retry and twice as long before each next one
+    pub max_epoch_proof_retries: u64,
+    pub epoch_proof_retry_backoff_ms: u64,
+    // root hash requests of a peer auditing the state of this node are dropped if they arrive
+    // sooner than this after its previous one
+    pub min_audit_request_interval_ms: u64,
+    // if set, the transaction infos of every `verification_interval`-th chunk applied from another
+    // peer are checked against this trusted peer, and discrepancies are flagged, to catch a peer
+    // feeding wrong data that still passes the proof checks
+    pub verification_peer: Option<String>,
+    pub verification_interval: u64,
+    // messages from peers speaking an older state sync protocol version are rejected and the peers
+    // are not picked for requests, can be raised as a kill switch for incompatible peers
+    pub min_supported_protocol_version: u64,
+    // if set, the target ledger info of a chunk is also required to be signed by this many
+    // validators, on top of the quorum of voting power checked by the validator verifier
+    pub min_signature_quorum: Option<SignatureQuorum>,
+    // maximum number of chunks delivered to long-poll subscribers concurrently
+    pub max_concurrent_subscription_deliveries: usize,
+    // a chunk not delivered to a long-poll subscriber within this time is dropped
+    pub subscription_delivery_timeout_ms: u64,
+    // chunk responses are processed ahead of the other messages of peers received before them,
+    // e.g. chunk requests of downstream peers, except for messages waiting for this long already
+    pub max_chunk_request_deferral_ms: u64,
+    // if set, a checkpoint of the sync, i.e. the known version and the latest version advertised
+    // by each upstream peer, is written to this file every `checkpoint_interval_ms` and on
+    // shutdown, and loaded as hints on startup for a restarted node to resume a long catch-up
+    // without probing its peers again; relative paths are relative to the data dir
+    pub checkpoint_file: Option<PathBuf>,
+    pub checkpoint_interval_ms: u64,
+    // checkpoints older than this are ignored on startup
+    pub max_checkpoint_age_ms: u64,
+    // debugging aid: if set, the inputs of the coordinator are recorded so that the session can
+    // be replayed, which keeps all the chunks received in memory
+    pub record_session: bool,
     // List of peers to use as upstream in state sync protocols.
     #[serde(flatten)]
     pub upstream_peers: UpstreamPeersConfig,
@@ -28,9 +134,67 @@ impl Default for StateSyncConfig {
             chunk_limit: 1000,
             tick_interval_ms: 100,
             long_poll_timeout_ms: 30000,
+            adaptive_long_poll_timeout_ms: None,
+            catch_up_threshold: None,
             max_chunk_limit: 1000,
             max_timeout_ms: 120_000,
+            request_expiry_timeout_multiplier: 10,
+            hedge_after_ms: None,
+            drop_slowest_on_stuck: false,
+            slow_peer_cooldown_ms: 60_000,
+            min_progress_rate: None,
+            peer_score_tie_epsilon: 1.0,
+            timeout_penalty_grace_period_ms: None,
+            network_weights: vec![],
+            ledger_info_gossip_interval_ms: None,
+            min_ledger_info_summary_interval_ms: 500,
+            committed_data_buffer_size: 1024,
+            block_on_committed_data_lag: false,
+            serve_max_version: None,
+            serve_allowlist: vec![],
+            max_pending_chunks: 2,
+            slow_start_chunk_limit: None,
+            upstream_peers_reload_interval_ms: None,
+            consensus_sync_timeout_ms: 100,
+            slow_execution_warning_ms: 10_000,
+            max_chunk_execution_retries: 2,
+            chunk_execution_retry_backoff_ms: 100,
+            chunk_execution_retry_expiry_ms: 10_000,
+            max_epoch_proof_retries: 3,
+            epoch_proof_retry_backoff_ms: 100,
+            min_audit_request_interval_ms: 1000,
+            verification_peer: None,
+            verification_interval: 10,
+            min_supported_protocol_version: 0,
+            min_signature_quorum: None,
+            max_concurrent_subscription_deliveries: 32,
+            subscription_delivery_timeout_ms: 10_000,
+            max_chunk_request_deferral_ms: 1000,
+            checkpoint_file: None,
+            checkpoint_interval_ms: 300_000,
+            max_checkpoint_age_ms: 86_400_000,
+            record_session: false,
             upstream_peers: UpstreamPeersConfig::default(),
         }
     }
 }
+
+/// A minimum number of signatures, given either as a count or as a fraction of the validators.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureQuorum {
+    Count(usize),
+    Fraction(f64),
+}
+
+impl SignatureQuorum {
+    /// Number of signatures required out of a set of `num_validators` validators.
+    pub fn num_required(&self, num_validators: usize) -> usize {
+        match self {
+            SignatureQuorum::Count(count) => *count,
+            SignatureQuorum::Fraction(fraction) => {
+                (fraction * num_validators as f64).ceil() as usize
+            }
+        }
+    }
+}
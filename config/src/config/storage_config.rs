@@ -11,6 +11,11 @@ pub struct StorageConfig {
     pub port: u16,
     pub dir: PathBuf,
     pub grpc_max_receive_len: Option<i32>,
+    // how often the internal statistics of RocksDB are published as metrics
+    pub rocksdb_metrics_interval_ms: u64,
+    // whether the state Merkle tree nodes read are checked against their stored checksums to
+    // detect disk corruption, at the cost of an extra read per node
+    pub verify_node_checksums: bool,
 }
 
 impl Default for StorageConfig {
@@ -20,6 +25,8 @@ impl Default for StorageConfig {
             port: 6184,
             dir: PathBuf::from("libradb/db"),
             grpc_max_receive_len: Some(100_000_000),
+            rocksdb_metrics_interval_ms: 10_000,
+            verify_node_checksums: true,
         }
     }
 }
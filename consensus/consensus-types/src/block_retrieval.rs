@@ -3,7 +3,7 @@
 
 use crate::{block::Block, common::Payload};
 use failure::prelude::*;
-use libra_crypto::hash::HashValue;
+use libra_crypto::{hash::HashValue, ShortStr};
 use libra_types::crypto_proxies::ValidatorVerifier;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
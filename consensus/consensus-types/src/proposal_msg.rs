@@ -7,6 +7,7 @@ use crate::{
     sync_info::SyncInfo,
 };
 use failure::prelude::*;
+use libra_crypto::ShortStr;
 use libra_types::crypto_proxies::ValidatorVerifier;
 use serde::{Deserialize, Serialize};
 use std::convert::{TryFrom, TryInto};
@@ -3,7 +3,7 @@
 
 use crate::{common::Author, timeout::Timeout, vote_data::VoteData};
 use failure::{ensure, ResultExt};
-use libra_crypto::hash::CryptoHash;
+use libra_crypto::{hash::CryptoHash, ShortStr};
 use libra_types::{
     crypto_proxies::{Signature, ValidatorSigner, ValidatorVerifier},
     ledger_info::LedgerInfo,
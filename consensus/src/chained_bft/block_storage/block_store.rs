@@ -15,7 +15,7 @@ use consensus_types::{
 };
 use executor::ProcessedVMOutput;
 use failure::ResultExt;
-use libra_crypto::HashValue;
+use libra_crypto::{HashValue, ShortStr};
 use libra_logger::prelude::*;
 
 use libra_types::crypto_proxies::{LedgerInfoWithSignatures, ValidatorVerifier};
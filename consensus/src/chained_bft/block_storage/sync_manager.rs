@@ -16,6 +16,7 @@ use consensus_types::{
     sync_info::SyncInfo,
 };
 use failure;
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use libra_types::account_address::AccountAddress;
 use mirai_annotations::checked_precondition;
@@ -160,10 +161,15 @@ impl<T: Payload> BlockStore<T> {
         // to the stored quorum certs as the new root.
         self.storage
             .save_tree(blocks.clone(), quorum_certs.clone())?;
-        let pre_sync_instance = Instant::now();
-        self.state_computer
+        let sync_completion_info = self
+            .state_computer
             .sync_to_or_bail(highest_ledger_info.ledger_info().clone());
-        counters::STATE_SYNC_DURATION_S.observe_duration(pre_sync_instance.elapsed());
+        counters::STATE_SYNC_DURATION_S.observe_duration(sync_completion_info.duration);
+        debug!(
+            "State sync applied {} versions to reach {}",
+            sync_completion_info.versions_applied,
+            sync_completion_info.reached_li.ledger_info()
+        );
         let root = (
             blocks.pop().expect("should have 3-chain"),
             quorum_certs.last().expect("should have 3-chain").clone(),
@@ -25,7 +25,7 @@ use libra_config::config::{
     ConsensusProposerType::{self, FixedProposer, MultipleOrderedProposers, RotatingProposer},
     {SafetyRulesBackend, SafetyRulesConfig},
 };
-use libra_crypto::hash::CryptoHash;
+use libra_crypto::{hash::CryptoHash, ShortStr};
 use libra_types::{
     crypto_proxies::{
         random_validator_verifier, LedgerInfoWithSignatures, ValidatorSigner, ValidatorVerifier,
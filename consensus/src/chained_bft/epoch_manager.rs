@@ -113,23 +113,41 @@ impl<T: Payload> EpochManager<T> {
         }
     }
 
+    /// Sends the epoch change proof from start_epoch to the current epoch one page at a time, the
+    /// peer verifies each page against the validators the previous one ratcheted it to.
     pub async fn process_epoch_retrieval(&mut self, start_epoch: u64, peer_id: AccountAddress) {
-        let proof = match self.state_computer.get_epoch_proof(start_epoch).await {
-            Ok(proof) => proof,
-            Err(e) => {
-                warn!("Failed to get epoch proof from storage: {:?}", e);
+        let mut start_epoch = start_epoch;
+        loop {
+            let page = match self
+                .state_computer
+                .get_epoch_proof(start_epoch, self.epoch)
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    warn!("Failed to get epoch proof from storage: {:?}", e);
+                    return;
+                }
+            };
+            let next_epoch = match page.proof.ledger_info_with_sigs.last() {
+                Some(ledger_info) => ledger_info.ledger_info().epoch() + 1,
+                None => return,
+            };
+            let msg = ConsensusMsg {
+                message: Some(ConsensusMsg_oneof::EpochChange(page.proof.into())),
+            };
+            if let Err(e) = self.network_sender.send_to(peer_id, msg).await {
+                warn!(
+                    "Failed to send a epoch retrieval to peer {}: {:?}",
+                    peer_id, e
+                );
+                return;
+            };
+            if !page.more {
                 return;
             }
-        };
-        let msg = ConsensusMsg {
-            message: Some(ConsensusMsg_oneof::EpochChange(proof.into())),
-        };
-        if let Err(e) = self.network_sender.send_to(peer_id, msg).await {
-            warn!(
-                "Failed to send a epoch retrieval to peer {}: {:?}",
-                peer_id, e
-            );
-        };
+            start_epoch = next_epoch;
+        }
     }
 
     pub async fn process_different_epoch(&mut self, different_epoch: u64, peer_id: AccountAddress) {
@@ -31,7 +31,7 @@ use consensus_types::{
     vote_proposal::VoteProposal,
 };
 use failure::ResultExt;
-use libra_crypto::hash::TransactionAccumulatorHasher;
+use libra_crypto::{hash::TransactionAccumulatorHasher, ShortStr};
 use libra_logger::prelude::*;
 use libra_prost_ext::MessageExt;
 use libra_types::crypto_proxies::{
@@ -462,11 +462,8 @@ impl<T: Payload> NetworkTask<T> {
         let msg_epoch = proof.epoch()?;
         match msg_epoch.cmp(&self.epoch) {
             Ordering::Equal => {
-                let target_ledger_info = proof.verify(self.epoch, &self.validators)?;
-                let validators = match target_ledger_info.ledger_info().next_validator_set() {
-                    Some(v) => v.into(),
-                    None => bail!("Epoch change doesn't carry next validator set"),
-                };
+                let (target_ledger_info, validators) =
+                    proof.verify_and_ratchet(self.epoch, &self.validators)?;
                 self.epoch = target_ledger_info.ledger_info().epoch() + 1;
                 self.validators = Arc::new(validators);
                 self.epoch_change_tx.push(peer_id, target_ledger_info)
@@ -11,11 +11,22 @@ use executor::{ExecutedTrees, ProcessedVMOutput};
 use failure::Result;
 use futures::{channel::mpsc, future, Future, FutureExt};
 use libra_logger::prelude::*;
-use libra_types::crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof};
+use libra_types::crypto_proxies::LedgerInfoWithSignatures;
 use libra_types::validator_set::ValidatorSet;
-use std::{pin::Pin, sync::Arc};
+use state_synchronizer::{EpochProofPage, SyncCompletionInfo, SyncSessionMetrics};
+use std::{pin::Pin, sync::Arc, time::Duration};
 use termion::color::*;
 
+/// The completion of a sync that reached `target` instantly without applying anything.
+fn fake_sync_completion_info(target: LedgerInfoWithSignatures) -> SyncCompletionInfo {
+    SyncCompletionInfo {
+        reached_li: target,
+        versions_applied: 0,
+        duration: Duration::from_secs(0),
+        metrics: SyncSessionMetrics::default(),
+    }
+}
+
 pub struct MockStateComputer {
     commit_callback: mpsc::UnboundedSender<LedgerInfoWithSignatures>,
     consensus_db: Arc<MockStorage<TestPayload>>,
@@ -68,7 +79,7 @@ impl StateComputer for MockStateComputer {
     fn sync_to(
         &self,
         commit: LedgerInfoWithSignatures,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Result<SyncCompletionInfo>> + Send>> {
         debug!(
             "{}Fake sync{} to block id {}",
             Fg(Blue),
@@ -80,7 +91,7 @@ impl StateComputer for MockStateComputer {
         self.commit_callback
             .unbounded_send(commit.clone())
             .expect("Fail to notify about sync");
-        async { Ok(()) }.boxed()
+        future::ok(fake_sync_completion_info(commit)).boxed()
     }
 
     fn committed_trees(&self) -> ExecutedTrees {
@@ -90,7 +101,8 @@ impl StateComputer for MockStateComputer {
     fn get_epoch_proof(
         &self,
         _start_epoch: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<ValidatorChangeEventWithProof>> + Send>> {
+        _end_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
         future::err(format_err!(
             "epoch proof not supported in mock state computer"
         ))
@@ -125,9 +137,9 @@ impl StateComputer for EmptyStateComputer {
 
     fn sync_to(
         &self,
-        _commit: LedgerInfoWithSignatures,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
-        async { Ok(()) }.boxed()
+        commit: LedgerInfoWithSignatures,
+    ) -> Pin<Box<dyn Future<Output = Result<SyncCompletionInfo>> + Send>> {
+        future::ok(fake_sync_completion_info(commit)).boxed()
     }
 
     fn committed_trees(&self) -> ExecutedTrees {
@@ -137,7 +149,8 @@ impl StateComputer for EmptyStateComputer {
     fn get_epoch_proof(
         &self,
         _start_epoch: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<ValidatorChangeEventWithProof>> + Send>> {
+        _end_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
         future::err(format_err!(
             "epoch proof not supported in empty state computer"
         ))
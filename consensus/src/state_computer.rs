@@ -8,12 +8,11 @@ use executor::{CommittableBlock, ExecutedTrees, Executor, ProcessedVMOutput};
 use failure::Result;
 use futures::{Future, FutureExt};
 use libra_logger::prelude::*;
-use libra_types::crypto_proxies::ValidatorChangeEventWithProof;
 use libra_types::{
     crypto_proxies::LedgerInfoWithSignatures,
     transaction::{SignedTransaction, Transaction},
 };
-use state_synchronizer::StateSyncClient;
+use state_synchronizer::{EpochProofPage, StateSyncClient, SyncCompletionInfo, SyncPurpose};
 use std::{
     convert::TryFrom,
     pin::Pin,
@@ -143,9 +142,11 @@ impl StateComputer for ExecutionProxy {
     fn sync_to(
         &self,
         target: LedgerInfoWithSignatures,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+    ) -> Pin<Box<dyn Future<Output = Result<SyncCompletionInfo>> + Send>> {
         counters::STATE_SYNC_COUNT.inc();
-        self.synchronizer.sync_to(target).boxed()
+        self.synchronizer
+            .sync_to(target, SyncPurpose::Consensus)
+            .boxed()
     }
 
     fn committed_trees(&self) -> ExecutedTrees {
@@ -155,7 +156,10 @@ impl StateComputer for ExecutionProxy {
     fn get_epoch_proof(
         &self,
         start_epoch: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<ValidatorChangeEventWithProof>> + Send>> {
-        self.synchronizer.get_epoch_proof(start_epoch).boxed()
+        end_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
+        self.synchronizer
+            .get_epoch_proof(start_epoch, Some(end_epoch), None)
+            .boxed()
     }
 }
@@ -6,7 +6,8 @@ use consensus_types::executed_block::ExecutedBlock;
 use executor::{ExecutedTrees, ProcessedVMOutput, StateComputeResult};
 use failure::Result;
 use futures::Future;
-use libra_types::crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof};
+use libra_types::crypto_proxies::LedgerInfoWithSignatures;
+use state_synchronizer::{EpochProofPage, SyncCompletionInfo};
 use std::{pin::Pin, sync::Arc};
 
 /// Retrieves and updates the status of transactions on demand (e.g., via talking with Mempool)
@@ -58,17 +59,18 @@ pub trait StateComputer: Send + Sync {
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
     /// Best effort state synchronization to the given target LedgerInfo.
-    /// In case of success (`Result::Ok`) the LI of storage is at the given target.
+    /// In case of success (`Result::Ok`) the LI of storage is at the given target, which is
+    /// returned in the `SyncCompletionInfo`.
     /// In case of failure (`Result::Error`) the LI of storage remains unchanged, and the validator
     /// can assume there were no modifications to the storage made.
     fn sync_to(
         &self,
         target: LedgerInfoWithSignatures,
-    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
+    ) -> Pin<Box<dyn Future<Output = Result<SyncCompletionInfo>> + Send>>;
 
     fn committed_trees(&self) -> ExecutedTrees;
 
-    fn sync_to_or_bail(&self, commit: LedgerInfoWithSignatures) {
+    fn sync_to_or_bail(&self, commit: LedgerInfoWithSignatures) -> SyncCompletionInfo {
         let status = futures::executor::block_on(self.sync_to(commit));
         // TODO: this is going to change after https://github.com/libra/libra/issues/1590
         status.expect(
@@ -78,11 +80,13 @@ pub trait StateComputer: Send + Sync {
         )
     }
 
-    /// Generate the epoch change proof from start_epoch to the latest epoch.
+    /// Generate a page of the epoch change proof from start_epoch up to, but excluding,
+    /// end_epoch. The page is flagged with `more` if the range isn't fully covered.
     fn get_epoch_proof(
         &self,
         start_epoch: u64,
-    ) -> Pin<Box<dyn Future<Output = Result<ValidatorChangeEventWithProof>> + Send>>;
+        end_epoch: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>>;
 }
 
 pub trait StateMachineReplication {
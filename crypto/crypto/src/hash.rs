@@ -97,7 +97,7 @@ use mirai_annotations::*;
 use proptest_derive::Arbitrary;
 use rand::{rngs::EntropyRng, Rng};
 use serde::{de, ser};
-use std::{self, convert::AsRef, fmt};
+use std::{self, convert::AsRef, fmt, str::FromStr};
 use tiny_keccak::Keccak;
 
 const LIBRA_HASH_SUFFIX: &[u8] = b"@@$$LIBRA$$@@";
@@ -108,6 +108,37 @@ mod hash_test;
 
 const SHORT_STRING_LENGTH: usize = 4;
 
+/// The abbreviated form of hashes and hash-like identifiers used in log lines: `0x` followed by
+/// the first 4 bytes in hex.
+pub trait ShortStr {
+    /// Returns the abbreviated form of `self`.
+    fn short_str(&self) -> String;
+}
+
+/// Returns `0x` followed by the first 4 bytes of `bytes` in hex, the form of `ShortStr`.
+pub fn short_hex(bytes: &[u8]) -> String {
+    format!("0x{}", hex::encode(&bytes[..SHORT_STRING_LENGTH]))
+}
+
+/// Decodes the full hex form of a `num_bytes` long value, with or without `0x`. Abbreviated
+/// forms such as the ones of `ShortStr` are rejected since they are ambiguous.
+pub fn decode_full_hex(s: &str, num_bytes: usize) -> Result<Vec<u8>> {
+    let hex_str = if s.starts_with("0x") { &s[2..] } else { s };
+    ensure!(
+        hex_str.len() >= num_bytes * 2,
+        "Ambiguous abbreviated form {}, all {} hex digits are needed",
+        s,
+        num_bytes * 2
+    );
+    ensure!(
+        hex_str.len() == num_bytes * 2,
+        "{} has more than {} hex digits",
+        s,
+        num_bytes * 2
+    );
+    Ok(hex::decode(hex_str)?)
+}
+
 /// Output value of our hash function. Intentionally opaque for safety and modularity.
 #[derive(Clone, Copy, Eq, Hash, PartialEq, PartialOrd, Ord)]
 #[cfg_attr(any(test, feature = "fuzzing"), derive(Arbitrary))]
@@ -246,10 +277,20 @@ impl HashValue {
             self[index / 2] & 0x0F
         })
     }
+}
+
+impl ShortStr for HashValue {
+    fn short_str(&self) -> String {
+        short_hex(&self.hash)
+    }
+}
+
+impl FromStr for HashValue {
+    type Err = failure::Error;
 
-    /// Returns first SHORT_STRING_LENGTH bytes as String in hex
-    pub fn short_str(&self) -> String {
-        hex::encode(&self.hash[0..SHORT_STRING_LENGTH]).to_string()
+    /// Parses the full hex form of a hash, with or without `0x`.
+    fn from_str(s: &str) -> Result<Self> {
+        HashValue::from_slice(&decode_full_hex(s, HashValue::LENGTH)?)
     }
 }
 
@@ -335,13 +376,14 @@ impl fmt::Debug for HashValue {
     }
 }
 
-/// Will print shortened (4 bytes) hash
+/// Will print the shortened hash of `ShortStr`, or the full hash with `{:#}`, both with `0x`
 impl fmt::Display for HashValue {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for byte in self.hash.iter().take(4) {
-            write!(f, "{:02x}", byte)?;
+        if f.alternate() {
+            write!(f, "0x{:x}", self)
+        } else {
+            write!(f, "{}", self.short_str())
         }
-        Ok(())
     }
 }
 
@@ -74,6 +74,20 @@
 //! let derived_bytes = Hkdf::<Sha256>::extract_then_expand(salt, &seed, info, 64);
 //! assert_eq!(derived_bytes.unwrap().len(), 64)
 //! ```
+//!
+//! The salt and the application info are both plain byte-arrays above, which makes it easy to
+//! pass one for the other. Key derivation schemes that fix them, e.g. to derive the keys of a
+//! wallet, should rather wrap them in `HkdfSalt` and `HkdfInfo`, which `derive` and the
+//! `_with_salt` / `_with_info` variants of the steps take, so that swapping them does not
+//! compile. `Sha3Hkdf` is the HKDF based on SHA3-256, the hash function used across Libra.
+//! ```
+//! use libra_crypto::hkdf::{HkdfInfo, HkdfSalt, Sha3Hkdf};
+//!
+//! const SALT: HkdfSalt<'static> = HkdfSalt(b"EXAMPLE: salt$");
+//! let seed = [3u8; 32];
+//! let derived_bytes = Sha3Hkdf::derive(SALT, &seed, HkdfInfo(b"EXAMPLE: key 0$"), 32);
+//! assert_eq!(derived_bytes.unwrap().len(), 32)
+//! ```
 
 use digest::{
     generic_array::{self, ArrayLength, GenericArray},
@@ -81,8 +95,20 @@ use digest::{
 };
 use generic_array::typenum::Unsigned;
 use hmac::{Hmac, Mac};
+use sha3::Sha3_256;
 use std::marker::PhantomData;
 
+/// HKDF based on the SHA3-256 hash function.
+pub type Sha3Hkdf = Hkdf<Sha3_256>;
+
+/// Salt of HKDF-Extract ('salt' in RFC 5869), kept apart from `HkdfInfo` by its type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HkdfSalt<'a>(pub &'a [u8]);
+
+/// Application info of HKDF-Expand ('info' in RFC 5869), kept apart from `HkdfSalt` by its type.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HkdfInfo<'a>(pub &'a [u8]);
+
 /// Structure representing the HKDF, capable of HKDF-Extract and HKDF-Expand operations, as defined
 /// in RFC 5869.
 #[derive(Clone, Debug)]
@@ -163,6 +189,32 @@ where
         let prk = Hkdf::<D>::extract(salt, ikm)?;
         Hkdf::<D>::expand(&prk, info, length)
     }
+
+    /// The RFC5869 HKDF-Extract operation with a typed salt.
+    pub fn extract_with_salt(salt: HkdfSalt, ikm: &[u8]) -> Result<Vec<u8>, HkdfError> {
+        Hkdf::<D>::extract(Some(salt.0), ikm)
+    }
+
+    /// The RFC5869 HKDF-Expand operation with a typed application info.
+    pub fn expand_with_info(
+        prk: &[u8],
+        info: HkdfInfo,
+        length: usize,
+    ) -> Result<Vec<u8>, HkdfError> {
+        Hkdf::<D>::expand(prk, Some(info.0), length)
+    }
+
+    /// HKDF Extract then Expand operation as a single step, with a typed salt and application
+    /// info.
+    pub fn derive(
+        salt: HkdfSalt,
+        ikm: &[u8],
+        info: HkdfInfo,
+        length: usize,
+    ) -> Result<Vec<u8>, HkdfError> {
+        let prk = Hkdf::<D>::extract_with_salt(salt, ikm)?;
+        Hkdf::<D>::expand_with_info(&prk, info, length)
+    }
 }
 
 /// An error type for HKDF key derivation issues.
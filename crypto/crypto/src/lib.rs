@@ -19,4 +19,4 @@ mod unit_tests;
 pub mod test_utils;
 
 pub use self::traits::*;
-pub use hash::HashValue;
+pub use hash::{HashValue, ShortStr};
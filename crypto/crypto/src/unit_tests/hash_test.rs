@@ -11,6 +11,24 @@ use serde::Serialize;
 #[derive(Serialize)]
 struct Foo(u32);
 
+#[test]
+fn test_display_and_from_str() {
+    let full = "3338be694f50c5f338814986cdf0686453a888b84f424d792af4b9202398f392";
+    let hash = b"hello".test_only_hash();
+    assert_eq!(hash.to_string(), "0x3338be69");
+    assert_eq!(hash.short_str(), "0x3338be69");
+    assert_eq!(format!("{:#}", hash), format!("0x{}", full));
+
+    assert_eq!(full.parse::<HashValue>().unwrap(), hash);
+    assert_eq!(format!("{:#}", hash).parse::<HashValue>().unwrap(), hash);
+    // the abbreviated forms are ambiguous
+    let err = hash.to_string().parse::<HashValue>().unwrap_err();
+    assert!(err.to_string().contains("Ambiguous"));
+    assert!("3338be69".parse::<HashValue>().is_err());
+    assert!(format!("{}00", full).parse::<HashValue>().is_err());
+    assert!(full[1..].parse::<HashValue>().is_err());
+}
+
 #[test]
 fn test_default_hasher() {
     assert_eq!(
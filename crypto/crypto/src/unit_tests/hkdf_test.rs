@@ -24,6 +24,25 @@ fn test_sha256_test_vectors() {
     }
 }
 
+// Testing against the inputs of the sha256 test vectors, with outputs computed for sha3.
+#[test]
+fn test_sha3_256_test_vectors() {
+    let tests = test_vectors_sha3_256();
+    for t in tests.iter() {
+        let ikm = hex::decode(&t.ikm).unwrap();
+        let salt = hex::decode(&t.salt).unwrap();
+        let info = hex::decode(&t.info).unwrap();
+
+        let hkdf_extract = Sha3Hkdf::extract_with_salt(HkdfSalt(&salt), &ikm).unwrap();
+        let hkdf_expand = Sha3Hkdf::expand_with_info(&hkdf_extract, HkdfInfo(&info), t.length);
+        let hkdf_full = Sha3Hkdf::derive(HkdfSalt(&salt), &ikm, HkdfInfo(&info), t.length);
+
+        assert_eq!(t.prk, hex::encode(hkdf_extract));
+        assert_eq!(t.okm, hex::encode(hkdf_expand.unwrap()));
+        assert_eq!(t.okm, hex::encode(hkdf_full.unwrap()));
+    }
+}
+
 // Testing against sha256 test vectors for the extract_then_expand function.
 #[test]
 fn test_extract_then_expand() {
@@ -214,3 +233,30 @@ fn test_vectors_sha256<'a>() -> Vec<Test<'a>> {
         },
     ]
 }
+
+// The inputs of the test vectors for sha256 from https://tools.ietf.org/html/rfc5869, with the
+// outputs of HKDF based on sha3-256.
+fn test_vectors_sha3_256<'a>() -> Vec<Test<'a>> {
+    test_vectors_sha256()
+        .into_iter()
+        .zip(vec![
+            (
+                "7d4194836f7a113a44677abc825640ade07af1c1d69a9a4b109b280a8fe54ef0",
+                "0c5160501d65021deaf2c14f5abce04c5bd2635abceeba61c2edb6e8ed726749005577\
+                 28f2c9f2c4c179",
+            ),
+            (
+                "addf31835b49366ac27734104d9f1865c1c2e7c8a2ebc1fed712808e4eab677c",
+                "3dc251e66c75da6560405ec5ac10e17d851eedfbfdc13feafbec16964c25d021bd9714\
+                 65a3e9c615f27769019e3f0407d84986fb0ba24e729c99834624baa21cb623dc0098f4\
+                 30d52e18bbdf694df4edd8b2",
+            ),
+            (
+                "b899e6e4b88a35f9f5d618f48b424c313f9704012763eb6295414d673365928a",
+                "bc1342cdd75c05e8b0c3ae609ce4410684d197232875073499b30cdfe2de2853c1c1be\
+                 d63d725e885e78",
+            ),
+        ])
+        .map(|(t, (prk, okm))| Test { prk, okm, ..t })
+        .collect()
+}
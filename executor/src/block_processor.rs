@@ -2,8 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    Chunk, Command, CommittableBlock, CommittableBlockBatch, ExecutableBlock, ExecutedTrees,
-    ProcessedVMOutput, TransactionData, OP_COUNTERS,
+    Chunk, ChunkExecutionStages, Command, CommittableBlock, CommittableBlockBatch,
+    CommittedTransactionData, ExecutableBlock, ExecutedTrees, ProcessedVMOutput, TransactionData,
+    OP_COUNTERS,
 };
 use failure::prelude::*;
 use futures::channel::oneshot;
@@ -33,6 +34,7 @@ use std::{
     convert::TryFrom,
     marker::PhantomData,
     sync::{mpsc, Arc, Mutex},
+    time::Instant,
 };
 use storage_client::{StorageRead, StorageWrite, VerifiedStateView};
 use vm_runtime::VMExecutor;
@@ -53,6 +55,9 @@ pub(crate) struct BlockProcessor<V> {
     /// The latest committed merkle trees.
     committed_trees: Arc<Mutex<ExecutedTrees>>,
 
+    /// Stage durations of the last chunk executed, shared with the executor.
+    last_chunk_stages: Arc<Mutex<ChunkExecutionStages>>,
+
     /// The latest merkle trees synced to storage but not committed. `synced_trees` are always ahead of committed_trees or be `None`.
     synced_trees: Option<ExecutedTrees>,
 
@@ -86,6 +91,7 @@ where
         storage_read_client: Arc<dyn StorageRead>,
         storage_write_client: Arc<dyn StorageWrite>,
         committed_trees: Arc<Mutex<ExecutedTrees>>,
+        last_chunk_stages: Arc<Mutex<ChunkExecutionStages>>,
         synced_trees: Option<ExecutedTrees>,
         committed_timestamp_usecs: u64,
         vm_config: VMConfig,
@@ -96,6 +102,7 @@ where
             command_receiver,
             committed_timestamp_usecs,
             committed_trees,
+            last_chunk_stages,
             synced_trees,
             blocks_to_execute: VecDeque::new(),
             block_batch_to_commit: None,
@@ -261,7 +268,9 @@ where
 
     /// Verifies the transactions based on the provided proofs and ledger info. If the transactions
     /// are valid, executes them and commits immediately if execution results match the proofs.
-    fn execute_and_commit_chunk(&mut self, chunk: Chunk) -> Result<()> {
+    /// Returns the data of the committed transactions.
+    fn execute_and_commit_chunk(&mut self, chunk: Chunk) -> Result<Vec<CommittedTransactionData>> {
+        *self.last_chunk_stages.lock().unwrap() = ChunkExecutionStages::default();
         if chunk.ledger_info_with_sigs.ledger_info().timestamp_usecs()
             <= self.committed_timestamp_usecs
         {
@@ -270,7 +279,7 @@ where
                 self.committed_timestamp_usecs,
                 chunk.ledger_info_with_sigs.ledger_info().timestamp_usecs(),
             );
-            return Ok(());
+            return Ok(vec![]);
         }
 
         if let Mode::Normal = self.mode {
@@ -290,8 +299,11 @@ where
             chunk.txn_list_with_proof.transactions.len(),
         );
 
+        let verify_start = Instant::now();
         let (num_txns_to_skip, first_version) =
             Self::verify_chunk(&chunk, synced_trees.txn_accumulator().num_leaves())?;
+        self.last_chunk_stages.lock().unwrap().verify = verify_start.elapsed();
+        let execute_start = Instant::now();
 
         let (txn_list_with_proof, li_with_sigs) =
             (chunk.txn_list_with_proof, chunk.ledger_info_with_sigs);
@@ -323,6 +335,10 @@ where
         }
 
         let (account_to_btree, account_to_proof) = state_view.into();
+        let write_sets: Vec<_> = vm_outputs
+            .iter()
+            .map(|output| output.write_set().clone())
+            .collect();
 
         let output = Self::process_vm_outputs(
             account_to_btree,
@@ -362,15 +378,33 @@ where
             // nothing to write to storage. Since storage expect either new transaction or new
             // ledger info, we need to return here.
             if txns_to_commit.is_empty() {
-                return Ok(());
+                return Ok(vec![]);
             }
             None
         };
+        let committed_data: Vec<_> = itertools::zip_eq(
+            itertools::zip_eq(&txns_to_commit, write_sets),
+            output.transaction_data(),
+        )
+        .enumerate()
+        .map(
+            |(i, ((txn_to_commit, write_set), txn_data))| CommittedTransactionData {
+                version: first_version + i as Version,
+                txn: txn_to_commit.transaction().clone(),
+                status: txn_data.status().clone(),
+                events: txn_to_commit.events().to_vec(),
+                write_set,
+            },
+        )
+        .collect();
+        self.last_chunk_stages.lock().unwrap().execute = execute_start.elapsed();
+        let save_start = Instant::now();
         self.storage_write_client.save_transactions(
             txns_to_commit,
             first_version,
             ledger_info_to_commit.clone(),
         )?;
+        self.last_chunk_stages.lock().unwrap().save = save_start.elapsed();
 
         self.synced_trees = Some(output.executed_trees().clone());
 
@@ -385,7 +419,7 @@ where
                 ledger_info_with_sigs.ledger_info().version()
             );
         }
-        Ok(())
+        Ok(committed_data)
     }
 
     /// Verifies proofs using provided ledger info. Also verifies that the version of the first
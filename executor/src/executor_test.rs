@@ -5,7 +5,7 @@ use crate::{
     mock_vm::{
         encode_mint_transaction, encode_transfer_transaction, MockVM, DISCARD_STATUS, KEEP_STATUS,
     },
-    CommittableBlock, Executor, OP_COUNTERS,
+    CommittableBlock, ExecutedTrees, Executor, OP_COUNTERS,
 };
 use futures::executor::block_on;
 use grpcio::{EnvBuilder, ServerBuilder};
@@ -15,6 +15,7 @@ use libra_prost_ext::MessageExt;
 use libra_types::block_info::BlockInfo;
 use libra_types::{
     account_address::{AccountAddress, ADDRESS_LENGTH},
+    account_state_blob::AccountStateBlob,
     crypto_proxies::LedgerInfoWithSignatures,
     ledger_info::LedgerInfo,
     transaction::{Transaction, TransactionListWithProof, Version},
@@ -23,6 +24,7 @@ use proptest::prelude::*;
 use rusty_fork::{rusty_fork_id, rusty_fork_test, rusty_fork_test_name};
 use std::{
     collections::BTreeMap,
+    convert::TryInto,
     fs::File,
     io::Write,
     sync::{mpsc, Arc},
@@ -118,27 +120,36 @@ fn execute_and_commit_block(executor: &TestExecutor, txn_index: u64) {
     .unwrap();
 }
 
-struct TestExecutor {
+pub(crate) struct TestExecutor {
     // The config is kept around because it owns the temp dir used in the test.
-    _config: NodeConfig,
+    config: NodeConfig,
     storage_server: Option<grpcio::Server>,
     shutdown_receiver: mpsc::Receiver<()>,
     executor: Executor<MockVM>,
 }
 
 impl TestExecutor {
-    fn new() -> TestExecutor {
+    pub(crate) fn new() -> TestExecutor {
         let mut config = get_config();
         let (storage_server, shutdown_receiver) = create_storage_server(&mut config);
         let executor = create_executor(&config);
 
         TestExecutor {
-            _config: config,
+            config,
             storage_server: Some(storage_server),
             shutdown_receiver,
             executor,
         }
     }
+
+    /// A client of the storage the executor commits to.
+    pub(crate) fn storage_read_client(&self) -> StorageReadServiceClient {
+        StorageReadServiceClient::new(
+            Arc::new(EnvBuilder::new().build()),
+            "localhost",
+            self.config.storage.port,
+        )
+    }
 }
 
 impl std::ops::Deref for TestExecutor {
@@ -158,21 +169,21 @@ impl Drop for TestExecutor {
     }
 }
 
-fn gen_address(index: u64) -> AccountAddress {
+pub(crate) fn gen_address(index: u64) -> AccountAddress {
     let bytes = index.to_be_bytes();
     let mut buf = [0; ADDRESS_LENGTH];
     buf[ADDRESS_LENGTH - 8..].copy_from_slice(&bytes);
     AccountAddress::new(buf)
 }
 
-fn gen_block_id(index: u64) -> HashValue {
+pub(crate) fn gen_block_id(index: u64) -> HashValue {
     let bytes = index.to_be_bytes();
     let mut buf = [0; HashValue::LENGTH];
     buf[HashValue::LENGTH - 8..].copy_from_slice(&bytes);
     HashValue::new(buf)
 }
 
-fn gen_ledger_info(
+pub(crate) fn gen_ledger_info(
     version: u64,
     root_hash: HashValue,
     commit_block_id: HashValue,
@@ -307,6 +318,63 @@ fn test_executor_execute_same_block_multiple_times() {
     }
 }
 
+fn balance(blob: Option<AccountStateBlob>) -> Option<u64> {
+    blob.map(|blob| {
+        let account_state: BTreeMap<Vec<u8>, Vec<u8>> = (&blob).try_into().unwrap();
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&account_state[&b"balance".to_vec()]);
+        u64::from_le_bytes(buf)
+    })
+}
+
+#[test]
+fn test_executor_speculative_account_state() {
+    let executor = TestExecutor::new();
+    execute_and_commit_block(&executor, 0);
+
+    // Committed -> B -> D
+    //           |
+    //           └--> C
+    let block_b = TestBlock::new(1..2, 10, gen_block_id(1), gen_block_id(2));
+    let block_c = TestBlock::new(1..3, 20, gen_block_id(1), gen_block_id(3));
+    let block_d = TestBlock::new(1..2, 5, gen_block_id(2), gen_block_id(4));
+    let execute = |block: &TestBlock, parent_trees| {
+        block_on(executor.execute_block(
+            block.txns.clone(),
+            parent_trees,
+            block.parent_id,
+            block.id,
+        ))
+        .unwrap()
+        .unwrap()
+        .executed_trees()
+        .clone()
+    };
+    let trees_b = execute(&block_b, executor.committed_trees());
+    let trees_c = execute(&block_c, executor.committed_trees());
+    let trees_d = execute(&block_d, trees_b.clone());
+
+    let read = |trees: &ExecutedTrees, index| {
+        balance(
+            executor
+                .get_account_state(trees, gen_address(index))
+                .unwrap(),
+        )
+    };
+    // Each branch sees its own updates on top of the committed state read from storage.
+    for trees in vec![&trees_b, &trees_c, &trees_d] {
+        assert_eq!(read(trees, 0), Some(100));
+        assert_eq!(read(trees, 3), None);
+    }
+    assert_eq!(read(&trees_b, 1), Some(10));
+    assert_eq!(read(&trees_b, 2), None);
+    assert_eq!(read(&trees_c, 1), Some(20));
+    assert_eq!(read(&trees_c, 2), Some(20));
+    assert_eq!(read(&trees_d, 1), Some(15));
+    assert_eq!(read(&trees_d, 2), None);
+    assert_eq!(read(&executor.committed_trees(), 1), None);
+}
+
 rusty_fork_test! {
     #[test]
     fn test_num_accounts_created_counter() {
@@ -417,47 +485,80 @@ fn test_executor_execute_and_commit_chunk() {
     );
 
     // Execute the first chunk. After that we should still get the genesis ledger info from DB.
-    block_on(executor.execute_and_commit_chunk(chunks[0].clone(), ledger_info.clone()))
-        .unwrap()
-        .unwrap();
+    let mut committed_data =
+        block_on(executor.execute_and_commit_chunk(chunks[0].clone(), ledger_info.clone()))
+            .unwrap()
+            .unwrap();
     let (_, li, _, _) = storage_client.update_to_latest_ledger(0, vec![]).unwrap();
     assert_eq!(li.ledger_info().version(), 0);
     assert_eq!(li.ledger_info().consensus_block_id(), *PRE_GENESIS_BLOCK_ID);
 
     // Execute the second chunk. After that we should still get the genesis ledger info from DB.
-    block_on(executor.execute_and_commit_chunk(chunks[1].clone(), ledger_info.clone()))
-        .unwrap()
-        .unwrap();
+    committed_data.extend(
+        block_on(executor.execute_and_commit_chunk(chunks[1].clone(), ledger_info.clone()))
+            .unwrap()
+            .unwrap(),
+    );
     let (_, li, _, _) = storage_client.update_to_latest_ledger(0, vec![]).unwrap();
     assert_eq!(li.ledger_info().version(), 0);
     assert_eq!(li.ledger_info().consensus_block_id(), *PRE_GENESIS_BLOCK_ID);
 
     // Execute an empty chunk. After that we should still get the genesis ledger info from DB.
-    block_on(
+    assert!(block_on(
         executor
             .execute_and_commit_chunk(TransactionListWithProof::new_empty(), ledger_info.clone()),
     )
     .unwrap()
-    .unwrap();
+    .unwrap()
+    .is_empty());
     let (_, li, _, _) = storage_client.update_to_latest_ledger(0, vec![]).unwrap();
     assert_eq!(li.ledger_info().version(), 0);
     assert_eq!(li.ledger_info().consensus_block_id(), *PRE_GENESIS_BLOCK_ID);
 
-    // Execute the second chunk again. After that we should still get the same thing.
-    block_on(executor.execute_and_commit_chunk(chunks[1].clone(), ledger_info.clone()))
-        .unwrap()
-        .unwrap();
+    // Execute the second chunk again. After that we should still get the same thing, and nothing
+    // is reported as newly committed.
+    assert!(
+        block_on(executor.execute_and_commit_chunk(chunks[1].clone(), ledger_info.clone()))
+            .unwrap()
+            .unwrap()
+            .is_empty()
+    );
     let (_, li, _, _) = storage_client.update_to_latest_ledger(0, vec![]).unwrap();
     assert_eq!(li.ledger_info().version(), 0);
     assert_eq!(li.ledger_info().consensus_block_id(), *PRE_GENESIS_BLOCK_ID);
 
     // Execute the third chunk. After that we should get the new ledger info.
-    block_on(executor.execute_and_commit_chunk(chunks[2].clone(), ledger_info.clone()))
-        .unwrap()
-        .unwrap();
+    committed_data.extend(
+        block_on(executor.execute_and_commit_chunk(chunks[2].clone(), ledger_info.clone()))
+            .unwrap()
+            .unwrap(),
+    );
     let (_, li, _, _) = storage_client.update_to_latest_ledger(0, vec![]).unwrap();
     assert_eq!(li, ledger_info);
 
+    // Every version was reported committed exactly once, with the data storage returns for it.
+    let ledger_version = li.ledger_info().version();
+    let txn_list = storage_client
+        .get_transactions(
+            1,
+            ledger_version,
+            ledger_version,
+            true, /* fetch_events */
+        )
+        .unwrap();
+    assert_eq!(committed_data.len() as u64, ledger_version);
+    for (i, ((data, txn), events)) in committed_data
+        .iter()
+        .zip(txn_list.transactions)
+        .zip(txn_list.events.unwrap())
+        .enumerate()
+    {
+        assert_eq!(data.version, i as Version + 1);
+        assert_eq!(data.txn, txn);
+        assert_eq!(data.events, events);
+        assert!(!data.write_set.is_empty());
+    }
+
     drop(storage_server);
     shutdown_receiver.recv().unwrap();
 }
@@ -7,6 +7,8 @@ mod block_processor;
 mod executor_test;
 #[cfg(test)]
 mod mock_vm;
+#[cfg(test)]
+mod state_root_test;
 
 use crate::block_processor::BlockProcessor;
 use failure::{format_err, Result};
@@ -16,8 +18,8 @@ use lazy_static::lazy_static;
 use libra_config::config::NodeConfig;
 use libra_crypto::{
     hash::{
-        EventAccumulatorHasher, TransactionAccumulatorHasher, ACCUMULATOR_PLACEHOLDER_HASH,
-        PRE_GENESIS_BLOCK_ID, SPARSE_MERKLE_PLACEHOLDER_HASH,
+        CryptoHash, EventAccumulatorHasher, TransactionAccumulatorHasher,
+        ACCUMULATOR_PLACEHOLDER_HASH, PRE_GENESIS_BLOCK_ID, SPARSE_MERKLE_PLACEHOLDER_HASH,
     },
     HashValue,
 };
@@ -32,13 +34,15 @@ use libra_types::{
     proof::accumulator::InMemoryAccumulator,
     transaction::{Transaction, TransactionListWithProof, TransactionStatus, Version},
     validator_set::ValidatorSet,
+    write_set::WriteSet,
 };
-use scratchpad::SparseMerkleTree;
+use scratchpad::{AccountState, SparseMerkleTree};
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::{
     marker::PhantomData,
     sync::{mpsc, Arc, Mutex},
+    time::Duration,
 };
 use storage_client::{StorageRead, StorageWrite};
 use vm_runtime::VMExecutor;
@@ -48,6 +52,29 @@ lazy_static! {
         libra_metrics::OpMetrics::new_and_registered("executor");
 }
 
+/// A transaction committed by `execute_and_commit_chunk`, together with what executing it
+/// produced.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommittedTransactionData {
+    pub version: Version,
+    pub txn: Transaction,
+    pub status: TransactionStatus,
+    pub events: Vec<ContractEvent>,
+    pub write_set: WriteSet,
+}
+
+/// How long each stage of the last chunk processed by `execute_and_commit_chunk` took. Stages the
+/// chunk did not get to are left at zero.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ChunkExecutionStages {
+    /// Verifying the chunk against its proofs.
+    pub verify: Duration,
+    /// Running the transactions through the VM and computing the resulting trees.
+    pub execute: Duration,
+    /// Writing the transactions to storage, where write stalls show up.
+    pub save: Duration,
+}
+
 /// A structure that summarizes the result of the execution needed for consensus to agree on.
 /// The execution is responsible for generating the ID of the new state, which is returned in the
 /// result.
@@ -289,6 +316,12 @@ pub struct Executor<V> {
 
     committed_trees: Arc<Mutex<ExecutedTrees>>,
 
+    /// Stage durations of the last chunk executed, updated by the block processor.
+    last_chunk_stages: Arc<Mutex<ChunkExecutionStages>>,
+
+    /// Used to read the state not held in memory by the executed trees.
+    storage_read_client: Arc<dyn StorageRead>,
+
     phantom: PhantomData<V>,
 }
 
@@ -340,6 +373,9 @@ where
             .get_genesis_transaction()
             .expect("failed to load genesis transaction!");
         let cloned_committed_trees = committed_trees.clone();
+        let last_chunk_stages = Arc::new(Mutex::new(ChunkExecutionStages::default()));
+        let cloned_last_chunk_stages = Arc::clone(&last_chunk_stages);
+        let cloned_storage_read_client = Arc::clone(&storage_read_client);
         let (resp_sender, resp_receiver) = oneshot::channel();
         let executor = Executor {
             block_processor_thread: Some(
@@ -351,6 +387,7 @@ where
                             storage_read_client,
                             storage_write_client,
                             cloned_committed_trees,
+                            cloned_last_chunk_stages,
                             synced_trees,
                             committed_timestamp_usecs,
                             vm_config,
@@ -364,6 +401,8 @@ where
             command_sender: Mutex::new(Some(command_sender)),
             phantom: PhantomData,
             committed_trees,
+            last_chunk_stages,
+            storage_read_client: cloned_storage_read_client,
         };
         block_on(resp_receiver).expect("initialization is done");
         executor
@@ -487,12 +526,13 @@ where
     }
 
     /// Executes and commits a chunk of transactions that are already committed by majority of the
-    /// validators.
+    /// validators. Returns the data of the transactions newly committed, which excludes the ones
+    /// of the chunk that had been committed before.
     pub fn execute_and_commit_chunk(
         &self,
         txn_list_with_proof: TransactionListWithProof,
         ledger_info_with_sigs: LedgerInfoWithSignatures,
-    ) -> oneshot::Receiver<Result<()>> {
+    ) -> oneshot::Receiver<Result<Vec<CommittedTransactionData>>> {
         debug!(
             "Received request to execute chunk. Chunk size: {}. Target version: {}.",
             txn_list_with_proof.transactions.len(),
@@ -525,6 +565,25 @@ where
     pub fn committed_trees(&self) -> ExecutedTrees {
         (*self.committed_trees.lock().unwrap()).clone()
     }
+
+    /// How long each stage of the last chunk processed by `execute_and_commit_chunk` took.
+    pub fn last_chunk_stages(&self) -> ChunkExecutionStages {
+        *self.last_chunk_stages.lock().unwrap()
+    }
+
+    /// Reads the state of `address` as of `trees`, which are either the committed trees or the
+    /// ones of a speculatively executed block descending from them.
+    pub fn get_account_state(
+        &self,
+        trees: &ExecutedTrees,
+        address: AccountAddress,
+    ) -> Result<Option<AccountStateBlob>> {
+        trees.get_account_state(
+            address,
+            self.storage_read_client.as_ref(),
+            &self.committed_trees(),
+        )
+    }
 }
 
 impl<V> Drop for Executor<V> {
@@ -597,7 +656,7 @@ enum Command {
     },
     ExecuteAndCommitChunk {
         chunk: Chunk,
-        resp_sender: oneshot::Sender<Result<()>>,
+        resp_sender: oneshot::Sender<Result<Vec<CommittedTransactionData>>>,
     },
 }
 
@@ -657,4 +716,41 @@ impl ExecutedTrees {
     pub fn new_empty() -> ExecutedTrees {
         Self::new(*SPARSE_MERKLE_PLACEHOLDER_HASH, vec![], 0)
     }
+
+    /// Reads the state of `address` as of these trees. Accounts updated since `persisted_trees`,
+    /// the committed trees these ones descend from, are read from the in-memory tree, whose nodes
+    /// are shared with the trees of the parent blocks. Other accounts are read from `reader` at
+    /// the version of `persisted_trees` and verified against their state root.
+    pub fn get_account_state(
+        &self,
+        address: AccountAddress,
+        reader: &dyn StorageRead,
+        persisted_trees: &ExecutedTrees,
+    ) -> Result<Option<AccountStateBlob>> {
+        let address_hash = address.hash();
+        match self.state_tree.get(address_hash) {
+            AccountState::ExistsInScratchPad(blob) => Ok(Some(blob)),
+            AccountState::DoesNotExist => Ok(None),
+            // Only the hash of the blob is in memory in the former case.
+            AccountState::ExistsInDB | AccountState::Unknown => {
+                let version = match persisted_trees.version() {
+                    Some(version) => version,
+                    None => return Ok(None),
+                };
+                let (blob, proof) =
+                    reader.get_account_state_with_proof_by_version(address, version)?;
+                proof
+                    .verify(persisted_trees.state_root(), address_hash, blob.as_ref())
+                    .map_err(|err| {
+                        format_err!(
+                            "Proof is invalid for address {:?} at version {}: {}",
+                            address,
+                            version,
+                            err
+                        )
+                    })?;
+                Ok(blob)
+            }
+        }
+    }
 }
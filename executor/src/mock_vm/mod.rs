@@ -191,8 +191,13 @@ fn seqnum_ap(account: AccountAddress) -> AccessPath {
     AccessPath::new(account, b"seqnum".to_vec())
 }
 
+/// The only account written by the genesis transaction.
+pub fn genesis_account() -> AccountAddress {
+    AccountAddress::new([0xff; ADDRESS_LENGTH])
+}
+
 fn gen_genesis_writeset() -> WriteSet {
-    let address = AccountAddress::new([0xff; ADDRESS_LENGTH]);
+    let address = genesis_account();
     let path = b"hello".to_vec();
     let mut write_set = WriteSetMut::default();
     write_set.push((
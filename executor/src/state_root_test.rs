@@ -0,0 +1,227 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cross-checks the state roots the executor computes in its in-memory sparse Merkle tree against
+//! roots computed independently: the one of the Jellyfish Merkle tree storage persists, and the one
+//! of a fresh Jellyfish Merkle tree the account blobs written by the executed transactions are
+//! replayed into. Random blocks of mints and transfers are executed and committed one after the
+//! other. On a divergence, proptest shrinks the blocks down to a minimal failing sequence, which is
+//! reported together with the block that diverged.
+
+use crate::{
+    executor_test::{gen_address, gen_block_id, gen_ledger_info, TestExecutor},
+    mock_vm::{encode_mint_transaction, encode_transfer_transaction, genesis_account},
+    CommittableBlock,
+};
+use futures::executor::block_on;
+use jellyfish_merkle::{mock_tree_store::MockTreeStore, JellyfishMerkleTree};
+use libra_crypto::{
+    hash::{CryptoHash, PRE_GENESIS_BLOCK_ID, SPARSE_MERKLE_PLACEHOLDER_HASH},
+    HashValue,
+};
+use libra_types::{
+    account_state_blob::AccountStateBlob,
+    transaction::{Transaction, TransactionStatus, Version},
+};
+use proptest::{collection::vec, prelude::*};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use storage_client::StorageRead;
+
+// Number of accounts the transactions are between, few enough for most transfers to involve
+// accounts minted into before.
+const NUM_ACCOUNTS: u64 = 8;
+
+/// A transaction of the MockVM between the accounts `gen_address(0..NUM_ACCOUNTS)`.
+#[derive(Clone, Debug)]
+enum Op {
+    Mint {
+        account: u64,
+        amount: u64,
+    },
+    Transfer {
+        sender: u64,
+        recipient: u64,
+        amount: u64,
+    },
+}
+
+fn arb_op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        (0..NUM_ACCOUNTS, 0..1000u64).prop_map(|(account, amount)| Op::Mint { account, amount }),
+        // no transfer to the sender itself
+        (0..NUM_ACCOUNTS, 1..NUM_ACCOUNTS, 0..1000u64).prop_map(|(sender, offset, amount)| {
+            Op::Transfer {
+                sender,
+                recipient: (sender + offset) % NUM_ACCOUNTS,
+                amount,
+            }
+        }),
+    ]
+}
+
+fn arb_blocks(max_blocks: usize, max_block_size: usize) -> impl Strategy<Value = Vec<Vec<Op>>> {
+    vec(vec(arb_op(), 1..max_block_size), 1..max_blocks)
+}
+
+/// Turns `ops` into transactions, keeping `balances` up to date. Transfers are capped at the
+/// balance of the sender, so that no transaction is discarded and every block commits a version.
+fn to_transactions(ops: &[Op], balances: &mut HashMap<u64, u64>) -> Vec<Transaction> {
+    ops.iter()
+        .map(|op| match *op {
+            Op::Mint { account, amount } => {
+                *balances.entry(account).or_default() += amount;
+                encode_mint_transaction(gen_address(account), amount)
+            }
+            Op::Transfer {
+                sender,
+                recipient,
+                amount,
+            } => {
+                let sender_balance = balances.entry(sender).or_default();
+                let amount = std::cmp::min(amount, *sender_balance);
+                *sender_balance -= amount;
+                *balances.entry(recipient).or_default() += amount;
+                encode_transfer_transaction(gen_address(sender), gen_address(recipient), amount)
+            }
+        })
+        .collect()
+}
+
+/// A Jellyfish Merkle tree built from scratch out of the account blobs written by transactions,
+/// one version per replayed block.
+struct ReplayedTree {
+    store: MockTreeStore,
+    next_version: Version,
+    root_hash: HashValue,
+}
+
+impl ReplayedTree {
+    fn new() -> Self {
+        Self {
+            store: MockTreeStore::default(),
+            next_version: 0,
+            root_hash: *SPARSE_MERKLE_PLACEHOLDER_HASH,
+        }
+    }
+
+    /// Puts `blobs` into a new version of the tree and returns its root hash.
+    fn replay(&mut self, blobs: BTreeMap<HashValue, AccountStateBlob>) -> HashValue {
+        if !blobs.is_empty() {
+            let (root_hash, batch) = JellyfishMerkleTree::new(&self.store)
+                .put_blob_set(blobs.into_iter().collect(), self.next_version)
+                .unwrap();
+            self.store.write_tree_update_batch(batch).unwrap();
+            self.next_version += 1;
+            self.root_hash = root_hash;
+        }
+        self.root_hash
+    }
+}
+
+/// Executes and commits `blocks` one after the other through storage, checking after each of
+/// them that the state root of the executor matches the ones of storage and of the replayed tree.
+fn cross_check_state_roots(blocks: &[Vec<Op>]) -> std::result::Result<(), TestCaseError> {
+    let executor = TestExecutor::new();
+    let storage = executor.storage_read_client();
+
+    // the genesis transaction was committed by the executor on startup
+    let (genesis_blob, _proof) = storage
+        .get_account_state_with_proof_by_version(genesis_account(), 0)
+        .unwrap();
+    let mut replayed_tree = ReplayedTree::new();
+    let mut genesis_blobs = BTreeMap::new();
+    genesis_blobs.insert(
+        genesis_account().hash(),
+        genesis_blob.expect("The genesis account must exist."),
+    );
+    prop_assert_eq!(
+        replayed_tree.replay(genesis_blobs),
+        executor.committed_trees().state_root(),
+        "the genesis transaction writes other accounts than genesis_account"
+    );
+
+    let mut balances = HashMap::new();
+    let mut trees = executor.committed_trees();
+    let mut parent_id = *PRE_GENESIS_BLOCK_ID;
+    for (i, block) in blocks.iter().enumerate() {
+        let txns = to_transactions(block, &mut balances);
+        let id = gen_block_id(i as u64 + 1);
+        let output = block_on(executor.execute_block(txns.clone(), trees, parent_id, id))
+            .unwrap()
+            .unwrap();
+        let executor_root_hash = output.executed_trees().state_root();
+
+        // the later blobs of an account replace the earlier ones within the block
+        let mut blobs = BTreeMap::new();
+        for txn_data in output.transaction_data() {
+            if let TransactionStatus::Keep(_) = txn_data.status() {
+                blobs.extend(
+                    txn_data
+                        .account_blobs()
+                        .iter()
+                        .map(|(address, blob)| (address.hash(), blob.clone())),
+                );
+            }
+        }
+        let replayed_root_hash = replayed_tree.replay(blobs);
+
+        trees = output.executed_trees().clone();
+        let ledger_info = gen_ledger_info(
+            output.version().expect("Genesis was committed."),
+            output.accu_root(),
+            id,
+            i as u64 + 1,
+        );
+        block_on(executor.commit_blocks(
+            vec![CommittableBlock::new(txns, Arc::new(output))],
+            ledger_info,
+        ))
+        .unwrap()
+        .unwrap();
+        let storage_root_hash = storage
+            .get_startup_info()
+            .unwrap()
+            .expect("Storage was bootstrapped.")
+            .committed_tree_state
+            .account_state_root_hash;
+
+        prop_assert_eq!(
+            executor_root_hash,
+            replayed_root_hash,
+            "executor and replayed tree diverged at block {}: {:?}",
+            i,
+            block
+        );
+        prop_assert_eq!(
+            executor_root_hash,
+            storage_root_hash,
+            "executor and storage diverged at block {}: {:?}",
+            i,
+            block
+        );
+        parent_id = id;
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(3))]
+
+    #[test]
+    fn test_state_roots_agree_smoke(blocks in arb_blocks(5, 10)) {
+        cross_check_state_roots(&blocks)?;
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(100))]
+
+    #[test]
+    #[ignore]
+    fn test_state_roots_agree(blocks in arb_blocks(30, 50)) {
+        cross_check_state_roots(&blocks)?;
+    }
+}
@@ -5,6 +5,7 @@ use crate::mapping::SourceMapping;
 use crate::source_map::{FunctionSourceMap, SourceName};
 use bytecode_verifier::control_flow_graph::{ControlFlowGraph, VMControlFlowGraph};
 use failure::prelude::*;
+use libra_crypto::ShortStr;
 use libra_types::identifier::IdentStr;
 use vm::access::ModuleAccess;
 use vm::file_format::{
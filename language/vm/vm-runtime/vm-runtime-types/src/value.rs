@@ -7,6 +7,7 @@ use crate::{
         def::NativeStructTag, vector::NativeVector, NativeStructType, NativeStructValue,
     },
 };
+use libra_crypto::ShortStr;
 use libra_types::{
     access_path::AccessPath,
     account_address::{AccountAddress, ADDRESS_LENGTH},
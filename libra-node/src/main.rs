@@ -50,7 +50,10 @@ fn main() {
     let (mut config, _logger) =
         setup_executable(args.config.as_ref().map(PathBuf::as_path), args.no_logging);
 
-    let _node_handle = libra_node::main_node::setup_environment(&mut config);
+    let node_handle = libra_node::main_node::setup_environment(&mut config);
+    if let Some(config_path) = args.config {
+        node_handle.watch_config(config_path, &config);
+    }
 
     let term = Arc::new(AtomicBool::new(false));
     register_signals(Arc::clone(&term));
@@ -3,8 +3,13 @@
 
 use admission_control_service::runtime::AdmissionControlRuntime;
 use consensus::consensus_provider::{make_consensus_provider, ConsensusProvider};
-use debug_interface::{node_debug_service::NodeDebugService, proto::create_node_debug_interface};
+use debug_interface::{
+    node_debug_service::{DbStatsProvider, NodeDebugService, StateAuditor},
+    proto::{create_node_debug_interface, VersionAudit},
+};
 use executor::Executor;
+use failure::prelude::*;
+use futures::executor::block_on;
 use grpc_helpers::ServerHandle;
 use grpcio::EnvBuilder;
 use libra_config::config::{NetworkConfig, NodeConfig, RoleType};
@@ -27,29 +32,41 @@ use network::{
     },
     NetworkPublicKeys, ProtocolId,
 };
-use state_synchronizer::StateSynchronizer;
+use state_synchronizer::{StateSyncClient, StateSynchronizer, StateSynchronizerBuilder};
 use std::{
     convert::{TryFrom, TryInto},
+    path::PathBuf,
     str::FromStr,
     sync::Arc,
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 use storage_client::{StorageRead, StorageReadServiceClient, StorageWriteServiceClient};
-use storage_service::start_storage_service;
+use storage_service::start_storage_service_with_db_stats;
 use tokio::runtime::{Builder, Runtime};
 use vm_runtime::MoveVM;
 
 pub struct LibraHandle {
     _ac: AdmissionControlRuntime,
     _mempool: Option<MempoolRuntime>,
-    _state_synchronizer: StateSynchronizer,
+    state_synchronizer: StateSynchronizer,
     _network_runtimes: Vec<Runtime>,
     consensus: Option<Box<dyn ConsensusProvider>>,
     _storage: ServerHandle,
     _debug: ServerHandle,
 }
 
+impl LibraHandle {
+    /// Reloads the state sync upstream peers whenever the node config at `config_path` changes,
+    /// if enabled by `upstream_peers_reload_interval_ms`.
+    pub fn watch_config(&self, config_path: PathBuf, config: &NodeConfig) {
+        if let Some(interval_ms) = config.state_sync.upstream_peers_reload_interval_ms {
+            self.state_synchronizer
+                .watch_upstream_peers(config_path, Duration::from_millis(interval_ms));
+        }
+    }
+}
+
 impl Drop for LibraHandle {
     fn drop(&mut self) {
         if let Some(consensus) = &mut self.consensus {
@@ -79,10 +96,42 @@ fn setup_executor(config: &NodeConfig) -> Arc<Executor<MoveVM>> {
     ))
 }
 
-fn setup_debug_interface(config: &NodeConfig) -> ::grpcio::Server {
+/// Serves the state audits of the debug interface through state sync.
+struct StateSyncAuditor {
+    client: Arc<StateSyncClient>,
+}
+
+impl StateAuditor for StateSyncAuditor {
+    fn audit_state(&self, peer_id: &[u8], versions: Vec<u64>) -> Result<Vec<VersionAudit>> {
+        let peer_id = PeerId::try_from(peer_id.to_vec())?;
+        let audits = block_on(self.client.audit_state(peer_id, versions))?;
+        Ok(audits
+            .into_iter()
+            .map(|audit| VersionAudit {
+                version: audit.version,
+                local_hash: audit.local_hash.map_or_else(Vec::new, |hash| hash.to_vec()),
+                peer_hash: audit.peer_hash.map_or_else(Vec::new, |hash| hash.to_vec()),
+                is_mismatch: audit.is_mismatch(),
+            })
+            .collect())
+    }
+}
+
+fn setup_debug_interface(
+    config: &NodeConfig,
+    state_sync_client: Arc<StateSyncClient>,
+    db_stats_provider: Arc<dyn DbStatsProvider>,
+) -> ::grpcio::Server {
     let env = Arc::new(EnvBuilder::new().name_prefix("grpc-debug-").build());
     // Start Debug interface
-    let debug_service = create_node_debug_interface(NodeDebugService::new());
+    let state_auditor = Arc::new(StateSyncAuditor {
+        client: state_sync_client,
+    });
+    let debug_service = create_node_debug_interface(
+        NodeDebugService::new()
+            .with_state_auditor(state_auditor)
+            .with_db_stats_provider(db_stats_provider),
+    );
     ::grpcio::ServerBuilder::new(env)
         .register_service(debug_service)
         .bind(
@@ -184,7 +233,7 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         .expect("Building rayon global thread pool should work.");
 
     let mut instant = Instant::now();
-    let storage = start_storage_service(&node_config);
+    let (storage, db_stats_provider) = start_storage_service_with_db_stats(&node_config);
     debug!(
         "Storage service started in {} ms",
         instant.elapsed().as_millis()
@@ -240,8 +289,6 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         }
     }
 
-    let debug_if = ServerHandle::setup(setup_debug_interface(&node_config));
-
     let metrics_port = node_config.debug_interface.metrics_server_port;
     let metric_host = node_config.debug_interface.address.clone();
     thread::spawn(move || metric_server::start_server(metric_host, metrics_port, false));
@@ -251,11 +298,17 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         metric_server::start_server(public_metric_host, public_metrics_port, true)
     });
 
-    let state_synchronizer = StateSynchronizer::bootstrap(
-        state_sync_network_handles,
-        Arc::clone(&executor),
+    let state_synchronizer =
+        StateSynchronizerBuilder::for_node(Arc::clone(&executor), &node_config)
+            .expect("Failed to read storage for state synchronizer")
+            .with_networks(state_sync_network_handles)
+            .build()
+            .expect("Failed to build state synchronizer");
+    let debug_if = ServerHandle::setup(setup_debug_interface(
         &node_config,
-    );
+        state_synchronizer.create_client(),
+        db_stats_provider,
+    ));
     let admission_control = AdmissionControlRuntime::bootstrap(
         &node_config,
         ac_network_sender.unwrap(),
@@ -314,7 +367,7 @@ pub fn setup_environment(node_config: &mut NodeConfig) -> LibraHandle {
         _network_runtimes: network_runtimes,
         _ac: admission_control,
         _mempool: mempool,
-        _state_synchronizer: state_synchronizer,
+        state_synchronizer,
         consensus,
         _storage: storage,
         _debug: debug_if,
@@ -6,7 +6,7 @@
 pub mod proto;
 use crate::proto::mempool_status::MempoolAddTransactionStatusCode;
 use failure::prelude::*;
-use std::convert::TryFrom;
+use std::{convert::TryFrom, ops::Range};
 
 /// Status of transaction insertion operation
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -15,12 +15,25 @@ pub struct MempoolAddTransactionStatus {
     pub code: MempoolAddTransactionStatusCode,
     /// Message to give more details about the transaction insertion operation
     pub message: String,
+    /// Sequence numbers the sender is missing in front of its parked transactions, if they have
+    /// been parked long enough to hint the gap
+    pub missing_sequence_numbers: Option<Range<u64>>,
 }
 
 impl MempoolAddTransactionStatus {
     /// Create a new MempoolAddTransactionStatus
     pub fn new(code: MempoolAddTransactionStatusCode, message: String) -> Self {
-        Self { code, message }
+        Self {
+            code,
+            message,
+            missing_sequence_numbers: None,
+        }
+    }
+
+    /// Attach a hint of the sequence numbers missing in front of the parked transactions
+    pub fn with_missing_sequence_numbers(mut self, missing: Option<Range<u64>>) -> Self {
+        self.missing_sequence_numbers = missing;
+        self
     }
 }
 
@@ -33,10 +46,12 @@ impl TryFrom<crate::proto::mempool_status::MempoolAddTransactionStatus>
     type Error = Error;
 
     fn try_from(proto: crate::proto::mempool_status::MempoolAddTransactionStatus) -> Result<Self> {
-        Ok(MempoolAddTransactionStatus::new(
-            proto.code(),
-            proto.message,
-        ))
+        let code = proto.code();
+        let missing_sequence_numbers = proto
+            .missing_sequence_numbers
+            .map(|range| range.start..range.end);
+        Ok(MempoolAddTransactionStatus::new(code, proto.message)
+            .with_missing_sequence_numbers(missing_sequence_numbers))
     }
 }
 
@@ -47,6 +62,13 @@ impl From<MempoolAddTransactionStatus>
         let mut mempool_add_transaction_status = Self::default();
         mempool_add_transaction_status.message = status.message;
         mempool_add_transaction_status.set_code(status.code);
+        mempool_add_transaction_status.missing_sequence_numbers =
+            status.missing_sequence_numbers.map(|range| {
+                let mut proto_range = crate::proto::mempool_status::SequenceNumberRange::default();
+                proto_range.start = range.start;
+                proto_range.end = range.end;
+                proto_range
+            });
         mempool_add_transaction_status
     }
 }
@@ -16,7 +16,7 @@ pub type AccountTransactions = BTreeMap<u64, MempoolTransaction>;
 
 /// PriorityIndex represents main Priority Queue in Mempool
 /// It's used to form transaction block for Consensus
-/// Transactions are ordered by gas price. Second level ordering is done by expiration time
+/// Transactions are ordered by gas price. Second level ordering is done by arrival in Mempool
 ///
 /// We don't store full content of transaction in index
 /// Instead we use `OrderedQueueKey` - logical reference to transaction in main store
@@ -50,7 +50,7 @@ impl PriorityIndex {
     fn make_key(&self, txn: &MempoolTransaction) -> OrderedQueueKey {
         OrderedQueueKey {
             gas_price: txn.get_gas_price(),
-            expiration_time: txn.expiration_time,
+            arrival_id: txn.arrival_id,
             address: txn.get_sender(),
             sequence_number: txn.get_sequence_number(),
         }
@@ -69,7 +69,7 @@ impl PriorityIndex {
 #[derive(Eq, PartialEq, Clone, Debug, Hash)]
 pub struct OrderedQueueKey {
     pub gas_price: u64,
-    pub expiration_time: Duration,
+    pub arrival_id: u64,
     pub address: AccountAddress,
     pub sequence_number: u64,
 }
@@ -86,7 +86,7 @@ impl Ord for OrderedQueueKey {
             Ordering::Equal => {}
             ordering => return ordering,
         }
-        match self.expiration_time.cmp(&other.expiration_time).reverse() {
+        match self.arrival_id.cmp(&other.arrival_id).reverse() {
             Ordering::Equal => {}
             ordering => return ordering,
         }
@@ -98,6 +98,45 @@ impl Ord for OrderedQueueKey {
     }
 }
 
+/// ArrivalIndex orders the same transactions as PriorityIndex by their arrival in Mempool only
+/// It's used to form transaction block for Consensus when gas price is ignored
+pub struct ArrivalIndex {
+    data: BTreeSet<(u64, AccountAddress, u64)>,
+}
+
+impl ArrivalIndex {
+    pub(crate) fn new() -> Self {
+        Self {
+            data: BTreeSet::new(),
+        }
+    }
+
+    /// add transaction to index
+    pub(crate) fn insert(&mut self, txn: &MempoolTransaction) {
+        self.data.insert(self.make_key(txn));
+    }
+
+    /// remove transaction from index
+    pub(crate) fn remove(&mut self, txn: &MempoolTransaction) {
+        self.data.remove(&self.make_key(txn));
+    }
+
+    fn make_key(&self, txn: &MempoolTransaction) -> (u64, AccountAddress, u64) {
+        (txn.arrival_id, txn.get_sender(), txn.get_sequence_number())
+    }
+
+    /// returns iterator over transactions, oldest first
+    pub(crate) fn iter(&self) -> impl Iterator<Item = TxnPointer> + '_ {
+        self.data
+            .iter()
+            .map(|&(_, address, sequence_number)| (address, sequence_number))
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
 /// TTLIndex is used to perform garbage collection of old transactions in Mempool
 /// Periodically separate GC-like job queries this index to find out transactions that have to be
 /// removed Index is represented as `BTreeSet<TTLOrderingKey>`
@@ -14,14 +14,14 @@ use crate::{
     OP_COUNTERS,
 };
 use chrono::Utc;
-use libra_config::config::NodeConfig;
+use libra_config::config::{BlockOrdering, MempoolConfig, NodeConfig};
 use libra_logger::prelude::*;
 use libra_mempool_shared_proto::{
     proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
 };
 use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
 use lru_cache::LruCache;
-use std::{cmp::max, collections::HashSet, convert::TryFrom};
+use std::{cmp::max, collections::HashSet, convert::TryFrom, ops::Range};
 use ttl_cache::TtlCache;
 
 pub struct Mempool {
@@ -35,6 +35,8 @@ pub struct Mempool {
     // by consensus
     pub(crate) metrics_cache: TtlCache<(AccountAddress, u64), i64>,
     pub system_transaction_timeout: Duration,
+    // order in which transactions are pulled into blocks
+    block_ordering: BlockOrdering,
 }
 
 impl Mempool {
@@ -46,6 +48,19 @@ impl Mempool {
             system_transaction_timeout: Duration::from_secs(
                 config.mempool.system_transaction_timeout_secs,
             ),
+            block_ordering: config.mempool.block_ordering,
+        }
+    }
+
+    /// Applies the parts of `config` that can change while Mempool is running
+    /// Takes effect on the next call to `get_block`
+    pub(crate) fn update_config(&mut self, config: &MempoolConfig) {
+        if self.block_ordering != config.block_ordering {
+            info!(
+                "[Mempool] Block ordering changed from {:?} to {:?}",
+                self.block_ordering, config.block_ordering
+            );
+            self.block_ordering = config.block_ordering;
         }
     }
 
@@ -132,6 +147,10 @@ impl Mempool {
         self.sequence_number_cache
             .insert(txn.sender(), sequence_number);
 
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("init timestamp failure");
+
         // don't accept old transactions (e.g. seq is less than account's current seq_number)
         if txn.sequence_number() < sequence_number {
             return MempoolAddTransactionStatus::new(
@@ -141,13 +160,14 @@ impl Mempool {
                     txn.sequence_number(),
                     sequence_number,
                 ),
+            )
+            .with_missing_sequence_numbers(
+                self.transactions
+                    .missing_sequence_numbers(&txn.sender(), now),
             );
         }
 
-        let expiration_time = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("init timestamp failure")
-            + self.system_transaction_timeout;
+        let expiration_time = now + self.system_transaction_timeout;
         if timeline_state != TimelineState::NonQualified {
             self.metrics_cache.insert(
                 (txn.sender(), txn.sequence_number()),
@@ -181,18 +201,17 @@ impl Mempool {
         // `skipped` DS and rechecked once it's ancestor becomes available
         let mut skipped = HashSet::new();
 
-        // iterate over the queue of transactions based on gas price
-        'main: for txn in self.transactions.iter_queue() {
-            if seen.contains(&TxnPointer::from(txn)) {
+        // iterate over the queue of transactions based on gas price or arrival
+        'main: for ptr in self.transactions.iter_queue(self.block_ordering) {
+            if seen.contains(&ptr) {
                 continue;
             }
-            let mut seq = txn.sequence_number;
-            let account_sequence_number = self.sequence_number_cache.get_mut(&txn.address);
-            let seen_previous = seq > 0 && seen.contains(&(txn.address, seq - 1));
+            let (address, mut seq) = ptr;
+            let account_sequence_number = self.sequence_number_cache.get_mut(&address);
+            let seen_previous = seq > 0 && seen.contains(&(address, seq - 1));
             // include transaction if it's "next" for given account or
             // we've already sent its ancestor to Consensus
             if seen_previous || account_sequence_number == Some(&mut seq) {
-                let ptr = TxnPointer::from(txn);
                 seen.insert(ptr);
                 result.push(ptr);
                 if (result.len() as u64) == batch_size {
@@ -201,17 +220,17 @@ impl Mempool {
 
                 // check if we can now include some transactions
                 // that were skipped before for given account
-                let mut skipped_txn = (txn.address, seq + 1);
+                let mut skipped_txn = (address, seq + 1);
                 while skipped.contains(&skipped_txn) {
                     seen.insert(skipped_txn);
                     result.push(skipped_txn);
                     if (result.len() as u64) == batch_size {
                         break 'main;
                     }
-                    skipped_txn = (txn.address, skipped_txn.1 + 1);
+                    skipped_txn = (address, skipped_txn.1 + 1);
                 }
             } else {
-                skipped.insert(TxnPointer::from(txn));
+                skipped.insert(ptr);
             }
         }
         // convert transaction pointers to real values
@@ -239,6 +258,51 @@ impl Mempool {
         self.transactions.gc_by_expiration_time(block_time);
     }
 
+    /// Garbage collection of transactions waiting behind a sequence number gap for too long
+    pub(crate) fn gc_by_sequence_gap(&mut self, now: Duration) {
+        self.transactions.gc_by_sequence_gap(now);
+    }
+
+    /// Sequence numbers missing in front of the parked transactions of `address`, hinted once
+    /// they have waited long enough at `now`
+    pub(crate) fn missing_sequence_numbers(
+        &self,
+        address: &AccountAddress,
+        now: Duration,
+    ) -> Option<Range<u64>> {
+        self.transactions.missing_sequence_numbers(address, now)
+    }
+
+    /// Number of transactions of `address` in Mempool
+    pub(crate) fn num_transactions(&self, address: &AccountAddress) -> usize {
+        self.transactions.num_transactions(address)
+    }
+
+    /// Whether Mempool holds the transaction of `address` at `sequence_number`
+    pub(crate) fn contains(&self, address: &AccountAddress, sequence_number: u64) -> bool {
+        self.transactions.get(address, sequence_number).is_some()
+    }
+
+    /// Number of transactions in Mempool
+    pub(crate) fn size(&self) -> usize {
+        self.transactions.size()
+    }
+
+    /// Total size of the transactions in Mempool in bytes
+    pub(crate) fn size_bytes(&self) -> usize {
+        self.transactions.size_bytes()
+    }
+
+    /// Maximum number of transactions in Mempool
+    pub(crate) fn capacity(&self) -> usize {
+        self.transactions.capacity()
+    }
+
+    /// Maximum number of transactions of an account in Mempool
+    pub(crate) fn capacity_per_user(&self) -> usize {
+        self.transactions.capacity_per_user()
+    }
+
     /// Read `count` transactions from timeline since `timeline_id`
     /// Returns block of transactions and new last_timeline_id
     pub(crate) fn read_timeline(
@@ -11,6 +11,10 @@ pub struct MempoolTransaction {
     pub expiration_time: Duration,
     pub gas_amount: u64,
     pub timeline_state: TimelineState,
+    // position of transaction in the order transactions entered Mempool. Assigned on insertion
+    pub arrival_id: u64,
+    // size of the raw transaction in bytes
+    pub size_bytes: usize,
 }
 
 impl MempoolTransaction {
@@ -20,11 +24,14 @@ impl MempoolTransaction {
         gas_amount: u64,
         timeline_state: TimelineState,
     ) -> Self {
+        let size_bytes = txn.raw_txn_bytes_len();
         Self {
             txn,
+            size_bytes,
             gas_amount,
             expiration_time,
             timeline_state,
+            arrival_id: 0,
         }
     }
     pub(crate) fn get_sequence_number(&self) -> u64 {
@@ -4,15 +4,15 @@
 use crate::{
     core_mempool::{
         index::{
-            AccountTransactions, ParkingLotIndex, PriorityIndex, PriorityQueueIter, TTLIndex,
-            TimelineIndex,
+            AccountTransactions, ArrivalIndex, ParkingLotIndex, PriorityIndex, TTLIndex,
+            TimelineIndex, TxnPointer,
         },
         transaction::{MempoolTransaction, TimelineState},
     },
     OP_COUNTERS,
 };
 use failure::prelude::*;
-use libra_config::config::MempoolConfig;
+use libra_config::config::{BlockOrdering, MempoolConfig};
 use libra_logger::prelude::*;
 use libra_mempool_shared_proto::{
     proto::mempool_status::MempoolAddTransactionStatusCode, MempoolAddTransactionStatus,
@@ -20,8 +20,9 @@ use libra_mempool_shared_proto::{
 use libra_types::{account_address::AccountAddress, transaction::SignedTransaction};
 use mirai_annotations::*;
 use std::{
+    cmp::min,
     collections::HashMap,
-    ops::Bound,
+    ops::{Bound, Range},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -32,6 +33,8 @@ pub struct TransactionStore {
 
     // indexes
     priority_index: PriorityIndex,
+    // same transactions as `priority_index`, ordered by arrival only
+    arrival_index: ArrivalIndex,
     // TTLIndex based on client-specified expiration time
     expiration_time_index: TTLIndex,
     // TTLIndex based on system expiration time
@@ -41,10 +44,28 @@ pub struct TransactionStore {
     timeline_index: TimelineIndex,
     // keeps track of "non-ready" txns (transactions that can't be included in next block)
     parking_lot_index: ParkingLotIndex,
+    // for each account with txns waiting behind a sequence number gap, where the gap starts
+    // and since when they wait
+    sequence_gaps: HashMap<AccountAddress, SequenceGap>,
+    // arrival id of the next inserted transaction
+    next_arrival_id: u64,
+    // total size of the transactions in bytes
+    size_bytes: usize,
 
     // configuration
     capacity: usize,
     capacity_per_user: usize,
+    sequence_gap_hint_timeout: Duration,
+    sequence_gap_gc_timeout: Duration,
+}
+
+struct SequenceGap {
+    // first missing sequence number
+    start: u64,
+    // system time the oldest parked transaction started waiting behind the gap
+    since: Duration,
+    // whether the wait has already been counted as a timeout
+    timed_out: bool,
 }
 
 impl TransactionStore {
@@ -59,12 +80,18 @@ impl TransactionStore {
                 t.txn.expiration_time()
             })),
             priority_index: PriorityIndex::new(),
+            arrival_index: ArrivalIndex::new(),
             timeline_index: TimelineIndex::new(),
             parking_lot_index: ParkingLotIndex::new(),
+            sequence_gaps: HashMap::new(),
+            next_arrival_id: 0,
+            size_bytes: 0,
 
             // configuration
             capacity: config.capacity,
             capacity_per_user: config.capacity_per_user,
+            sequence_gap_hint_timeout: Duration::from_secs(config.sequence_gap_hint_timeout_secs),
+            sequence_gap_gc_timeout: Duration::from_secs(config.sequence_gap_gc_timeout_secs),
         }
     }
 
@@ -88,7 +115,7 @@ impl TransactionStore {
     /// performs validation checks and updates indexes
     pub(crate) fn insert(
         &mut self,
-        txn: MempoolTransaction,
+        mut txn: MempoolTransaction,
         current_sequence_number: u64,
     ) -> MempoolAddTransactionStatus {
         if self.handle_gas_price_update(&txn).is_err() {
@@ -132,8 +159,11 @@ impl TransactionStore {
             }
 
             // insert into storage and other indexes
+            txn.arrival_id = self.next_arrival_id;
+            self.next_arrival_id += 1;
             self.system_ttl_index.insert(&txn);
             self.expiration_time_index.insert(&txn);
+            self.size_bytes += txn.size_bytes;
             txns.insert(sequence_number, txn);
             self.track_indices();
         }
@@ -145,6 +175,7 @@ impl TransactionStore {
         OP_COUNTERS.set("txn.system_ttl_index", self.system_ttl_index.size());
         OP_COUNTERS.set("txn.parking_lot_index", self.parking_lot_index.size());
         OP_COUNTERS.set("txn.priority_index", self.priority_index.size());
+        OP_COUNTERS.set("txn.arrival_index", self.arrival_index.size());
     }
 
     /// Check if mempool can handle new insertion requests
@@ -152,6 +183,24 @@ impl TransactionStore {
         self.system_ttl_index.size() < self.capacity || self.parking_lot_index.size() > 0
     }
 
+    /// Number of transactions in Mempool
+    pub(crate) fn size(&self) -> usize {
+        self.system_ttl_index.size()
+    }
+
+    /// Total size of the transactions in Mempool in bytes
+    pub(crate) fn size_bytes(&self) -> usize {
+        self.size_bytes
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn capacity_per_user(&self) -> usize {
+        self.capacity_per_user
+    }
+
     /// checks if Mempool is full
     /// If it's full, tries to free some space by evicting transactions from ParkingLot
     fn check_if_full(&mut self) -> bool {
@@ -196,7 +245,7 @@ impl TransactionStore {
 
     /// fixes following invariants:
     /// all transactions of given account that are sequential to current sequence number
-    /// supposed to be included in both PriorityIndex (ordering for Consensus) and
+    /// supposed to be included in PriorityIndex and ArrivalIndex (ordering for Consensus) and
     /// TimelineIndex (txns for SharedMempool)
     /// Other txns are considered to be "non-ready" and should be added to ParkingLotIndex
     fn process_ready_transactions(
@@ -208,6 +257,7 @@ impl TransactionStore {
             let mut sequence_number = current_sequence_number;
             while let Some(txn) = txns.get_mut(&sequence_number) {
                 self.priority_index.insert(txn);
+                self.arrival_index.insert(txn);
 
                 if txn.timeline_state == TimelineState::NotReady {
                     self.timeline_index.insert(txn);
@@ -228,7 +278,54 @@ impl TransactionStore {
             debug!("[Mempool] txns for account {:?}. Current sequence_number: {}, length: {}, parking lot: {}",
                 address, current_sequence_number, txns.len(), parking_lot_txns,
             );
+            self.track_sequence_gap(address, sequence_number);
+        }
+    }
+
+    /// records that txns of `address` after `gap_start` wait behind a gap, keeping the time the
+    /// wait started if they already did. Forgets the gap if nothing waits behind it
+    fn track_sequence_gap(&mut self, address: &AccountAddress, gap_start: u64) {
+        let is_waiting = self.transactions.get(address).map_or(false, |txns| {
+            txns.range((Bound::Excluded(gap_start), Bound::Unbounded))
+                .next()
+                .is_some()
+        });
+        if is_waiting {
+            self.sequence_gaps
+                .entry(*address)
+                .or_insert_with(|| SequenceGap {
+                    start: gap_start,
+                    since: system_time_now(),
+                    timed_out: false,
+                })
+                .start = gap_start;
+        } else {
+            self.sequence_gaps.remove(address);
+        }
+    }
+
+    /// returns the sequence numbers missing in front of the parked txns of `address`, once they
+    /// have waited behind the gap for longer than the hint timeout at `now`
+    pub(crate) fn missing_sequence_numbers(
+        &self,
+        address: &AccountAddress,
+        now: Duration,
+    ) -> Option<Range<u64>> {
+        let gap = self.sequence_gaps.get(address)?;
+        if now.checked_sub(gap.since).unwrap_or_default() < self.sequence_gap_hint_timeout {
+            return None;
         }
+        let (end, _) = self
+            .transactions
+            .get(address)?
+            .range((Bound::Excluded(gap.start), Bound::Unbounded))
+            .next()?;
+        Some(gap.start..*end)
+    }
+
+    /// returns number of transactions of given account in Mempool
+    pub(crate) fn num_transactions(&self, address: &AccountAddress) -> usize {
+        self.transactions.get(address).map_or(0, |txns| txns.len())
     }
 
     fn clean_committed_transactions(&mut self, address: &AccountAddress, sequence_number: u64) {
@@ -261,6 +358,7 @@ impl TransactionStore {
     }
 
     pub(crate) fn reject_transaction(&mut self, account: &AccountAddress, _sequence_number: u64) {
+        self.sequence_gaps.remove(&account);
         if let Some(txns) = self.transactions.remove(&account) {
             for transaction in txns.values() {
                 self.index_remove(&transaction);
@@ -273,8 +371,10 @@ impl TransactionStore {
         self.system_ttl_index.remove(&txn);
         self.expiration_time_index.remove(&txn);
         self.priority_index.remove(&txn);
+        self.arrival_index.remove(&txn);
         self.timeline_index.remove(&txn);
         self.parking_lot_index.remove(&txn);
+        self.size_bytes -= txn.size_bytes;
         self.track_indices();
     }
 
@@ -316,11 +416,34 @@ impl TransactionStore {
 
     /// GC old transactions
     pub(crate) fn gc_by_system_ttl(&mut self) {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("init timestamp failure");
+        self.gc(system_time_now(), true);
+    }
+
+    /// GC transactions that have been waiting behind a sequence number gap for longer than the
+    /// gap GC timeout at `now`. Waits longer than the hint timeout are counted once
+    pub(crate) fn gc_by_sequence_gap(&mut self, now: Duration) {
+        let mut expired = vec![];
+        for (address, gap) in self.sequence_gaps.iter_mut() {
+            let waited = now.checked_sub(gap.since).unwrap_or_default();
+            if waited >= self.sequence_gap_gc_timeout {
+                expired.push((*address, gap.start));
+            } else if waited >= self.sequence_gap_hint_timeout && !gap.timed_out {
+                gap.timed_out = true;
+                OP_COUNTERS.inc("sequence_gap.timeout");
+            }
+        }
 
-        self.gc(now, true);
+        for (address, gap_start) in expired {
+            self.sequence_gaps.remove(&address);
+            if let Some(txns) = self.transactions.get_mut(&address) {
+                let parked = txns.split_off(&gap_start);
+                OP_COUNTERS.inc_by("gc.sequence_gap", parked.len());
+                for txn in parked.values() {
+                    self.index_remove(txn);
+                }
+            }
+        }
+        self.track_indices();
     }
 
     /// GC old transactions based on client-specified expiration time
@@ -342,6 +465,7 @@ impl TransactionStore {
                 for (_, t) in txns.range((Bound::Excluded(key.sequence_number), Bound::Unbounded)) {
                     self.parking_lot_index.insert(&t);
                     self.priority_index.remove(&t);
+                    self.arrival_index.remove(&t);
                     self.timeline_index.remove(&t);
                 }
                 if let Some(txn) = txns.remove(&key.sequence_number) {
@@ -350,12 +474,33 @@ impl TransactionStore {
                     OP_COUNTERS.inc(&format!("{}.{}", index_name, status));
                     self.index_remove(&txn);
                 }
+                // following transactions now wait behind the gap left by the removed one
+                let gap_start = self
+                    .sequence_gaps
+                    .get(&key.address)
+                    .map_or(key.sequence_number, |gap| {
+                        min(gap.start, key.sequence_number)
+                    });
+                self.track_sequence_gap(&key.address, gap_start);
             }
         }
         self.track_indices();
     }
 
-    pub(crate) fn iter_queue(&self) -> PriorityQueueIter {
-        self.priority_index.iter()
+    /// returns iterator over transactions that can be included in next block, in given order
+    pub(crate) fn iter_queue(
+        &self,
+        ordering: BlockOrdering,
+    ) -> Box<dyn Iterator<Item = TxnPointer> + '_> {
+        match ordering {
+            BlockOrdering::GasPrice => Box::new(self.priority_index.iter().map(TxnPointer::from)),
+            BlockOrdering::Timeline => Box::new(self.arrival_index.iter()),
+        }
     }
 }
+
+fn system_time_now() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("init timestamp failure")
+}
@@ -4,14 +4,17 @@
 use crate::core_mempool::{
     unit_tests::common::{
         add_signed_txn, add_txn, add_txns_to_mempool, exist_in_metrics_cache, setup_mempool,
-        TestTransaction,
+        ConsensusMock, TestTransaction,
     },
     CoreMempool, TimelineState,
 };
-use libra_config::config::NodeConfigHelpers;
+use libra_config::config::{BlockOrdering, NodeConfigHelpers};
 use libra_mempool_shared_proto::proto::mempool_status::MempoolAddTransactionStatusCode;
 use libra_types::transaction::SignedTransaction;
-use std::{collections::HashSet, time::Duration};
+use std::{
+    collections::HashSet,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[test]
 fn test_transaction_ordering() {
@@ -373,3 +376,139 @@ fn test_clean_stuck_transactions() {
     assert_eq!(block.len(), 1);
     assert_eq!(block[0].sequence_number(), 10);
 }
+
+#[test]
+fn test_sequence_gap_hint_and_gc() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.sequence_gap_hint_timeout_secs = 60;
+    config.mempool.sequence_gap_gc_timeout_secs = 600;
+    let mut pool = CoreMempool::new(&config);
+    let address = TestTransaction::get_address(1);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+    // transaction 1 is lost, so 2 and 3 are parked behind it
+    for seq in &[0, 2, 3] {
+        add_txn(&mut pool, TestTransaction::new(1, *seq, 1)).unwrap();
+    }
+    assert_eq!(pool.missing_sequence_numbers(&address, now), None);
+
+    // the gap is hinted once the parked transactions waited past the hint timeout
+    let later = now + Duration::from_secs(61);
+    assert_eq!(pool.missing_sequence_numbers(&address, later), Some(1..2));
+    pool.gc_by_sequence_gap(later);
+    assert_eq!(pool.num_transactions(&address), 3);
+
+    // committing the transaction in front of the gap keeps it
+    pool.remove_transaction(&address, 0, false);
+    assert_eq!(pool.missing_sequence_numbers(&address, later), Some(1..2));
+
+    // parked transactions are evicted past the gc timeout, the rest of the account is kept
+    add_txn(&mut pool, TestTransaction::new(0, 0, 1)).unwrap();
+    pool.gc_by_sequence_gap(now + Duration::from_secs(601));
+    assert_eq!(pool.num_transactions(&address), 0);
+    assert_eq!(pool.missing_sequence_numbers(&address, later), None);
+    assert_eq!(pool.num_transactions(&TestTransaction::get_address(0)), 1);
+
+    // the lost transaction can be resubmitted
+    add_txn(&mut pool, TestTransaction::new(1, 1, 1)).unwrap();
+    assert_eq!(pool.get_block(10, HashSet::new()).len(), 2);
+}
+
+#[test]
+fn test_sequence_gap_hint_on_invalid_sequence_number() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    config.mempool.sequence_gap_hint_timeout_secs = 0;
+    let mut pool = CoreMempool::new(&config);
+    let address = TestTransaction::get_address(1);
+
+    // transactions up to 1 are committed, 2 is lost and 3 is parked
+    pool.remove_transaction(&address, 1, false);
+    add_txn(&mut pool, TestTransaction::new(1, 3, 1)).unwrap();
+
+    let status = pool.add_txn(
+        TestTransaction::new(1, 0, 1).make_signed_transaction(),
+        0,
+        0,
+        1000,
+        TimelineState::NotReady,
+    );
+    assert_eq!(
+        status.code,
+        MempoolAddTransactionStatusCode::InvalidSeqNumber
+    );
+    assert_eq!(status.missing_sequence_numbers, Some(2..3));
+
+    // no hint once the gap is filled
+    add_txn(&mut pool, TestTransaction::new(1, 2, 1)).unwrap();
+    let status = pool.add_txn(
+        TestTransaction::new(1, 0, 1).make_signed_transaction(),
+        0,
+        0,
+        1000,
+        TimelineState::NotReady,
+    );
+    assert_eq!(
+        status.code,
+        MempoolAddTransactionStatusCode::InvalidSeqNumber
+    );
+    assert_eq!(status.missing_sequence_numbers, None);
+}
+
+#[test]
+fn test_gas_price_ordering_keeps_sequence_order() {
+    let (mut mempool, mut consensus) = setup_mempool();
+    // later transactions of account 0 pay more than earlier ones
+    let transactions = add_txns_to_mempool(
+        &mut mempool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(0, 1, 100),
+            TestTransaction::new(0, 2, 200),
+            TestTransaction::new(1, 0, 50),
+        ],
+    );
+    assert_eq!(
+        consensus.get_block(&mut mempool, 4),
+        vec![
+            transactions[3].clone(),
+            transactions[0].clone(),
+            transactions[1].clone(),
+            transactions[2].clone(),
+        ]
+    );
+}
+
+#[test]
+fn test_update_block_ordering() {
+    let mut config = NodeConfigHelpers::get_single_node_test_config(true);
+    let mut mempool = CoreMempool::new(&config);
+    let mut consensus = ConsensusMock::new();
+    let transactions = add_txns_to_mempool(
+        &mut mempool,
+        vec![
+            TestTransaction::new(0, 0, 1),
+            TestTransaction::new(1, 0, 5),
+            TestTransaction::new(2, 0, 3),
+            TestTransaction::new(3, 1, 10),
+            TestTransaction::new(3, 0, 1),
+        ],
+    );
+    // gas price ordering by default
+    assert_eq!(
+        consensus.get_block(&mut mempool, 1),
+        vec![transactions[1].clone()]
+    );
+
+    // next block is ordered by arrival, still in sequence order for account 3
+    config.mempool.block_ordering = BlockOrdering::Timeline;
+    mempool.update_config(&config.mempool);
+    assert_eq!(
+        consensus.get_block(&mut mempool, 4),
+        vec![
+            transactions[0].clone(),
+            transactions[2].clone(),
+            transactions[4].clone(),
+            transactions[3].clone(),
+        ]
+    );
+}
@@ -9,6 +9,7 @@ use crate::{
 use futures::Future;
 use grpc_helpers::{create_grpc_invalid_arg_status, default_reply_error_logger};
 use libra_logger::prelude::*;
+use libra_mempool_shared_proto::proto::mempool_status::SequenceNumberRange;
 use libra_metrics::counters::SVC_COUNTERS;
 use libra_types::{
     account_address::AccountAddress, proto::types::SignedTransactionsBlock,
@@ -19,7 +20,7 @@ use std::{
     collections::HashSet,
     convert::TryFrom,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 #[derive(Clone)]
@@ -150,4 +151,82 @@ impl Mempool for MempoolService {
         response.is_healthy = pool.health_check();
         ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
     }
+
+    fn get_account_status(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::GetAccountStatusRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::GetAccountStatusResponse>,
+    ) {
+        trace!("[GRPC] Mempool::get_account_status");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let address = match AccountAddress::try_from(&req.address[..]) {
+            Ok(address) => address,
+            Err(e) => {
+                ctx.spawn(
+                    sink.fail(create_grpc_invalid_arg_status("get_account_status", e))
+                        .map_err(default_reply_error_logger),
+                );
+                SVC_COUNTERS.resp(&ctx, false);
+                return;
+            }
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("init timestamp failure");
+        let pool = self
+            .core_mempool
+            .lock()
+            .expect("[get_account_status] acquire mempool lock");
+        let mut response = crate::proto::mempool::GetAccountStatusResponse::default();
+        response.num_transactions = pool.num_transactions(&address) as u64;
+        response.missing_sequence_numbers =
+            pool.missing_sequence_numbers(&address, now).map(|range| {
+                let mut proto_range = SequenceNumberRange::default();
+                proto_range.start = range.start;
+                proto_range.end = range.end;
+                proto_range
+            });
+        ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+        SVC_COUNTERS.resp(&ctx, true);
+    }
+
+    fn get_mempool_capacity(
+        &mut self,
+        ctx: ::grpcio::RpcContext<'_>,
+        req: crate::proto::mempool::GetMempoolCapacityRequest,
+        sink: ::grpcio::UnarySink<crate::proto::mempool::GetMempoolCapacityResponse>,
+    ) {
+        trace!("[GRPC] Mempool::get_mempool_capacity");
+        let _timer = SVC_COUNTERS.req(&ctx);
+        let address = match AccountAddress::try_from(&req.address[..]) {
+            Ok(address) => address,
+            Err(e) => {
+                ctx.spawn(
+                    sink.fail(create_grpc_invalid_arg_status("get_mempool_capacity", e))
+                        .map_err(default_reply_error_logger),
+                );
+                SVC_COUNTERS.resp(&ctx, false);
+                return;
+            }
+        };
+        let pool = self
+            .core_mempool
+            .lock()
+            .expect("[get_mempool_capacity] acquire mempool lock");
+        let mut sender_num_transactions = pool.num_transactions(&address);
+        // a transaction at the requested sequence number gets replaced by the submission
+        if pool.contains(&address, req.sequence_number) {
+            sender_num_transactions -= 1;
+        }
+        let mut response = crate::proto::mempool::GetMempoolCapacityResponse::default();
+        response.size = pool.size() as u64;
+        response.size_bytes = pool.size_bytes() as u64;
+        response.capacity = pool.capacity() as u64;
+        response.capacity_per_user = pool.capacity_per_user() as u64;
+        response.is_full = !pool.health_check();
+        response.sender_num_transactions = sender_num_transactions as u64;
+        ctx.spawn(sink.success(response).map_err(default_reply_error_logger));
+        SVC_COUNTERS.resp(&ctx, true);
+    }
 }
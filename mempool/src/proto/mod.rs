@@ -12,6 +12,8 @@ pub mod mempool {
 }
 
 pub mod mempool_client {
+    use std::time::Duration;
+
     pub trait MempoolClientTrait: Clone + Send + Sync {
         fn add_transaction_with_validation(
             &self,
@@ -26,6 +28,21 @@ pub mod mempool_client {
         ) -> ::grpcio::Result<super::mempool::HealthCheckResponse> {
             unimplemented!();
         }
+
+        fn get_account_status(
+            &self,
+            _req: &super::mempool::GetAccountStatusRequest,
+        ) -> ::grpcio::Result<super::mempool::GetAccountStatusResponse> {
+            unimplemented!();
+        }
+
+        fn get_mempool_capacity(
+            &self,
+            _req: &super::mempool::GetMempoolCapacityRequest,
+            _timeout: Duration,
+        ) -> ::grpcio::Result<super::mempool::GetMempoolCapacityResponse> {
+            unimplemented!();
+        }
     }
 
     impl MempoolClientTrait for super::mempool::MempoolClient {
@@ -42,5 +59,20 @@ pub mod mempool_client {
         ) -> ::grpcio::Result<super::mempool::HealthCheckResponse> {
             self.health_check(req)
         }
+
+        fn get_account_status(
+            &self,
+            req: &super::mempool::GetAccountStatusRequest,
+        ) -> ::grpcio::Result<super::mempool::GetAccountStatusResponse> {
+            self.get_account_status(req)
+        }
+
+        fn get_mempool_capacity(
+            &self,
+            req: &super::mempool::GetMempoolCapacityRequest,
+            timeout: Duration,
+        ) -> ::grpcio::Result<super::mempool::GetMempoolCapacityResponse> {
+            self.get_mempool_capacity_opt(req, ::grpcio::CallOption::default().timeout(timeout))
+        }
     }
 }
@@ -7,7 +7,7 @@ use crate::{
 };
 use grpc_helpers::ServerHandle;
 use grpcio::EnvBuilder;
-use libra_config::config::NodeConfig;
+use libra_config::config::{MempoolConfig, NodeConfig};
 use network::validator_network::{MempoolNetworkEvents, MempoolNetworkSender};
 use std::{
     cmp::max,
@@ -23,6 +23,7 @@ pub struct MempoolRuntime {
     pub grpc_server: ServerHandle,
     /// separate shared mempool runtime
     pub shared_mempool: Runtime,
+    core_mempool: Arc<Mutex<CoreMempool>>,
 }
 
 impl MempoolRuntime {
@@ -63,7 +64,7 @@ impl MempoolRuntime {
         let vm_validator = Arc::new(VMValidator::new(&config, Arc::clone(&storage_client)));
         let shared_mempool = start_shared_mempool(
             config,
-            mempool,
+            Arc::clone(&mempool),
             network_sender,
             network_events,
             storage_client,
@@ -74,6 +75,15 @@ impl MempoolRuntime {
         Self {
             grpc_server: ServerHandle::setup(grpc_server),
             shared_mempool,
+            core_mempool: mempool,
         }
     }
+
+    /// Applies the parts of `config` that can change while Mempool is running
+    pub fn update_config(&self, config: &MempoolConfig) {
+        self.core_mempool
+            .lock()
+            .expect("[update_config] acquire mempool lock")
+            .update_config(config);
+    }
 }
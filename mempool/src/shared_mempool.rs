@@ -21,7 +21,7 @@ use std::{
     ops::Deref,
     pin::Pin,
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use storage_client::StorageRead;
 use tokio::{
@@ -357,14 +357,18 @@ async fn inbound_network_task<V>(
     crit!("SharedMempool inbound_network_task terminated");
 }
 
-/// GC all expired transactions by SystemTTL
+/// GC all expired transactions by SystemTTL, and those stuck behind a sequence number gap
 async fn gc_task(mempool: Arc<Mutex<CoreMempool>>, gc_interval_ms: u64) {
     let mut interval = Interval::new_interval(Duration::from_millis(gc_interval_ms));
     while let Some(_interval) = interval.next().await {
-        mempool
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("init timestamp failure");
+        let mut mempool = mempool
             .lock()
-            .expect("[shared mempool] failed to acquire mempool lock")
-            .gc_by_system_ttl();
+            .expect("[shared mempool] failed to acquire mempool lock");
+        mempool.gc_by_system_ttl();
+        mempool.gc_by_sequence_gap(now);
     }
 
     crit!("SharedMempool gc_task terminated");
@@ -147,3 +147,44 @@ fn test_gc_by_expiration_time() {
     let response = client.get_block(&GetBlockRequest::default()).unwrap();
     assert_eq!(response.block.unwrap().transactions.len(), 1);
 }
+
+#[test]
+fn test_get_mempool_capacity() {
+    let (server, client) = setup_mempool();
+    let _handle = ServerHandle::setup(server);
+
+    let add_req = create_add_transaction_request(0);
+    client.add_transaction_with_validation(&add_req).unwrap();
+    let signed_txn = SignedTransaction::try_from(add_req.transaction.unwrap()).unwrap();
+
+    let mut req = GetMempoolCapacityRequest::default();
+    req.address = signed_txn.sender().as_ref().to_vec();
+    req.sequence_number = 1;
+    let response = client.get_mempool_capacity(&req).unwrap();
+    assert_eq!(response.size, 1);
+    assert_eq!(response.size_bytes, signed_txn.raw_txn_bytes_len() as u64);
+    assert!(response.capacity > 0);
+    assert!(response.capacity_per_user > 0);
+    assert!(!response.is_full);
+    assert_eq!(response.sender_num_transactions, 1);
+
+    // the transaction at the requested sequence number would be replaced
+    req.sequence_number = 0;
+    let response = client.get_mempool_capacity(&req).unwrap();
+    assert_eq!(response.sender_num_transactions, 0);
+
+    // byte usage goes back down once the transaction is committed
+    let mut transaction = CommittedTransaction::default();
+    transaction.sender = req.address.clone();
+    transaction.sequence_number = 0;
+    let mut commit_req = CommitTransactionsRequest::default();
+    commit_req.transactions = vec![transaction];
+    client.commit_transactions(&commit_req).unwrap();
+    let response = client.get_mempool_capacity(&req).unwrap();
+    assert_eq!(response.size, 0);
+    assert_eq!(response.size_bytes, 0);
+
+    // invalid address
+    req.address = vec![1, 2, 3];
+    assert!(client.get_mempool_capacity(&req).is_err());
+}
@@ -5,7 +5,7 @@ use super::*;
 use crate::peer_manager::PeerManagerRequest;
 use core::str::FromStr;
 use futures::SinkExt;
-use libra_crypto::{ed25519::compat, test_utils::TEST_SEED, x25519};
+use libra_crypto::{ed25519::compat, test_utils::TEST_SEED, x25519, ShortStr};
 use memsocket::MemorySocket;
 use rand::{rngs::StdRng, SeedableRng};
 use std::io;
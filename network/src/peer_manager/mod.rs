@@ -21,6 +21,7 @@ use futures::{
     stream::{Fuse, FuturesUnordered, StreamExt},
 };
 use libra_config::config::RoleType;
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use libra_types::PeerId;
 use netcore::{
@@ -38,6 +38,7 @@ pub use self::{
     },
     state_synchronizer::{
         state_synchronizer_msg::Message as StateSynchronizerMsg_oneof, GetChunkRequest,
-        GetChunkResponse, StateSynchronizerMsg,
+        GetChunkResponse, GetRootHashesRequest, GetRootHashesResponse, LedgerInfoSummary,
+        ProtocolError, StateSynchronizerMsg, SubscriptionClosed, VersionRootHash,
     },
 };
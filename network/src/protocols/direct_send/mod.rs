@@ -56,6 +56,7 @@ use futures::{
     sink::SinkExt,
     stream::StreamExt,
 };
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use libra_types::PeerId;
 use netcore::compat::IoCompat;
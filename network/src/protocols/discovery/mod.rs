@@ -48,7 +48,7 @@ use futures::{
 use libra_crypto::{
     ed25519::*,
     hash::{CryptoHasher, DiscoveryMsgHasher},
-    HashValue,
+    HashValue, ShortStr,
 };
 use libra_logger::prelude::*;
 use libra_types::{
@@ -28,6 +28,7 @@ use futures::{
     channel::oneshot,
     stream::{FusedStream, FuturesUnordered, Stream, StreamExt},
 };
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use libra_types::PeerId;
 use rand::{rngs::SmallRng, seq::SliceRandom, FromEntropy, Rng};
@@ -73,6 +73,7 @@ use futures::{
     stream::StreamExt,
     task::Context,
 };
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use libra_types::PeerId;
 use netcore::compat::IoCompat;
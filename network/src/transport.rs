@@ -7,7 +7,7 @@ use crate::{
 };
 use libra_crypto::{
     x25519::{X25519StaticPrivateKey, X25519StaticPublicKey},
-    ValidKey,
+    ShortStr, ValidKey,
 };
 use libra_logger::prelude::*;
 use libra_types::PeerId;
@@ -0,0 +1,71 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Checkpoints of the sync persisted to a file, for a node restarting in the middle of a long
+//! catch-up to resume with what it knew of its upstream peers instead of probing them again. The
+//! checkpoint only holds hints: the versions it records are verified again on the first contact
+//! with each peer.
+
+use crate::PeerId;
+use failure::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub(crate) struct SyncCheckpoint {
+    // version committed locally when the checkpoint was taken
+    pub known_version: u64,
+    // latest version advertised by each upstream peer, as far as known
+    pub advertised_versions: Vec<(PeerId, u64)>,
+    // when the checkpoint was taken, in seconds since the epoch
+    pub timestamp_secs: u64,
+}
+
+impl SyncCheckpoint {
+    pub fn new(
+        known_version: u64,
+        advertised_versions: Vec<(PeerId, u64)>,
+        timestamp: SystemTime,
+    ) -> Self {
+        Self {
+            known_version,
+            advertised_versions,
+            timestamp_secs: timestamp
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_secs()),
+        }
+    }
+
+    /// Reads the checkpoint written to `path`, failing if the file is missing or does not hold a
+    /// checkpoint.
+    pub fn read(path: &Path) -> Result<Self> {
+        lcs::from_bytes(&fs::read(path)?).map_err(Into::into)
+    }
+
+    /// Writes the checkpoint to `path`, through a temporary file renamed over it, so that a crash
+    /// while writing leaves the previous checkpoint intact.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, lcs::to_bytes(self)?)?;
+        fs::rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Time elapsed from when the checkpoint was taken until `now`, zero if it was taken later.
+    pub fn age(&self, now: SystemTime) -> Duration {
+        now.duration_since(UNIX_EPOCH + Duration::from_secs(self.timestamp_secs))
+            .unwrap_or_default()
+    }
+
+    /// Highest version advertised by any peer of the checkpoint.
+    pub fn max_advertised_version(&self) -> Option<u64> {
+        self.advertised_versions
+            .iter()
+            .map(|(_, version)| *version)
+            .max()
+    }
+}
@@ -2,57 +2,384 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    checkpoint::SyncCheckpoint,
+    clock::{Clock, SystemClock},
     counters,
-    executor_proxy::ExecutorProxyTrait,
+    executor_proxy::{
+        is_transient_storage_error, EpochProofPage, ExecutorProxyTrait, TransientError,
+    },
     peer_manager::{PeerManager, PeerScoreUpdateType},
+    session::SessionEvent,
     LedgerInfo, PeerId,
 };
+use bounded_executor::BoundedExecutor;
+use channel::keyed_watch;
 use failure::prelude::*;
 use futures::{
     channel::{mpsc, oneshot},
-    stream::{futures_unordered::FuturesUnordered, select_all},
+    future::{self, Either, Future, FutureExt, Shared},
+    stream::select_all,
     StreamExt,
 };
 use libra_config::config::RoleType;
 use libra_config::config::StateSyncConfig;
+use libra_crypto::{hash::CryptoHash, HashValue, ShortStr};
 use libra_logger::prelude::*;
-use libra_types::crypto_proxies::ValidatorChangeEventWithProof;
 use libra_types::{
     crypto_proxies::LedgerInfoWithSignatures, transaction::TransactionListWithProof,
+    validator_set::ValidatorSet,
 };
 use network::{
-    proto::{GetChunkRequest, GetChunkResponse, StateSynchronizerMsg, StateSynchronizerMsg_oneof},
+    proto::{
+        GetChunkRequest, GetChunkResponse, GetRootHashesRequest, GetRootHashesResponse,
+        LedgerInfoSummary, ProtocolError, StateSynchronizerMsg, StateSynchronizerMsg_oneof,
+        SubscriptionClosed, VersionRootHash,
+    },
     validator_network::{Event, StateSynchronizerEvents, StateSynchronizerSender},
 };
+use prost::Message as _;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
     convert::TryInto,
+    pin::Pin,
     str::FromStr,
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::timer::Interval;
+use tokio::{
+    runtime::TaskExecutor,
+    timer::{delay_for, Interval, Timeout},
+};
 
 pub(crate) struct SyncRequest {
     // The Result value returned to the caller is Error in case the StateSynchronizer failed to
     // reach the target (the LI in the storage remains unchanged as if nothing happened).
-    pub callback: oneshot::Sender<Result<()>>,
+    pub callback: oneshot::Sender<Result<SyncCompletionInfo>>,
     pub target: LedgerInfoWithSignatures,
+    pub purpose: SyncPurpose,
+    // If set, the new version is sent on it after each chunk applied toward the target, before
+    // the final result is sent on `callback`.
+    pub progress: Option<mpsc::UnboundedSender<u64>>,
+}
+
+/// Why a sync is requested, which decides how aggressively the coordinator drives it.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SyncPurpose {
+    /// Consensus is blocked until the target is reached: requests time out after
+    /// `consensus_sync_timeout_ms` and always go to the best scored peers.
+    Consensus,
+    /// Background catch-up: requests time out after two ticks and are spread over the peers
+    /// according to their scores.
+    Catchup,
+}
+
+/// Describes where a successful sync request ended up.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyncCompletionInfo {
+    /// The ledger info storage is at once the sync completed, i.e. the requested target.
+    pub reached_li: LedgerInfoWithSignatures,
+    /// Number of versions committed between the request and its completion.
+    pub versions_applied: u64,
+    /// Time between the coordinator receiving the request and its completion.
+    pub duration: Duration,
+    /// What the sync session of the request took to complete.
+    pub metrics: SyncSessionMetrics,
+}
+
+/// Work done by the coordinator since the current sync request was received. Chunks applied
+/// while no sync is requested, e.g. by full nodes catching up, count toward the next session.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SyncSessionMetrics {
+    /// Number of transactions of the chunks applied.
+    pub txns_replayed: u64,
+    /// Number of chunks applied.
+    pub chunks_applied: u64,
+    /// Encoded size of the chunk responses received, applied or not.
+    pub bytes_transferred: u64,
+    /// Time since the sync request was received.
+    pub elapsed: Duration,
+}
+
+/// Milestones of the coordinator reported to the event listener, see
+/// `StateSynchronizerBuilder::with_event_listener`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SyncEvent {
+    /// The known version reached the highest version advertised by peers for the first time since
+    /// startup, e.g. for the node to start serving clients. Sent at most once.
+    InitialSyncComplete { version: u64 },
+}
+
+/// Reasons for a sync request to fail.
+#[derive(Clone, Debug, Eq, Fail, PartialEq)]
+pub enum SyncError {
+    /// The request was replaced by a newer one before reaching its target.
+    #[fail(
+        display = "Sync to version {} was superseded by a sync to version {}",
+        pending_version, requested_version
+    )]
+    TargetConflict {
+        pending_version: u64,
+        requested_version: u64,
+    },
+    /// State synchronizer stopped before the request completed.
+    #[fail(display = "State synchronizer shut down")]
+    Shutdown,
+    /// The chunk starting at `version` verifies against its signed target, but extends a ledger
+    /// that forks from the local one before `version`. Applying it would rewind the local ledger.
+    #[fail(
+        display = "Chunk starting at version {} forks from the local ledger",
+        version
+    )]
+    Reorg { version: u64 },
+    /// No upstream peer is left to sync from, e.g. after all of them disconnected.
+    #[fail(display = "No upstream peer available to sync from")]
+    NoPeersAvailable,
+}
+
+/// Outcome of auditing one version against a peer, see `StateSyncClient::audit_state`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct VersionAudit {
+    pub version: u64,
+    /// Hash of the transaction info committed locally at `version`, if committed.
+    pub local_hash: Option<HashValue>,
+    /// Hash of the transaction info the peer committed at `version`, if committed.
+    pub peer_hash: Option<HashValue>,
+}
+
+impl VersionAudit {
+    /// Whether both sides committed `version`, with different transaction infos. A version not
+    /// committed by either side yet is not a mismatch.
+    pub fn is_mismatch(&self) -> bool {
+        match (self.local_hash, self.peer_hash) {
+            (Some(local_hash), Some(peer_hash)) => local_hash != peer_hash,
+            _ => false,
+        }
+    }
+}
+
+/// Why the most recent chunk request or response failed, kept for operators to tell why a sync
+/// stalls.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChunkFailure {
+    /// The request for the chunk starting at `version` sent to `peer_id` timed out.
+    Timeout { peer_id: PeerId, version: u64 },
+    /// The chunk sent by `peer_id` or its target ledger info failed to verify.
+    InvalidProof { peer_id: PeerId, error: String },
+    /// The chunk sent by `peer_id` did not start right after the known version.
+    NonSequential {
+        peer_id: PeerId,
+        known_version: u64,
+        received_version: u64,
+    },
+    /// The chunk response sent by `peer_id` is inconsistent in itself, e.g. a non-empty
+    /// transaction list without a first version.
+    MalformedResponse { peer_id: PeerId, error: String },
+    /// The chunk sent by `peer_id` failed to execute.
+    ExecutorError { peer_id: PeerId, error: String },
+    /// The chunk starting at `version` sent by `peer_id` forks from the local ledger, see
+    /// `SyncError::Reorg`.
+    Reorg { peer_id: PeerId, version: u64 },
+    /// The transaction info at `version` of the chunk applied from `peer_id` differs from the one
+    /// of the verification peer.
+    Discrepancy {
+        peer_id: PeerId,
+        verification_peer_id: PeerId,
+        version: u64,
+    },
+}
+
+/// Reasons for a chunk to fail to apply, telling apart the faults of the peer that sent it from
+/// local ones.
+#[derive(Debug, Fail)]
+pub(crate) enum StateSyncError {
+    /// The chunk or its target ledger info does not verify, the peer that sent it is to blame.
+    #[fail(display = "Invalid chunk: {}", _0)]
+    InvalidChunk(failure::Error),
+    /// Executing the chunk failed locally, e.g. on a storage error.
+    #[fail(display = "Failed to execute chunk: {}", _0)]
+    Execution(failure::Error),
+    /// The chunk starting at `version` forks from the local ledger, the peer is not to blame for
+    /// relaying what a quorum signed.
+    #[fail(
+        display = "Chunk starting at version {} forks from the local ledger",
+        version
+    )]
+    Reorg { version: u64 },
 }
 
+// Version of the state sync protocol spoken by this node, advertised in every message sent.
+// Peers can stop talking to it by raising their `min_supported_protocol_version` above it.
+pub(crate) const PROTOCOL_VERSION: u64 = 1;
+
+// Upper bound on the number of versions audited in one request.
+const MAX_AUDIT_VERSIONS: usize = 100;
+
+// Time after which an audit whose root hash request was not answered fails.
+const AUDIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Upper bound on the number of epoch change ledger infos returned in one page.
+const MAX_EPOCH_PROOF_PAGE_SIZE: u64 = 50;
+
 pub(crate) struct EpochRetrievalRequest {
     pub start_epoch: u64,
-    pub callback: oneshot::Sender<Result<ValidatorChangeEventWithProof>>,
+    // Epoch to stop before. Defaults to the latest epoch.
+    pub end_epoch: Option<u64>,
+    // Maximum number of ledger infos in the page, capped at MAX_EPOCH_PROOF_PAGE_SIZE.
+    pub limit: Option<u64>,
+    pub callback: oneshot::Sender<Result<EpochProofPage>>,
+}
+
+pub(crate) struct AuditRequest {
+    pub peer_id: PeerId,
+    pub versions: Vec<u64>,
+    pub callback: oneshot::Sender<Result<Vec<VersionAudit>>>,
+}
+
+// An audit waiting for the root hashes of the peer.
+struct PendingAudit {
+    versions: Vec<u64>,
+    deadline: SystemTime,
+    reporter: AuditReporter,
+}
+
+// Who gets the outcome of an audit.
+enum AuditReporter {
+    // the caller of `StateSyncClient::audit_state`
+    Client(oneshot::Sender<Result<Vec<VersionAudit>>>),
+    // the coordinator itself, checking a chunk applied from `chunk_peer` against the
+    // verification peer, see `verification_peer`
+    Verification { chunk_peer: PeerId },
 }
 
 /// message used by StateSyncClient for communication with Coordinator
 pub(crate) enum CoordinatorMessage {
     // used to initiate new sync
     Request(SyncRequest),
-    // used to notify about new txn commit
-    Commit(u64),
     GetState(oneshot::Sender<u64>),
+    // used to check whether a version is committed
+    IsCommitted(u64, oneshot::Sender<bool>),
+    // used to get notified once a version is committed
+    WaitForVersion(u64, oneshot::Sender<()>),
     // used to generate epoch proof
     GetEpochProof(EpochRetrievalRequest),
+    // used to fetch the ledger info committed at a given version
+    GetLedgerInfo(u64, oneshot::Sender<Result<LedgerInfoWithSignatures>>),
+    // used to fetch why the last chunk request or response of the sync session failed
+    GetLastError(oneshot::Sender<Option<ChunkFailure>>),
+    // used to fetch the metrics of the pending sync session, if any
+    GetSessionMetrics(oneshot::Sender<Option<SyncSessionMetrics>>),
+    // used to compare the transaction infos at some versions with the ones of a peer
+    AuditState(AuditRequest),
+    // used to replace the upstream peers after the config was reloaded
+    UpdateUpstreamPeers(Vec<PeerId>),
+    // used to replace the minimum supported protocol version after the config was reloaded
+    UpdateMinProtocolVersion(u64),
+    // used to stop the coordinator once the long-poll subscribers are notified
+    Shutdown(oneshot::Sender<()>),
+}
+
+// Number of recent commit intervals the adaptive long-poll timeout is derived from.
+const NUM_COMMIT_INTERVALS: usize = 16;
+
+// The adaptive long-poll timeout spans this many average commit intervals, for a request to
+// usually see new data before it times out.
+const LONG_POLL_COMMIT_INTERVALS: u32 = 3;
+
+// Maximum number of network events taken in one iteration of the event loop, for a flood of
+// messages not to hold up the other events.
+const MAX_NETWORK_EVENTS_PER_ITERATION: usize = 100;
+
+/// Messages of peers waiting to be processed. Chunk responses, which advance the state of this
+/// node, go ahead of the other messages, mostly chunk requests of downstream peers, unless the
+/// oldest of these has been waiting for `max_deferral` already.
+struct InboundQueue {
+    responses: VecDeque<(PeerId, StateSynchronizerMsg)>,
+    // arrival time, number of responses pushed before, peer and message
+    others: VecDeque<(SystemTime, u64, PeerId, StateSynchronizerMsg)>,
+    num_responses_pushed: u64,
+    max_deferral: Duration,
+}
+
+impl InboundQueue {
+    fn new(max_deferral: Duration) -> Self {
+        Self {
+            responses: VecDeque::new(),
+            others: VecDeque::new(),
+            num_responses_pushed: 0,
+            max_deferral,
+        }
+    }
+
+    fn push(&mut self, peer_id: PeerId, message: StateSynchronizerMsg, now: SystemTime) {
+        if let Some(StateSynchronizerMsg_oneof::ChunkResponse(_)) = &message.message {
+            self.responses.push_back((peer_id, message));
+            self.num_responses_pushed += 1;
+        } else {
+            self.others
+                .push_back((now, self.num_responses_pushed, peer_id, message));
+        }
+    }
+
+    /// Takes the next message to process at `now`, together with how long it was deferred if
+    /// chunk responses received after it went first.
+    fn pop(&mut self, now: SystemTime) -> Option<(PeerId, StateSynchronizerMsg, Option<Duration>)> {
+        let max_deferral = self.max_deferral;
+        let overdue = self.others.front().map_or(false, |(arrival, ..)| {
+            now.duration_since(*arrival)
+                .map_or(false, |age| age >= max_deferral)
+        });
+        if !overdue {
+            if let Some((peer_id, message)) = self.responses.pop_front() {
+                return Some((peer_id, message, None));
+            }
+        }
+        let (arrival, num_responses_before, peer_id, message) = self.others.pop_front()?;
+        // the responses still queued are the latest ones
+        let num_responses_after = self.num_responses_pushed - num_responses_before;
+        let deferral = if num_responses_after > self.responses.len() as u64 {
+            Some(now.duration_since(arrival).unwrap_or_default())
+        } else {
+            None
+        };
+        Some((peer_id, message, deferral))
+    }
+}
+
+/// Chunk deliveries in flight to a long-poll subscriber.
+struct PeerDeliveries {
+    // completes once the latest delivery to the peer is done, the next one waits for it so that
+    // the peer gets its chunks in order
+    last_delivery: oneshot::Receiver<()>,
+    // completes for every delivery to the peer once `_connected` is dropped
+    disconnected: Shared<oneshot::Receiver<()>>,
+    _connected: oneshot::Sender<()>,
+}
+
+impl PeerDeliveries {
+    fn new() -> Self {
+        let (last_delivery_sender, last_delivery) = oneshot::channel();
+        drop(last_delivery_sender);
+        let (connected, disconnected) = oneshot::channel();
+        Self {
+            last_delivery,
+            disconnected: disconnected.shared(),
+            _connected: connected,
+        }
+    }
+}
+
+/// A verified chunk whose execution failed on a transient storage error, kept to be executed again
+/// by `retry_buffered_chunk`.
+struct BufferedChunk {
+    peer_id: PeerId,
+    txn_list_with_proof: TransactionListWithProof,
+    target: LedgerInfo,
+    // number of times the chunk was executed again already
+    num_retries: u64,
+    // when the chunk is to be executed again
+    retry_at: SystemTime,
+    // past which the chunk is no longer executed again
+    expiry: SystemTime,
 }
 
 /// used to coordinate synchronization process
@@ -60,6 +387,8 @@ pub(crate) enum CoordinatorMessage {
 pub(crate) struct SyncCoordinator<T> {
     // used to process client requests
     client_events: mpsc::UnboundedReceiver<CoordinatorMessage>,
+    // used to notify about new txn commits, only the latest committed version matters
+    commit_notifications: keyed_watch::Receiver<(), u64>,
     // last committed version that validator is aware of
     known_version: u64,
     // config
@@ -68,21 +397,106 @@ pub(crate) struct SyncCoordinator<T> {
     role: RoleType,
     // peers used for synchronization. TBD: value is meta information about peer sync quality
     peer_manager: PeerManager,
+    // changes of whether `peer_manager` has any peer to pick, see `process_usable_peers_changes`
+    usable_peers_changes: mpsc::UnboundedReceiver<bool>,
     // Optional sync request to be called when the target sync is reached
     sync_request: Option<SyncRequest>,
+    // requests for the target of `sync_request` received while it was pending, completed along
+    // with it
+    coalesced_sync_requests: Vec<SyncRequest>,
+    // time and known version at which the current sync request was received
+    sync_request_start: (SystemTime, u64),
+    // work done since the current sync request was received, `elapsed` excluded
+    session_metrics: SyncSessionMetrics,
     // queue of incoming long polling requests
     // peer will be notified about new chunk of transactions if it's available before expiry time
     // value format is (expiration_time, known_version, limit)
     subscriptions: HashMap<PeerId, (SystemTime, u64, u64)>,
-    executor_proxy: T,
+    // shared with the tasks retrying epoch proof reads
+    executor_proxy: Arc<T>,
+    // size of the validator set of the current epoch, updated on each epoch change applied
+    num_validators: usize,
+    // source of the current time for subscription handling
+    clock: Arc<dyn Clock>,
+    // highest version seen in the ledger infos of chunk responses
+    target_version: u64,
+    // start time and known version of the current throughput measurement, used to enforce
+    // `min_progress_rate`. Only kept while behind `target_version`.
+    progress_window: Option<(SystemTime, u64)>,
+    // limit of the chunks requested next, ramped up from `slow_start_chunk_limit` if set
+    chunk_limit: u64,
+    // runs the chunk deliveries to long-poll subscribers off the event loop
+    executor: TaskExecutor,
+    // bounds the number of chunk deliveries to long-poll subscribers running concurrently
+    delivery_executor: BoundedExecutor,
+    // chunk deliveries in flight per long-poll subscriber
+    deliveries: HashMap<PeerId, PeerDeliveries>,
+    // if set, the inputs handled are recorded on it, see `record_session`
+    session_recorder: Option<mpsc::UnboundedSender<SessionEvent>>,
+    // if set, the new known version is sent on it after each commit advancing it
+    commit_listener: Option<mpsc::UnboundedSender<u64>>,
+    // if set, the milestones of the coordinator are sent on it
+    event_listener: Option<mpsc::UnboundedSender<SyncEvent>>,
+    // whether a peer sent a ledger info since startup, before which `target_version` may only
+    // come from a checkpoint
+    frontier_known: bool,
+    // latched once `SyncEvent::InitialSyncComplete` is sent, for it to be sent only once
+    initial_sync_complete: bool,
+    // callers waiting for a version to be committed
+    version_waiters: Vec<(u64, oneshot::Sender<()>)>,
+    // when the latest ledger info summary was gossiped, see `ledger_info_gossip_interval_ms`
+    last_gossip_time: SystemTime,
+    // messages of peers received in the current iteration of the event loop
+    inbound: InboundQueue,
+    // time of the latest commit and intervals between the recent ones, oldest first, see
+    // `adaptive_long_poll_timeout_ms`
+    last_commit_time: Option<SystemTime>,
+    commit_intervals: VecDeque<Duration>,
+    // why the last chunk request or response of the sync session failed, if any
+    last_chunk_failure: Option<ChunkFailure>,
+    // audits waiting for the root hashes of a peer, at most one per peer
+    pending_audits: HashMap<PeerId, PendingAudit>,
+    // trusted peer the chunks applied from other peers are checked against, and the number of
+    // such chunks applied since the last check, see `verification_interval`
+    verification_peer: Option<PeerId>,
+    chunks_since_verification: u64,
+    // peers chunks are served to, or any peer if empty, see `serve_allowlist`
+    serve_allowlist: HashSet<PeerId>,
+    // when the latest checkpoint was written, see `checkpoint_file`
+    last_checkpoint_time: SystemTime,
+    // the chunk following the known version, waiting to be executed again after a transient
+    // storage error, see `max_chunk_execution_retries`
+    buffered_chunk: Option<BufferedChunk>,
 }
 
-impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
+impl<T: ExecutorProxyTrait + 'static> SyncCoordinator<T> {
     pub fn new(
         client_events: mpsc::UnboundedReceiver<CoordinatorMessage>,
+        commit_notifications: keyed_watch::Receiver<(), u64>,
         role: RoleType,
         config: StateSyncConfig,
         executor_proxy: T,
+        executor: TaskExecutor,
+    ) -> Self {
+        Self::new_with_clock(
+            client_events,
+            commit_notifications,
+            role,
+            config,
+            executor_proxy,
+            executor,
+            Arc::new(SystemClock),
+        )
+    }
+
+    pub(crate) fn new_with_clock(
+        client_events: mpsc::UnboundedReceiver<CoordinatorMessage>,
+        commit_notifications: keyed_watch::Receiver<(), u64>,
+        role: RoleType,
+        config: StateSyncConfig,
+        executor_proxy: T,
+        executor: TaskExecutor,
+        clock: Arc<dyn Clock>,
     ) -> Self {
         let upstream_peers: Vec<_> = config
             .upstream_peers
@@ -94,15 +508,84 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
                 })
             })
             .collect();
+        let verification_peer = config.verification_peer.as_ref().map(|peer_id_str| {
+            PeerId::from_str(peer_id_str).unwrap_or_else(|_| {
+                panic!(
+                    "Failed to parse verification peer_id from string: {}",
+                    peer_id_str
+                )
+            })
+        });
+        let serve_allowlist = config
+            .serve_allowlist
+            .iter()
+            .map(|peer_id_str| {
+                PeerId::from_str(peer_id_str).unwrap_or_else(|_| {
+                    panic!(
+                        "Failed to parse allowlisted peer_id from string: {}",
+                        peer_id_str
+                    )
+                })
+            })
+            .collect();
+        let mut peer_manager = PeerManager::new_with_clock(upstream_peers, Arc::clone(&clock));
+        let usable_peers_changes = peer_manager.subscribe_usable_peers();
+        peer_manager.set_tie_score_epsilon(config.peer_score_tie_epsilon);
+        peer_manager.set_network_weights(config.network_weights.clone());
+        peer_manager.set_min_protocol_version(config.min_supported_protocol_version);
+        peer_manager.set_timeout_grace_period(
+            config
+                .timeout_penalty_grace_period_ms
+                .map(Duration::from_millis),
+        );
+        let chunk_limit = config.slow_start_chunk_limit.unwrap_or(config.chunk_limit);
+        let inbound =
+            InboundQueue::new(Duration::from_millis(config.max_chunk_request_deferral_ms));
+        let delivery_executor = BoundedExecutor::new(
+            config.max_concurrent_subscription_deliveries,
+            executor.clone(),
+        );
+        let last_checkpoint_time = clock.now();
+        let num_validators = executor_proxy.num_validators();
         Self {
             client_events,
+            commit_notifications,
             known_version: 0,
             config,
             role,
-            peer_manager: PeerManager::new(upstream_peers),
+            peer_manager,
+            usable_peers_changes,
             subscriptions: HashMap::new(),
             sync_request: None,
-            executor_proxy,
+            coalesced_sync_requests: vec![],
+            sync_request_start: (UNIX_EPOCH, 0),
+            session_metrics: SyncSessionMetrics::default(),
+            executor_proxy: Arc::new(executor_proxy),
+            num_validators,
+            clock,
+            target_version: 0,
+            progress_window: None,
+            chunk_limit,
+            executor,
+            delivery_executor,
+            deliveries: HashMap::new(),
+            last_checkpoint_time,
+            session_recorder: None,
+            commit_listener: None,
+            event_listener: None,
+            frontier_known: false,
+            initial_sync_complete: false,
+            version_waiters: vec![],
+            last_gossip_time: UNIX_EPOCH,
+            inbound,
+            last_commit_time: None,
+            commit_intervals: VecDeque::new(),
+            last_chunk_failure: None,
+            pending_audits: HashMap::new(),
+            verification_peer,
+            chunks_since_verification: 0,
+            serve_allowlist,
+            buffered_chunk: None,
         }
     }
 
@@ -113,6 +596,7 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             .get_latest_version()
             .await
             .expect("[start sync] failed to fetch latest version from storage");
+        self.load_checkpoint();
 
         let mut interval =
             Interval::new_interval(Duration::from_millis(self.config.tick_interval_ms)).fuse();
@@ -129,275 +613,1420 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
         loop {
             ::futures::select! {
                 msg = self.client_events.select_next_some() => {
-                    match msg {
-                        CoordinatorMessage::Request(request) => {
-                            self.request_sync(request).await;
-                        }
-                        CoordinatorMessage::Commit(version) => {
-                             self.commit(version).await;
-                        }
-                        CoordinatorMessage::GetState(callback) => {
-                            self.get_state(callback);
-                        }
-                        CoordinatorMessage::GetEpochProof(request) => {
-                            self.get_epoch_proof(request).await;
-                        }
+                    let is_shutdown = match msg {
+                        CoordinatorMessage::Shutdown(_) => true,
+                        _ => false,
                     };
+                    self.process_client_message(msg).await;
+                    if is_shutdown {
+                        return;
+                    }
+                },
+                (_, version) = self.commit_notifications.select_next_some() => {
+                    self.record(|| SessionEvent::Commit(version));
+                    self.commit(version).await;
                 },
                 (idx, network_event) = network_events.select_next_some() => {
-                    match network_event {
-                        Ok(event) => {
-                            match event {
-                                Event::NewPeer(peer_id) => {
-                                    debug!("[state sync] new peer {}", peer_id);
-                                    self.peer_manager.enable_peer(peer_id, network_senders[idx].clone());
-                                    self.check_progress().await;
-                                }
-                                Event::LostPeer(peer_id) => {
-                                    debug!("[state sync] lost peer {}", peer_id);
-                                    self.peer_manager.disable_peer(&peer_id);
-                                }
-                                Event::Message((peer_id, mut message)) => {
-                                    match message.message.unwrap() {
-                                        StateSynchronizerMsg_oneof::ChunkRequest(request) => {
-                                            let known_version = request.known_version;
-                                            if let Err(err) = self.process_chunk_request(peer_id, request).await {
-                                                error!("[state sync] failed to serve chunk request to {} with known version {}: {}", peer_id, known_version, err);
-                                            }
-                                        }
-                                        StateSynchronizerMsg_oneof::ChunkResponse(response) => {
-                                            if let Err(err) = self.process_chunk_response(&peer_id, response).await {
-                                                error!("[state sync] failed to process chunk response from {}: {}", peer_id, err);
-                                                counters::APPLY_CHUNK_FAILURE.with_label_values(&[&*peer_id.to_string()]).inc();
-                                            } else {
-                                                self.peer_manager.update_score(&peer_id, PeerScoreUpdateType::Success);
-                                                counters::APPLY_CHUNK_SUCCESS.with_label_values(&[&*peer_id.to_string()]).inc();
-                                            }
-                                        }
-                                    }
-                                }
-                                _ => {}
+                    // take the events already received as well, for the chunk responses among
+                    // them to be processed first
+                    self.process_network_event(idx, network_event, &network_senders).await;
+                    for _ in 1..MAX_NETWORK_EVENTS_PER_ITERATION {
+                        match network_events.select_next_some().now_or_never() {
+                            Some((idx, network_event)) => {
+                                self.process_network_event(idx, network_event, &network_senders).await;
                             }
-                        },
-                        Err(err) => { error!("[state sync] network error {}", err); },
+                            None => break,
+                        }
                     }
+                    self.process_inbound_messages().await;
                 },
                 _ = interval.select_next_some() => {
+                    self.retry_buffered_chunk().await;
                     self.check_progress().await;
+                    self.gossip_ledger_info_summary().await;
+                    self.expire_audits();
+                    self.checkpoint_if_due();
                 }
             }
         }
     }
 
-    async fn request_sync(&mut self, request: SyncRequest) {
-        self.known_version = self
-            .executor_proxy
-            .get_latest_version()
-            .await
-            .expect("[state sync] failed to fetch latest version from storage");
-        let target_version = request.target.ledger_info().version();
-        counters::TARGET_VERSION.set(target_version as i64);
-        debug!(
-            "[state sync] sync requested. Known version: {}, requested_version: {}",
-            self.known_version, target_version
-        );
+    /// Handles an event of network `idx`. Messages are queued to be processed by
+    /// `process_inbound_messages`.
+    async fn process_network_event(
+        &mut self,
+        idx: usize,
+        network_event: std::result::Result<Event<StateSynchronizerMsg>, impl std::fmt::Display>,
+        network_senders: &[StateSynchronizerSender],
+    ) {
+        match network_event {
+            Ok(Event::NewPeer(peer_id)) => {
+                self.process_new_peer(peer_id, idx, network_senders[idx].clone())
+                    .await;
+            }
+            Ok(Event::LostPeer(peer_id)) => {
+                // the messages the peer sent before are still processed
+                self.process_inbound_messages().await;
+                self.process_lost_peer(peer_id);
+            }
+            Ok(Event::Message((peer_id, message))) => self.enqueue_message(peer_id, message),
+            Ok(_) => {}
+            Err(err) => error!("[state sync] network error {}", err),
+        }
+    }
 
-        if target_version <= self.known_version {
-            debug!("[state sync] sync contains only empty blocks");
-            self.store_transactions(
-                TransactionListWithProof::new_empty(),
-                request.target.clone(),
-            )
-            .await
-            .expect("[state sync] failed to execute empty blocks");
-            if request.callback.send(Ok(())).is_err() {
-                error!("[state sync] coordinator failed to notify subscriber");
+    /// Queues `message` of `peer_id` to be processed by `process_inbound_messages`.
+    pub(crate) fn enqueue_message(&mut self, peer_id: PeerId, message: StateSynchronizerMsg) {
+        let now = self.clock.now();
+        self.inbound.push(peer_id, message, now);
+    }
+
+    /// Processes the queued messages of peers, chunk responses ahead of the other messages for
+    /// at most `max_chunk_request_deferral_ms`.
+    pub(crate) async fn process_inbound_messages(&mut self) {
+        let mut max_deferral = None;
+        while let Some((peer_id, message, deferral)) = self.inbound.pop(self.clock.now()) {
+            if let Some(deferral) = deferral {
+                counters::DEFERRED_MESSAGES.inc();
+                max_deferral = std::cmp::max(max_deferral, Some(deferral));
             }
-            return;
+            self.process_message(peer_id, message).await;
         }
+        if let Some(max_deferral) = max_deferral {
+            counters::MAX_MESSAGE_DEFERRAL_MS.set(max_deferral.as_millis() as i64);
+        }
+    }
 
-        let peers = request.target.signatures().keys().copied().collect();
-        self.peer_manager.set_peers(peers);
-        self.sync_request = Some(request);
-        self.request_next_chunk(0).await;
+    /// Records the inputs handled from now on to `recorder`, so that the session can be
+    /// replayed by `replay`.
+    pub(crate) fn record_session(&mut self, recorder: mpsc::UnboundedSender<SessionEvent>) {
+        self.session_recorder = Some(recorder);
     }
 
-    async fn commit(&mut self, version: u64) {
-        debug!(
-            "[state sync] commit. Known version: {}, version: {}",
-            self.known_version, version
+    /// Sends the new known version to `listener` after each commit advancing it. Versions
+    /// committed in a row may be reported once, as commit notifications are coalesced.
+    pub(crate) fn set_commit_listener(&mut self, listener: mpsc::UnboundedSender<u64>) {
+        self.commit_listener = Some(listener);
+    }
+
+    /// Sends the milestones of the coordinator to `listener`.
+    pub(crate) fn set_event_listener(&mut self, listener: mpsc::UnboundedSender<SyncEvent>) {
+        self.event_listener = Some(listener);
+    }
+
+    /// Sends `SyncEvent::InitialSyncComplete` the first time the known version reaches the highest
+    /// version advertised by peers.
+    fn check_initial_sync_complete(&mut self) {
+        if self.initial_sync_complete
+            || !self.frontier_known
+            || self.known_version < self.target_version
+        {
+            return;
+        }
+        self.initial_sync_complete = true;
+        info!(
+            "[state sync] initial sync complete at version {}",
+            self.known_version
         );
-        let is_update = version > self.known_version;
-        self.known_version = std::cmp::max(version, self.known_version);
-        if is_update {
-            if let Some(last_request_tst) =
-                self.peer_manager.get_request_time(self.known_version + 1)
-            {
-                if let Ok(duration) = SystemTime::now().duration_since(last_request_tst) {
-                    counters::SYNC_PROGRESS_DURATION.observe_duration(duration);
-                }
+        if let Some(listener) = &self.event_listener {
+            let event = SyncEvent::InitialSyncComplete {
+                version: self.known_version,
+            };
+            if listener.unbounded_send(event).is_err() {
+                debug!("[state sync] event listener dropped");
+                self.event_listener = None;
             }
-            if let Err(err) = self.check_subscriptions().await {
-                error!("[state sync] failed to check subscriptions: {}", err);
+        }
+    }
+
+    /// Records the event built by `event` if the session is recorded. Recording stops once the
+    /// receiving end of the recorder is dropped.
+    fn record(&mut self, event: impl FnOnce() -> SessionEvent) {
+        if let Some(recorder) = &self.session_recorder {
+            if recorder.unbounded_send(event()).is_err() {
+                warn!("[state sync] session recorder dropped, recording stopped");
+                self.session_recorder = None;
             }
         }
-        let sync_request_complete = self.sync_request.as_ref().map_or(false, |sync_req| {
-            sync_req.target.ledger_info().version() == self.known_version
-        });
+    }
 
-        if sync_request_complete {
-            debug!(
-                "[state sync] synchronization to {} is finished",
-                self.known_version
-            );
-            if let Some(sync_request) = self.sync_request.take() {
-                if sync_request.callback.send(Ok(())).is_err() {
-                    error!("[state sync] failed to notify subscriber");
+    /// Feeds `events` recorded by `record_session` through the same handlers they went through,
+    /// and returns the version reached. Queries are skipped and the replies to sync requests are
+    /// dropped. The peers connect through `network_sender`.
+    pub(crate) async fn replay(
+        &mut self,
+        events: Vec<SessionEvent>,
+        network_sender: StateSynchronizerSender,
+    ) -> Result<u64> {
+        self.known_version = self.executor_proxy.get_latest_version().await?;
+        for event in events {
+            match event {
+                SessionEvent::SyncRequest { target, purpose } => {
+                    let (callback, _) = oneshot::channel();
+                    self.request_sync(SyncRequest {
+                        callback,
+                        target,
+                        purpose,
+                        progress: None,
+                    })
+                    .await;
+                }
+                SessionEvent::GetState
+                | SessionEvent::IsCommitted(_)
+                | SessionEvent::WaitForVersion(_)
+                | SessionEvent::GetEpochProof { .. }
+                | SessionEvent::GetLedgerInfo(_)
+                | SessionEvent::GetLastError
+                | SessionEvent::GetSessionMetrics
+                | SessionEvent::AuditState { .. } => {}
+                SessionEvent::UpdateUpstreamPeers(peers) => self.update_upstream_peers(peers),
+                SessionEvent::UpdateMinProtocolVersion(version) => {
+                    self.update_min_protocol_version(version)
+                }
+                SessionEvent::Commit(version) => self.commit(version).await,
+                SessionEvent::Shutdown => self.drain_subscriptions().await,
+                SessionEvent::NewPeer(peer_id, network) => {
+                    self.process_new_peer(peer_id, network, network_sender.clone())
+                        .await
+                }
+                SessionEvent::LostPeer(peer_id) => self.process_lost_peer(peer_id),
+                SessionEvent::Message(peer_id, message) => {
+                    self.process_message(peer_id, message).await
                 }
             }
         }
-        self.peer_manager.remove_requests(version);
-        counters::COMMITTED_VERSION.set(version as i64);
+        Ok(self.known_version)
     }
 
-    fn get_state(&self, callback: oneshot::Sender<u64>) {
-        if callback.send(self.known_version).is_err() {
-            error!("[state sync] failed to fetch internal state");
+    /// Dispatches a request of the state sync client.
+    pub(crate) async fn process_client_message(&mut self, msg: CoordinatorMessage) {
+        self.record(|| match &msg {
+            CoordinatorMessage::Request(request) => SessionEvent::SyncRequest {
+                target: request.target.clone(),
+                purpose: request.purpose,
+            },
+            CoordinatorMessage::GetState(_) => SessionEvent::GetState,
+            CoordinatorMessage::IsCommitted(version, _) => SessionEvent::IsCommitted(*version),
+            CoordinatorMessage::WaitForVersion(version, _) => {
+                SessionEvent::WaitForVersion(*version)
+            }
+            CoordinatorMessage::GetEpochProof(request) => SessionEvent::GetEpochProof {
+                start_epoch: request.start_epoch,
+                end_epoch: request.end_epoch,
+                limit: request.limit,
+            },
+            CoordinatorMessage::GetLedgerInfo(version, _) => SessionEvent::GetLedgerInfo(*version),
+            CoordinatorMessage::GetLastError(_) => SessionEvent::GetLastError,
+            CoordinatorMessage::GetSessionMetrics(_) => SessionEvent::GetSessionMetrics,
+            CoordinatorMessage::AuditState(request) => SessionEvent::AuditState {
+                peer_id: request.peer_id,
+                versions: request.versions.clone(),
+            },
+            CoordinatorMessage::UpdateUpstreamPeers(peers) => {
+                SessionEvent::UpdateUpstreamPeers(peers.clone())
+            }
+            CoordinatorMessage::UpdateMinProtocolVersion(version) => {
+                SessionEvent::UpdateMinProtocolVersion(*version)
+            }
+            CoordinatorMessage::Shutdown(_) => SessionEvent::Shutdown,
+        });
+        match msg {
+            CoordinatorMessage::Request(request) => {
+                self.request_sync(request).await;
+            }
+            CoordinatorMessage::GetState(callback) => {
+                self.get_state(callback);
+            }
+            CoordinatorMessage::IsCommitted(version, callback) => {
+                self.is_committed(version, callback);
+            }
+            CoordinatorMessage::WaitForVersion(version, callback) => {
+                self.wait_for_version(version, callback);
+            }
+            CoordinatorMessage::GetEpochProof(request) => {
+                self.get_epoch_proof(request);
+            }
+            CoordinatorMessage::GetLedgerInfo(version, callback) => {
+                self.get_ledger_info_at(version, callback).await;
+            }
+            CoordinatorMessage::GetLastError(callback) => {
+                self.get_last_error(callback);
+            }
+            CoordinatorMessage::GetSessionMetrics(callback) => {
+                self.get_session_metrics(callback);
+            }
+            CoordinatorMessage::AuditState(request) => {
+                self.audit_state(request).await;
+            }
+            CoordinatorMessage::UpdateUpstreamPeers(peers) => {
+                self.update_upstream_peers(peers);
+            }
+            CoordinatorMessage::UpdateMinProtocolVersion(version) => {
+                self.update_min_protocol_version(version);
+            }
+            CoordinatorMessage::Shutdown(callback) => {
+                self.drain_subscriptions().await;
+                self.save_checkpoint();
+                if callback.send(()).is_err() {
+                    error!("[state sync] failed to send shutdown callback");
+                }
+            }
         }
     }
 
-    /// Get a batch of transactions
-    async fn process_chunk_request(
-        &mut self,
-        peer_id: PeerId,
-        mut request: GetChunkRequest,
-    ) -> Result<()> {
-        if request.timeout > self.config.max_timeout_ms
-            || request.limit > self.config.max_chunk_limit
-        {
-            return Err(format_err!(
-                "[state sync] timeout: {}, chunk limit: {}, but timeout must not exceed {} ms, and chunk limit must not exceed {}",
-                request.timeout,
-                request.limit,
-                self.config.max_timeout_ms,
-                self.config.max_chunk_limit
-            ));
+    /// Dispatches a message received from `peer_id`. Messages of peers speaking a protocol version
+    /// below `min_supported_protocol_version` are answered with a protocol error instead.
+    pub(crate) async fn process_message(&mut self, peer_id: PeerId, message: StateSynchronizerMsg) {
+        if let Some(StateSynchronizerMsg_oneof::ChunkResponse(_)) = &message.message {
+            self.record(|| SessionEvent::Message(peer_id, message.clone()));
         }
-
-        let latest_ledger_info = self.executor_proxy.get_latest_ledger_info().await?;
-        let target = match request
-            .ledger_info_with_sigs
-            .take()
-            .map(TryInto::try_into)
-            .transpose()
-        {
-            Ok(Some(x)) => x,
-            _ => latest_ledger_info.clone(),
-        };
-
-        debug!("[state sync] chunk request: peer_id: {}, known_version: {}, latest_ledger_info: {}, target: {}", peer_id, request.known_version, latest_ledger_info.ledger_info().version(), target.ledger_info().version());
-
-        // if upstream synchronizer doesn't have new data and request timeout is set
-        // add peer request into subscription queue
-        if self.known_version <= request.known_version && request.timeout > 0 {
-            let expiration_time =
-                SystemTime::now().checked_add(Duration::from_millis(request.timeout));
-            if let Some(time) = expiration_time {
-                self.subscriptions
-                    .insert(peer_id, (time, request.known_version, request.limit));
+        self.peer_manager
+            .set_protocol_version(&peer_id, message.protocol_version);
+        if message.protocol_version < self.config.min_supported_protocol_version {
+            warn!(
+                "[state sync] rejecting message from {} speaking protocol version {}, minimum supported is {}",
+                peer_id.short_str(), message.protocol_version, self.config.min_supported_protocol_version
+            );
+            counters::INCOMPATIBLE_PEER_MESSAGES.inc();
+            self.send_protocol_error(peer_id).await;
+            return;
+        }
+        match message.message {
+            Some(StateSynchronizerMsg_oneof::ChunkRequest(request)) => {
+                let known_version = request.known_version;
+                if let Err(err) = self.process_chunk_request(peer_id, request).await {
+                    error!("[state sync] failed to serve chunk request to {} with known version {}: {}", peer_id.short_str(), known_version, err);
+                }
             }
-            Ok(())
-        } else {
-            match self.peer_manager.get_network_sender(&peer_id) {
-                Some(sender) => {
-                    self.deliver_chunk(
-                        peer_id,
-                        request.known_version,
-                        request.limit,
-                        target,
-                        sender,
-                    )
-                    .await
+            Some(StateSynchronizerMsg_oneof::ChunkResponse(response)) => {
+                if let Err(err) = self.process_chunk_response(&peer_id, response).await {
+                    error!(
+                        "[state sync] failed to process chunk response from {}: {}",
+                        peer_id.short_str(),
+                        err
+                    );
+                    counters::APPLY_CHUNK_FAILURE
+                        .with_label_values(&[&*peer_id.to_string()])
+                        .inc();
                 }
-                None => Err(format_err!(
-                    "[state sync] failed to find network for peer {}",
-                    peer_id
-                )),
             }
+            Some(StateSynchronizerMsg_oneof::LedgerInfoSummary(summary)) => {
+                self.process_ledger_info_summary(peer_id, summary).await;
+            }
+            Some(StateSynchronizerMsg_oneof::SubscriptionClosed(_)) => {
+                self.process_subscription_closed(peer_id).await;
+            }
+            Some(StateSynchronizerMsg_oneof::RootHashesRequest(request)) => {
+                self.process_root_hashes_request(peer_id, request).await;
+            }
+            Some(StateSynchronizerMsg_oneof::RootHashesResponse(response)) => {
+                self.process_root_hashes_response(peer_id, response).await;
+            }
+            Some(StateSynchronizerMsg_oneof::ProtocolError(error)) => {
+                warn!(
+                    "[state sync] peer {} requires protocol version {}, but this node speaks {}",
+                    peer_id.short_str(),
+                    error.min_supported_protocol_version,
+                    PROTOCOL_VERSION
+                );
+            }
+            None => error!("[state sync] empty message from {}", peer_id.short_str()),
         }
     }
 
-    async fn deliver_chunk(
-        &self,
-        peer_id: PeerId,
-        known_version: u64,
-        limit: u64,
-        target: LedgerInfo,
-        mut network_sender: StateSynchronizerSender,
-    ) -> Result<()> {
-        let response = self
-            .executor_proxy
-            .get_chunk(known_version, limit, target)
-            .await?;
-        let msg = StateSynchronizerMsg {
-            message: Some(StateSynchronizerMsg_oneof::ChunkResponse(response)),
+    async fn send_protocol_error(&self, peer_id: PeerId) {
+        let mut sender = match self.peer_manager.get_network_sender(&peer_id) {
+            Some(sender) => sender,
+            None => return,
         };
-        if network_sender.send_to(peer_id, msg).await.is_err() {
+        let error = ProtocolError {
+            min_supported_protocol_version: self.config.min_supported_protocol_version,
+        };
+        let msg = new_message(StateSynchronizerMsg_oneof::ProtocolError(error));
+        if sender.send_to(peer_id, msg).await.is_err() {
             error!("[state sync] failed to send p2p message");
         }
-        Ok(())
     }
 
-    /// processes batch of transactions downloaded from peer
-    /// executes transactions, updates progress state, calls callback if some sync is finished
-    async fn process_chunk_response(
-        &mut self,
-        peer_id: &PeerId,
+    /// Asks the peer of `request` for its transaction info hashes at the requested versions. The
+    /// report is sent on the callback once they are received and compared with the local ones,
+    /// see `process_root_hashes_response`.
+    pub(crate) async fn audit_state(&mut self, request: AuditRequest) {
+        let AuditRequest {
+            peer_id,
+            versions,
+            callback,
+        } = request;
+        let result = if versions.is_empty() || versions.len() > MAX_AUDIT_VERSIONS {
+            Err(format_err!(
+                "an audit covers 1 to {} versions, {} requested",
+                MAX_AUDIT_VERSIONS,
+                versions.len()
+            ))
+        } else if self.pending_audits.contains_key(&peer_id) {
+            Err(format_err!(
+                "an audit of peer {} is already in flight",
+                peer_id.short_str()
+            ))
+        } else {
+            self.send_root_hashes_request(peer_id, versions.clone())
+                .await
+        };
+        if let Err(err) = result {
+            send_audit_report(callback, Err(err));
+            return;
+        }
+        let deadline = self.clock.now() + AUDIT_TIMEOUT;
+        self.pending_audits.insert(
+            peer_id,
+            PendingAudit {
+                versions,
+                deadline,
+                reporter: AuditReporter::Client(callback),
+            },
+        );
+    }
+
+    /// Samples the chunk just applied from `chunk_peer`, ending at `version` and starting after
+    /// `previous_version`, for a check against the verification peer: every
+    /// `verification_interval`-th such chunk, the verification peer is asked for its transaction
+    /// info hashes over the range of the chunk, and they are compared with the local ones once
+    /// received, see `process_root_hashes_response`.
+    async fn sample_verification(
+        &mut self,
+        chunk_peer: PeerId,
+        previous_version: u64,
+        version: u64,
+    ) {
+        let verification_peer = match self.verification_peer {
+            Some(verification_peer) if verification_peer != chunk_peer => verification_peer,
+            _ => return,
+        };
+        self.chunks_since_verification += 1;
+        if self.chunks_since_verification < self.config.verification_interval
+            || self.pending_audits.contains_key(&verification_peer)
+        {
+            return;
+        }
+        self.chunks_since_verification = 0;
+        let start_version = std::cmp::max(
+            previous_version + 1,
+            (version + 1).saturating_sub(MAX_AUDIT_VERSIONS as u64),
+        );
+        let versions: Vec<_> = (start_version..=version).collect();
+        if let Err(err) = self
+            .send_root_hashes_request(verification_peer, versions.clone())
+            .await
+        {
+            warn!(
+                "[state sync] failed to check versions {} to {} against the verification peer: {}",
+                start_version, version, err
+            );
+            return;
+        }
+        let deadline = self.clock.now() + AUDIT_TIMEOUT;
+        self.pending_audits.insert(
+            verification_peer,
+            PendingAudit {
+                versions,
+                deadline,
+                reporter: AuditReporter::Verification { chunk_peer },
+            },
+        );
+    }
+
+    async fn send_root_hashes_request(&self, peer_id: PeerId, versions: Vec<u64>) -> Result<()> {
+        let mut sender = self
+            .peer_manager
+            .get_network_sender(&peer_id)
+            .ok_or_else(|| format_err!("peer {} is not connected", peer_id.short_str()))?;
+        let request = GetRootHashesRequest { versions };
+        let msg = new_message(StateSynchronizerMsg_oneof::RootHashesRequest(request));
+        sender.send_to(peer_id, msg).await?;
+        counters::STATE_AUDIT_REQUESTS
+            .with_label_values(&["sent"])
+            .inc();
+        Ok(())
+    }
+
+    /// Answers the root hash request of a peer auditing this node with the transaction info
+    /// hashes at the requested versions. Only reads storage, and drops the requests arriving
+    /// sooner than `min_audit_request_interval_ms` after the previous one of the peer.
+    async fn process_root_hashes_request(
+        &mut self,
+        peer_id: PeerId,
+        mut request: GetRootHashesRequest,
+    ) {
+        let min_interval = Duration::from_millis(self.config.min_audit_request_interval_ms);
+        if !self
+            .peer_manager
+            .process_audit_request(&peer_id, min_interval)
+        {
+            counters::STATE_AUDIT_REQUESTS
+                .with_label_values(&["dropped"])
+                .inc();
+            return;
+        }
+        request.versions.truncate(MAX_AUDIT_VERSIONS);
+        let mut root_hashes = vec![];
+        for version in request.versions {
+            let transaction_info_hash =
+                match self.executor_proxy.get_transaction_info_hash(version).await {
+                    Ok(hash) => hash.map_or_else(Vec::new, |hash| hash.to_vec()),
+                    Err(err) => {
+                        error!(
+                            "[state sync] failed to read the transaction info at version {}: {}",
+                            version, err
+                        );
+                        vec![]
+                    }
+                };
+            root_hashes.push(VersionRootHash {
+                version,
+                transaction_info_hash,
+            });
+        }
+        let mut sender = match self.peer_manager.get_network_sender(&peer_id) {
+            Some(sender) => sender,
+            None => return,
+        };
+        let response = GetRootHashesResponse { root_hashes };
+        let msg = new_message(StateSynchronizerMsg_oneof::RootHashesResponse(response));
+        if sender.send_to(peer_id, msg).await.is_err() {
+            error!("[state sync] failed to send p2p message");
+        }
+        counters::STATE_AUDIT_REQUESTS
+            .with_label_values(&["served"])
+            .inc();
+    }
+
+    /// Compares the root hashes sent by `peer_id` with the local ones, and completes the audit
+    /// waiting for them. Every mismatch is logged as critical, and a mismatch with the
+    /// verification peer is also reported as the last error of the sync.
+    async fn process_root_hashes_response(
+        &mut self,
+        peer_id: PeerId,
+        response: GetRootHashesResponse,
+    ) {
+        let audit = match self.pending_audits.remove(&peer_id) {
+            Some(audit) => audit,
+            None => {
+                warn!(
+                    "[state sync] unexpected root hashes from {}",
+                    peer_id.short_str()
+                );
+                return;
+            }
+        };
+        let peer_hashes: HashMap<_, _> = response
+            .root_hashes
+            .into_iter()
+            .map(|root_hash| {
+                (
+                    root_hash.version,
+                    HashValue::from_slice(&root_hash.transaction_info_hash).ok(),
+                )
+            })
+            .collect();
+        let mut report = vec![];
+        for version in audit.versions {
+            let local_hash = match self.executor_proxy.get_transaction_info_hash(version).await {
+                Ok(hash) => hash,
+                Err(err) => {
+                    report_audit(audit.reporter, peer_id, Err(err));
+                    return;
+                }
+            };
+            let version_audit = VersionAudit {
+                version,
+                local_hash,
+                peer_hash: peer_hashes.get(&version).cloned().unwrap_or(None),
+            };
+            if version_audit.is_mismatch() {
+                crit!(
+                    "[state sync] state divergence with {} at version {}: local transaction info {:?}, peer transaction info {:?}",
+                    peer_id.short_str(), version, version_audit.local_hash, version_audit.peer_hash
+                );
+                counters::STATE_AUDIT_MISMATCHES.inc();
+            }
+            report.push(version_audit);
+        }
+        match audit.reporter {
+            AuditReporter::Verification { chunk_peer } => {
+                let mismatches: Vec<_> =
+                    report.iter().filter(|audit| audit.is_mismatch()).collect();
+                counters::VERIFICATION_DISCREPANCIES.inc_by(mismatches.len() as i64);
+                if let Some(mismatch) = mismatches.first() {
+                    self.last_chunk_failure = Some(ChunkFailure::Discrepancy {
+                        peer_id: chunk_peer,
+                        verification_peer_id: peer_id,
+                        version: mismatch.version,
+                    });
+                }
+            }
+            reporter => report_audit(reporter, peer_id, Ok(report)),
+        }
+    }
+
+    /// Seeds the versions advertised by the upstream peers and the highest version known to sync
+    /// to with the hints of the checkpoint left by the previous run, if any. Checkpoints that
+    /// can't be read, are older than `max_checkpoint_age_ms`, or are ahead of the local storage,
+    /// e.g. because it was wiped since, are ignored.
+    pub(crate) fn load_checkpoint(&mut self) {
+        let path = match &self.config.checkpoint_file {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        if !path.exists() {
+            debug!("[state sync] no checkpoint found at {:?}", path);
+            return;
+        }
+        let checkpoint = match SyncCheckpoint::read(&path) {
+            Ok(checkpoint) => checkpoint,
+            Err(err) => {
+                warn!(
+                    "[state sync] ignoring corrupt checkpoint {:?}: {}",
+                    path, err
+                );
+                return;
+            }
+        };
+        let age = checkpoint.age(self.clock.now());
+        if age > Duration::from_millis(self.config.max_checkpoint_age_ms) {
+            info!(
+                "[state sync] ignoring checkpoint {:?} taken {:?} ago",
+                path, age
+            );
+            return;
+        }
+        if checkpoint.known_version > self.known_version {
+            warn!(
+                "[state sync] ignoring checkpoint {:?} at version {}, ahead of storage at version {}",
+                path, checkpoint.known_version, self.known_version
+            );
+            return;
+        }
+        for (peer_id, version) in &checkpoint.advertised_versions {
+            if self.peer_manager.is_upstream(peer_id) {
+                self.peer_manager.seed_advertised_version(peer_id, *version);
+                self.target_version = std::cmp::max(self.target_version, *version);
+            }
+        }
+        info!(
+            "[state sync] resuming from checkpoint {:?}, highest advertised version {}",
+            path, self.target_version
+        );
+    }
+
+    /// Writes a checkpoint to `checkpoint_file` if `checkpoint_interval_ms` passed since the
+    /// previous one.
+    fn checkpoint_if_due(&mut self) {
+        let interval = Duration::from_millis(self.config.checkpoint_interval_ms);
+        let is_due = self
+            .clock
+            .now()
+            .duration_since(self.last_checkpoint_time)
+            .map_or(false, |elapsed| elapsed >= interval);
+        if is_due {
+            self.save_checkpoint();
+        }
+    }
+
+    /// Writes the known version and the versions advertised by the upstream peers to
+    /// `checkpoint_file`, if set.
+    pub(crate) fn save_checkpoint(&mut self) {
+        let path = match &self.config.checkpoint_file {
+            Some(path) => path,
+            None => return,
+        };
+        let now = self.clock.now();
+        self.last_checkpoint_time = now;
+        let checkpoint = SyncCheckpoint::new(
+            self.known_version,
+            self.peer_manager.upstream_advertised_versions(),
+            now,
+        );
+        if let Err(err) = checkpoint.write(path) {
+            warn!(
+                "[state sync] failed to write checkpoint {:?}: {}",
+                path, err
+            );
+        }
+    }
+
+    /// Fails the audits whose peer did not answer within `AUDIT_TIMEOUT`.
+    fn expire_audits(&mut self) {
+        let now = self.clock.now();
+        let expired: Vec<_> = self
+            .pending_audits
+            .iter()
+            .filter(|(_, audit)| audit.deadline <= now)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired {
+            if let Some(audit) = self.pending_audits.remove(&peer_id) {
+                report_audit(
+                    audit.reporter,
+                    peer_id,
+                    Err(format_err!(
+                        "peer {} did not answer the audit in time",
+                        peer_id.short_str()
+                    )),
+                );
+            }
+        }
+    }
+
+    /// Sends a summary of the latest ledger info to every connected peer, once
+    /// `ledger_info_gossip_interval_ms` passed since the previous one. Nothing is gossiped while
+    /// the latest ledger info is beyond `serve_max_version`, as chunks up to it are not served.
+    pub(crate) async fn gossip_ledger_info_summary(&mut self) {
+        let interval = match self.config.ledger_info_gossip_interval_ms {
+            Some(interval_ms) => Duration::from_millis(interval_ms),
+            None => return,
+        };
+        let now = self.clock.now();
+        if now
+            .duration_since(self.last_gossip_time)
+            .map_or(true, |elapsed| elapsed < interval)
+        {
+            return;
+        }
+        self.last_gossip_time = now;
+
+        let ledger_info = match self.executor_proxy.get_latest_ledger_info().await {
+            Ok(ledger_info) => ledger_info,
+            Err(err) => {
+                error!(
+                    "[state sync] failed to read the ledger info to gossip: {}",
+                    err
+                );
+                return;
+            }
+        };
+        let ledger_info = ledger_info.ledger_info();
+        if ledger_info.version() > self.max_served_version() {
+            return;
+        }
+        let summary = LedgerInfoSummary {
+            ledger_info_hash: ledger_info.hash().to_vec(),
+            version: ledger_info.version(),
+            epoch: ledger_info.epoch(),
+        };
+        for (peer_id, mut sender) in self.peer_manager.connected_peers() {
+            let msg = new_message(StateSynchronizerMsg_oneof::LedgerInfoSummary(
+                summary.clone(),
+            ));
+            if sender.send_to(peer_id, msg).await.is_err() {
+                error!("[state sync] failed to send p2p message");
+            }
+        }
+        counters::LEDGER_INFO_SUMMARIES
+            .with_label_values(&["sent"])
+            .inc();
+    }
+
+    /// Records the version advertised by `peer_id`. If it is ahead of the known version and the
+    /// peer can be requested from, the next chunk is requested from it right away, rather than
+    /// waiting for the current request to be answered or to time out.
+    async fn process_ledger_info_summary(&mut self, peer_id: PeerId, summary: LedgerInfoSummary) {
+        let min_interval = Duration::from_millis(self.config.min_ledger_info_summary_interval_ms);
+        if !self
+            .peer_manager
+            .process_ledger_info_summary(&peer_id, summary.version, min_interval)
+        {
+            counters::LEDGER_INFO_SUMMARIES
+                .with_label_values(&["dropped"])
+                .inc();
+            return;
+        }
+        counters::LEDGER_INFO_SUMMARIES
+            .with_label_values(&["received"])
+            .inc();
+
+        let can_request = self.role == RoleType::FullNode || self.sync_request.is_some();
+        if !can_request
+            || summary.version <= self.known_version
+            || !self.peer_manager.is_active(&peer_id)
+        {
+            return;
+        }
+        let version = self.known_version + 1;
+        let is_requested_from_peer = self
+            .peer_manager
+            .get_request(version)
+            .map_or(false, |(requested_peer_id, _)| requested_peer_id == peer_id);
+        if is_requested_from_peer {
+            return;
+        }
+        if let Some(sender) = self.peer_manager.get_network_sender(&peer_id) {
+            debug!(
+                "[state sync] {} advertised version {}, known version: {}",
+                peer_id.short_str(),
+                summary.version,
+                self.known_version
+            );
+            self.send_chunk_request(peer_id, sender, 0).await;
+        }
+    }
+
+    /// Starts using `peer_id`, connected through the network at index `network`.
+    pub(crate) async fn process_new_peer(
+        &mut self,
+        peer_id: PeerId,
+        network: usize,
+        sender: StateSynchronizerSender,
+    ) {
+        debug!("[state sync] new peer {}", peer_id.short_str());
+        self.record(|| SessionEvent::NewPeer(peer_id, network));
+        self.peer_manager.enable_peer(peer_id, network, sender);
+        self.process_usable_peers_changes();
+        self.check_progress().await;
+    }
+
+    /// Stops using `peer_id`, dropping the chunk deliveries still in flight to it.
+    pub(crate) fn process_lost_peer(&mut self, peer_id: PeerId) {
+        debug!("[state sync] lost peer {}", peer_id.short_str());
+        self.record(|| SessionEvent::LostPeer(peer_id));
+        self.peer_manager.disable_peer(&peer_id);
+        self.process_usable_peers_changes();
+        self.deliveries.remove(&peer_id);
+        if let Some(audit) = self.pending_audits.remove(&peer_id) {
+            report_audit(
+                audit.reporter,
+                peer_id,
+                Err(format_err!(
+                    "lost peer {} before it answered the audit",
+                    peer_id.short_str()
+                )),
+            );
+        }
+    }
+
+    pub(crate) async fn request_sync(&mut self, request: SyncRequest) {
+        if let Some(pending_request) = self.sync_request.as_mut() {
+            if pending_request.target.ledger_info().hash() == request.target.ledger_info().hash() {
+                // e.g. several proposals carrying the same commit, the pending sync goes on
+                // undisturbed and completes the duplicate along with itself
+                debug!(
+                    "[state sync] sync to version {} already pending, coalescing the request",
+                    request.target.ledger_info().version()
+                );
+                counters::COALESCED_SYNC_REQUESTS.inc();
+                if request.purpose == SyncPurpose::Consensus {
+                    pending_request.purpose = SyncPurpose::Consensus;
+                }
+                self.coalesced_sync_requests.push(request);
+                return;
+            }
+        }
+        self.known_version = self
+            .executor_proxy
+            .get_latest_version()
+            .await
+            .expect("[state sync] failed to fetch latest version from storage");
+        self.sync_request_start = (self.clock.now(), self.known_version);
+        self.session_metrics = SyncSessionMetrics::default();
+        self.last_chunk_failure = None;
+        let target_version = request.target.ledger_info().version();
+        counters::TARGET_VERSION.set(target_version as i64);
+        debug!(
+            "[state sync] sync requested. Known version: {}, requested_version: {}",
+            self.known_version, target_version
+        );
+
+        if target_version <= self.known_version {
+            debug!("[state sync] sync contains only empty blocks");
+            self.store_transactions(
+                TransactionListWithProof::new_empty(),
+                request.target.clone(),
+            )
+            .await
+            .expect("[state sync] failed to execute empty blocks");
+            let completion_info = self.sync_completion_info(request.target.clone());
+            if request.callback.send(Ok(completion_info)).is_err() {
+                error!("[state sync] coordinator failed to notify subscriber");
+            }
+            return;
+        }
+
+        if let Some(pending_request) = &self.sync_request {
+            let error = SyncError::TargetConflict {
+                pending_version: pending_request.target.ledger_info().version(),
+                requested_version: target_version,
+            };
+            self.finish_sync_request(Err(error));
+        }
+
+        let peers = request.target.signatures().keys().copied().collect();
+        self.peer_manager.set_peers(peers);
+        self.reset_chunk_limit();
+        // a request made while no peer is usable waits for one, only losing the last peer fails
+        // the pending request
+        self.process_usable_peers_changes();
+        self.sync_request = Some(request);
+        self.request_next_chunk(0).await;
+    }
+
+    /// Handles the changes of whether any upstream peer can be picked for requests. Once none is
+    /// left, the pending sync request fails right away instead of waiting for a peer that may
+    /// never come back, e.g. for consensus to retry with another target.
+    fn process_usable_peers_changes(&mut self) {
+        while let Ok(Some(has_usable_peers)) = self.usable_peers_changes.try_next() {
+            if has_usable_peers {
+                info!("[state sync] upstream peers available again");
+                counters::NO_SYNC_PEERS.set(0);
+            } else {
+                crit!("[state sync] no upstream peer left to sync from");
+                counters::NO_SYNC_PEERS.set(1);
+                if self.sync_request.is_some() {
+                    self.finish_sync_request(Err(SyncError::NoPeersAvailable));
+                }
+            }
+        }
+    }
+
+    pub(crate) async fn commit(&mut self, version: u64) {
+        let commit_time = self.clock.now();
+        debug!(
+            "[state sync] commit. Known version: {}, version: {}",
+            self.known_version, version
+        );
+        let is_update = version > self.known_version;
+        self.known_version = std::cmp::max(version, self.known_version);
+        if is_update {
+            self.record_commit_interval(commit_time);
+            if let Some(last_request_tst) =
+                self.peer_manager.get_request_time(self.known_version + 1)
+            {
+                if let Ok(duration) = self.clock.now().duration_since(last_request_tst) {
+                    counters::SYNC_PROGRESS_DURATION.observe_duration(duration);
+                }
+            }
+            if let Err(err) = self.check_subscriptions(commit_time).await {
+                error!("[state sync] failed to check subscriptions: {}", err);
+            }
+            self.notify_version_waiters();
+            if let Some(listener) = &self.commit_listener {
+                if listener.unbounded_send(self.known_version).is_err() {
+                    debug!("[state sync] commit listener dropped");
+                    self.commit_listener = None;
+                }
+            }
+            self.check_initial_sync_complete();
+        }
+        // commit notifications are coalesced, so the target version may have been skipped
+        let sync_request_complete = self.sync_request.as_ref().map_or(false, |sync_req| {
+            sync_req.target.ledger_info().version() <= self.known_version
+        });
+
+        if sync_request_complete {
+            debug!(
+                "[state sync] synchronization to {} is finished",
+                self.known_version
+            );
+            if let Some(sync_request) = &self.sync_request {
+                let completion_info = self.sync_completion_info(sync_request.target.clone());
+                self.finish_sync_request(Ok(completion_info));
+            }
+        }
+        self.peer_manager.remove_requests(version);
+        counters::COMMITTED_VERSION.set(version as i64);
+    }
+
+    /// Ends the pending sync request, sending `outcome` to its requester and to the requesters of
+    /// the same target coalesced into it.
+    fn finish_sync_request(&mut self, outcome: std::result::Result<SyncCompletionInfo, SyncError>) {
+        let requests = self
+            .sync_request
+            .take()
+            .into_iter()
+            .chain(self.coalesced_sync_requests.drain(..));
+        for request in requests {
+            if request
+                .callback
+                .send(outcome.clone().map_err(Into::into))
+                .is_err()
+            {
+                error!("[state sync] coordinator failed to notify subscriber");
+            }
+        }
+    }
+
+    /// Describes the completion of the current sync request at `reached_li`.
+    fn sync_completion_info(&self, reached_li: LedgerInfoWithSignatures) -> SyncCompletionInfo {
+        let (_, start_version) = self.sync_request_start;
+        let metrics = self.session_metrics();
+        SyncCompletionInfo {
+            reached_li,
+            versions_applied: self.known_version.saturating_sub(start_version),
+            duration: metrics.elapsed,
+            metrics,
+        }
+    }
+
+    /// Metrics of the current sync session, up to now.
+    fn session_metrics(&self) -> SyncSessionMetrics {
+        let (start_time, _) = self.sync_request_start;
+        SyncSessionMetrics {
+            elapsed: self
+                .clock
+                .now()
+                .duration_since(start_time)
+                .unwrap_or_else(|_| Duration::from_secs(0)),
+            ..self.session_metrics
+        }
+    }
+
+    pub(crate) fn get_state(&self, callback: oneshot::Sender<u64>) {
+        if callback.send(self.known_version).is_err() {
+            error!("[state sync] failed to fetch internal state");
+        }
+    }
+
+    pub(crate) fn get_last_error(&self, callback: oneshot::Sender<Option<ChunkFailure>>) {
+        if callback.send(self.last_chunk_failure.clone()).is_err() {
+            error!("[state sync] failed to send last chunk failure");
+        }
+    }
+
+    pub(crate) fn get_session_metrics(
+        &self,
+        callback: oneshot::Sender<Option<SyncSessionMetrics>>,
+    ) {
+        let metrics = self.sync_request.as_ref().map(|_| self.session_metrics());
+        if callback.send(metrics).is_err() {
+            error!("[state sync] failed to send sync session metrics");
+        }
+    }
+
+    pub(crate) fn is_committed(&self, version: u64, callback: oneshot::Sender<bool>) {
+        if callback.send(self.known_version >= version).is_err() {
+            error!(
+                "[state sync] failed to send back whether {} is committed",
+                version
+            );
+        }
+    }
+
+    /// Notifies `callback` once `version` is committed, right away if it already is.
+    pub(crate) fn wait_for_version(&mut self, version: u64, callback: oneshot::Sender<()>) {
+        if self.known_version >= version {
+            if callback.send(()).is_err() {
+                error!(
+                    "[state sync] failed to notify waiter of version {}",
+                    version
+                );
+            }
+        } else {
+            self.version_waiters.push((version, callback));
+        }
+    }
+
+    /// Notifies the waiters of versions committed by now, and forgets those no longer waiting.
+    fn notify_version_waiters(&mut self) {
+        let known_version = self.known_version;
+        let (reached, waiting): (Vec<_>, Vec<_>) = self
+            .version_waiters
+            .drain(..)
+            .filter(|(_, callback)| !callback.is_canceled())
+            .partition(|(version, _)| *version <= known_version);
+        self.version_waiters = waiting;
+        for (version, callback) in reached {
+            if callback.send(()).is_err() {
+                error!(
+                    "[state sync] failed to notify waiter of version {}",
+                    version
+                );
+            }
+        }
+    }
+
+    /// Replaces the set of upstream peers. Peers no longer listed are only demoted: their
+    /// connections are kept and the requests already sent to them can still be answered.
+    pub(crate) fn update_upstream_peers(&mut self, peers: Vec<PeerId>) {
+        info!("[state sync] updating upstream peers to {:?}", peers);
+        self.config.upstream_peers.upstream_peers = peers.iter().map(PeerId::to_string).collect();
+        self.peer_manager.set_peers(peers);
+        self.process_usable_peers_changes();
+    }
+
+    /// Replaces the minimum protocol version peers must speak. Peers excluded by the previous
+    /// minimum only are picked for requests again.
+    pub(crate) fn update_min_protocol_version(&mut self, version: u64) {
+        info!(
+            "[state sync] updating min supported protocol version to {}",
+            version
+        );
+        self.config.min_supported_protocol_version = version;
+        self.peer_manager.set_min_protocol_version(version);
+        self.process_usable_peers_changes();
+    }
+
+    #[cfg(test)]
+    pub(crate) fn peer_manager(&self) -> &PeerManager {
+        &self.peer_manager
+    }
+
+    #[cfg(test)]
+    pub(crate) fn num_validators(&self) -> usize {
+        self.num_validators
+    }
+
+    /// Get a batch of transactions
+    pub(crate) async fn process_chunk_request(
+        &mut self,
+        peer_id: PeerId,
+        mut request: GetChunkRequest,
+    ) -> Result<()> {
+        if !self.serve_allowlist.is_empty() && !self.serve_allowlist.contains(&peer_id) {
+            counters::NON_ALLOWLISTED_CHUNK_REQUESTS.inc();
+            return Err(format_err!(
+                "[state sync] peer {} is not on the serve allowlist",
+                peer_id.short_str()
+            ));
+        }
+        if request.timeout > self.config.max_timeout_ms
+            || request.limit > self.config.max_chunk_limit
+        {
+            return Err(format_err!(
+                "[state sync] timeout: {}, chunk limit: {}, but timeout must not exceed {} ms, and chunk limit must not exceed {}",
+                request.timeout,
+                request.limit,
+                self.config.max_timeout_ms,
+                self.config.max_chunk_limit
+            ));
+        }
+
+        let latest_ledger_info = self.executor_proxy.get_latest_ledger_info().await?;
+        let target = match request
+            .ledger_info_with_sigs
+            .take()
+            .map(TryInto::try_into)
+            .transpose()
+        {
+            Ok(Some(x)) => x,
+            _ => latest_ledger_info.clone(),
+        };
+
+        debug!("[state sync] chunk request: peer_id: {}, known_version: {}, latest_ledger_info: {}, target: {}", peer_id.short_str(), request.known_version, latest_ledger_info.ledger_info().version(), target.ledger_info().version());
+
+        // if upstream synchronizer doesn't have new data (up to the pinned version, if any) and
+        // request timeout is set add peer request into subscription queue
+        if self.max_served_version() <= request.known_version && request.timeout > 0 {
+            let expiration_time = self
+                .clock
+                .now()
+                .checked_add(Duration::from_millis(request.timeout));
+            if let Some(time) = expiration_time {
+                self.subscriptions
+                    .insert(peer_id, (time, request.known_version, request.limit));
+            }
+            Ok(())
+        } else {
+            match self.peer_manager.get_network_sender(&peer_id) {
+                Some(sender) => {
+                    self.deliver_chunk(
+                        peer_id,
+                        request.known_version,
+                        request.limit,
+                        target,
+                        sender,
+                    )
+                    .await
+                }
+                None => Err(format_err!(
+                    "[state sync] failed to find network for peer {}",
+                    peer_id.short_str()
+                )),
+            }
+        }
+    }
+
+    /// The latest version served to other peers: the known version, unless a lower one is pinned
+    /// by `serve_max_version`.
+    fn max_served_version(&self) -> u64 {
+        self.config
+            .serve_max_version
+            .map_or(self.known_version, |pin| {
+                std::cmp::min(pin, self.known_version)
+            })
+    }
+
+    async fn deliver_chunk(
+        &self,
+        peer_id: PeerId,
+        known_version: u64,
+        limit: u64,
+        target: LedgerInfo,
+        network_sender: StateSynchronizerSender,
+    ) -> Result<()> {
+        let chunk = self
+            .get_chunk_to_serve(known_version, limit, target)
+            .await?;
+        send_chunk(peer_id, chunk, network_sender).await
+    }
+
+    /// Reads the chunk of at most `limit` transactions after `known_version` to serve toward
+    /// `target`, or toward the latest ledger info at or before the pinned version if `target` is
+    /// beyond it. Only the lookup of that ledger info is awaited, the chunk is read when the
+    /// returned future is polled.
+    async fn get_chunk_to_serve(
+        &self,
+        known_version: u64,
+        limit: u64,
+        target: LedgerInfo,
+    ) -> Result<Pin<Box<dyn Future<Output = Result<GetChunkResponse>> + Send>>> {
+        let (limit, target) = match self.config.serve_max_version {
+            Some(pin) if target.ledger_info().version() > pin => {
+                // ledger infos are only stored for some versions, the pin may fall in between
+                let target = self
+                    .executor_proxy
+                    .get_latest_ledger_info_at_or_before(pin)
+                    .await?;
+                let target_version = target.ledger_info().version();
+                ensure!(
+                    known_version < target_version,
+                    "[state sync] nothing to serve beyond version {}, pinned at {}",
+                    target_version,
+                    pin
+                );
+                (std::cmp::min(limit, target_version - known_version), target)
+            }
+            _ => (limit, target),
+        };
+        Ok(self.executor_proxy.get_chunk(known_version, limit, target))
+    }
+
+    /// Delivers `chunk` to the long-poll subscriber `peer_id` on the bounded delivery executor,
+    /// after the deliveries already in flight to it. The delivery is dropped if it takes longer
+    /// than `subscription_delivery_timeout_ms` or if the peer disconnects meanwhile.
+    fn spawn_delivery(
+        &mut self,
+        peer_id: PeerId,
+        chunk: Pin<Box<dyn Future<Output = Result<GetChunkResponse>> + Send>>,
+        network_sender: StateSynchronizerSender,
+        commit_time: SystemTime,
+    ) {
+        let peer_deliveries = self
+            .deliveries
+            .entry(peer_id)
+            .or_insert_with(PeerDeliveries::new);
+        let (done_sender, done_receiver) = oneshot::channel::<()>();
+        let previous_delivery =
+            std::mem::replace(&mut peer_deliveries.last_delivery, done_receiver);
+        let disconnected = peer_deliveries.disconnected.clone();
+        let clock = Arc::clone(&self.clock);
+        let deliver = async move {
+            let res = send_chunk(peer_id, chunk, network_sender).await;
+            if res.is_ok() {
+                if let Ok(latency) = clock.now().duration_since(commit_time) {
+                    counters::SUBSCRIPTION_SERVE_LATENCY.observe(latency.as_secs_f64());
+                }
+            }
+            res
+        };
+        let deliver = Timeout::new(
+            deliver,
+            Duration::from_millis(self.config.subscription_delivery_timeout_ms),
+        );
+        let delivery_executor = self.delivery_executor.clone();
+        self.executor.spawn(async move {
+            // a delivery waits for the previous one to the same peer without holding a slot of the
+            // delivery executor
+            let _ = previous_delivery.await;
+            delivery_executor
+                .spawn(async move {
+                    match future::select(deliver.boxed(), disconnected).await {
+                        Either::Left((Ok(Ok(())), _)) => {}
+                        Either::Left((Ok(Err(err)), _)) => {
+                            error!("[state sync] failed to notify subscriber {}", err);
+                        }
+                        Either::Left((Err(_), _)) => {
+                            warn!(
+                                "[state sync] chunk delivery to {} timed out",
+                                peer_id.short_str()
+                            );
+                            counters::SUBSCRIPTION_DELIVERIES_DROPPED
+                                .with_label_values(&["timeout"])
+                                .inc();
+                        }
+                        Either::Right(_) => {
+                            debug!(
+                                "[state sync] {} disconnected before its chunk was delivered",
+                                peer_id.short_str()
+                            );
+                            counters::SUBSCRIPTION_DELIVERIES_DROPPED
+                                .with_label_values(&["disconnected"])
+                                .inc();
+                        }
+                    }
+                    drop(done_sender);
+                })
+                .await;
+        });
+    }
+
+    /// processes batch of transactions downloaded from peer
+    /// executes transactions, updates progress state, calls callback if some sync is finished
+    pub(crate) async fn process_chunk_response(
+        &mut self,
+        peer_id: &PeerId,
         response: GetChunkResponse,
     ) -> Result<()> {
         counters::RESPONSES_RECEIVED
             .with_label_values(&[&*peer_id.to_string()])
             .inc();
+        self.session_metrics.bytes_transferred += response.encoded_len() as u64;
         let txn_list_with_proof: TransactionListWithProof = response
             .txn_list_with_proof
             .ok_or_else(|| format_err!("Missing txn_list_with_proof"))?
             .try_into()?;
+        let target: LedgerInfo = response
+            .ledger_info_with_sigs
+            .ok_or_else(|| format_err!("Missing ledger_info_with_sigs"))?
+            .try_into()?;
 
-        if let Some(version) = txn_list_with_proof.first_transaction_version {
-            let has_requested = self.peer_manager.has_requested(version, *peer_id);
-            // node has received a response from peer, so remove peer entry from requests map
-            self.peer_manager.process_response(version, *peer_id);
-
-            if version != self.known_version + 1 {
-                // version was not requested, or version was requested from a different peer,
-                // so need to penalize peer for maliciously sending chunk
-                if has_requested {
-                    self.peer_manager
-                        .update_score(&peer_id, PeerScoreUpdateType::InvalidChunk)
-                }
-                return Err(format_err!(
-                    "[state sync] non sequential chunk. Known version: {}, received: {}",
-                    self.known_version,
-                    version,
+        if txn_list_with_proof.is_empty() {
+            // the answer of a peer without data beyond the known version, e.g. to a long poll
+            let target_version = target.ledger_info().version();
+            if target_version != self.known_version {
+                return Err(self.reject_malformed_response(
+                    peer_id,
+                    format!(
+                        "empty transaction list toward version {}, known version {}",
+                        target_version, self.known_version
+                    ),
                 ));
             }
+            self.peer_manager
+                .process_response(self.known_version + 1, *peer_id);
+            self.peer_manager
+                .record_advertised_version(peer_id, target_version);
+            self.frontier_known = true;
+            self.check_initial_sync_complete();
+            return Ok(());
+        }
+        let version = match txn_list_with_proof.first_transaction_version {
+            Some(version) => version,
+            None => {
+                return Err(self.reject_malformed_response(
+                    peer_id,
+                    "non-empty transaction list without first transaction version".to_string(),
+                ));
+            }
+        };
+        if self.peer_manager.is_duplicate_response(version, *peer_id) {
+            debug!(
+                "[state sync] ignoring response from {} for version {}, the hedged request was already answered",
+                peer_id.short_str(), version
+            );
+            return Ok(());
+        }
+        let has_requested = self.peer_manager.has_requested(version, *peer_id);
+        // node has received a response from peer, so remove peer entry from requests map
+        self.peer_manager.process_response(version, *peer_id);
+
+        if version != self.known_version + 1 {
+            // version was not requested, or version was requested from a different peer,
+            // so need to penalize peer for maliciously sending chunk. Chunks requested ahead of
+            // the buffered one are no fault of the peer though.
+            if has_requested && self.buffered_chunk.is_none() {
+                self.peer_manager
+                    .update_score(&peer_id, PeerScoreUpdateType::InvalidChunk)
+            }
+            self.last_chunk_failure = Some(ChunkFailure::NonSequential {
+                peer_id: *peer_id,
+                known_version: self.known_version,
+                received_version: version,
+            });
+            return Err(format_err!(
+                "[state sync] non sequential chunk. Known version: {}, received: {}",
+                self.known_version,
+                version,
+            ));
         }
 
+        self.ramp_up_chunk_limit();
+
+        let expiry =
+            self.clock.now() + Duration::from_millis(self.config.chunk_execution_retry_expiry_ms);
+        self.apply_chunk(peer_id, txn_list_with_proof, target, 0, expiry)
+            .await
+    }
+
+    /// Verifies and executes a chunk, or only executes it if it is executed again for the
+    /// `num_retries`-th time. Whatever the outcome, the state of the sync and the score of the
+    /// peer that sent the chunk are updated, and a chunk failing on a transient storage error is
+    /// buffered to be executed again until `expiry`.
+    async fn apply_chunk(
+        &mut self,
+        peer_id: &PeerId,
+        txn_list_with_proof: TransactionListWithProof,
+        target: LedgerInfo,
+        num_retries: u64,
+        expiry: SystemTime,
+    ) -> Result<()> {
         let previous_version = self.known_version;
         let chunk_size = txn_list_with_proof.len();
-        let target: LedgerInfo = response
-            .ledger_info_with_sigs
-            .ok_or_else(|| format_err!("Missing ledger_info_with_sigs"))?
-            .try_into()?;
+        let ends_epoch = Self::ends_epoch(&txn_list_with_proof, &target);
+        // an epoch ending chunk short of the highest version known to sync to was cut at the
+        // epoch boundary, the rest follows in the next epoch
+        let sync_target_version = self
+            .sync_request
+            .as_ref()
+            .map_or(self.target_version, |request| {
+                std::cmp::max(self.target_version, request.target.ledger_info().version())
+            });
+        let is_split = ends_epoch && target.ledger_info().version() < sync_target_version;
+        self.target_version = std::cmp::max(self.target_version, target.ledger_info().version());
+        self.frontier_known = true;
 
         let result = self
-            .validate_and_store_chunk(txn_list_with_proof, target.clone())
+            .validate_and_store_chunk(&txn_list_with_proof, target.clone(), num_retries > 0)
             .await;
+        if result.is_ok() && ends_epoch {
+            counters::EPOCH_BOUNDARY_CHUNKS
+                .with_label_values(&["reconfiguration"])
+                .inc();
+            if is_split {
+                counters::EPOCH_BOUNDARY_CHUNKS
+                    .with_label_values(&["split"])
+                    .inc();
+            }
+            self.process_epoch_change(&target);
+        }
+        if let Err(StateSyncError::Reorg { version }) = &result {
+            // a quorum signed a ledger the local one is not a prefix of, the pending sync can
+            // never complete
+            self.finish_sync_request(Err(SyncError::Reorg { version: *version }));
+        }
         let latest_version = self.executor_proxy.get_latest_version().await?;
         if latest_version <= previous_version {
-            self.peer_manager
-                .update_score(peer_id, PeerScoreUpdateType::InvalidChunk);
+            match &result {
+                // the chunk and its target are verified before the chunk is executed, so only a
+                // failed verification is the fault of the peer
+                Err(StateSyncError::InvalidChunk(_)) => self
+                    .peer_manager
+                    .update_score(peer_id, PeerScoreUpdateType::InvalidChunk),
+                // the chunk failed to execute locally, e.g. on a storage error
+                Err(StateSyncError::Execution(err)) => {
+                    let is_buffered = is_transient_storage_error(err)
+                        && self.buffer_chunk(
+                            *peer_id,
+                            txn_list_with_proof,
+                            target.clone(),
+                            num_retries,
+                            expiry,
+                        );
+                    if !is_buffered {
+                        counters::CHUNK_EXECUTION_FAILURES.inc();
+                    }
+                }
+                _ => {}
+            }
         } else {
+            self.notify_sync_progress(latest_version);
             self.commit(latest_version).await;
         }
         debug!(
@@ -405,34 +2034,274 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
             previous_version, self.known_version, chunk_size
         );
 
-        result
+        if result.is_ok() {
+            self.peer_manager
+                .record_advertised_version(peer_id, target.ledger_info().version());
+            self.peer_manager
+                .update_score(peer_id, PeerScoreUpdateType::Success);
+            counters::APPLY_CHUNK_SUCCESS
+                .with_label_values(&[&*peer_id.to_string()])
+                .inc();
+            if latest_version > previous_version {
+                self.sample_verification(*peer_id, previous_version, latest_version)
+                    .await;
+            }
+        } else {
+            self.reset_chunk_limit();
+        }
+        if let Err(err) = &result {
+            self.last_chunk_failure = Some(match err {
+                StateSyncError::InvalidChunk(error) => ChunkFailure::InvalidProof {
+                    peer_id: *peer_id,
+                    error: error.to_string(),
+                },
+                StateSyncError::Execution(error) => ChunkFailure::ExecutorError {
+                    peer_id: *peer_id,
+                    error: error.to_string(),
+                },
+                StateSyncError::Reorg { version } => ChunkFailure::Reorg {
+                    peer_id: *peer_id,
+                    version: *version,
+                },
+            });
+        }
+        result.map_err(|err| match err {
+            StateSyncError::Reorg { version } => SyncError::Reorg { version }.into(),
+            err => err.into(),
+        })
+    }
+
+    /// Fails a chunk response of `peer_id` that is inconsistent in itself, before anything is
+    /// executed, and penalizes the peer for it.
+    fn reject_malformed_response(&mut self, peer_id: &PeerId, error: String) -> failure::Error {
+        self.peer_manager
+            .update_score(peer_id, PeerScoreUpdateType::InvalidChunk);
+        let err = format_err!("[state sync] malformed chunk response: {}", error);
+        self.last_chunk_failure = Some(ChunkFailure::MalformedResponse {
+            peer_id: *peer_id,
+            error,
+        });
+        err
+    }
+
+    /// Tells whether the chunk `txn_list_with_proof` ends its epoch: either it emits a validator
+    /// set change event, or it reaches a `target` carrying the validator set of the next epoch.
+    fn ends_epoch(txn_list_with_proof: &TransactionListWithProof, target: &LedgerInfo) -> bool {
+        let has_reconfiguration_event = txn_list_with_proof.events.iter().flatten().any(|events| {
+            events
+                .iter()
+                .any(|event| *event.key() == ValidatorSet::change_event_key())
+        });
+        let reaches_epoch_change = target.ledger_info().next_validator_set().is_some()
+            && txn_list_with_proof
+                .first_transaction_version
+                .map(|version| version + txn_list_with_proof.len() as u64 - 1)
+                == Some(target.ledger_info().version());
+        has_reconfiguration_event || reaches_epoch_change
+    }
+
+    /// Called once a chunk ending the epoch of `target` was applied. With the validator set of the
+    /// next epoch known, the validators that left it are no longer picked for validator syncs.
+    fn process_epoch_change(&mut self, target: &LedgerInfo) {
+        let ledger_info = target.ledger_info();
+        let next_validator_set = match ledger_info.next_validator_set() {
+            Some(validator_set) => validator_set,
+            None => return,
+        };
+        let new_num_validators = next_validator_set.payload().len();
+        info!(
+            "[state sync] epoch transition: old_epoch={} new_epoch={} version={} num_validators={} validator_count_delta={}",
+            ledger_info.epoch(),
+            ledger_info.epoch() + 1,
+            ledger_info.version(),
+            new_num_validators,
+            new_num_validators as i64 - self.num_validators as i64,
+        );
+        self.num_validators = new_num_validators;
+        if self.role == RoleType::Validator {
+            let peers = next_validator_set
+                .payload()
+                .iter()
+                .map(|keys| *keys.account_address())
+                .collect();
+            self.peer_manager.set_peers(peers);
+        }
+    }
+
+    /// Doubles the limit of the chunks requested next, up to `max_chunk_limit`, if slow start is
+    /// enabled.
+    fn ramp_up_chunk_limit(&mut self) {
+        if self.config.slow_start_chunk_limit.is_some() {
+            self.chunk_limit = std::cmp::min(
+                self.chunk_limit.saturating_mul(2),
+                self.config.max_chunk_limit,
+            );
+        }
+    }
+
+    /// Goes back to the initial chunk limit of slow start, if enabled.
+    fn reset_chunk_limit(&mut self) {
+        if let Some(chunk_limit) = self.config.slow_start_chunk_limit {
+            self.chunk_limit = chunk_limit;
+        }
+    }
+
+    /// Tells the requesters of the pending sync, if they asked for it, that a chunk was applied up
+    /// to `version`.
+    fn notify_sync_progress(&mut self, version: u64) {
+        let requests = self
+            .sync_request
+            .iter_mut()
+            .chain(self.coalesced_sync_requests.iter_mut());
+        for sync_request in requests {
+            if let Some(progress) = &sync_request.progress {
+                if progress.unbounded_send(version).is_err() {
+                    // the requester is no longer interested in the progress
+                    sync_request.progress = None;
+                }
+            }
+        }
     }
 
+    /// Verifies the chunk `txn_list_with_proof` and its target, unless it is executed again and
+    /// was verified already, then executes and commits it.
     async fn validate_and_store_chunk(
         &mut self,
-        txn_list_with_proof: TransactionListWithProof,
+        txn_list_with_proof: &TransactionListWithProof,
         target: LedgerInfo,
-    ) -> Result<()> {
-        // optimistically fetch next chunk
+        is_retry: bool,
+    ) -> std::result::Result<(), StateSyncError> {
         let chunk_size = txn_list_with_proof.len() as u64;
-        self.request_next_chunk(chunk_size).await;
-        debug!(
-            "[state sync] process chunk response. chunk_size: {}",
-            chunk_size
-        );
+        let is_backlog_full = self.is_apply_backlog_full();
+        if !is_retry {
+            // optimistically fetch next chunk, unless enough chunks are already pending
+            if is_backlog_full {
+                counters::APPLY_BACKLOG_FULL.inc();
+            } else {
+                self.request_next_chunk(chunk_size).await;
+            }
+            debug!(
+                "[state sync] process chunk response. chunk_size: {}",
+                chunk_size
+            );
 
-        self.executor_proxy.validate_ledger_info(&target)?;
+            let validation = self.executor_proxy.validate_ledger_info(&target);
+            if target.ledger_info().next_validator_set().is_some() {
+                let result = if validation.is_ok() {
+                    "success"
+                } else {
+                    "failure"
+                };
+                counters::EPOCH_PROOF_VERIFICATIONS
+                    .with_label_values(&[result])
+                    .inc();
+            }
+            validation.map_err(StateSyncError::InvalidChunk)?;
+            self.check_signature_quorum(&target)
+                .map_err(StateSyncError::InvalidChunk)?;
+            self.executor_proxy
+                .validate_chunk(txn_list_with_proof, &target)
+                .map_err(StateSyncError::InvalidChunk)?;
+            self.check_no_reorg(txn_list_with_proof).await?;
+        }
 
-        self.store_transactions(txn_list_with_proof, target).await?;
+        self.store_transactions(txn_list_with_proof.clone(), target)
+            .await
+            .map_err(StateSyncError::Execution)?;
+        if is_backlog_full || is_retry {
+            // the backlog drained with this chunk applied, or the chunks requested ahead of it
+            // were dropped as non sequential while it was buffered
+            self.request_next_chunk(chunk_size).await;
+        }
 
         counters::STATE_SYNC_TXN_REPLAYED.inc_by(chunk_size as i64);
+        self.session_metrics.txns_replayed += chunk_size;
+        self.session_metrics.chunks_applied += 1;
+
+        Ok(())
+    }
+
+    /// Tells whether the chunk being applied and the chunks requested ahead of it, not applied
+    /// yet either, leave no room for requesting another one under `max_pending_chunks`.
+    fn is_apply_backlog_full(&self) -> bool {
+        self.peer_manager.num_requests() as u64 + 1 >= self.config.max_pending_chunks
+    }
+
+    /// Rejects the chunk `txn_list_with_proof`, verified against its target, if it extends a
+    /// ledger forking from the local one: the left siblings of its accumulator proof are the
+    /// frozen subtrees of the ledger before the chunk, which must be those of the local
+    /// accumulator.
+    async fn check_no_reorg(
+        &self,
+        txn_list_with_proof: &TransactionListWithProof,
+    ) -> std::result::Result<(), StateSyncError> {
+        let first_version = match txn_list_with_proof.first_transaction_version {
+            Some(version) => version,
+            None => return Ok(()),
+        };
+        let local_hashes = self
+            .executor_proxy
+            .get_frozen_subtree_hashes()
+            .await
+            .map_err(StateSyncError::Execution)?;
+        // the ledger before the chunk has `first_version` transactions, the local accumulator
+        // is compared only if it has as many
+        if local_hashes.len() != first_version.count_ones() as usize {
+            return Ok(());
+        }
+        let chunk_hashes = txn_list_with_proof
+            .proof
+            .ledger_info_to_transaction_infos_proof()
+            .left_siblings();
+        if local_hashes[..] == chunk_hashes[..] {
+            return Ok(());
+        }
+        error!(
+            "[state sync] chunk starting at version {} forks from the local ledger, refusing to apply it",
+            first_version
+        );
+        Err(StateSyncError::Reorg {
+            version: first_version,
+        })
+    }
 
+    /// Rejects `target` if it is signed by fewer validators than `min_signature_quorum` requires,
+    /// even though it carries a quorum of voting power.
+    fn check_signature_quorum(&self, target: &LedgerInfo) -> Result<()> {
+        if let Some(quorum) = self.config.min_signature_quorum {
+            let num_required = quorum.num_required(self.executor_proxy.num_validators());
+            let num_signatures = target.signatures().len();
+            ensure!(
+                num_signatures >= num_required,
+                "[state sync] target ledger info at version {} has {} signatures, {} required",
+                target.ledger_info().version(),
+                num_signatures,
+                num_required,
+            );
+        }
         Ok(())
     }
 
     /// ensures that StateSynchronizer makes progress
     /// if peer is not responding, issues new sync request
-    async fn check_progress(&mut self) {
+    pub(crate) async fn check_progress(&mut self) {
+        let timeout = self.progress_timeout_ms();
+
+        // drop records of requests that are long past their timeout and will never be answered
+        let now = self.clock.now();
+        let max_request_age =
+            Duration::from_millis(timeout * self.config.request_expiry_timeout_multiplier);
+        if let Some(expiry) = now.checked_sub(max_request_age) {
+            self.peer_manager.remove_expired_requests(expiry);
+        }
+        self.peer_manager.readmit_evicted_peers();
+        // e.g. peers excluded for their protocol version by the messages processed meanwhile
+        self.process_usable_peers_changes();
+        if self.buffered_chunk.is_some() {
+            // the next chunk is at hand, waiting to be executed again
+            return;
+        }
+
         if !self.peer_manager.is_empty()
             && (self.role == RoleType::FullNode || self.sync_request.is_some())
         {
@@ -440,85 +2309,356 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
                 .peer_manager
                 .get_request_time(self.known_version + 1)
                 .unwrap_or(UNIX_EPOCH);
-            let timeout = match self.role {
-                RoleType::FullNode => {
-                    self.config.tick_interval_ms + self.config.long_poll_timeout_ms
-                }
-                RoleType::Validator => 2 * self.config.tick_interval_ms,
-            };
 
             // if coordinator didn't make progress by expected time, issue new request
             if let Some(tst) = last_request_tst.checked_add(Duration::from_millis(timeout)) {
-                if SystemTime::now().duration_since(tst).is_ok() {
+                if now.duration_since(tst).is_ok() {
+                    let version = self.known_version + 1;
+                    if let Some((peer_id, _)) = self.peer_manager.get_request(version) {
+                        self.last_chunk_failure = Some(ChunkFailure::Timeout { peer_id, version });
+                    }
                     self.peer_manager
                         .process_timeout(self.known_version + 1, self.role == RoleType::Validator);
+                    if self.config.drop_slowest_on_stuck {
+                        let cooldown = Duration::from_millis(self.config.slow_peer_cooldown_ms);
+                        self.peer_manager.evict_slowest_peer(cooldown);
+                    }
                     self.request_next_chunk(0).await;
                     counters::TIMEOUT.inc();
+                    return;
                 }
             }
+
+            if let Some(min_progress_rate) = self.config.min_progress_rate {
+                if self.is_progress_too_slow(now, Duration::from_millis(timeout), min_progress_rate)
+                {
+                    self.fail_over_slow_peer().await;
+                    return;
+                }
+            }
+
+            if let Some(hedge_after_ms) = self.config.hedge_after_ms {
+                self.hedge_request(now, Duration::from_millis(hedge_after_ms))
+                    .await;
+            }
         }
     }
 
-    async fn request_next_chunk(&mut self, offset: u64) {
-        if self.role == RoleType::FullNode || self.sync_request.is_some() {
-            if let Some((peer_id, mut sender)) = self.peer_manager.pick_peer() {
-                let mut req = GetChunkRequest::default();
-                req.known_version = self.known_version + offset;
-                req.limit = self.config.chunk_limit;
-                self.peer_manager
-                    .process_request(self.known_version + offset + 1, peer_id);
-                let timeout = match self.role {
-                    RoleType::Validator => {
-                        if let Some(sync_req) = &self.sync_request {
-                            req.ledger_info_with_sigs = Some(sync_req.target.clone().into());
-                        }
-                        0
-                    }
-                    RoleType::FullNode => {
-                        req.timeout = self.config.long_poll_timeout_ms;
-                        self.config.long_poll_timeout_ms
-                    }
-                };
-                debug!(
-                    "[state sync] request next chunk. peer_id: {}, known_version: {}, timeout: {}",
-                    peer_id,
-                    self.known_version + offset,
-                    timeout
-                );
+    /// Time after which a chunk request without response is considered timed out.
+    fn progress_timeout_ms(&self) -> u64 {
+        match self.role {
+            RoleType::FullNode if self.is_catching_up() => 2 * self.config.tick_interval_ms,
+            RoleType::FullNode => self.config.tick_interval_ms + self.long_poll_timeout_ms(),
+            RoleType::Validator => match self.sync_purpose() {
+                Some(SyncPurpose::Consensus) => self.config.consensus_sync_timeout_ms,
+                _ => 2 * self.config.tick_interval_ms,
+            },
+        }
+    }
 
-                let msg = StateSynchronizerMsg {
-                    message: Some(StateSynchronizerMsg_oneof::ChunkRequest(req)),
-                };
+    /// Timeout of the long-poll requests of a full node: `long_poll_timeout_ms`, or with
+    /// `adaptive_long_poll_timeout_ms` set, a few of the recent commit intervals within bounds.
+    pub(crate) fn long_poll_timeout_ms(&self) -> u64 {
+        let (min_timeout_ms, max_timeout_ms) = match self.config.adaptive_long_poll_timeout_ms {
+            Some(bounds) => bounds,
+            None => return self.config.long_poll_timeout_ms,
+        };
+        let timeout_ms = if self.commit_intervals.is_empty() {
+            self.config.long_poll_timeout_ms
+        } else {
+            let total: Duration = self.commit_intervals.iter().sum();
+            let average = total / self.commit_intervals.len() as u32;
+            (average * LONG_POLL_COMMIT_INTERVALS).as_millis() as u64
+        };
+        std::cmp::min(std::cmp::max(timeout_ms, min_timeout_ms), max_timeout_ms)
+    }
+
+    /// Whether a full node is further than `catch_up_threshold` behind the highest version seen,
+    /// in which case it requests chunks without a timeout instead of long-polling.
+    pub(crate) fn is_catching_up(&self) -> bool {
+        self.config.catch_up_threshold.map_or(false, |threshold| {
+            self.target_version.saturating_sub(self.known_version) > threshold
+        })
+    }
 
-                if sender.send_to(peer_id, msg).await.is_err() {
-                    error!("[state sync] failed to send p2p message");
+    /// Records the interval between the previous commit and the one at `commit_time`.
+    fn record_commit_interval(&mut self, commit_time: SystemTime) {
+        if let Some(last_commit_time) = self.last_commit_time.replace(commit_time) {
+            if let Ok(interval) = commit_time.duration_since(last_commit_time) {
+                if self.commit_intervals.len() == NUM_COMMIT_INTERVALS {
+                    self.commit_intervals.pop_front();
+                }
+                self.commit_intervals.push_back(interval);
+            }
+        }
+    }
+
+    /// Purpose of the pending sync request, if any.
+    fn sync_purpose(&self) -> Option<SyncPurpose> {
+        self.sync_request.as_ref().map(|request| request.purpose)
+    }
+
+    /// Measures the sync throughput over windows of `window` and tells whether the last complete
+    /// one stayed below `min_progress_rate` versions per second. Nothing is measured once caught
+    /// up with the highest version seen, as there is no more to sync.
+    fn is_progress_too_slow(
+        &mut self,
+        now: SystemTime,
+        window: Duration,
+        min_progress_rate: u64,
+    ) -> bool {
+        if self.known_version >= self.target_version {
+            self.progress_window = None;
+            return false;
+        }
+        let (start, start_version) = *self
+            .progress_window
+            .get_or_insert((now, self.known_version));
+        let elapsed = match now.duration_since(start) {
+            Ok(elapsed) if elapsed >= window => elapsed,
+            _ => return false,
+        };
+        self.progress_window = Some((now, self.known_version));
+        let rate = (self.known_version - start_version) as f64 / elapsed.as_secs_f64();
+        rate < min_progress_rate as f64
+    }
+
+    /// Penalizes the peer the next chunk was requested from and requests it from another peer,
+    /// for peers that keep answering but with too little data to sync at a reasonable rate.
+    async fn fail_over_slow_peer(&mut self) {
+        let version = self.known_version + 1;
+        let slow_peer = self
+            .peer_manager
+            .get_request(version)
+            .map(|(peer_id, _)| peer_id);
+        self.peer_manager.process_timeout(version, true);
+        let next_peer = slow_peer
+            .and_then(|peer_id| self.peer_manager.pick_peer_excluding(&peer_id))
+            .or_else(|| self.peer_manager.pick_peer());
+        if let Some((peer_id, sender)) = next_peer {
+            debug!(
+                "[state sync] too little progress from {:?}, failing over to {}",
+                slow_peer.map(|peer_id| peer_id.short_str()),
+                peer_id.short_str()
+            );
+            self.send_chunk_request(peer_id, sender, 0).await;
+        }
+        counters::SLOW_PROGRESS_FAILOVERS.inc();
+    }
+
+    /// Sends the outstanding request for the next version to a second peer as well if the first
+    /// peer did not answer within `hedge_after`. Whichever response arrives first is applied.
+    async fn hedge_request(&mut self, now: SystemTime, hedge_after: Duration) {
+        let version = self.known_version + 1;
+        let (peer_id, request_time) = match self.peer_manager.get_request(version) {
+            Some(request) => request,
+            None => return,
+        };
+        let is_due = now
+            .duration_since(request_time)
+            .map_or(false, |elapsed| elapsed >= hedge_after);
+        if !is_due || self.peer_manager.is_hedged(version) {
+            return;
+        }
+        if let Some((hedge_peer_id, mut sender)) = self.peer_manager.pick_peer_excluding(&peer_id) {
+            let req = self.new_chunk_request(self.known_version);
+            self.peer_manager
+                .process_hedged_request(version, hedge_peer_id);
+            debug!(
+                "[state sync] hedge request. peer_id: {}, slow peer_id: {}, known_version: {}",
+                hedge_peer_id.short_str(),
+                peer_id.short_str(),
+                self.known_version
+            );
+
+            let msg = new_message(StateSynchronizerMsg_oneof::ChunkRequest(req));
+            if sender.send_to(hedge_peer_id, msg).await.is_err() {
+                error!("[state sync] failed to send p2p message");
+            }
+            counters::HEDGED_REQUESTS.inc();
+            counters::REQUESTS_SENT
+                .with_label_values(&[&*hedge_peer_id.to_string()])
+                .inc();
+        }
+    }
+
+    fn new_chunk_request(&self, known_version: u64) -> GetChunkRequest {
+        let mut req = GetChunkRequest::default();
+        req.known_version = known_version;
+        req.limit = self.chunk_limit;
+        match self.role {
+            RoleType::Validator => {
+                if let Some(sync_req) = &self.sync_request {
+                    req.ledger_info_with_sigs = Some(sync_req.target.clone().into());
+                }
+            }
+            RoleType::FullNode => {
+                // peers answer requests without a timeout right away, even with no new data
+                if !self.is_catching_up() {
+                    req.timeout = self.long_poll_timeout_ms();
                 }
-                counters::REQUESTS_SENT
-                    .with_label_values(&[&*peer_id.to_string()])
-                    .inc();
             }
         }
+        req
+    }
+
+    async fn request_next_chunk(&mut self, offset: u64) {
+        if self.role == RoleType::FullNode || self.sync_request.is_some() {
+            let peer = match self.sync_purpose() {
+                Some(SyncPurpose::Consensus) => self.peer_manager.pick_best_peer(),
+                _ => self.peer_manager.pick_peer(),
+            };
+            if let Some((peer_id, sender)) = peer {
+                self.send_chunk_request(peer_id, sender, offset).await;
+            }
+        }
+    }
+
+    async fn send_chunk_request(
+        &mut self,
+        peer_id: PeerId,
+        mut sender: StateSynchronizerSender,
+        offset: u64,
+    ) {
+        let req = self.new_chunk_request(self.known_version + offset);
+        self.peer_manager
+            .process_request(self.known_version + offset + 1, peer_id);
+        debug!(
+            "[state sync] request next chunk. peer_id: {}, known_version: {}, timeout: {}",
+            peer_id.short_str(),
+            self.known_version + offset,
+            req.timeout
+        );
+
+        let msg = new_message(StateSynchronizerMsg_oneof::ChunkRequest(req));
+
+        if sender.send_to(peer_id, msg).await.is_err() {
+            error!("[state sync] failed to send p2p message");
+        }
+        counters::REQUESTS_SENT
+            .with_label_values(&[&*peer_id.to_string()])
+            .inc();
+    }
+
+    /// Buffers the chunk whose execution failed on a transient storage error for the
+    /// `num_retries`-th time, to execute it again after `chunk_execution_retry_backoff_ms`,
+    /// doubled on each retry. Returns false if the chunk is dropped instead, as it was executed
+    /// again `max_chunk_execution_retries` times already or would be after `expiry`.
+    fn buffer_chunk(
+        &mut self,
+        peer_id: PeerId,
+        txn_list_with_proof: TransactionListWithProof,
+        target: LedgerInfo,
+        num_retries: u64,
+        expiry: SystemTime,
+    ) -> bool {
+        if num_retries >= self.config.max_chunk_execution_retries {
+            return false;
+        }
+        let backoff = 2u32.checked_pow(num_retries as u32).and_then(|factor| {
+            Duration::from_millis(self.config.chunk_execution_retry_backoff_ms).checked_mul(factor)
+        });
+        let retry_at = match backoff.and_then(|backoff| self.clock.now().checked_add(backoff)) {
+            Some(retry_at) if retry_at <= expiry => retry_at,
+            _ => return false,
+        };
+        warn!(
+            "[state sync] failed to execute chunk at version {:?}, retry {} of {} in {:?}",
+            txn_list_with_proof.first_transaction_version,
+            num_retries + 1,
+            self.config.max_chunk_execution_retries,
+            backoff.unwrap_or_default(),
+        );
+        self.buffered_chunk = Some(BufferedChunk {
+            peer_id,
+            txn_list_with_proof,
+            target,
+            num_retries,
+            retry_at,
+            expiry,
+        });
+        true
+    }
+
+    /// Executes the buffered chunk again once its backoff is over. The chunk is dropped if the
+    /// known version moved meanwhile, e.g. as the same chunk was received again and applied.
+    pub(crate) async fn retry_buffered_chunk(&mut self) {
+        let now = self.clock.now();
+        match &self.buffered_chunk {
+            Some(chunk) if chunk.retry_at <= now => (),
+            _ => return,
+        }
+        let chunk = self.buffered_chunk.take().expect("Checked above.");
+        if chunk.txn_list_with_proof.first_transaction_version != Some(self.known_version + 1) {
+            return;
+        }
+        counters::CHUNK_EXECUTION_RETRIES.inc();
+        if let Err(err) = self
+            .apply_chunk(
+                &chunk.peer_id,
+                chunk.txn_list_with_proof,
+                chunk.target,
+                chunk.num_retries + 1,
+                chunk.expiry,
+            )
+            .await
+        {
+            error!(
+                "[state sync] failed to execute chunk from {} again: {}",
+                chunk.peer_id.short_str(),
+                err
+            );
+        }
     }
 
+    /// Executes and commits a chunk. The event loop is held up meanwhile, e.g. by a storage write
+    /// stall, so the requests already sent are pushed back by the time it took: their responses
+    /// may be waiting to be processed, and they are neither timed out nor sent again.
     async fn store_transactions(
-        &self,
+        &mut self,
         txn_list_with_proof: TransactionListWithProof,
         ledger_info: LedgerInfoWithSignatures,
     ) -> Result<()> {
-        self.executor_proxy
+        let first_version = txn_list_with_proof.first_transaction_version;
+        let chunk_size = txn_list_with_proof.len();
+        let start = self.clock.now();
+        let result = self
+            .executor_proxy
             .execute_chunk(txn_list_with_proof, ledger_info)
-            .await
+            .await;
+        let duration = self.clock.now().duration_since(start).unwrap_or_default();
+        counters::EXECUTE_CHUNK_DURATION.observe_duration(duration);
+        self.peer_manager.defer_requests(duration);
+
+        if duration >= Duration::from_millis(self.config.slow_execution_warning_ms) {
+            let stages = self.executor_proxy.last_chunk_stages();
+            warn!(
+                "[state sync] slow chunk execution: first_version={:?} chunk_size={} success={} duration_ms={} verify_ms={} execute_ms={} save_ms={}",
+                first_version,
+                chunk_size,
+                result.is_ok(),
+                duration.as_millis(),
+                stages.verify.as_millis(),
+                stages.execute.as_millis(),
+                stages.save.as_millis(),
+            );
+            counters::SLOW_CHUNK_EXECUTIONS.inc();
+        }
+        result
     }
 
-    async fn check_subscriptions(&mut self) -> Result<()> {
+    /// Serves the subscribers whose known version is behind the committed version. `commit_time`
+    /// is when the commit that made the new data available happened.
+    async fn check_subscriptions(&mut self, commit_time: SystemTime) -> Result<()> {
         let ledger_info = self.executor_proxy.get_latest_ledger_info().await?;
-        let committed_version = self.known_version;
+        let committed_version = self.max_served_version();
+        let now = self.clock.now();
         let mut ready = vec![];
 
         self.subscriptions
             .retain(|peer_id, (expiry, known_version, limit)| {
                 // filter out expired peer requests
-                if SystemTime::now().duration_since(expiry.clone()).is_ok() {
+                if now.duration_since(expiry.clone()).is_ok() {
                     return false;
                 }
                 if *known_version < committed_version {
@@ -529,33 +2669,215 @@ impl<T: ExecutorProxyTrait> SyncCoordinator<T> {
                 }
             });
 
-        let mut futures = FuturesUnordered::new();
         for (peer_id, known_version, limit) in ready {
             if let Some(sender) = self.peer_manager.get_network_sender(&peer_id) {
-                futures.push(self.deliver_chunk(
-                    peer_id,
-                    known_version,
-                    limit,
-                    ledger_info.clone(),
-                    sender,
-                ));
+                match self
+                    .get_chunk_to_serve(known_version, limit, ledger_info.clone())
+                    .await
+                {
+                    Ok(chunk) => self.spawn_delivery(peer_id, chunk, sender, commit_time),
+                    Err(err) => error!("[state sync] failed to notify subscriber {}", err),
+                }
             }
         }
-        while let Some(res) = futures.next().await {
-            if let Err(err) = res {
-                error!("[state sync] failed to notify subscriber {}", err);
+        Ok(())
+    }
+
+    /// Notifies every long-poll subscriber before the coordinator stops: the ones behind the
+    /// served version get their chunk, the others are told that their subscription is closed, so
+    /// that they fail over to other peers rather than wait for their requests to time out.
+    pub(crate) async fn drain_subscriptions(&mut self) {
+        let subscriptions = std::mem::replace(&mut self.subscriptions, HashMap::new());
+        info!(
+            "[state sync] shutting down, draining {} subscriptions",
+            subscriptions.len()
+        );
+        // without the latest ledger info, every subscriber is told that its subscription is closed
+        let ledger_info = match self.executor_proxy.get_latest_ledger_info().await {
+            Ok(ledger_info) => Some(ledger_info),
+            Err(err) => {
+                error!("[state sync] failed to fetch latest ledger info: {}", err);
+                None
+            }
+        };
+        let served_version = self.max_served_version();
+        let now = self.clock.now();
+        for (peer_id, (expiry, known_version, limit)) in subscriptions {
+            if now.duration_since(expiry).is_ok() {
+                continue;
+            }
+            let mut sender = match self.peer_manager.get_network_sender(&peer_id) {
+                Some(sender) => sender,
+                None => continue,
+            };
+            let target = ledger_info
+                .as_ref()
+                .filter(|_| known_version < served_version);
+            if let Some(target) = target {
+                match self
+                    .deliver_chunk(
+                        peer_id,
+                        known_version,
+                        limit,
+                        target.clone(),
+                        sender.clone(),
+                    )
+                    .await
+                {
+                    Ok(()) => {
+                        counters::DRAINED_SUBSCRIPTIONS
+                            .with_label_values(&["served"])
+                            .inc();
+                        continue;
+                    }
+                    Err(err) => error!(
+                        "[state sync] failed to serve subscriber {} on shutdown: {}",
+                        peer_id.short_str(),
+                        err
+                    ),
+                }
             }
+            let msg = new_message(StateSynchronizerMsg_oneof::SubscriptionClosed(
+                SubscriptionClosed { known_version },
+            ));
+            if sender.send_to(peer_id, msg).await.is_err() {
+                error!("[state sync] failed to send p2p message");
+            }
+            counters::DRAINED_SUBSCRIPTIONS
+                .with_label_values(&["closed"])
+                .inc();
+        }
+    }
+
+    /// Fails the request for the next chunk over to another peer right away if it went to
+    /// `peer_id`, which closed the subscription of this node.
+    async fn process_subscription_closed(&mut self, peer_id: PeerId) {
+        let version = self.known_version + 1;
+        if !self.peer_manager.has_requested(version, peer_id) {
+            return;
+        }
+        debug!(
+            "[state sync] subscription closed by {}, failing over",
+            peer_id.short_str()
+        );
+        self.peer_manager.process_timeout(version, false);
+        if let Some((next_peer_id, sender)) = self.peer_manager.pick_peer_excluding(&peer_id) {
+            self.send_chunk_request(next_peer_id, sender, 0).await;
+        }
+    }
+
+    /// Serves one page of the epoch change proof. The storage read runs on a separate task, so a
+    /// long range doesn't hold up the event loop, and is retried there if it fails transiently.
+    pub(crate) fn get_epoch_proof(&self, request: EpochRetrievalRequest) {
+        let EpochRetrievalRequest {
+            start_epoch,
+            end_epoch,
+            limit,
+            callback,
+        } = request;
+        let limit = std::cmp::min(
+            limit.unwrap_or(MAX_EPOCH_PROOF_PAGE_SIZE),
+            MAX_EPOCH_PROOF_PAGE_SIZE,
+        );
+        let page = get_epoch_proof_with_retries(
+            Arc::clone(&self.executor_proxy),
+            start_epoch,
+            end_epoch.unwrap_or_else(u64::max_value),
+            limit,
+            self.config.max_epoch_proof_retries,
+            Duration::from_millis(self.config.epoch_proof_retry_backoff_ms),
+        );
+        self.executor.spawn(async move {
+            if callback.send(page.await).is_err() {
+                error!("[state sync] coordinator failed to send back epoch proof");
+            }
+        });
+    }
+
+    pub(crate) async fn get_ledger_info_at(
+        &self,
+        version: u64,
+        callback: oneshot::Sender<Result<LedgerInfoWithSignatures>>,
+    ) {
+        let ledger_info = self.executor_proxy.get_ledger_info_at(version).await;
+        if callback.send(ledger_info).is_err() {
+            error!("[state sync] coordinator failed to send back ledger info");
         }
-        Ok(())
     }
+}
 
-    async fn get_epoch_proof(&self, request: EpochRetrievalRequest) {
-        if request
-            .callback
-            .send(self.executor_proxy.get_epoch_proof(request.start_epoch))
-            .is_err()
+/// Reads one page of the epoch change proof, retrying up to `max_retries` times on a
+/// `TransientError`. The first retry waits `backoff`, each next one twice as long as the previous.
+async fn get_epoch_proof_with_retries<T: ExecutorProxyTrait>(
+    executor_proxy: Arc<T>,
+    start_epoch: u64,
+    end_epoch: u64,
+    limit: u64,
+    max_retries: u64,
+    mut backoff: Duration,
+) -> Result<EpochProofPage> {
+    let mut retries = 0;
+    loop {
+        match executor_proxy
+            .get_epoch_proof(start_epoch, end_epoch, limit)
+            .await
         {
-            error!("[state sync] coordinator failed to send back epoch proof");
+            Err(e) if retries < max_retries && e.downcast_ref::<TransientError>().is_some() => {
+                retries += 1;
+                counters::EPOCH_PROOF_RETRIES.inc();
+                warn!(
+                    "[state sync] failed to get epoch proof from epoch {}: {}, retry {} of {} in {:?}",
+                    start_epoch, e, retries, max_retries, backoff
+                );
+                delay_for(backoff).await;
+                backoff *= 2;
+            }
+            result => return result,
         }
     }
 }
+
+/// Sends `chunk` to `peer_id` once it is read. A failure to send is only logged.
+async fn send_chunk(
+    peer_id: PeerId,
+    chunk: Pin<Box<dyn Future<Output = Result<GetChunkResponse>> + Send>>,
+    mut network_sender: StateSynchronizerSender,
+) -> Result<()> {
+    let msg = new_message(StateSynchronizerMsg_oneof::ChunkResponse(chunk.await?));
+    if network_sender.send_to(peer_id, msg).await.is_err() {
+        error!("[state sync] failed to send p2p message");
+    }
+    Ok(())
+}
+
+fn send_audit_report(
+    callback: oneshot::Sender<Result<Vec<VersionAudit>>>,
+    report: Result<Vec<VersionAudit>>,
+) {
+    if callback.send(report).is_err() {
+        error!("[state sync] coordinator failed to send the audit report");
+    }
+}
+
+// Hands the outcome of the audit of `peer_id` to `reporter`. The discrepancies found by a check
+// against the verification peer are handled by the coordinator, so only its failures are left to
+// log here.
+fn report_audit(reporter: AuditReporter, peer_id: PeerId, report: Result<Vec<VersionAudit>>) {
+    match (reporter, report) {
+        (AuditReporter::Client(callback), report) => send_audit_report(callback, report),
+        (AuditReporter::Verification { .. }, Err(err)) => warn!(
+            "[state sync] check against the verification peer {} failed: {}",
+            peer_id.short_str(),
+            err
+        ),
+        (AuditReporter::Verification { .. }, Ok(_)) => {}
+    }
+}
+
+/// Wraps `message` for sending, advertising the protocol version of this node.
+fn new_message(message: StateSynchronizerMsg_oneof) -> StateSynchronizerMsg {
+    StateSynchronizerMsg {
+        message: Some(message),
+        protocol_version: PROTOCOL_VERSION,
+    }
+}
@@ -3,7 +3,7 @@
 
 use lazy_static;
 use libra_metrics::DurationHistogram;
-use prometheus::{IntCounter, IntCounterVec, IntGauge};
+use prometheus::{Histogram, IntCounter, IntCounterVec, IntGauge};
 
 lazy_static::lazy_static! {
     /// Number of sync requests sent from a node
@@ -44,6 +44,21 @@ lazy_static::lazy_static! {
         "Number of transactions the state synchronizer has retrieved since last restart"
     ).unwrap();
 
+    /// Number of applied chunks ending an epoch ("reconfiguration"), and of those that were cut
+    /// at the epoch boundary short of the version being synced to ("split")
+    pub static ref EPOCH_BOUNDARY_CHUNKS: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_epoch_boundary_chunks_total",
+        "Number of applied chunks ending an epoch",
+        &["type"]
+    ).unwrap();
+
+    /// Number of verifications of ledger infos ending an epoch, the links of epoch change proofs
+    pub static ref EPOCH_PROOF_VERIFICATIONS: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_epoch_proof_verifications_total",
+        "Number of verifications of ledger infos ending an epoch",
+        &["result"]
+    ).unwrap();
+
     /// Number of peers that are currently active and upstream.
     /// They are the set of nodes a node can make sync requests to
     pub static ref ACTIVE_UPSTREAM_PEERS: IntGauge = register_int_gauge!(
@@ -51,6 +66,18 @@ lazy_static::lazy_static! {
         "Number of upstream peers that are currently active"
     ).unwrap();
 
+    /// 1 while no upstream peer can be picked for requests after one could, 0 otherwise
+    pub static ref NO_SYNC_PEERS: IntGauge = register_int_gauge!(
+        "libra_state_sync_no_sync_peers",
+        "Whether the node lost all the upstream peers it can sync from"
+    ).unwrap();
+
+    /// Number of chunk requests recorded by the peer manager that have not been answered yet
+    pub static ref PENDING_REQUESTS: IntGauge = register_int_gauge!(
+        "libra_state_sync_pending_requests",
+        "Number of chunk requests that have not been answered yet"
+    ).unwrap();
+
     /// Most recent version that has been committed
     pub static ref COMMITTED_VERSION: IntGauge = register_int_gauge!(
         "libra_state_sync_committed_version",
@@ -67,6 +94,83 @@ lazy_static::lazy_static! {
         .unwrap()
     );
 
+    /// How long it takes to execute and commit a chunk, storage writes included
+    pub static ref EXECUTE_CHUNK_DURATION: DurationHistogram = DurationHistogram::new(
+        register_histogram!(
+            "libra_state_sync_execute_chunk_duration_s",
+            "Histogram of time it takes to execute and commit a chunk"
+        )
+        .unwrap()
+    );
+
+    /// Number of chunks applied before requesting the next one, because `max_pending_chunks` were
+    /// already waiting to be applied or requested
+    pub static ref APPLY_BACKLOG_FULL: IntCounter = register_int_counter!(
+        "libra_state_sync_apply_backlog_full_total",
+        "Number of chunks applied before requesting the next one because the apply backlog was full"
+    ).unwrap();
+
+    /// Number of chunk executions that took longer than `slow_execution_warning_ms`
+    pub static ref SLOW_CHUNK_EXECUTIONS: IntCounter = register_int_counter!(
+        "libra_state_sync_slow_chunk_executions_total",
+        "Number of chunk executions that took longer than the slow execution threshold"
+    ).unwrap();
+
+    /// Number of chunks dropped after their execution kept failing locally
+    pub static ref CHUNK_EXECUTION_FAILURES: IntCounter = register_int_counter!(
+        "libra_state_sync_chunk_execution_failures_total",
+        "Number of chunks dropped after their execution kept failing locally"
+    ).unwrap();
+
+    /// Number of times a chunk was executed again after its execution failed locally
+    pub static ref CHUNK_EXECUTION_RETRIES: IntCounter = register_int_counter!(
+        "libra_state_sync_chunk_execution_retries_total",
+        "Number of times a chunk was executed again after its execution failed locally"
+    ).unwrap();
+
+    /// Number of times the read of an epoch proof was retried after failing transiently
+    pub static ref EPOCH_PROOF_RETRIES: IntCounter = register_int_counter!(
+        "libra_state_sync_epoch_proof_retries_total",
+        "Number of times the read of an epoch proof was retried after failing transiently"
+    ).unwrap();
+
+    /// Number of root hash requests for state audits, by outcome: sent, served or dropped by the
+    /// rate limit
+    pub static ref STATE_AUDIT_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_state_audit_requests_total",
+        "Number of root hash requests for state audits, by outcome",
+        &["outcome"]
+    ).unwrap();
+
+    /// Number of versions whose transaction info differs between this node and an audited peer
+    pub static ref STATE_AUDIT_MISMATCHES: IntCounter = register_int_counter!(
+        "libra_state_sync_state_audit_mismatches_total",
+        "Number of versions whose transaction info differs between this node and an audited peer"
+    ).unwrap();
+
+    /// Number of versions of applied chunks whose transaction info differs from the one of the
+    /// verification peer
+    pub static ref VERIFICATION_DISCREPANCIES: IntCounter = register_int_counter!(
+        "libra_state_sync_verification_discrepancies_total",
+        "Number of versions of applied chunks whose transaction info differs from the one of the verification peer"
+    ).unwrap();
+
+    /// Time from a commit making new data available to a long-poll subscriber to the subscriber
+    /// being served that data
+    pub static ref SUBSCRIPTION_SERVE_LATENCY: Histogram = register_histogram!(
+        "libra_state_sync_subscription_serve_latency_s",
+        "Histogram of time from a commit advancing past a subscriber's known version to the subscriber being served"
+    ).unwrap();
+
+    /// Number of chunks not delivered to a long-poll subscriber, because it disconnected
+    /// ("disconnected") or the delivery took longer than `subscription_delivery_timeout_ms`
+    /// ("timeout")
+    pub static ref SUBSCRIPTION_DELIVERIES_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_subscription_deliveries_dropped_total",
+        "Number of chunks not delivered to a long-poll subscriber",
+        &["reason"]
+    ).unwrap();
+
     /// Version a node is trying to catch up to
     pub static ref TARGET_VERSION: IntGauge = register_int_gauge!(
         "libra_state_sync_target_version",
@@ -78,4 +182,84 @@ lazy_static::lazy_static! {
         "libra_state_sync_timeout_total",
         "Number of timeouts that occur during sync"
     ).unwrap();
+
+    /// Number of chunk requests re-sent to a second peer because the first one was slow
+    pub static ref HEDGED_REQUESTS: IntCounter = register_int_counter!(
+        "libra_state_sync_hedged_requests_total",
+        "Number of chunk requests re-sent to a second peer because the first one was slow"
+    ).unwrap();
+
+    /// Number of hedged chunk requests answered first by the second peer
+    pub static ref HEDGE_WINS: IntCounter = register_int_counter!(
+        "libra_state_sync_hedge_wins_total",
+        "Number of hedged chunk requests answered first by the second peer"
+    ).unwrap();
+
+    /// Number of times sync failed over from a peer that answered but made too little progress
+    pub static ref SLOW_PROGRESS_FAILOVERS: IntCounter = register_int_counter!(
+        "libra_state_sync_slow_progress_failovers_total",
+        "Number of times sync failed over from a peer that answered but made too little progress"
+    ).unwrap();
+
+    /// Number of committed transactions not passed to a subscriber because it lagged behind
+    pub static ref COMMITTED_DATA_DROPPED: IntCounter = register_int_counter!(
+        "libra_state_sync_committed_data_dropped_total",
+        "Number of committed transactions not passed to a subscriber because it lagged behind"
+    ).unwrap();
+
+    /// Number of ledger info summaries gossiped to the connected peers ("sent"), and received from
+    /// peers, either recorded ("received") or dropped by the rate limit ("dropped")
+    pub static ref LEDGER_INFO_SUMMARIES: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_ledger_info_summaries_total",
+        "Number of ledger info summaries sent and received",
+        &["type"]
+    ).unwrap();
+
+    /// Number of long-poll subscriptions drained on shutdown, either served with a chunk
+    /// ("served") or told that the subscription is closed ("closed")
+    pub static ref DRAINED_SUBSCRIPTIONS: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_drained_subscriptions_total",
+        "Number of long-poll subscriptions drained on shutdown",
+        &["type"]
+    ).unwrap();
+
+    /// Number of messages of peers, mostly chunk requests, processed after chunk responses
+    /// received later than them
+    pub static ref DEFERRED_MESSAGES: IntCounter = register_int_counter!(
+        "libra_state_sync_deferred_messages_total",
+        "Number of messages of peers processed after chunk responses received later than them"
+    ).unwrap();
+
+    /// Longest time a message of a peer waited behind chunk responses in the latest batch of
+    /// messages processed with deferrals
+    pub static ref MAX_MESSAGE_DEFERRAL_MS: IntGauge = register_int_gauge!(
+        "libra_state_sync_max_message_deferral_ms",
+        "Longest time a message of a peer waited behind chunk responses in the latest batch"
+    ).unwrap();
+
+    /// Number of messages rejected because their sender speaks an unsupported protocol version
+    pub static ref INCOMPATIBLE_PEER_MESSAGES: IntCounter = register_int_counter!(
+        "libra_state_sync_incompatible_peer_messages_total",
+        "Number of messages rejected because their sender speaks an unsupported protocol version"
+    ).unwrap();
+
+    /// Number of chunk requests refused because their sender is not on the serve allowlist
+    pub static ref NON_ALLOWLISTED_CHUNK_REQUESTS: IntCounter = register_int_counter!(
+        "libra_state_sync_non_allowlisted_chunk_requests_total",
+        "Number of chunk requests refused because their sender is not on the serve allowlist"
+    ).unwrap();
+
+    /// Number of commit notifications sent to the coordinator, coalesced with a later one before
+    /// being processed, and processed
+    pub static ref COMMIT_NOTIFICATIONS: IntCounterVec = register_int_counter_vec!(
+        "libra_state_sync_commit_notifications_total",
+        "Number of commit notifications sent to the coordinator, coalesced and processed",
+        &["state"]
+    ).unwrap();
+
+    /// Number of sync requests for the target of the pending one, coalesced into it
+    pub static ref COALESCED_SYNC_REQUESTS: IntCounter = register_int_counter!(
+        "libra_state_sync_coalesced_sync_requests_total",
+        "Number of sync requests for the target of the pending one, coalesced into it"
+    ).unwrap();
 }
@@ -1,12 +1,16 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::LedgerInfo;
-use executor::Executor;
+use crate::{counters, LedgerInfo};
+use executor::{ChunkExecutionStages, CommittedTransactionData, Executor};
 use failure::prelude::*;
-use futures::{channel::oneshot, Future, FutureExt};
-use grpcio::EnvBuilder;
+use futures::{
+    channel::{mpsc, oneshot},
+    Future, FutureExt, SinkExt,
+};
+use grpcio::{EnvBuilder, RpcStatusCode};
 use libra_config::config::NodeConfig;
+use libra_crypto::{hash::CryptoHash, HashValue};
 use libra_logger::prelude::*;
 use libra_types::crypto_proxies::ValidatorChangeEventWithProof;
 use libra_types::{
@@ -14,7 +18,10 @@ use libra_types::{
     transaction::TransactionListWithProof,
 };
 use network::proto::GetChunkResponse;
-use std::{pin::Pin, sync::Arc};
+use std::{
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
 use storage_client::{StorageRead, StorageReadServiceClient};
 use vm_runtime::MoveVM;
 
@@ -26,13 +33,32 @@ pub trait ExecutorProxyTrait: Sync + Send {
     /// Return the latest known ledger info
     fn get_latest_ledger_info(&self) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>>;
 
-    /// Execute and commit a batch of transactions
+    /// Return the ledger info that was committed at `version`. Only the latest ledger info and the
+    /// ones ending an epoch are stored, so this fails for any other version.
+    fn get_ledger_info_at(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>>;
+
+    /// Return the latest ledger info stored at or before `version`: the latest one if it is not
+    /// newer than `version`, or else the one ending the last epoch that ends by `version`. Fails
+    /// if there is none.
+    fn get_latest_ledger_info_at_or_before(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>>;
+
+    /// Execute and commit a batch of transactions. The data of the committed transactions is
+    /// passed on to the subscribers of `subscribe_committed_data`.
     fn execute_chunk(
         &self,
         txn_list_with_proof: TransactionListWithProof,
         ledger_info_with_sigs: LedgerInfoWithSignatures,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
+    /// How long each stage of the last `execute_chunk` took.
+    fn last_chunk_stages(&self) -> ChunkExecutionStages;
+
     /// Gets chunk of transactions
     fn get_chunk(
         &self,
@@ -43,30 +69,210 @@ pub trait ExecutorProxyTrait: Sync + Send {
 
     fn validate_ledger_info(&self, target: &LedgerInfoWithSignatures) -> Result<()>;
 
-    fn get_epoch_proof(&self, start_epoch: u64) -> Result<ValidatorChangeEventWithProof>;
+    /// Verifies the transactions of `txn_list_with_proof` against `target`, for a chunk with a
+    /// bad proof to be rejected before it is executed.
+    fn validate_chunk(
+        &self,
+        txn_list_with_proof: &TransactionListWithProof,
+        target: &LedgerInfoWithSignatures,
+    ) -> Result<()>;
+
+    /// Number of validators whose signatures `validate_ledger_info` checks.
+    fn num_validators(&self) -> usize;
+
+    /// Gets at most `limit` epoch change ledger infos from `start_epoch` up to, but excluding,
+    /// `end_epoch`. Failures worth retrying are reported as `TransientError`.
+    fn get_epoch_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+        limit: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>>;
+
+    /// Streams the data of every transaction committed through `execute_chunk` from now on, in
+    /// version order.
+    fn subscribe_committed_data(&self) -> mpsc::Receiver<CommittedTransactionData>;
+
+    /// Hash of the transaction info committed at `version`, or None if `version` is not
+    /// committed yet.
+    fn get_transaction_info_hash(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<HashValue>>> + Send>>;
+
+    /// Hashes of the frozen subtrees of the transaction accumulator at the latest version, the
+    /// leftmost first.
+    fn get_frozen_subtree_hashes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<HashValue>>> + Send>>;
 }
 
-pub(crate) struct ExecutorProxy {
+/// Error of a read that may succeed if retried, e.g. because storage couldn't be reached.
+#[derive(Debug, Fail)]
+#[fail(display = "transient error: {}", _0)]
+pub struct TransientError(pub String);
+
+/// Whether `error`, returned by the storage service, may go away if the read is retried, i.e. the
+/// service couldn't be reached or didn't answer in time. Other failures, e.g. a missing epoch or
+/// a corrupted DB, are permanent.
+pub(crate) fn is_transient_storage_error(error: &Error) -> bool {
+    match error.downcast_ref::<grpcio::Error>() {
+        Some(grpcio::Error::RpcFailure(status)) => {
+            status.status == RpcStatusCode::UNAVAILABLE
+                || status.status == RpcStatusCode::DEADLINE_EXCEEDED
+                || status.status == RpcStatusCode::RESOURCE_EXHAUSTED
+        }
+        _ => false,
+    }
+}
+
+/// One page of a paginated epoch change proof.
+#[derive(Clone, Debug)]
+pub struct EpochProofPage {
+    pub proof: ValidatorChangeEventWithProof,
+    /// Whether more epoch changes follow the last one in `proof` within the requested range.
+    pub more: bool,
+}
+
+impl EpochProofPage {
+    /// Builds a page out of `ledger_infos`, which should hold up to `limit + 1` entries so that
+    /// the presence of a next page can be told without another read.
+    pub fn new(mut ledger_infos: Vec<LedgerInfoWithSignatures>, limit: u64) -> Self {
+        let more = ledger_infos.len() as u64 > limit;
+        ledger_infos.truncate(limit as usize);
+        Self {
+            proof: ValidatorChangeEventWithProof::new(ledger_infos),
+            more,
+        }
+    }
+}
+
+/// Passes the data of committed transactions on to subscribers, e.g. an embedded indexer. Once a
+/// subscriber lags `buffer_size` transactions behind, it either holds up publishing until it
+/// catches up or misses the transactions published meanwhile, depending on `block_on_lag`.
+pub(crate) struct CommittedDataPublisher {
+    subscribers: Mutex<Vec<mpsc::Sender<CommittedTransactionData>>>,
+    buffer_size: usize,
+    block_on_lag: bool,
+}
+
+impl CommittedDataPublisher {
+    pub(crate) fn new(buffer_size: usize, block_on_lag: bool) -> Self {
+        Self {
+            subscribers: Mutex::new(vec![]),
+            buffer_size,
+            block_on_lag,
+        }
+    }
+
+    pub(crate) fn subscribe(&self) -> mpsc::Receiver<CommittedTransactionData> {
+        let (sender, receiver) = mpsc::channel(self.buffer_size);
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends `data` to every subscriber, forgetting the ones that went away. The returned future
+    /// completes once all subscribers have room for it, which is right away unless
+    /// `block_on_lag` is set.
+    pub(crate) fn publish(
+        &self,
+        data: Vec<CommittedTransactionData>,
+    ) -> impl Future<Output = ()> + Send {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|sender| !sender.is_closed());
+        let mut blocking_subscribers = if self.block_on_lag {
+            subscribers.clone()
+        } else {
+            for item in &data {
+                for sender in subscribers.iter_mut() {
+                    if let Err(e) = sender.try_send(item.clone()) {
+                        if e.is_full() {
+                            counters::COMMITTED_DATA_DROPPED.inc();
+                        }
+                    }
+                }
+            }
+            vec![]
+        };
+        async move {
+            for item in data {
+                for sender in &mut blocking_subscribers {
+                    // fails only if the subscriber went away meanwhile
+                    let _ = sender.send(item.clone()).await;
+                }
+            }
+        }
+    }
+}
+
+pub struct ExecutorProxy {
     storage_read_client: Arc<StorageReadServiceClient>,
     executor: Arc<Executor<MoveVM>>,
     validator_verifier: ValidatorVerifier,
+    committed_data: Arc<CommittedDataPublisher>,
 }
 
 impl ExecutorProxy {
-    pub(crate) fn new(executor: Arc<Executor<MoveVM>>, config: &NodeConfig) -> Self {
+    /// Connects to the storage service of the node configured by `config`, failing if its startup
+    /// info can't be read.
+    pub(crate) fn new(executor: Arc<Executor<MoveVM>>, config: &NodeConfig) -> Result<Self> {
         let client_env = Arc::new(EnvBuilder::new().name_prefix("grpc-coord-").build());
         let storage_read_client = Arc::new(StorageReadServiceClient::new(
             client_env,
             &config.storage.address,
             config.storage.port,
         ));
-        let validator_verifier = config.consensus.consensus_peers.get_validator_verifier();
-        Self {
+        // Verify ledger infos against the validator set in storage when the node starts, unless
+        // the DB is still waiting for the genesis. It is read only once, so it doesn't follow the
+        // epoch changes synced afterwards.
+        let startup_info = storage_read_client.get_startup_info().map_err(|e| {
+            format_err!(
+                "[state sync] failed to read startup info from storage: {}",
+                e
+            )
+        })?;
+        let validator_verifier = match startup_info {
+            Some(info) => (&info.latest_validator_set).into(),
+            None => config.consensus.consensus_peers.get_validator_verifier(),
+        };
+        let committed_data = Arc::new(CommittedDataPublisher::new(
+            config.state_sync.committed_data_buffer_size,
+            config.state_sync.block_on_committed_data_lag,
+        ));
+        Ok(Self {
             storage_read_client,
             executor,
             validator_verifier,
+            committed_data,
+        })
+    }
+}
+
+/// Reads the ledger info ending the last epoch that ends at or before `version`, out of the epochs
+/// before `current_epoch`. Epochs end at increasing versions, so it is searched for by reading one
+/// epoch change at a time rather than all of them.
+async fn get_last_epoch_change_by(
+    client: &StorageReadServiceClient,
+    version: u64,
+    current_epoch: u64,
+) -> Result<Option<LedgerInfo>> {
+    let (mut low, mut high) = (0, current_epoch);
+    let mut last_epoch_change = None;
+    while low < high {
+        let epoch = low + (high - low) / 2;
+        let ledger_info = client
+            .get_epoch_change_ledger_infos_async(epoch, epoch + 1)
+            .await?
+            .pop()
+            .ok_or_else(|| format_err!("no ledger info stored for the end of epoch {}", epoch))?;
+        if ledger_info.ledger_info().version() <= version {
+            low = epoch + 1;
+            last_epoch_change = Some(ledger_info);
+        } else {
+            high = epoch;
         }
     }
+    Ok(last_epoch_change)
 }
 
 fn convert_to_future<T: Send + 'static>(
@@ -89,11 +295,8 @@ impl ExecutorProxyTrait for ExecutorProxy {
         let client = Arc::clone(&self.storage_read_client);
         async move {
             let resp = client.get_startup_info_async().await?;
-            resp.map(|r| {
-                r.synced_tree_state
-                    .map_or(r.ledger_info.version(), |s| s.version)
-            })
-            .ok_or_else(|| format_err!("failed to fetch startup info"))
+            resp.map(|r| r.latest_version())
+                .ok_or_else(|| format_err!("storage is not bootstrapped with the genesis yet"))
         }
             .boxed()
     }
@@ -103,15 +306,71 @@ impl ExecutorProxyTrait for ExecutorProxy {
         async move { Ok(client.update_to_latest_ledger_async(0, vec![]).await?.1) }.boxed()
     }
 
+    fn get_ledger_info_at(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let client = Arc::clone(&self.storage_read_client);
+        async move {
+            // Only the latest ledger info and the ones ending an epoch are kept in storage.
+            let latest = client.update_to_latest_ledger_async(0, vec![]).await?.1;
+            if latest.ledger_info().version() == version {
+                return Ok(latest);
+            }
+            ensure!(
+                version < latest.ledger_info().version(),
+                "version {} is newer than the latest ledger info at version {}",
+                version,
+                latest.ledger_info().version()
+            );
+            match get_last_epoch_change_by(&client, version, latest.ledger_info().epoch()).await? {
+                Some(ledger_info) if ledger_info.ledger_info().version() == version => {
+                    Ok(ledger_info)
+                }
+                _ => bail!("no ledger info stored for version {}", version),
+            }
+        }
+            .boxed()
+    }
+
+    fn get_latest_ledger_info_at_or_before(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let client = Arc::clone(&self.storage_read_client);
+        async move {
+            let latest = client.update_to_latest_ledger_async(0, vec![]).await?.1;
+            if latest.ledger_info().version() <= version {
+                return Ok(latest);
+            }
+            get_last_epoch_change_by(&client, version, latest.ledger_info().epoch())
+                .await?
+                .ok_or_else(|| {
+                    format_err!("no ledger info stored at or before version {}", version)
+                })
+        }
+            .boxed()
+    }
+
     fn execute_chunk(
         &self,
         txn_list_with_proof: TransactionListWithProof,
         ledger_info_with_sigs: LedgerInfoWithSignatures,
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
-        convert_to_future(
+        let committed = convert_to_future(
             self.executor
                 .execute_and_commit_chunk(txn_list_with_proof, ledger_info_with_sigs),
-        )
+        );
+        let committed_data = Arc::clone(&self.committed_data);
+        async move {
+            committed_data.publish(committed.await?).await;
+            Ok(())
+        }
+            .boxed()
+    }
+
+    fn last_chunk_stages(&self) -> ChunkExecutionStages {
+        self.executor.last_chunk_stages()
     }
 
     fn get_chunk(
@@ -149,10 +408,94 @@ impl ExecutorProxyTrait for ExecutorProxy {
         Ok(())
     }
 
-    fn get_epoch_proof(&self, start_epoch: u64) -> Result<ValidatorChangeEventWithProof> {
-        let ledger_info_per_epoch = self
-            .storage_read_client
-            .get_epoch_change_ledger_infos(start_epoch)?;
-        Ok(ValidatorChangeEventWithProof::new(ledger_info_per_epoch))
+    fn validate_chunk(
+        &self,
+        txn_list_with_proof: &TransactionListWithProof,
+        target: &LedgerInfo,
+    ) -> Result<()> {
+        txn_list_with_proof.verify(
+            target.ledger_info(),
+            txn_list_with_proof.first_transaction_version,
+        )
+    }
+
+    fn num_validators(&self) -> usize {
+        self.validator_verifier.len()
+    }
+
+    fn get_epoch_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+        limit: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
+        let client = Arc::clone(&self.storage_read_client);
+        // Read one extra ledger info to tell whether another page follows.
+        let end_epoch = std::cmp::min(end_epoch, start_epoch.saturating_add(limit + 1));
+        async move {
+            ensure!(
+                start_epoch <= end_epoch,
+                "invalid epoch range: start epoch {} > end epoch {}",
+                start_epoch,
+                end_epoch
+            );
+            let ledger_infos = client
+                .get_epoch_change_ledger_infos_async(start_epoch, end_epoch)
+                .await
+                .map_err(|e| {
+                    if is_transient_storage_error(&e) {
+                        TransientError(e.to_string()).into()
+                    } else {
+                        e
+                    }
+                })?;
+            Ok(EpochProofPage::new(ledger_infos, limit))
+        }
+            .boxed()
+    }
+
+    fn subscribe_committed_data(&self) -> mpsc::Receiver<CommittedTransactionData> {
+        self.committed_data.subscribe()
+    }
+
+    fn get_transaction_info_hash(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<HashValue>>> + Send>> {
+        let client = Arc::clone(&self.storage_read_client);
+        async move {
+            let latest = client.update_to_latest_ledger_async(0, vec![]).await?.1;
+            let ledger_version = latest.ledger_info().version();
+            if version > ledger_version {
+                return Ok(None);
+            }
+            let transactions = client
+                .get_transactions_async(version, 1, ledger_version, false)
+                .await?;
+            let transaction_info = transactions
+                .proof
+                .transaction_infos()
+                .first()
+                .ok_or_else(|| format_err!("no transaction info stored for version {}", version))?;
+            Ok(Some(transaction_info.hash()))
+        }
+            .boxed()
+    }
+
+    fn get_frozen_subtree_hashes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<HashValue>>> + Send>> {
+        let client = Arc::clone(&self.storage_read_client);
+        async move {
+            let startup_info = client
+                .get_startup_info_async()
+                .await?
+                .ok_or_else(|| format_err!("storage is not bootstrapped with the genesis yet"))?;
+            let tree_state = startup_info
+                .synced_tree_state
+                .unwrap_or(startup_info.committed_tree_state);
+            Ok(tree_state.ledger_frozen_subtree_hashes)
+        }
+            .boxed()
     }
 }
@@ -9,12 +9,22 @@ extern crate prometheus;
 
 use libra_types::{account_address::AccountAddress, crypto_proxies::LedgerInfoWithSignatures};
 
-pub use synchronizer::{StateSyncClient, StateSynchronizer};
+pub use coordinator::{
+    ChunkFailure, SyncCompletionInfo, SyncError, SyncEvent, SyncPurpose, SyncSessionMetrics,
+    VersionAudit,
+};
+pub use executor::CommittedTransactionData;
+pub use executor_proxy::{EpochProofPage, TransientError};
+pub use session::{replay_session, SessionEvent};
+pub use synchronizer::{BuildError, StateSyncClient, StateSynchronizer, StateSynchronizerBuilder};
 
+mod checkpoint;
+mod clock;
 mod coordinator;
 mod counters;
 mod executor_proxy;
 mod peer_manager;
+mod session;
 mod synchronizer;
 
 type PeerId = AccountAddress;
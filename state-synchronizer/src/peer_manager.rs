@@ -1,7 +1,12 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::{counters, PeerId};
+use crate::{
+    clock::{Clock, SystemClock},
+    counters, PeerId,
+};
+use futures::channel::mpsc;
+use libra_crypto::ShortStr;
 use libra_logger::prelude::*;
 use network::validator_network::StateSynchronizerSender;
 use rand::{
@@ -9,18 +14,44 @@ use rand::{
     thread_rng,
 };
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
-    time::SystemTime,
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
 const MAX_SCORE: f64 = 100.0;
 const MIN_SCORE: f64 = 1.0;
+/// Weight of a new sample in the moving average of a peer's response latency
+const LATENCY_SMOOTHING: f64 = 0.2;
+/// Peers scoring within this much of the top score are picked in turn, see `pick_peer`
+const DEFAULT_TIE_SCORE_EPSILON: f64 = 1.0;
 
 #[derive(Default, Debug, Clone)]
 pub struct PeerInfo {
     is_alive: bool,
     is_upstream: bool,
     score: f64,
+    // moving average of the response latency in seconds, if any response or timeout was seen
+    avg_latency_secs: Option<f64>,
+    // the peer is not picked for requests until then
+    evicted_until: Option<SystemTime>,
+    // state sync protocol version advertised in the last message of the peer, if any
+    protocol_version: Option<u64>,
+    // index of the network the peer is connected through
+    network: usize,
+    // version of the latest ledger info summary gossiped by the peer, or of the latest ledger info
+    // of a chunk it sent, if any
+    advertised_version: Option<u64>,
+    // whether `advertised_version` is only a hint loaded from a checkpoint, not heard from the
+    // peer yet
+    is_advertised_version_hint: bool,
+    // when the latest ledger info summary of the peer was received, to rate limit them
+    last_summary_time: Option<SystemTime>,
+    // when the latest root hash request of the peer was served, to rate limit them
+    last_audit_request_time: Option<SystemTime>,
+    // times of the request timeouts of the peer within the timeout grace period, oldest first
+    recent_timeouts: VecDeque<SystemTime>,
 }
 
 impl PeerInfo {
@@ -29,10 +60,46 @@ impl PeerInfo {
             is_alive,
             is_upstream,
             score,
+            avg_latency_secs: None,
+            evicted_until: None,
+            protocol_version: None,
+            network: 0,
+            advertised_version: None,
+            is_advertised_version_hint: false,
+            last_summary_time: None,
+            last_audit_request_time: None,
+            recent_timeouts: VecDeque::new(),
+        }
+    }
+
+    /// Records a request timeout of the peer at `now`, forgetting the ones older than
+    /// `grace_period`. Returns whether it timed out before within `grace_period`.
+    fn record_timeout(&mut self, now: SystemTime, grace_period: Duration) -> bool {
+        while let Some(oldest) = self.recent_timeouts.front() {
+            match now.duration_since(*oldest) {
+                Ok(age) if age >= grace_period => {
+                    self.recent_timeouts.pop_front();
+                }
+                _ => break,
+            }
         }
+        self.recent_timeouts.push_back(now);
+        self.recent_timeouts.len() > 1
     }
 }
 
+/// Why a peer known to the peer manager is not picked for requests.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum InactiveReason {
+    NotUpstream,
+    Disconnected,
+    /// Evicted for being the slowest peer, until the cooldown passes.
+    Evicted,
+    /// The peer speaks the given protocol version, below the minimum supported one. It is picked
+    /// again once it upgrades or the minimum is lowered.
+    IncompatibleProtocolVersion(u64),
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum PeerScoreUpdateType {
     Success,
@@ -45,11 +112,36 @@ pub struct PeerManager {
     network_senders: HashMap<PeerId, StateSynchronizerSender>,
     // Latest requested block versions from a peer
     requests: BTreeMap<u64, (PeerId, SystemTime)>,
+    // Requests re-sent to a second peer because the first one was slow to respond
+    hedged_requests: BTreeMap<u64, (PeerId, SystemTime)>,
+    // Peers that lost the race for a hedged request, whose late response is dropped silently
+    hedge_losers: BTreeMap<u64, (PeerId, SystemTime)>,
     weighted_index: Option<WeightedIndex<f64>>,
+    // Peers within this much of the top score count as equally good
+    tie_score_epsilon: f64,
+    // Rotates over the equally good peers
+    tie_cursor: usize,
+    // Number of times each peer was picked for a request
+    selection_counts: HashMap<PeerId, u64>,
+    // peers advertising an older state sync protocol version are not picked
+    min_protocol_version: u64,
+    // weight of the peers of each network, by network index; missing networks weigh 1
+    network_weights: Vec<f64>,
+    // if set, a timeout only lowers the score of a peer that timed out before within this long
+    timeout_grace_period: Option<Duration>,
+    // whether any peer could be picked for requests as of the latest `compute_weighted_index`
+    has_usable_peers: bool,
+    // notified when `has_usable_peers` changes, see `subscribe_usable_peers`
+    usable_peers_subscribers: Vec<mpsc::UnboundedSender<bool>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl PeerManager {
     pub fn new(peer_ids: Vec<PeerId>) -> Self {
+        Self::new_with_clock(peer_ids, Arc::new(SystemClock))
+    }
+
+    pub(crate) fn new_with_clock(peer_ids: Vec<PeerId>, clock: Arc<dyn Clock>) -> Self {
         let peers = peer_ids
             .into_iter()
             .map(|peer_id| (peer_id, PeerInfo::new(false, true, MAX_SCORE)))
@@ -58,10 +150,47 @@ impl PeerManager {
             peers,
             network_senders: HashMap::new(),
             requests: BTreeMap::new(),
+            hedged_requests: BTreeMap::new(),
+            hedge_losers: BTreeMap::new(),
             weighted_index: None,
+            tie_score_epsilon: DEFAULT_TIE_SCORE_EPSILON,
+            tie_cursor: 0,
+            selection_counts: HashMap::new(),
+            min_protocol_version: 0,
+            network_weights: vec![],
+            timeout_grace_period: None,
+            has_usable_peers: false,
+            usable_peers_subscribers: vec![],
+            clock,
         }
     }
 
+    pub fn set_tie_score_epsilon(&mut self, tie_score_epsilon: f64) {
+        self.tie_score_epsilon = tie_score_epsilon;
+    }
+
+    /// Sets the weight the peers of each network get when picking peers, by network index. A peer
+    /// is picked with a probability proportional to its score times the weight of its network.
+    pub fn set_network_weights(&mut self, network_weights: Vec<f64>) {
+        self.network_weights = network_weights;
+        self.compute_weighted_index();
+    }
+
+    /// Sets how long a peer is given to recover from a request timeout: with a grace period, a
+    /// timeout only lowers the score of the peer if it already timed out within that period.
+    pub fn set_timeout_grace_period(&mut self, timeout_grace_period: Option<Duration>) {
+        self.timeout_grace_period = timeout_grace_period;
+    }
+
+    fn network_weight(&self, network: usize) -> f64 {
+        self.network_weights.get(network).cloned().unwrap_or(1.0)
+    }
+
+    /// Score of the peer weighted by the priority of its network, used to pick peers.
+    fn weighted_score(&self, peer_info: &PeerInfo) -> f64 {
+        peer_info.score * self.network_weight(peer_info.network)
+    }
+
     pub fn set_peers(&mut self, peer_ids: Vec<PeerId>) {
         let new_peer_ids: HashSet<_> = peer_ids.iter().collect();
         for (peer_id, info) in self.peers.iter_mut() {
@@ -77,15 +206,21 @@ impl PeerManager {
         debug!("[state sync] (set_peers) state: {:?}", self.peers);
     }
 
-    pub fn enable_peer(&mut self, peer_id: PeerId, sender: StateSynchronizerSender) {
+    /// Marks `peer_id` connected through the network at index `network`, reached with `sender`.
+    pub fn enable_peer(
+        &mut self,
+        peer_id: PeerId,
+        network: usize,
+        sender: StateSynchronizerSender,
+    ) {
         debug!("[state sync] state before: {:?}", self.peers);
         self.network_senders.insert(peer_id, sender);
-        if let Some(peer_info) = self.peers.get_mut(&peer_id) {
-            peer_info.is_alive = true;
-        } else {
-            self.peers
-                .insert(peer_id, PeerInfo::new(true, false, MAX_SCORE));
-        }
+        let peer_info = self
+            .peers
+            .entry(peer_id)
+            .or_insert_with(|| PeerInfo::new(false, false, MAX_SCORE));
+        peer_info.is_alive = true;
+        peer_info.network = network;
         self.compute_weighted_index();
         debug!("[state sync] state after: {:?}", self.peers);
     }
@@ -96,6 +231,61 @@ impl PeerManager {
             peer_info.is_alive = false;
         };
         self.compute_weighted_index();
+        // a disabled peer will never answer its outstanding requests
+        self.remove_requests_for_peer(peer_id);
+    }
+
+    /// Records the protocol version advertised by `peer_id`. A peer below the minimum stops being
+    /// picked and its pending requests are dropped, without touching its score.
+    pub fn set_protocol_version(&mut self, peer_id: &PeerId, protocol_version: u64) {
+        let was_compatible = self.is_compatible(peer_id);
+        if let Some(peer_info) = self.peers.get_mut(peer_id) {
+            peer_info.protocol_version = Some(protocol_version);
+        }
+        let is_compatible = self.is_compatible(peer_id);
+        if was_compatible != is_compatible {
+            self.compute_weighted_index();
+        }
+        if !is_compatible {
+            self.remove_requests_for_peer(peer_id);
+        }
+    }
+
+    /// Sets the minimum state sync protocol version of the peers to pick. Peers excluded by the
+    /// previous minimum but not by this one are picked again.
+    pub fn set_min_protocol_version(&mut self, min_protocol_version: u64) {
+        self.min_protocol_version = min_protocol_version;
+        self.compute_weighted_index();
+    }
+
+    fn is_compatible(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .map_or(true, |peer_info| self.is_compatible_info(peer_info))
+    }
+
+    fn is_compatible_info(&self, peer_info: &PeerInfo) -> bool {
+        peer_info
+            .protocol_version
+            .map_or(true, |version| version >= self.min_protocol_version)
+    }
+
+    /// Why `peer_id` is not picked for requests, `None` if it is active or unknown.
+    pub fn inactive_reason(&self, peer_id: &PeerId) -> Option<InactiveReason> {
+        let peer_info = self.peers.get(peer_id)?;
+        if !peer_info.is_upstream {
+            Some(InactiveReason::NotUpstream)
+        } else if !peer_info.is_alive {
+            Some(InactiveReason::Disconnected)
+        } else if !self.is_compatible_info(peer_info) {
+            peer_info
+                .protocol_version
+                .map(InactiveReason::IncompatibleProtocolVersion)
+        } else if peer_info.evicted_until.is_some() {
+            Some(InactiveReason::Evicted)
+        } else {
+            None
+        }
     }
 
     pub fn is_empty(&self) -> bool {
@@ -103,6 +293,8 @@ impl PeerManager {
     }
 
     pub fn update_score(&mut self, peer_id: &PeerId, update_type: PeerScoreUpdateType) {
+        let now = self.clock.now();
+        let timeout_grace_period = self.timeout_grace_period;
         if let Some(peer_info) = self.peers.get_mut(peer_id) {
             let old_score = peer_info.score;
             match update_type {
@@ -115,6 +307,15 @@ impl PeerManager {
                     peer_info.score = new_score.max(MIN_SCORE);
                 }
                 PeerScoreUpdateType::TimeOut => {
+                    if let Some(grace_period) = timeout_grace_period {
+                        if !peer_info.record_timeout(now, grace_period) {
+                            debug!(
+                                "[state sync] first recent timeout of {}, not penalized",
+                                peer_id.short_str()
+                            );
+                            return;
+                        }
+                    }
                     let new_score = peer_info.score * 0.95;
                     peer_info.score = new_score.max(MIN_SCORE);
                 }
@@ -125,14 +326,24 @@ impl PeerManager {
         }
     }
 
+    /// Returns a stream of the changes of whether any peer can be picked for requests: `false`
+    /// once the last usable peer is disconnected or otherwise stops being picked, `true` once one
+    /// can be picked again. No peer is usable until the first one connects.
+    pub fn subscribe_usable_peers(&mut self) -> mpsc::UnboundedReceiver<bool> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.usable_peers_subscribers.push(sender);
+        receiver
+    }
+
     fn compute_weighted_index(&mut self) {
         let active_peers = self.get_active_upstream_peers();
         counters::ACTIVE_UPSTREAM_PEERS.set(active_peers.len() as i64);
+        let has_usable_peers = !active_peers.is_empty();
 
         if !active_peers.is_empty() {
             let weights: Vec<_> = active_peers
                 .iter()
-                .map(|(_, peer_info)| peer_info.score)
+                .map(|(_, peer_info)| self.weighted_score(peer_info))
                 .collect();
             match WeightedIndex::new(&weights) {
                 Ok(weighted_index) => {
@@ -146,68 +357,452 @@ impl PeerManager {
                 }
             }
         }
+
+        if has_usable_peers != self.has_usable_peers {
+            self.has_usable_peers = has_usable_peers;
+            self.usable_peers_subscribers
+                .retain(|subscriber| subscriber.unbounded_send(has_usable_peers).is_ok());
+        }
     }
 
-    pub fn pick_peer(&self) -> Option<(PeerId, StateSynchronizerSender)> {
-        let active_peers = self.get_active_upstream_peers();
+    /// Picks a peer at random weighted by score and network weight. If the sampled peer is among
+    /// the top scored ones (within the tie epsilon), the top scored peers are picked in turn
+    /// instead, so that equally healthy peers get an even share of the requests.
+    pub fn pick_peer(&mut self) -> Option<(PeerId, StateSynchronizerSender)> {
         debug!("[state sync] (pick_peer) state: {:?}", self.peers);
+        let candidates = self.get_candidates(None);
+        let index = self.weighted_index.as_ref()?.sample(&mut thread_rng());
+        let peer_id = self.resolve_tie(&candidates, index)?;
+        match self.get_network_sender(&peer_id) {
+            Some(sender) => {
+                *self.selection_counts.entry(peer_id).or_insert(0) += 1;
+                Some((peer_id, sender))
+            }
+            None => {
+                debug!(
+                    "[state sync] (pick_peer) no sender for {}",
+                    peer_id.short_str()
+                );
+                None
+            }
+        }
+    }
+
+    /// Picks a peer like `pick_peer`, but never `excluded`
+    pub fn pick_peer_excluding(
+        &mut self,
+        excluded: &PeerId,
+    ) -> Option<(PeerId, StateSynchronizerSender)> {
+        let candidates = self.get_candidates(Some(excluded));
+        let weights: Vec<_> = candidates.iter().map(|(_, score)| *score).collect();
+        let weighted_index = WeightedIndex::new(&weights).ok()?;
+        let peer_id = self.resolve_tie(&candidates, weighted_index.sample(&mut thread_rng()))?;
+        let sender = self.get_network_sender(&peer_id)?;
+        *self.selection_counts.entry(peer_id).or_insert(0) += 1;
+        Some((peer_id, sender))
+    }
+
+    /// Picks one of the top scored peers, never sampling the lower scored ones like `pick_peer`
+    /// does. Used when the latency of a sync matters more than spreading the load.
+    pub fn pick_best_peer(&mut self) -> Option<(PeerId, StateSynchronizerSender)> {
+        let candidates = self.get_candidates(None);
+        let best_index = (0..candidates.len()).max_by(|a, b| {
+            candidates[*a]
+                .1
+                .partial_cmp(&candidates[*b].1)
+                .unwrap_or(Ordering::Equal)
+        })?;
+        let peer_id = self.resolve_tie(&candidates, best_index)?;
+        let sender = self.get_network_sender(&peer_id)?;
+        *self.selection_counts.entry(peer_id).or_insert(0) += 1;
+        Some((peer_id, sender))
+    }
 
-        if let Some(weighted_index) = &self.weighted_index {
-            let mut rng = thread_rng();
-            if let Some(peer) = active_peers.get(weighted_index.sample(&mut rng)) {
-                let peer_id = *peer.0;
-                if let Some(sender) = self.get_network_sender(&peer_id) {
-                    return Some((peer_id, sender));
-                } else {
-                    debug!("[state sync] (pick_peer) no sender for {}", peer_id);
+    /// Active upstream peers and their scores weighted by network, in the order the weighted index
+    /// refers to them.
+    fn get_candidates(&self, excluded: Option<&PeerId>) -> Vec<(PeerId, f64)> {
+        self.get_active_upstream_peers()
+            .into_iter()
+            .filter(|(peer_id, _)| Some(*peer_id) != excluded)
+            .map(|(peer_id, peer_info)| (*peer_id, self.weighted_score(peer_info)))
+            .collect()
+    }
+
+    /// Returns the candidate at `index`, unless it is within the tie epsilon of the top score, in
+    /// which case the next top scored candidate in round robin order is returned.
+    fn resolve_tie(&mut self, candidates: &[(PeerId, f64)], index: usize) -> Option<PeerId> {
+        let (sampled, score) = *candidates.get(index)?;
+        let top_score = candidates
+            .iter()
+            .map(|(_, score)| *score)
+            .fold(MIN_SCORE, f64::max);
+        let tie_threshold = top_score - self.tie_score_epsilon;
+        if score < tie_threshold {
+            return Some(sampled);
+        }
+        let mut tied: Vec<_> = candidates
+            .iter()
+            .filter(|(_, score)| *score >= tie_threshold)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        tied.sort();
+        let peer_id = tied[self.tie_cursor % tied.len()];
+        self.tie_cursor = self.tie_cursor.wrapping_add(1);
+        Some(peer_id)
+    }
+
+    /// Number of times each peer was picked for a request.
+    pub fn selection_counts(&self) -> &HashMap<PeerId, u64> {
+        &self.selection_counts
+    }
+
+    /// Records the version `peer_id` advertised in a ledger info summary, and returns whether it
+    /// did. Summaries of unknown peers, and summaries arriving less than `min_interval` after the
+    /// previous one of the peer, are dropped.
+    pub fn process_ledger_info_summary(
+        &mut self,
+        peer_id: &PeerId,
+        version: u64,
+        min_interval: Duration,
+    ) -> bool {
+        let now = self.clock.now();
+        let peer_info = match self.peers.get_mut(peer_id) {
+            Some(peer_info) => peer_info,
+            None => return false,
+        };
+        if let Some(last_summary_time) = peer_info.last_summary_time {
+            if now
+                .duration_since(last_summary_time)
+                .map_or(true, |elapsed| elapsed < min_interval)
+            {
+                return false;
+            }
+        }
+        peer_info.last_summary_time = Some(now);
+        peer_info.advertised_version = Some(version);
+        peer_info.is_advertised_version_hint = false;
+        true
+    }
+
+    /// Records that `peer_id` has the ledger info at `version`, as seen in a chunk it sent. A hint
+    /// loaded from a checkpoint is replaced, otherwise the advertised version only moves forward.
+    pub fn record_advertised_version(&mut self, peer_id: &PeerId, version: u64) {
+        if let Some(peer_info) = self.peers.get_mut(peer_id) {
+            peer_info.advertised_version = Some(match peer_info.advertised_version {
+                Some(advertised_version) if !peer_info.is_advertised_version_hint => {
+                    std::cmp::max(advertised_version, version)
                 }
+                _ => version,
+            });
+            peer_info.is_advertised_version_hint = false;
+        }
+    }
+
+    /// Seeds the version advertised by `peer_id` with a hint loaded from a checkpoint, replaced as
+    /// soon as the peer is heard from. Only known peers not heard from yet are seeded.
+    pub fn seed_advertised_version(&mut self, peer_id: &PeerId, version: u64) {
+        if let Some(peer_info) = self.peers.get_mut(peer_id) {
+            if peer_info.advertised_version.is_none() {
+                peer_info.advertised_version = Some(version);
+                peer_info.is_advertised_version_hint = true;
+            }
+        }
+    }
+
+    /// Versions advertised by the upstream peers that advertised one, including hints.
+    pub fn upstream_advertised_versions(&self) -> Vec<(PeerId, u64)> {
+        self.peers
+            .iter()
+            .filter(|(_, peer_info)| peer_info.is_upstream)
+            .filter_map(|(peer_id, peer_info)| {
+                peer_info
+                    .advertised_version
+                    .map(|version| (*peer_id, version))
+            })
+            .collect()
+    }
+
+    /// Tells whether a root hash request of `peer_id` is to be served, i.e. at least
+    /// `min_interval` passed since the previous one served.
+    pub fn process_audit_request(&mut self, peer_id: &PeerId, min_interval: Duration) -> bool {
+        let now = self.clock.now();
+        let peer_info = match self.peers.get_mut(peer_id) {
+            Some(peer_info) => peer_info,
+            None => return false,
+        };
+        if let Some(last_request_time) = peer_info.last_audit_request_time {
+            if now
+                .duration_since(last_request_time)
+                .map_or(true, |elapsed| elapsed < min_interval)
+            {
+                return false;
             }
         }
-        None
+        peer_info.last_audit_request_time = Some(now);
+        true
+    }
+
+    /// Version of the latest ledger info summary gossiped by `peer_id`, or of the latest ledger
+    /// info of a chunk it sent, if any.
+    pub fn advertised_version(&self, peer_id: &PeerId) -> Option<u64> {
+        self.peers
+            .get(peer_id)
+            .and_then(|peer_info| peer_info.advertised_version)
+    }
+
+    /// Current score of `peer_id`, if known.
+    pub fn score(&self, peer_id: &PeerId) -> Option<f64> {
+        self.peers.get(peer_id).map(|peer_info| peer_info.score)
+    }
+
+    /// Whether `peer_id` can be picked for requests.
+    pub fn is_active(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .map_or(false, |peer_info| self.is_active_info(peer_info))
+    }
+
+    fn is_active_info(&self, peer_info: &PeerInfo) -> bool {
+        peer_info.is_alive
+            && peer_info.is_upstream
+            && peer_info.evicted_until.is_none()
+            && self.is_compatible_info(peer_info)
+    }
+
+    /// The connected peers, upstream or not, with their network senders.
+    pub fn connected_peers(&self) -> Vec<(PeerId, StateSynchronizerSender)> {
+        self.network_senders
+            .iter()
+            .map(|(peer_id, sender)| (*peer_id, sender.clone()))
+            .collect()
+    }
+
+    /// Whether the peer is currently configured as upstream.
+    pub fn is_upstream(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .map_or(false, |peer_info| peer_info.is_upstream)
     }
 
     fn get_active_upstream_peers(&self) -> Vec<(&PeerId, &PeerInfo)> {
         self.peers
             .iter()
-            .filter(|&(_, peer_info)| peer_info.is_alive && peer_info.is_upstream)
+            .filter(|&(_, peer_info)| self.is_active_info(peer_info))
             .collect()
     }
 
+    fn record_latency(&mut self, peer_id: &PeerId, latency: Duration) {
+        if let Some(peer_info) = self.peers.get_mut(peer_id) {
+            let sample = latency.as_secs_f64();
+            peer_info.avg_latency_secs = Some(match peer_info.avg_latency_secs {
+                Some(avg) => avg + LATENCY_SMOOTHING * (sample - avg),
+                None => sample,
+            });
+        }
+    }
+
+    /// Evicts the active peer with the highest response latency for `cooldown`, unless it is the
+    /// last active peer. Returns the evicted peer.
+    pub fn evict_slowest_peer(&mut self, cooldown: Duration) -> Option<PeerId> {
+        let active_peers = self.get_active_upstream_peers();
+        if active_peers.len() < 2 {
+            return None;
+        }
+        let slowest = active_peers
+            .into_iter()
+            .filter_map(|(peer_id, peer_info)| {
+                peer_info
+                    .avg_latency_secs
+                    .map(|latency| (*peer_id, latency))
+            })
+            .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(peer_id, _)| peer_id)?;
+        let evicted_until = self.clock.now().checked_add(cooldown)?;
+        if let Some(peer_info) = self.peers.get_mut(&slowest) {
+            peer_info.evicted_until = Some(evicted_until);
+        }
+        self.compute_weighted_index();
+        debug!(
+            "[state sync] evicted slowest peer {} for {:?}",
+            slowest.short_str(),
+            cooldown
+        );
+        Some(slowest)
+    }
+
+    /// Makes peers whose eviction cooldown has passed available for requests again.
+    pub fn readmit_evicted_peers(&mut self) {
+        let now = self.clock.now();
+        let mut readmitted = false;
+        for peer_info in self.peers.values_mut() {
+            if peer_info.evicted_until.map_or(false, |until| until <= now) {
+                peer_info.evicted_until = None;
+                readmitted = true;
+            }
+        }
+        if readmitted {
+            self.compute_weighted_index();
+        }
+    }
+
+    pub fn is_evicted(&self, peer_id: &PeerId) -> bool {
+        self.peers
+            .get(peer_id)
+            .map_or(false, |peer_info| peer_info.evicted_until.is_some())
+    }
+
     pub fn get_network_sender(&self, peer_id: &PeerId) -> Option<StateSynchronizerSender> {
         self.network_senders.get(peer_id).cloned()
     }
 
     pub fn process_request(&mut self, version: u64, peer_id: PeerId) {
-        self.requests.insert(version, (peer_id, SystemTime::now()));
+        self.requests.insert(version, (peer_id, self.clock.now()));
+        self.hedged_requests.remove(&version);
+        self.update_pending_requests();
+    }
+
+    /// Records that the request for `version` was also sent to `peer_id`
+    pub fn process_hedged_request(&mut self, version: u64, peer_id: PeerId) {
+        if self.requests.contains_key(&version) {
+            self.hedged_requests
+                .insert(version, (peer_id, self.clock.now()));
+        }
+    }
+
+    pub fn get_request(&self, version: u64) -> Option<(PeerId, SystemTime)> {
+        self.requests.get(&version).cloned()
     }
 
     pub fn get_request_time(&self, version: u64) -> Option<SystemTime> {
         self.requests.get(&version).map(|(_, tst)| tst).cloned()
     }
 
+    pub fn is_hedged(&self, version: u64) -> bool {
+        self.hedged_requests.contains_key(&version)
+    }
+
+    /// Removes the request for `version` if `peer_id` was asked for it. If the request was
+    /// hedged, the other peer is remembered so that its late response can be recognized as a
+    /// duplicate.
     pub fn process_response(&mut self, version: u64, peer_id: PeerId) {
-        if let Some((id, _)) = self.requests.get(&version) {
-            if *id == peer_id {
-                self.requests.remove(&version);
+        if !self.has_requested(version, peer_id) {
+            return;
+        }
+        let now = self.clock.now();
+        let primary = self.requests.remove(&version);
+        let hedge = self.hedged_requests.remove(&version);
+        let request_time = [primary, hedge]
+            .iter()
+            .filter_map(|request| *request)
+            .find(|(id, _)| *id == peer_id)
+            .map(|(_, request_time)| request_time);
+        if let Some(latency) = request_time.and_then(|tst| now.duration_since(tst).ok()) {
+            self.record_latency(&peer_id, latency);
+        }
+        if let Some((hedge_peer_id, _)) = hedge {
+            let loser = if hedge_peer_id == peer_id {
+                counters::HEDGE_WINS.inc();
+                primary.map(|(primary_peer_id, _)| primary_peer_id)
+            } else {
+                Some(hedge_peer_id)
+            };
+            if let Some(loser) = loser {
+                self.hedge_losers.insert(version, (loser, now));
             }
         }
+        self.update_pending_requests();
     }
 
     pub fn has_requested(&self, version: u64, peer_id: PeerId) -> bool {
-        if let Some((id, _)) = self.requests.get(&version) {
-            return *id == peer_id;
+        [&self.requests, &self.hedged_requests]
+            .iter()
+            .any(|requests| match requests.get(&version) {
+                Some((id, _)) => *id == peer_id,
+                None => false,
+            })
+    }
+
+    /// Returns true (once) if this is the late response of the peer that lost the race for the
+    /// hedged request for `version`.
+    pub fn is_duplicate_response(&mut self, version: u64, peer_id: PeerId) -> bool {
+        match self.hedge_losers.get(&version) {
+            Some((loser, _)) if *loser == peer_id => {
+                self.hedge_losers.remove(&version);
+                true
+            }
+            _ => false,
         }
-        false
     }
 
     pub fn remove_requests(&mut self, version: u64) {
         self.requests = self.requests.split_off(&(version + 1));
+        self.hedged_requests = self.hedged_requests.split_off(&(version + 1));
+        self.update_pending_requests();
+    }
+
+    /// Removes all request records sent before `expiry`. Such requests are not expected to be
+    /// answered anymore, e.g. because the sync they were part of got superseded.
+    pub fn remove_expired_requests(&mut self, expiry: SystemTime) {
+        self.remove_requests_matching(|_, request_time| request_time < expiry);
+    }
+
+    /// Pushes back the pending requests by `delay`, a time during which their responses could not
+    /// be processed, so that it is not held against the peers they were sent to.
+    pub fn defer_requests(&mut self, delay: Duration) {
+        for requests in &mut [
+            &mut self.requests,
+            &mut self.hedged_requests,
+            &mut self.hedge_losers,
+        ] {
+            for (_, request_time) in requests.values_mut() {
+                *request_time += delay;
+            }
+        }
+    }
+
+    fn remove_requests_for_peer(&mut self, peer_id: &PeerId) {
+        self.remove_requests_matching(|id, _| id == peer_id);
+    }
+
+    fn remove_requests_matching(&mut self, predicate: impl Fn(&PeerId, SystemTime) -> bool) {
+        for requests in &mut [
+            &mut self.requests,
+            &mut self.hedged_requests,
+            &mut self.hedge_losers,
+        ] {
+            let versions: Vec<_> = requests
+                .iter()
+                .filter(|(_, (peer_id, request_time))| predicate(peer_id, *request_time))
+                .map(|(version, _)| *version)
+                .collect();
+            for version in versions {
+                requests.remove(&version);
+            }
+        }
+        self.update_pending_requests();
+    }
+
+    pub fn num_requests(&self) -> usize {
+        self.requests.len()
+    }
+
+    fn update_pending_requests(&self) {
+        counters::PENDING_REQUESTS.set(self.requests.len() as i64);
     }
 
     pub fn process_timeout(&mut self, version: u64, penalize: bool) {
-        if let Some((peer_id, _)) = self.requests.remove(&version) {
+        let hedge = self.hedged_requests.remove(&version);
+        if let Some((peer_id, request_time)) = self.requests.remove(&version) {
+            self.update_pending_requests();
+            // the peer took at least this long
+            if let Ok(latency) = self.clock.now().duration_since(request_time) {
+                self.record_latency(&peer_id, latency);
+            }
             if penalize {
                 self.update_score(&peer_id, PeerScoreUpdateType::TimeOut);
+                if let Some((hedge_peer_id, _)) = hedge {
+                    self.update_score(&hedge_peer_id, PeerScoreUpdateType::TimeOut);
+                }
             }
         }
     }
@@ -0,0 +1,88 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recording and replay of the inputs of the coordinator, a debugging aid enabled by
+//! `record_session`. A recorded session fed back through the handlers of a fresh coordinator
+//! reproduces the chunks it applied, e.g. against a copy of the storage the session started from.
+
+use crate::{
+    coordinator::{SyncCoordinator, SyncPurpose},
+    executor_proxy::ExecutorProxyTrait,
+    PeerId,
+};
+use channel::keyed_watch;
+use failure::prelude::*;
+use futures::channel::mpsc;
+use libra_config::config::{RoleType, StateSyncConfig};
+use libra_types::crypto_proxies::LedgerInfoWithSignatures;
+use network::{proto::StateSynchronizerMsg, validator_network::StateSynchronizerSender};
+use tokio::runtime::TaskExecutor;
+
+/// An input of the coordinator, recorded in the order it was handled. Ticks are not recorded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    /// A request to sync to `target`.
+    SyncRequest {
+        target: LedgerInfoWithSignatures,
+        purpose: SyncPurpose,
+    },
+    /// A query of the known version.
+    GetState,
+    /// A query of whether a version is committed.
+    IsCommitted(u64),
+    /// A wait for a version to be committed.
+    WaitForVersion(u64),
+    /// A query of an epoch change proof.
+    GetEpochProof {
+        start_epoch: u64,
+        end_epoch: Option<u64>,
+        limit: Option<u64>,
+    },
+    /// A query of the ledger info at a version.
+    GetLedgerInfo(u64),
+    /// A query of why the last chunk request or response failed.
+    GetLastError,
+    /// A query of the metrics of the pending sync session.
+    GetSessionMetrics,
+    /// An audit of the transaction infos at `versions` against `peer_id`.
+    AuditState { peer_id: PeerId, versions: Vec<u64> },
+    /// A replacement of the upstream peers.
+    UpdateUpstreamPeers(Vec<PeerId>),
+    /// A replacement of the minimum supported protocol version.
+    UpdateMinProtocolVersion(u64),
+    /// A commit notification.
+    Commit(u64),
+    /// A new connection to a peer, through the network at the given index.
+    NewPeer(PeerId, usize),
+    /// A lost connection to a peer.
+    LostPeer(PeerId),
+    /// A request to shut down, which drains the long-poll subscriptions.
+    Shutdown,
+    /// A message received from a peer. Only chunk responses are recorded.
+    Message(PeerId, StateSynchronizerMsg),
+}
+
+/// Feeds `events` recorded by a coordinator through the handlers of a fresh coordinator over
+/// `executor_proxy`, and returns the version it reached. Queries are skipped and the replies to
+/// sync requests are dropped. The peers connect through `network_sender`, which gets the requests
+/// sent meanwhile.
+pub async fn replay_session<E: ExecutorProxyTrait + 'static>(
+    role: RoleType,
+    config: StateSyncConfig,
+    executor_proxy: E,
+    executor: TaskExecutor,
+    network_sender: StateSynchronizerSender,
+    events: Vec<SessionEvent>,
+) -> Result<u64> {
+    let (_client_sender, client_events) = mpsc::unbounded();
+    let (_commit_sender, commit_notifications) = keyed_watch::new(None);
+    let mut coordinator = SyncCoordinator::new(
+        client_events,
+        commit_notifications,
+        role,
+        config,
+        executor_proxy,
+        executor,
+    );
+    coordinator.replay(events, network_sender).await
+}
@@ -2,50 +2,217 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::coordinator::EpochRetrievalRequest;
 use crate::{
-    coordinator::{CoordinatorMessage, SyncCoordinator, SyncRequest},
-    executor_proxy::{ExecutorProxy, ExecutorProxyTrait},
+    coordinator::{
+        AuditRequest, ChunkFailure, CoordinatorMessage, SyncCompletionInfo, SyncCoordinator,
+        SyncError, SyncEvent, SyncPurpose, SyncRequest, SyncSessionMetrics, VersionAudit,
+    },
+    counters,
+    executor_proxy::{EpochProofPage, ExecutorProxy, ExecutorProxyTrait},
+    session::SessionEvent,
+    PeerId,
 };
+use channel::keyed_watch;
 use executor::Executor;
 use failure::prelude::*;
 use futures::{
     channel::{mpsc, oneshot},
-    future::Future,
-    SinkExt,
+    executor::block_on,
+    future::{self, Future},
+    SinkExt, StreamExt,
 };
-use libra_config::config::{NodeConfig, RoleType, StateSyncConfig};
+use libra_config::config::{NodeConfig, PersistableConfig, RoleType, StateSyncConfig};
+use libra_logger::prelude::*;
 use libra_types::crypto_proxies::LedgerInfoWithSignatures;
-use libra_types::crypto_proxies::ValidatorChangeEventWithProof;
 use network::validator_network::{StateSynchronizerEvents, StateSynchronizerSender};
-use std::sync::Arc;
-use tokio::runtime::{Builder, Runtime};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{
+    runtime::{Builder, Runtime},
+    timer::Interval,
+};
 use vm_runtime::MoveVM;
 
 pub struct StateSynchronizer {
-    _runtime: Runtime,
+    runtime: Runtime,
     coordinator_sender: mpsc::UnboundedSender<CoordinatorMessage>,
+    commit_sender: keyed_watch::Sender<(), u64>,
+    // inputs of the coordinator, if `record_session` is set
+    session_recording: Option<mpsc::UnboundedReceiver<SessionEvent>>,
+}
+
+/// Pieces missing from a `StateSynchronizerBuilder` to build a `StateSynchronizer`.
+#[derive(Clone, Copy, Debug, Eq, Fail, PartialEq)]
+pub enum BuildError {
+    /// No network was given to sync through, see `with_network`.
+    #[fail(display = "No network to sync through")]
+    MissingNetwork,
+    /// No executor proxy was given to apply chunks with, see `with_executor_proxy`.
+    #[fail(display = "No executor proxy to apply chunks with")]
+    MissingExecutorProxy,
+    /// The role of the node was not given, see `with_role`.
+    #[fail(display = "No role for the node")]
+    MissingRole,
+}
+
+/// Builds a `StateSynchronizer` out of the networks to sync through, the executor proxy applying
+/// the chunks and the role of the node, which are required. The config defaults to
+/// `StateSyncConfig::default()` and no commit or event listener is set.
+pub struct StateSynchronizerBuilder<E> {
+    networks: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
+    executor_proxy: Option<E>,
+    role: Option<RoleType>,
+    config: StateSyncConfig,
+    commit_listener: Option<mpsc::UnboundedSender<u64>>,
+    event_listener: Option<mpsc::UnboundedSender<SyncEvent>>,
+}
+
+impl<E: ExecutorProxyTrait + 'static> Default for StateSynchronizerBuilder<E> {
+    fn default() -> Self {
+        Self {
+            networks: vec![],
+            executor_proxy: None,
+            role: None,
+            config: StateSyncConfig::default(),
+            commit_listener: None,
+            event_listener: None,
+        }
+    }
+}
+
+impl StateSynchronizerBuilder<ExecutorProxy> {
+    /// Starts a builder for the node configured by `config`, which applies chunks through
+    /// `executor`. The role and the state sync config are taken from `config`. Fails if the
+    /// storage of the node can't be read.
+    pub fn for_node(executor: Arc<Executor<MoveVM>>, config: &NodeConfig) -> Result<Self> {
+        // the checkpoint file is configured relative to the data directory
+        let mut state_sync_config = config.state_sync.clone();
+        state_sync_config.checkpoint_file = config.get_state_sync_checkpoint_file();
+        Ok(Self::new()
+            .with_executor_proxy(ExecutorProxy::new(executor, config)?)
+            .with_role(config.get_role())
+            .with_config(state_sync_config))
+    }
+}
+
+impl<E: ExecutorProxyTrait + 'static> StateSynchronizerBuilder<E> {
+    /// A builder with none of the required pieces set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a network to sync through. Peers of all the networks added are synced with.
+    pub fn with_network(
+        mut self,
+        sender: StateSynchronizerSender,
+        events: StateSynchronizerEvents,
+    ) -> Self {
+        self.networks.push((sender, events));
+        self
+    }
+
+    /// Adds the networks to sync through, see `with_network`.
+    pub fn with_networks(
+        mut self,
+        networks: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
+    ) -> Self {
+        self.networks.extend(networks);
+        self
+    }
+
+    /// Sets the executor proxy the chunks are applied and served with.
+    pub fn with_executor_proxy(mut self, executor_proxy: E) -> Self {
+        self.executor_proxy = Some(executor_proxy);
+        self
+    }
+
+    /// Sets the role of the node, which decides whom it syncs with.
+    pub fn with_role(mut self, role: RoleType) -> Self {
+        self.role = Some(role);
+        self
+    }
+
+    /// Replaces the default config.
+    pub fn with_config(mut self, config: StateSyncConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Sets a listener the new latest version is sent to after each commit advancing it.
+    /// Versions committed in a row may be reported once, as commit notifications are coalesced.
+    pub fn with_commit_listener(mut self, listener: mpsc::UnboundedSender<u64>) -> Self {
+        self.commit_listener = Some(listener);
+        self
+    }
+
+    /// Sets a listener the milestones of the sync are sent to, see `SyncEvent`.
+    pub fn with_event_listener(mut self, listener: mpsc::UnboundedSender<SyncEvent>) -> Self {
+        self.event_listener = Some(listener);
+        self
+    }
+
+    /// Spawns the coordinator on a runtime of its own, failing if a required piece is missing.
+    pub fn build(self) -> std::result::Result<StateSynchronizer, BuildError> {
+        if self.networks.is_empty() {
+            return Err(BuildError::MissingNetwork);
+        }
+        let executor_proxy = self
+            .executor_proxy
+            .ok_or(BuildError::MissingExecutorProxy)?;
+        let role = self.role.ok_or(BuildError::MissingRole)?;
+        Ok(StateSynchronizer::start(
+            self.networks,
+            role,
+            &self.config,
+            executor_proxy,
+            self.commit_listener,
+            self.event_listener,
+        ))
+    }
 }
 
 impl StateSynchronizer {
     /// Setup state synchronizer. spawns coordinator and downloader routines on executor
+    #[deprecated(note = "use `StateSynchronizerBuilder::for_node` instead")]
     pub fn bootstrap(
         network: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
         executor: Arc<Executor<MoveVM>>,
         config: &NodeConfig,
     ) -> Self {
-        let executor_proxy = ExecutorProxy::new(executor, config);
-        Self::bootstrap_with_executor_proxy(
-            network,
-            config.get_role(),
-            &config.state_sync,
-            executor_proxy,
-        )
+        StateSynchronizerBuilder::for_node(executor, config)
+            .expect("[state synchronizer] failed to read storage")
+            .with_networks(network)
+            .build()
+            .expect("[state synchronizer] failed to build")
     }
 
+    #[deprecated(note = "use `StateSynchronizerBuilder` instead")]
     pub fn bootstrap_with_executor_proxy<E: ExecutorProxyTrait + 'static>(
         network: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
         role: RoleType,
         state_sync_config: &StateSyncConfig,
         executor_proxy: E,
+    ) -> Self {
+        StateSynchronizerBuilder::new()
+            .with_networks(network)
+            .with_role(role)
+            .with_config(state_sync_config.clone())
+            .with_executor_proxy(executor_proxy)
+            .build()
+            .expect("[state synchronizer] failed to build")
+    }
+
+    fn start<E: ExecutorProxyTrait + 'static>(
+        network: Vec<(StateSynchronizerSender, StateSynchronizerEvents)>,
+        role: RoleType,
+        state_sync_config: &StateSyncConfig,
+        executor_proxy: E,
+        commit_listener: Option<mpsc::UnboundedSender<u64>>,
+        event_listener: Option<mpsc::UnboundedSender<SyncEvent>>,
     ) -> Self {
         let runtime = Builder::new()
             .name_prefix("state-sync-")
@@ -54,56 +221,201 @@ impl StateSynchronizer {
         let executor = runtime.executor();
 
         let (coordinator_sender, coordinator_receiver) = mpsc::unbounded();
+        let (commit_sender, commit_receiver) =
+            keyed_watch::new(Some(&counters::COMMIT_NOTIFICATIONS));
 
-        let coordinator = SyncCoordinator::new(
+        let mut coordinator = SyncCoordinator::new(
             coordinator_receiver,
+            commit_receiver,
             role,
             state_sync_config.clone(),
             executor_proxy,
+            executor.clone(),
         );
+        let session_recording = if state_sync_config.record_session {
+            let (recorder, recording) = mpsc::unbounded();
+            coordinator.record_session(recorder);
+            Some(recording)
+        } else {
+            None
+        };
+        if let Some(listener) = commit_listener {
+            coordinator.set_commit_listener(listener);
+        }
+        if let Some(listener) = event_listener {
+            coordinator.set_event_listener(listener);
+        }
         executor.spawn(coordinator.start(network));
 
         Self {
-            _runtime: runtime,
+            runtime,
             coordinator_sender,
+            commit_sender,
+            session_recording,
+        }
+    }
+
+    /// Takes the inputs of the coordinator recorded since bootstrap, and recorded from now on, if
+    /// `record_session` is set. They can be fed to `replay_session` to reproduce the session.
+    pub fn take_session_recording(&mut self) -> Option<mpsc::UnboundedReceiver<SessionEvent>> {
+        self.session_recording.take()
+    }
+
+    /// Stops state synchronization. The long-poll subscribers of this node are served if it has
+    /// data for them, or told that their subscription is closed, before the coordinator stops.
+    pub fn shutdown(self) {
+        let (callback, drained) = oneshot::channel();
+        if self
+            .coordinator_sender
+            .unbounded_send(CoordinatorMessage::Shutdown(callback))
+            .is_ok()
+        {
+            // fails only if the coordinator stopped meanwhile
+            let _ = block_on(drained);
         }
     }
 
     pub fn create_client(&self) -> Arc<StateSyncClient> {
         Arc::new(StateSyncClient {
             coordinator_sender: self.coordinator_sender.clone(),
+            commit_sender: self.commit_sender.clone(),
         })
     }
+
+    /// Checks the node config at `config_path` for changes every `reload_interval` and hands the
+    /// upstream peers and the minimum supported protocol version of each changed version to the
+    /// coordinator. A config that fails to load is logged and leaves the current ones untouched.
+    pub fn watch_upstream_peers(&self, config_path: PathBuf, reload_interval: Duration) {
+        let mut sender = self.coordinator_sender.clone();
+        self.runtime.executor().spawn(async move {
+            let mut last_modified = modified_time(&config_path);
+            let mut interval = Interval::new_interval(reload_interval);
+            while interval.next().await.is_some() {
+                let modified = modified_time(&config_path);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                match load_state_sync_config(&config_path) {
+                    Ok((peers, state_sync_config)) => {
+                        let msgs = vec![
+                            CoordinatorMessage::UpdateUpstreamPeers(peers),
+                            CoordinatorMessage::UpdateMinProtocolVersion(
+                                state_sync_config.min_supported_protocol_version,
+                            ),
+                        ];
+                        for msg in msgs {
+                            if sender.send(msg).await.is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    Err(err) => error!(
+                        "[state sync] ignoring reloaded config {:?}: {}",
+                        config_path, err
+                    ),
+                }
+            }
+        });
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Loads the state sync section of the node config at `path`, along with its parsed upstream
+/// peers.
+fn load_state_sync_config(path: &Path) -> Result<(Vec<PeerId>, StateSyncConfig)> {
+    let config = NodeConfig::parse(&fs::read_to_string(path)?)?;
+    let peers = get_upstream_peers(&config.state_sync)?;
+    Ok((peers, config.state_sync))
+}
+
+/// Parses a serialized node config and returns the upstream peers of its state sync section.
+pub(crate) fn parse_upstream_peers(serialized: &str) -> Result<Vec<PeerId>> {
+    get_upstream_peers(&NodeConfig::parse(serialized)?.state_sync)
+}
+
+fn get_upstream_peers(config: &StateSyncConfig) -> Result<Vec<PeerId>> {
+    let mut peers = vec![];
+    for peer_id_str in &config.upstream_peers.upstream_peers {
+        ensure!(!peer_id_str.is_empty(), "Empty upstream peer");
+        let peer_id = PeerId::from_str(peer_id_str)
+            .map_err(|err| format_err!("Invalid upstream peer {}: {}", peer_id_str, err))?;
+        ensure!(
+            !peers.contains(&peer_id),
+            "Duplicate upstream peer {}",
+            peer_id_str
+        );
+        peers.push(peer_id);
+    }
+    Ok(peers)
 }
 
 pub struct StateSyncClient {
     coordinator_sender: mpsc::UnboundedSender<CoordinatorMessage>,
+    commit_sender: keyed_watch::Sender<(), u64>,
 }
 
 impl StateSyncClient {
     /// Sync validator's state to target.
-    /// In case of success (`Result::Ok`) the LI of storage is at the given target.
+    /// In case of success (`Result::Ok`) the LI of storage is at the given target, which is
+    /// returned together with how much was synced.
     /// In case of failure (`Result::Error`) the LI of storage remains unchanged, and the validator
     /// can assume there were no modifications to the storage made.
     /// It is up to state synchronizer to decide about the specific criteria for the failure
-    /// (e.g., lack of progress with all of the peer validators).
-    pub fn sync_to(&self, target: LedgerInfoWithSignatures) -> impl Future<Output = Result<()>> {
+    /// (e.g., lack of progress with all of the peer validators). The failures of the request
+    /// itself are reported as a `SyncError`.
+    /// `purpose` tunes how aggressively the target is pursued.
+    pub fn sync_to(
+        &self,
+        target: LedgerInfoWithSignatures,
+        purpose: SyncPurpose,
+    ) -> impl Future<Output = Result<SyncCompletionInfo>> {
+        self.send_sync_request(target, purpose, None)
+    }
+
+    /// Same as `sync_to`, but also sends the new version on `progress` after each chunk applied
+    /// toward the target, ahead of the final result.
+    pub fn sync_to_with_progress(
+        &self,
+        target: LedgerInfoWithSignatures,
+        purpose: SyncPurpose,
+        progress: mpsc::UnboundedSender<u64>,
+    ) -> impl Future<Output = Result<SyncCompletionInfo>> {
+        self.send_sync_request(target, purpose, Some(progress))
+    }
+
+    fn send_sync_request(
+        &self,
+        target: LedgerInfoWithSignatures,
+        purpose: SyncPurpose,
+        progress: Option<mpsc::UnboundedSender<u64>>,
+    ) -> impl Future<Output = Result<SyncCompletionInfo>> {
         let mut sender = self.coordinator_sender.clone();
         let (callback, cb_receiver) = oneshot::channel();
-        let request = SyncRequest { callback, target };
+        let request = SyncRequest {
+            callback,
+            target,
+            purpose,
+            progress,
+        };
         async move {
-            sender.send(CoordinatorMessage::Request(request)).await?;
-            cb_receiver.await?
+            sender
+                .send(CoordinatorMessage::Request(request))
+                .await
+                .map_err(|_| SyncError::Shutdown)?;
+            cb_receiver.await.map_err(|_| SyncError::Shutdown)?
         }
     }
 
-    /// Notifies state synchronizer about new version
+    /// Notifies state synchronizer about new version. Notifications the coordinator hasn't
+    /// processed yet are replaced by this one, so commits never pile up.
     pub fn commit(&self, version: u64) -> impl Future<Output = Result<()>> {
-        let mut sender = self.coordinator_sender.clone();
-        async move {
-            sender.send(CoordinatorMessage::Commit(version)).await?;
-            Ok(())
-        }
+        future::ready(self.commit_sender.insert((), version))
     }
 
     /// Returns information about StateSynchronizer internal state
@@ -117,14 +429,48 @@ impl StateSyncClient {
         }
     }
 
+    /// Returns whether `version` is committed, i.e. at or below the latest version the state
+    /// synchronizer knows of.
+    pub fn is_committed(&self, version: u64) -> impl Future<Output = Result<bool>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        async move {
+            sender
+                .send(CoordinatorMessage::IsCommitted(version, cb_sender))
+                .await?;
+            Ok(cb_receiver.await?)
+        }
+    }
+
+    /// Completes once `version` is committed, i.e. at or below the latest version the state
+    /// synchronizer knows of.
+    pub fn wait_for_version(&self, version: u64) -> impl Future<Output = Result<()>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        async move {
+            sender
+                .send(CoordinatorMessage::WaitForVersion(version, cb_sender))
+                .await?;
+            Ok(cb_receiver.await?)
+        }
+    }
+
+    /// Returns a page of the epoch change proof starting at `start_epoch`. `end_epoch` is
+    /// exclusive and defaults to the latest epoch; `limit` caps the number of ledger infos in the
+    /// page. Callers keep requesting from the epoch after the last one received while `more` is
+    /// set.
     pub fn get_epoch_proof(
         &self,
         start_epoch: u64,
-    ) -> impl Future<Output = Result<ValidatorChangeEventWithProof>> {
+        end_epoch: Option<u64>,
+        limit: Option<u64>,
+    ) -> impl Future<Output = Result<EpochProofPage>> {
         let mut sender = self.coordinator_sender.clone();
         let (cb_sender, cb_receiver) = oneshot::channel();
         let request = EpochRetrievalRequest {
             start_epoch,
+            end_epoch,
+            limit,
             callback: cb_sender,
         };
         async move {
@@ -134,4 +480,66 @@ impl StateSyncClient {
             cb_receiver.await?
         }
     }
+
+    /// Returns the ledger info committed at `version`
+    pub fn get_ledger_info(
+        &self,
+        version: u64,
+    ) -> impl Future<Output = Result<LedgerInfoWithSignatures>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        async move {
+            sender
+                .send(CoordinatorMessage::GetLedgerInfo(version, cb_sender))
+                .await?;
+            cb_receiver.await?
+        }
+    }
+
+    /// Returns why the last chunk request or response of the current sync session failed, if
+    /// any, e.g. to tell why a sync stalls.
+    pub fn get_last_error(&self) -> impl Future<Output = Result<Option<ChunkFailure>>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        async move {
+            sender
+                .send(CoordinatorMessage::GetLastError(cb_sender))
+                .await?;
+            Ok(cb_receiver.await?)
+        }
+    }
+
+    /// Returns what the pending sync request took so far, none if no sync is pending. The
+    /// metrics of a completed sync come with its `SyncCompletionInfo`.
+    pub fn get_session_metrics(&self) -> impl Future<Output = Result<Option<SyncSessionMetrics>>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        async move {
+            sender
+                .send(CoordinatorMessage::GetSessionMetrics(cb_sender))
+                .await?;
+            Ok(cb_receiver.await?)
+        }
+    }
+
+    /// Compares the transaction infos committed at `versions` with the ones of `peer_id`, e.g.
+    /// when the local state is suspected to diverge. Neither side writes anything, and the peer
+    /// drops audit requests arriving too often. Mismatches are also logged as critical.
+    pub fn audit_state(
+        &self,
+        peer_id: PeerId,
+        versions: Vec<u64>,
+    ) -> impl Future<Output = Result<Vec<VersionAudit>>> {
+        let mut sender = self.coordinator_sender.clone();
+        let (cb_sender, cb_receiver) = oneshot::channel();
+        let request = AuditRequest {
+            peer_id,
+            versions,
+            callback: cb_sender,
+        };
+        async move {
+            sender.send(CoordinatorMessage::AuditState(request)).await?;
+            cb_receiver.await?
+        }
+    }
 }
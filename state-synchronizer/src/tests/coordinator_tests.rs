@@ -0,0 +1,2782 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    checkpoint::SyncCheckpoint,
+    coordinator::{
+        AuditRequest, CoordinatorMessage, EpochRetrievalRequest, SyncCoordinator, SyncEvent,
+        SyncPurpose, SyncRequest, PROTOCOL_VERSION,
+    },
+    counters,
+    executor_proxy::{EpochProofPage, ExecutorProxyTrait, TransientError},
+    peer_manager::InactiveReason,
+    replay_session,
+    tests::FakeClock,
+    ChunkFailure, CommittedTransactionData, LedgerInfo, PeerId, SessionEvent, SyncError,
+    SyncSessionMetrics,
+};
+use channel::{self, keyed_watch};
+use executor::ChunkExecutionStages;
+use failure::prelude::*;
+use futures::{
+    channel::{mpsc, oneshot},
+    executor::block_on,
+    future::{self, FutureExt},
+    task::{noop_waker, Context},
+    Future, StreamExt,
+};
+use grpcio::{RpcStatus, RpcStatusCode};
+use lazy_static::lazy_static;
+use libra_config::config::{RoleType, SignatureQuorum, StateSyncConfig};
+use libra_crypto::{
+    hash::{CryptoHash, TransactionAccumulatorHasher},
+    HashValue,
+};
+use libra_tools::tempdir::TempPath;
+use libra_types::{
+    block_info::BlockInfo,
+    crypto_proxies::{
+        random_validator_verifier, LedgerInfoWithSignatures, ValidatorInfo, ValidatorSigner,
+        ValidatorVerifier,
+    },
+    ledger_info::LedgerInfo as TypesLedgerInfo,
+    proof::{
+        accumulator::InMemoryAccumulator, TransactionAccumulatorRangeProof, TransactionListProof,
+    },
+    transaction::{Transaction, TransactionInfo, TransactionListWithProof},
+    validator_set::ValidatorSet,
+    vm_error::StatusCode,
+    write_set::WriteSet,
+};
+use network::{
+    interface::NetworkRequest,
+    proto::{
+        GetChunkRequest, GetChunkResponse, GetRootHashesRequest, GetRootHashesResponse,
+        StateSynchronizerMsg, StateSynchronizerMsg_oneof, VersionRootHash,
+    },
+    validator_network::StateSynchronizerSender,
+};
+use prost::Message as _;
+use std::{
+    collections::BTreeMap,
+    convert::TryInto,
+    fs,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use tokio::{runtime::Runtime, timer::Timeout};
+
+lazy_static! {
+    // runs the chunk deliveries to long-poll subscribers
+    static ref TEST_RUNTIME: Runtime = Runtime::new().unwrap();
+}
+
+/// Executor proxy starting at version 0 whose `get_chunk` takes `chunk_delay` and whose
+/// `execute_chunk` takes `execute_delay` on the fake clock
+struct TestExecutorProxy {
+    clock: Arc<FakeClock>,
+    chunk_delay: Duration,
+    execute_delay: Duration,
+    // if set, the next `execute_chunk` completes only once this is notified
+    execute_gate: Mutex<Option<oneshot::Receiver<()>>>,
+    version: AtomicU64,
+    // epoch change ledger infos served by `get_epoch_proof`
+    epoch_changes: Vec<LedgerInfo>,
+    // size of the validator set, ledger infos are not actually verified
+    num_validators: usize,
+    // if set, `get_chunk` never completes for chunks of this limit, like a stalled peer
+    stalled_limit: Option<u64>,
+    // if set, the next `execute_chunk` fails with this status without applying the chunk, like a
+    // storage error
+    fail_next_execute: Mutex<Option<RpcStatusCode>>,
+    // number of next `get_epoch_proof` calls failing transiently, like an unreachable storage
+    epoch_proof_failures: AtomicU64,
+    // frozen subtree hashes of the local accumulator, empty like the proofs of the test chunks
+    frozen_subtree_hashes: Vec<HashValue>,
+}
+
+impl ExecutorProxyTrait for TestExecutorProxy {
+    fn get_latest_version(&self) -> Pin<Box<dyn Future<Output = Result<u64>> + Send>> {
+        let version = self.version.load(Ordering::Relaxed);
+        async move { Ok(version) }.boxed()
+    }
+
+    fn get_latest_ledger_info(&self) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let response = test_ledger_info(self.version.load(Ordering::Relaxed));
+        async move { Ok(response) }.boxed()
+    }
+
+    fn get_ledger_info_at(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let response = if version <= self.version.load(Ordering::Relaxed) {
+            Ok(test_ledger_info(version))
+        } else {
+            Err(format_err!("no ledger info at version {}", version))
+        };
+        async move { response }.boxed()
+    }
+
+    fn get_latest_ledger_info_at_or_before(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        // like in storage, ledger infos before the latest one are only kept at the end of epochs,
+        // here every 10 versions
+        let latest_version = self.version.load(Ordering::Relaxed);
+        let version = if version >= latest_version {
+            latest_version
+        } else {
+            version - version % 10
+        };
+        async move { Ok(test_ledger_info(version)) }.boxed()
+    }
+
+    fn execute_chunk(
+        &self,
+        txn_list_with_proof: TransactionListWithProof,
+        _ledger_info_with_sigs: LedgerInfoWithSignatures,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        if let Some(status) = self.fail_next_execute.lock().unwrap().take() {
+            let error = grpcio::Error::RpcFailure(RpcStatus::new(
+                status,
+                Some("Injected storage error".to_string()),
+            ));
+            return async move { Err(error.into()) }.boxed();
+        }
+        if let Some(first_version) = txn_list_with_proof.first_transaction_version {
+            self.version.store(
+                first_version + txn_list_with_proof.len() as u64 - 1,
+                Ordering::Relaxed,
+            );
+        }
+        self.clock.advance(self.execute_delay);
+        let gate = self.execute_gate.lock().unwrap().take();
+        async move {
+            if let Some(gate) = gate {
+                gate.await?;
+            }
+            Ok(())
+        }
+            .boxed()
+    }
+
+    fn last_chunk_stages(&self) -> ChunkExecutionStages {
+        ChunkExecutionStages::default()
+    }
+
+    fn get_chunk(
+        &self,
+        known_version: u64,
+        limit: u64,
+        target: LedgerInfo,
+    ) -> Pin<Box<dyn Future<Output = Result<GetChunkResponse>> + Send>> {
+        if self.stalled_limit == Some(limit) {
+            return future::pending().boxed();
+        }
+        self.clock.advance(self.chunk_delay);
+        let end_version = std::cmp::min(known_version + limit, target.ledger_info().version());
+        let txn_list_with_proof = TransactionListWithProof::new(
+            (known_version..end_version)
+                .map(|_| Transaction::WriteSet(WriteSet::default()))
+                .collect(),
+            None,
+            Some(known_version + 1),
+            TransactionListProof::new_empty(),
+        );
+        let response = GetChunkResponse {
+            txn_list_with_proof: Some(txn_list_with_proof.into()),
+            ledger_info_with_sigs: Some(target.into()),
+        };
+        async move { Ok(response) }.boxed()
+    }
+
+    fn validate_ledger_info(&self, _target: &LedgerInfo) -> Result<()> {
+        Ok(())
+    }
+
+    fn validate_chunk(
+        &self,
+        txn_list_with_proof: &TransactionListWithProof,
+        target: &LedgerInfo,
+    ) -> Result<()> {
+        // most test chunks carry no proof at all, only those that do are verified
+        if txn_list_with_proof.proof.transaction_infos().is_empty() {
+            return Ok(());
+        }
+        txn_list_with_proof.verify(
+            target.ledger_info(),
+            txn_list_with_proof.first_transaction_version,
+        )
+    }
+
+    fn num_validators(&self) -> usize {
+        self.num_validators
+    }
+
+    fn get_epoch_proof(
+        &self,
+        start_epoch: u64,
+        end_epoch: u64,
+        limit: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
+        if self.epoch_proof_failures.load(Ordering::Relaxed) > 0 {
+            self.epoch_proof_failures.fetch_sub(1, Ordering::Relaxed);
+            return async { Err(TransientError("storage unavailable".to_string()).into()) }.boxed();
+        }
+        let ledger_infos = self
+            .epoch_changes
+            .iter()
+            .filter(|li| (start_epoch..end_epoch).contains(&li.ledger_info().epoch()))
+            .take(limit as usize + 1)
+            .cloned()
+            .collect();
+        let page = EpochProofPage::new(ledger_infos, limit);
+        async move { Ok(page) }.boxed()
+    }
+
+    fn subscribe_committed_data(&self) -> mpsc::Receiver<CommittedTransactionData> {
+        unimplemented!()
+    }
+
+    fn get_transaction_info_hash(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<HashValue>>> + Send>> {
+        let hash = if version <= self.version.load(Ordering::Relaxed) {
+            Some(test_transaction_info_hash(version))
+        } else {
+            None
+        };
+        async move { Ok(hash) }.boxed()
+    }
+
+    fn get_frozen_subtree_hashes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<HashValue>>> + Send>> {
+        let hashes = self.frozen_subtree_hashes.clone();
+        async move { Ok(hashes) }.boxed()
+    }
+}
+
+fn test_transaction_info_hash(version: u64) -> HashValue {
+    HashValue::from_sha3_256(&version.to_le_bytes())
+}
+
+fn test_ledger_info(version: u64) -> LedgerInfo {
+    let ledger_info = TypesLedgerInfo::new(
+        BlockInfo::new(0, 0, HashValue::zero(), HashValue::zero(), version, 0, None),
+        HashValue::zero(),
+    );
+    LedgerInfoWithSignatures::new(ledger_info, BTreeMap::new())
+}
+
+/// A chunk of one transaction at `version`
+fn test_chunk_response(version: u64) -> GetChunkResponse {
+    test_chunk_response_with_target(version, version)
+}
+
+/// A chunk of one transaction at `version` from a peer whose latest version is `target_version`
+fn test_chunk_response_with_target(version: u64, target_version: u64) -> GetChunkResponse {
+    let txn_list_with_proof = TransactionListWithProof::new(
+        vec![Transaction::WriteSet(WriteSet::default())],
+        None,
+        Some(version),
+        TransactionListProof::new_empty(),
+    );
+    GetChunkResponse {
+        txn_list_with_proof: Some(txn_list_with_proof.into()),
+        ledger_info_with_sigs: Some(test_ledger_info(target_version).into()),
+    }
+}
+
+fn create_coordinator(
+    clock: Arc<FakeClock>,
+    chunk_delay: Duration,
+) -> SyncCoordinator<TestExecutorProxy> {
+    create_coordinator_with_config(clock, chunk_delay, StateSyncConfig::default())
+}
+
+fn create_coordinator_with_config(
+    clock: Arc<FakeClock>,
+    chunk_delay: Duration,
+    config: StateSyncConfig,
+) -> SyncCoordinator<TestExecutorProxy> {
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay,
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    create_coordinator_with_proxy(clock, config, executor_proxy)
+}
+
+fn create_coordinator_with_proxy(
+    clock: Arc<FakeClock>,
+    config: StateSyncConfig,
+    executor_proxy: TestExecutorProxy,
+) -> SyncCoordinator<TestExecutorProxy> {
+    let (_client_sender, client_receiver) = mpsc::unbounded();
+    let (_commit_sender, commit_receiver) = keyed_watch::new(None);
+    SyncCoordinator::new_with_clock(
+        client_receiver,
+        commit_receiver,
+        RoleType::FullNode,
+        config,
+        executor_proxy,
+        TEST_RUNTIME.executor(),
+        clock,
+    )
+}
+
+fn create_validator_coordinator(clock: Arc<FakeClock>) -> SyncCoordinator<TestExecutorProxy> {
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let (_client_sender, client_receiver) = mpsc::unbounded();
+    let (_commit_sender, commit_receiver) = keyed_watch::new(None);
+    SyncCoordinator::new_with_clock(
+        client_receiver,
+        commit_receiver,
+        RoleType::Validator,
+        StateSyncConfig::default(),
+        executor_proxy,
+        TEST_RUNTIME.executor(),
+        clock,
+    )
+}
+
+/// Connects a new peer to `coordinator`, returning the receiving end of its network sender
+fn connect_peer(
+    coordinator: &mut SyncCoordinator<TestExecutorProxy>,
+    peer_id: PeerId,
+) -> channel::Receiver<NetworkRequest> {
+    let (network_reqs_tx, network_reqs_rx) = channel::new_test(8);
+    block_on(coordinator.process_new_peer(
+        peer_id,
+        0,
+        StateSynchronizerSender::new(network_reqs_tx),
+    ));
+    network_reqs_rx
+}
+
+/// Number of messages sent through `receiver`'s sender so far
+fn num_sent(receiver: &mut channel::Receiver<NetworkRequest>) -> usize {
+    let mut count = 0;
+    while receiver.select_next_some().now_or_never().is_some() {
+        count += 1;
+    }
+    count
+}
+
+/// The next message sent through `receiver`'s sender, waiting for it up to 5 seconds since
+/// chunks are delivered to long-poll subscribers on other tasks
+fn next_sent(receiver: &mut channel::Receiver<NetworkRequest>) -> Option<NetworkRequest> {
+    TEST_RUNTIME
+        .block_on(Timeout::new(receiver.next(), Duration::from_secs(5)))
+        .ok()
+        .and_then(|request| request)
+}
+
+/// Waits up to 5 seconds for `condition` to hold, returning whether it did
+fn wait_until(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !condition() {
+        if Instant::now() > deadline {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+/// Subscribes `peer_id` to the next chunk of at most `limit` transactions after `known_version`
+fn subscribe(
+    coordinator: &mut SyncCoordinator<TestExecutorProxy>,
+    peer_id: PeerId,
+    known_version: u64,
+    limit: u64,
+) {
+    let mut request = GetChunkRequest::default();
+    request.known_version = known_version;
+    request.limit = limit;
+    request.timeout = 10_000;
+    block_on(coordinator.process_chunk_request(peer_id, request)).unwrap();
+}
+
+/// Messages sent through `receiver`'s sender so far
+fn sent_messages(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<StateSynchronizerMsg> {
+    let mut messages = vec![];
+    while let Some(request) = receiver.select_next_some().now_or_never() {
+        if let NetworkRequest::SendMessage(_, msg) = request {
+            messages.push(StateSynchronizerMsg::decode(msg.mdata.as_ref()).unwrap());
+        }
+    }
+    messages
+}
+
+/// Chunk responses sent through `receiver`'s sender so far
+fn sent_chunk_responses(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<GetChunkResponse> {
+    let mut responses = vec![];
+    while let Some(request) = receiver.select_next_some().now_or_never() {
+        if let NetworkRequest::SendMessage(_, msg) = request {
+            let msg = StateSynchronizerMsg::decode(msg.mdata.as_ref()).unwrap();
+            if let Some(StateSynchronizerMsg_oneof::ChunkResponse(response)) = msg.message {
+                responses.push(response);
+            }
+        }
+    }
+    responses
+}
+
+/// Limits of the chunk requests sent through `receiver`'s sender so far
+fn sent_chunk_request_limits(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<u64> {
+    let mut limits = vec![];
+    while let Some(request) = receiver.select_next_some().now_or_never() {
+        if let NetworkRequest::SendMessage(_, msg) = request {
+            let msg = StateSynchronizerMsg::decode(msg.mdata.as_ref()).unwrap();
+            if let Some(StateSynchronizerMsg_oneof::ChunkRequest(request)) = msg.message {
+                limits.push(request.limit);
+            }
+        }
+    }
+    limits
+}
+
+/// Minimum protocol versions of the protocol errors sent through `receiver`'s sender so far
+fn sent_protocol_errors(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<u64> {
+    let mut versions = vec![];
+    while let Some(request) = receiver.select_next_some().now_or_never() {
+        if let NetworkRequest::SendMessage(_, msg) = request {
+            let msg = StateSynchronizerMsg::decode(msg.mdata.as_ref()).unwrap();
+            if let Some(StateSynchronizerMsg_oneof::ProtocolError(error)) = msg.message {
+                versions.push(error.min_supported_protocol_version);
+            }
+        }
+    }
+    versions
+}
+
+/// Ledger infos ending epochs `0..num_epochs`, each signed by the validators of its epoch and
+/// carrying the next validator set, together with the verifier of epoch 0
+fn test_epoch_changes(num_epochs: u64) -> (Vec<LedgerInfo>, ValidatorVerifier) {
+    let (mut signers, first_verifier) = random_validator_verifier(1, None, true);
+    let ledger_infos = (0..num_epochs)
+        .map(|epoch| {
+            let (next_signers, next_verifier) = random_validator_verifier(1, None, true);
+            let ledger_info = TypesLedgerInfo::new(
+                BlockInfo::new(
+                    epoch,
+                    0,
+                    HashValue::zero(),
+                    HashValue::zero(),
+                    epoch,
+                    0,
+                    Some((&next_verifier).into()),
+                ),
+                HashValue::zero(),
+            );
+            let signatures = signers
+                .iter()
+                .map(|s| (s.author(), s.sign_message(ledger_info.hash()).unwrap()))
+                .collect();
+            signers = next_signers;
+            LedgerInfoWithSignatures::new(ledger_info, signatures)
+        })
+        .collect();
+    (ledger_infos, first_verifier)
+}
+
+fn config_with_upstream_peers(upstream_peers: &[PeerId]) -> StateSyncConfig {
+    let mut config = StateSyncConfig::default();
+    config.upstream_peers.upstream_peers = upstream_peers.iter().map(ToString::to_string).collect();
+    config
+}
+
+fn create_hedging_coordinator(
+    clock: Arc<FakeClock>,
+    upstream_peers: &[PeerId],
+) -> SyncCoordinator<TestExecutorProxy> {
+    let mut config = config_with_upstream_peers(upstream_peers);
+    config.hedge_after_ms = Some(500);
+    create_coordinator_with_config(clock, Duration::from_secs(0), config)
+}
+
+#[test]
+fn test_subscription_serve_latency() {
+    let clock = Arc::new(FakeClock::new());
+    let chunk_delay = Duration::from_secs(42);
+    let mut coordinator = create_coordinator(Arc::clone(&clock), chunk_delay);
+
+    let peer_id = PeerId::random();
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+
+    // nothing newer than version 0 yet, so the request becomes a subscription
+    subscribe(&mut coordinator, peer_id, 0, 10);
+
+    let count_before = counters::SUBSCRIPTION_SERVE_LATENCY.get_sample_count();
+    let sum_before = counters::SUBSCRIPTION_SERVE_LATENCY.get_sample_sum();
+    block_on(coordinator.commit(1));
+    assert!(next_sent(&mut network_reqs_rx).is_some());
+
+    // other tests may observe concurrently, but only with real (sub-second) latencies
+    assert!(wait_until(|| counters::SUBSCRIPTION_SERVE_LATENCY
+        .get_sample_count()
+        > count_before));
+    let observed = counters::SUBSCRIPTION_SERVE_LATENCY.get_sample_sum() - sum_before;
+    assert!(observed >= chunk_delay.as_secs_f64());
+    assert!(observed < chunk_delay.as_secs_f64() + 1.0);
+}
+
+#[test]
+fn test_is_committed() {
+    let mut coordinator = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+    block_on(coordinator.commit(5));
+    let is_committed = |version| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.is_committed(version, callback);
+        block_on(receiver).unwrap()
+    };
+    assert!(is_committed(0));
+    assert!(is_committed(4));
+    assert!(is_committed(5));
+    assert!(!is_committed(6));
+    assert!(!is_committed(100));
+}
+
+#[test]
+fn test_wait_for_version() {
+    let mut coordinator = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+    block_on(coordinator.commit(5));
+
+    // fires right away for a committed version
+    let (callback, receiver) = oneshot::channel();
+    coordinator.wait_for_version(5, callback);
+    assert_eq!(block_on(receiver), Ok(()));
+
+    let (callback, mut receiver) = oneshot::channel();
+    coordinator.wait_for_version(10, callback);
+    block_on(coordinator.commit(9));
+    assert_eq!(receiver.try_recv(), Ok(None));
+
+    // fires once committed past it, and only once
+    block_on(coordinator.commit(12));
+    assert_eq!(receiver.try_recv(), Ok(Some(())));
+    block_on(coordinator.commit(15));
+    assert!(receiver.try_recv().is_err());
+}
+
+#[test]
+fn test_get_ledger_info() {
+    let coordinator = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+
+    let (callback, receiver) = oneshot::channel();
+    block_on(coordinator.get_ledger_info_at(0, callback));
+    assert_eq!(block_on(receiver).unwrap().unwrap(), test_ledger_info(0));
+
+    let (callback, receiver) = oneshot::channel();
+    block_on(coordinator.get_ledger_info_at(1, callback));
+    assert!(block_on(receiver).unwrap().is_err());
+}
+
+#[test]
+fn test_hedged_request_fires_for_slow_peer() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut coordinator = create_hedging_coordinator(Arc::clone(&clock), &[peer_a, peer_b]);
+
+    // A is the only peer when the first request goes out
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+
+    // A doesn't answer in time, so the same request is sent to B
+    let hedged_before = counters::HEDGED_REQUESTS.get();
+    clock.advance(Duration::from_millis(600));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(num_sent(&mut rx_b), 1);
+    assert!(counters::HEDGED_REQUESTS.get() > hedged_before);
+
+    // nothing more to hedge until the next request
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_b), 0);
+
+    // B wins, and the late duplicate from A is dropped instead of failing as non sequential
+    let wins_before = counters::HEDGE_WINS.get();
+    block_on(coordinator.process_chunk_response(&peer_b, test_chunk_response(1))).unwrap();
+    assert!(counters::HEDGE_WINS.get() > wins_before);
+    block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response(1))).unwrap();
+
+    // a second duplicate is not expected and treated as usual
+    assert!(block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response(1))).is_err());
+}
+
+#[test]
+fn test_hedged_request_not_fired_for_fast_peer() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut coordinator = create_hedging_coordinator(Arc::clone(&clock), &[peer_a, peer_b]);
+
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+
+    clock.advance(Duration::from_millis(400));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_b), 0);
+
+    // A answers before the hedge delay, which also requests the next chunk
+    block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response(1))).unwrap();
+    assert_eq!(num_sent(&mut rx_a) + num_sent(&mut rx_b), 1);
+
+    // past the hedge delay of the first request, but not of the one that followed
+    clock.advance(Duration::from_millis(200));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_a) + num_sent(&mut rx_b), 0);
+}
+
+#[test]
+fn test_drop_slowest_peer_when_stuck() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[peer_a, peer_b]);
+    config.drop_slowest_on_stuck = true;
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+    let timeout = Duration::from_millis(
+        StateSyncConfig::default().tick_interval_ms
+            + StateSyncConfig::default().long_poll_timeout_ms,
+    );
+
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+
+    // A stalls progress, so it is dropped and the request goes to B
+    clock.advance(timeout + Duration::from_secs(1));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(num_sent(&mut rx_b), 1);
+
+    // B is never dropped as the last peer left, and A stays out while cooling down
+    clock.advance(timeout + Duration::from_secs(1));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(num_sent(&mut rx_b), 1);
+}
+
+#[test]
+fn test_fail_over_on_slow_progress() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[peer_a, peer_b]);
+    config.min_progress_rate = Some(100);
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+    let window = Duration::from_millis(
+        StateSyncConfig::default().tick_interval_ms
+            + StateSyncConfig::default().long_poll_timeout_ms,
+    );
+
+    // A answers every request in time, but with a single transaction out of the 1000 it has
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut version = 0;
+    while Duration::from_secs(version) <= window {
+        version += 1;
+        clock.advance(Duration::from_secs(1));
+        block_on(
+            coordinator
+                .process_chunk_response(&peer_a, test_chunk_response_with_target(version, 1000)),
+        )
+        .unwrap();
+        block_on(coordinator.check_progress());
+        assert_eq!(num_sent(&mut rx_a), 1);
+    }
+
+    // a whole window at about one version per second is too slow, so the request goes to B
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+    assert_eq!(num_sent(&mut rx_b), 0);
+    let failovers_before = counters::SLOW_PROGRESS_FAILOVERS.get();
+    clock.advance(Duration::from_secs(1));
+    block_on(coordinator.check_progress());
+    assert!(counters::SLOW_PROGRESS_FAILOVERS.get() > failovers_before);
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(num_sent(&mut rx_b), 1);
+}
+
+#[test]
+fn test_slow_execution_does_not_time_out_requests() {
+    let clock = Arc::new(FakeClock::new());
+    let peer_id = PeerId::random();
+    let timeout = Duration::from_millis(
+        StateSyncConfig::default().tick_interval_ms
+            + StateSyncConfig::default().long_poll_timeout_ms,
+    );
+    // a storage write stall holds up every chunk execution for two request timeouts
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: 2 * timeout,
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(
+        Arc::clone(&clock),
+        config_with_upstream_peers(&[peer_id]),
+        executor_proxy,
+    );
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+
+    let slow_executions_before = counters::SLOW_CHUNK_EXECUTIONS.get();
+    for version in 1..=3 {
+        block_on(
+            coordinator
+                .process_chunk_response(&peer_id, test_chunk_response_with_target(version, 10)),
+        )
+        .unwrap();
+        // the next chunk was requested before executing this one, and is not requested again
+        // while its response may be waiting to be processed
+        assert_eq!(num_sent(&mut network_reqs_rx), 1);
+        block_on(coordinator.check_progress());
+        assert_eq!(num_sent(&mut network_reqs_rx), 0);
+    }
+    assert!(counters::SLOW_CHUNK_EXECUTIONS.get() >= slow_executions_before + 3);
+
+    // a peer that really doesn't answer still times out
+    clock.advance(timeout + Duration::from_secs(1));
+    block_on(coordinator.check_progress());
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+}
+
+#[test]
+fn test_chunk_requests_pause_while_apply_backlog_full() {
+    let clock = Arc::new(FakeClock::new());
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.max_pending_chunks = 1;
+    let (release, gate) = oneshot::channel();
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(Some(gate)),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let mut apply = Box::pin(
+        coordinator.process_chunk_response(&peer_id, test_chunk_response_with_target(1, 10)),
+    );
+    // the chunk being applied fills the backlog, so the next one is not requested meanwhile
+    assert!(apply.poll_unpin(&mut cx).is_pending());
+    assert_eq!(num_sent(&mut network_reqs_rx), 0);
+
+    release.send(()).unwrap();
+    block_on(apply).unwrap();
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+}
+
+#[test]
+fn test_learn_new_versions_from_sibling_gossip() {
+    let (node_id, upstream, sibling) = (PeerId::random(), PeerId::random(), PeerId::random());
+    // the node long-polls an upstream peer that has nothing new, next to a sibling full node
+    let mut node = create_coordinator_with_config(
+        Arc::new(FakeClock::new()),
+        Duration::from_secs(0),
+        config_with_upstream_peers(&[upstream, sibling]),
+    );
+    let mut upstream_rx = connect_peer(&mut node, upstream);
+    assert_eq!(num_sent(&mut upstream_rx), 1);
+    let mut node_to_sibling_rx = connect_peer(&mut node, sibling);
+    assert_eq!(num_sent(&mut node_to_sibling_rx), 0);
+
+    // the sibling is at version 5 and gossips it
+    let clock = Arc::new(FakeClock::new());
+    let mut config = StateSyncConfig::default();
+    config.ledger_info_gossip_interval_ms = Some(1000);
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(5),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut sibling_node = create_coordinator_with_proxy(clock, config, executor_proxy);
+    block_on(sibling_node.commit(5));
+    let mut sibling_to_node_rx = connect_peer(&mut sibling_node, node_id);
+    block_on(sibling_node.gossip_ledger_info_summary());
+    let summary = sent_messages(&mut sibling_to_node_rx).pop().unwrap();
+    match &summary.message {
+        Some(StateSynchronizerMsg_oneof::LedgerInfoSummary(summary)) => {
+            assert_eq!(summary.version, 5);
+            assert_eq!(
+                summary.ledger_info_hash,
+                test_ledger_info(5).ledger_info().hash().to_vec()
+            );
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    // the gossip interval did not pass yet
+    block_on(sibling_node.gossip_ledger_info_summary());
+    assert_eq!(num_sent(&mut sibling_to_node_rx), 0);
+
+    // the node requests the next chunk from the sibling right away
+    block_on(node.process_message(sibling, summary.clone()));
+    assert_eq!(node.peer_manager().advertised_version(&sibling), Some(5));
+    let request = sent_messages(&mut node_to_sibling_rx).pop().unwrap();
+    match &request.message {
+        Some(StateSynchronizerMsg_oneof::ChunkRequest(request)) => {
+            assert_eq!(request.known_version, 0)
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    // a repeated summary is dropped by the rate limit and triggers no other request
+    block_on(node.process_message(sibling, summary));
+    assert_eq!(num_sent(&mut node_to_sibling_rx), 0);
+
+    block_on(sibling_node.process_message(node_id, request));
+    let response = match next_sent(&mut sibling_to_node_rx) {
+        Some(NetworkRequest::SendMessage(_, msg)) => {
+            StateSynchronizerMsg::decode(msg.mdata.as_ref()).unwrap()
+        }
+        request => panic!("unexpected request {:?}", request),
+    };
+    block_on(node.process_message(sibling, response));
+    let (callback, known_version) = oneshot::channel();
+    block_on(node.process_client_message(CoordinatorMessage::GetState(callback)));
+    assert_eq!(block_on(known_version).unwrap(), 5);
+}
+
+#[test]
+fn test_get_epoch_proof_paginated() {
+    let num_epochs = 50;
+    let (epoch_changes, mut verifier) = test_epoch_changes(num_epochs);
+    let clock = Arc::new(FakeClock::new());
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes,
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let coordinator =
+        create_coordinator_with_proxy(clock, StateSyncConfig::default(), executor_proxy);
+    let runtime = Runtime::new().unwrap();
+    let get_page = |start_epoch, end_epoch, limit| {
+        let (callback, receiver) = oneshot::channel();
+        let request = EpochRetrievalRequest {
+            start_epoch,
+            end_epoch,
+            limit,
+            callback,
+        };
+        runtime.block_on(async {
+            coordinator.get_epoch_proof(request);
+            receiver.await.unwrap().unwrap()
+        })
+    };
+
+    // walk the whole range 10 epochs at a time, ratcheting the verifier along
+    let mut epoch = 0;
+    let mut num_pages = 0;
+    loop {
+        let page = get_page(epoch, None, Some(10));
+        num_pages += 1;
+        assert_eq!(page.proof.ledger_info_with_sigs.len(), 10);
+        let (last, next_verifier) = page.proof.verify_and_ratchet(epoch, &verifier).unwrap();
+        epoch = last.ledger_info().epoch() + 1;
+        verifier = next_verifier;
+        if !page.more {
+            break;
+        }
+    }
+    assert_eq!(epoch, num_epochs);
+    assert_eq!(num_pages, 5);
+
+    // the end epoch is exclusive and ends the pagination early
+    let page = get_page(45, Some(48), Some(10));
+    assert_eq!(page.proof.ledger_info_with_sigs.len(), 3);
+    assert!(!page.more);
+}
+
+#[test]
+fn test_get_epoch_proof_retries_transient_failures() {
+    let (epoch_changes, verifier) = test_epoch_changes(5);
+    let mut config = StateSyncConfig::default();
+    config.max_epoch_proof_retries = 2;
+    config.epoch_proof_retry_backoff_ms = 1;
+    let runtime = Runtime::new().unwrap();
+    let get_page = |num_failures| {
+        let clock = Arc::new(FakeClock::new());
+        let executor_proxy = TestExecutorProxy {
+            clock: Arc::clone(&clock),
+            chunk_delay: Duration::from_secs(0),
+            execute_delay: Duration::from_secs(0),
+            execute_gate: Mutex::new(None),
+            version: AtomicU64::new(0),
+            epoch_changes: epoch_changes.clone(),
+            num_validators: 1,
+            stalled_limit: None,
+            fail_next_execute: Mutex::new(None),
+            epoch_proof_failures: AtomicU64::new(num_failures),
+        };
+        let coordinator = create_coordinator_with_proxy(clock, config.clone(), executor_proxy);
+        let (callback, receiver) = oneshot::channel();
+        let request = EpochRetrievalRequest {
+            start_epoch: 0,
+            end_epoch: None,
+            limit: None,
+            callback,
+        };
+        runtime.block_on(async {
+            coordinator.get_epoch_proof(request);
+            receiver.await.unwrap()
+        })
+    };
+
+    // the proof is delivered once storage recovers within the retries
+    let page = get_page(2).unwrap();
+    assert_eq!(page.proof.ledger_info_with_sigs.len(), 5);
+    assert!(page.proof.verify_and_ratchet(0, &verifier).is_ok());
+
+    // the failure is reported once the retries are used up
+    let error = get_page(3).unwrap_err();
+    assert!(error.downcast_ref::<TransientError>().is_some());
+}
+
+#[test]
+fn test_serve_up_to_pinned_version() {
+    let clock = Arc::new(FakeClock::new());
+    let mut config = StateSyncConfig::default();
+    config.serve_max_version = Some(40);
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(100),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    block_on(coordinator.commit(100));
+
+    let peer_id = PeerId::random();
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    sent_chunk_responses(&mut network_reqs_rx);
+
+    // a request reaching beyond the pin is served up to the pin, proven by the ledger info there
+    let mut request = GetChunkRequest::default();
+    request.known_version = 30;
+    request.limit = 50;
+    block_on(coordinator.process_chunk_request(peer_id, request)).unwrap();
+    let responses = sent_chunk_responses(&mut network_reqs_rx);
+    assert_eq!(responses.len(), 1);
+    let target: LedgerInfo = responses[0]
+        .ledger_info_with_sigs
+        .clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(target.ledger_info().version(), 40);
+    let txn_list_with_proof: TransactionListWithProof = responses[0]
+        .txn_list_with_proof
+        .clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(txn_list_with_proof.first_transaction_version, Some(31));
+    assert_eq!(txn_list_with_proof.len(), 10);
+
+    // nothing is served to a peer already at the pin, not even once more data is committed
+    let mut request = GetChunkRequest::default();
+    request.known_version = 40;
+    request.limit = 50;
+    assert!(block_on(coordinator.process_chunk_request(peer_id, request.clone())).is_err());
+    request.timeout = 10_000;
+    block_on(coordinator.process_chunk_request(peer_id, request)).unwrap();
+    block_on(coordinator.commit(101));
+    assert!(sent_chunk_responses(&mut network_reqs_rx).is_empty());
+}
+
+#[test]
+fn test_serve_up_to_ledger_info_before_pin() {
+    let clock = Arc::new(FakeClock::new());
+    let mut config = StateSyncConfig::default();
+    config.serve_max_version = Some(45);
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(100),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    block_on(coordinator.commit(100));
+
+    let peer_id = PeerId::random();
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    sent_chunk_responses(&mut network_reqs_rx);
+
+    // no ledger info is stored at the pin, so the chunk is served up to the last one before it
+    let mut request = GetChunkRequest::default();
+    request.known_version = 30;
+    request.limit = 50;
+    block_on(coordinator.process_chunk_request(peer_id, request)).unwrap();
+    let responses = sent_chunk_responses(&mut network_reqs_rx);
+    assert_eq!(responses.len(), 1);
+    let target: LedgerInfo = responses[0]
+        .ledger_info_with_sigs
+        .clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(target.ledger_info().version(), 40);
+    let txn_list_with_proof: TransactionListWithProof = responses[0]
+        .txn_list_with_proof
+        .clone()
+        .unwrap()
+        .try_into()
+        .unwrap();
+    assert_eq!(txn_list_with_proof.len(), 10);
+
+    let mut request = GetChunkRequest::default();
+    request.known_version = 40;
+    request.limit = 50;
+    assert!(block_on(coordinator.process_chunk_request(peer_id, request)).is_err());
+}
+
+#[test]
+fn test_serve_allowlisted_peers_only() {
+    let clock = Arc::new(FakeClock::new());
+    let (allowed_peer, other_peer) = (PeerId::random(), PeerId::random());
+    let mut config = StateSyncConfig::default();
+    config.serve_allowlist = vec![allowed_peer.to_string()];
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(100),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    block_on(coordinator.commit(100));
+
+    let mut allowed_reqs_rx = connect_peer(&mut coordinator, allowed_peer);
+    let mut other_reqs_rx = connect_peer(&mut coordinator, other_peer);
+    sent_chunk_responses(&mut allowed_reqs_rx);
+    sent_chunk_responses(&mut other_reqs_rx);
+
+    let mut request = GetChunkRequest::default();
+    request.known_version = 30;
+    request.limit = 50;
+    block_on(coordinator.process_chunk_request(allowed_peer, request.clone())).unwrap();
+    assert_eq!(sent_chunk_responses(&mut allowed_reqs_rx).len(), 1);
+
+    // the other peer is refused, and not subscribed to new data either
+    assert!(block_on(coordinator.process_chunk_request(other_peer, request.clone())).is_err());
+    request.known_version = 100;
+    request.timeout = 10_000;
+    assert!(block_on(coordinator.process_chunk_request(other_peer, request)).is_err());
+    block_on(coordinator.commit(101));
+    assert!(sent_chunk_responses(&mut other_reqs_rx).is_empty());
+}
+
+#[test]
+fn test_update_upstream_peers() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b, peer_c) = (PeerId::random(), PeerId::random(), PeerId::random());
+    let config = config_with_upstream_peers(&[peer_a, peer_b]);
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+
+    // A is rotated out while its request for version 1 is in flight
+    coordinator.update_upstream_peers(vec![peer_b, peer_c]);
+    assert!(!coordinator.peer_manager().is_upstream(&peer_a));
+    assert!(coordinator.peer_manager().is_upstream(&peer_b));
+    assert!(coordinator.peer_manager().is_upstream(&peer_c));
+
+    // the response of A still counts, and the next chunk is requested from B
+    block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response(1))).unwrap();
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 1);
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(num_sent(&mut rx_b), 1);
+}
+
+#[test]
+fn test_slow_start_chunk_limit() {
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.slow_start_chunk_limit = Some(10);
+    config.max_chunk_limit = 50;
+    let mut coordinator =
+        create_coordinator_with_config(Arc::new(FakeClock::new()), Duration::from_secs(0), config);
+
+    // the first request probes the peer with the small limit
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    assert_eq!(sent_chunk_request_limits(&mut network_reqs_rx), vec![10]);
+
+    // every chunk received doubles the limit, up to the maximum
+    for (version, limit) in &[(1, 20), (2, 40), (3, 50), (4, 50)] {
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(*version)))
+            .unwrap();
+        assert_eq!(
+            sent_chunk_request_limits(&mut network_reqs_rx),
+            vec![*limit]
+        );
+    }
+}
+
+#[test]
+fn test_sync_request_completion_info() {
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator(Arc::clone(&clock), Duration::from_secs(0));
+
+    let (callback, mut first_receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(5),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    assert!(first_receiver.try_recv().unwrap().is_none());
+
+    // a request to another target replaces the pending one
+    let (callback, mut second_receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(7),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    let error = first_receiver.try_recv().unwrap().unwrap().unwrap_err();
+    assert_eq!(
+        error.downcast::<SyncError>().unwrap(),
+        SyncError::TargetConflict {
+            pending_version: 5,
+            requested_version: 7,
+        }
+    );
+
+    clock.advance(Duration::from_secs(3));
+    block_on(coordinator.commit(4));
+    assert!(second_receiver.try_recv().unwrap().is_none());
+    block_on(coordinator.commit(7));
+    let completion_info = second_receiver.try_recv().unwrap().unwrap().unwrap();
+    assert_eq!(completion_info.reached_li, test_ledger_info(7));
+    assert_eq!(completion_info.versions_applied, 7);
+    assert_eq!(completion_info.duration, Duration::from_secs(3));
+
+    // a target that is already reached completes right away
+    let (callback, mut third_receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(0),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    let completion_info = third_receiver.try_recv().unwrap().unwrap().unwrap();
+    assert_eq!(completion_info.reached_li, test_ledger_info(0));
+    assert_eq!(completion_info.versions_applied, 0);
+}
+
+#[test]
+fn test_consensus_sync_times_out_sooner_than_catchup() {
+    let (signers, _) = random_validator_verifier(1, None, true);
+    let ledger_info = TypesLedgerInfo::new(
+        BlockInfo::new(0, 0, HashValue::zero(), HashValue::zero(), 10, 0, None),
+        HashValue::zero(),
+    );
+    let signatures = signers
+        .iter()
+        .map(|s| (s.author(), s.sign_message(ledger_info.hash()).unwrap()))
+        .collect();
+    let target = LedgerInfoWithSignatures::new(ledger_info, signatures);
+    let config = StateSyncConfig::default();
+    let elapsed = Duration::from_millis(config.consensus_sync_timeout_ms + 50);
+    assert!(elapsed < Duration::from_millis(2 * config.tick_interval_ms));
+
+    for (purpose, expect_retry) in &[
+        (SyncPurpose::Consensus, true),
+        (SyncPurpose::Catchup, false),
+    ] {
+        let clock = Arc::new(FakeClock::new());
+        let mut coordinator = create_validator_coordinator(Arc::clone(&clock));
+        let mut network_reqs_rx = connect_peer(&mut coordinator, signers[0].author());
+
+        let (callback, _receiver) = oneshot::channel();
+        block_on(coordinator.request_sync(SyncRequest {
+            callback,
+            target: target.clone(),
+            purpose: *purpose,
+            progress: None,
+        }));
+        assert_eq!(num_sent(&mut network_reqs_rx), 1);
+
+        clock.advance(elapsed);
+        block_on(coordinator.check_progress());
+        assert_eq!(
+            num_sent(&mut network_reqs_rx),
+            if *expect_retry { 1 } else { 0 },
+            "purpose: {:?}",
+            purpose
+        );
+    }
+}
+
+#[test]
+fn test_sync_request_progress() {
+    let peer_id = PeerId::random();
+    let mut coordinator = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+    let (callback, mut completion_receiver) = oneshot::channel();
+    let (progress_sender, mut progress_receiver) = mpsc::unbounded();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(3),
+        purpose: SyncPurpose::Catchup,
+        progress: Some(progress_sender),
+    }));
+
+    // every chunk is reported, and the completion only comes with the last one
+    for version in 1..=3 {
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(version)))
+            .unwrap();
+        assert_eq!(
+            progress_receiver.select_next_some().now_or_never(),
+            Some(version)
+        );
+        assert_eq!(
+            completion_receiver.try_recv().unwrap().is_some(),
+            version == 3
+        );
+    }
+    assert_eq!(progress_receiver.select_next_some().now_or_never(), None);
+}
+
+#[test]
+fn test_initial_sync_complete_sent_once() {
+    let peer_id = PeerId::random();
+    let mut coordinator = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+    let (event_sender, mut events) = mpsc::unbounded();
+    coordinator.set_event_listener(event_sender);
+
+    // behind the version advertised by the peer until the third chunk
+    for version in 1..=3 {
+        block_on(
+            coordinator
+                .process_chunk_response(&peer_id, test_chunk_response_with_target(version, 3)),
+        )
+        .unwrap();
+        let expected = if version == 3 {
+            Some(SyncEvent::InitialSyncComplete { version: 3 })
+        } else {
+            None
+        };
+        assert_eq!(events.select_next_some().now_or_never(), expected);
+    }
+
+    // neither falling behind and catching up again nor keeping up fires it again
+    for version in 4..=6 {
+        block_on(
+            coordinator
+                .process_chunk_response(&peer_id, test_chunk_response_with_target(version, 6)),
+        )
+        .unwrap();
+    }
+    for version in 7..=8 {
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(version)))
+            .unwrap();
+    }
+    block_on(coordinator.process_chunk_response(&peer_id, chunk_response(0, None, 8))).unwrap();
+    assert_eq!(events.select_next_some().now_or_never(), None);
+}
+
+#[test]
+fn test_sync_session_metrics() {
+    let peer_id = PeerId::random();
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator(Arc::clone(&clock), Duration::from_secs(0));
+    let session_metrics = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, mut receiver) = oneshot::channel();
+        coordinator.get_session_metrics(callback);
+        receiver.try_recv().unwrap().unwrap()
+    };
+    assert_eq!(session_metrics(&coordinator), None);
+
+    // a chunk applied before the session does not count toward it
+    block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).unwrap();
+    let (callback, mut completion_receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(3),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    assert_eq!(
+        session_metrics(&coordinator),
+        Some(SyncSessionMetrics::default())
+    );
+
+    let mut bytes_transferred = 0;
+    for version in 2..=3 {
+        let response = test_chunk_response_with_target(version, 3);
+        bytes_transferred += response.encoded_len() as u64;
+        clock.advance(Duration::from_secs(1));
+        block_on(coordinator.process_chunk_response(&peer_id, response)).unwrap();
+        if version == 2 {
+            assert_eq!(
+                session_metrics(&coordinator),
+                Some(SyncSessionMetrics {
+                    txns_replayed: 1,
+                    chunks_applied: 1,
+                    bytes_transferred,
+                    elapsed: Duration::from_secs(1),
+                })
+            );
+        }
+    }
+
+    let completion_info = completion_receiver.try_recv().unwrap().unwrap().unwrap();
+    assert_eq!(
+        completion_info.metrics,
+        SyncSessionMetrics {
+            txns_replayed: completion_info.versions_applied,
+            chunks_applied: 2,
+            bytes_transferred,
+            elapsed: completion_info.duration,
+        }
+    );
+    assert_eq!(completion_info.versions_applied, 2);
+    assert_eq!(completion_info.duration, Duration::from_secs(2));
+    assert_eq!(session_metrics(&coordinator), None);
+}
+
+#[test]
+fn test_coalesce_sync_requests_for_same_target() {
+    let (signers, _) = random_validator_verifier(1, None, true);
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_validator_coordinator(Arc::clone(&clock));
+    let mut network_reqs_rx = connect_peer(&mut coordinator, signers[0].author());
+    let request_sync = |coordinator: &mut SyncCoordinator<TestExecutorProxy>, version| {
+        let (callback, receiver) = oneshot::channel();
+        block_on(coordinator.request_sync(SyncRequest {
+            callback,
+            target: signed_ledger_info(0, version, None, &signers),
+            purpose: SyncPurpose::Consensus,
+            progress: None,
+        }));
+        receiver
+    };
+
+    // three requests for the same target make a single sync, completing all of them
+    let receivers: Vec<_> = (0..3).map(|_| request_sync(&mut coordinator, 5)).collect();
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+    block_on(coordinator.commit(5));
+    for mut receiver in receivers {
+        let completion_info = receiver.try_recv().unwrap().unwrap().unwrap();
+        assert_eq!(completion_info.reached_li.ledger_info().version(), 5);
+    }
+
+    // all of them fail as well when another target supersedes them
+    let receivers: Vec<_> = (0..3).map(|_| request_sync(&mut coordinator, 8)).collect();
+    assert_eq!(num_sent(&mut network_reqs_rx), 1);
+    let _receiver = request_sync(&mut coordinator, 10);
+    for mut receiver in receivers {
+        let error = receiver.try_recv().unwrap().unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast::<SyncError>().unwrap(),
+            SyncError::TargetConflict {
+                pending_version: 8,
+                requested_version: 10,
+            }
+        );
+    }
+}
+
+#[test]
+fn test_fail_sync_request_without_peers() {
+    let (signers, _) = random_validator_verifier(2, None, true);
+    let peers: Vec<_> = signers.iter().map(ValidatorSigner::author).collect();
+    let mut coordinator = create_validator_coordinator(Arc::new(FakeClock::new()));
+    let _network_reqs_rxs: Vec<_> = peers
+        .iter()
+        .map(|peer_id| connect_peer(&mut coordinator, *peer_id))
+        .collect();
+    let request_sync = |coordinator: &mut SyncCoordinator<TestExecutorProxy>, version| {
+        let (callback, receiver) = oneshot::channel();
+        block_on(coordinator.request_sync(SyncRequest {
+            callback,
+            target: signed_ledger_info(0, version, None, &signers),
+            purpose: SyncPurpose::Consensus,
+            progress: None,
+        }));
+        receiver
+    };
+
+    for version in &[5, 10] {
+        let mut receiver = request_sync(&mut coordinator, *version);
+
+        // the sync goes on as long as a peer is left
+        coordinator.process_lost_peer(peers[0]);
+        assert!(receiver.try_recv().unwrap().is_none());
+
+        // losing the last one fails it right away
+        coordinator.process_lost_peer(peers[1]);
+        assert_eq!(counters::NO_SYNC_PEERS.get(), 1);
+        let error = receiver.try_recv().unwrap().unwrap().unwrap_err();
+        assert_eq!(
+            error.downcast::<SyncError>().unwrap(),
+            SyncError::NoPeersAvailable
+        );
+
+        // the condition clears once a peer is back, which the next sync goes to
+        let mut network_reqs_rx = connect_peer(&mut coordinator, peers[1]);
+        assert_eq!(counters::NO_SYNC_PEERS.get(), 0);
+        let mut receiver = request_sync(&mut coordinator, *version);
+        assert_eq!(num_sent(&mut network_reqs_rx), 1);
+        block_on(coordinator.commit(*version));
+        assert!(receiver.try_recv().unwrap().unwrap().is_ok());
+        let _network_reqs_rx = connect_peer(&mut coordinator, peers[0]);
+    }
+}
+
+#[test]
+fn test_reject_peer_below_min_protocol_version() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[peer_a, peer_b]);
+    config.min_supported_protocol_version = 1;
+    let timeout = Duration::from_millis(config.tick_interval_ms + config.long_poll_timeout_ms);
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+    let mut rx_a = connect_peer(&mut coordinator, peer_a);
+    assert_eq!(num_sent(&mut rx_a), 1);
+    let mut rx_b = connect_peer(&mut coordinator, peer_b);
+
+    // A turns out to speak the unversioned protocol: its request is answered with an error only
+    let mut request = GetChunkRequest::default();
+    request.limit = 10;
+    let message = StateSynchronizerMsg {
+        message: Some(StateSynchronizerMsg_oneof::ChunkRequest(request)),
+        protocol_version: 0,
+    };
+    block_on(coordinator.process_message(peer_a, message));
+    assert_eq!(sent_protocol_errors(&mut rx_a), vec![1]);
+    assert_eq!(
+        coordinator.peer_manager().inactive_reason(&peer_a),
+        Some(InactiveReason::IncompatibleProtocolVersion(0))
+    );
+
+    // the request in flight to A is dropped and A is not picked anymore
+    for _ in 0..10 {
+        block_on(coordinator.check_progress());
+        clock.advance(timeout);
+    }
+    assert_eq!(num_sent(&mut rx_a), 0);
+    assert_eq!(sent_chunk_request_limits(&mut rx_b).len(), 10);
+
+    // relaxing the minimum through the config reload path brings A back
+    coordinator.update_min_protocol_version(0);
+    assert_eq!(coordinator.peer_manager().inactive_reason(&peer_a), None);
+    for _ in 0..10 {
+        block_on(coordinator.check_progress());
+        clock.advance(timeout);
+    }
+    assert!(!sent_chunk_request_limits(&mut rx_a).is_empty());
+}
+
+#[test]
+fn test_reject_chunk_below_min_signature_quorum() {
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.min_signature_quorum = Some(SignatureQuorum::Fraction(0.75));
+    let clock = Arc::new(FakeClock::new());
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 4,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+
+    let (signers, _verifier) = random_validator_verifier(4, None, true);
+    let chunk_signed_by = |num_signers: usize| {
+        let mut response = test_chunk_response(1);
+        let target: LedgerInfo = response.ledger_info_with_sigs.unwrap().try_into().unwrap();
+        let signatures = signers[..num_signers]
+            .iter()
+            .map(|s| {
+                (
+                    s.author(),
+                    s.sign_message(target.ledger_info().hash()).unwrap(),
+                )
+            })
+            .collect();
+        let target = LedgerInfoWithSignatures::new(target.ledger_info().clone(), signatures);
+        response.ledger_info_with_sigs = Some(target.into());
+        response
+    };
+
+    // 2 out of 4 signatures might well be a quorum of voting power, but not 75% of the validators
+    let err =
+        block_on(coordinator.process_chunk_response(&peer_id, chunk_signed_by(2))).unwrap_err();
+    assert!(err.to_string().contains("2 signatures, 3 required"));
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+
+    block_on(coordinator.process_chunk_response(&peer_id, chunk_signed_by(3))).unwrap();
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 1);
+}
+
+/// A chunk of one transaction at version 1, extending a ledger whose transaction at version 0 has
+/// the transaction info hash `genesis_hash`, with a target ledger info it verifies against
+fn test_chunk_response_extending(genesis_hash: HashValue) -> GetChunkResponse {
+    let transaction = Transaction::WriteSet(WriteSet::default());
+    let txn_info = TransactionInfo::new(
+        transaction.hash(),
+        HashValue::zero(),
+        HashValue::zero(),
+        0,
+        StatusCode::EXECUTED,
+    );
+    let root_hash = InMemoryAccumulator::<TransactionAccumulatorHasher>::from_leaves(&[
+        genesis_hash,
+        txn_info.hash(),
+    ])
+    .root_hash();
+    let proof = TransactionListProof::new(
+        TransactionAccumulatorRangeProof::new(vec![genesis_hash], vec![]),
+        vec![txn_info],
+    );
+    let txn_list_with_proof =
+        TransactionListWithProof::new(vec![transaction], None, Some(1), proof);
+    let target = TypesLedgerInfo::new(
+        BlockInfo::new(0, 0, HashValue::zero(), root_hash, 1, 0, None),
+        HashValue::zero(),
+    );
+    GetChunkResponse {
+        txn_list_with_proof: Some(txn_list_with_proof.into()),
+        ledger_info_with_sigs: Some(LedgerInfoWithSignatures::new(target, BTreeMap::new()).into()),
+    }
+}
+
+#[test]
+fn test_reject_chunk_forking_from_local_ledger() {
+    let peer_id = PeerId::random();
+    let clock = Arc::new(FakeClock::new());
+    let local_genesis_hash = HashValue::from_sha3_256(b"local genesis");
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![local_genesis_hash],
+    };
+    let mut coordinator = create_coordinator_with_proxy(
+        clock,
+        config_with_upstream_peers(&[peer_id]),
+        executor_proxy,
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let (callback, mut receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(1),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    let last_error = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.get_last_error(callback);
+        block_on(receiver).unwrap()
+    };
+
+    // a forked chunk whose proof does not verify is merely invalid
+    let mut invalid_chunk = test_chunk_response_extending(HashValue::from_sha3_256(b"fork"));
+    invalid_chunk.ledger_info_with_sigs = Some(test_ledger_info(1).into());
+    let error = block_on(coordinator.process_chunk_response(&peer_id, invalid_chunk)).unwrap_err();
+    assert!(error.downcast::<SyncError>().is_err());
+    match last_error(&coordinator) {
+        Some(ChunkFailure::InvalidProof { .. }) => (),
+        failure => panic!("unexpected chunk failure {:?}", failure),
+    }
+    assert!(receiver.try_recv().unwrap().is_none());
+
+    // a forked chunk verifying against its signed target is refused, failing the sync
+    let forked_chunk = test_chunk_response_extending(HashValue::from_sha3_256(b"fork"));
+    let error = block_on(coordinator.process_chunk_response(&peer_id, forked_chunk)).unwrap_err();
+    assert_eq!(
+        error.downcast::<SyncError>().unwrap(),
+        SyncError::Reorg { version: 1 }
+    );
+    assert_eq!(
+        last_error(&coordinator),
+        Some(ChunkFailure::Reorg {
+            peer_id,
+            version: 1,
+        })
+    );
+    let error = receiver.try_recv().unwrap().unwrap().unwrap_err();
+    assert_eq!(
+        error.downcast::<SyncError>().unwrap(),
+        SyncError::Reorg { version: 1 }
+    );
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+
+    // the chunk extending the local ledger applies
+    block_on(
+        coordinator
+            .process_chunk_response(&peer_id, test_chunk_response_extending(local_genesis_hash)),
+    )
+    .unwrap();
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 1);
+}
+
+/// Ledger info at `version` of `epoch` signed by `signers`, ending the epoch if
+/// `next_validator_set` is given
+fn signed_ledger_info(
+    epoch: u64,
+    version: u64,
+    next_validator_set: Option<ValidatorSet>,
+    signers: &[ValidatorSigner],
+) -> LedgerInfo {
+    let ledger_info = TypesLedgerInfo::new(
+        BlockInfo::new(
+            epoch,
+            0,
+            HashValue::zero(),
+            HashValue::zero(),
+            version,
+            0,
+            next_validator_set,
+        ),
+        HashValue::zero(),
+    );
+    let signatures = signers
+        .iter()
+        .map(|s| (s.author(), s.sign_message(ledger_info.hash()).unwrap()))
+        .collect();
+    LedgerInfoWithSignatures::new(ledger_info, signatures)
+}
+
+#[test]
+fn test_epoch_boundary_chunk() {
+    let reconfiguration_chunks =
+        counters::EPOCH_BOUNDARY_CHUNKS.with_label_values(&["reconfiguration"]);
+    let split_chunks = counters::EPOCH_BOUNDARY_CHUNKS.with_label_values(&["split"]);
+    let verifications = counters::EPOCH_PROOF_VERIFICATIONS.with_label_values(&["success"]);
+    let (num_reconfiguration_chunks, num_split_chunks, num_verifications) = (
+        reconfiguration_chunks.get(),
+        split_chunks.get(),
+        verifications.get(),
+    );
+
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_validator_coordinator(clock);
+    let (signers, _) = random_validator_verifier(3, None, true);
+    let (peer_a, peer_b, peer_c) = (
+        signers[0].author(),
+        signers[1].author(),
+        signers[2].author(),
+    );
+    let _network_reqs_rxs: Vec<_> = [peer_a, peer_b, peer_c]
+        .iter()
+        .map(|peer_id| connect_peer(&mut coordinator, *peer_id))
+        .collect();
+
+    // A and B validate epoch 0, which ends at version 1 with B and C taking over
+    let next_verifier = ValidatorVerifier::new(
+        signers[1..]
+            .iter()
+            .map(|s| (s.author(), ValidatorInfo::new(s.public_key(), 1)))
+            .collect(),
+    );
+    let epoch_change = signed_ledger_info(0, 1, Some((&next_verifier).into()), &signers[..2]);
+    let (callback, _receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: signed_ledger_info(0, 2, None, &signers[..2]),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    assert!(coordinator.peer_manager().is_upstream(&peer_a));
+    assert!(!coordinator.peer_manager().is_upstream(&peer_c));
+
+    // the chunk is cut at the epoch boundary, short of the sync target
+    let mut response = test_chunk_response(1);
+    response.ledger_info_with_sigs = Some(epoch_change.into());
+    block_on(coordinator.process_chunk_response(&peer_a, response)).unwrap();
+    assert_eq!(reconfiguration_chunks.get(), num_reconfiguration_chunks + 1);
+    assert_eq!(split_chunks.get(), num_split_chunks + 1);
+    assert_eq!(verifications.get(), num_verifications + 1);
+
+    // A left the validator set and is no longer picked, C joined it
+    assert!(!coordinator.peer_manager().is_upstream(&peer_a));
+    assert!(coordinator.peer_manager().is_upstream(&peer_b));
+    assert!(coordinator.peer_manager().is_upstream(&peer_c));
+    assert_eq!(coordinator.num_validators(), 2);
+
+    // B and C validate epoch 1, which ends at version 2 with C alone taking over
+    let next_verifier = ValidatorVerifier::new(
+        signers[2..]
+            .iter()
+            .map(|s| (s.author(), ValidatorInfo::new(s.public_key(), 1)))
+            .collect(),
+    );
+    let epoch_change = signed_ledger_info(1, 2, Some((&next_verifier).into()), &signers[1..]);
+    let mut response = test_chunk_response(2);
+    response.ledger_info_with_sigs = Some(epoch_change.into());
+    block_on(coordinator.process_chunk_response(&peer_b, response)).unwrap();
+    assert_eq!(reconfiguration_chunks.get(), num_reconfiguration_chunks + 2);
+    // the chunk reaches the sync target
+    assert_eq!(split_chunks.get(), num_split_chunks + 1);
+    assert_eq!(verifications.get(), num_verifications + 2);
+
+    // the validator set shrank from the one of epoch 1, not of the startup epoch
+    assert!(!coordinator.peer_manager().is_upstream(&peer_b));
+    assert!(coordinator.peer_manager().is_upstream(&peer_c));
+    assert_eq!(coordinator.num_validators(), 1);
+}
+
+/// A coordinator whose `get_chunk` never completes for chunks of 7 transactions
+fn create_stalling_coordinator(config: StateSyncConfig) -> SyncCoordinator<TestExecutorProxy> {
+    let clock = Arc::new(FakeClock::new());
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: Some(7),
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    create_coordinator_with_proxy(clock, config, executor_proxy)
+}
+
+#[test]
+fn test_stalled_subscriber_does_not_delay_others() {
+    let mut config = StateSyncConfig::default();
+    config.max_concurrent_subscription_deliveries = 2;
+    let mut coordinator = create_stalling_coordinator(config);
+    let stalled_peer = PeerId::random();
+    let mut stalled_rx = connect_peer(&mut coordinator, stalled_peer);
+    let peers = [PeerId::random(), PeerId::random()];
+    let mut receivers: Vec<_> = peers
+        .iter()
+        .map(|peer_id| connect_peer(&mut coordinator, *peer_id))
+        .collect();
+
+    subscribe(&mut coordinator, stalled_peer, 0, 7);
+    for peer_id in &peers {
+        subscribe(&mut coordinator, *peer_id, 0, 10);
+    }
+    block_on(coordinator.commit(1));
+    // the other subscribers are served while the stalled delivery holds a slot of the executor
+    for receiver in &mut receivers {
+        assert!(next_sent(receiver).is_some());
+    }
+    for peer_id in &peers {
+        subscribe(&mut coordinator, *peer_id, 1, 10);
+    }
+    block_on(coordinator.commit(2));
+    for receiver in &mut receivers {
+        assert!(next_sent(receiver).is_some());
+    }
+    assert_eq!(num_sent(&mut stalled_rx), 0);
+
+    // the stalled delivery is dropped once its peer disconnects
+    let dropped = counters::SUBSCRIPTION_DELIVERIES_DROPPED.with_label_values(&["disconnected"]);
+    let count_before = dropped.get();
+    coordinator.process_lost_peer(stalled_peer);
+    assert!(wait_until(|| dropped.get() > count_before));
+}
+
+#[test]
+fn test_stalled_subscription_delivery_times_out() {
+    let mut config = StateSyncConfig::default();
+    config.subscription_delivery_timeout_ms = 100;
+    let mut coordinator = create_stalling_coordinator(config);
+    let peer_id = PeerId::random();
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+
+    let timed_out = counters::SUBSCRIPTION_DELIVERIES_DROPPED.with_label_values(&["timeout"]);
+    let count_before = timed_out.get();
+    subscribe(&mut coordinator, peer_id, 0, 7);
+    block_on(coordinator.commit(1));
+    assert!(wait_until(|| timed_out.get() > count_before));
+
+    // the next delivery to the peer no longer waits for the stalled one
+    subscribe(&mut coordinator, peer_id, 1, 10);
+    block_on(coordinator.commit(2));
+    assert!(next_sent(&mut network_reqs_rx).is_some());
+}
+
+#[test]
+fn test_record_and_replay_session() {
+    let peer_id = PeerId::random();
+    let config = config_with_upstream_peers(&[peer_id]);
+    let mut coordinator = create_coordinator_with_config(
+        Arc::new(FakeClock::new()),
+        Duration::from_secs(0),
+        config.clone(),
+    );
+    let (recorder, recording) = mpsc::unbounded();
+    coordinator.record_session(recorder);
+
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let (callback, _receiver) = oneshot::channel();
+    block_on(
+        coordinator.process_client_message(CoordinatorMessage::Request(SyncRequest {
+            callback,
+            target: test_ledger_info(3),
+            purpose: SyncPurpose::Catchup,
+            progress: None,
+        })),
+    );
+    for version in 1..=3 {
+        let message = StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::ChunkResponse(
+                test_chunk_response(version),
+            )),
+            protocol_version: PROTOCOL_VERSION,
+        };
+        block_on(coordinator.process_message(peer_id, message));
+    }
+    let (callback, receiver) = oneshot::channel();
+    block_on(coordinator.process_client_message(CoordinatorMessage::GetState(callback)));
+    let recorded_version = block_on(receiver).unwrap();
+    assert_eq!(recorded_version, 3);
+
+    // dropping the coordinator ends the recording
+    drop(coordinator);
+    let events: Vec<_> = block_on(recording.collect());
+    assert_eq!(events.len(), 6);
+    assert_eq!(events[0], SessionEvent::NewPeer(peer_id, 0));
+    assert_eq!(events[5], SessionEvent::GetState);
+
+    // replaying against a fresh executor proxy reaches the same version
+    let clock = Arc::new(FakeClock::new());
+    let executor_proxy = TestExecutorProxy {
+        clock,
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let (network_reqs_tx, _network_reqs_rx) = channel::new_test(8);
+    let replayed_version = block_on(replay_session(
+        RoleType::FullNode,
+        config,
+        executor_proxy,
+        TEST_RUNTIME.executor(),
+        StateSynchronizerSender::new(network_reqs_tx),
+        events,
+    ))
+    .unwrap();
+    assert_eq!(replayed_version, recorded_version);
+}
+
+#[test]
+fn test_shutdown_drains_subscriptions() {
+    let mut upstream = create_coordinator(Arc::new(FakeClock::new()), Duration::from_secs(0));
+    let peers = [PeerId::random(), PeerId::random()];
+    let mut receivers: Vec<_> = peers
+        .iter()
+        .map(|peer_id| connect_peer(&mut upstream, *peer_id))
+        .collect();
+    // nothing newer than version 0 yet, so the requests become subscriptions
+    for peer_id in &peers {
+        subscribe(&mut upstream, *peer_id, 0, 10);
+    }
+    for receiver in &mut receivers {
+        assert_eq!(num_sent(receiver), 0);
+    }
+
+    let (callback, drained) = oneshot::channel();
+    block_on(upstream.process_client_message(CoordinatorMessage::Shutdown(callback)));
+    block_on(drained).unwrap();
+    let mut closed_messages = vec![];
+    for receiver in &mut receivers {
+        let mut messages = sent_messages(receiver);
+        assert_eq!(messages.len(), 1);
+        let message = messages.pop().unwrap();
+        match &message.message {
+            Some(StateSynchronizerMsg_oneof::SubscriptionClosed(closed)) => {
+                assert_eq!(closed.known_version, 0)
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+        closed_messages.push(message);
+    }
+
+    // a full node long-polling the upstream peer fails over to its other upstream peer right away
+    let (closing_peer, other_upstream) = (peers[0], PeerId::random());
+    let mut node = create_coordinator_with_config(
+        Arc::new(FakeClock::new()),
+        Duration::from_secs(0),
+        config_with_upstream_peers(&[closing_peer, other_upstream]),
+    );
+    let mut closing_rx = connect_peer(&mut node, closing_peer);
+    assert_eq!(num_sent(&mut closing_rx), 1);
+    let mut other_rx = connect_peer(&mut node, other_upstream);
+    assert_eq!(num_sent(&mut other_rx), 0);
+    block_on(node.process_message(closing_peer, closed_messages.swap_remove(0)));
+    assert_eq!(sent_chunk_request_limits(&mut other_rx).len(), 1);
+    assert_eq!(num_sent(&mut closing_rx), 0);
+}
+
+/// A message carrying a chunk request of at most 10 transactions after version 0, to be served
+/// right away
+fn chunk_request_message() -> StateSynchronizerMsg {
+    let mut request = GetChunkRequest::default();
+    request.limit = 10;
+    StateSynchronizerMsg {
+        message: Some(StateSynchronizerMsg_oneof::ChunkRequest(request)),
+        protocol_version: PROTOCOL_VERSION,
+    }
+}
+
+fn chunk_response_message(version: u64) -> StateSynchronizerMsg {
+    StateSynchronizerMsg {
+        message: Some(StateSynchronizerMsg_oneof::ChunkResponse(
+            test_chunk_response(version),
+        )),
+        protocol_version: PROTOCOL_VERSION,
+    }
+}
+
+/// Versions of the ledger infos of the chunk responses sent through `receiver`'s sender so far
+fn sent_chunk_response_targets(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<u64> {
+    sent_chunk_responses(receiver)
+        .into_iter()
+        .map(|response| {
+            let target: LedgerInfo = response.ledger_info_with_sigs.unwrap().try_into().unwrap();
+            target.ledger_info().version()
+        })
+        .collect()
+}
+
+#[test]
+fn test_chunk_responses_processed_before_requests() {
+    let clock = Arc::new(FakeClock::new());
+    let (upstream, downstream) = (PeerId::random(), PeerId::random());
+    let config = config_with_upstream_peers(&[upstream]);
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_millis(10), config);
+    let _upstream_rx = connect_peer(&mut coordinator, upstream);
+    let (network_reqs_tx, mut downstream_rx) = channel::new_test(100);
+    block_on(coordinator.process_new_peer(
+        downstream,
+        0,
+        StateSynchronizerSender::new(network_reqs_tx),
+    ));
+
+    // the downstream peer floods the node with requests while the chunks it is syncing arrive
+    let deferred_before = counters::DEFERRED_MESSAGES.get();
+    for _ in 0..50 {
+        coordinator.enqueue_message(downstream, chunk_request_message());
+    }
+    for version in 1..=3 {
+        coordinator.enqueue_message(upstream, chunk_response_message(version));
+    }
+    block_on(coordinator.process_inbound_messages());
+
+    // the chunks are applied first and every request is still served, from the new state
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 3);
+    assert_eq!(sent_chunk_response_targets(&mut downstream_rx), vec![3; 50]);
+    assert!(counters::DEFERRED_MESSAGES.get() >= deferred_before + 50);
+}
+
+#[test]
+fn test_chunk_request_deferral_is_bounded() {
+    let clock = Arc::new(FakeClock::new());
+    let (upstream, downstream) = (PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[upstream]);
+    config.max_chunk_request_deferral_ms = 100;
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_millis(60),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    let mut coordinator = create_coordinator_with_proxy(clock, config, executor_proxy);
+    let _upstream_rx = connect_peer(&mut coordinator, upstream);
+    let mut downstream_rx = connect_peer(&mut coordinator, downstream);
+
+    coordinator.enqueue_message(downstream, chunk_request_message());
+    for version in 1..=3 {
+        coordinator.enqueue_message(upstream, chunk_response_message(version));
+    }
+    block_on(coordinator.process_inbound_messages());
+
+    // each chunk takes 60ms to apply, so the request is served once it waited behind two of them
+    assert_eq!(sent_chunk_response_targets(&mut downstream_rx), vec![2]);
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 3);
+}
+
+#[test]
+fn test_adaptive_long_poll_timeout() {
+    let clock = Arc::new(FakeClock::new());
+    let mut config = StateSyncConfig::default();
+    config.adaptive_long_poll_timeout_ms = Some((1000, 60_000));
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+    // without commits to go by, the static timeout is used
+    assert_eq!(coordinator.long_poll_timeout_ms(), 30_000);
+
+    // a commit every 500ms: 3 intervals
+    for version in 1..=20 {
+        block_on(coordinator.commit(version));
+        clock.advance(Duration::from_millis(500));
+    }
+    assert_eq!(coordinator.long_poll_timeout_ms(), 1500);
+
+    // a commit every 100ms hits the lower bound
+    for version in 21..=40 {
+        block_on(coordinator.commit(version));
+        clock.advance(Duration::from_millis(100));
+    }
+    assert_eq!(coordinator.long_poll_timeout_ms(), 1000);
+
+    // a commit every 10s: the timeout grows with the recent intervals up to the upper bound
+    block_on(coordinator.commit(41));
+    clock.advance(Duration::from_secs(10));
+    block_on(coordinator.commit(42));
+    assert!(coordinator.long_poll_timeout_ms() > 1000);
+    for version in 43..=60 {
+        clock.advance(Duration::from_secs(10));
+        block_on(coordinator.commit(version));
+    }
+    assert_eq!(coordinator.long_poll_timeout_ms(), 30_000);
+
+    // the chosen timeout is requested from upstream peers
+    let peer_id = PeerId::random();
+    coordinator.update_upstream_peers(vec![peer_id]);
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    assert_eq!(
+        sent_chunk_request_timeouts(&mut network_reqs_rx),
+        vec![30_000]
+    );
+}
+
+/// Timeouts of the chunk requests sent through `receiver`'s sender so far
+fn sent_chunk_request_timeouts(receiver: &mut channel::Receiver<NetworkRequest>) -> Vec<u64> {
+    sent_messages(receiver)
+        .into_iter()
+        .filter_map(|msg| match msg.message {
+            Some(StateSynchronizerMsg_oneof::ChunkRequest(request)) => Some(request.timeout),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_catch_up_then_long_poll() {
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.catch_up_threshold = Some(100);
+    let mut coordinator =
+        create_coordinator_with_config(Arc::new(FakeClock::new()), Duration::from_secs(0), config);
+
+    // nothing is known of the frontier yet, so the first request long-polls
+    let mut network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    assert!(!coordinator.is_catching_up());
+    assert_eq!(
+        sent_chunk_request_timeouts(&mut network_reqs_rx),
+        vec![30_000]
+    );
+
+    // far behind the version of the peer: the next chunks are requested without a timeout
+    block_on(
+        coordinator.process_chunk_response(&peer_id, test_chunk_response_with_target(1, 1000)),
+    )
+    .unwrap();
+    assert!(coordinator.is_catching_up());
+    let timeouts = sent_chunk_request_timeouts(&mut network_reqs_rx);
+    assert!(!timeouts.is_empty());
+    assert!(timeouts.iter().all(|timeout| *timeout == 0));
+
+    // within the threshold of the frontier: back to long-polling
+    block_on(coordinator.commit(950));
+    block_on(
+        coordinator.process_chunk_response(&peer_id, test_chunk_response_with_target(951, 1000)),
+    )
+    .unwrap();
+    assert!(!coordinator.is_catching_up());
+    let timeouts = sent_chunk_request_timeouts(&mut network_reqs_rx);
+    assert!(!timeouts.is_empty());
+    assert!(timeouts.iter().all(|timeout| *timeout == 30_000));
+}
+
+/// Coordinator syncing from `peer_id` whose next chunk execution fails with `status`
+fn create_coordinator_failing_execution(
+    clock: Arc<FakeClock>,
+    config: StateSyncConfig,
+    status: RpcStatusCode,
+) -> SyncCoordinator<TestExecutorProxy> {
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(0),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(Some(status)),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    create_coordinator_with_proxy(clock, config, executor_proxy)
+}
+
+fn is_executor_error(failure: Option<ChunkFailure>, peer_id: PeerId) -> bool {
+    match failure {
+        Some(ChunkFailure::ExecutorError { peer_id: id, .. }) => id == peer_id,
+        _ => false,
+    }
+}
+
+#[test]
+fn test_retry_chunk_after_execution_failure() {
+    let peer_id = PeerId::random();
+    let config = config_with_upstream_peers(&[peer_id]);
+    let backoff = Duration::from_millis(config.chunk_execution_retry_backoff_ms);
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator_failing_execution(
+        Arc::clone(&clock),
+        config,
+        RpcStatusCode::UNAVAILABLE,
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let score = coordinator.peer_manager().score(&peer_id);
+    let known_version = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.get_state(callback);
+        block_on(receiver).unwrap()
+    };
+
+    // the chunk fails to execute on an unreachable storage and is kept, without waiting for the
+    // backoff
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).is_err()
+    );
+    assert_eq!(known_version(&coordinator), 0);
+    block_on(coordinator.retry_buffered_chunk());
+    assert_eq!(known_version(&coordinator), 0);
+
+    // it is applied when executed again after the backoff
+    clock.advance(backoff);
+    block_on(coordinator.retry_buffered_chunk());
+    assert_eq!(known_version(&coordinator), 1);
+    assert_eq!(coordinator.peer_manager().score(&peer_id), score);
+}
+
+#[test]
+fn test_chunk_execution_retries_are_bounded() {
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.max_chunk_execution_retries = 0;
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator_failing_execution(
+        Arc::clone(&clock),
+        config,
+        RpcStatusCode::UNAVAILABLE,
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let score = coordinator.peer_manager().score(&peer_id);
+
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).is_err()
+    );
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_last_error(callback);
+    assert!(is_executor_error(block_on(receiver).unwrap(), peer_id));
+    clock.advance(Duration::from_secs(1));
+    block_on(coordinator.retry_buffered_chunk());
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+    // the failure is local, so it doesn't count against the peer
+    assert_eq!(coordinator.peer_manager().score(&peer_id), score);
+}
+
+#[test]
+fn test_permanent_execution_failure_is_not_retried() {
+    let peer_id = PeerId::random();
+    let config = config_with_upstream_peers(&[peer_id]);
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator =
+        create_coordinator_failing_execution(Arc::clone(&clock), config, RpcStatusCode::UNKNOWN);
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let score = coordinator.peer_manager().score(&peer_id);
+
+    // storage served the write and failed it, executing the chunk again wouldn't help
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).is_err()
+    );
+    clock.advance(Duration::from_secs(1));
+    block_on(coordinator.retry_buffered_chunk());
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+    assert_eq!(coordinator.peer_manager().score(&peer_id), score);
+}
+
+#[test]
+fn test_expired_chunk_is_not_retried() {
+    let peer_id = PeerId::random();
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.chunk_execution_retry_backoff_ms = 1;
+    config.chunk_execution_retry_expiry_ms = 0;
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator_failing_execution(
+        Arc::clone(&clock),
+        config,
+        RpcStatusCode::UNAVAILABLE,
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+
+    // the retry would come past the expiry of the chunk, so the chunk is dropped right away
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).is_err()
+    );
+    clock.advance(Duration::from_secs(1));
+    block_on(coordinator.retry_buffered_chunk());
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+}
+
+#[test]
+fn test_invalid_chunk_proof_penalizes_peer() {
+    let peer_id = PeerId::random();
+    let clock = Arc::new(FakeClock::new());
+    let mut coordinator = create_coordinator_with_config(
+        clock,
+        Duration::from_secs(0),
+        config_with_upstream_peers(&[peer_id]),
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let score = coordinator.peer_manager().score(&peer_id).unwrap();
+
+    // the proof of the chunk does not lead to the accumulator root of its target, so the chunk
+    // is rejected before it is executed
+    let mut chunk = test_chunk_response_extending(HashValue::zero());
+    chunk.ledger_info_with_sigs = Some(test_ledger_info(1).into());
+    assert!(block_on(coordinator.process_chunk_response(&peer_id, chunk)).is_err());
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_last_error(callback);
+    match block_on(receiver).unwrap() {
+        Some(ChunkFailure::InvalidProof { peer_id: id, .. }) => assert_eq!(id, peer_id),
+        failure => panic!("unexpected chunk failure {:?}", failure),
+    }
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_state(callback);
+    assert_eq!(block_on(receiver).unwrap(), 0);
+    assert!(coordinator.peer_manager().score(&peer_id).unwrap() < score);
+}
+
+#[test]
+fn test_last_error_reports_non_sequential_chunk() {
+    let clock = Arc::new(FakeClock::new());
+    let peer_id = PeerId::random();
+    let mut coordinator = create_coordinator_with_config(
+        clock,
+        Duration::from_secs(0),
+        config_with_upstream_peers(&[peer_id]),
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let last_error = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.get_last_error(callback);
+        block_on(receiver).unwrap()
+    };
+    assert_eq!(last_error(&coordinator), None);
+
+    // the chunk at version 5 skips the versions 1 to 4
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(5))).is_err()
+    );
+    assert_eq!(
+        last_error(&coordinator),
+        Some(ChunkFailure::NonSequential {
+            peer_id,
+            known_version: 0,
+            received_version: 5,
+        })
+    );
+
+    // a new sync session starts without error
+    let (callback, _receiver) = oneshot::channel();
+    block_on(coordinator.request_sync(SyncRequest {
+        callback,
+        target: test_ledger_info(10),
+        purpose: SyncPurpose::Catchup,
+        progress: None,
+    }));
+    assert_eq!(last_error(&coordinator), None);
+}
+
+/// A chunk of `num_txns` transactions starting at `first_version`, toward `target_version`
+fn chunk_response(
+    num_txns: usize,
+    first_version: Option<u64>,
+    target_version: u64,
+) -> GetChunkResponse {
+    let txn_list_with_proof = TransactionListWithProof::new(
+        vec![Transaction::WriteSet(WriteSet::default()); num_txns],
+        None,
+        first_version,
+        TransactionListProof::new_empty(),
+    );
+    GetChunkResponse {
+        txn_list_with_proof: Some(txn_list_with_proof.into()),
+        ledger_info_with_sigs: Some(test_ledger_info(target_version).into()),
+    }
+}
+
+#[test]
+fn test_chunk_response_first_version_presence() {
+    let clock = Arc::new(FakeClock::new());
+    let peer_id = PeerId::random();
+    let mut coordinator = create_coordinator_with_config(
+        clock,
+        Duration::from_secs(0),
+        config_with_upstream_peers(&[peer_id]),
+    );
+    let _network_reqs_rx = connect_peer(&mut coordinator, peer_id);
+    let known_version = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.get_state(callback);
+        block_on(receiver).unwrap()
+    };
+    let last_error = |coordinator: &SyncCoordinator<TestExecutorProxy>| {
+        let (callback, receiver) = oneshot::channel();
+        coordinator.get_last_error(callback);
+        block_on(receiver).unwrap()
+    };
+    let is_malformed = |failure: Option<ChunkFailure>| match failure {
+        Some(ChunkFailure::MalformedResponse { peer_id: id, .. }) => id == peer_id,
+        _ => false,
+    };
+    let initial_score = coordinator.peer_manager().score(&peer_id).unwrap();
+
+    // an empty list toward the known version tells there is no new data, with or without a first
+    // version
+    for first_version in vec![None, Some(1)] {
+        block_on(coordinator.process_chunk_response(&peer_id, chunk_response(0, first_version, 0)))
+            .unwrap();
+    }
+    assert_eq!(known_version(&coordinator), 0);
+    assert_eq!(last_error(&coordinator), None);
+    assert_eq!(
+        coordinator.peer_manager().score(&peer_id),
+        Some(initial_score)
+    );
+
+    // an empty list toward a higher version is malformed, with or without a first version
+    for first_version in vec![None, Some(1)] {
+        let score = coordinator.peer_manager().score(&peer_id).unwrap();
+        assert!(block_on(
+            coordinator.process_chunk_response(&peer_id, chunk_response(0, first_version, 5))
+        )
+        .is_err());
+        assert!(is_malformed(last_error(&coordinator)));
+        assert!(coordinator.peer_manager().score(&peer_id).unwrap() < score);
+    }
+
+    // a non-empty list without a first version is malformed, and not executed
+    let score = coordinator.peer_manager().score(&peer_id).unwrap();
+    assert!(
+        block_on(coordinator.process_chunk_response(&peer_id, chunk_response(1, None, 1))).is_err()
+    );
+    assert!(is_malformed(last_error(&coordinator)));
+    assert!(coordinator.peer_manager().score(&peer_id).unwrap() < score);
+    assert_eq!(known_version(&coordinator), 0);
+
+    // a non-empty list with a first version is applied
+    block_on(coordinator.process_chunk_response(&peer_id, chunk_response(1, Some(1), 1))).unwrap();
+    assert_eq!(known_version(&coordinator), 1);
+}
+
+/// A coordinator whose storage is at `version`
+fn create_coordinator_at_version(
+    clock: Arc<FakeClock>,
+    version: u64,
+) -> SyncCoordinator<TestExecutorProxy> {
+    let executor_proxy = TestExecutorProxy {
+        clock: Arc::clone(&clock),
+        chunk_delay: Duration::from_secs(0),
+        execute_delay: Duration::from_secs(0),
+        execute_gate: Mutex::new(None),
+        version: AtomicU64::new(version),
+        epoch_changes: vec![],
+        num_validators: 1,
+        stalled_limit: None,
+        fail_next_execute: Mutex::new(None),
+        epoch_proof_failures: AtomicU64::new(0),
+        frozen_subtree_hashes: vec![],
+    };
+    create_coordinator_with_proxy(clock, StateSyncConfig::default(), executor_proxy)
+}
+
+/// The state sync messages of `receiver` whose payload `select` picks
+fn sent_payloads<T>(
+    receiver: &mut channel::Receiver<NetworkRequest>,
+    select: impl Fn(StateSynchronizerMsg_oneof) -> Option<T>,
+) -> Vec<T> {
+    sent_messages(receiver)
+        .into_iter()
+        .filter_map(|msg| msg.message.and_then(&select))
+        .collect()
+}
+
+#[test]
+fn test_audit_state_reports_divergent_version() {
+    let clock = Arc::new(FakeClock::new());
+    let (auditor_id, peer_id) = (PeerId::random(), PeerId::random());
+    let mut auditor = create_coordinator_at_version(Arc::clone(&clock), 10);
+    let mut peer = create_coordinator_at_version(clock, 10);
+    let mut auditor_rx = connect_peer(&mut auditor, peer_id);
+    let mut peer_rx = connect_peer(&mut peer, auditor_id);
+
+    let (callback, receiver) = oneshot::channel();
+    block_on(auditor.audit_state(AuditRequest {
+        peer_id,
+        versions: vec![3, 5, 12],
+        callback,
+    }));
+    let requests = sent_payloads(&mut auditor_rx, |msg| match msg {
+        StateSynchronizerMsg_oneof::RootHashesRequest(request) => Some(request),
+        _ => None,
+    });
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].versions, vec![3, 5, 12]);
+
+    // the peer answers from its storage, and its transaction info at version 5 is made to diverge
+    block_on(peer.process_message(
+        auditor_id,
+        StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::RootHashesRequest(
+                requests[0].clone(),
+            )),
+            protocol_version: PROTOCOL_VERSION,
+        },
+    ));
+    let mut responses = sent_payloads(&mut peer_rx, |msg| match msg {
+        StateSynchronizerMsg_oneof::RootHashesResponse(response) => Some(response),
+        _ => None,
+    });
+    assert_eq!(responses.len(), 1);
+    let mut response = responses.remove(0);
+    response.root_hashes[1].transaction_info_hash = HashValue::random().to_vec();
+    let mismatches = counters::STATE_AUDIT_MISMATCHES.get();
+    block_on(auditor.process_message(
+        peer_id,
+        StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::RootHashesResponse(response)),
+            protocol_version: PROTOCOL_VERSION,
+        },
+    ));
+
+    let report = block_on(receiver).unwrap().unwrap();
+    assert_eq!(report.len(), 3);
+    assert_eq!(report[0].local_hash, Some(test_transaction_info_hash(3)));
+    assert_eq!(report[0].peer_hash, report[0].local_hash);
+    assert!(!report[0].is_mismatch());
+    assert_eq!(report[1].version, 5);
+    assert!(report[1].is_mismatch());
+    // neither side committed version 12 yet
+    assert_eq!((report[2].local_hash, report[2].peer_hash), (None, None));
+    assert!(!report[2].is_mismatch());
+    assert!(counters::STATE_AUDIT_MISMATCHES.get() > mismatches);
+}
+
+#[test]
+fn test_audit_requests_are_rate_limited() {
+    let clock = Arc::new(FakeClock::new());
+    let auditor_id = PeerId::random();
+    let mut peer = create_coordinator_at_version(Arc::clone(&clock), 10);
+    let mut peer_rx = connect_peer(&mut peer, auditor_id);
+    let request = || StateSynchronizerMsg {
+        message: Some(StateSynchronizerMsg_oneof::RootHashesRequest(
+            GetRootHashesRequest { versions: vec![1] },
+        )),
+        protocol_version: PROTOCOL_VERSION,
+    };
+    let num_responses = |peer_rx: &mut channel::Receiver<NetworkRequest>| {
+        sent_payloads(peer_rx, |msg| match msg {
+            StateSynchronizerMsg_oneof::RootHashesResponse(response) => Some(response),
+            _ => None,
+        })
+        .len()
+    };
+
+    block_on(peer.process_message(auditor_id, request()));
+    block_on(peer.process_message(auditor_id, request()));
+    assert_eq!(num_responses(&mut peer_rx), 1);
+
+    clock.advance(Duration::from_millis(
+        StateSyncConfig::default().min_audit_request_interval_ms,
+    ));
+    block_on(peer.process_message(auditor_id, request()));
+    assert_eq!(num_responses(&mut peer_rx), 1);
+}
+
+#[test]
+fn test_verification_peer_discrepancy_is_flagged() {
+    let clock = Arc::new(FakeClock::new());
+    let (peer_id, verification_peer_id) = (PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.verification_peer = Some(verification_peer_id.to_string());
+    config.verification_interval = 2;
+    let mut coordinator = create_coordinator_with_config(clock, Duration::from_secs(0), config);
+    let _peer_rx = connect_peer(&mut coordinator, peer_id);
+    let mut verification_rx = connect_peer(&mut coordinator, verification_peer_id);
+    let root_hashes_requests = |receiver: &mut channel::Receiver<NetworkRequest>| {
+        sent_payloads(receiver, |msg| match msg {
+            StateSynchronizerMsg_oneof::RootHashesRequest(request) => Some(request),
+            _ => None,
+        })
+    };
+
+    // only every second chunk applied is checked against the verification peer
+    block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(1))).unwrap();
+    assert!(root_hashes_requests(&mut verification_rx).is_empty());
+    block_on(coordinator.process_chunk_response(&peer_id, test_chunk_response(2))).unwrap();
+    let requests = root_hashes_requests(&mut verification_rx);
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].versions, vec![2]);
+
+    // the root of the verification peer at version 2 disagrees with the one applied
+    let discrepancies = counters::VERIFICATION_DISCREPANCIES.get();
+    let response = GetRootHashesResponse {
+        root_hashes: vec![VersionRootHash {
+            version: 2,
+            transaction_info_hash: HashValue::random().to_vec(),
+        }],
+    };
+    block_on(coordinator.process_message(
+        verification_peer_id,
+        StateSynchronizerMsg {
+            message: Some(StateSynchronizerMsg_oneof::RootHashesResponse(response)),
+            protocol_version: PROTOCOL_VERSION,
+        },
+    ));
+    let (callback, receiver) = oneshot::channel();
+    coordinator.get_last_error(callback);
+    assert_eq!(
+        block_on(receiver).unwrap(),
+        Some(ChunkFailure::Discrepancy {
+            peer_id,
+            verification_peer_id,
+            version: 2,
+        })
+    );
+    assert!(counters::VERIFICATION_DISCREPANCIES.get() > discrepancies);
+}
+
+#[test]
+fn test_checkpoint_seeds_upstream_peers() {
+    let clock = Arc::new(FakeClock::new());
+    let dir = TempPath::new();
+    dir.create_as_dir().unwrap();
+    let (peer_a, peer_b, downstream) = (PeerId::random(), PeerId::random(), PeerId::random());
+    let mut config = config_with_upstream_peers(&[peer_a, peer_b]);
+    config.checkpoint_file = Some(dir.path().join("checkpoint"));
+
+    // A sent a chunk of its ledger at version 10, B was not heard from
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config.clone());
+    let _rx_a = connect_peer(&mut coordinator, peer_a);
+    let _rx_downstream = connect_peer(&mut coordinator, downstream);
+    block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response_with_target(1, 10)))
+        .unwrap();
+    coordinator.save_checkpoint();
+    let checkpoint = SyncCheckpoint::read(config.checkpoint_file.as_ref().unwrap()).unwrap();
+    assert_eq!(checkpoint.known_version, 1);
+    assert_eq!(checkpoint.advertised_versions, vec![(peer_a, 10)]);
+
+    // the restarted node starts with A's version as a hint
+    clock.advance(Duration::from_secs(60));
+    let mut coordinator =
+        create_coordinator_with_config(Arc::clone(&clock), Duration::from_secs(0), config);
+    block_on(coordinator.commit(1));
+    coordinator.load_checkpoint();
+    assert_eq!(
+        coordinator.peer_manager().advertised_version(&peer_a),
+        Some(10)
+    );
+    assert_eq!(coordinator.peer_manager().advertised_version(&peer_b), None);
+
+    // which is replaced once A is heard from
+    let _rx_a = connect_peer(&mut coordinator, peer_a);
+    block_on(coordinator.process_chunk_response(&peer_a, test_chunk_response_with_target(2, 8)))
+        .unwrap();
+    assert_eq!(
+        coordinator.peer_manager().advertised_version(&peer_a),
+        Some(8)
+    );
+}
+
+#[test]
+fn test_unusable_checkpoint_is_ignored() {
+    let clock = Arc::new(FakeClock::new());
+    let dir = TempPath::new();
+    dir.create_as_dir().unwrap();
+    let peer_id = PeerId::random();
+    let path = dir.path().join("checkpoint");
+    let mut config = config_with_upstream_peers(&[peer_id]);
+    config.checkpoint_file = Some(path.clone());
+    let max_age = Duration::from_millis(config.max_checkpoint_age_ms);
+    let load_checkpoint = |checkpoint: &[u8]| {
+        fs::write(&path, checkpoint).unwrap();
+        let mut coordinator = create_coordinator_with_config(
+            Arc::clone(&clock),
+            Duration::from_secs(0),
+            config.clone(),
+        );
+        block_on(coordinator.commit(5));
+        coordinator.load_checkpoint();
+        coordinator.peer_manager().advertised_version(&peer_id)
+    };
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let checkpoint = |known_version, timestamp| {
+        lcs::to_bytes(&SyncCheckpoint::new(
+            known_version,
+            vec![(peer_id, 100)],
+            timestamp,
+        ))
+        .unwrap()
+    };
+
+    assert_eq!(load_checkpoint(&checkpoint(5, now - max_age)), Some(100));
+    // stale
+    assert_eq!(
+        load_checkpoint(&checkpoint(5, now - max_age - Duration::from_secs(1))),
+        None
+    );
+    // ahead of the storage, which was wiped since
+    assert_eq!(load_checkpoint(&checkpoint(6, now)), None);
+    // corrupt
+    assert_eq!(load_checkpoint(b"not a checkpoint"), None);
+}
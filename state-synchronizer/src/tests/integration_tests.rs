@@ -2,17 +2,19 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
-    executor_proxy::ExecutorProxyTrait, LedgerInfo, PeerId, StateSyncClient, StateSynchronizer,
+    executor_proxy::{EpochProofPage, ExecutorProxyTrait},
+    BuildError, CommittedTransactionData, LedgerInfo, PeerId, StateSyncClient, StateSynchronizer,
+    StateSynchronizerBuilder, SyncCompletionInfo, SyncPurpose,
 };
 use config_builder::util::get_test_config;
+use executor::ChunkExecutionStages;
 use failure::{prelude::*, Result};
-use futures::{executor::block_on, future::FutureExt, Future};
+use futures::{channel::mpsc, executor::block_on, future::FutureExt, stream::StreamExt, Future};
 use libra_config::config::RoleType;
 use libra_crypto::{
     ed25519::*, test_utils::TEST_SEED, traits::Genesis, x25519, HashValue, SigningKey,
 };
 use libra_types::block_info::BlockInfo;
-use libra_types::crypto_proxies::ValidatorChangeEventWithProof;
 use libra_types::{
     account_address::AccountAddress,
     crypto_proxies::LedgerInfoWithSignatures,
@@ -25,7 +27,7 @@ use network::{
     proto::GetChunkResponse,
     validator_network::{
         network_builder::{NetworkBuilder, TransportType},
-        STATE_SYNCHRONIZER_DIRECT_SEND_PROTOCOL,
+        StateSynchronizerEvents, StateSynchronizerSender, STATE_SYNCHRONIZER_DIRECT_SEND_PROTOCOL,
     },
     NetworkPublicKeys, ProtocolId,
 };
@@ -109,6 +111,23 @@ impl ExecutorProxyTrait for MockExecutorProxy {
         async move { Ok(version) }.boxed()
     }
 
+    fn get_ledger_info_at(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let response = Self::mock_ledger_info(self.peer_id, version);
+        async move { Ok(response) }.boxed()
+    }
+
+    fn get_latest_ledger_info_at_or_before(
+        &self,
+        version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<LedgerInfo>> + Send>> {
+        let version = std::cmp::min(version, self.version.load(Ordering::Relaxed));
+        let response = Self::mock_ledger_info(self.peer_id, version);
+        async move { Ok(response) }.boxed()
+    }
+
     fn execute_chunk(
         &self,
         _txn_list_with_proof: TransactionListWithProof,
@@ -119,6 +138,10 @@ impl ExecutorProxyTrait for MockExecutorProxy {
         async move { Ok(()) }.boxed()
     }
 
+    fn last_chunk_stages(&self) -> ChunkExecutionStages {
+        ChunkExecutionStages::default()
+    }
+
     fn get_chunk(
         &self,
         known_version: u64,
@@ -133,9 +156,44 @@ impl ExecutorProxyTrait for MockExecutorProxy {
         Ok(())
     }
 
-    fn get_epoch_proof(&self, _start_epoch: u64) -> Result<ValidatorChangeEventWithProof> {
+    fn validate_chunk(
+        &self,
+        _txn_list_with_proof: &TransactionListWithProof,
+        _target: &LedgerInfo,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    fn num_validators(&self) -> usize {
+        1
+    }
+
+    fn get_epoch_proof(
+        &self,
+        _start_epoch: u64,
+        _end_epoch: u64,
+        _limit: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<EpochProofPage>> + Send>> {
         unimplemented!("get epoch proof not supported for mock executor proxy");
     }
+
+    fn subscribe_committed_data(&self) -> mpsc::Receiver<CommittedTransactionData> {
+        unimplemented!("committed data not supported for mock executor proxy");
+    }
+
+    fn get_transaction_info_hash(
+        &self,
+        _version: u64,
+    ) -> Pin<Box<dyn Future<Output = Result<Option<HashValue>>> + Send>> {
+        unimplemented!("transaction info hashes not supported for mock executor proxy");
+    }
+
+    fn get_frozen_subtree_hashes(
+        &self,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<HashValue>>> + Send>> {
+        // the chunks of the mock carry empty proofs
+        async move { Ok(vec![]) }.boxed()
+    }
 }
 
 struct SynchronizerEnv {
@@ -224,18 +282,20 @@ impl SynchronizerEnv {
             .upstream_peers
             .push(peers[1].to_string());
         let synchronizers: Vec<StateSynchronizer> = vec![
-            StateSynchronizer::bootstrap_with_executor_proxy(
-                vec![(sender_a, events_a)],
-                role,
-                &config.state_sync,
-                MockExecutorProxy::new(peers[0], Self::default_handler()),
-            ),
-            StateSynchronizer::bootstrap_with_executor_proxy(
-                vec![(sender_b, events_b)],
-                role,
-                &get_test_config().0.state_sync,
-                MockExecutorProxy::new(peers[1], handler),
-            ),
+            StateSynchronizerBuilder::new()
+                .with_network(sender_a, events_a)
+                .with_role(role)
+                .with_config(config.state_sync)
+                .with_executor_proxy(MockExecutorProxy::new(peers[0], Self::default_handler()))
+                .build()
+                .unwrap(),
+            StateSynchronizerBuilder::new()
+                .with_network(sender_b, events_b)
+                .with_role(role)
+                .with_config(get_test_config().0.state_sync)
+                .with_executor_proxy(MockExecutorProxy::new(peers[1], handler))
+                .build()
+                .unwrap(),
         ];
         let clients = synchronizers.iter().map(|s| s.create_client()).collect();
 
@@ -251,9 +311,12 @@ impl SynchronizerEnv {
         Box::new(|resp| -> Result<GetChunkResponse> { Ok(resp) })
     }
 
-    fn sync_to(&self, peer_id: usize, version: u64) {
+    fn sync_to(&self, peer_id: usize, version: u64) -> SyncCompletionInfo {
         let target = MockExecutorProxy::mock_ledger_info(self.peers[1], version);
-        block_on(self.clients[peer_id].sync_to(target)).unwrap()
+        let completion_info =
+            block_on(self.clients[peer_id].sync_to(target.clone(), SyncPurpose::Catchup)).unwrap();
+        assert_eq!(completion_info.reached_li, target);
+        completion_info
     }
 
     fn commit(&self, peer_id: usize, version: u64) {
@@ -279,10 +342,10 @@ fn test_basic_catch_up() {
 
     // test small sequential syncs
     for version in 1..5 {
-        env.sync_to(0, version);
+        assert_eq!(env.sync_to(0, version).versions_applied, 1);
     }
     // test batch sync for multiple transactions
-    env.sync_to(0, 10);
+    assert_eq!(env.sync_to(0, 10).versions_applied, 6);
 }
 
 #[test]
@@ -313,3 +376,91 @@ fn test_full_node() {
     // after receiving first chunk immediately
     assert!(env.wait_for_version(0, 20));
 }
+
+// The ends of the network channels the synchronizer doesn't hold are dropped, it never sees
+// a peer.
+fn test_network() -> (StateSynchronizerSender, StateSynchronizerEvents) {
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let (_, network_notifs_rx) = channel::new_test(8);
+    (
+        StateSynchronizerSender::new(network_reqs_tx),
+        StateSynchronizerEvents::new(network_notifs_rx),
+    )
+}
+
+fn test_executor_proxy() -> MockExecutorProxy {
+    MockExecutorProxy::new(PeerId::random(), SynchronizerEnv::default_handler())
+}
+
+#[test]
+fn test_builder_missing_pieces() {
+    let build_error = |builder: StateSynchronizerBuilder<MockExecutorProxy>| builder.build().err();
+    assert_eq!(
+        build_error(StateSynchronizerBuilder::new()),
+        Some(BuildError::MissingNetwork)
+    );
+    assert_eq!(
+        build_error(
+            StateSynchronizerBuilder::new()
+                .with_executor_proxy(test_executor_proxy())
+                .with_role(RoleType::Validator)
+        ),
+        Some(BuildError::MissingNetwork)
+    );
+    let (sender, events) = test_network();
+    assert_eq!(
+        build_error(
+            StateSynchronizerBuilder::new()
+                .with_network(sender, events)
+                .with_role(RoleType::Validator)
+        ),
+        Some(BuildError::MissingExecutorProxy)
+    );
+    let (sender, events) = test_network();
+    assert_eq!(
+        build_error(
+            StateSynchronizerBuilder::new()
+                .with_network(sender, events)
+                .with_executor_proxy(test_executor_proxy())
+        ),
+        Some(BuildError::MissingRole)
+    );
+}
+
+#[test]
+fn test_builder_defaults() {
+    let (sender, events) = test_network();
+    let mut synchronizer = StateSynchronizerBuilder::new()
+        .with_network(sender, events)
+        .with_executor_proxy(test_executor_proxy())
+        .with_role(RoleType::Validator)
+        .build()
+        .unwrap();
+    // the default config doesn't record the session
+    assert!(synchronizer.take_session_recording().is_none());
+    let client = synchronizer.create_client();
+    assert_eq!(block_on(client.get_state()).unwrap(), 0);
+    block_on(client.commit(5)).unwrap();
+    block_on(client.wait_for_version(5)).unwrap();
+    assert_eq!(block_on(client.get_state()).unwrap(), 5);
+}
+
+#[test]
+fn test_builder_commit_listener() {
+    let (sender, events) = test_network();
+    let (listener, mut commits) = mpsc::unbounded();
+    let synchronizer = StateSynchronizerBuilder::new()
+        .with_network(sender, events)
+        .with_executor_proxy(test_executor_proxy())
+        .with_role(RoleType::Validator)
+        .with_commit_listener(listener)
+        .build()
+        .unwrap();
+    let client = synchronizer.create_client();
+    block_on(client.commit(5)).unwrap();
+    assert_eq!(block_on(commits.next()), Some(5));
+    // commits not moving the version forward aren't notified
+    block_on(client.commit(3)).unwrap();
+    block_on(client.commit(8)).unwrap();
+    assert_eq!(block_on(commits.next()), Some(8));
+}
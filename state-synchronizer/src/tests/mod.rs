@@ -1,5 +1,31 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
+mod coordinator_tests;
 mod integration_tests;
 mod unit_tests;
+
+use crate::clock::Clock;
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Clock that only moves when told to
+pub(crate) struct FakeClock(Mutex<SystemTime>);
+
+impl FakeClock {
+    pub(crate) fn new() -> Self {
+        Self(Mutex::new(UNIX_EPOCH + Duration::from_secs(1_000_000)))
+    }
+
+    pub(crate) fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
@@ -2,12 +2,30 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use crate::{
+    checkpoint::SyncCheckpoint,
+    counters,
+    executor_proxy::{is_transient_storage_error, CommittedDataPublisher},
     peer_manager::{PeerManager, PeerScoreUpdateType},
-    PeerId,
+    synchronizer::parse_upstream_peers,
+    tests::FakeClock,
+    CommittedTransactionData, PeerId,
 };
 use channel;
+use futures::{executor::block_on, StreamExt};
+use libra_tools::tempdir::TempPath;
+use libra_types::{
+    transaction::{Transaction, TransactionStatus},
+    vm_error::{StatusCode, VMStatus},
+    write_set::WriteSet,
+};
 use network::validator_network::StateSynchronizerSender;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    sync::Arc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[test]
 fn test_peer_manager() {
@@ -21,7 +39,7 @@ fn test_peer_manager() {
     let (network_reqs_tx, _) = channel::new_test(8);
     let sender = StateSynchronizerSender::new(network_reqs_tx);
     for peer_id in peers.clone() {
-        peer_manager.enable_peer(peer_id, sender.clone());
+        peer_manager.enable_peer(peer_id, 0, sender.clone());
     }
 
     for _ in 0..50 {
@@ -42,6 +60,62 @@ fn test_peer_manager() {
     assert!(pick_counts.get(&peers[0]).unwrap_or(&0) < pick_counts.get(&peers[3]).unwrap());
 }
 
+#[test]
+fn test_pick_peer_round_robin_among_equal_scores() {
+    const NUM_PICKS: u64 = 10_000;
+
+    let peers = vec![PeerId::random(), PeerId::random(), PeerId::random()];
+    let mut peer_manager = PeerManager::new(peers.clone());
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    for peer_id in peers.clone() {
+        peer_manager.enable_peer(peer_id, 0, sender.clone());
+    }
+
+    for _ in 0..NUM_PICKS {
+        peer_manager.pick_peer().unwrap();
+    }
+
+    let counts = peer_manager.selection_counts();
+    assert_eq!(counts.values().sum::<u64>(), NUM_PICKS);
+    let fair_share = NUM_PICKS / peers.len() as u64;
+    for peer_id in &peers {
+        let count = counts[peer_id];
+        assert!(
+            count > fair_share * 97 / 100 && count < fair_share * 103 / 100,
+            "peer {} picked {} times out of {}",
+            peer_id,
+            count,
+            NUM_PICKS
+        );
+    }
+}
+
+#[test]
+fn test_pick_peer_prefers_higher_priority_network() {
+    const NUM_PICKS: u64 = 1000;
+
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let mut peer_manager = PeerManager::new(peers.clone());
+    peer_manager.set_network_weights(vec![10.0, 1.0]);
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    // same score, one peer on each network
+    peer_manager.enable_peer(peers[0], 0, sender.clone());
+    peer_manager.enable_peer(peers[1], 1, sender.clone());
+
+    for _ in 0..NUM_PICKS {
+        peer_manager.pick_peer().unwrap();
+    }
+
+    let counts = peer_manager.selection_counts();
+    let preferred = counts.get(&peers[0]).cloned().unwrap_or(0);
+    let other = counts.get(&peers[1]).cloned().unwrap_or(0);
+    assert_eq!(preferred + other, NUM_PICKS);
+    // expected 10 to 1
+    assert!(preferred > 3 * other, "{} vs {}", preferred, other);
+}
+
 #[test]
 fn test_remove_requests() {
     let peers = vec![PeerId::random(), PeerId::random()];
@@ -61,3 +135,325 @@ fn test_remove_requests() {
     assert!(peer_manager.has_requested(10, peers[0]));
     assert!(peer_manager.has_requested(12, peers[1]));
 }
+
+#[test]
+fn test_remove_expired_requests() {
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let mut peer_manager = PeerManager::new(peers.clone());
+
+    peer_manager.process_request(1, peers[0]);
+    peer_manager.process_request(2, peers[1]);
+    let expiry = SystemTime::now() + Duration::from_secs(1);
+    peer_manager.remove_expired_requests(UNIX_EPOCH);
+    assert_eq!(peer_manager.num_requests(), 2);
+    peer_manager.remove_expired_requests(expiry);
+    assert_eq!(peer_manager.num_requests(), 0);
+}
+
+#[test]
+fn test_disable_peer_removes_requests() {
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let mut peer_manager = PeerManager::new(peers.clone());
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    for peer_id in peers.clone() {
+        peer_manager.enable_peer(peer_id, 0, sender.clone());
+    }
+
+    peer_manager.process_request(1, peers[0]);
+    peer_manager.process_request(2, peers[1]);
+    peer_manager.process_request(3, peers[0]);
+    peer_manager.disable_peer(&peers[0]);
+
+    assert!(!peer_manager.has_requested(1, peers[0]));
+    assert!(peer_manager.has_requested(2, peers[1]));
+    assert!(!peer_manager.has_requested(3, peers[0]));
+    assert_eq!(peer_manager.num_requests(), 1);
+}
+
+#[test]
+fn test_requests_bounded_under_peer_churn() {
+    const NUM_ALIVE_PEERS: usize = 4;
+    const REQUESTS_PER_ROUND: u64 = 3;
+
+    let mut peer_manager = PeerManager::new(vec![]);
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    let mut alive_peers = VecDeque::new();
+    let mut version = 0;
+
+    for _ in 0..1000 {
+        let new_peer = PeerId::random();
+        peer_manager.enable_peer(new_peer, 0, sender.clone());
+        alive_peers.push_back(new_peer);
+        if alive_peers.len() > NUM_ALIVE_PEERS {
+            let old_peer = alive_peers.pop_front().unwrap();
+            peer_manager.disable_peer(&old_peer);
+        }
+
+        // requests that are never answered nor committed, e.g. because the sync got superseded
+        for peer_id in alive_peers.iter().take(REQUESTS_PER_ROUND as usize) {
+            version += 1;
+            peer_manager.process_request(version, *peer_id);
+        }
+
+        assert!(peer_manager.num_requests() <= NUM_ALIVE_PEERS * REQUESTS_PER_ROUND as usize);
+    }
+}
+
+#[test]
+fn test_evict_slowest_peer() {
+    let peers = vec![PeerId::random(), PeerId::random(), PeerId::random()];
+    let clock = Arc::new(FakeClock::new());
+    let mut peer_manager = PeerManager::new_with_clock(peers.clone(), clock.clone());
+    let (network_reqs_tx, _) = channel::new_test(8);
+    let sender = StateSynchronizerSender::new(network_reqs_tx);
+    for peer_id in peers.clone() {
+        peer_manager.enable_peer(peer_id, 0, sender.clone());
+    }
+
+    // peers[1] answers after 5s, peers[0] after 1s and peers[2] never answers
+    peer_manager.process_request(1, peers[1]);
+    peer_manager.process_request(2, peers[0]);
+    clock.advance(Duration::from_secs(1));
+    peer_manager.process_response(2, peers[0]);
+    clock.advance(Duration::from_secs(4));
+    peer_manager.process_response(1, peers[1]);
+
+    let cooldown = Duration::from_secs(60);
+    assert_eq!(peer_manager.evict_slowest_peer(cooldown), Some(peers[1]));
+    assert!(peer_manager.is_evicted(&peers[1]));
+    for _ in 0..100 {
+        let (picked_peer_id, _) = peer_manager.pick_peer().unwrap();
+        assert_ne!(picked_peer_id, peers[1]);
+    }
+
+    // peers without any latency sample are never picked as the slowest
+    assert_eq!(peer_manager.evict_slowest_peer(cooldown), Some(peers[0]));
+    // the last active peer is kept
+    assert_eq!(peer_manager.evict_slowest_peer(cooldown), None);
+    assert!(!peer_manager.is_evicted(&peers[2]));
+
+    clock.advance(cooldown - Duration::from_secs(1));
+    peer_manager.readmit_evicted_peers();
+    assert!(peer_manager.is_evicted(&peers[0]));
+    clock.advance(Duration::from_secs(1));
+    peer_manager.readmit_evicted_peers();
+    assert!(!peer_manager.is_evicted(&peers[0]));
+    assert!(!peer_manager.is_evicted(&peers[1]));
+}
+
+#[test]
+fn test_timeout_grace_period() {
+    let peers = vec![PeerId::random(), PeerId::random()];
+    let clock = Arc::new(FakeClock::new());
+    let mut peer_manager = PeerManager::new_with_clock(peers.clone(), clock.clone());
+    let grace_period = Duration::from_secs(10);
+    peer_manager.set_timeout_grace_period(Some(grace_period));
+    let initial_score = peer_manager.score(&peers[0]).unwrap();
+    let mut version = 0;
+    let mut time_out = |peer_manager: &mut PeerManager, peer_id: PeerId| {
+        version += 1;
+        peer_manager.process_request(version, peer_id);
+        clock.advance(Duration::from_secs(1));
+        peer_manager.process_timeout(version, true);
+    };
+
+    // timeouts further apart than the grace period are forgiven
+    time_out(&mut peer_manager, peers[0]);
+    clock.advance(grace_period);
+    time_out(&mut peer_manager, peers[0]);
+    assert_eq!(peer_manager.score(&peers[0]), Some(initial_score));
+
+    // every timeout following another within the grace period is penalized
+    for _ in 0..3 {
+        time_out(&mut peer_manager, peers[1]);
+    }
+    assert_eq!(
+        peer_manager.score(&peers[1]),
+        Some(initial_score * 0.95 * 0.95)
+    );
+    assert_eq!(peer_manager.score(&peers[0]), Some(initial_score));
+}
+
+#[test]
+fn test_ledger_info_summary_rate_limit() {
+    let peer_id = PeerId::random();
+    let clock = Arc::new(FakeClock::new());
+    let mut peer_manager = PeerManager::new_with_clock(vec![peer_id], clock.clone());
+    let min_interval = Duration::from_secs(1);
+
+    // summaries of unknown peers are dropped
+    assert!(!peer_manager.process_ledger_info_summary(&PeerId::random(), 10, min_interval));
+
+    assert!(peer_manager.process_ledger_info_summary(&peer_id, 10, min_interval));
+    clock.advance(min_interval - Duration::from_millis(1));
+    assert!(!peer_manager.process_ledger_info_summary(&peer_id, 20, min_interval));
+    assert_eq!(peer_manager.advertised_version(&peer_id), Some(10));
+    clock.advance(Duration::from_millis(1));
+    assert!(peer_manager.process_ledger_info_summary(&peer_id, 20, min_interval));
+    assert_eq!(peer_manager.advertised_version(&peer_id), Some(20));
+}
+
+fn committed_data(versions: std::ops::Range<u64>) -> Vec<CommittedTransactionData> {
+    versions
+        .map(|version| CommittedTransactionData {
+            version,
+            txn: Transaction::WriteSet(WriteSet::default()),
+            status: TransactionStatus::Keep(VMStatus::new(StatusCode::EXECUTED)),
+            events: vec![],
+            write_set: WriteSet::default(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_committed_data_dropped_on_lag() {
+    let publisher = CommittedDataPublisher::new(2, false /* block_on_lag */);
+    let mut receiver = publisher.subscribe();
+    let dropped_before = counters::COMMITTED_DATA_DROPPED.get();
+
+    // the channel holds the buffer plus one slot of the publisher, the rest is dropped
+    block_on(publisher.publish(committed_data(0..5)));
+    assert_eq!(counters::COMMITTED_DATA_DROPPED.get() - dropped_before, 2);
+    let received: Vec<_> = (0..3)
+        .map(|_| block_on(receiver.next()).unwrap().version)
+        .collect();
+    assert_eq!(received, vec![0, 1, 2]);
+
+    // once caught up, the subscriber gets new data again
+    block_on(publisher.publish(committed_data(5..6)));
+    assert_eq!(block_on(receiver.next()).unwrap().version, 5);
+
+    // subscribers that went away are skipped
+    drop(receiver);
+    block_on(publisher.publish(committed_data(6..10)));
+    assert_eq!(counters::COMMITTED_DATA_DROPPED.get() - dropped_before, 2);
+}
+
+#[test]
+fn test_committed_data_blocks_on_lag() {
+    let publisher = CommittedDataPublisher::new(1, true /* block_on_lag */);
+    let mut receiver = publisher.subscribe();
+    let data = committed_data(0..10);
+
+    // publishing only completes as the subscriber consumes the data
+    let publish = publisher.publish(data.clone());
+    let handle = thread::spawn(move || block_on(publish));
+    let received: Vec<_> = data
+        .iter()
+        .map(|_| block_on(receiver.next()).unwrap())
+        .collect();
+    handle.join().unwrap();
+    assert_eq!(received, data);
+}
+
+#[test]
+fn test_transient_storage_errors() {
+    let rpc_failure = |status| -> failure::Error {
+        grpcio::Error::RpcFailure(grpcio::RpcStatus::new(status, None)).into()
+    };
+    assert!(is_transient_storage_error(&rpc_failure(
+        grpcio::RpcStatusCode::UNAVAILABLE
+    )));
+    assert!(is_transient_storage_error(&rpc_failure(
+        grpcio::RpcStatusCode::DEADLINE_EXCEEDED
+    )));
+    // the storage service failed to serve the read, e.g. because the epoch is missing
+    assert!(!is_transient_storage_error(&rpc_failure(
+        grpcio::RpcStatusCode::UNKNOWN
+    )));
+    assert!(!is_transient_storage_error(&failure::err_msg(
+        "Corrupted DB"
+    )));
+}
+
+#[test]
+fn test_parse_upstream_peers() {
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let config = format!(
+        "[state_sync]\nupstream_peers = [\"{}\", \"{}\"]\n",
+        peer_a, peer_b
+    );
+    assert_eq!(parse_upstream_peers(&config).unwrap(), vec![peer_a, peer_b]);
+    assert!(parse_upstream_peers("").unwrap().is_empty());
+
+    // invalid configs are rejected as a whole
+    for invalid in &[
+        "[state_sync]\nupstream_peers = \"not a list\"\n".to_string(),
+        "[state_sync]\nupstream_peers = [\"\"]\n".to_string(),
+        format!("[state_sync]\nupstream_peers = [\"{}\", \"xyz\"]\n", peer_a),
+        format!(
+            "[state_sync]\nupstream_peers = [\"{}\", \"{}\"]\n",
+            peer_a, peer_a
+        ),
+    ] {
+        assert!(parse_upstream_peers(invalid).is_err());
+    }
+}
+
+#[test]
+fn test_sync_checkpoint_round_trip() {
+    let dir = TempPath::new();
+    dir.create_as_dir().unwrap();
+    let path = dir.path().join("checkpoint");
+    let (peer_a, peer_b) = (PeerId::random(), PeerId::random());
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+
+    let checkpoint = SyncCheckpoint::new(42, vec![(peer_a, 100), (peer_b, 200)], now);
+    checkpoint.write(&path).unwrap();
+    let read = SyncCheckpoint::read(&path).unwrap();
+    assert_eq!(read, checkpoint);
+    assert_eq!(read.max_advertised_version(), Some(200));
+    assert_eq!(
+        read.age(now + Duration::from_secs(60)),
+        Duration::from_secs(60)
+    );
+    assert_eq!(
+        read.age(now - Duration::from_secs(60)),
+        Duration::from_secs(0)
+    );
+
+    // a newer checkpoint replaces the previous one
+    let checkpoint = SyncCheckpoint::new(43, vec![], now);
+    checkpoint.write(&path).unwrap();
+    assert_eq!(SyncCheckpoint::read(&path).unwrap(), checkpoint);
+    assert_eq!(checkpoint.max_advertised_version(), None);
+
+    // missing or corrupt checkpoints are errors
+    assert!(SyncCheckpoint::read(&dir.path().join("missing")).is_err());
+    fs::write(&path, b"not a checkpoint").unwrap();
+    assert!(SyncCheckpoint::read(&path).is_err());
+}
+
+#[test]
+fn test_peer_manager_advertised_version_hint() {
+    let (upstream, downstream) = (PeerId::random(), PeerId::random());
+    let mut peer_manager = PeerManager::new(vec![upstream]);
+    let (network_reqs_tx, _) = channel::new_test(8);
+    peer_manager.enable_peer(downstream, 0, StateSynchronizerSender::new(network_reqs_tx));
+
+    peer_manager.seed_advertised_version(&upstream, 100);
+    assert_eq!(peer_manager.advertised_version(&upstream), Some(100));
+    assert_eq!(
+        peer_manager.upstream_advertised_versions(),
+        vec![(upstream, 100)]
+    );
+
+    // the hint is replaced by what the peer actually has, even if lower
+    peer_manager.record_advertised_version(&upstream, 50);
+    assert_eq!(peer_manager.advertised_version(&upstream), Some(50));
+    // afterwards the advertised version only moves forward
+    peer_manager.record_advertised_version(&upstream, 40);
+    assert_eq!(peer_manager.advertised_version(&upstream), Some(50));
+    // and is not overridden by hints anymore
+    peer_manager.seed_advertised_version(&upstream, 100);
+    assert_eq!(peer_manager.advertised_version(&upstream), Some(50));
+
+    // only upstream peers are checkpointed
+    peer_manager.record_advertised_version(&downstream, 70);
+    assert_eq!(
+        peer_manager.upstream_advertised_versions(),
+        vec![(upstream, 50)]
+    );
+}
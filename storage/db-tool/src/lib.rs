@@ -0,0 +1,10 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Offline tools operating on the [`LibraDB`](libradb::LibraDB) of a node.
+
+#[macro_use]
+extern crate prometheus;
+
+pub mod replay;
+pub mod verify;
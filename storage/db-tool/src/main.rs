@@ -0,0 +1,186 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use failure::prelude::*;
+use jellyfish_merkle::node_type::NodeKey;
+use libra_config::config::{NodeConfig, VMConfig};
+use libra_db_tool::{
+    replay::{replay_range, replay_range_parallel},
+    verify::{follow, FollowOptions, LedgerVerifier},
+};
+use libra_metrics::metric_server;
+use libra_types::transaction::Version;
+use libradb::LibraDB;
+use std::{
+    path::PathBuf,
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+    time::Duration,
+};
+use structopt::StructOpt;
+use vm_runtime::MoveVM;
+
+#[derive(Debug, StructOpt)]
+#[structopt(about = "Offline tools operating on the DB of a Libra node")]
+enum Command {
+    /// Re-executes a range of committed transactions and compares the results against the stored
+    /// transaction infos, reporting the first divergence.
+    Replay {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, i.e. `storage.dir` in its config.
+        db: PathBuf,
+        #[structopt(long)]
+        /// First version to replay.
+        start: Version,
+        #[structopt(long)]
+        /// Last version to replay, inclusive.
+        end: Version,
+        #[structopt(long, parse(from_os_str))]
+        /// Node config to take the VM config from. The default VM config is used if not given.
+        config: Option<PathBuf>,
+        #[structopt(long, default_value = "1")]
+        /// Number of sub-ranges to replay concurrently, each anchored at the state right before it.
+        parallel: u64,
+    },
+    /// Shows a state Merkle tree node and the version since which it is stale, if it is.
+    InspectNode {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, i.e. `storage.dir` in its config.
+        db: PathBuf,
+        #[structopt(long, parse(try_from_str = parse_node_key))]
+        /// Key of the node as stored in the DB, in hex.
+        node_key: NodeKey,
+    },
+    /// Populates the index of stale state Merkle tree nodes by node key, for DBs created before it
+    /// existed. The node must not be running.
+    BackfillRetirementIndex {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, i.e. `storage.dir` in its config.
+        db: PathBuf,
+    },
+    /// Checks every state Merkle tree node against the checksum stored with it to detect disk
+    /// corruption, failing on the first corrupted node.
+    VerifyNodeChecksums {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, i.e. `storage.dir` in its config.
+        db: PathBuf,
+    },
+    /// Verifies the ledger from genesis: the signatures of every ledger info against the validator
+    /// set of its epoch, the transaction accumulator and sampled account state proofs. Fails on the
+    /// first ledger info that doesn't verify.
+    Verify {
+        #[structopt(long, parse(from_os_str))]
+        /// Storage directory of the node, i.e. `storage.dir` in its config.
+        db: PathBuf,
+        #[structopt(long)]
+        /// Keeps verifying new commits as the node makes them instead of exiting once caught up.
+        follow: bool,
+        #[structopt(long, requires = "follow")]
+        /// Logs verification failures and keeps following from the latest ledger info instead of
+        /// exiting.
+        keep_going: bool,
+        #[structopt(long, default_value = "1000")]
+        /// Milliseconds to wait between two polls of the DB when following.
+        poll_interval_ms: u64,
+        #[structopt(long, default_value = "8")]
+        /// Maximum number of accounts whose state is proven for each ledger info.
+        sampled_accounts: usize,
+        #[structopt(long)]
+        /// Port to serve the verified version and failure count on for Prometheus to scrape.
+        metrics_port: Option<u16>,
+    },
+}
+
+fn parse_node_key(s: &str) -> Result<NodeKey> {
+    NodeKey::decode(&hex::decode(s)?)
+}
+
+fn main() -> Result<()> {
+    match Command::from_args() {
+        Command::Replay {
+            db,
+            start,
+            end,
+            config,
+            parallel,
+        } => {
+            let vm_config = match config {
+                Some(path) => NodeConfig::load(path)?.vm_config,
+                None => VMConfig::default(),
+            };
+            let db = Arc::new(LibraDB::open_readonly(db)?);
+            let report = if parallel > 1 {
+                replay_range_parallel::<MoveVM>(db, &vm_config, start, end, parallel)?
+            } else {
+                replay_range::<MoveVM>(&db, &vm_config, start, end)?
+            };
+            println!("{}", report);
+            if report.divergence.is_some() {
+                std::process::exit(1);
+            }
+        }
+        Command::InspectNode { db, node_key } => {
+            let db = LibraDB::open_readonly(db)?;
+            match db.get_state_node_option(&node_key)? {
+                Some(node) => println!("{:?}", node),
+                None => println!("Node {:?} does not exist.", node_key),
+            }
+            match db.get_retirement_info(&node_key)? {
+                Some(stale_since_version) => {
+                    println!("Stale since version {}.", stale_since_version)
+                }
+                None => println!("Not known to be stale."),
+            }
+        }
+        Command::BackfillRetirementIndex { db } => {
+            let num_written = LibraDB::new(db).backfill_retirement_index()?;
+            println!("Backfilled {} entries.", num_written);
+        }
+        Command::VerifyNodeChecksums { db } => {
+            let (num_verified, num_without_checksum) =
+                LibraDB::open_readonly(db)?.verify_state_node_checksums()?;
+            println!(
+                "Verified {} nodes, {} nodes without checksum.",
+                num_verified, num_without_checksum
+            );
+        }
+        Command::Verify {
+            db,
+            follow: false,
+            sampled_accounts,
+            ..
+        } => {
+            let db = LibraDB::open_readonly(db)?;
+            let mut verifier = LedgerVerifier::new(&db, sampled_accounts)?;
+            // Each call verifies a bounded number of epochs, so repeat until caught up.
+            loop {
+                let verified_version = verifier.verified_version();
+                if verifier.verify_new_commits(&db)? == verified_version {
+                    break;
+                }
+            }
+            println!("Verified up to version {}.", verifier.verified_version());
+        }
+        Command::Verify {
+            db,
+            follow: true,
+            keep_going,
+            poll_interval_ms,
+            sampled_accounts,
+            metrics_port,
+        } => {
+            if let Some(port) = metrics_port {
+                thread::spawn(move || {
+                    metric_server::start_server("0.0.0.0".to_string(), port, false)
+                });
+            }
+            let options = FollowOptions {
+                poll_interval: Duration::from_millis(poll_interval_ms),
+                keep_going,
+                num_sampled_accounts: sampled_accounts,
+            };
+            follow(&db, &options, &AtomicBool::new(false))?;
+        }
+    }
+    Ok(())
+}
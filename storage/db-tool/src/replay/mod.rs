@@ -0,0 +1,481 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module re-executes a range of committed transactions read from a [`LibraDB`] and compares
+//! the results against the stored `TransactionInfo`s, in order to hunt down nondeterminism or VM
+//! regressions offline.
+//!
+//! Replaying `[start_version, end_version]` executes on top of the state as of
+//! `start_version - 1`, which is lazily loaded from the DB with every account proof verified
+//! against the stored state root. Everything the replay writes stays in memory. The replay stops at
+//! the first transaction whose state root, event root, gas used or status differs from the stored
+//! one and reports it together with a diff of its write set against the stored state.
+
+#[cfg(test)]
+mod replay_test;
+
+use failure::prelude::*;
+use libra_config::config::VMConfig;
+use libra_crypto::{
+    hash::{CryptoHash, EventAccumulatorHasher, SPARSE_MERKLE_PLACEHOLDER_HASH},
+    HashValue,
+};
+use libra_state_view::StateView;
+use libra_types::{
+    access_path::AccessPath,
+    account_address::AccountAddress,
+    account_state_blob::AccountStateBlob,
+    proof::{accumulator::InMemoryAccumulator, SparseMerkleProof},
+    transaction::{TransactionInfo, TransactionOutput, TransactionStatus, Version},
+    vm_error::StatusCode,
+    write_set::{WriteOp, WriteSet, WriteSetDiff, WriteSetMut},
+};
+use libradb::LibraDB;
+use scratchpad::{ProofRead, SparseMerkleTree};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
+    convert::TryFrom,
+    fmt,
+    sync::Arc,
+    thread,
+};
+use vm_runtime::VMExecutor;
+
+/// The maximum number of transactions fetched from the DB and executed as a single block.
+const MAX_BLOCK_SIZE: u64 = 1000;
+
+/// The outcome of replaying a version range.
+#[derive(Debug)]
+pub struct ReplayReport {
+    pub start_version: Version,
+    pub end_version: Version,
+    /// Number of transactions re-executed, including the divergent one if any.
+    pub num_replayed: u64,
+    /// The first divergence found, or `None` if the whole range replayed identically.
+    pub divergence: Option<Divergence>,
+}
+
+impl fmt::Display for ReplayReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Replayed {} transaction(s) in [{}, {}]",
+            self.num_replayed, self.start_version, self.end_version
+        )?;
+        match &self.divergence {
+            Some(divergence) => write!(f, ", {}", divergence),
+            None => write!(f, ", no divergence found."),
+        }
+    }
+}
+
+/// A transaction whose replayed result does not match the stored `TransactionInfo`.
+#[derive(Debug)]
+pub struct Divergence {
+    pub version: Version,
+    pub mismatches: Vec<Mismatch>,
+    /// The replayed write set diffed against the stored state at `version`: the left side is the
+    /// stored value of each replayed access path (`Deletion` if it does not exist), the right side
+    /// is the replayed write.
+    pub write_set_diff: WriteSetDiff,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "diverged at version {}:", self.version)?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        if !self.write_set_diff.is_empty() {
+            writeln!(f, "  write set diff (stored -> replayed):")?;
+            for entry in self.write_set_diff.iter() {
+                writeln!(f, "    {}", entry)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single field of `TransactionInfo` that differs between the stored and the replayed result.
+#[derive(Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    Status {
+        stored: StatusCode,
+        replayed: TransactionStatus,
+    },
+    StateRoot {
+        stored: HashValue,
+        replayed: HashValue,
+    },
+    EventRoot {
+        stored: HashValue,
+        replayed: HashValue,
+    },
+    GasUsed {
+        stored: u64,
+        replayed: u64,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::Status { stored, replayed } => {
+                write!(f, "status: stored {:?}, replayed {:?}", stored, replayed)
+            }
+            Mismatch::StateRoot { stored, replayed } => write!(
+                f,
+                "state root hash: stored {:x}, replayed {:x}",
+                stored, replayed
+            ),
+            Mismatch::EventRoot { stored, replayed } => write!(
+                f,
+                "event root hash: stored {:x}, replayed {:x}",
+                stored, replayed
+            ),
+            Mismatch::GasUsed { stored, replayed } => {
+                write!(f, "gas used: stored {}, replayed {}", stored, replayed)
+            }
+        }
+    }
+}
+
+/// Re-executes the committed transactions in `[start_version, end_version]` with VM `V` and
+/// compares each result against the stored `TransactionInfo`, stopping at the first divergence.
+pub fn replay_range<V: VMExecutor>(
+    db: &LibraDB,
+    vm_config: &VMConfig,
+    start_version: Version,
+    end_version: Version,
+) -> Result<ReplayReport> {
+    ensure!(
+        start_version <= end_version,
+        "Start version {} is greater than end version {}.",
+        start_version,
+        end_version
+    );
+    let latest_version = db
+        .get_latest_transaction_info_option()?
+        .map(|(version, _)| version)
+        .ok_or_else(|| format_err!("DB is empty."))?;
+    ensure!(
+        end_version <= latest_version,
+        "End version {} is greater than the latest version {} in DB.",
+        end_version,
+        latest_version
+    );
+
+    let mut state = ReplayState::new(db, start_version.checked_sub(1))?;
+    let mut version = start_version;
+    while version <= end_version {
+        // The VM treats a whole block as genesis or not, so genesis has to be executed alone.
+        let limit = if state.is_genesis() {
+            1
+        } else {
+            std::cmp::min(MAX_BLOCK_SIZE, end_version - version + 1)
+        };
+        let txn_list = db.get_transactions(version, limit, latest_version, false)?;
+        let txn_infos = txn_list.proof.transaction_infos().to_vec();
+        let outputs =
+            V::execute_block(txn_list.transactions, vm_config, &state).map_err(|status| {
+                format_err!(
+                    "Failed to execute block starting at version {}: {:?}",
+                    version,
+                    status
+                )
+            })?;
+        ensure!(
+            outputs.len() == txn_infos.len(),
+            "VM returned {} outputs for {} transactions.",
+            outputs.len(),
+            txn_infos.len()
+        );
+
+        for (output, txn_info) in outputs.iter().zip(txn_infos.iter()) {
+            let mismatches = state.replay_output(output, txn_info)?;
+            if !mismatches.is_empty() {
+                let write_set_diff = state.diff_write_set(output.write_set(), version)?;
+                return Ok(ReplayReport {
+                    start_version,
+                    end_version,
+                    num_replayed: state.num_replayed,
+                    divergence: Some(Divergence {
+                        version,
+                        mismatches,
+                        write_set_diff,
+                    }),
+                });
+            }
+            version += 1;
+        }
+    }
+
+    Ok(ReplayReport {
+        start_version,
+        end_version,
+        num_replayed: state.num_replayed,
+        divergence: None,
+    })
+}
+
+/// Like [`replay_range`], but splits `[start_version, end_version]` into `num_ranges` consecutive
+/// sub-ranges and replays them concurrently, each anchored at the stored state right before it.
+/// The earliest divergence across all sub-ranges is reported.
+pub fn replay_range_parallel<V: VMExecutor + 'static>(
+    db: Arc<LibraDB>,
+    vm_config: &VMConfig,
+    start_version: Version,
+    end_version: Version,
+    num_ranges: u64,
+) -> Result<ReplayReport> {
+    ensure!(num_ranges > 0, "Number of ranges should be positive.");
+    ensure!(
+        start_version <= end_version,
+        "Start version {} is greater than end version {}.",
+        start_version,
+        end_version
+    );
+
+    let range_size = (end_version - start_version) / num_ranges + 1;
+    let handles: Vec<_> = (start_version..=end_version)
+        .step_by(range_size as usize)
+        .map(|range_start| {
+            let range_end = std::cmp::min(range_start + range_size - 1, end_version);
+            let db = Arc::clone(&db);
+            let vm_config = vm_config.clone();
+            thread::spawn(move || replay_range::<V>(&db, &vm_config, range_start, range_end))
+        })
+        .collect();
+
+    let mut num_replayed = 0;
+    let mut divergence = None;
+    // Sub-ranges are joined in order, so the first divergence seen is the earliest one.
+    for handle in handles {
+        let report = handle
+            .join()
+            .map_err(|_| format_err!("Replay thread panicked."))??;
+        num_replayed += report.num_replayed;
+        if divergence.is_none() {
+            divergence = report.divergence;
+        }
+    }
+
+    Ok(ReplayReport {
+        start_version,
+        end_version,
+        num_replayed,
+        divergence,
+    })
+}
+
+/// The state transactions are replayed against: the DB state as of `base_version`, loaded lazily,
+/// plus everything replayed on top of it so far.
+struct ReplayState<'a> {
+    db: &'a LibraDB,
+    /// The version the replay starts after, `None` if replaying from genesis.
+    base_version: Option<Version>,
+    base_state_root: HashValue,
+    /// The latest content of every account touched so far, either by the VM reading it or by a
+    /// replayed write set.
+    account_to_btree: RefCell<HashMap<AccountAddress, BTreeMap<Vec<u8>, Vec<u8>>>>,
+    /// Proofs of the accounts above against `base_state_root`, needed to update `state_tree`.
+    account_to_proof: RefCell<HashMap<HashValue, SparseMerkleProof>>,
+    state_tree: SparseMerkleTree,
+    num_replayed: u64,
+}
+
+impl<'a> ReplayState<'a> {
+    fn new(db: &'a LibraDB, base_version: Option<Version>) -> Result<Self> {
+        let base_state_root = match base_version {
+            Some(version) => db
+                .get_transactions(version, 1, version, false)?
+                .proof
+                .transaction_infos()
+                .first()
+                .ok_or_else(|| format_err!("Transaction info at version {} is missing.", version))?
+                .state_root_hash(),
+            None => *SPARSE_MERKLE_PLACEHOLDER_HASH,
+        };
+
+        Ok(Self {
+            db,
+            base_version,
+            base_state_root,
+            account_to_btree: RefCell::new(HashMap::new()),
+            account_to_proof: RefCell::new(HashMap::new()),
+            state_tree: SparseMerkleTree::new(base_state_root),
+            num_replayed: 0,
+        })
+    }
+
+    /// Makes sure `address` is in `account_to_btree`, loading it from the DB at `base_version` if
+    /// the replay has not touched it before.
+    fn load_account(&self, address: AccountAddress) -> Result<()> {
+        if let Entry::Vacant(vacant) = self.account_to_btree.borrow_mut().entry(address) {
+            let (blob, proof) = match self.base_version {
+                Some(version) => self
+                    .db
+                    .get_account_state_with_proof_by_version(address, version)?,
+                None => (None, SparseMerkleProof::new(None, vec![])),
+            };
+            proof
+                .verify(self.base_state_root, address.hash(), blob.as_ref())
+                .map_err(|err| {
+                    format_err!(
+                        "Proof is invalid for address {:?} with state root hash {:?}: {}",
+                        address,
+                        self.base_state_root,
+                        err
+                    )
+                })?;
+            self.account_to_proof
+                .borrow_mut()
+                .insert(address.hash(), proof);
+            vacant.insert(
+                blob.as_ref()
+                    .map(BTreeMap::try_from)
+                    .transpose()?
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Applies the result of a replayed transaction and compares it against `expected`.
+    fn replay_output(
+        &mut self,
+        output: &TransactionOutput,
+        expected: &TransactionInfo,
+    ) -> Result<Vec<Mismatch>> {
+        if let TransactionStatus::Keep(_) = output.status() {
+            self.apply_write_set(output.write_set())?;
+        }
+        self.num_replayed += 1;
+
+        let mut mismatches = vec![];
+        match output.status() {
+            TransactionStatus::Keep(status) if status.major_status == expected.major_status() => (),
+            status => mismatches.push(Mismatch::Status {
+                stored: expected.major_status(),
+                replayed: status.clone(),
+            }),
+        }
+        let state_root = self.state_tree.root_hash();
+        if state_root != expected.state_root_hash() {
+            mismatches.push(Mismatch::StateRoot {
+                stored: expected.state_root_hash(),
+                replayed: state_root,
+            });
+        }
+        let event_hashes: Vec<_> = output.events().iter().map(CryptoHash::hash).collect();
+        let event_root =
+            InMemoryAccumulator::<EventAccumulatorHasher>::from_leaves(&event_hashes).root_hash();
+        if event_root != expected.event_root_hash() {
+            mismatches.push(Mismatch::EventRoot {
+                stored: expected.event_root_hash(),
+                replayed: event_root,
+            });
+        }
+        if output.gas_used() != expected.gas_used() {
+            mismatches.push(Mismatch::GasUsed {
+                stored: expected.gas_used(),
+                replayed: output.gas_used(),
+            });
+        }
+        Ok(mismatches)
+    }
+
+    /// Applies `write_set` to the touched accounts and updates `state_tree` accordingly.
+    fn apply_write_set(&mut self, write_set: &WriteSet) -> Result<()> {
+        let mut addrs = HashSet::new();
+        for (access_path, write_op) in write_set {
+            let address = access_path.address;
+            self.load_account(address)?;
+            let mut account_to_btree = self.account_to_btree.borrow_mut();
+            let account_btree = account_to_btree
+                .get_mut(&address)
+                .expect("Account should have been loaded.");
+            match write_op {
+                WriteOp::Value(value) => {
+                    account_btree.insert(access_path.path.clone(), value.clone())
+                }
+                WriteOp::Deletion => account_btree.remove(&access_path.path),
+            };
+            addrs.insert(address);
+        }
+
+        let updates = {
+            let account_to_btree = self.account_to_btree.borrow();
+            addrs
+                .into_iter()
+                .map(|addr| {
+                    Ok((
+                        addr.hash(),
+                        AccountStateBlob::try_from(&account_to_btree[&addr])?,
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?
+        };
+        let state_tree = self
+            .state_tree
+            .update(updates, &ProofReader(&self.account_to_proof.borrow()))
+            .map_err(|err| format_err!("Failed to update state tree: {:?}", err))?;
+        self.state_tree = state_tree;
+        Ok(())
+    }
+
+    /// Compares every entry of `write_set` against the state stored in the DB at `version`.
+    fn diff_write_set(&self, write_set: &WriteSet, version: Version) -> Result<WriteSetDiff> {
+        let mut stored_accounts: HashMap<AccountAddress, BTreeMap<Vec<u8>, Vec<u8>>> =
+            HashMap::new();
+        let mut stored_writes = vec![];
+        for (access_path, _write_op) in write_set {
+            let stored_account = match stored_accounts.entry(access_path.address) {
+                Entry::Occupied(occupied) => occupied.into_mut(),
+                Entry::Vacant(vacant) => {
+                    let (blob, _proof) = self
+                        .db
+                        .get_account_state_with_proof_by_version(access_path.address, version)?;
+                    vacant.insert(
+                        blob.as_ref()
+                            .map(BTreeMap::try_from)
+                            .transpose()?
+                            .unwrap_or_default(),
+                    )
+                }
+            };
+            let stored = match stored_account.get(&access_path.path) {
+                Some(value) => WriteOp::Value(value.clone()),
+                None => WriteOp::Deletion,
+            };
+            stored_writes.push((access_path.clone(), stored));
+        }
+        Ok(WriteSetMut::new(stored_writes).freeze()?.diff(write_set))
+    }
+}
+
+impl<'a> StateView for ReplayState<'a> {
+    fn get(&self, access_path: &AccessPath) -> Result<Option<Vec<u8>>> {
+        self.load_account(access_path.address)?;
+        Ok(self.account_to_btree.borrow()[&access_path.address]
+            .get(&access_path.path)
+            .cloned())
+    }
+
+    fn multi_get(&self, _access_paths: &[AccessPath]) -> Result<Vec<Option<Vec<u8>>>> {
+        unimplemented!();
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.base_version.is_none() && self.num_replayed == 0
+    }
+}
+
+struct ProofReader<'a>(&'a HashMap<HashValue, SparseMerkleProof>);
+
+impl<'a> ProofRead for ProofReader<'a> {
+    fn get_proof(&self, key: HashValue) -> Option<&SparseMerkleProof> {
+        self.0.get(&key)
+    }
+}
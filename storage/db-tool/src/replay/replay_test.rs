@@ -0,0 +1,186 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use libra_config::config::VMPublishingOption;
+use libra_tools::tempdir::TempPath;
+use libra_types::{
+    account_config::association_address,
+    test_helpers::transaction_test_helpers::get_test_signed_txn,
+    transaction::{Transaction, TransactionToCommit},
+};
+use transaction_builder::encode_mint_script;
+use vm_genesis::{encode_genesis_transaction, GENESIS_KEYPAIR};
+use vm_runtime::MoveVM;
+
+const NUM_MINT_TXNS: u64 = 10;
+
+/// A plain in-memory state the test chain is generated against.
+#[derive(Default)]
+struct ChainState {
+    accounts: HashMap<AccountAddress, BTreeMap<Vec<u8>, Vec<u8>>>,
+    is_genesis: bool,
+}
+
+impl StateView for ChainState {
+    fn get(&self, access_path: &AccessPath) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .accounts
+            .get(&access_path.address)
+            .and_then(|account| account.get(&access_path.path).cloned()))
+    }
+
+    fn multi_get(&self, _access_paths: &[AccessPath]) -> Result<Vec<Option<Vec<u8>>>> {
+        unimplemented!();
+    }
+
+    fn is_genesis(&self) -> bool {
+        self.is_genesis
+    }
+}
+
+fn vm_config() -> VMConfig {
+    VMConfig {
+        publishing_options: VMPublishingOption::Open,
+    }
+}
+
+/// Executes `txns` as one block on top of `state` and turns the outputs into what the executor
+/// would have committed.
+fn execute_and_apply(state: &mut ChainState, txns: Vec<Transaction>) -> Vec<TransactionToCommit> {
+    let outputs = MoveVM::execute_block(txns.clone(), &vm_config(), state).unwrap();
+    txns.into_iter()
+        .zip(outputs.into_iter())
+        .map(|(txn, output)| {
+            let major_status = match output.status() {
+                TransactionStatus::Keep(status) => status.major_status,
+                TransactionStatus::Discard(status) => panic!("Discarded: {:?}", status),
+            };
+            let mut addrs = HashSet::new();
+            for (access_path, write_op) in output.write_set() {
+                let account = state.accounts.entry(access_path.address).or_default();
+                match write_op {
+                    WriteOp::Value(value) => {
+                        account.insert(access_path.path.clone(), value.clone())
+                    }
+                    WriteOp::Deletion => account.remove(&access_path.path),
+                };
+                addrs.insert(access_path.address);
+            }
+            let account_states = addrs
+                .into_iter()
+                .map(|addr| {
+                    (
+                        addr,
+                        AccountStateBlob::try_from(&state.accounts[&addr]).unwrap(),
+                    )
+                })
+                .collect();
+            TransactionToCommit::new(
+                txn,
+                account_states,
+                output.events().to_vec(),
+                output.gas_used(),
+                major_status,
+            )
+        })
+        .collect()
+}
+
+/// Generates a chain of a genesis transaction followed by `NUM_MINT_TXNS` mints and saves it to a
+/// new DB under `path`. If `corrupt_gas_at` is given, the gas used of that version is stored off
+/// by one.
+fn generate_chain(path: &TempPath, corrupt_gas_at: Option<Version>) {
+    let (private_key, public_key) = &*GENESIS_KEYPAIR;
+    let mut state = ChainState {
+        is_genesis: true,
+        ..ChainState::default()
+    };
+    let genesis_txn = Transaction::UserTransaction(
+        encode_genesis_transaction(private_key, public_key.clone()).into_inner(),
+    );
+    let mut txns_to_commit = execute_and_apply(&mut state, vec![genesis_txn]);
+
+    state.is_genesis = false;
+    let mint_txns = (1..=NUM_MINT_TXNS)
+        .map(|seq_num| {
+            Transaction::UserTransaction(get_test_signed_txn(
+                association_address(),
+                seq_num,
+                private_key.clone(),
+                public_key.clone(),
+                Some(encode_mint_script(&AccountAddress::random(), 1_000)),
+            ))
+        })
+        .collect();
+    txns_to_commit.extend(execute_and_apply(&mut state, mint_txns));
+
+    if let Some(version) = corrupt_gas_at {
+        let txn = &txns_to_commit[version as usize];
+        txns_to_commit[version as usize] = TransactionToCommit::new(
+            txn.transaction().clone(),
+            txn.account_states().clone(),
+            txn.events().to_vec(),
+            txn.gas_used() + 1,
+            txn.major_status(),
+        );
+    }
+
+    let db = LibraDB::new(path.path());
+    db.save_transactions(&txns_to_commit, 0 /* first_version */, &None)
+        .unwrap();
+}
+
+#[test]
+fn test_replay_generated_chain() {
+    let tmp_dir = TempPath::new();
+    generate_chain(&tmp_dir, None);
+    let db = LibraDB::open_readonly(tmp_dir.path()).unwrap();
+
+    let report = replay_range::<MoveVM>(&db, &vm_config(), 0, NUM_MINT_TXNS).unwrap();
+    assert!(report.divergence.is_none(), "{}", report);
+    assert_eq!(report.num_replayed, NUM_MINT_TXNS + 1);
+
+    // Anchored in the middle of the chain.
+    let report = replay_range::<MoveVM>(&db, &vm_config(), 4, NUM_MINT_TXNS).unwrap();
+    assert!(report.divergence.is_none(), "{}", report);
+    assert_eq!(report.num_replayed, NUM_MINT_TXNS - 3);
+
+    let report =
+        replay_range_parallel::<MoveVM>(Arc::new(db), &vm_config(), 0, NUM_MINT_TXNS, 3).unwrap();
+    assert!(report.divergence.is_none(), "{}", report);
+    assert_eq!(report.num_replayed, NUM_MINT_TXNS + 1);
+}
+
+#[test]
+fn test_replay_corrupted_transaction_info() {
+    let corrupted_version = 5;
+    let tmp_dir = TempPath::new();
+    generate_chain(&tmp_dir, Some(corrupted_version));
+    let db = LibraDB::open_readonly(tmp_dir.path()).unwrap();
+
+    let report = replay_range::<MoveVM>(&db, &vm_config(), 1, NUM_MINT_TXNS).unwrap();
+    let divergence = report.divergence.unwrap();
+    assert_eq!(divergence.version, corrupted_version);
+    assert_eq!(report.num_replayed, corrupted_version);
+    match divergence.mismatches.as_slice() {
+        [Mismatch::GasUsed { stored, replayed }] => assert_eq!(*stored, *replayed + 1),
+        mismatches => panic!("Unexpected mismatches: {:?}", mismatches),
+    }
+    // Only the stored gas is off, the state written is identical.
+    assert!(divergence.write_set_diff.is_empty());
+
+    let report =
+        replay_range_parallel::<MoveVM>(Arc::new(db), &vm_config(), 0, NUM_MINT_TXNS, 4).unwrap();
+    assert_eq!(report.divergence.unwrap().version, corrupted_version);
+}
+
+#[test]
+fn test_replay_invalid_range() {
+    let tmp_dir = TempPath::new();
+    generate_chain(&tmp_dir, None);
+    let db = LibraDB::open_readonly(tmp_dir.path()).unwrap();
+
+    assert!(replay_range::<MoveVM>(&db, &vm_config(), 3, 2).is_err());
+    assert!(replay_range::<MoveVM>(&db, &vm_config(), 0, NUM_MINT_TXNS + 1).is_err());
+}
@@ -0,0 +1,339 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module verifies the ledger committed to a [`LibraDB`] by a node, as an independent check
+//! that can keep following the DB while the node runs.
+//!
+//! Only the genesis ledger info is trusted. Every ledger info committed after it is checked to
+//!   1) carry a quorum of signatures from the validator set of its epoch, as tracked through the
+//!      epoch change ledger infos;
+//!   2) have a transaction accumulator root equal to the one verified so far extended with the
+//!      transaction infos committed up to it, which must themselves be proven against it;
+//!   3) prove the states of a few sampled accounts against its state root.
+//!
+//! A DB opened read-only only sees what was committed before it was opened, so [`follow`] reopens
+//! it on every poll to catch up with the node writing it.
+
+#[cfg(test)]
+mod verify_test;
+
+use failure::prelude::*;
+use lazy_static::lazy_static;
+use libra_crypto::{
+    hash::{CryptoHash, TransactionAccumulatorHasher},
+    HashValue,
+};
+use libra_logger::prelude::*;
+use libra_metrics::{IntCounter, IntGauge};
+use libra_types::{
+    account_address::AccountAddress,
+    crypto_proxies::{LedgerInfoWithSignatures, ValidatorVerifier},
+    ledger_info::LedgerInfo,
+    proof::accumulator::InMemoryAccumulator,
+    transaction::{Transaction, Version},
+};
+use libradb::LibraDB;
+use std::{
+    collections::BTreeSet,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+/// The maximum number of transactions fetched from the DB at once.
+const MAX_CHUNK_SIZE: u64 = 1000;
+
+/// The maximum number of epoch change ledger infos verified by one
+/// [`LedgerVerifier::verify_new_commits`], within what the DB serves at once.
+pub const MAX_EPOCHS_PER_CALL: u64 = 100;
+
+lazy_static! {
+    /// Version of the latest verified ledger info.
+    pub static ref VERIFIED_VERSION: IntGauge = register_int_gauge!(
+        "libra_db_tool_verified_version",
+        "Version of the latest verified ledger info"
+    )
+    .unwrap();
+
+    /// Number of ledger infos that failed verification.
+    pub static ref VERIFICATION_FAILURES: IntCounter = register_int_counter!(
+        "libra_db_tool_verification_failures",
+        "Number of ledger infos that failed verification"
+    )
+    .unwrap();
+}
+
+/// A committed ledger info that doesn't verify. Errors reading the DB are reported as plain
+/// errors instead, so callers can tell the two apart with `downcast_ref`.
+#[derive(Debug, Fail)]
+pub enum VerificationFailure {
+    #[fail(
+        display = "Ledger info at version {} is not signed by a quorum of epoch {}: {}",
+        version, epoch, error
+    )]
+    Signatures {
+        version: Version,
+        epoch: u64,
+        error: String,
+    },
+    #[fail(
+        display = "Ledger info at version {} is for epoch {}, expected epoch {}",
+        version, epoch, expected_epoch
+    )]
+    Epoch {
+        version: Version,
+        epoch: u64,
+        expected_epoch: u64,
+    },
+    #[fail(
+        display = "Transactions up to version {} don't verify against its ledger info: {}",
+        version, error
+    )]
+    Transactions { version: Version, error: String },
+    #[fail(
+        display = "Transaction accumulator root at version {} is {:x}, expected {:x}",
+        version, actual, expected
+    )]
+    Accumulator {
+        version: Version,
+        expected: HashValue,
+        actual: HashValue,
+    },
+    #[fail(
+        display = "State of account {} at version {} doesn't verify against the state root: {}",
+        address, version, error
+    )]
+    AccountState {
+        version: Version,
+        address: AccountAddress,
+        error: String,
+    },
+}
+
+/// Verifies the ledger infos committed to a DB one after another, tracking what has been verified
+/// so far.
+pub struct LedgerVerifier {
+    /// The latest verified ledger info.
+    ledger_info: LedgerInfo,
+    /// Epoch the ledger info following `ledger_info` belongs to.
+    next_epoch: u64,
+    /// Validator set of `next_epoch`.
+    validator_verifier: ValidatorVerifier,
+    /// Transaction accumulator as of the version of `ledger_info`.
+    accumulator: InMemoryAccumulator<TransactionAccumulatorHasher>,
+    /// Maximum number of accounts whose state is proven for each ledger info.
+    num_sampled_accounts: usize,
+}
+
+impl LedgerVerifier {
+    /// Starts from the genesis ledger info of `db`, which is trusted.
+    pub fn new(db: &LibraDB, num_sampled_accounts: usize) -> Result<Self> {
+        let genesis = db
+            .get_epoch_change_ledger_infos(0, 1)?
+            .pop()
+            .ok_or_else(|| format_err!("DB has no genesis ledger info."))?;
+        let validator_set = genesis
+            .ledger_info()
+            .next_validator_set()
+            .ok_or_else(|| format_err!("Genesis ledger info has no validator set."))?;
+        ensure!(
+            genesis.ledger_info().version() == 0,
+            "Genesis ledger info is at version {}.",
+            genesis.ledger_info().version(),
+        );
+        let txn_list = db.get_transactions(0, 1, 0, false)?;
+        txn_list.verify(genesis.ledger_info(), Some(0))?;
+        let genesis_txn_info_hash = txn_list.proof.transaction_infos()[0].hash();
+        let accumulator = InMemoryAccumulator::from_leaves(&[genesis_txn_info_hash]);
+
+        VERIFIED_VERSION.set(0);
+        Ok(Self {
+            next_epoch: genesis.ledger_info().epoch() + 1,
+            validator_verifier: ValidatorVerifier::from(validator_set),
+            ledger_info: genesis.ledger_info().clone(),
+            accumulator,
+            num_sampled_accounts,
+        })
+    }
+
+    /// Version of the latest verified ledger info.
+    pub fn verified_version(&self) -> Version {
+        self.ledger_info.version()
+    }
+
+    /// Gives up on what is between the latest verified ledger info and the latest ledger info of
+    /// `db`, and trusts the latter as if verification started from it.
+    pub fn restart_from_latest(&mut self, db: &LibraDB) -> Result<()> {
+        let startup_info = db
+            .get_startup_info()?
+            .ok_or_else(|| format_err!("DB is not bootstrapped."))?;
+        let tree_state = startup_info.committed_tree_state;
+        self.accumulator = InMemoryAccumulator::new(
+            tree_state.ledger_frozen_subtree_hashes,
+            tree_state.version + 1,
+        )?;
+        self.validator_verifier = ValidatorVerifier::from(&startup_info.latest_validator_set);
+        self.next_epoch = startup_info.ledger_info.epoch()
+            + startup_info
+                .ledger_info
+                .next_validator_set()
+                .map_or(0, |_| 1);
+        self.ledger_info = startup_info.ledger_info;
+        VERIFIED_VERSION.set(self.verified_version() as i64);
+        Ok(())
+    }
+
+    /// Verifies the ledger infos committed to `db` since the latest verified one: the epoch change
+    /// ledger infos in between and the latest ledger info. Stops at the first one that fails with
+    /// a [`VerificationFailure`], leaving it unverified.
+    ///
+    /// At most [`MAX_EPOCHS_PER_CALL`] epoch changes are verified at once, the latest ledger info
+    /// being left to a later call if it is further ahead.
+    pub fn verify_new_commits(&mut self, db: &LibraDB) -> Result<Version> {
+        let latest = db.update_to_latest_ledger(0, vec![])?.1;
+        if latest.ledger_info() == &self.ledger_info {
+            return Ok(self.verified_version());
+        }
+        let latest_epoch = latest.ledger_info().epoch();
+        let end_epoch = std::cmp::min(latest_epoch, self.next_epoch + MAX_EPOCHS_PER_CALL);
+        let mut ledger_infos = db.get_epoch_change_ledger_infos(self.next_epoch, end_epoch)?;
+        if end_epoch == latest_epoch {
+            ledger_infos.push(latest);
+        }
+
+        for ledger_info in ledger_infos {
+            self.verify_ledger_info(db, ledger_info)?;
+        }
+        Ok(self.verified_version())
+    }
+
+    /// Verifies `ledger_info`, the one following the latest verified ledger info, and moves on to
+    /// it on success.
+    fn verify_ledger_info(
+        &mut self,
+        db: &LibraDB,
+        ledger_info: LedgerInfoWithSignatures,
+    ) -> Result<()> {
+        let version = ledger_info.ledger_info().version();
+        let epoch = ledger_info.ledger_info().epoch();
+        if epoch != self.next_epoch {
+            bail!(VerificationFailure::Epoch {
+                version,
+                epoch,
+                expected_epoch: self.next_epoch,
+            });
+        }
+        if let Err(error) = ledger_info.verify(&self.validator_verifier) {
+            bail!(VerificationFailure::Signatures {
+                version,
+                epoch,
+                error: error.to_string(),
+            });
+        }
+
+        // Extend the accumulator with the transaction infos committed since the latest verified
+        // version, each chunk proven against `ledger_info`.
+        let mut accumulator = self.accumulator.clone();
+        let mut sampled_addresses = BTreeSet::new();
+        let mut state_root_hash = None;
+        let mut start_version = self.verified_version() + 1;
+        while start_version <= version {
+            let limit = std::cmp::min(MAX_CHUNK_SIZE, version - start_version + 1);
+            let txn_list = db.get_transactions(start_version, limit, version, false)?;
+            if let Err(error) = txn_list.verify(ledger_info.ledger_info(), Some(start_version)) {
+                bail!(VerificationFailure::Transactions {
+                    version,
+                    error: error.to_string(),
+                });
+            }
+            let txn_infos = txn_list.proof.transaction_infos();
+            let txn_info_hashes: Vec<_> = txn_infos.iter().map(CryptoHash::hash).collect();
+            accumulator = accumulator.append(&txn_info_hashes);
+            state_root_hash = txn_infos.last().map(|info| info.state_root_hash());
+            for txn in &txn_list.transactions {
+                if let Transaction::UserTransaction(signed_txn) = txn {
+                    if sampled_addresses.len() < self.num_sampled_accounts {
+                        sampled_addresses.insert(signed_txn.sender());
+                    }
+                }
+            }
+            start_version += txn_list.len() as u64;
+        }
+        let expected = ledger_info.ledger_info().transaction_accumulator_hash();
+        if accumulator.root_hash() != expected {
+            bail!(VerificationFailure::Accumulator {
+                version,
+                expected,
+                actual: accumulator.root_hash(),
+            });
+        }
+
+        // Prove the state of the sampled accounts, and one that most likely doesn't exist.
+        if let Some(state_root_hash) = state_root_hash {
+            sampled_addresses.insert(AccountAddress::random());
+            for address in sampled_addresses {
+                let (blob, proof) = db.get_account_state_with_proof_by_version(address, version)?;
+                if let Err(error) = proof.verify(state_root_hash, address.hash(), blob.as_ref()) {
+                    bail!(VerificationFailure::AccountState {
+                        version,
+                        address,
+                        error: error.to_string(),
+                    });
+                }
+            }
+        }
+
+        if let Some(validator_set) = ledger_info.ledger_info().next_validator_set() {
+            self.validator_verifier = ValidatorVerifier::from(validator_set);
+            self.next_epoch += 1;
+        }
+        self.ledger_info = ledger_info.ledger_info().clone();
+        self.accumulator = accumulator;
+        VERIFIED_VERSION.set(version as i64);
+        Ok(())
+    }
+}
+
+/// Options of [`follow`].
+#[derive(Clone, Debug)]
+pub struct FollowOptions {
+    /// How long to wait between two polls of the DB.
+    pub poll_interval: Duration,
+    /// Whether to keep following after a verification failure instead of returning it.
+    pub keep_going: bool,
+    /// Maximum number of accounts whose state is proven for each ledger info.
+    pub num_sampled_accounts: usize,
+}
+
+/// Verifies the DB at `db_root_path` from genesis, then keeps verifying what gets committed to it
+/// until `stop` is set.
+///
+/// Returns the first [`VerificationFailure`] unless `options.keep_going` is set, in which case
+/// the failure is logged and counted, and verification restarts from the latest ledger info
+/// committed by then, see [`LedgerVerifier::restart_from_latest`]. Errors reading the DB are
+/// logged and retried on the next poll. Returns the latest verified version once stopped.
+pub fn follow(db_root_path: &Path, options: &FollowOptions, stop: &AtomicBool) -> Result<Version> {
+    let mut verifier = LedgerVerifier::new(
+        &LibraDB::open_readonly(db_root_path)?,
+        options.num_sampled_accounts,
+    )?;
+    while !stop.load(Ordering::SeqCst) {
+        match LibraDB::open_readonly(db_root_path).and_then(|db| verifier.verify_new_commits(&db)) {
+            Ok(version) => debug!("Verified up to version {}.", version),
+            Err(error) => match error.downcast_ref::<VerificationFailure>() {
+                Some(failure) => {
+                    VERIFICATION_FAILURES.inc();
+                    if !options.keep_going {
+                        return Err(error);
+                    }
+                    error!("{}", failure);
+                    verifier.restart_from_latest(&LibraDB::open_readonly(db_root_path)?)?;
+                }
+                None => warn!("Failed to read the DB, retrying: {}", error),
+            },
+        }
+        thread::sleep(options.poll_interval);
+    }
+    Ok(verifier.verified_version())
+}
@@ -0,0 +1,220 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use libra_crypto::{ed25519::compat, hash::GENESIS_BLOCK_ID};
+use libra_tools::tempdir::TempPath;
+use libra_types::{
+    account_state_blob::AccountStateBlob,
+    block_info::BlockInfo,
+    crypto_proxies::ValidatorSigner,
+    transaction::{RawTransaction, Script, TransactionToCommit},
+    validator_public_keys::ValidatorPublicKeys,
+    validator_set::ValidatorSet,
+    vm_error::StatusCode,
+};
+use libradb::mock_genesis::GENESIS_INFO;
+use std::collections::BTreeMap;
+
+const NUM_SAMPLED_ACCOUNTS: usize = 4;
+
+fn validator_set(signer: &ValidatorSigner) -> ValidatorSet {
+    ValidatorSet::new(vec![ValidatorPublicKeys::new_with_random_network_keys(
+        signer.author(),
+        signer.public_key(),
+        1,
+    )])
+}
+
+/// Writes a chain to a DB the way a node would, while the verifier reads it.
+struct ChainWriter {
+    db: LibraDB,
+    next_version: Version,
+    epoch: u64,
+}
+
+impl ChainWriter {
+    /// Creates a DB under `path` with the mock genesis transaction, whose ledger info hands the
+    /// first epoch to `signer`.
+    fn new(path: &TempPath, signer: &ValidatorSigner) -> Self {
+        let (genesis_txn_info, _, genesis_txn) = &*GENESIS_INFO;
+        let ledger_info = LedgerInfo::new(
+            BlockInfo::new(
+                0,
+                0,
+                *GENESIS_BLOCK_ID,
+                genesis_txn_info.hash(),
+                0,
+                0,
+                Some(validator_set(signer)),
+            ),
+            HashValue::zero(),
+        );
+        let db = LibraDB::new(path.path());
+        db.save_transactions(
+            &[genesis_txn.clone()],
+            0,
+            &Some(LedgerInfoWithSignatures::new(ledger_info, BTreeMap::new())),
+        )
+        .unwrap();
+        Self {
+            db,
+            next_version: 1,
+            epoch: 1,
+        }
+    }
+
+    /// Commits a block of `num_txns` transactions from new senders with a ledger info signed by
+    /// `signer`, handing the next epoch to `next_signer` if given. Returns the version of the
+    /// ledger info.
+    fn commit_block(
+        &mut self,
+        num_txns: u64,
+        signer: &ValidatorSigner,
+        next_signer: Option<&ValidatorSigner>,
+    ) -> Version {
+        let txns_to_commit: Vec<_> = (0..num_txns)
+            .map(|i| {
+                let (private_key, public_key) = compat::generate_keypair(None);
+                let sender = AccountAddress::from_public_key(&public_key);
+                let txn = RawTransaction::new_script(
+                    sender,
+                    0,
+                    Script::new(vec![], vec![]),
+                    0,
+                    0,
+                    Duration::new(0, 0),
+                )
+                .sign(&private_key, public_key)
+                .unwrap()
+                .into_inner();
+                TransactionToCommit::new(
+                    Transaction::UserTransaction(txn),
+                    vec![(sender, AccountStateBlob::from(vec![i as u8]))]
+                        .into_iter()
+                        .collect(),
+                    vec![],
+                    0,
+                    StatusCode::EXECUTED,
+                )
+            })
+            .collect();
+        self.db
+            .save_transactions(&txns_to_commit, self.next_version, &None)
+            .unwrap();
+        self.next_version += num_txns;
+
+        // Let the DB compute the accumulator the ledger info commits to, then commit it.
+        let version = self.next_version - 1;
+        let tree_state = self
+            .db
+            .get_startup_info()
+            .unwrap()
+            .unwrap()
+            .synced_tree_state;
+        let accumulator_hash = InMemoryAccumulator::<TransactionAccumulatorHasher>::new(
+            tree_state.unwrap().ledger_frozen_subtree_hashes,
+            version + 1,
+        )
+        .unwrap()
+        .root_hash();
+        let ledger_info = LedgerInfo::new(
+            BlockInfo::new(
+                self.epoch,
+                version,
+                HashValue::random(),
+                accumulator_hash,
+                version,
+                0,
+                next_signer.map(validator_set),
+            ),
+            HashValue::zero(),
+        );
+        let mut signatures = BTreeMap::new();
+        signatures.insert(
+            signer.author(),
+            signer.sign_message(ledger_info.hash()).unwrap(),
+        );
+        self.db
+            .save_transactions(
+                &[],
+                self.next_version,
+                &Some(LedgerInfoWithSignatures::new(ledger_info, signatures)),
+            )
+            .unwrap();
+        if next_signer.is_some() {
+            self.epoch += 1;
+        }
+        version
+    }
+}
+
+fn assert_signature_failure(error: &Error, expected_version: Version) {
+    match error.downcast_ref::<VerificationFailure>() {
+        Some(VerificationFailure::Signatures { version, .. }) => {
+            assert_eq!(*version, expected_version)
+        }
+        _ => panic!("Unexpected error: {}", error),
+    }
+}
+
+#[test]
+fn test_verify_new_commits() {
+    let tmp_dir = TempPath::new();
+    let signer = ValidatorSigner::random([0u8; 32]);
+    let next_signer = ValidatorSigner::random([1u8; 32]);
+    let mut writer = ChainWriter::new(&tmp_dir, &signer);
+    let open = || LibraDB::open_readonly(tmp_dir.path()).unwrap();
+
+    let mut verifier = LedgerVerifier::new(&open(), NUM_SAMPLED_ACCOUNTS).unwrap();
+    assert_eq!(verifier.verify_new_commits(&open()).unwrap(), 0);
+
+    writer.commit_block(3, &signer, None);
+    let version = writer.commit_block(5, &signer, None);
+    assert_eq!(verifier.verify_new_commits(&open()).unwrap(), version);
+    assert_eq!(verifier.verify_new_commits(&open()).unwrap(), version);
+
+    // Both the epoch change and the latest ledger info are verified, the latter against the
+    // validator set handed over by the former.
+    writer.commit_block(2, &signer, Some(&next_signer));
+    let version = writer.commit_block(2, &next_signer, None);
+    assert_eq!(verifier.verify_new_commits(&open()).unwrap(), version);
+
+    // The validators of the previous epoch can't sign anymore.
+    let forged_version = writer.commit_block(1, &signer, None);
+    let error = verifier.verify_new_commits(&open()).unwrap_err();
+    assert_signature_failure(&error, forged_version);
+    assert_eq!(verifier.verified_version(), version);
+
+    verifier.restart_from_latest(&open()).unwrap();
+    assert_eq!(verifier.verified_version(), forged_version);
+    let version = writer.commit_block(4, &next_signer, None);
+    assert_eq!(verifier.verify_new_commits(&open()).unwrap(), version);
+}
+
+#[test]
+fn test_follow_stops_at_forged_ledger_info() {
+    let tmp_dir = TempPath::new();
+    let signer = ValidatorSigner::random([0u8; 32]);
+    let forger = ValidatorSigner::random([2u8; 32]);
+    let mut writer = ChainWriter::new(&tmp_dir, &signer);
+
+    let path = tmp_dir.path().to_path_buf();
+    let follower = thread::spawn(move || {
+        let options = FollowOptions {
+            poll_interval: Duration::from_millis(10),
+            keep_going: false,
+            num_sampled_accounts: NUM_SAMPLED_ACCOUNTS,
+        };
+        follow(&path, &options, &AtomicBool::new(false))
+    });
+
+    for _ in 0..5 {
+        writer.commit_block(3, &signer, None);
+        thread::sleep(Duration::from_millis(20));
+    }
+    let forged_version = writer.commit_block(1, &forger, None);
+
+    let error = follower.join().unwrap().unwrap_err();
+    assert_signature_failure(&error, forged_version);
+}
@@ -0,0 +1,175 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! A [`ConsistencyProof`] shows that one version of a Jellyfish Merkle tree extends an earlier
+//! version by a given set of updates, without access to the rest of either tree. It is produced by
+//! `TreeCache::consistency_proof`, e.g. for light-client-style verification between staged
+//! versions.
+//!
+//! [`ConsistencyProof`]: struct.ConsistencyProof.html
+
+use crate::{nibble_path::NibblePath, node_type::Node, ROOT_NIBBLE_HEIGHT};
+use failure::prelude::*;
+use libra_crypto::{
+    hash::{CryptoHash, SPARSE_MERKLE_PLACEHOLDER_HASH},
+    HashValue,
+};
+use libra_nibble::Nibble;
+use libra_types::{account_state_blob::AccountStateBlob, proof::SparseMerkleLeafNode};
+use std::collections::{HashMap, HashSet};
+
+/// The nodes of two versions of the tree along the paths of the keys updated in between. Every
+/// other subtree is only known by its hash, which the proof shows to be the same in both versions.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ConsistencyProof {
+    /// Nodes of the old version on the paths of the updated keys.
+    from_nodes: Vec<Node>,
+    /// Nodes of the new version on the paths of the updated keys.
+    to_nodes: Vec<Node>,
+}
+
+impl ConsistencyProof {
+    pub fn new(from_nodes: Vec<Node>, to_nodes: Vec<Node>) -> Self {
+        Self {
+            from_nodes,
+            to_nodes,
+        }
+    }
+
+    pub fn from_nodes(&self) -> &[Node] {
+        &self.from_nodes
+    }
+
+    pub fn to_nodes(&self) -> &[Node] {
+        &self.to_nodes
+    }
+
+    /// Verifies that the tree with root `to_root_hash` is the tree with root `from_root_hash` with
+    /// `updates` applied, i.e. that every key of `updates` holds the given blob in the new tree
+    /// and that nothing else differs between the two trees.
+    pub fn verify(
+        &self,
+        from_root_hash: HashValue,
+        to_root_hash: HashValue,
+        updates: &[(HashValue, AccountStateBlob)],
+    ) -> Result<()> {
+        let from = PartialTree::expand(&self.from_nodes, from_root_hash)?;
+        let mut to = PartialTree::expand(&self.to_nodes, to_root_hash)?;
+
+        let updates: HashMap<_, _> = updates
+            .iter()
+            .map(|(key, blob)| (*key, blob.hash()))
+            .collect();
+        for (key, blob_hash) in updates.iter() {
+            let leaf_hash = to.leaves.remove(key).ok_or_else(|| {
+                format_err!("Updated key {:x} is missing from the new tree.", key)
+            })?;
+            ensure!(
+                leaf_hash == SparseMerkleLeafNode::new(*key, *blob_hash).hash(),
+                "Updated key {:x} has another value in the new tree.",
+                key
+            );
+        }
+
+        // Subtrees left out of the old tree must be left as is in the new one. They stay at the
+        // same positions since the updated keys are all outside of them.
+        for subtree in from.subtrees.iter() {
+            ensure!(
+                to.subtrees.remove(subtree),
+                "Subtree {:x} of the old tree is not in the new tree.",
+                subtree.1
+            );
+        }
+
+        // The other leaves on the paths of the updated keys are unchanged, but may have been moved
+        // down to make room for new keys next to them.
+        for (key, leaf_hash) in from.leaves {
+            if updates.contains_key(&key) {
+                continue;
+            }
+            if let Some(new_leaf_hash) = to.leaves.remove(&key) {
+                ensure!(
+                    new_leaf_hash == leaf_hash,
+                    "Key {:x} changed without being updated.",
+                    key
+                );
+                continue;
+            }
+            let subtree = to
+                .subtrees
+                .iter()
+                .find(|(position, hash)| *hash == leaf_hash && is_prefix_of(position, key))
+                .cloned()
+                .ok_or_else(|| format_err!("Key {:x} is missing from the new tree.", key))?;
+            to.subtrees.remove(&subtree);
+        }
+
+        ensure!(
+            to.leaves.is_empty() && to.subtrees.is_empty(),
+            "The new tree has content that is neither in the old tree nor in the updates."
+        );
+        Ok(())
+    }
+}
+
+/// The part of a tree revealed by a proof.
+#[derive(Default)]
+struct PartialTree {
+    /// Leaf hashes of the leaves reached, by key.
+    leaves: HashMap<HashValue, HashValue>,
+    /// Hashes of the subtrees left out, by position.
+    subtrees: HashSet<(NibblePath, HashValue)>,
+}
+
+impl PartialTree {
+    /// Walks down from `root_hash` through `nodes`, which are looked up by hash so that only the
+    /// ones matching the hashes of their parents are reached.
+    fn expand(nodes: &[Node], root_hash: HashValue) -> Result<Self> {
+        let nodes_by_hash: HashMap<_, _> = nodes.iter().map(|node| (node.hash(), node)).collect();
+        let mut tree = PartialTree::default();
+        let mut pending = vec![(NibblePath::new(vec![]), root_hash)];
+        while let Some((position, hash)) = pending.pop() {
+            match nodes_by_hash.get(&hash) {
+                None => {
+                    if hash != *SPARSE_MERKLE_PLACEHOLDER_HASH {
+                        tree.subtrees.insert((position, hash));
+                    }
+                }
+                Some(Node::Null) => (),
+                Some(Node::Leaf(leaf_node)) => {
+                    ensure!(
+                        is_prefix_of(&position, leaf_node.account_key()),
+                        "Leaf of key {:x} is out of place.",
+                        leaf_node.account_key()
+                    );
+                    tree.leaves.insert(leaf_node.account_key(), hash);
+                }
+                Some(Node::Internal(internal_node)) => {
+                    ensure!(
+                        position.num_nibbles() < ROOT_NIBBLE_HEIGHT,
+                        "Internal node {:x} is below the maximum height.",
+                        hash
+                    );
+                    for i in 0..16u8 {
+                        let nibble = Nibble::from(i);
+                        if let Some(child) = internal_node.child(nibble) {
+                            let mut child_position = position.clone();
+                            child_position.push(nibble);
+                            pending.push((child_position, child.hash));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(tree)
+    }
+}
+
+/// Whether `key` is under `position` in the tree.
+fn is_prefix_of(position: &NibblePath, key: HashValue) -> bool {
+    let key_path = NibblePath::new(key.to_vec());
+    let mut key_nibbles = key_path.nibbles();
+    position
+        .nibbles()
+        .all(|nibble| key_nibbles.next() == Some(nibble))
+}
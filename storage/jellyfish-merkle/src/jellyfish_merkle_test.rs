@@ -106,6 +106,68 @@ fn test_insert_at_leaf_with_internal_created() {
     assert_eq!(db.get_node(&internal_node_key).unwrap(), internal);
 }
 
+#[test]
+fn test_single_leaf_tree() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+
+    let key1 = HashValue::new([0x00u8; HashValue::LENGTH]);
+    let value1 = AccountStateBlob::from(vec![1u8]);
+    let value1_update = AccountStateBlob::from(vec![2u8]);
+    let (_root0_hash, batch) = tree
+        .put_blob_set(vec![(key1, value1.clone())], 0 /* version */)
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // Updating the only leaf replaces the root.
+    let (_root1_hash, batch) = tree
+        .put_blob_set(vec![(key1, value1_update.clone())], 1 /* version */)
+        .unwrap();
+    assert_eq!(batch.stale_node_index_batch.len(), 1);
+    assert_eq!(batch.num_new_leaves, 1);
+    assert_eq!(batch.num_stale_leaves, 1);
+    db.write_tree_update_batch(batch).unwrap();
+    assert_eq!(
+        db.get_node(&NodeKey::new_empty_path(1)).unwrap(),
+        Node::new_leaf(key1, value1_update.clone())
+    );
+    assert_eq!(tree.get(key1, 0).unwrap().unwrap(), value1);
+    assert_eq!(tree.get(key1, 1).unwrap().unwrap(), value1_update);
+
+    // A key sharing the first 2 nibbles splits the leaf into two below a chain of 3 internal
+    // nodes.
+    let key2 = update_nibble(&key1, 2, 15);
+    let value2 = AccountStateBlob::from(vec![3u8]);
+    let (root2_hash, batch) = tree
+        .put_blob_set(vec![(key2, value2.clone())], 2 /* version */)
+        .unwrap();
+    assert_eq!(batch.stale_node_index_batch.len(), 1);
+    db.write_tree_update_batch(batch).unwrap();
+    assert_eq!(db.num_nodes(), 7 /* 1 + 1 + 5 */);
+
+    let leaf1 = Node::new_leaf(key1, value1_update.clone());
+    let leaf2 = Node::new_leaf(key2, value2.clone());
+    let internal_node_key = NodeKey::new_empty_path(2)
+        .gen_child_node_key(2 /* version */, Nibble::from(0))
+        .gen_child_node_key(2 /* version */, Nibble::from(0));
+    assert_eq!(
+        db.get_node(&internal_node_key.gen_child_node_key(2 /* version */, Nibble::from(0)))
+            .unwrap(),
+        leaf1
+    );
+    assert_eq!(
+        db.get_node(&internal_node_key.gen_child_node_key(2 /* version */, Nibble::from(15)))
+            .unwrap(),
+        leaf2
+    );
+    assert_eq!(tree.get_root_hash(2).unwrap(), root2_hash[0]);
+    for (key, value) in &[(key1, value1_update), (key2, value2)] {
+        let (blob, proof) = tree.get_with_proof(*key, 2).unwrap();
+        assert_eq!(blob.as_ref(), Some(value));
+        proof.verify(root2_hash[0], *key, Some(value)).unwrap();
+    }
+}
+
 #[test]
 fn test_insert_at_leaf_with_multiple_internals_created() {
     let db = MockTreeStore::default();
@@ -475,6 +537,7 @@ fn test_put_blob_sets() {
                 .extend(batch.stale_node_index_batch);
             batch_one_by_one.num_new_leaves += batch.num_new_leaves;
             batch_one_by_one.num_stale_leaves += batch.num_stale_leaves;
+            batch_one_by_one.root_node_keys.extend(batch.root_node_keys);
         }
     }
     {
@@ -495,6 +558,96 @@ fn test_put_blob_sets() {
     }
 }
 
+#[test]
+fn test_stale_indices_grouped_by_version() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+
+    let key = HashValue::random();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            vec![(key, AccountStateBlob::from(vec![1u8]))],
+            0, /* version */
+        )
+        .unwrap();
+    assert!(batch.stale_indices_grouped_by_version().is_empty());
+    db.write_tree_update_batch(batch).unwrap();
+
+    // Overwriting the only leaf makes the previous leaf stale at each version.
+    let (_root_hashes, batch) = tree
+        .put_blob_sets(
+            vec![
+                vec![(key, AccountStateBlob::from(vec![2u8]))],
+                vec![(key, AccountStateBlob::from(vec![3u8]))],
+            ],
+            1, /* first_version */
+        )
+        .unwrap();
+    let grouped = batch.stale_indices_grouped_by_version();
+    assert_eq!(grouped.len(), 2);
+    assert_eq!(grouped[&1], vec![NodeKey::new_empty_path(0)]);
+    assert_eq!(grouped[&2], vec![NodeKey::new_empty_path(1)]);
+    assert_eq!(
+        grouped.values().map(Vec::len).sum::<usize>(),
+        batch.stale_node_index_batch.len()
+    );
+}
+
+#[test]
+fn test_get_with_proof_by_root() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+
+    let key = HashValue::random();
+    let other_key = HashValue::random();
+    let mut root_hashes = vec![];
+    for version in 0..3 {
+        let mut blob_set = vec![(key, AccountStateBlob::from(vec![version as u8]))];
+        if version == 0 {
+            blob_set.push((other_key, AccountStateBlob::from(vec![0xff])));
+        }
+        let (root_hash, batch) = tree.put_blob_set(blob_set, version).unwrap();
+        db.write_tree_update_batch(batch).unwrap();
+        root_hashes.push(root_hash);
+    }
+
+    for (version, root_hash) in root_hashes.iter().enumerate() {
+        let (blob, proof) = tree.get_with_proof_by_root(*root_hash, key).unwrap();
+        assert_eq!(blob, Some(AccountStateBlob::from(vec![version as u8])));
+        assert!(proof.verify(*root_hash, key, blob.as_ref()).is_ok());
+        assert_eq!(
+            (blob, proof),
+            tree.get_with_proof(key, version as Version).unwrap()
+        );
+    }
+
+    let unknown_root_hash = HashValue::random();
+    let err = tree
+        .get_with_proof_by_root(unknown_root_hash, key)
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<JellyfishMerkleError>().unwrap(),
+        JellyfishMerkleError::UnknownRoot(unknown_root_hash)
+    );
+
+    // Pruning removes the trees of the intermediate versions, but not the latest one.
+    db.purge_stale_nodes(2).unwrap();
+    for root_hash in &root_hashes[..2] {
+        let err = tree.get_with_proof_by_root(*root_hash, key).unwrap_err();
+        assert_eq!(
+            err.downcast::<JellyfishMerkleError>().unwrap(),
+            JellyfishMerkleError::UnknownRoot(*root_hash)
+        );
+    }
+    let (blob, proof) = tree
+        .get_with_proof_by_root(root_hashes[2], other_key)
+        .unwrap();
+    assert_eq!(blob, Some(AccountStateBlob::from(vec![0xff])));
+    assert!(proof
+        .verify(root_hashes[2], other_key, blob.as_ref())
+        .is_ok());
+}
+
 fn many_keys_get_proof_and_verify_tree_root(seed: &[u8], num_keys: usize) {
     assert!(seed.len() < 32);
     let mut actual_seed = [0u8; 32];
@@ -66,17 +66,19 @@
 //! [`InternalNode`]: node_type/struct.InternalNode.html
 //! [`LeafNode`]: node_type/struct.LeafNode.html
 
+pub mod consistency_proof;
 pub mod iterator;
 #[cfg(test)]
 mod jellyfish_merkle_test;
-#[cfg(test)]
-mod mock_tree_store;
+#[cfg(any(test, feature = "testing"))]
+pub mod mock_tree_store;
 mod nibble_path;
 pub mod node_type;
+pub mod proof_helpers;
 pub mod restore;
 #[cfg(test)]
 mod test_helper;
-mod tree_cache;
+pub mod tree_cache;
 
 use failure::prelude::*;
 use libra_crypto::{hash::CryptoHash, HashValue};
@@ -111,11 +113,57 @@ pub trait TreeReader {
     /// Gets the rightmost leaf. Note that this assumes we are in the process of restoring the tree
     /// and all nodes are at the same version.
     fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>>;
+
+    /// Gets the key of the root node of the tree at `version`, `None` if there is no tree at that
+    /// version. By default the root is assumed to be stored under the empty path at `version`.
+    fn get_root_node_key(&self, version: Version) -> Result<Option<NodeKey>> {
+        Ok(Some(NodeKey::new_empty_path(version)))
+    }
+
+    /// Gets the number of nodes created at `version`. Unsupported by default.
+    fn num_nodes_at_version(&self, version: Version) -> Result<u64> {
+        bail!(
+            "Unsupported: node count at version {} is not tracked.",
+            version
+        )
+    }
+
+    /// Gets the key of the root node whose hash is `root_hash`, `None` if no root with that hash
+    /// was indexed. Entries are dropped when their nodes are pruned, but a key returned may still
+    /// refer to a node pruned in the meantime.
+    /// Unsupported by default.
+    fn get_root_node_key_by_hash(&self, root_hash: HashValue) -> Result<Option<NodeKey>> {
+        bail!(
+            "Unsupported: root node {:#} is not indexed by hash.",
+            root_hash
+        )
+    }
 }
 
 pub trait TreeWriter {
     /// Writes a node batch into storage.
     fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()>;
+
+    /// Writes an entry of the index of root nodes by hash.
+    fn write_root_node_key(&self, root_hash: HashValue, node_key: &NodeKey) -> Result<()>;
+}
+
+/// Errors of [`JellyfishMerkleTree`](struct.JellyfishMerkleTree.html) reads and of tree cache
+/// updates that callers may want to tell apart.
+#[derive(Debug, Fail, PartialEq)]
+pub enum JellyfishMerkleError {
+    /// No tree with the requested root hash is known, or it has been pruned.
+    #[fail(display = "Unknown root hash {:#}.", _0)]
+    UnknownRoot(HashValue),
+    /// Updates led to a root hash other than the one expected, e.g. by a ledger info.
+    #[fail(
+        display = "Root hash {:#} differs from the expected {:#}.",
+        actual, expected
+    )]
+    RootMismatch {
+        expected: HashValue,
+        actual: HashValue,
+    },
 }
 
 /// Node batch that will be written into db atomically with other batches.
@@ -145,11 +193,30 @@ pub struct TreeUpdateBatch {
     pub stale_node_index_batch: StaleNodeIndexBatch,
     pub num_new_leaves: usize,
     pub num_stale_leaves: usize,
+    /// Keys of the root nodes of the versions in the batch, by root hash.
+    pub root_node_keys: BTreeMap<HashValue, NodeKey>,
+}
+
+impl TreeUpdateBatch {
+    /// Groups the keys of the nodes that became stale in this batch by the version since which
+    /// they are stale, in the shape pruners consume them.
+    pub fn stale_indices_grouped_by_version(&self) -> BTreeMap<Version, Vec<NodeKey>> {
+        let mut grouped: BTreeMap<Version, Vec<NodeKey>> = BTreeMap::new();
+        for index in &self.stale_node_index_batch {
+            grouped
+                .entry(index.stale_since_version)
+                .or_default()
+                .push(index.node_key.clone());
+        }
+        grouped
+    }
 }
 
 /// The Jellyfish Merkle tree data structure. See [`crate`] for description.
 pub struct JellyfishMerkleTree<'a, R: 'a + TreeReader> {
     reader: &'a R,
+    // Whether the tree caches of updates reuse the children maps of retired internal nodes.
+    pool_nodes: bool,
 }
 
 impl<'a, R> JellyfishMerkleTree<'a, R>
@@ -158,7 +225,14 @@ where
 {
     /// Creates a `JellyfishMerkleTree` backed by the given [`TreeReader`](trait.TreeReader.html).
     pub fn new(reader: &'a R) -> Self {
-        Self { reader }
+        Self::new_with_node_pool(reader, false)
+    }
+
+    /// Creates a `JellyfishMerkleTree` like `new`. With `pool_nodes` set, updates build new
+    /// internal nodes in the children maps of the ones they retire, see
+    /// [`TreeCache::new_with_node_pool`](tree_cache/struct.GenericTreeCache.html#method.new_with_node_pool).
+    pub fn new_with_node_pool(reader: &'a R, pool_nodes: bool) -> Self {
+        Self { reader, pool_nodes }
     }
 
     /// This is a convenient function that calls
@@ -225,7 +299,8 @@ where
         blob_sets: Vec<Vec<(HashValue, AccountStateBlob)>>,
         first_version: Version,
     ) -> Result<(Vec<HashValue>, TreeUpdateBatch)> {
-        let mut tree_cache = TreeCache::new(self.reader, first_version);
+        let mut tree_cache =
+            TreeCache::new_with_node_pool(self.reader, first_version, self.pool_nodes)?;
         for (idx, blob_set) in blob_sets.into_iter().enumerate() {
             assert!(
                 !blob_set.is_empty(),
@@ -253,22 +328,94 @@ where
 
         // Get the root node. If this is the first operation, it would get the root node from the
         // underlying db. Otherwise it most likely would come from `cache`.
-        let root_node_key = tree_cache.get_root_node_key();
+        let root_node_key = tree_cache.get_root_node_key().clone();
+
+        // A tree of a single leaf is updated in place or split into two leaves directly.
+        if let Node::Leaf(leaf_node) = tree_cache.get_node(&root_node_key)? {
+            let new_root_node_key =
+                Self::put_at_single_leaf(root_node_key, leaf_node, key, blob, version, tree_cache)?;
+            tree_cache.set_root_node_key(new_root_node_key);
+            return Ok(());
+        }
+
         let mut nibble_iter = nibble_path.nibbles();
 
         // Start insertion from the root node.
-        let (new_root_node_key, _) = Self::insert_at(
-            root_node_key.clone(),
-            version,
-            &mut nibble_iter,
-            blob,
-            tree_cache,
-        )?;
+        let (new_root_node_key, new_root_node) =
+            Self::insert_at(root_node_key, version, &mut nibble_iter, blob, tree_cache)?;
+        tree_cache.recycle_node(new_root_node);
 
         tree_cache.set_root_node_key(new_root_node_key);
         Ok(())
     }
 
+    /// Puts `key` into a tree consisting of the single leaf `existing_leaf_node` at its root.
+    /// Either the leaf is replaced, or it is split into two leaves hanging off an internal node at
+    /// the common prefix of their keys, below a chain of single-child internal nodes up to the
+    /// root. Returns the key of the new root.
+    fn put_at_single_leaf(
+        root_node_key: NodeKey,
+        existing_leaf_node: LeafNode,
+        key: HashValue,
+        blob: AccountStateBlob,
+        version: Version,
+        tree_cache: &mut TreeCache<R>,
+    ) -> Result<NodeKey> {
+        // The existing leaf is either replaced or moved down, so it won't be referenced anymore
+        // at its current key since this version.
+        tree_cache.delete_node(&root_node_key, true /* is_leaf */);
+        let new_leaf_node = Node::new_leaf(key, blob);
+        if existing_leaf_node.account_key() == key {
+            let new_root_node_key = NodeKey::new_empty_path(version);
+            tree_cache.put_node(new_root_node_key.clone(), new_leaf_node)?;
+            return Ok(new_root_node_key);
+        }
+
+        let nibble_path = NibblePath::new(key.to_vec());
+        let mut nibble_iter = nibble_path.nibbles();
+        let existing_leaf_nibble_path = NibblePath::new(existing_leaf_node.account_key().to_vec());
+        let mut existing_leaf_nibble_iter = existing_leaf_nibble_path.nibbles();
+        skip_common_prefix(&mut nibble_iter, &mut existing_leaf_nibble_iter);
+        let mut common_nibble_path = nibble_iter.visited_nibbles().collect::<NibblePath>();
+        let new_leaf_index = nibble_iter.next().expect("Ran out of nibbles");
+        let existing_leaf_index = existing_leaf_nibble_iter
+            .next()
+            .expect("Ran out of nibbles");
+
+        let mut node_key = NodeKey::new(version, common_nibble_path.clone());
+        let mut children = tree_cache.new_children();
+        children.insert(
+            existing_leaf_index,
+            Child::new(existing_leaf_node.hash(), version, true /* is_leaf */),
+        );
+        children.insert(
+            new_leaf_index,
+            Child::new(new_leaf_node.hash(), version, true /* is_leaf */),
+        );
+        tree_cache.put_node(
+            node_key.gen_child_node_key(version, existing_leaf_index),
+            existing_leaf_node.into(),
+        )?;
+        tree_cache.put_node(
+            node_key.gen_child_node_key(version, new_leaf_index),
+            new_leaf_node,
+        )?;
+
+        let mut node: Node = InternalNode::new(children).into();
+        while let Some(nibble) = common_nibble_path.pop() {
+            let mut children = tree_cache.new_children();
+            children.insert(
+                nibble,
+                Child::new(node.hash(), version, false /* is_leaf */),
+            );
+            tree_cache.put_node(node_key, node)?;
+            node_key = NodeKey::new(version, common_nibble_path.clone());
+            node = InternalNode::new(children).into();
+        }
+        tree_cache.put_node(node_key.clone(), node)?;
+        Ok(node_key)
+    }
+
     /// Helper function for recursive insertion into the subtree that starts from the current
     /// [`NodeKey`](node_type/struct.NodeKey.html). Returns the newly inserted node.
     /// It is safe to use recursion here because the max depth is limited by the key length which
@@ -280,7 +427,7 @@ where
         blob: AccountStateBlob,
         tree_cache: &mut TreeCache<R>,
     ) -> Result<(NodeKey, Node)> {
-        let node = tree_cache.get_node(&node_key)?;
+        let node = tree_cache.get_node_pooled(&node_key)?;
         match node {
             Node::Internal(internal_node) => Self::insert_at_internal_node(
                 node_key,
@@ -356,13 +503,15 @@ where
             child_index,
             Child::new(new_child_node.hash(), version, new_child_node.is_leaf()),
         );
-        let new_internal_node = InternalNode::new(children);
+        tree_cache.recycle_node(new_child_node);
+        let new_internal_node: Node = InternalNode::new(children).into();
 
         node_key.set_version(version);
 
         // Cache this new internal node.
-        tree_cache.put_node(node_key.clone(), new_internal_node.clone().into())?;
-        Ok((node_key, new_internal_node.into()))
+        let cached_node = tree_cache.copy_node(&new_internal_node);
+        tree_cache.put_node(node_key.clone(), cached_node)?;
+        Ok((node_key, new_internal_node))
     }
 
     /// Helper function for recursive insertion into the subtree that starts from the
@@ -432,7 +581,7 @@ where
         let new_leaf_index = nibble_iter.next().expect("Ran out of nibbles");
         assert_ne!(existing_leaf_index, new_leaf_index);
 
-        let mut children = Children::new();
+        let mut children = tree_cache.new_children();
         children.insert(
             existing_leaf_index,
             Child::new(existing_leaf_node.hash(), version, true /* is_leaf */),
@@ -454,26 +603,27 @@ where
             Child::new(new_leaf_node.hash(), version, true /* is_leaf */),
         );
 
-        let internal_node = InternalNode::new(children);
-        let mut next_internal_node = internal_node.clone();
-        tree_cache.put_node(node_key.clone(), internal_node.into())?;
+        let internal_node: Node = InternalNode::new(children).into();
+        let mut next_internal_node = tree_cache.copy_node(&internal_node);
+        tree_cache.put_node(node_key.clone(), internal_node)?;
 
         for _i in 0..num_common_nibbles_below_internal {
             let nibble = common_nibble_path
                 .pop()
                 .expect("Common nibble_path below internal node ran out of nibble");
             node_key = NodeKey::new(version, common_nibble_path.clone());
-            let mut children = Children::new();
+            let mut children = tree_cache.new_children();
             children.insert(
                 nibble,
                 Child::new(next_internal_node.hash(), version, false /* is_leaf */),
             );
-            let internal_node = InternalNode::new(children);
-            next_internal_node = internal_node.clone();
-            tree_cache.put_node(node_key.clone(), internal_node.into())?;
+            tree_cache.recycle_node(next_internal_node);
+            let internal_node: Node = InternalNode::new(children).into();
+            next_internal_node = tree_cache.copy_node(&internal_node);
+            tree_cache.put_node(node_key.clone(), internal_node)?;
         }
 
-        Ok((node_key, next_internal_node.into()))
+        Ok((node_key, next_internal_node))
     }
 
     /// Helper function for creating leaf nodes. Returns the newly created leaf node.
@@ -500,9 +650,35 @@ where
         &self,
         key: HashValue,
         version: Version,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        self.get_with_proof_from(key, NodeKey::new_empty_path(version))
+    }
+
+    /// Returns the account state blob (if applicable) and the corresponding merkle proof in the
+    /// tree whose root hash is `root_hash`, so that callers holding a trusted root hash don't need
+    /// to trust the version it maps to. Fails with
+    /// [`JellyfishMerkleError::UnknownRoot`](enum.JellyfishMerkleError.html) if no such tree is
+    /// known, including when it has been pruned.
+    pub fn get_with_proof_by_root(
+        &self,
+        root_hash: HashValue,
+        key: HashValue,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        let root_node_key = match self.reader.get_root_node_key_by_hash(root_hash)? {
+            // The index is not cleaned up by pruning.
+            Some(node_key) if self.reader.get_node_option(&node_key)?.is_some() => node_key,
+            _ => return Err(JellyfishMerkleError::UnknownRoot(root_hash).into()),
+        };
+        self.get_with_proof_from(key, root_node_key)
+    }
+
+    fn get_with_proof_from(
+        &self,
+        key: HashValue,
+        root_node_key: NodeKey,
     ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
         // Empty tree just returns proof with no sibling hash.
-        let mut next_node_key = NodeKey::new_empty_path(version);
+        let mut next_node_key = root_node_key;
         let mut siblings = vec![];
         let nibble_path = NibblePath::new(key.to_vec());
         let mut nibble_iter = nibble_path.nibbles();
@@ -0,0 +1,145 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::JellyfishMerkleTree;
+use libra_types::account_state_blob::AccountStateBlob;
+
+fn put(db: &MockTreeStore, key: HashValue, value: u8, version: Version) -> TreeUpdateBatch {
+    let (_root_hash, batch) = JellyfishMerkleTree::new(db)
+        .put_blob_set(vec![(key, AccountStateBlob::from(vec![value]))], version)
+        .unwrap();
+    batch
+}
+
+#[test]
+fn test_write_tree_update_batch_is_atomic() {
+    let db = MockTreeStore::default();
+    let key1 = HashValue::new([0x00u8; HashValue::LENGTH]);
+    let key2 = HashValue::new([0xffu8; HashValue::LENGTH]);
+    db.write_tree_update_batch(put(&db, key1, 1, 0)).unwrap();
+
+    // The new root at version 1 already exists, so none of the other nodes, the stale index of
+    // the old root or the root hash are written.
+    let batch = put(&db, key2, 2, 1);
+    let root_key = NodeKey::new_empty_path(1);
+    let root_hash = *batch.root_node_keys.keys().next().unwrap();
+    db.put_node(root_key.clone(), batch.node_batch[&root_key].clone())
+        .unwrap();
+    assert!(db.write_tree_update_batch(batch.clone()).is_err());
+    assert_eq!(db.num_nodes(), 2);
+    assert_eq!(db.num_stale_node_indices(), 0);
+    assert_eq!(db.get_root_node_key_by_hash(root_hash).unwrap(), None);
+
+    let db = MockTreeStore::default();
+    db.write_tree_update_batch(put(&db, key1, 1, 0)).unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    assert_eq!(db.num_nodes(), 4);
+    assert_eq!(db.num_stale_node_indices(), 1);
+    assert_eq!(
+        db.get_root_node_key_by_hash(root_hash).unwrap(),
+        Some(root_key)
+    );
+}
+
+#[test]
+fn test_purge_stale_nodes() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let key = HashValue::random();
+    let batch = put(&db, key, 1, 0);
+    let root_hash0 = *batch.root_node_keys.keys().next().unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let batch = put(&db, key, 2, 1);
+    let root_hash1 = *batch.root_node_keys.keys().next().unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    assert_eq!(db.num_nodes(), 2);
+    assert_eq!(db.num_stale_node_indices(), 1);
+
+    // The leaf of version 0 is stale since version 1, so it's needed as long as version 0 is.
+    db.purge_stale_nodes(0).unwrap();
+    assert_eq!(db.num_nodes(), 2);
+    assert_eq!(
+        tree.get(key, 0).unwrap(),
+        Some(AccountStateBlob::from(vec![1]))
+    );
+
+    db.purge_stale_nodes(1).unwrap();
+    assert_eq!(db.num_nodes(), 1);
+    assert_eq!(db.num_stale_node_indices(), 0);
+    assert!(tree.get(key, 0).is_err());
+    assert_eq!(
+        tree.get(key, 1).unwrap(),
+        Some(AccountStateBlob::from(vec![2]))
+    );
+    // The root of version 0 is no longer indexed by hash, unlike the one of version 1.
+    assert_eq!(db.get_root_node_key_by_hash(root_hash0).unwrap(), None);
+    assert_eq!(
+        db.get_root_node_key_by_hash(root_hash1).unwrap(),
+        Some(NodeKey::new_empty_path(1))
+    );
+}
+
+#[test]
+fn test_purge_stale_nodes_is_atomic() {
+    let db = MockTreeStore::default();
+    let key = HashValue::random();
+    db.write_tree_update_batch(put(&db, key, 1, 0)).unwrap();
+    let mut batch = put(&db, key, 2, 1);
+    batch.stale_node_index_batch.insert(StaleNodeIndex {
+        stale_since_version: 1,
+        node_key: NodeKey::new_empty_path(5),
+    });
+    db.write_tree_update_batch(batch).unwrap();
+
+    assert!(db.purge_stale_nodes(1).is_err());
+    assert_eq!(db.num_nodes(), 2);
+    assert_eq!(db.num_stale_node_indices(), 2);
+}
+
+#[test]
+fn test_fail_nth_read() {
+    let db = MockTreeStore::default();
+    let key = HashValue::random();
+    db.write_tree_update_batch(put(&db, key, 1, 0)).unwrap();
+    let node_key = NodeKey::new_empty_path(0);
+
+    db.fail_nth_read(2);
+    assert!(db.get_node_option(&node_key).unwrap().is_some());
+    assert!(db.get_node_option(&node_key).is_err());
+    // Only the Nth read fails.
+    assert!(db.get_node_option(&node_key).unwrap().is_some());
+
+    // The failure surfaces through the tree.
+    db.fail_nth_read(1);
+    assert!(JellyfishMerkleTree::new(&db).get(key, 0).is_err());
+    assert!(JellyfishMerkleTree::new(&db).get(key, 0).unwrap().is_some());
+
+    db.fail_nth_read(1);
+    db.clear_faults();
+    assert!(db.get_rightmost_leaf().unwrap().is_some());
+}
+
+#[test]
+fn test_fail_nth_write() {
+    let db = MockTreeStore::default();
+    let key = HashValue::random();
+    let batch = put(&db, key, 1, 0);
+
+    db.fail_nth_write(2);
+    db.write_root_node_key(HashValue::random(), &NodeKey::new_empty_path(0))
+        .unwrap();
+    assert!(db.write_tree_update_batch(batch.clone()).is_err());
+    assert_eq!(db.num_nodes(), 0);
+
+    // A failed write changes nothing, so it can be retried.
+    db.write_tree_update_batch(batch).unwrap();
+    assert_eq!(db.num_nodes(), 1);
+
+    db.write_tree_update_batch(put(&db, key, 2, 1)).unwrap();
+    db.fail_nth_write(1);
+    assert!(db.purge_stale_nodes(1).is_err());
+    assert_eq!(db.num_nodes(), 2);
+    db.purge_stale_nodes(1).unwrap();
+    assert_eq!(db.num_nodes(), 1);
+}
@@ -0,0 +1,238 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! An in-memory store implementing both [`TreeReader`](../trait.TreeReader.html) and
+//! [`TreeWriter`](../trait.TreeWriter.html), which is the supported harness for testing code built
+//! on top of [`JellyfishMerkleTree`](../struct.JellyfishMerkleTree.html) without a real database.
+//! It is available to other crates with the `testing` feature.
+//!
+//! Like the storage layer, [`MockTreeStore`](struct.MockTreeStore.html) commits a
+//! [`TreeUpdateBatch`](../struct.TreeUpdateBatch.html) atomically, keeps track of the indices of
+//! stale nodes and purges the nodes that are not readable anymore. It can also be told to fail the
+//! Nth read or write, to exercise the error paths of its users.
+
+#[cfg(test)]
+mod mock_tree_store_test;
+
+use crate::{
+    node_type::{LeafNode, Node, NodeKey},
+    NodeBatch, StaleNodeIndex, TreeReader, TreeUpdateBatch, TreeWriter,
+};
+use failure::prelude::*;
+use libra_crypto::HashValue;
+use libra_types::transaction::Version;
+use std::{
+    collections::{hash_map::Entry, BTreeSet, HashMap},
+    sync::{Mutex, RwLock},
+};
+
+#[derive(Default)]
+struct Contents {
+    nodes: HashMap<NodeKey, Node>,
+    stale_node_indices: BTreeSet<StaleNodeIndex>,
+    root_node_keys: HashMap<HashValue, NodeKey>,
+}
+
+impl Contents {
+    fn ensure_new_nodes<'a>(&self, node_keys: impl IntoIterator<Item = &'a NodeKey>) -> Result<()> {
+        for node_key in node_keys {
+            ensure!(
+                !self.nodes.contains_key(node_key),
+                "Key {:?} exists.",
+                node_key
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Fails the Nth of a kind of operations once armed.
+#[derive(Default)]
+struct FaultInjector(Mutex<Option<usize>>);
+
+impl FaultInjector {
+    fn arm(&self, n: usize) {
+        assert!(n > 0, "Operations are counted from 1.");
+        *self.0.lock().unwrap() = Some(n);
+    }
+
+    fn disarm(&self) {
+        *self.0.lock().unwrap() = None;
+    }
+
+    /// Counts an operation, failing it if it is the one the injector was armed for.
+    fn check(&self, operation: &str) -> Result<()> {
+        let mut remaining = self.0.lock().unwrap();
+        if let Some(n) = remaining.as_mut() {
+            *n -= 1;
+            if *n == 0 {
+                *remaining = None;
+                bail!("Injected failure of {}.", operation);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// In-memory node store. See the [module level documentation](index.html).
+#[derive(Default)]
+pub struct MockTreeStore {
+    contents: RwLock<Contents>,
+    read_faults: FaultInjector,
+    write_faults: FaultInjector,
+}
+
+impl TreeReader for MockTreeStore {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        self.read_faults.check("get_node_option")?;
+        Ok(self.contents.read().unwrap().nodes.get(node_key).cloned())
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        self.read_faults.check("get_rightmost_leaf")?;
+        let locked = self.contents.read().unwrap();
+        let mut node_key_and_node: Option<(NodeKey, LeafNode)> = None;
+
+        for (key, value) in locked.nodes.iter() {
+            if let Node::Leaf(leaf_node) = value {
+                if node_key_and_node.is_none()
+                    || leaf_node.account_key() > node_key_and_node.as_ref().unwrap().1.account_key()
+                {
+                    node_key_and_node.replace((key.clone(), leaf_node.clone()));
+                }
+            }
+        }
+
+        Ok(node_key_and_node)
+    }
+
+    fn get_root_node_key_by_hash(&self, root_hash: HashValue) -> Result<Option<NodeKey>> {
+        self.read_faults.check("get_root_node_key_by_hash")?;
+        Ok(self
+            .contents
+            .read()
+            .unwrap()
+            .root_node_keys
+            .get(&root_hash)
+            .cloned())
+    }
+}
+
+impl TreeWriter for MockTreeStore {
+    /// Writes all the nodes of `node_batch`, or none of them if any already exists.
+    fn write_node_batch(&self, node_batch: &NodeBatch) -> Result<()> {
+        self.write_faults.check("write_node_batch")?;
+        let mut locked = self.contents.write().unwrap();
+        locked.ensure_new_nodes(node_batch.keys())?;
+        locked.nodes.extend(node_batch.clone());
+        Ok(())
+    }
+
+    fn write_root_node_key(&self, root_hash: HashValue, node_key: &NodeKey) -> Result<()> {
+        self.write_faults.check("write_root_node_key")?;
+        self.contents
+            .write()
+            .unwrap()
+            .root_node_keys
+            .insert(root_hash, node_key.clone());
+        Ok(())
+    }
+}
+
+impl MockTreeStore {
+    pub fn put_node(&self, node_key: NodeKey, node: Node) -> Result<()> {
+        self.write_faults.check("put_node")?;
+        match self.contents.write().unwrap().nodes.entry(node_key) {
+            Entry::Occupied(o) => bail!("Key {:?} exists.", o.key()),
+            Entry::Vacant(v) => {
+                v.insert(node);
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits `batch` as a single write the way the storage layer does: nothing is written if
+    /// any of its nodes or stale node indices already exists.
+    pub fn write_tree_update_batch(&self, batch: TreeUpdateBatch) -> Result<()> {
+        self.write_faults.check("write_tree_update_batch")?;
+        let mut locked = self.contents.write().unwrap();
+        locked.ensure_new_nodes(batch.node_batch.keys())?;
+        for index in &batch.stale_node_index_batch {
+            ensure!(
+                !locked.stale_node_indices.contains(index),
+                "Duplicated retire log {:?}.",
+                index
+            );
+        }
+
+        locked.nodes.extend(batch.node_batch);
+        locked
+            .stale_node_indices
+            .extend(batch.stale_node_index_batch);
+        locked.root_node_keys.extend(batch.root_node_keys);
+        Ok(())
+    }
+
+    /// Removes the nodes that became stale at or before `least_readable_version` together with
+    /// their indices and the entries of the root hash index pointing at them, so that versions older than `least_readable_version` may not be readable
+    /// anymore. Nothing is removed if an index refers to a node that doesn't exist.
+    pub fn purge_stale_nodes(&self, least_readable_version: Version) -> Result<()> {
+        self.write_faults.check("purge_stale_nodes")?;
+        let mut wlocked = self.contents.write().unwrap();
+
+        // Only records retired before or at `least_readable_version` can be purged in order
+        // to keep that version still readable.
+        let to_prune = wlocked
+            .stale_node_indices
+            .iter()
+            .take_while(|log| log.stale_since_version <= least_readable_version)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for log in &to_prune {
+            ensure!(
+                wlocked.nodes.contains_key(&log.node_key),
+                "Stale node index refers to non-existent node {:?}.",
+                log.node_key
+            );
+        }
+        for log in to_prune {
+            let node = wlocked.nodes.remove(&log.node_key).expect("Checked above.");
+            let root_hash = node.hash();
+            if wlocked.root_node_keys.get(&root_hash) == Some(&log.node_key) {
+                wlocked.root_node_keys.remove(&root_hash);
+            }
+            wlocked.stale_node_indices.remove(&log);
+        }
+
+        Ok(())
+    }
+
+    pub fn num_nodes(&self) -> usize {
+        self.contents.read().unwrap().nodes.len()
+    }
+
+    /// Number of stale node indices not purged yet.
+    pub fn num_stale_node_indices(&self) -> usize {
+        self.contents.read().unwrap().stale_node_indices.len()
+    }
+
+    /// Makes the `n`th read from now on fail, counting from 1. Only one read can be set to fail
+    /// at a time.
+    pub fn fail_nth_read(&self, n: usize) {
+        self.read_faults.arm(n);
+    }
+
+    /// Makes the `n`th write from now on fail without changing the store, counting from 1. Only
+    /// one write can be set to fail at a time.
+    pub fn fail_nth_write(&self, n: usize) {
+        self.write_faults.arm(n);
+    }
+
+    /// Cancels the failures set up by [`fail_nth_read`](#method.fail_nth_read) and
+    /// [`fail_nth_write`](#method.fail_nth_write) that didn't happen yet.
+    pub fn clear_faults(&self) {
+        self.read_faults.disarm();
+        self.write_faults.disarm();
+    }
+}
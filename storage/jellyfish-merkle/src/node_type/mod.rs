@@ -13,7 +13,14 @@
 #[cfg(test)]
 mod node_type_test;
 
-use crate::nibble_path::NibblePath;
+use crate::{
+    nibble_path::NibblePath,
+    proof_helpers::{
+        child_bit, children_in_order, generate_bitmaps, get_collapsed_child, proof_levels,
+        range_bitmaps,
+    },
+    ROOT_NIBBLE_HEIGHT,
+};
 use bincode::{deserialize, serialize};
 use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
 use failure::{Fail, Result, *};
@@ -111,6 +118,12 @@ impl NodeKey {
         let mut reader = Cursor::new(val);
         let version = reader.read_u64::<BigEndian>()?;
         let num_nibbles = reader.read_u8()? as usize;
+        ensure!(
+            num_nibbles <= ROOT_NIBBLE_HEIGHT,
+            "encoded num_nibbles {} exceeds the maximum of {}",
+            num_nibbles,
+            ROOT_NIBBLE_HEIGHT
+        );
         let mut nibble_bytes = Vec::with_capacity((num_nibbles + 1) / 2);
         reader.read_to_end(&mut nibble_bytes)?;
         ensure!(
@@ -122,6 +135,11 @@ impl NodeKey {
         let nibble_path = if num_nibbles % 2 == 0 {
             NibblePath::new(nibble_bytes)
         } else {
+            ensure!(
+                nibble_bytes.last().map_or(false, |byte| byte & 0x0f == 0),
+                "odd nibble path bytes {:?} not padded with a 0 nibble",
+                nibble_bytes
+            );
             NibblePath::new_odd(nibble_bytes)
         };
         Ok(NodeKey::new(version, nibble_path))
@@ -262,15 +280,13 @@ impl InternalNode {
     }
 
     pub fn serialize(&self, binary: &mut Vec<u8>) -> Result<()> {
-        let (mut existence_bitmap, leaf_bitmap) = self.generate_bitmaps();
+        let (existence_bitmap, leaf_bitmap) = self.generate_bitmaps();
         binary.write_u16::<LittleEndian>(existence_bitmap)?;
         binary.write_u16::<LittleEndian>(leaf_bitmap)?;
-        for _ in 0..existence_bitmap.count_ones() {
-            let next_child = existence_bitmap.trailing_zeros() as u8;
-            let child = &self.children[&Nibble::from(next_child)];
+        for n in children_in_order(existence_bitmap) {
+            let child = &self.children[&n];
             serialize_u64_varint(child.version, binary);
             binary.extend(child.hash.to_vec());
-            existence_bitmap &= !(1 << next_child);
         }
         Ok(())
     }
@@ -280,7 +296,7 @@ impl InternalNode {
         let len = data.len();
 
         // Read and validate existence and leaf bitmaps
-        let mut existence_bitmap = reader.read_u16::<LittleEndian>()?;
+        let existence_bitmap = reader.read_u16::<LittleEndian>()?;
         let leaf_bitmap = reader.read_u16::<LittleEndian>()?;
         match existence_bitmap {
             0 => return Err(NodeDecodeError::NoChildren.into()),
@@ -296,8 +312,7 @@ impl InternalNode {
 
         // Reconstruct children
         let mut children = HashMap::new();
-        for _ in 0..existence_bitmap.count_ones() {
-            let next_child = existence_bitmap.trailing_zeros() as u8;
+        for n in children_in_order(existence_bitmap) {
             let version = deserialize_u64_varint(&mut reader)?;
             let pos = reader.position() as usize;
             let remaining = len - pos;
@@ -307,19 +322,16 @@ impl InternalNode {
                 existence_bitmap.count_ones(),
                 remaining
             );
-            let child_bit = 1 << next_child;
             children.insert(
-                Nibble::from(next_child),
+                n,
                 Child::new(
                     HashValue::from_slice(&reader.get_ref()[pos..pos + size_of::<HashValue>()])?,
                     version,
-                    (leaf_bitmap & child_bit) != 0,
+                    (leaf_bitmap & child_bit(n)) != 0,
                 ),
             );
             reader.seek(SeekFrom::Current(size_of::<HashValue>() as i64))?;
-            existence_bitmap &= !child_bit;
         }
-        assert_eq!(existence_bitmap, 0);
         Ok(Self { children })
     }
 
@@ -337,29 +349,7 @@ impl InternalNode {
     /// exists if `existence_bitmap[i]` is set; child at index `i` is leaf node if
     /// `leaf_bitmap[i]` is set.
     pub fn generate_bitmaps(&self) -> (u16, u16) {
-        let mut existence_bitmap = 0;
-        let mut leaf_bitmap = 0;
-        for (nibble, child) in self.children.iter() {
-            let i = u8::from(*nibble);
-            existence_bitmap |= 1u16 << i;
-            leaf_bitmap |= (child.is_leaf as u16) << i;
-        }
-        // `leaf_bitmap` must be a subset of `existence_bitmap`.
-        assert_eq!(existence_bitmap | leaf_bitmap, existence_bitmap);
-        (existence_bitmap, leaf_bitmap)
-    }
-
-    /// Given a range [start, start + width), returns the sub-bitmap of that range.
-    fn range_bitmaps(start: u8, width: u8, bitmaps: (u16, u16)) -> (u16, u16) {
-        assert!(start < 16 && width.count_ones() == 1 && start % width == 0);
-        // A range with `start == 8` and `width == 4` will generate a mask 0b0000111100000000.
-        let mask = if width == 16 {
-            0xffff
-        } else {
-            assert!(width <= 16);
-            (1 << width) - 1
-        } << start;
-        (bitmaps.0 & mask, bitmaps.1 & mask)
+        generate_bitmaps(&self.children)
     }
 
     fn merkle_hash(
@@ -369,15 +359,12 @@ impl InternalNode {
         (existence_bitmap, leaf_bitmap): (u16, u16),
     ) -> HashValue {
         // Given a bit [start, 1 << nibble_height], return the value of that range.
-        let (range_existence_bitmap, range_leaf_bitmap) =
-            Self::range_bitmaps(start, width, (existence_bitmap, leaf_bitmap));
-        if range_existence_bitmap == 0 {
+        let bitmaps_in_range = range_bitmaps(start, width, (existence_bitmap, leaf_bitmap));
+        if bitmaps_in_range.0 == 0 {
             // No child under this subtree
             *SPARSE_MERKLE_PLACEHOLDER_HASH
-        } else if range_existence_bitmap.count_ones() == 1 && (range_leaf_bitmap != 0 || width == 1)
-        {
+        } else if let Some(only_child_index) = get_collapsed_child(bitmaps_in_range, width) {
             // Only 1 leaf child under this subtree or reach the lowest level
-            let only_child_index = Nibble::from(range_existence_bitmap.trailing_zeros() as u8);
             self.child(only_child_index)
                 .unwrap_or_else(|| {
                     panic!(
@@ -427,32 +414,30 @@ impl InternalNode {
         let (existence_bitmap, leaf_bitmap) = self.generate_bitmaps();
 
         // Nibble height from 3 to 0.
-        for h in (0..4).rev() {
-            // Get the number of children of the internal node that each subtree at this height
-            // covers.
-            let width = 1 << h;
-            let (child_half_start, sibling_half_start) = get_child_and_sibling_half_start(n, h);
+        for level in proof_levels(n) {
             // Compute the root hash of the subtree rooted at the sibling of `r`.
             siblings.push(self.merkle_hash(
-                sibling_half_start,
-                width,
+                level.sibling_half_start,
+                level.width,
                 (existence_bitmap, leaf_bitmap),
             ));
 
-            let (range_existence_bitmap, range_leaf_bitmap) =
-                Self::range_bitmaps(child_half_start, width, (existence_bitmap, leaf_bitmap));
+            let bitmaps_in_range = range_bitmaps(
+                level.child_half_start,
+                level.width,
+                (existence_bitmap, leaf_bitmap),
+            );
 
-            if range_existence_bitmap == 0 {
+            if bitmaps_in_range.0 == 0 {
                 // No child in this range.
                 return (None, siblings);
-            } else if range_existence_bitmap.count_ones() == 1
-                && (range_leaf_bitmap.count_ones() == 1 || width == 1)
+            } else if let Some(only_child_index) =
+                get_collapsed_child(bitmaps_in_range, level.width)
             {
                 // Return the only 1 leaf child under this subtree or reach the lowest level
                 // Even this leaf child is not the n-th child, it should be returned instead of
                 // `None` because it's existence indirectly proves the n-th child doesn't exist.
                 // Please read proof format for details.
-                let only_child_index = Nibble::from(range_existence_bitmap.trailing_zeros() as u8);
                 return (
                     {
                         let only_child_version = self
@@ -475,21 +460,6 @@ impl InternalNode {
     }
 }
 
-/// Given a nibble, computes the start position of its `child_half_start` and `sibling_half_start`
-/// at `height` level.
-pub(crate) fn get_child_and_sibling_half_start(n: Nibble, height: u8) -> (u8, u8) {
-    // Get the index of the first child belonging to the same subtree whose root, let's say `r` is
-    // at `height` that the n-th child belongs to.
-    // Note: `child_half_start` will be always equal to `n` at height 0.
-    let child_half_start = (0xff << height) & u8::from(n);
-
-    // Get the index of the first child belonging to the subtree whose root is the sibling of `r`
-    // at `height`.
-    let sibling_half_start = child_half_start ^ (1 << height);
-
-    (child_half_start, sibling_half_start)
-}
-
 /// Represents an account.
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, CryptoHasher)]
 pub struct LeafNode {
@@ -557,6 +527,22 @@ pub enum Node {
     Leaf(LeafNode),
 }
 
+#[cfg(any(test, feature = "fuzzing"))]
+impl Arbitrary for Node {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Self>;
+
+    fn arbitrary_with(_args: ()) -> Self::Strategy {
+        prop_oneof![
+            Just(Node::Null),
+            any::<InternalNode>().prop_map(Node::Internal),
+            (any::<HashValue>(), any::<AccountStateBlob>())
+                .prop_map(|(account_key, blob)| Node::new_leaf(account_key, blob)),
+        ]
+        .boxed()
+    }
+}
+
 impl From<InternalNode> for Node {
     fn from(node: InternalNode) -> Self {
         Node::Internal(node)
@@ -82,6 +82,24 @@ fn test_encode_decode() {
     }
 }
 
+#[test]
+fn test_node_key_decode_invalid_nibble_path() {
+    let node_key = random_63nibbles_node_key();
+    let encoded = node_key.encode().unwrap();
+    assert_eq!(NodeKey::decode(&encoded).unwrap(), node_key);
+
+    // More nibbles than the tree is high.
+    let mut encoded = 0u64.to_be_bytes().to_vec();
+    encoded.push(255);
+    encoded.extend(vec![0u8; 128]);
+    assert!(NodeKey::decode(&encoded).is_err());
+
+    // Odd number of nibbles, but the padding nibble is not 0.
+    let mut encoded = 0u64.to_be_bytes().to_vec();
+    encoded.extend(vec![1, 0x11]);
+    assert!(NodeKey::decode(&encoded).is_err());
+}
+
 proptest! {
     #[test]
     fn test_u64_varint_roundtrip(input in any::<u64>()) {
@@ -96,6 +114,16 @@ proptest! {
         input.serialize(&mut vec).unwrap();
         assert_eq!(InternalNode::deserialize(&vec).unwrap(), input);
     }
+
+    #[test]
+    fn test_node_roundtrip(input in any::<Node>()) {
+        assert_eq!(Node::decode(&input.encode().unwrap()).unwrap(), input);
+    }
+
+    #[test]
+    fn test_node_key_roundtrip(input in any::<NodeKey>()) {
+        assert_eq!(NodeKey::decode(&input.encode().unwrap()).unwrap(), input);
+    }
 }
 
 #[test]
@@ -0,0 +1,154 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Helpers interpreting the children of an [`InternalNode`], as used to build its hash and the
+//! [`SparseMerkleProof`]s going through it.
+//!
+//! An internal node stands for a binary tree of height [`INTERNAL_NODE_HEIGHT`] whose
+//! [`NUM_CHILDREN`] bottom positions are indexed by nibbles, `0` being the leftmost one. Its
+//! children are described by two bitmaps where bit `i` stands for the child at nibble `i`:
+//! the existence bitmap has the bits of the children present set, the leaf bitmap those of the
+//! children which are leaves, always a subset of the existence bitmap.
+//!
+//! A range of the children with only one child present, which is a leaf or alone at the bottom
+//! level, is represented by that child directly in the tree, see [`get_collapsed_child`]. A proof
+//! for the child at nibble `n` has one sibling per level of the internal node, top to bottom, see
+//! [`proof_levels`], until the range containing `n` collapses. The siblings of all the internal
+//! nodes on the path are finally reversed, as a [`SparseMerkleProof`] lists them bottom to top.
+//!
+//! [`InternalNode`]: ../node_type/struct.InternalNode.html
+//! [`SparseMerkleProof`]: ../../libra_types/proof/struct.SparseMerkleProof.html
+//! [`INTERNAL_NODE_HEIGHT`]: constant.INTERNAL_NODE_HEIGHT.html
+//! [`NUM_CHILDREN`]: constant.NUM_CHILDREN.html
+//! [`get_collapsed_child`]: fn.get_collapsed_child.html
+//! [`proof_levels`]: fn.proof_levels.html
+
+#[cfg(test)]
+mod proof_helpers_test;
+
+use crate::node_type::Children;
+use libra_nibble::Nibble;
+
+/// The number of children an internal node can have.
+pub const NUM_CHILDREN: usize = 16;
+
+/// The height of the binary tree an internal node stands for.
+pub const INTERNAL_NODE_HEIGHT: u8 = 4;
+
+/// Converts the index of a child, from `0` to `15`, into the nibble it is found at.
+pub fn child_index_to_nibble(index: u8) -> Nibble {
+    assert!(
+        (index as usize) < NUM_CHILDREN,
+        "child index {} out of range",
+        index
+    );
+    Nibble::from(index)
+}
+
+/// The bit standing for the child at nibble `n` in the bitmaps.
+pub fn child_bit(n: Nibble) -> u16 {
+    1 << u8::from(n)
+}
+
+/// Generates `existence_bitmap` and `leaf_bitmap` of `children` as a pair of `u16`s: the child at
+/// index `i` exists if `existence_bitmap[i]` is set and is a leaf if `leaf_bitmap[i]` is set.
+pub fn generate_bitmaps(children: &Children) -> (u16, u16) {
+    let mut existence_bitmap = 0;
+    let mut leaf_bitmap = 0;
+    for (nibble, child) in children.iter() {
+        existence_bitmap |= child_bit(*nibble);
+        if child.is_leaf {
+            leaf_bitmap |= child_bit(*nibble);
+        }
+    }
+    // `leaf_bitmap` must be a subset of `existence_bitmap`.
+    assert_eq!(existence_bitmap | leaf_bitmap, existence_bitmap);
+    (existence_bitmap, leaf_bitmap)
+}
+
+/// Iterates over the nibbles of the children present in `existence_bitmap`, from left to right,
+/// which is the order the children are serialized in.
+pub fn children_in_order(existence_bitmap: u16) -> impl Iterator<Item = Nibble> {
+    (0..NUM_CHILDREN as u8)
+        .filter(move |index| existence_bitmap & (1 << index) != 0)
+        .map(child_index_to_nibble)
+}
+
+/// Given a range `[start, start + width)`, returns the sub-bitmaps of that range. `width` must be
+/// a power of two and `start` a multiple of it, i.e. the range must be a subtree of the node.
+pub fn range_bitmaps(start: u8, width: u8, bitmaps: (u16, u16)) -> (u16, u16) {
+    assert!(start < 16 && width.count_ones() == 1 && start % width == 0);
+    // A range with `start == 8` and `width == 4` will generate a mask 0b0000111100000000.
+    let mask = if width == 16 {
+        0xffff
+    } else {
+        assert!(width <= 16);
+        (1 << width) - 1
+    } << start;
+    (bitmaps.0 & mask, bitmaps.1 & mask)
+}
+
+/// Given the sub-bitmaps of a range of `width` children, returns the only child standing for the
+/// whole range, if any: the range holds a single child, and it is a leaf or `width` is 1. Such a
+/// range hashes to the hash of that child, and proofs stop descending into it.
+pub fn get_collapsed_child(
+    (range_existence_bitmap, range_leaf_bitmap): (u16, u16),
+    width: u8,
+) -> Option<Nibble> {
+    if range_existence_bitmap.count_ones() == 1 && (range_leaf_bitmap != 0 || width == 1) {
+        Some(child_index_to_nibble(
+            range_existence_bitmap.trailing_zeros() as u8,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Given a nibble, computes the start position of its `child_half_start` and `sibling_half_start`
+/// at `height` level.
+pub fn get_child_and_sibling_half_start(n: Nibble, height: u8) -> (u8, u8) {
+    // Get the index of the first child belonging to the same subtree whose root, let's say `r` is
+    // at `height` that the n-th child belongs to.
+    // Note: `child_half_start` will be always equal to `n` at height 0.
+    let child_half_start = (0xff << height) & u8::from(n);
+
+    // Get the index of the first child belonging to the subtree whose root is the sibling of `r`
+    // at `height`.
+    let sibling_half_start = child_half_start ^ (1 << height);
+
+    (child_half_start, sibling_half_start)
+}
+
+/// One level of the path to a child within an internal node.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ProofLevel {
+    /// Height of the subtrees split at this level, from `INTERNAL_NODE_HEIGHT - 1` down to 0.
+    pub height: u8,
+    /// Number of children covered by each of the two subtrees, `1 << height`.
+    pub width: u8,
+    /// First child of the subtree the path continues into.
+    pub child_half_start: u8,
+    /// First child of the other subtree, whose hash is the sibling at this level.
+    pub sibling_half_start: u8,
+}
+
+impl ProofLevel {
+    /// Whether the path continues into the right subtree, the sibling being on the left.
+    pub fn is_right(&self) -> bool {
+        self.child_half_start > self.sibling_half_start
+    }
+}
+
+/// The levels of the path to the child at nibble `n`, top to bottom, in the order the siblings
+/// of a proof going through the node are generated.
+pub fn proof_levels(n: Nibble) -> impl Iterator<Item = ProofLevel> {
+    (0..INTERNAL_NODE_HEIGHT).rev().map(move |height| {
+        let (child_half_start, sibling_half_start) = get_child_and_sibling_half_start(n, height);
+        ProofLevel {
+            height,
+            width: 1 << height,
+            child_half_start,
+            sibling_half_start,
+        }
+    })
+}
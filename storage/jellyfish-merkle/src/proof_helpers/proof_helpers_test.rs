@@ -0,0 +1,270 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    mock_tree_store::MockTreeStore,
+    node_type::{Child, InternalNode, NodeKey},
+    JellyfishMerkleTree,
+};
+use libra_crypto::{hash::CryptoHash, HashValue};
+use libra_types::{
+    account_state_blob::AccountStateBlob,
+    proof::{SparseMerkleInternalNode, SparseMerkleLeafNode},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::collections::HashMap;
+
+/// Leaf bitmaps tried along with each existence bitmap: none, all and two halves of the children.
+fn leaf_bitmaps(existence_bitmap: u16) -> Vec<u16> {
+    vec![
+        0,
+        existence_bitmap,
+        existence_bitmap & 0x5555,
+        existence_bitmap & 0xf0f0,
+    ]
+}
+
+/// The ranges of children a subtree of an internal node can cover, as `(start, width)`.
+fn subtree_ranges() -> Vec<(u8, u8)> {
+    let mut ranges = vec![];
+    for height in 0..=INTERNAL_NODE_HEIGHT {
+        let width = 1 << height;
+        for start in (0..NUM_CHILDREN as u8).step_by(width as usize) {
+            ranges.push((start, width));
+        }
+    }
+    ranges
+}
+
+fn children(existence_bitmap: u16, leaf_bitmap: u16) -> Children {
+    children_in_order(existence_bitmap)
+        .map(|n| {
+            let is_leaf = leaf_bitmap & child_bit(n) != 0;
+            (n, Child::new(HashValue::random(), 0, is_leaf))
+        })
+        .collect()
+}
+
+#[test]
+fn test_child_index_to_nibble() {
+    for index in 0..NUM_CHILDREN as u8 {
+        let n = child_index_to_nibble(index);
+        assert_eq!(u8::from(n), index);
+        assert_eq!(child_bit(n), 1 << index);
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_child_index_to_nibble_out_of_range() {
+    child_index_to_nibble(NUM_CHILDREN as u8);
+}
+
+#[test]
+fn test_children_in_order() {
+    for existence_bitmap in 0..=u16::max_value() {
+        let nibbles: Vec<_> = children_in_order(existence_bitmap).map(u8::from).collect();
+        assert_eq!(nibbles.len(), existence_bitmap.count_ones() as usize);
+        assert!(nibbles.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(
+            nibbles.iter().fold(0, |bitmap, index| bitmap | 1 << index),
+            existence_bitmap
+        );
+    }
+}
+
+#[test]
+fn test_generate_bitmaps() {
+    for existence_bitmap in 0..=u16::max_value() {
+        for leaf_bitmap in leaf_bitmaps(existence_bitmap) {
+            assert_eq!(
+                generate_bitmaps(&children(existence_bitmap, leaf_bitmap)),
+                (existence_bitmap, leaf_bitmap)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_range_bitmaps() {
+    let ranges = subtree_ranges();
+    for existence_bitmap in 0..=u16::max_value() {
+        let leaf_bitmap = existence_bitmap & 0x5555;
+        for &(start, width) in &ranges {
+            let (range_existence_bitmap, range_leaf_bitmap) =
+                range_bitmaps(start, width, (existence_bitmap, leaf_bitmap));
+            for index in 0..NUM_CHILDREN as u8 {
+                let bit = 1 << index;
+                let in_range = index >= start && index < start + width;
+                assert_eq!(
+                    range_existence_bitmap & bit != 0,
+                    in_range && existence_bitmap & bit != 0
+                );
+                assert_eq!(
+                    range_leaf_bitmap & bit != 0,
+                    in_range && leaf_bitmap & bit != 0
+                );
+            }
+        }
+    }
+}
+
+#[test]
+#[should_panic]
+fn test_range_bitmaps_unaligned() {
+    range_bitmaps(2, 4, (0xffff, 0));
+}
+
+#[test]
+fn test_get_collapsed_child() {
+    let ranges = subtree_ranges();
+    for existence_bitmap in 0..=u16::max_value() {
+        for leaf_bitmap in leaf_bitmaps(existence_bitmap) {
+            for &(start, width) in &ranges {
+                let in_range: Vec<_> = children_in_order(existence_bitmap)
+                    .filter(|n| u8::from(*n) >= start && u8::from(*n) < start + width)
+                    .collect();
+                let expected = match in_range.as_slice() {
+                    [n] if width == 1 || leaf_bitmap & child_bit(*n) != 0 => Some(*n),
+                    _ => None,
+                };
+                assert_eq!(
+                    get_collapsed_child(
+                        range_bitmaps(start, width, (existence_bitmap, leaf_bitmap)),
+                        width
+                    ),
+                    expected
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_proof_levels() {
+    for index in 0..NUM_CHILDREN as u8 {
+        let levels: Vec<_> = proof_levels(child_index_to_nibble(index)).collect();
+        assert_eq!(
+            levels.iter().map(|level| level.height).collect::<Vec<_>>(),
+            vec![3, 2, 1, 0]
+        );
+        let mut parent = (0, NUM_CHILDREN as u8);
+        for level in &levels {
+            assert_eq!(level.width, 1 << level.height);
+            // the path goes into the half of the parent range holding the child
+            assert!(
+                index >= level.child_half_start && index < level.child_half_start + level.width
+            );
+            // and the sibling is the other half
+            let left_half_start = std::cmp::min(level.child_half_start, level.sibling_half_start);
+            assert_eq!(left_half_start, parent.0);
+            assert_eq!(2 * level.width, parent.1);
+            assert_eq!(
+                std::cmp::max(level.child_half_start, level.sibling_half_start),
+                parent.0 + level.width
+            );
+            assert_eq!(level.is_right(), index & level.width != 0);
+            parent = (level.child_half_start, level.width);
+        }
+        assert_eq!(parent, (index, 1));
+    }
+}
+
+#[test]
+fn test_internal_node_proofs_follow_proof_levels() {
+    // every 97th configuration, with every child queried
+    for existence_bitmap in (1..=u16::max_value()).step_by(97) {
+        for leaf_bitmap in leaf_bitmaps(existence_bitmap) {
+            if existence_bitmap.count_ones() == 1 && leaf_bitmap != 0 {
+                // a single leaf is never wrapped in an internal node
+                continue;
+            }
+            let node = InternalNode::new(children(existence_bitmap, leaf_bitmap));
+            let node_key = NodeKey::new_empty_path(0);
+            for index in 0..NUM_CHILDREN as u8 {
+                let n = child_index_to_nibble(index);
+                let (child_key, siblings) = node.get_child_with_siblings(&node_key, n);
+                // the proof stops at the first level whose range collapses or is empty
+                let num_levels = proof_levels(n)
+                    .position(|level| {
+                        let bitmaps = range_bitmaps(
+                            level.child_half_start,
+                            level.width,
+                            (existence_bitmap, leaf_bitmap),
+                        );
+                        bitmaps.0 == 0 || get_collapsed_child(bitmaps, level.width).is_some()
+                    })
+                    .unwrap()
+                    + 1;
+                assert_eq!(siblings.len(), num_levels);
+                let child = child_key
+                    .map(|key| key.nibble_path().last().unwrap())
+                    .map(u8::from);
+                let expected_child = proof_levels(n).nth(num_levels - 1).and_then(|level| {
+                    get_collapsed_child(
+                        range_bitmaps(
+                            level.child_half_start,
+                            level.width,
+                            (existence_bitmap, leaf_bitmap),
+                        ),
+                        level.width,
+                    )
+                });
+                assert_eq!(child, expected_child.map(u8::from));
+            }
+        }
+    }
+}
+
+/// Recomputes the root hash of a tree from a proof of `key`, the way an external verifier would:
+/// the path to the leaf follows the levels of the internal nodes, each consuming a nibble of
+/// `key`, and the siblings of the proof are listed bottom to top.
+fn reconstruct_root(key: HashValue, leaf_hash: HashValue, siblings: &[HashValue]) -> HashValue {
+    let goes_right: Vec<bool> = (0..HashValue::LENGTH * 2)
+        .flat_map(|i| proof_levels(key.get_nibble(i)))
+        .map(|level| level.is_right())
+        .take(siblings.len())
+        .collect();
+    siblings
+        .iter()
+        .zip(goes_right.iter().rev())
+        .fold(leaf_hash, |hash, (sibling, is_right)| {
+            if *is_right {
+                SparseMerkleInternalNode::new(*sibling, hash).hash()
+            } else {
+                SparseMerkleInternalNode::new(hash, *sibling).hash()
+            }
+        })
+}
+
+#[test]
+fn test_reconstruct_root_from_proof() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let mut rng: StdRng = SeedableRng::from_seed([0; 32]);
+    let blob_set: HashMap<_, _> = (0..100u8)
+        .map(|i| {
+            (
+                HashValue::random_with_rng(&mut rng),
+                AccountStateBlob::from(vec![i]),
+            )
+        })
+        .collect();
+    let (root_hash, batch) = tree
+        .put_blob_set(blob_set.clone().into_iter().collect(), 0 /* version */)
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    for (key, blob) in &blob_set {
+        let (found_blob, proof) = tree.get_with_proof(*key, 0).unwrap();
+        assert_eq!(found_blob.as_ref(), Some(blob));
+        let (leaf_key, blob_hash) = proof.leaf().unwrap();
+        assert_eq!(leaf_key, *key);
+        let leaf_hash = SparseMerkleLeafNode::new(leaf_key, blob_hash).hash();
+        assert_eq!(
+            reconstruct_root(*key, leaf_hash, proof.siblings()),
+            root_hash
+        );
+    }
+}
@@ -9,9 +9,8 @@ mod restore_test;
 
 use crate::{
     nibble_path::{NibbleIterator, NibblePath},
-    node_type::{
-        get_child_and_sibling_half_start, Child, Children, InternalNode, LeafNode, Node, NodeKey,
-    },
+    node_type::{Child, Children, InternalNode, LeafNode, Node, NodeKey},
+    proof_helpers::get_child_and_sibling_half_start,
     NodeBatch, TreeReader, TreeWriter, ROOT_NIBBLE_HEIGHT,
 };
 use failure::prelude::*;
@@ -572,7 +571,7 @@ where
 
         ensure!(
             current_hash == self.expected_root_hash,
-            "Root hashes do not match. Actual root hash: {:x}. Expected root hash: {:x}.",
+            "Root hashes do not match. Actual root hash: {:#}. Expected root hash: {:#}.",
             current_hash,
             self.expected_root_hash,
         );
@@ -642,14 +641,20 @@ where
                 if let Some(node) = leaf {
                     let node_key = NodeKey::new_empty_path(self.version);
                     assert!(self.frozen_nodes.is_empty());
-                    self.frozen_nodes.insert(node_key, node.into());
+                    self.frozen_nodes.insert(node_key.clone(), node.into());
                     self.store.write_node_batch(&self.frozen_nodes)?;
-                    return Ok(());
+                    return self
+                        .store
+                        .write_root_node_key(self.expected_root_hash, &node_key);
                 }
             }
         }
 
         self.freeze(0);
-        self.store.write_node_batch(&self.frozen_nodes)
+        self.store.write_node_batch(&self.frozen_nodes)?;
+        self.store.write_root_node_key(
+            self.expected_root_hash,
+            &NodeKey::new_empty_path(self.version),
+        )
     }
 }
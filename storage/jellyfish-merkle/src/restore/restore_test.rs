@@ -99,6 +99,10 @@ fn assert_success(
     let tree = JellyfishMerkleTree::new(db);
     for (key, value) in btree {
         assert_eq!(tree.get(*key, version).unwrap(), Some(value.clone()));
+        let (blob, _proof) = tree
+            .get_with_proof_by_root(expected_root_hash, *key)
+            .unwrap();
+        assert_eq!(blob, Some(value.clone()));
     }
 
     let actual_root_hash = tree.get_root_hash(version).unwrap();
@@ -65,30 +65,89 @@
 //!      anything. Otherwise we delete it from the tree cache.
 //! Updating node could be operated as deletion of the node followed by insertion of the updated
 //! node.
+//!
+//! Rather than putting and deleting nodes one by one, callers can hand a whole set of leaf updates
+//! to `apply_updates`, which walks the tree once for all of them, putting the new nodes and
+//! deleting the stale ones, and returns the new root hash.
+//!
+//! `pending_digest` hashes all the changes staged in the cache into a digest, for caches built
+//! independently, e.g. on different nodes, to tell cheaply whether they staged the same batch.
+//!
+//! Mutations of the mutable part of the cache can be undone back to a `Savepoint`: once
+//! `savepoint` is called, every `put_node` and `delete_node` is recorded in an undo log, including
+//! the value of a deleted node that only lived in the cache, until the next `freeze`.
+//!
+//! With `verify_on_put` set, `put_node` rejects a leaf whose account key does not start with the
+//! nibble path of the key it is put under, and `put_node_with_hash` additionally checks the hash
+//! of the node against the one the caller expects. This is meant as a safeguard in debug builds
+//! and tests against inserting a node under the wrong key.
+//!
+//! Batches built in parallel by several caches, each updating keys in a range disjoint from the
+//! others on top of the same tree, can be combined with `TreeCache::merge_shards`. The shards only
+//! share the internal nodes above their subtrees, which are recomputed from the children written by
+//! all of them.
+//!
+//! Once `count_leaves` is called with the number of leaves of the tree the cache starts from, the
+//! number of leaves of each frozen version is kept up to date from the leaves the version added
+//! and made stale, and can be read back with `frozen_leaf_count`.
+//!
+//! A cache constructed with `pool_nodes` keeps the children maps of the internal nodes retired
+//! from the mutable cache in a pool, and builds new internal nodes in them rather than in freshly
+//! allocated ones, which spares the allocator during large batches of updates.
+//!
+//! The cache reads through to its reader via any handle that dereferences to it. `TreeCache`
+//! borrows the reader, which costs nothing for synchronous use, while `OwnedTreeCache` owns an
+//! `Arc` of it so that the cache is `'static` and can be held across `.await` points in async
+//! tasks.
 
 #[cfg(test)]
 mod tree_cache_test;
 
 use crate::{
-    node_type::{Node, NodeKey},
-    StaleNodeIndex, TreeReader, TreeUpdateBatch,
+    consistency_proof::ConsistencyProof,
+    nibble_path::NibblePath,
+    node_type::{Child, Children, InternalNode, LeafNode, Node, NodeKey},
+    StaleNodeIndex, TreeReader, TreeUpdateBatch, ROOT_NIBBLE_HEIGHT,
 };
 use failure::prelude::*;
 use libra_crypto::HashValue;
-use libra_types::transaction::Version;
+use libra_nibble::Nibble;
+use libra_types::{
+    account_state_blob::AccountStateBlob, proof::SparseMerkleProof, transaction::Version,
+};
 use std::{
+    cmp::Reverse,
     collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
     convert::Into,
+    ops::Deref,
+    sync::Arc,
 };
 
+/// The subtree at some position once `apply_updates` is done with it.
+enum SubtreeUpdate {
+    /// No leaf is left in the subtree.
+    Empty,
+    /// A single leaf is left in the subtree. It is left to the parent to put it at the right
+    /// position, unless it is the leaf at `existing_key` and stays where it is.
+    Leaf {
+        leaf: LeafNode,
+        existing_key: Option<NodeKey>,
+    },
+    /// The subtree is the given child of its parent, either untouched or rewritten.
+    Child(Child),
+}
+
+/// Maximum number of children maps kept by a `NodePool`.
+const MAX_POOLED_NODES: usize = 1024;
+
 /// `FrozenTreeCache` is used as a field of `TreeCache` storing all the nodes and blobs that are
 /// are generated by earlier transactions so they have to be immutable. The motivation of
 /// `FrozenTreeCache` is to let `TreeCache` freeze intermediate results from each transaction to
 /// help commit more than one transaction in a row atomically.
 #[derive(Default)]
 struct FrozenTreeCache {
-    /// Immutable node_cache.
-    node_cache: BTreeMap<NodeKey, Node>,
+    /// Immutable node_cache, shared with the `FrozenHandle`s taken from it.
+    node_cache: Arc<BTreeMap<NodeKey, Node>>,
 
     /// # of leaves in the `node_cache`,
     num_new_leaves: usize,
@@ -101,11 +160,140 @@ struct FrozenTreeCache {
 
     /// Frozen root hashes after each earlier transaction.
     root_hashes: Vec<HashValue>,
+
+    /// Keys of the frozen root nodes after each earlier transaction, in the order of
+    /// `root_hashes`.
+    root_node_key_list: Vec<NodeKey>,
+
+    /// Keys of the frozen root nodes by root hash. Versions with the same root hash share an
+    /// entry, so versions are looked up in `root_node_key_list` instead.
+    root_node_keys: BTreeMap<HashValue, NodeKey>,
+
+    /// Number of leaves of the tree at each frozen version, if counted.
+    leaf_counts: BTreeMap<Version, usize>,
+}
+
+/// A point-in-time, read-only view of the nodes frozen in a `TreeCache`, taken by
+/// `TreeCache::frozen_handle`. It is cheap to clone and can be sent to other tasks, outliving the
+/// cache it was taken from. Nodes frozen after it was taken are not visible through it.
+#[derive(Clone, Debug, Default)]
+pub struct FrozenHandle {
+    node_cache: Arc<BTreeMap<NodeKey, Node>>,
+}
+
+impl FrozenHandle {
+    /// Number of nodes visible through the handle.
+    pub fn num_nodes(&self) -> usize {
+        self.node_cache.len()
+    }
+}
+
+impl TreeReader for FrozenHandle {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        Ok(self.node_cache.get(node_key).cloned())
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        bail!("Unsupported: frozen handles do not track the rightmost leaf.")
+    }
+}
+
+/// An entry of the undo log, describing how to revert one mutation of the mutable cache.
+enum UndoRecord {
+    /// A node was put into `node_cache`.
+    Put(NodeKey),
+    /// A node that only lived in `node_cache` was deleted, together with its value.
+    RemoveCached(NodeKey, Node),
+    /// An on-disk node was marked stale.
+    MarkStale { node_key: NodeKey, is_leaf: bool },
+}
+
+/// The state of the mutable cache at the time `GenericTreeCache::savepoint` was called.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Savepoint {
+    id: u64,
+    root_node_key: NodeKey,
+    next_version: Version,
+    undo_log_len: usize,
+}
+
+/// How much of the base version of the tree the current version in a `GenericTreeCache` shares.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReuseStats {
+    /// Nodes of the current version created in the cache, i.e. the nodes on the modified paths.
+    pub num_new_nodes: usize,
+    /// Nodes of the base version the new nodes point to. Each of them stands for a whole subtree
+    /// that is shared as is.
+    pub num_reused_nodes: usize,
+}
+
+/// How the node pool of a `GenericTreeCache` was used.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct NodePoolStats {
+    /// Children maps of new internal nodes taken from the pool.
+    pub hits: usize,
+    /// Children maps of new internal nodes allocated because the pool was empty.
+    pub misses: usize,
+    /// Retired internal nodes whose children maps were returned to the pool.
+    pub recycled: usize,
+}
+
+/// Children maps of retired internal nodes, cleared for reuse.
+#[derive(Default)]
+struct NodePool {
+    children: Vec<Children>,
+    stats: NodePoolStats,
+}
+
+impl NodePool {
+    fn take(&mut self) -> Children {
+        match self.children.pop() {
+            Some(children) => {
+                self.stats.hits += 1;
+                children
+            }
+            None => {
+                self.stats.misses += 1;
+                Children::new()
+            }
+        }
+    }
+
+    fn recycle(&mut self, node: Node) {
+        if let Node::Internal(internal_node) = node {
+            if self.children.len() < MAX_POOLED_NODES {
+                let mut children: Children = internal_node.into();
+                children.clear();
+                self.children.push(children);
+                self.stats.recycled += 1;
+            }
+        }
+    }
+
+    fn copy(&mut self, node: &Node) -> Node {
+        match node {
+            Node::Internal(internal_node) => {
+                let mut children = self.take();
+                for i in 0..16u8 {
+                    let nibble = Nibble::from(i);
+                    if let Some(child) = internal_node.child(nibble) {
+                        children.insert(nibble, child.clone());
+                    }
+                }
+                InternalNode::new(children).into()
+            }
+            _ => node.clone(),
+        }
+    }
 }
 
-/// `TreeCache` is a in-memory cache for per-transaction updates of sparse Merkle nodes and value
-/// blobs.
-pub struct TreeCache<'a, R: 'a + TreeReader> {
+/// `GenericTreeCache` is a in-memory cache for per-transaction updates of sparse Merkle nodes and
+/// value blobs, reading through to the reader `D` dereferences to.
+pub struct GenericTreeCache<D>
+where
+    D: Deref,
+    D::Target: TreeReader,
+{
     /// `NodeKey` of the current root node in cache.
     root_node_key: NodeKey,
 
@@ -127,16 +315,49 @@ pub struct TreeCache<'a, R: 'a + TreeReader> {
     /// The immutable part of this cache, which will be committed to the underlying storage.
     frozen_cache: FrozenTreeCache,
 
+    /// Mutations since the oldest live savepoint, `None` if there is no savepoint.
+    undo_log: Option<Vec<UndoRecord>>,
+
+    /// Ids of the live savepoints, in the order they were taken.
+    live_savepoints: Vec<u64>,
+
+    /// Id of the next savepoint, unique for the lifetime of the cache.
+    next_savepoint_id: u64,
+
+    /// Whether nodes are checked against their keys when put.
+    verify_on_put: bool,
+
+    /// Number of leaves of the tree at `next_version - 1`, `None` unless counting was started by
+    /// `count_leaves`.
+    num_leaves: Option<usize>,
+
+    /// Children maps to build new internal nodes in, `None` unless constructed with `pool_nodes`.
+    node_pool: Option<NodePool>,
+
     /// The underlying persistent storage.
-    reader: &'a R,
+    reader: D,
 }
 
-impl<'a, R> TreeCache<'a, R>
+/// A `GenericTreeCache` borrowing its reader.
+pub type TreeCache<'a, R> = GenericTreeCache<&'a R>;
+
+/// A `GenericTreeCache` owning its reader, which makes it `'static`.
+pub type OwnedTreeCache<R> = GenericTreeCache<Arc<R>>;
+
+impl<D> GenericTreeCache<D>
 where
-    R: 'a + TreeReader,
+    D: Deref,
+    D::Target: TreeReader,
 {
-    /// Constructs a new `TreeCache` instance.
-    pub fn new(reader: &'a R, next_version: Version) -> Self {
+    /// Constructs a new `GenericTreeCache` instance on top of the tree at `next_version - 1`, whose
+    /// root key is looked up in `reader`.
+    pub fn new(reader: D, next_version: Version) -> Result<Self> {
+        Self::new_with_node_pool(reader, next_version, false)
+    }
+
+    /// Constructs a new `GenericTreeCache` like `new`. With `pool_nodes` set, the children maps of
+    /// the internal nodes retired from the mutable cache are reused for new ones.
+    pub fn new_with_node_pool(reader: D, next_version: Version, pool_nodes: bool) -> Result<Self> {
         let mut node_cache = HashMap::new();
         let root_node_key = if next_version == 0 {
             // If the first version is 0, it means we need to start from an empty tree so we insert
@@ -144,18 +365,30 @@ where
             node_cache.insert(NodeKey::new_empty_path(0), Node::new_null());
             NodeKey::new_empty_path(0)
         } else {
-            NodeKey::new_empty_path(next_version - 1)
+            reader
+                .get_root_node_key(next_version - 1)?
+                .ok_or_else(|| format_err!("Missing root node at version {}.", next_version - 1))?
         };
-        Self {
+        Ok(Self {
             node_cache,
             stale_node_index_cache: HashSet::new(),
             frozen_cache: FrozenTreeCache::default(),
+            undo_log: None,
+            live_savepoints: vec![],
+            next_savepoint_id: 0,
+            verify_on_put: false,
+            num_leaves: None,
+            node_pool: if pool_nodes {
+                Some(NodePool::default())
+            } else {
+                None
+            },
             root_node_key,
             next_version,
             reader,
             num_stale_leaves: 0,
             num_new_leaves: 0,
-        }
+        })
     }
 
     /// Gets a node with given node key. If it doesn't exist in node cache, read from `reader`.
@@ -169,6 +402,502 @@ where
         })
     }
 
+    /// Gets the key of the root node whose hash is `root_hash`, looking through the versions frozen
+    /// in this cache before the ones in `reader`.
+    pub fn get_root_node_key_by_hash(&self, root_hash: HashValue) -> Result<Option<NodeKey>> {
+        match self.frozen_cache.root_node_keys.get(&root_hash) {
+            Some(node_key) => Ok(Some(node_key.clone())),
+            None => self.reader.get_root_node_key_by_hash(root_hash),
+        }
+    }
+
+    /// Verifies `proof` against the root hash of the current version of the tree: that `key` has
+    /// the blob `value` in the tree, or that `key` is not in the tree if `value` is `None`.
+    pub fn verify_proof(
+        &self,
+        key: HashValue,
+        value: Option<&[u8]>,
+        proof: &SparseMerkleProof,
+    ) -> Result<()> {
+        let root_hash = self.get_node(&self.root_node_key)?.hash();
+        let blob = value.map(|value| AccountStateBlob::from(value.to_vec()));
+        proof.verify(root_hash, key, blob.as_ref())
+    }
+
+    /// Gets the blob of `key` in the current version of the tree, reading through the cache, or
+    /// `None` if `key` is not in the tree. A tree of at most one leaf is answered from its root
+    /// alone.
+    pub fn get_value(&self, key: HashValue) -> Result<Option<AccountStateBlob>> {
+        let leaf_value = |leaf_node: LeafNode| {
+            if leaf_node.account_key() == key {
+                Some(leaf_node.blob().clone())
+            } else {
+                None
+            }
+        };
+        let mut internal_node = match self.get_node(&self.root_node_key)? {
+            Node::Internal(internal_node) => internal_node,
+            Node::Leaf(leaf_node) => return Ok(leaf_value(leaf_node)),
+            Node::Null => return Ok(None),
+        };
+
+        let mut node_key = self.root_node_key.clone();
+        let nibble_path = NibblePath::new(key.to_vec());
+        let mut nibble_iter = nibble_path.nibbles();
+        // Bounded for the same reason as the walk in `JellyfishMerkleTree::get_with_proof`.
+        for _ in 0..ROOT_NIBBLE_HEIGHT {
+            let index = nibble_iter
+                .next()
+                .ok_or_else(|| format_err!("ran out of nibbles"))?;
+            let child_version = match internal_node.child(index) {
+                Some(child) => child.version,
+                None => return Ok(None),
+            };
+            node_key = node_key.gen_child_node_key(child_version, index);
+            internal_node = match self.get_node(&node_key)? {
+                Node::Internal(internal_node) => internal_node,
+                Node::Leaf(leaf_node) => return Ok(leaf_value(leaf_node)),
+                Node::Null => bail!("Non-root null node exists with node key {:?}", node_key),
+            };
+        }
+        bail!("Jellyfish Merkle tree has cyclic graph inside.");
+    }
+
+    /// Gets every node on the path from the current root down to the leaf of `key`, root first and
+    /// the leaf last, reading through the cache. These are the nodes needed to prove the leaf
+    /// without access to the rest of the tree. Fails if `key` is not in the tree.
+    pub fn ancestors_of(&self, key: HashValue) -> Result<Vec<(NodeKey, Node)>> {
+        let path = self.path_from(self.root_node_key.clone(), key)?;
+        match path.last() {
+            Some((_, Node::Leaf(leaf_node))) if leaf_node.account_key() == key => Ok(path),
+            _ => bail!("Key {:#} does not exist in the tree.", key),
+        }
+    }
+
+    /// Estimates the size in bytes of the proof for `key` in the current version of the tree
+    /// without building it, reading only the nodes on the path to `key`. Each internal node on the
+    /// path is counted as a full nibble of sibling hashes and a leaf at the end of the path as its
+    /// account key and blob hash, so the estimate never falls short of the proof and exceeds it by
+    /// at most the three siblings the last internal node may leave out.
+    pub fn estimated_proof_size(&self, key: HashValue) -> Result<usize> {
+        let path = self.path_from(self.root_node_key.clone(), key)?;
+        let num_internal_nodes = path
+            .iter()
+            .filter(|(_, node)| match node {
+                Node::Internal(_) => true,
+                Node::Leaf(_) | Node::Null => false,
+            })
+            .count();
+        let leaf_size = match path.last() {
+            Some((_, Node::Leaf(_))) => 2 * HashValue::LENGTH,
+            _ => 0,
+        };
+        Ok(num_internal_nodes * 4 * HashValue::LENGTH + leaf_size)
+    }
+
+    /// Gets the nodes of the current version of the tree in the top `max_depth` levels, the root
+    /// being the first level, reading through the cache. Nodes are listed level by level, each
+    /// level from left to right. Together with the hashes of the children of the lowest internal
+    /// nodes they hold, they let a receiver check the top of the tree against the root hash without
+    /// the rest of the tree.
+    pub fn export_root_subtree(&self, max_depth: usize) -> Result<Vec<(NodeKey, Node)>> {
+        let mut nodes = vec![];
+        let mut level = vec![self.root_node_key.clone()];
+        for _ in 0..std::cmp::min(max_depth, ROOT_NIBBLE_HEIGHT + 1) {
+            let mut next_level = vec![];
+            for node_key in level {
+                let node = self.get_node(&node_key)?;
+                if let Node::Internal(internal_node) = &node {
+                    for i in 0..16u8 {
+                        let nibble = Nibble::from(i);
+                        if let Some(child) = internal_node.child(nibble) {
+                            next_level.push(node_key.gen_child_node_key(child.version, nibble));
+                        }
+                    }
+                }
+                nodes.push((node_key, node));
+            }
+            if next_level.is_empty() {
+                break;
+            }
+            level = next_level;
+        }
+        Ok(nodes)
+    }
+
+    /// Gets the nodes on the path from the root at `root_node_key` towards the leaf of `key`, root
+    /// first, reading through the cache. The path ends at the leaf `key` would be found at, which
+    /// may hold another key, or at the node without a child where `key` would be.
+    fn path_from(&self, root_node_key: NodeKey, key: HashValue) -> Result<Vec<(NodeKey, Node)>> {
+        let mut path = vec![];
+        let mut next_node_key = root_node_key;
+        let nibble_path = NibblePath::new(key.to_vec());
+        let mut nibble_iter = nibble_path.nibbles();
+
+        // Bounded for the same reason as the walk in `JellyfishMerkleTree::get_with_proof`.
+        for _ in 0..=ROOT_NIBBLE_HEIGHT {
+            let node = self.get_node(&next_node_key)?;
+            let child_node_key = match &node {
+                Node::Internal(internal_node) => {
+                    let index = nibble_iter
+                        .next()
+                        .ok_or_else(|| format_err!("ran out of nibbles"))?;
+                    internal_node
+                        .child(index)
+                        .map(|child| next_node_key.gen_child_node_key(child.version, index))
+                }
+                Node::Leaf(_) | Node::Null => None,
+            };
+            path.push((next_node_key, node));
+            match child_node_key {
+                Some(node_key) => next_node_key = node_key,
+                None => return Ok(path),
+            }
+        }
+        bail!("Jellyfish Merkle tree has cyclic graph inside.");
+    }
+
+    /// Produces a proof that the tree at version `to` is the tree at version `from` with the
+    /// updates made in between applied. Each version is either frozen in this cache or in `reader`.
+    pub fn consistency_proof(&self, from: Version, to: Version) -> Result<ConsistencyProof> {
+        ensure!(
+            from <= to,
+            "Cannot prove version {} consistent with later version {}.",
+            to,
+            from
+        );
+        let from_root_node_key = self.root_node_key_at(from)?;
+        let to_root_node_key = self.root_node_key_at(to)?;
+
+        // The nodes of `to` created after `from` make up the paths to the leaves updated in
+        // between, or moved down by them. The older nodes hanging off these paths are shared with
+        // `from`.
+        let mut to_nodes = vec![];
+        let mut updated_keys = vec![];
+        let mut pending = vec![to_root_node_key];
+        while let Some(node_key) = pending.pop() {
+            if node_key.version() <= from {
+                continue;
+            }
+            let node = self.get_node(&node_key)?;
+            match &node {
+                Node::Internal(internal_node) => {
+                    for i in 0..16u8 {
+                        let nibble = Nibble::from(i);
+                        if let Some(child) = internal_node.child(nibble) {
+                            pending.push(node_key.gen_child_node_key(child.version, nibble));
+                        }
+                    }
+                }
+                Node::Leaf(leaf_node) => updated_keys.push(leaf_node.account_key()),
+                Node::Null => (),
+            }
+            to_nodes.push(node);
+        }
+
+        let mut from_nodes = BTreeMap::new();
+        for key in updated_keys {
+            from_nodes.extend(self.path_from(from_root_node_key.clone(), key)?);
+        }
+        Ok(ConsistencyProof::new(
+            from_nodes.into_iter().map(|(_, node)| node).collect(),
+            to_nodes,
+        ))
+    }
+
+    /// Gets the key of the root node at `version`, frozen in this cache or in `reader`.
+    fn root_node_key_at(&self, version: Version) -> Result<NodeKey> {
+        let num_frozen = self.frozen_cache.root_hashes.len() as Version;
+        let first_frozen_version = self.next_version - num_frozen;
+        if version >= first_frozen_version {
+            ensure!(
+                version < self.next_version,
+                "Version {} is not frozen yet.",
+                version
+            );
+            let index = (version - first_frozen_version) as usize;
+            return Ok(self.frozen_cache.root_node_key_list[index].clone());
+        }
+        self.reader
+            .get_root_node_key(version)?
+            .ok_or_else(|| format_err!("Missing root node at version {}.", version))
+    }
+
+    /// Counts the nodes the current version of the tree creates along the modified paths versus
+    /// the nodes it reuses from the base version in `reader`, which quantifies the structural
+    /// sharing between the two versions.
+    pub fn reuse_stats(&self) -> Result<ReuseStats> {
+        let mut stats = ReuseStats::default();
+        let mut pending = vec![self.root_node_key.clone()];
+        while let Some(node_key) = pending.pop() {
+            let node = match self.get_cached_node(&node_key) {
+                Some(node) => node,
+                None => {
+                    stats.num_reused_nodes += 1;
+                    continue;
+                }
+            };
+            stats.num_new_nodes += 1;
+            if let Node::Internal(internal_node) = node {
+                for i in 0..16u8 {
+                    let nibble = Nibble::from(i);
+                    if let Some(child) = internal_node.child(nibble) {
+                        pending.push(node_key.gen_child_node_key(child.version, nibble));
+                    }
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Digest of all the changes staged in the cache, frozen or not: the keys and hashes of the
+    /// nodes put and the stale node indices, each in order. Caches that staged the same changes on
+    /// top of the same tree have the same digest, whatever order the changes were made in, which
+    /// is cheaper to compare than the batches themselves. The nodes made stale in the mutable
+    /// cache are digested as stale since `next_version`, like `freeze` records them, so freezing
+    /// leaves the digest unchanged.
+    pub fn pending_digest(&self) -> HashValue {
+        let nodes: BTreeMap<&NodeKey, &Node> = self
+            .frozen_cache
+            .node_cache
+            .iter()
+            .chain(self.node_cache.iter())
+            .collect();
+        let stale_node_indices: BTreeSet<StaleNodeIndex> = self
+            .frozen_cache
+            .stale_node_index_cache
+            .iter()
+            .cloned()
+            .chain(
+                self.stale_node_index_cache
+                    .iter()
+                    .map(|node_key| StaleNodeIndex {
+                        stale_since_version: self.next_version,
+                        node_key: node_key.clone(),
+                    }),
+            )
+            .collect();
+
+        // node keys encode their own length, so the concatenation is unambiguous
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&(nodes.len() as u64).to_be_bytes());
+        for (node_key, node) in nodes {
+            bytes.extend(
+                node_key
+                    .encode()
+                    .expect("Encoding a node key should not fail."),
+            );
+            bytes.extend_from_slice(node.hash().as_ref());
+        }
+        bytes.extend_from_slice(&(stale_node_indices.len() as u64).to_be_bytes());
+        for index in stale_node_indices {
+            bytes.extend_from_slice(&index.stale_since_version.to_be_bytes());
+            bytes.extend(
+                index
+                    .node_key
+                    .encode()
+                    .expect("Encoding a node key should not fail."),
+            );
+        }
+        HashValue::from_sha3_256(&bytes)
+    }
+
+    /// Checks that the stale node indices staged in the cache, frozen or not, are consistent with
+    /// the nodes put, as a self-check of the update path. A node can only be made stale by a
+    /// version later than the one creating it, so it has to be either in the tree in `reader` or
+    /// put by an earlier version staged in the cache. In particular, no node can be both put and
+    /// made stale by the same version.
+    pub fn validate_batch(&self) -> Result<()> {
+        let stale_node_indices = self
+            .frozen_cache
+            .stale_node_index_cache
+            .iter()
+            .map(|index| (index.stale_since_version, &index.node_key))
+            .chain(
+                self.stale_node_index_cache
+                    .iter()
+                    .map(|node_key| (self.next_version, node_key)),
+            );
+        for (stale_since_version, node_key) in stale_node_indices {
+            ensure!(
+                node_key.version() < stale_since_version
+                    && (stale_since_version != self.next_version
+                        || !self.node_cache.contains_key(node_key)),
+                "Node {:?} is both put and made stale by version {}.",
+                node_key,
+                stale_since_version,
+            );
+            ensure!(
+                self.frozen_cache.node_cache.contains_key(node_key)
+                    || self.reader.get_node_option(node_key)?.is_some(),
+                "Node {:?} made stale by version {} was never put.",
+                node_key,
+                stale_since_version,
+            );
+        }
+        Ok(())
+    }
+
+    /// Lists the nodes frozen in the cache that no frozen root reaches, in order. Each node put by
+    /// a version is part of the tree at that version, so any such orphan is garbage left by a bug
+    /// in the update path, which would otherwise be written along with the batch. Nodes that are
+    /// not frozen yet are not checked.
+    pub fn find_orphans(&self) -> Result<Vec<NodeKey>> {
+        let frozen_nodes = &self.frozen_cache.node_cache;
+        let mut reachable = HashSet::new();
+        let mut pending = self.frozen_cache.root_node_key_list.clone();
+        while let Some(node_key) = pending.pop() {
+            // nodes outside the frozen cache are in `reader`, and so are their descendants
+            let node = match frozen_nodes.get(&node_key) {
+                Some(node) => node,
+                None => continue,
+            };
+            if !reachable.insert(node_key.clone()) {
+                continue;
+            }
+            if let Node::Internal(internal_node) = node {
+                for i in 0..16u8 {
+                    let nibble = Nibble::from(i);
+                    if let Some(child) = internal_node.child(nibble) {
+                        pending.push(node_key.gen_child_node_key(child.version, nibble));
+                    }
+                }
+            }
+        }
+        Ok(frozen_nodes
+            .keys()
+            .filter(|node_key| !reachable.contains(*node_key))
+            .cloned()
+            .collect())
+    }
+
+    /// Lists the keys whose value differs between the tree in `reader` the cache started from and
+    /// the current version, in order. These are the keys of the leaves in cache that are not
+    /// stale, and of the leaves from `reader` made stale, whose values differ. Leaves only moved
+    /// down the tree by an insertion are rewritten with the same value and are not reported, nor
+    /// are keys put again with the value they had. Removed keys are reported.
+    pub fn modified_keys(&self) -> Result<Vec<HashValue>> {
+        let stale_node_keys: HashSet<&NodeKey> = self
+            .stale_node_index_cache
+            .iter()
+            .chain(
+                self.frozen_cache
+                    .stale_node_index_cache
+                    .iter()
+                    .map(|index| &index.node_key),
+            )
+            .collect();
+
+        let mut old_blob_hashes = BTreeMap::new();
+        for node_key in &stale_node_keys {
+            if self.get_cached_node(node_key).is_some() {
+                continue;
+            }
+            if let Node::Leaf(leaf_node) = self.reader.get_node(node_key)? {
+                old_blob_hashes.insert(leaf_node.account_key(), leaf_node.blob_hash());
+            }
+        }
+        let mut new_blob_hashes = BTreeMap::new();
+        for (node_key, node) in self
+            .node_cache
+            .iter()
+            .chain(self.frozen_cache.node_cache.iter())
+        {
+            if let Node::Leaf(leaf_node) = node {
+                if !stale_node_keys.contains(node_key) {
+                    new_blob_hashes.insert(leaf_node.account_key(), leaf_node.blob_hash());
+                }
+            }
+        }
+
+        let mut keys: BTreeSet<HashValue> = new_blob_hashes
+            .iter()
+            .filter(|(key, blob_hash)| old_blob_hashes.get(key) != Some(blob_hash))
+            .map(|(key, _)| *key)
+            .collect();
+        keys.extend(
+            old_blob_hashes
+                .keys()
+                .filter(|key| !new_blob_hashes.contains_key(key)),
+        );
+        Ok(keys.into_iter().collect())
+    }
+
+    /// Number of nodes the batch writes per leaf whose value it modifies, see `modified_keys`.
+    /// Every modified leaf rewrites the internal nodes on its path from the root, so a leaf deep
+    /// down a sparse path amplifies writes, while leaves sharing their paths amortize them.
+    pub fn write_amplification(&self) -> Result<f64> {
+        let num_modified_leaves = self.modified_keys()?.len();
+        ensure!(num_modified_leaves > 0, "No leaf is modified in the batch.");
+        let num_nodes_written = self.node_cache.len() + self.frozen_cache.node_cache.len();
+        Ok(num_nodes_written as f64 / num_modified_leaves as f64)
+    }
+
+    /// Gets a node like `get_node`, copying a node pending in cache into a pooled children map if
+    /// the node pool is enabled.
+    pub(crate) fn get_node_pooled(&mut self, node_key: &NodeKey) -> Result<Node> {
+        let (node_cache, frozen_node_cache) = (&self.node_cache, &self.frozen_cache.node_cache);
+        let pool = match &mut self.node_pool {
+            Some(pool) => pool,
+            None => return self.get_node(node_key),
+        };
+        match node_cache
+            .get(node_key)
+            .or_else(|| frozen_node_cache.get(node_key))
+        {
+            Some(node) => Ok(pool.copy(node)),
+            None => self.reader.get_node(node_key),
+        }
+    }
+
+    /// Returns an empty children map for a new internal node, taken from the node pool if
+    /// enabled.
+    pub(crate) fn new_children(&mut self) -> Children {
+        match &mut self.node_pool {
+            Some(pool) => pool.take(),
+            None => Children::new(),
+        }
+    }
+
+    /// Copies `node`, into a pooled children map if the node pool is enabled.
+    pub(crate) fn copy_node(&mut self, node: &Node) -> Node {
+        match &mut self.node_pool {
+            Some(pool) => pool.copy(node),
+            None => node.clone(),
+        }
+    }
+
+    /// Returns the children map of `node`, which is no longer used, to the node pool if enabled.
+    pub(crate) fn recycle_node(&mut self, node: Node) {
+        if let Some(pool) = &mut self.node_pool {
+            pool.recycle(node);
+        }
+    }
+
+    /// How the node pool was used, `None` unless it is enabled.
+    pub fn node_pool_stats(&self) -> Option<NodePoolStats> {
+        self.node_pool.as_ref().map(|pool| pool.stats)
+    }
+
+    /// Gets a node with given node key only if it is pending in cache.
+    fn get_cached_node(&self, node_key: &NodeKey) -> Option<&Node> {
+        self.node_cache
+            .get(node_key)
+            .or_else(|| self.frozen_cache.node_cache.get(node_key))
+    }
+
+    /// Gets the number of nodes created at `version`, counting both the ones in `reader` and the
+    /// ones pending in cache.
+    pub fn num_nodes_at_version(&self, version: Version) -> Result<u64> {
+        let num_pending = self
+            .node_cache
+            .keys()
+            .chain(self.frozen_cache.node_cache.keys())
+            .filter(|node_key| node_key.version() == version)
+            .count();
+        Ok(self.reader.num_nodes_at_version(version)? + num_pending as u64)
+    }
+
     /// Gets the current root node key.
     pub fn get_root_node_key(&self) -> &NodeKey {
         &self.root_node_key
@@ -179,13 +908,320 @@ where
         self.root_node_key = root_node_key;
     }
 
+    /// Sets whether nodes are checked against their keys when put.
+    pub fn set_verify_on_put(&mut self, verify_on_put: bool) {
+        self.verify_on_put = verify_on_put;
+    }
+
+    /// Starts counting the leaves of every version frozen from now on, given `num_leaves`, the
+    /// number of leaves of the tree at `next_version - 1`.
+    pub fn count_leaves(&mut self, num_leaves: usize) {
+        self.num_leaves = Some(num_leaves);
+    }
+
+    /// Number of leaves of the tree at `version`, if that version was frozen by this cache since
+    /// `count_leaves` was called.
+    pub fn frozen_leaf_count(&self, version: Version) -> Option<usize> {
+        self.frozen_cache.leaf_counts.get(&version).cloned()
+    }
+
+    /// Applies `updates` to the current version of the tree, putting the value of each key, or
+    /// deleting the key if its value is `None`, and returns the new root hash. The keys are
+    /// visited in order so that the nodes above keys with a common prefix are traversed and
+    /// rewritten once. Of several updates to the same key the last one wins, and deleting a key
+    /// that is not in the tree does nothing. The version is not frozen.
+    pub fn apply_updates(
+        &mut self,
+        updates: Vec<(HashValue, Option<Vec<u8>>)>,
+    ) -> Result<HashValue> {
+        let updates: Vec<_> = updates
+            .into_iter()
+            .map(|(key, value)| (key, value.map(AccountStateBlob::from)))
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
+            .collect();
+        let root_node_key = self.root_node_key.clone();
+        let root_node = self.get_node(&root_node_key)?;
+        if updates.is_empty() {
+            return Ok(root_node.hash());
+        }
+
+        let version = self.next_version;
+        let new_root_node_key = NodeKey::new_empty_path(version);
+        let new_root_node_key = match self.update_subtree(
+            NibblePath::new(vec![]),
+            Some((root_node_key, root_node)),
+            &updates,
+        )? {
+            SubtreeUpdate::Empty => {
+                self.put_node(new_root_node_key.clone(), Node::new_null())?;
+                new_root_node_key
+            }
+            SubtreeUpdate::Leaf { leaf, existing_key } => {
+                // Every version has its own root, even if the leaf stays the same.
+                if let Some(existing_key) = existing_key {
+                    self.delete_node(&existing_key, true /* is_leaf */);
+                }
+                self.put_node(new_root_node_key.clone(), leaf.into())?;
+                new_root_node_key
+            }
+            // The root is always rewritten if there are updates.
+            SubtreeUpdate::Child(_) => new_root_node_key,
+        };
+        self.set_root_node_key(new_root_node_key);
+        Ok(self.get_node(&self.root_node_key)?.hash())
+    }
+
+    /// Applies `updates` like `apply_updates` and checks that the new root hash is
+    /// `expected_root`, e.g. the one a ledger info commits to. If applying fails or the root hash
+    /// differs, the mutable cache is rolled back to its state before the call, and
+    /// `JellyfishMerkleError::RootMismatch` is returned in the latter case.
+    pub fn apply_updates_checked(
+        &mut self,
+        updates: Vec<(HashValue, Option<Vec<u8>>)>,
+        expected_root: HashValue,
+    ) -> Result<()> {
+        // mutations are only recorded for as long as needed, unless the caller holds savepoints
+        let was_recording = self.undo_log.is_some();
+        let savepoint = self.savepoint();
+        let result = self.apply_updates(updates).and_then(|root_hash| {
+            if root_hash == expected_root {
+                Ok(())
+            } else {
+                Err(JellyfishMerkleError::RootMismatch {
+                    expected: expected_root,
+                    actual: root_hash,
+                }
+                .into())
+            }
+        });
+        if result.is_err() {
+            self.rollback_to_savepoint(&savepoint)?;
+        }
+        // the savepoint is the last one taken and only needed by this call
+        self.live_savepoints.pop();
+        if !was_recording {
+            self.undo_log = None;
+        }
+        result
+    }
+
+    /// Applies `updates`, sorted by key and all under `path`, to the subtree at `path`, whose
+    /// current root is `existing`, if any.
+    fn update_subtree(
+        &mut self,
+        path: NibblePath,
+        existing: Option<(NodeKey, Node)>,
+        updates: &[(HashValue, Option<AccountStateBlob>)],
+    ) -> Result<SubtreeUpdate> {
+        let new_leaves = updates
+            .iter()
+            .filter_map(|(key, blob)| blob.clone().map(|blob| LeafNode::new(*key, blob)));
+        match existing {
+            Some((node_key, Node::Internal(internal_node))) => {
+                // The updates of each child are the next ones in key order.
+                self.delete_node(&node_key, false /* is_leaf */);
+                let depth = path.num_nibbles();
+                let mut remaining = updates;
+                let mut results = vec![];
+                for index in 0..16u8 {
+                    let nibble = Nibble::from(index);
+                    let num_child_updates = remaining
+                        .iter()
+                        .take_while(|(key, _)| key.get_nibble(depth) == nibble)
+                        .count();
+                    let (child_updates, rest) = remaining.split_at(num_child_updates);
+                    remaining = rest;
+                    let child = internal_node.child(nibble).cloned();
+                    let result = match child {
+                        None if child_updates.is_empty() => continue,
+                        Some(child) if child_updates.is_empty() => SubtreeUpdate::Child(child),
+                        child => {
+                            let existing_child = match child {
+                                Some(child) => {
+                                    let child_node_key =
+                                        node_key.gen_child_node_key(child.version, nibble);
+                                    let child_node = self.get_node(&child_node_key)?;
+                                    Some((child_node_key, child_node))
+                                }
+                                None => None,
+                            };
+                            let mut child_path = path.clone();
+                            child_path.push(nibble);
+                            self.update_subtree(child_path, existing_child, child_updates)?
+                        }
+                    };
+                    results.push((nibble, result));
+                }
+                ensure!(
+                    remaining.is_empty(),
+                    "Updates are not sorted or not under {:?}.",
+                    path
+                );
+                self.put_internal_node(path, results)
+            }
+            Some((node_key, Node::Leaf(leaf_node))) => {
+                let account_key = leaf_node.account_key();
+                let is_updated = updates.iter().any(|(key, _)| *key == account_key);
+                let mut leaves: Vec<_> = new_leaves.collect();
+                if !is_updated {
+                    if leaves.is_empty() {
+                        return Ok(SubtreeUpdate::Leaf {
+                            leaf: leaf_node,
+                            existing_key: Some(node_key),
+                        });
+                    }
+                    let position = leaves
+                        .iter()
+                        .position(|leaf| leaf.account_key() > account_key)
+                        .unwrap_or_else(|| leaves.len());
+                    leaves.insert(position, leaf_node);
+                }
+                // The leaf is replaced, deleted or moved down.
+                self.delete_node(&node_key, true /* is_leaf */);
+                self.build_subtree(path, leaves)
+            }
+            Some((node_key, Node::Null)) => {
+                ensure!(
+                    node_key.nibble_path().num_nibbles() == 0,
+                    "Null node exists for non-root node with node_key {:?}",
+                    node_key
+                );
+                if node_key.version() == self.next_version {
+                    self.delete_node(&node_key, false /* is_leaf */);
+                }
+                self.build_subtree(path, new_leaves.collect())
+            }
+            None => self.build_subtree(path, new_leaves.collect()),
+        }
+    }
+
+    /// Builds the subtree at `path` out of `leaves`, sorted by key and all under `path`.
+    fn build_subtree(&mut self, path: NibblePath, leaves: Vec<LeafNode>) -> Result<SubtreeUpdate> {
+        if leaves.len() <= 1 {
+            return Ok(match leaves.into_iter().next() {
+                Some(leaf) => SubtreeUpdate::Leaf {
+                    leaf,
+                    existing_key: None,
+                },
+                None => SubtreeUpdate::Empty,
+            });
+        }
+        let depth = path.num_nibbles();
+        let mut results = vec![];
+        let mut leaves = leaves.into_iter().peekable();
+        while let Some(leaf) = leaves.next() {
+            let nibble = leaf.account_key().get_nibble(depth);
+            let mut child_leaves = vec![leaf];
+            while let Some(next_leaf) = leaves.peek() {
+                if next_leaf.account_key().get_nibble(depth) != nibble {
+                    break;
+                }
+                child_leaves.extend(leaves.next());
+            }
+            let mut child_path = path.clone();
+            child_path.push(nibble);
+            results.push((nibble, self.build_subtree(child_path, child_leaves)?));
+        }
+        self.put_internal_node(path, results)
+    }
+
+    /// Puts the internal node at `path` with the children the subtrees below it were updated to.
+    /// If a single leaf is left below it, the leaf replaces it instead, and no node is put if
+    /// none is left.
+    fn put_internal_node(
+        &mut self,
+        path: NibblePath,
+        results: Vec<(Nibble, SubtreeUpdate)>,
+    ) -> Result<SubtreeUpdate> {
+        let version = self.next_version;
+        let node_key = NodeKey::new(version, path);
+        let mut results: Vec<_> = results
+            .into_iter()
+            .filter(|(_, result)| match result {
+                SubtreeUpdate::Empty => false,
+                SubtreeUpdate::Leaf { .. } | SubtreeUpdate::Child(_) => true,
+            })
+            .collect();
+        if results.len() == 1 {
+            match results.pop().expect("One result must exist.") {
+                (nibble, SubtreeUpdate::Child(ref child)) if child.is_leaf => {
+                    let child_node_key = node_key.gen_child_node_key(child.version, nibble);
+                    return match self.get_node(&child_node_key)? {
+                        Node::Leaf(leaf) => {
+                            self.delete_node(&child_node_key, true /* is_leaf */);
+                            Ok(SubtreeUpdate::Leaf {
+                                leaf,
+                                existing_key: None,
+                            })
+                        }
+                        _ => bail!("Child {:?} is not a leaf.", child_node_key),
+                    };
+                }
+                (_, SubtreeUpdate::Leaf { leaf, existing_key }) => {
+                    if let Some(existing_key) = existing_key {
+                        self.delete_node(&existing_key, true /* is_leaf */);
+                    }
+                    return Ok(SubtreeUpdate::Leaf {
+                        leaf,
+                        existing_key: None,
+                    });
+                }
+                result => results.push(result),
+            }
+        }
+        if results.is_empty() {
+            return Ok(SubtreeUpdate::Empty);
+        }
+
+        let mut children = self.new_children();
+        for (nibble, result) in results {
+            let child = match result {
+                SubtreeUpdate::Empty => unreachable!("Empty subtrees were filtered out."),
+                SubtreeUpdate::Leaf {
+                    leaf,
+                    existing_key: Some(existing_key),
+                } => Child::new(
+                    Node::from(leaf).hash(),
+                    existing_key.version(),
+                    true, /* is_leaf */
+                ),
+                SubtreeUpdate::Leaf {
+                    leaf,
+                    existing_key: None,
+                } => {
+                    let leaf_node = Node::from(leaf);
+                    let hash = leaf_node.hash();
+                    self.put_node(node_key.gen_child_node_key(version, nibble), leaf_node)?;
+                    Child::new(hash, version, true /* is_leaf */)
+                }
+                SubtreeUpdate::Child(child) => child,
+            };
+            children.insert(nibble, child);
+        }
+        let internal_node: Node = InternalNode::new(children).into();
+        let hash = internal_node.hash();
+        self.put_node(node_key, internal_node)?;
+        Ok(SubtreeUpdate::Child(Child::new(
+            hash, version, false, /* is_leaf */
+        )))
+    }
+
     /// Puts the node with given hash as key into node_cache.
     pub fn put_node(&mut self, node_key: NodeKey, new_node: Node) -> Result<()> {
+        if self.verify_on_put {
+            if let Node::Leaf(leaf) = &new_node {
+                verify_leaf_position(&node_key, leaf)?;
+            }
+        }
         match self.node_cache.entry(node_key) {
             Entry::Vacant(o) => {
                 if new_node.is_leaf() {
                     self.num_new_leaves += 1
                 }
+                if let Some(undo_log) = &mut self.undo_log {
+                    undo_log.push(UndoRecord::Put(o.key().clone()));
+                }
                 o.insert(new_node);
             }
             Entry::Occupied(o) => bail!("Node with key {:?} already exists in NodeBatch", o.key()),
@@ -193,30 +1229,148 @@ where
         Ok(())
     }
 
+    /// Puts the node like `put_node`. If `verify_on_put` is set, the hash of the node is first
+    /// recomputed and compared with `expected_hash`.
+    pub fn put_node_with_hash(
+        &mut self,
+        node_key: NodeKey,
+        new_node: Node,
+        expected_hash: HashValue,
+    ) -> Result<()> {
+        if self.verify_on_put {
+            let hash = new_node.hash();
+            ensure!(
+                hash == expected_hash,
+                "Node put under key {:?} has hash {:#}, expected {:#}.",
+                node_key,
+                hash,
+                expected_hash,
+            );
+        }
+        self.put_node(node_key, new_node)
+    }
+
     /// Deletes a node with given hash.
     pub fn delete_node(&mut self, old_node_key: &NodeKey, is_leaf: bool) {
         // If node cache doesn't have this node, it means the node is in the previous version of
         // the tree on the disk.
-        if self.node_cache.remove(&old_node_key).is_none() {
-            let is_new_entry = self.stale_node_index_cache.insert(old_node_key.clone());
-            assert!(is_new_entry, "Node gets stale twice unexpectedly.");
-            if is_leaf {
-                self.num_stale_leaves += 1;
+        match self.node_cache.remove(&old_node_key) {
+            None => {
+                let is_new_entry = self.stale_node_index_cache.insert(old_node_key.clone());
+                assert!(is_new_entry, "Node gets stale twice unexpectedly.");
+                if is_leaf {
+                    self.num_stale_leaves += 1;
+                }
+                if let Some(undo_log) = &mut self.undo_log {
+                    undo_log.push(UndoRecord::MarkStale {
+                        node_key: old_node_key.clone(),
+                        is_leaf,
+                    });
+                }
+            }
+            Some(old_node) => {
+                if is_leaf {
+                    self.num_new_leaves -= 1;
+                }
+                if let Some(undo_log) = &mut self.undo_log {
+                    undo_log.push(UndoRecord::RemoveCached(old_node_key.clone(), old_node));
+                } else if let Some(pool) = &mut self.node_pool {
+                    pool.recycle(old_node);
+                }
             }
-        } else if is_leaf {
-            self.num_new_leaves -= 1;
+        }
+    }
+
+    /// Starts recording mutations so that the mutable cache can be restored to its current state
+    /// by `rollback_to_savepoint`. Savepoints can be nested and stay valid until the next
+    /// `freeze`.
+    pub fn savepoint(&mut self) -> Savepoint {
+        let undo_log = self.undo_log.get_or_insert_with(Vec::new);
+        let id = self.next_savepoint_id;
+        self.next_savepoint_id += 1;
+        self.live_savepoints.push(id);
+        Savepoint {
+            id,
+            root_node_key: self.root_node_key.clone(),
+            next_version: self.next_version,
+            undo_log_len: undo_log.len(),
+        }
+    }
+
+    /// Undoes all `put_node`, `delete_node` and `set_root_node_key` calls made since `savepoint`
+    /// was taken. Savepoints taken after it are invalidated.
+    pub fn rollback_to_savepoint(&mut self, savepoint: &Savepoint) -> Result<()> {
+        ensure!(
+            savepoint.next_version == self.next_version,
+            "Savepoint taken at version {} was invalidated by freezing, next version is {}.",
+            savepoint.next_version,
+            self.next_version
+        );
+        let position = match self
+            .live_savepoints
+            .iter()
+            .position(|id| *id == savepoint.id)
+        {
+            Some(position) => position,
+            None => bail!("Savepoint was invalidated by rolling back to an earlier one."),
+        };
+        self.live_savepoints.truncate(position + 1);
+        let undo_log = self
+            .undo_log
+            .as_mut()
+            .expect("Mutations are recorded while there are live savepoints.");
+        for record in undo_log.drain(savepoint.undo_log_len..).rev() {
+            match record {
+                UndoRecord::Put(node_key) => {
+                    let node = self
+                        .node_cache
+                        .remove(&node_key)
+                        .expect("Node put since the savepoint must be in cache.");
+                    if node.is_leaf() {
+                        self.num_new_leaves -= 1;
+                    }
+                }
+                UndoRecord::RemoveCached(node_key, node) => {
+                    if node.is_leaf() {
+                        self.num_new_leaves += 1;
+                    }
+                    self.node_cache.insert(node_key, node);
+                }
+                UndoRecord::MarkStale { node_key, is_leaf } => {
+                    self.stale_node_index_cache.remove(&node_key);
+                    if is_leaf {
+                        self.num_stale_leaves -= 1;
+                    }
+                }
+            }
+        }
+        self.root_node_key = savepoint.root_node_key.clone();
+        Ok(())
+    }
+
+    /// Takes a handle on the nodes frozen so far, see `FrozenHandle`. The nodes are shared with
+    /// the cache until the next `freeze`, which copies them if the handle is still alive.
+    pub fn frozen_handle(&self) -> FrozenHandle {
+        FrozenHandle {
+            node_cache: Arc::clone(&self.frozen_cache.node_cache),
         }
     }
 
     /// Freezes all the contents in cache to be immutable and clear `node_cache`.
     pub fn freeze(&mut self) {
-        let root_node_key = self.get_root_node_key();
+        let root_node_key = self.get_root_node_key().clone();
         let root_hash = self
-            .get_node(root_node_key)
+            .get_node(&root_node_key)
             .unwrap_or_else(|_| panic!("Root node with key {:?} must exist", root_node_key))
             .hash();
         self.frozen_cache.root_hashes.push(root_hash);
-        self.frozen_cache.node_cache.extend(self.node_cache.drain());
+        self.frozen_cache
+            .root_node_key_list
+            .push(root_node_key.clone());
+        self.frozen_cache
+            .root_node_keys
+            .insert(root_hash, root_node_key);
+        Arc::make_mut(&mut self.frozen_cache.node_cache).extend(self.node_cache.drain());
 
         let stale_since_version = self.next_version;
         self.frozen_cache
@@ -229,28 +1383,222 @@ where
                         node_key,
                     }),
             );
+        if let Some(num_leaves) = self.num_leaves {
+            let num_leaves = (num_leaves + self.num_new_leaves)
+                .checked_sub(self.num_stale_leaves)
+                .expect("More leaves became stale than the tree had.");
+            self.frozen_cache
+                .leaf_counts
+                .insert(self.next_version, num_leaves);
+            self.num_leaves = Some(num_leaves);
+        }
         self.frozen_cache.num_stale_leaves += self.num_stale_leaves;
         self.num_stale_leaves = 0;
         self.frozen_cache.num_new_leaves += self.num_new_leaves;
         self.num_new_leaves = 0;
+        // Frozen contents are immutable, so there is nothing to roll back to anymore.
+        self.undo_log = None;
+        self.live_savepoints.clear();
 
         self.next_version += 1;
     }
 }
 
-impl<'a, R> Into<(Vec<HashValue>, TreeUpdateBatch)> for TreeCache<'a, R>
+impl<D> Into<(Vec<HashValue>, TreeUpdateBatch)> for GenericTreeCache<D>
 where
-    R: 'a + TreeReader,
+    D: Deref,
+    D::Target: TreeReader,
 {
     fn into(self) -> (Vec<HashValue>, TreeUpdateBatch) {
         (
             self.frozen_cache.root_hashes,
             TreeUpdateBatch {
-                node_batch: self.frozen_cache.node_cache,
+                node_batch: Arc::try_unwrap(self.frozen_cache.node_cache)
+                    .unwrap_or_else(|node_cache| (*node_cache).clone()),
                 stale_node_index_batch: self.frozen_cache.stale_node_index_cache,
                 num_new_leaves: self.frozen_cache.num_new_leaves,
                 num_stale_leaves: self.frozen_cache.num_stale_leaves,
+                root_node_keys: self.frozen_cache.root_node_keys,
             },
         )
     }
 }
+
+impl<'a, R> TreeCache<'a, R>
+where
+    R: 'a + TreeReader,
+{
+    /// Combines `shards`, the batches of caches that each updated the tree at `next_version - 1`
+    /// within a key range disjoint from the ones of the other shards, into a single batch for
+    /// `next_version`. The internal nodes written by more than one shard are recomputed from the
+    /// children of all of them. Returns the combined root hash along with the batch.
+    pub fn merge_shards(
+        reader: &'a R,
+        next_version: Version,
+        shards: Vec<TreeUpdateBatch>,
+    ) -> Result<(HashValue, TreeUpdateBatch)> {
+        let mut key_ranges = vec![];
+        for (i, shard) in shards.iter().enumerate() {
+            if let Some(node_key) = shard
+                .node_batch
+                .keys()
+                .find(|node_key| node_key.version() != next_version)
+            {
+                bail!(
+                    "Shard {} has node {:?} not created at version {}.",
+                    i,
+                    node_key,
+                    next_version
+                );
+            }
+            let mut account_keys = shard.node_batch.values().filter_map(|node| match node {
+                Node::Leaf(leaf) => Some(leaf.account_key()),
+                _ => None,
+            });
+            if let Some(first) = account_keys.next() {
+                let (min, max) = account_keys.fold((first, first), |(min, max), key| {
+                    (std::cmp::min(min, key), std::cmp::max(max, key))
+                });
+                key_ranges.push((min, max, i));
+            }
+        }
+        key_ranges.sort();
+        for pair in key_ranges.windows(2) {
+            ensure!(
+                pair[0].1 < pair[1].0,
+                "Key ranges of shards {} and {} overlap.",
+                pair[0].2,
+                pair[1].2
+            );
+        }
+
+        let mut nodes_by_key: BTreeMap<NodeKey, Vec<Node>> = BTreeMap::new();
+        let mut batch = TreeUpdateBatch::default();
+        for shard in shards {
+            for (node_key, node) in shard.node_batch {
+                nodes_by_key.entry(node_key).or_default().push(node);
+            }
+            batch
+                .stale_node_index_batch
+                .extend(shard.stale_node_index_batch);
+            batch.num_new_leaves += shard.num_new_leaves;
+            batch.num_stale_leaves += shard.num_stale_leaves;
+        }
+
+        let mut shared_nodes = vec![];
+        for (node_key, mut nodes) in nodes_by_key {
+            if nodes.len() == 1 {
+                batch
+                    .node_batch
+                    .insert(node_key, nodes.pop().expect("Must exist."));
+            } else {
+                shared_nodes.push((node_key, nodes));
+            }
+        }
+        // Children are merged before their parents.
+        shared_nodes.sort_by_key(|(node_key, _)| Reverse(node_key.nibble_path().num_nibbles()));
+        for (node_key, nodes) in shared_nodes {
+            let merged_node = merge_internal_nodes(&node_key, &nodes, &batch.node_batch)?;
+            batch.node_batch.insert(node_key, merged_node);
+        }
+
+        let root_node_key = NodeKey::new_empty_path(next_version);
+        let root_hash = match batch.node_batch.get(&root_node_key) {
+            Some(root) => {
+                let root_hash = root.hash();
+                batch.root_node_keys.insert(root_hash, root_node_key);
+                root_hash
+            }
+            // None of the shards changed the tree.
+            None if next_version == 0 => Node::new_null().hash(),
+            None => {
+                let root_node_key =
+                    reader.get_root_node_key(next_version - 1)?.ok_or_else(|| {
+                        format_err!("Missing root node at version {}.", next_version - 1)
+                    })?;
+                reader.get_node(&root_node_key)?.hash()
+            }
+        };
+        Ok((root_hash, batch))
+    }
+}
+
+/// Merges the versions of the internal node at `node_key` written by different shards. A child
+/// written by one of the shards is taken from `node_batch`, which already holds the merged
+/// children, while a child none of them wrote is kept unless a shard deleted it.
+fn merge_internal_nodes(
+    node_key: &NodeKey,
+    nodes: &[Node],
+    node_batch: &BTreeMap<NodeKey, Node>,
+) -> Result<Node> {
+    let internal_nodes = nodes
+        .iter()
+        .map(|node| match node {
+            Node::Internal(internal_node) => Ok(internal_node),
+            _ => bail!(
+                "Node {:?} is written by several shards but not an internal node.",
+                node_key
+            ),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut children = Children::new();
+    for i in 0..16u8 {
+        let nibble = Nibble::from(i);
+        let candidates: Vec<_> = internal_nodes
+            .iter()
+            .map(|internal_node| internal_node.child(nibble))
+            .collect();
+        if candidates
+            .iter()
+            .any(|child| child.map_or(false, |child| child.version == node_key.version()))
+        {
+            let child_key = node_key.gen_child_node_key(node_key.version(), nibble);
+            let child_node = node_batch
+                .get(&child_key)
+                .ok_or_else(|| format_err!("Child {:?} missing in shards.", child_key))?;
+            children.insert(
+                nibble,
+                Child::new(child_node.hash(), node_key.version(), child_node.is_leaf()),
+            );
+            continue;
+        }
+        let mut unchanged = candidates.iter().filter_map(|child| *child);
+        if let Some(child) = unchanged.next() {
+            ensure!(
+                unchanged.all(|other| other == child),
+                "Shards disagree on child {:?} of node {:?}.",
+                nibble,
+                node_key
+            );
+            // A shard that dropped the child deleted it.
+            if candidates.iter().all(Option::is_some) {
+                children.insert(nibble, child.clone());
+            }
+        }
+    }
+    ensure!(
+        children.len() > 1 || children.values().any(|child| !child.is_leaf),
+        "Merged node {:?} would have to be collapsed.",
+        node_key
+    );
+    Ok(Node::new_internal(children))
+}
+
+/// Checks that the account key of `leaf` starts with the nibble path of `node_key`, which holds for
+/// any leaf in its right position in the tree.
+fn verify_leaf_position(node_key: &NodeKey, leaf: &LeafNode) -> Result<()> {
+    let account_key = leaf.account_key();
+    let is_prefix = node_key
+        .nibble_path()
+        .nibbles()
+        .enumerate()
+        .all(|(i, nibble)| account_key.get_nibble(i) == nibble);
+    ensure!(
+        is_prefix,
+        "Leaf with account key {:#} put under key {:?} not on its path.",
+        account_key,
+        node_key,
+    );
+    Ok(())
+}
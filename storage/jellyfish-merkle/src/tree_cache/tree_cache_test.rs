@@ -2,9 +2,16 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use super::*;
-use crate::{mock_tree_store::MockTreeStore, nibble_path::NibblePath, node_type::Node, NodeKey};
+use crate::{
+    mock_tree_store::MockTreeStore,
+    nibble_path::NibblePath,
+    node_type::{LeafNode, Node},
+    JellyfishMerkleError, JellyfishMerkleTree, NodeKey,
+};
+use futures::{channel::oneshot, executor::block_on};
 use libra_crypto::HashValue;
 use libra_types::account_state_blob::AccountStateBlob;
+use std::{future::Future, thread};
 
 fn random_leaf_with_key(next_version: Version) -> (Node, NodeKey) {
     let address = HashValue::random();
@@ -16,11 +23,50 @@ fn random_leaf_with_key(next_version: Version) -> (Node, NodeKey) {
     (node, node_key)
 }
 
+/// A reader that only knows how many nodes were created at each version.
+struct NodeCountReader {
+    num_nodes: HashMap<Version, u64>,
+}
+
+impl TreeReader for NodeCountReader {
+    fn get_node_option(&self, _node_key: &NodeKey) -> Result<Option<Node>> {
+        Ok(None)
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        unimplemented!()
+    }
+
+    fn num_nodes_at_version(&self, version: Version) -> Result<u64> {
+        Ok(self.num_nodes.get(&version).cloned().unwrap_or(0))
+    }
+}
+
+/// A reader keeping the root of every version under a fixed key.
+struct FixedRootReader {
+    store: MockTreeStore,
+    root_node_key: Option<NodeKey>,
+}
+
+impl TreeReader for FixedRootReader {
+    fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        self.store.get_node_option(node_key)
+    }
+
+    fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
+        unimplemented!()
+    }
+
+    fn get_root_node_key(&self, _version: Version) -> Result<Option<NodeKey>> {
+        Ok(self.root_node_key.clone())
+    }
+}
+
 #[test]
 fn test_get_node() {
     let next_version = 0;
     let db = MockTreeStore::default();
-    let cache = TreeCache::new(&db, next_version);
+    let cache = TreeCache::new(&db, next_version).unwrap();
 
     let (node, node_key) = random_leaf_with_key(next_version);
     db.put_node(node_key.clone(), node.clone()).unwrap();
@@ -32,7 +78,7 @@ fn test_get_node() {
 fn test_root_node() {
     let next_version = 0;
     let db = MockTreeStore::default();
-    let mut cache = TreeCache::new(&db, next_version);
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
     assert_eq!(*cache.get_root_node_key(), NodeKey::new_empty_path(0));
 
     let (node, node_key) = random_leaf_with_key(next_version);
@@ -46,7 +92,7 @@ fn test_root_node() {
 fn test_freeze_with_delete() {
     let next_version = 0;
     let db = MockTreeStore::default();
-    let mut cache = TreeCache::new(&db, next_version);
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
 
     assert_eq!(*cache.get_root_node_key(), NodeKey::new_empty_path(0));
 
@@ -66,3 +112,1214 @@ fn test_freeze_with_delete() {
     assert_eq!(update_batch.node_batch.len(), 3);
     assert_eq!(update_batch.stale_node_index_batch.len(), 1);
 }
+
+#[test]
+fn test_rollback_to_savepoint() {
+    let next_version = 1;
+    let db = MockTreeStore::default();
+    let (disk_node, disk_node_key) = random_leaf_with_key(next_version - 1);
+    db.put_node(disk_node_key.clone(), disk_node).unwrap();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+
+    let (node1, node1_key) = random_leaf_with_key(next_version);
+    cache.put_node(node1_key.clone(), node1).unwrap();
+
+    let savepoint = cache.savepoint();
+    let node_cache = cache.node_cache.clone();
+    let stale_node_index_cache = cache.stale_node_index_cache.clone();
+    let (num_new_leaves, num_stale_leaves) = (cache.num_new_leaves, cache.num_stale_leaves);
+    let root_node_key = cache.get_root_node_key().clone();
+
+    cache.delete_node(&disk_node_key, true /* is_leaf */);
+    cache.delete_node(&node1_key, true /* is_leaf */);
+    let (node2, node2_key) = random_leaf_with_key(next_version);
+    cache.put_node(node2_key.clone(), node2).unwrap();
+    cache.set_root_node_key(node2_key.clone());
+
+    // a nested savepoint is undone along with everything after the outer one
+    let nested = cache.savepoint();
+    let (node3, node3_key) = random_leaf_with_key(next_version);
+    cache.put_node(node3_key, node3).unwrap();
+    cache.delete_node(&node2_key, true /* is_leaf */);
+
+    cache.rollback_to_savepoint(&savepoint).unwrap();
+    assert_eq!(cache.node_cache, node_cache);
+    assert_eq!(cache.stale_node_index_cache, stale_node_index_cache);
+    assert_eq!(cache.num_new_leaves, num_new_leaves);
+    assert_eq!(cache.num_stale_leaves, num_stale_leaves);
+    assert_eq!(*cache.get_root_node_key(), root_node_key);
+    assert!(cache.rollback_to_savepoint(&nested).is_err());
+
+    // the disk node can be deleted again after the rollback
+    cache.delete_node(&disk_node_key, true /* is_leaf */);
+    assert_eq!(cache.num_stale_leaves, 1);
+}
+
+#[test]
+fn test_savepoint_invalidated_by_rollback() {
+    let next_version = 0;
+    let db = MockTreeStore::default();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+
+    let outer = cache.savepoint();
+    let (node1, node1_key) = random_leaf_with_key(next_version);
+    cache.put_node(node1_key, node1).unwrap();
+    let nested = cache.savepoint();
+    cache.rollback_to_savepoint(&outer).unwrap();
+
+    // the log grows past the length it had when the nested savepoint was taken
+    for _ in 0..2 {
+        let (node, node_key) = random_leaf_with_key(next_version);
+        cache.put_node(node_key, node).unwrap();
+    }
+    assert!(cache.rollback_to_savepoint(&nested).is_err());
+    // the outer savepoint is still live
+    cache.rollback_to_savepoint(&outer).unwrap();
+    assert_eq!(cache.num_new_leaves, 0);
+}
+
+#[test]
+fn test_savepoint_invalidated_by_freeze() {
+    let next_version = 0;
+    let db = MockTreeStore::default();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+
+    let savepoint = cache.savepoint();
+    let (node, node_key) = random_leaf_with_key(next_version);
+    cache.put_node(node_key, node).unwrap();
+    cache.freeze();
+    assert!(cache.rollback_to_savepoint(&savepoint).is_err());
+}
+
+#[test]
+fn test_owned_cache_across_await() {
+    // Only compiles if the future, and hence the cache held across the `.await` inside it, can be
+    // spawned onto a multi-threaded runtime.
+    fn assert_spawnable<F: Future + Send + 'static>(future: F) -> F {
+        future
+    }
+
+    let next_version = 0;
+    let db = Arc::new(MockTreeStore::default());
+    let mut cache = OwnedTreeCache::new(Arc::clone(&db), next_version).unwrap();
+
+    let (node1, node1_key) = random_leaf_with_key(next_version);
+    db.put_node(node1_key.clone(), node1.clone()).unwrap();
+    let (node2, node2_key) = random_leaf_with_key(next_version);
+    let (sender, receiver) = oneshot::channel();
+    let future = assert_spawnable(async move {
+        cache.put_node(node2_key.clone(), node2.clone()).unwrap();
+        receiver.await.unwrap();
+        assert_eq!(cache.get_node(&node1_key).unwrap(), node1);
+        assert_eq!(cache.get_node(&node2_key).unwrap(), node2);
+        cache.freeze();
+        cache
+    });
+    let handle = thread::spawn(move || sender.send(()).unwrap());
+
+    let (root_hashes, update_batch) = block_on(future).into();
+    handle.join().unwrap();
+    assert_eq!(root_hashes.len(), 1);
+    assert_eq!(update_batch.node_batch.len(), 2);
+}
+
+#[test]
+fn test_num_nodes_at_version() {
+    let next_version = 3;
+    let reader = NodeCountReader {
+        num_nodes: vec![(1, 5), (2, 7)].into_iter().collect(),
+    };
+    let mut cache = TreeCache::new(&reader, next_version).unwrap();
+    assert_eq!(cache.num_nodes_at_version(2).unwrap(), 7);
+    assert_eq!(cache.num_nodes_at_version(next_version).unwrap(), 0);
+
+    let (node1, node1_key) = random_leaf_with_key(next_version);
+    cache.put_node(node1_key, node1).unwrap();
+    cache.freeze();
+    let (node2, node2_key) = random_leaf_with_key(next_version + 1);
+    cache.put_node(node2_key, node2).unwrap();
+
+    // Both frozen and mutable pending nodes are counted for their own version only.
+    assert_eq!(cache.num_nodes_at_version(2).unwrap(), 7);
+    assert_eq!(cache.num_nodes_at_version(next_version).unwrap(), 1);
+    assert_eq!(cache.num_nodes_at_version(next_version + 1).unwrap(), 1);
+
+    // The default implementation doesn't support it.
+    let db = MockTreeStore::default();
+    assert!(TreeCache::new(&db, next_version)
+        .unwrap()
+        .num_nodes_at_version(next_version)
+        .is_err());
+}
+
+#[test]
+fn test_verify_on_put() {
+    let next_version = 0;
+    let db = MockTreeStore::default();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+    cache.set_verify_on_put(true);
+
+    // A leaf put under a key whose path diverges from its account key is rejected.
+    let (node, node_key) = random_leaf_with_key(next_version);
+    let mut wrong_path = node_key.nibble_path().bytes().to_vec();
+    wrong_path[0] ^= 0xff;
+    let wrong_key = NodeKey::new(next_version, NibblePath::new(wrong_path));
+    assert!(cache.put_node(wrong_key.clone(), node.clone()).is_err());
+    assert!(cache.get_node(&wrong_key).is_err());
+
+    // So is a node that doesn't hash to what the caller expects.
+    assert!(cache
+        .put_node_with_hash(node_key.clone(), node.clone(), HashValue::random())
+        .is_err());
+    cache
+        .put_node_with_hash(node_key.clone(), node.clone(), node.hash())
+        .unwrap();
+    assert_eq!(cache.get_node(&node_key).unwrap(), node);
+
+    // Without verification the misplaced leaf goes in unchecked.
+    cache.set_verify_on_put(false);
+    cache.put_node(wrong_key.clone(), node.clone()).unwrap();
+    assert_eq!(cache.get_node(&wrong_key).unwrap(), node);
+}
+
+/// A random key whose first byte is `first_byte`.
+fn random_key_with_first_byte(first_byte: u8) -> HashValue {
+    let mut key = HashValue::random().to_vec();
+    key[0] = first_byte;
+    HashValue::from_slice(&key).unwrap()
+}
+
+fn random_blob() -> AccountStateBlob {
+    AccountStateBlob::from(HashValue::random().to_vec())
+}
+
+#[test]
+fn test_export_root_subtree() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11, 0x21]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys[..3].iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // Add keys[3] in the cache, the tree at version 1 is:
+    //                 root (1)
+    //           /        |         \
+    //     internal (0)  keys[2] (0)  keys[3] (1)
+    //      /    \
+    // keys[0]  keys[1]
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    cache.apply_updates(vec![(keys[3], Some(vec![3]))]).unwrap();
+    let root_key = NodeKey::new_empty_path(1);
+    let internal_key = NodeKey::new_empty_path(0).gen_child_node_key(0, Nibble::from(0));
+    let top_level_keys = vec![
+        root_key.clone(),
+        internal_key.clone(),
+        NodeKey::new_empty_path(0).gen_child_node_key(0, Nibble::from(1)),
+        root_key.gen_child_node_key(1, Nibble::from(2)),
+    ];
+    let nodes_at = |node_keys: &[NodeKey]| {
+        node_keys
+            .iter()
+            .map(|node_key| (node_key.clone(), cache.get_node(node_key).unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    assert!(cache.export_root_subtree(0).unwrap().is_empty());
+    assert_eq!(
+        cache.export_root_subtree(1).unwrap(),
+        nodes_at(&top_level_keys[..1])
+    );
+    assert_eq!(
+        cache.export_root_subtree(2).unwrap(),
+        nodes_at(&top_level_keys)
+    );
+    // the new nodes are only in the cache
+    assert!(db.get_node_option(&root_key).unwrap().is_none());
+
+    let mut all_keys = top_level_keys.clone();
+    all_keys.push(internal_key.gen_child_node_key(0, Nibble::from(1)));
+    all_keys.push(internal_key.gen_child_node_key(0, Nibble::from(2)));
+    assert_eq!(cache.export_root_subtree(3).unwrap(), nodes_at(&all_keys));
+    assert_eq!(cache.export_root_subtree(10).unwrap(), nodes_at(&all_keys));
+}
+
+#[test]
+fn test_merge_shards() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11, 0x21]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // One shard updates keys starting with nibble 0 and the other ones starting with nibble 1 to
+    // 3, so they share the root and nothing else.
+    let shard_a = vec![
+        (keys[0], random_blob()),
+        (random_key_with_first_byte(0x03), random_blob()),
+    ];
+    let shard_b = vec![
+        (keys[2], random_blob()),
+        (random_key_with_first_byte(0x35), random_blob()),
+    ];
+    let (_, batch_a) = tree.put_blob_set(shard_a.clone(), 1).unwrap();
+    let (_, batch_b) = tree.put_blob_set(shard_b.clone(), 1).unwrap();
+    let (root_hash, batch) = TreeCache::merge_shards(&db, 1, vec![batch_a, batch_b]).unwrap();
+
+    let (expected_root_hash, expected_batch) = tree
+        .put_blob_set(shard_a.into_iter().chain(shard_b).collect(), 1)
+        .unwrap();
+    assert_eq!(root_hash, expected_root_hash);
+    assert_eq!(batch, expected_batch);
+}
+
+#[test]
+fn test_merge_overlapping_shards() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let (_root_hash, batch) = tree
+        .put_blob_set(vec![(random_key_with_first_byte(0x10), random_blob())], 0)
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    let (_, batch_a) = tree
+        .put_blob_set(
+            vec![
+                (random_key_with_first_byte(0x01), random_blob()),
+                (random_key_with_first_byte(0x05), random_blob()),
+            ],
+            1,
+        )
+        .unwrap();
+    let (_, batch_b) = tree
+        .put_blob_set(vec![(random_key_with_first_byte(0x03), random_blob())], 1)
+        .unwrap();
+    assert!(TreeCache::merge_shards(&db, 1, vec![batch_a, batch_b]).is_err());
+}
+
+#[test]
+fn test_ancestors_of() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // The tree at version 0:
+    //            root
+    //           /    \
+    //     internal    keys[2]
+    //      /    \
+    // keys[0]  keys[1]
+    let root_key = NodeKey::new_empty_path(0);
+    let internal_key = root_key.gen_child_node_key(0, Nibble::from(0));
+    let nodes_at = |keys: &[NodeKey]| {
+        keys.iter()
+            .map(|key| (key.clone(), db.get_node(key).unwrap()))
+            .collect::<Vec<_>>()
+    };
+
+    let cache = TreeCache::new(&db, 1).unwrap();
+    assert_eq!(
+        cache.ancestors_of(keys[1]).unwrap(),
+        nodes_at(&[
+            root_key.clone(),
+            internal_key.clone(),
+            internal_key.gen_child_node_key(0, Nibble::from(2)),
+        ])
+    );
+    assert_eq!(
+        cache.ancestors_of(keys[2]).unwrap(),
+        nodes_at(&[
+            root_key.clone(),
+            root_key.gen_child_node_key(0, Nibble::from(1))
+        ])
+    );
+    assert!(cache
+        .ancestors_of(random_key_with_first_byte(0x03))
+        .is_err());
+    assert!(cache
+        .ancestors_of(random_key_with_first_byte(0x12))
+        .is_err());
+    assert!(cache
+        .ancestors_of(random_key_with_first_byte(0x20))
+        .is_err());
+
+    // Replace the leaf of keys[2] and the root in the cache, the path to keys[0] now starts in the
+    // cache and continues on disk.
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    let new_leaf_key = NodeKey::new_empty_path(1).gen_child_node_key(1, Nibble::from(1));
+    let new_leaf = Node::new_leaf(keys[2], random_blob());
+    let mut children = Children::new();
+    children.insert(
+        Nibble::from(0),
+        Child::new(db.get_node(&internal_key).unwrap().hash(), 0, false),
+    );
+    children.insert(Nibble::from(1), Child::new(new_leaf.hash(), 1, true));
+    let new_root = Node::new_internal(children);
+    cache
+        .put_node(new_leaf_key.clone(), new_leaf.clone())
+        .unwrap();
+    cache
+        .put_node(NodeKey::new_empty_path(1), new_root.clone())
+        .unwrap();
+    cache.set_root_node_key(NodeKey::new_empty_path(1));
+
+    assert_eq!(
+        cache.ancestors_of(keys[2]).unwrap(),
+        vec![
+            (NodeKey::new_empty_path(1), new_root.clone()),
+            (new_leaf_key, new_leaf),
+        ]
+    );
+    let ancestors = cache.ancestors_of(keys[0]).unwrap();
+    assert_eq!(ancestors[0], (NodeKey::new_empty_path(1), new_root));
+    assert_eq!(
+        ancestors[1..].to_vec(),
+        nodes_at(&[
+            internal_key.clone(),
+            internal_key.gen_child_node_key(0, Nibble::from(1)),
+        ])
+    );
+}
+
+#[test]
+fn test_reuse_stats() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    // Two leaves under nibble 0 and one leaf under each of the other 15 nibbles of the root.
+    let keys: Vec<_> = [0x01, 0x02]
+        .iter()
+        .cloned()
+        .chain((1..16u8).map(|nibble| nibble << 4))
+        .map(random_key_with_first_byte)
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // Nothing is modified yet, the whole tree is shared through the root.
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    assert_eq!(
+        cache.reuse_stats().unwrap(),
+        ReuseStats {
+            num_new_nodes: 0,
+            num_reused_nodes: 1,
+        }
+    );
+
+    // Updating keys[0] creates the root, the internal node under nibble 0 and the leaf. Everything
+    // else hangs off them unchanged: the 15 other children of the root and the sibling leaf.
+    JellyfishMerkleTree::put(keys[0], random_blob(), 1, &mut cache).unwrap();
+    assert_eq!(
+        cache.reuse_stats().unwrap(),
+        ReuseStats {
+            num_new_nodes: 3,
+            num_reused_nodes: 16,
+        }
+    );
+
+    // The stats cover the frozen part of the cache as well.
+    cache.freeze();
+    assert_eq!(cache.reuse_stats().unwrap().num_new_nodes, 3);
+}
+
+#[test]
+fn test_root_node_key_from_reader() {
+    let next_version = 3;
+    let (node, node_key) = random_leaf_with_key(next_version - 2);
+    let mut reader = FixedRootReader {
+        store: MockTreeStore::default(),
+        root_node_key: Some(node_key.clone()),
+    };
+    reader
+        .store
+        .put_node(node_key.clone(), node.clone())
+        .unwrap();
+
+    // The cache starts from the root the reader points at, not the one under the empty path.
+    let cache = TreeCache::new(&reader, next_version).unwrap();
+    assert_eq!(*cache.get_root_node_key(), node_key);
+    assert_eq!(cache.get_node(cache.get_root_node_key()).unwrap(), node);
+
+    // A version without a tree can't be built on.
+    reader.root_node_key = None;
+    assert!(TreeCache::new(&reader, next_version).is_err());
+}
+
+#[test]
+fn test_frozen_leaf_count() {
+    let next_version = 1;
+    let db = MockTreeStore::default();
+    let (disk_node, disk_node_key) = random_leaf_with_key(next_version - 1);
+    db.put_node(disk_node_key.clone(), disk_node.clone())
+        .unwrap();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+    cache.set_root_node_key(disk_node_key.clone());
+    cache.freeze();
+    // Versions frozen before counting started have no count.
+    assert_eq!(cache.frozen_leaf_count(next_version), None);
+
+    cache.count_leaves(1);
+    let (node1, node1_key) = random_leaf_with_key(next_version + 1);
+    cache.put_node(node1_key.clone(), node1).unwrap();
+    let (node2, node2_key) = random_leaf_with_key(next_version + 1);
+    cache.put_node(node2_key.clone(), node2).unwrap();
+    cache.freeze();
+    assert_eq!(cache.frozen_leaf_count(next_version + 1), Some(3));
+
+    // A leaf removed from disk and one removed from an earlier frozen version both count, while
+    // a leaf added and removed within the version doesn't.
+    cache.delete_node(&disk_node_key, true /* is_leaf */);
+    cache.delete_node(&node1_key, true /* is_leaf */);
+    let (node3, node3_key) = random_leaf_with_key(next_version + 2);
+    cache.put_node(node3_key.clone(), node3).unwrap();
+    cache.delete_node(&node3_key, true /* is_leaf */);
+    cache.freeze();
+    assert_eq!(cache.frozen_leaf_count(next_version + 1), Some(3));
+    assert_eq!(cache.frozen_leaf_count(next_version + 2), Some(1));
+
+    // Updating a leaf replaces it.
+    cache.delete_node(&node2_key, true /* is_leaf */);
+    let (node4, node4_key) = random_leaf_with_key(next_version + 3);
+    cache.put_node(node4_key, node4).unwrap();
+    cache.freeze();
+    assert_eq!(cache.frozen_leaf_count(next_version + 3), Some(1));
+    assert_eq!(cache.frozen_leaf_count(next_version + 4), None);
+}
+
+#[test]
+fn test_get_value() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let key = random_key_with_first_byte(0x01);
+    let blob = random_blob();
+
+    // Empty tree.
+    let cache = TreeCache::new(&db, 0).unwrap();
+    assert_eq!(cache.get_value(key).unwrap(), None);
+
+    // Single leaf at the root, whose key need not share any nibble with the one looked up.
+    let (_root_hash, batch) = tree
+        .put_blob_set(vec![(key, blob.clone())], 0 /* version */)
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let cache = TreeCache::new(&db, 1).unwrap();
+    assert_eq!(cache.get_value(key).unwrap(), Some(blob.clone()));
+    assert_eq!(
+        cache.get_value(random_key_with_first_byte(0x01)).unwrap(),
+        None
+    );
+    assert_eq!(
+        cache.get_value(random_key_with_first_byte(0xff)).unwrap(),
+        None
+    );
+
+    // Walking down internal nodes, partly in the cache and partly on disk.
+    let keys: Vec<_> = [0x02, 0x11]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            1, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let mut cache = TreeCache::new(&db, 2).unwrap();
+    let new_blob = random_blob();
+    JellyfishMerkleTree::put(keys[1], new_blob.clone(), 2, &mut cache).unwrap();
+    assert_eq!(cache.get_value(key).unwrap(), Some(blob));
+    assert_eq!(cache.get_value(keys[1]).unwrap(), Some(new_blob));
+    assert!(cache.get_value(keys[0]).unwrap().is_some());
+    assert_eq!(
+        cache.get_value(random_key_with_first_byte(0x03)).unwrap(),
+        None
+    );
+    assert_eq!(
+        cache.get_value(random_key_with_first_byte(0x20)).unwrap(),
+        None
+    );
+}
+
+#[test]
+fn test_consistency_proof() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11, 0x20, 0x12, 0x30]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (root_hash0, batch) = tree
+        .put_blob_set(
+            keys[..4].iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // Stage two versions on top of version 0 in the cache. Version 1 updates keys[0] and adds
+    // keys[4], which moves the leaf of keys[2] down. Version 2 updates keys[3] and adds keys[5].
+    let updates1 = vec![(keys[0], random_blob()), (keys[4], random_blob())];
+    let updates2 = vec![(keys[3], random_blob()), (keys[5], random_blob())];
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    for (key, blob) in updates1.iter() {
+        JellyfishMerkleTree::put(*key, blob.clone(), 1, &mut cache).unwrap();
+    }
+    cache.freeze();
+    for (key, blob) in updates2.iter() {
+        JellyfishMerkleTree::put(*key, blob.clone(), 2, &mut cache).unwrap();
+    }
+    cache.freeze();
+    let root_hash1 = cache.frozen_cache.root_hashes[0];
+    let root_hash2 = cache.frozen_cache.root_hashes[1];
+
+    let proof = cache.consistency_proof(1, 2).unwrap();
+    proof.verify(root_hash1, root_hash2, &updates2).unwrap();
+    assert!(proof.verify(root_hash0, root_hash2, &updates2).is_err());
+    assert!(proof
+        .verify(root_hash1, root_hash2, &updates2[..1])
+        .is_err());
+    assert!(proof
+        .verify(
+            root_hash1,
+            root_hash2,
+            &[updates2[0].clone(), (keys[5], random_blob())]
+        )
+        .is_err());
+
+    // From the version in the reader, across both staged versions.
+    let all_updates: Vec<_> = updates1.iter().chain(updates2.iter()).cloned().collect();
+    let proof = cache.consistency_proof(0, 2).unwrap();
+    proof.verify(root_hash0, root_hash2, &all_updates).unwrap();
+    assert!(proof.verify(root_hash0, root_hash2, &updates2).is_err());
+    assert!(proof.verify(root_hash0, root_hash1, &all_updates).is_err());
+
+    cache
+        .consistency_proof(0, 1)
+        .unwrap()
+        .verify(root_hash0, root_hash1, &updates1)
+        .unwrap();
+    cache
+        .consistency_proof(2, 2)
+        .unwrap()
+        .verify(root_hash2, root_hash2, &[])
+        .unwrap();
+    assert!(cache.consistency_proof(2, 1).is_err());
+    assert!(cache.consistency_proof(0, 3).is_err());
+}
+
+/// Stages versions 0 and 1 in a cache over an empty tree, both putting the same blob under the
+/// same key, so that the two versions have the same root hash but different root nodes.
+fn cache_with_same_root_hash_twice(db: &MockTreeStore) -> TreeCache<'_, MockTreeStore> {
+    let key = HashValue::random();
+    let blob = random_blob();
+    let mut cache = TreeCache::new(db, 0 /* next_version */).unwrap();
+    for version in 0..2 {
+        JellyfishMerkleTree::put(key, blob.clone(), version, &mut cache).unwrap();
+        cache.freeze();
+    }
+    assert_eq!(
+        cache.frozen_cache.root_hashes[0],
+        cache.frozen_cache.root_hashes[1]
+    );
+    cache
+}
+
+#[test]
+fn test_root_node_key_at_same_root_hash() {
+    let db = MockTreeStore::default();
+    let cache = cache_with_same_root_hash_twice(&db);
+    assert_eq!(
+        cache.root_node_key_at(0).unwrap(),
+        NodeKey::new_empty_path(0)
+    );
+    assert_eq!(
+        cache.root_node_key_at(1).unwrap(),
+        NodeKey::new_empty_path(1)
+    );
+}
+
+#[test]
+fn test_node_pool() {
+    let db = MockTreeStore::default();
+    let blob_sets: Vec<Vec<_>> = (0..10)
+        .map(|_| {
+            (0..100)
+                .map(|_| (HashValue::random(), random_blob()))
+                .collect()
+        })
+        .collect();
+
+    let mut root_hashes = vec![];
+    let mut stats = vec![];
+    for pool_nodes in [false, true].iter() {
+        let mut cache = TreeCache::new_with_node_pool(&db, 0, *pool_nodes).unwrap();
+        for (version, blob_set) in blob_sets.iter().enumerate() {
+            for (key, blob) in blob_set.iter() {
+                JellyfishMerkleTree::put(*key, blob.clone(), version as Version, &mut cache)
+                    .unwrap();
+            }
+            cache.freeze();
+        }
+        root_hashes.push(cache.frozen_cache.root_hashes.clone());
+        stats.push(cache.node_pool_stats());
+    }
+
+    // Pooling doesn't change the tree, and most of the internal nodes of a heavy batch are built
+    // in recycled children maps rather than freshly allocated ones.
+    assert_eq!(root_hashes[0], root_hashes[1]);
+    assert_eq!(stats[0], None);
+    let stats = stats[1].unwrap();
+    assert!(stats.recycled > 0);
+    assert!(stats.hits > stats.misses);
+}
+
+#[test]
+fn test_verify_proof() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let blobs: Vec<_> = keys.iter().map(|_| random_blob()).collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().cloned().zip(blobs.iter().cloned()).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+
+    // inclusion
+    let (blob, proof) = tree.get_with_proof(keys[1], 0).unwrap();
+    assert_eq!(blob.as_ref(), Some(&blobs[1]));
+    let value: Vec<u8> = blobs[1].clone().into();
+    cache.verify_proof(keys[1], Some(&value), &proof).unwrap();
+    assert!(cache.verify_proof(keys[1], None, &proof).is_err());
+
+    // non-inclusion
+    let absent_key = random_key_with_first_byte(0x20);
+    let (blob, non_inclusion_proof) = tree.get_with_proof(absent_key, 0).unwrap();
+    assert!(blob.is_none());
+    cache
+        .verify_proof(absent_key, None, &non_inclusion_proof)
+        .unwrap();
+
+    // tampered proofs
+    let other_value: Vec<u8> = random_blob().into();
+    assert!(cache
+        .verify_proof(keys[1], Some(&other_value), &proof)
+        .is_err());
+    let mut siblings = proof.siblings().to_vec();
+    siblings[0] = HashValue::random();
+    let tampered_proof = SparseMerkleProof::new(proof.leaf(), siblings);
+    assert!(cache
+        .verify_proof(keys[1], Some(&value), &tampered_proof)
+        .is_err());
+
+    // the proofs are checked against the current version of the tree
+    JellyfishMerkleTree::put(keys[0], random_blob(), 1, &mut cache).unwrap();
+    assert!(cache.verify_proof(keys[1], Some(&value), &proof).is_err());
+}
+
+#[test]
+fn test_frozen_handle() {
+    let next_version = 0;
+    let db = MockTreeStore::default();
+    let mut cache = TreeCache::new(&db, next_version).unwrap();
+
+    let (node1, node1_key) = random_leaf_with_key(next_version);
+    cache.put_node(node1_key.clone(), node1.clone()).unwrap();
+    cache.freeze();
+    let handle = cache.frozen_handle();
+
+    // nodes frozen after the handle was taken are not visible through it
+    let (node2, node2_key) = random_leaf_with_key(next_version + 1);
+    cache.put_node(node2_key.clone(), node2.clone()).unwrap();
+    cache.freeze();
+    assert_eq!(handle.get_node_option(&node2_key).unwrap(), None);
+    assert_eq!(cache.get_node(&node2_key).unwrap(), node2);
+
+    let task_handle = handle.clone();
+    let reader = thread::spawn(move || task_handle.get_node(&node1_key).unwrap());
+    // the handle outlives the cache it was taken from
+    let (_, update_batch) = cache.into();
+    assert_eq!(update_batch.node_batch.len(), 3);
+    assert_eq!(reader.join().unwrap(), node1);
+    assert_eq!(handle.num_nodes(), 2);
+}
+
+#[test]
+fn test_estimated_proof_size() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x02, 0x11, 0x12, 0x13, 0xf0]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let cache = TreeCache::new(&db, 1).unwrap();
+
+    let proof_size = |proof: &SparseMerkleProof| {
+        proof.siblings().len() * HashValue::LENGTH
+            + proof.leaf().map_or(0, |_| 2 * HashValue::LENGTH)
+    };
+    for key in keys
+        .iter()
+        .cloned()
+        .chain(vec![random_key_with_first_byte(0x20)])
+    {
+        let (_blob, proof) = tree.get_with_proof(key, 0).unwrap();
+        let actual = proof_size(&proof);
+        let estimate = cache.estimated_proof_size(key).unwrap();
+        assert!(estimate >= actual);
+        assert!(estimate - actual <= 3 * HashValue::LENGTH);
+    }
+}
+
+#[test]
+fn test_pending_digest() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x01, 0x12, 0x02, 0x11]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let blobs: Vec<_> = keys.iter().map(|_| random_blob()).collect();
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys[..2].iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+    let empty_digest = TreeCache::new(&db, 1).unwrap().pending_digest();
+
+    // an update of an existing key and two inserts next to existing leaves, in either order
+    let put_all = |order: &[usize]| {
+        let mut cache = TreeCache::new(&db, 1).unwrap();
+        for i in order {
+            JellyfishMerkleTree::put(keys[*i], blobs[*i].clone(), 1, &mut cache).unwrap();
+        }
+        cache
+    };
+    let mut cache = put_all(&[1, 2, 3]);
+    let digest = cache.pending_digest();
+    assert_ne!(digest, empty_digest);
+    assert_eq!(put_all(&[3, 2, 1]).pending_digest(), digest);
+    cache.freeze();
+    assert_eq!(cache.pending_digest(), digest);
+
+    // differing changes give differing digests
+    assert_ne!(put_all(&[1, 2]).pending_digest(), digest);
+    let mut other_cache = put_all(&[1, 2]);
+    JellyfishMerkleTree::put(keys[3], random_blob(), 1, &mut other_cache).unwrap();
+    assert_ne!(other_cache.pending_digest(), digest);
+}
+
+#[test]
+fn test_validate_batch() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            (0..2)
+                .map(|_| (HashValue::random(), random_blob()))
+                .collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // nodes put by a frozen version and made stale by the next one are fine
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    for _ in 0..2 {
+        JellyfishMerkleTree::put(HashValue::random(), random_blob(), 1, &mut cache).unwrap();
+    }
+    cache.validate_batch().unwrap();
+    cache.freeze();
+    JellyfishMerkleTree::put(HashValue::random(), random_blob(), 2, &mut cache).unwrap();
+    cache.validate_batch().unwrap();
+
+    // a node both put and made stale by the same version
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    let (node, node_key) = random_leaf_with_key(1);
+    cache.delete_node(&node_key, true /* is_leaf */);
+    cache.put_node(node_key, node).unwrap();
+    assert!(cache.validate_batch().is_err());
+    cache.freeze();
+    assert!(cache.validate_batch().is_err());
+
+    // a node made stale that never existed
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    let (_node, node_key) = random_leaf_with_key(0);
+    cache.delete_node(&node_key, true /* is_leaf */);
+    assert!(cache.validate_batch().is_err());
+}
+
+#[test]
+fn test_find_orphans() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            (0..10)
+                .map(|_| (HashValue::random(), random_blob()))
+                .collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // the nodes of every frozen version are reachable from its root, even once made stale
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    for version in 1..4 {
+        for _ in 0..3 {
+            JellyfishMerkleTree::put(HashValue::random(), random_blob(), version, &mut cache)
+                .unwrap();
+        }
+        cache.freeze();
+    }
+    assert!(cache.find_orphans().unwrap().is_empty());
+
+    // a node put without linking it to the tree
+    let (node, node_key) = random_leaf_with_key(4);
+    cache.put_node(node_key.clone(), node).unwrap();
+    JellyfishMerkleTree::put(HashValue::random(), random_blob(), 4, &mut cache).unwrap();
+    // only frozen nodes are checked
+    assert!(cache.find_orphans().unwrap().is_empty());
+    cache.freeze();
+    assert_eq!(cache.find_orphans().unwrap(), vec![node_key]);
+}
+
+#[test]
+fn test_find_orphans_same_root_hash() {
+    // the root of version 0 is reached from its own version, even though version 1 has the same
+    // root hash
+    let db = MockTreeStore::default();
+    let cache = cache_with_same_root_hash_twice(&db);
+    assert!(cache.find_orphans().unwrap().is_empty());
+}
+
+#[test]
+fn test_modified_keys() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    let keys: Vec<_> = [0x00, 0x10, 0x20, 0x30, 0x40, 0x41]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    let blob = |value: u8| AccountStateBlob::from(vec![value]);
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            (0..5).map(|i| (keys[i], blob(i as u8))).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // two leaves updated, and one put again with the value it had
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    JellyfishMerkleTree::put(keys[0], blob(10), 1, &mut cache).unwrap();
+    JellyfishMerkleTree::put(keys[1], blob(11), 1, &mut cache).unwrap();
+    JellyfishMerkleTree::put(keys[3], blob(3), 1, &mut cache).unwrap();
+    assert_eq!(cache.modified_keys().unwrap(), vec![keys[0], keys[1]]);
+    cache.freeze();
+
+    // a leaf removed, and one inserted next to keys[4], which is moved down with the same value
+    cache
+        .apply_updates(vec![(keys[2], None), (keys[5], Some(vec![5]))])
+        .unwrap();
+    assert_eq!(
+        cache.modified_keys().unwrap(),
+        vec![keys[0], keys[1], keys[2], keys[5]]
+    );
+}
+
+#[test]
+fn test_write_amplification() {
+    let db = MockTreeStore::default();
+    let tree = JellyfishMerkleTree::new(&db);
+    // the first two keys share their first 6 nibbles, below which they hang 7 levels deep
+    let key_with_prefix = |prefix: &[u8]| {
+        let mut bytes = HashValue::random().to_vec();
+        bytes[..prefix.len()].copy_from_slice(prefix);
+        HashValue::from_slice(&bytes).unwrap()
+    };
+    let keys = vec![
+        key_with_prefix(&[0x12, 0x34, 0x56, 0x00]),
+        key_with_prefix(&[0x12, 0x34, 0x56, 0xf0]),
+        key_with_prefix(&[0xf0]),
+    ];
+    let (_root_hash, batch) = tree
+        .put_blob_set(
+            keys.iter().map(|key| (*key, random_blob())).collect(),
+            0, /* version */
+        )
+        .unwrap();
+    db.write_tree_update_batch(batch).unwrap();
+
+    // nothing is modified yet
+    let mut cache = TreeCache::new(&db, 1).unwrap();
+    assert!(cache.write_amplification().is_err());
+
+    // the deep leaf rewrites the 7 internal nodes above it
+    JellyfishMerkleTree::put(keys[0], random_blob(), 1, &mut cache).unwrap();
+    assert_eq!(cache.write_amplification().unwrap(), 8.0);
+
+    // the shallow leaf only adds itself, sharing the root
+    JellyfishMerkleTree::put(keys[2], random_blob(), 1, &mut cache).unwrap();
+    cache.freeze();
+    assert_eq!(cache.write_amplification().unwrap(), 4.5);
+}
+
+/// Applies `updates` on top of the tree at `version - 1` in `db`, writes the resulting batch and
+/// checks that the tree at `version` holds exactly `expected`.
+fn apply_and_check(
+    db: &MockTreeStore,
+    version: Version,
+    updates: Vec<(HashValue, Option<Vec<u8>>)>,
+    expected: &BTreeMap<HashValue, AccountStateBlob>,
+) {
+    let mut cache = TreeCache::new(db, version).unwrap();
+    cache.set_verify_on_put(true);
+    let root_hash = cache.apply_updates(updates).unwrap();
+    cache.freeze();
+    let (root_hashes, batch) = cache.into();
+    assert_eq!(root_hashes, vec![root_hash]);
+    db.write_tree_update_batch(batch).unwrap();
+
+    // the same leaves put into an empty tree give the same root
+    let expected_root_hash = if expected.is_empty() {
+        Node::new_null().hash()
+    } else {
+        let fresh_db = MockTreeStore::default();
+        JellyfishMerkleTree::new(&fresh_db)
+            .put_blob_set(
+                expected
+                    .iter()
+                    .map(|(key, blob)| (*key, blob.clone()))
+                    .collect(),
+                0, /* version */
+            )
+            .unwrap()
+            .0
+    };
+    assert_eq!(root_hash, expected_root_hash);
+
+    let tree = JellyfishMerkleTree::new(db);
+    assert_eq!(tree.get_root_hash(version).unwrap(), root_hash);
+    for (key, blob) in expected {
+        let (value, proof) = tree.get_with_proof(*key, version).unwrap();
+        assert_eq!(value.as_ref(), Some(blob));
+        proof.verify(root_hash, *key, value.as_ref()).unwrap();
+    }
+}
+
+/// Records the update of `key` to `value` in `expected` and returns it.
+fn update(
+    expected: &mut BTreeMap<HashValue, AccountStateBlob>,
+    key: HashValue,
+    value: Option<Vec<u8>>,
+) -> (HashValue, Option<Vec<u8>>) {
+    match &value {
+        Some(value) => expected.insert(key, AccountStateBlob::from(value.clone())),
+        None => expected.remove(&key),
+    };
+    (key, value)
+}
+
+#[test]
+fn test_apply_updates() {
+    let db = MockTreeStore::default();
+    let mut expected = BTreeMap::new();
+    let keys: Vec<_> = [0x00, 0x01, 0x10, 0x11, 0x12, 0x20, 0xf0]
+        .iter()
+        .map(|first_byte| random_key_with_first_byte(*first_byte))
+        .collect();
+    // inserts into an empty tree, out of order
+    let updates = vec![
+        update(&mut expected, keys[3], Some(vec![3])),
+        update(&mut expected, keys[0], Some(vec![0])),
+        update(&mut expected, keys[1], Some(vec![1])),
+        update(&mut expected, keys[4], Some(vec![4])),
+    ];
+    apply_and_check(&db, 0, updates, &expected);
+
+    // updates of existing keys
+    let updates = vec![
+        update(&mut expected, keys[0], Some(vec![10])),
+        update(&mut expected, keys[4], Some(vec![14])),
+    ];
+    apply_and_check(&db, 1, updates, &expected);
+
+    // deletes, including one of a missing key, leaving a single leaf below the root
+    let updates = vec![
+        update(&mut expected, keys[1], None),
+        update(&mut expected, keys[3], None),
+        update(&mut expected, keys[4], None),
+        update(&mut expected, keys[6], None),
+    ];
+    apply_and_check(&db, 2, updates, &expected);
+
+    // a mix of inserts, updates and deletes, the last update of a key winning
+    let updates = vec![
+        update(&mut expected, keys[2], Some(vec![2])),
+        update(&mut expected, keys[0], None),
+        update(&mut expected, keys[5], Some(vec![5])),
+        update(&mut expected, keys[6], Some(vec![6])),
+        update(&mut expected, keys[5], Some(vec![15])),
+        update(&mut expected, keys[3], Some(vec![3])),
+    ];
+    apply_and_check(&db, 3, updates, &expected);
+
+    // deleting everything leaves an empty tree, which can be filled again
+    let updates = keys
+        .iter()
+        .map(|key| update(&mut expected, *key, None))
+        .collect();
+    apply_and_check(&db, 4, updates, &expected);
+    let updates = vec![update(&mut expected, keys[1], Some(vec![1]))];
+    apply_and_check(&db, 5, updates, &expected);
+}
+
+#[test]
+fn test_apply_updates_random() {
+    let db = MockTreeStore::default();
+    let mut expected = BTreeMap::new();
+    let mut keys = vec![];
+    for version in 0..10 {
+        let mut updates = vec![];
+        for _ in 0..20 {
+            let key = HashValue::random();
+            let blob = random_blob();
+            expected.insert(key, blob.clone());
+            keys.push(key);
+            updates.push((key, Some(blob.into())));
+        }
+        // delete every third key put so far
+        for key in keys.iter().skip(version as usize % 3).step_by(3) {
+            if expected.remove(key).is_some() {
+                updates.push((*key, None));
+            }
+        }
+        apply_and_check(&db, version, updates, &expected);
+    }
+}
+
+/// Writes a tree at version 0 with `keys` to `db` and returns the updates changing the first key
+/// and deleting the second one at version 1, together with the root hash they lead to.
+fn setup_checked_updates(
+    db: &MockTreeStore,
+    keys: &[HashValue],
+) -> (Vec<(HashValue, Option<Vec<u8>>)>, HashValue) {
+    let mut cache = TreeCache::new(db, 0 /* next_version */).unwrap();
+    cache
+        .apply_updates(keys.iter().map(|key| (*key, Some(vec![0]))).collect())
+        .unwrap();
+    cache.freeze();
+    let (_, batch) = cache.into();
+    db.write_tree_update_batch(batch).unwrap();
+
+    let updates = vec![(keys[0], Some(vec![1])), (keys[1], None)];
+    let mut cache = TreeCache::new(db, 1 /* next_version */).unwrap();
+    let root_hash = cache.apply_updates(updates.clone()).unwrap();
+    (updates, root_hash)
+}
+
+#[test]
+fn test_apply_updates_checked() {
+    let db = MockTreeStore::default();
+    let keys: Vec<_> = (0..4).map(|i| random_key_with_first_byte(i << 4)).collect();
+    let (updates, root_hash) = setup_checked_updates(&db, &keys);
+
+    let mut cache = TreeCache::new(&db, 1 /* next_version */).unwrap();
+    cache.apply_updates_checked(updates, root_hash).unwrap();
+    // no savepoint was held before, so mutations are not recorded anymore
+    assert!(cache.undo_log.is_none());
+    cache.freeze();
+    let (root_hashes, batch) = cache.into();
+    assert_eq!(root_hashes, vec![root_hash]);
+    db.write_tree_update_batch(batch).unwrap();
+
+    let tree = JellyfishMerkleTree::new(&db);
+    assert_eq!(tree.get_root_hash(1).unwrap(), root_hash);
+    assert_eq!(
+        tree.get(keys[0], 1).unwrap(),
+        Some(AccountStateBlob::from(vec![1]))
+    );
+    assert_eq!(tree.get(keys[1], 1).unwrap(), None);
+}
+
+#[test]
+fn test_apply_updates_checked_mismatch() {
+    let db = MockTreeStore::default();
+    let keys: Vec<_> = (0..4).map(|i| random_key_with_first_byte(i << 4)).collect();
+    let (updates, root_hash) = setup_checked_updates(&db, &keys);
+
+    let mut cache = TreeCache::new(&db, 1 /* next_version */).unwrap();
+    let outer = cache.savepoint();
+    let node_cache = cache.node_cache.clone();
+    let stale_node_index_cache = cache.stale_node_index_cache.clone();
+    let (num_new_leaves, num_stale_leaves) = (cache.num_new_leaves, cache.num_stale_leaves);
+    let root_node_key = cache.get_root_node_key().clone();
+
+    let wrong_root_hash = HashValue::random();
+    let error = cache
+        .apply_updates_checked(updates.clone(), wrong_root_hash)
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<JellyfishMerkleError>(),
+        Some(&JellyfishMerkleError::RootMismatch {
+            expected: wrong_root_hash,
+            actual: root_hash,
+        })
+    );
+    assert_eq!(cache.node_cache, node_cache);
+    assert_eq!(cache.stale_node_index_cache, stale_node_index_cache);
+    assert_eq!(cache.num_new_leaves, num_new_leaves);
+    assert_eq!(cache.num_stale_leaves, num_stale_leaves);
+    assert_eq!(*cache.get_root_node_key(), root_node_key);
+
+    // the savepoint held by the caller survives, and the cache can be updated again
+    cache.apply_updates_checked(updates, root_hash).unwrap();
+    cache.rollback_to_savepoint(&outer).unwrap();
+    assert_eq!(cache.node_cache, node_cache);
+}
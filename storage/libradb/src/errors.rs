@@ -4,6 +4,7 @@
 //! This module defines error types used by [`LibraDB`](crate::LibraDB).
 
 use failure::Fail;
+use jellyfish_merkle::node_type::NodeKey;
 
 /// This enum defines errors commonly used among [`LibraDB`](crate::LibraDB) APIs.
 #[derive(Debug, Fail)]
@@ -17,4 +18,11 @@ pub enum LibraDbError {
         _0, _1
     )]
     TooManyRequested(u64, u64),
+    /// A state Merkle tree node read from disk does not match the checksum stored with it, i.e. it
+    /// was corrupted since it was written.
+    #[fail(
+        display = "Checksum mismatch for state Merkle tree node {:?}: stored {:08x}, computed {:08x}.",
+        _0, _1, _2
+    )]
+    NodeChecksumMismatch(NodeKey, u32, u32),
 }
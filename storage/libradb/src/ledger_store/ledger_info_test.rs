@@ -51,7 +51,9 @@ proptest! {
         let start_epoch = ledger_infos_with_sigs.first().unwrap().ledger_info().epoch();
         let ledger_version = ledger_infos_with_sigs.last().unwrap().ledger_info().version();
         prop_assert_eq!(
-            &store.get_epoch_change_ledger_infos(start_epoch, ledger_version).unwrap(),
+            &store
+                .get_epoch_change_ledger_infos(start_epoch, u64::max_value(), ledger_version)
+                .unwrap(),
             &epoch_ledgers
         );
 
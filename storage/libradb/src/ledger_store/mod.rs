@@ -23,11 +23,13 @@ use libra_crypto::{
 };
 use libra_types::{
     crypto_proxies::LedgerInfoWithSignatures,
+    ledger_info::LedgerInfo,
     proof::{
         position::Position, AccumulatorConsistencyProof, TransactionAccumulatorProof,
         TransactionAccumulatorRangeProof,
     },
     transaction::{TransactionInfo, Version},
+    validator_set::ValidatorSet,
 };
 use schemadb::{ReadOptions, DB};
 use std::{ops::Deref, sync::Arc};
@@ -91,6 +93,7 @@ impl LedgerStore {
     pub fn get_epoch_change_ledger_infos(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
         ledger_version: Version,
     ) -> Result<Vec<LedgerInfoWithSignatures>> {
         let mut iter = self.db.iter::<LedgerInfoSchema>(ReadOptions::default())?;
@@ -98,7 +101,10 @@ impl LedgerStore {
 
         let mut result = Vec::new();
         for res in iter {
-            let (_epoch, ledger_info_with_sigs) = res?;
+            let (epoch, ledger_info_with_sigs) = res?;
+            if epoch >= end_epoch {
+                break;
+            }
             if ledger_info_with_sigs.ledger_info().version() > ledger_version {
                 break;
             }
@@ -138,6 +144,26 @@ impl LedgerStore {
             .store(Arc::new(Some(ledger_info_with_sigs)));
     }
 
+    /// Gets the validator set signing the ledger infos after `ledger_info`: its next validator set
+    /// if it ends an epoch, otherwise the one set by the ledger info ending the previous epoch.
+    pub fn get_validator_set_after(&self, ledger_info: &LedgerInfo) -> Result<ValidatorSet> {
+        if let Some(validator_set) = ledger_info.next_validator_set() {
+            return Ok(validator_set.clone());
+        }
+        let epoch = ledger_info.epoch();
+        ensure!(
+            epoch > 0,
+            "No validator set before ledger info of epoch 0 at version {}",
+            ledger_info.version()
+        );
+        self.db
+            .get::<LedgerInfoSchema>(&(epoch - 1))?
+            .and_then(|li| li.ledger_info().next_validator_set().cloned())
+            .ok_or_else(|| {
+                LibraDbError::NotFound(format!("Validator set of epoch {}", epoch)).into()
+            })
+    }
+
     /// Get transaction info given `version`
     pub fn get_transaction_info(&self, version: Version) -> Result<TransactionInfo> {
         self.db
@@ -21,6 +21,7 @@ mod event_store;
 mod ledger_counters;
 mod ledger_store;
 mod pruner;
+mod rocksdb_metrics;
 mod state_store;
 mod system_store;
 mod transaction_store;
@@ -35,6 +36,7 @@ use crate::{
     ledger_counters::LedgerCounters,
     ledger_store::LedgerStore,
     pruner::Pruner,
+    rocksdb_metrics::RocksdbMetricsCollector,
     schema::*,
     state_store::StateStore,
     system_store::SystemStore,
@@ -42,6 +44,10 @@ use crate::{
 };
 use failure::prelude::*;
 use itertools::{izip, zip_eq};
+use jellyfish_merkle::{
+    node_type::{Node, NodeKey},
+    TreeReader,
+};
 use lazy_static::lazy_static;
 use libra_crypto::hash::{CryptoHash, HashValue};
 use libra_logger::prelude::*;
@@ -64,7 +70,13 @@ use libra_types::{
     },
 };
 use schemadb::{ColumnFamilyOptions, ColumnFamilyOptionsMap, DB, DEFAULT_CF_NAME};
-use std::{convert::TryInto, iter::Iterator, path::Path, sync::Arc, time::Instant};
+use std::{
+    convert::TryInto,
+    iter::Iterator,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use storage_proto::StartupInfo;
 use storage_proto::TreeState;
 
@@ -74,6 +86,7 @@ lazy_static! {
 
 const MAX_LIMIT: u64 = 1000;
 const MAX_REQUEST_ITEMS: u64 = 100;
+const MAX_ACCOUNT_STATES_PER_REQUEST: u64 = 1000;
 
 fn error_if_too_many_requested(num_requested: u64, max_allowed: u64) -> Result<()> {
     if num_requested > max_allowed {
@@ -92,16 +105,18 @@ pub struct LibraDB {
     state_store: StateStore,
     event_store: EventStore,
     system_store: SystemStore,
-    pruner: Pruner,
+    pruner: Option<Pruner>,
+    _rocksdb_metrics_collector: Option<RocksdbMetricsCollector>,
 }
 
 impl LibraDB {
     /// Config parameter for the pruner.
     const NUM_HISTORICAL_VERSIONS_TO_KEEP: u64 = 1_000_000;
+    /// How often the RocksDB metrics are updated unless set with `new_with_metrics_interval`.
+    const DEFAULT_ROCKSDB_METRICS_INTERVAL: Duration = Duration::from_secs(10);
 
-    /// This creates an empty LibraDB instance on disk or opens one if it already exists.
-    pub fn new<P: AsRef<Path> + Clone>(db_root_path: P) -> Self {
-        let cf_opts_map: ColumnFamilyOptionsMap = [
+    fn column_families() -> ColumnFamilyOptionsMap {
+        [
             (
                 /* LedgerInfo CF = */ DEFAULT_CF_NAME,
                 ColumnFamilyOptions::default(),
@@ -114,8 +129,20 @@ impl LibraDB {
                 JELLYFISH_MERKLE_NODE_CF_NAME,
                 ColumnFamilyOptions::default(),
             ),
+            (
+                JELLYFISH_MERKLE_NODE_CHECKSUM_CF_NAME,
+                ColumnFamilyOptions::default(),
+            ),
             (LEDGER_COUNTERS_CF_NAME, ColumnFamilyOptions::default()),
+            (
+                ROOT_NODE_KEY_BY_HASH_CF_NAME,
+                ColumnFamilyOptions::default(),
+            ),
             (STALE_NODE_INDEX_CF_NAME, ColumnFamilyOptions::default()),
+            (
+                STALE_NODE_INDEX_BY_NODE_KEY_CF_NAME,
+                ColumnFamilyOptions::default(),
+            ),
             (TRANSACTION_CF_NAME, ColumnFamilyOptions::default()),
             (
                 TRANSACTION_ACCUMULATOR_CF_NAME,
@@ -130,20 +157,25 @@ impl LibraDB {
         ]
         .iter()
         .cloned()
-        .collect();
-
-        let path = db_root_path.as_ref().join("libradb");
-        let instant = Instant::now();
-        let db = Arc::new(
-            DB::open(path.clone(), cf_opts_map)
-                .unwrap_or_else(|e| panic!("LibraDB open failed: {:?}", e)),
-        );
+        .collect()
+    }
 
-        info!(
-            "Opened LibraDB at {:?} in {} ms",
-            path,
-            instant.elapsed().as_millis()
-        );
+    fn new_with_db(
+        db: DB,
+        pruner_enabled: bool,
+        rocksdb_metrics_interval: Option<Duration>,
+    ) -> Self {
+        let db = Arc::new(db);
+        let pruner = if pruner_enabled {
+            Some(Pruner::new(
+                Arc::clone(&db),
+                Self::NUM_HISTORICAL_VERSIONS_TO_KEEP,
+            ))
+        } else {
+            None
+        };
+        let rocksdb_metrics_collector = rocksdb_metrics_interval
+            .map(|interval| RocksdbMetricsCollector::new(Arc::clone(&db), interval));
 
         LibraDB {
             db: Arc::clone(&db),
@@ -152,10 +184,57 @@ impl LibraDB {
             state_store: StateStore::new(Arc::clone(&db)),
             transaction_store: TransactionStore::new(Arc::clone(&db)),
             system_store: SystemStore::new(Arc::clone(&db)),
-            pruner: Pruner::new(Arc::clone(&db), Self::NUM_HISTORICAL_VERSIONS_TO_KEEP),
+            pruner,
+            _rocksdb_metrics_collector: rocksdb_metrics_collector,
         }
     }
 
+    /// This creates an empty LibraDB instance on disk or opens one if it already exists.
+    pub fn new<P: AsRef<Path> + Clone>(db_root_path: P) -> Self {
+        Self::new_with_metrics_interval(db_root_path, Self::DEFAULT_ROCKSDB_METRICS_INTERVAL)
+    }
+
+    /// Same as `new`, with the internal statistics of RocksDB published as metrics every
+    /// `rocksdb_metrics_interval`.
+    pub fn new_with_metrics_interval<P: AsRef<Path> + Clone>(
+        db_root_path: P,
+        rocksdb_metrics_interval: Duration,
+    ) -> Self {
+        let path = db_root_path.as_ref().join("libradb");
+        let instant = Instant::now();
+        let db = DB::open(path.clone(), Self::column_families())
+            .unwrap_or_else(|e| panic!("LibraDB open failed: {:?}", e));
+
+        info!(
+            "Opened LibraDB at {:?} in {} ms",
+            path,
+            instant.elapsed().as_millis()
+        );
+
+        Self::new_with_db(
+            db,
+            true, /* pruner_enabled */
+            Some(rocksdb_metrics_interval),
+        )
+    }
+
+    /// Opens an existing LibraDB instance in read-only mode, e.g. for offline tools that inspect
+    /// the DB of a running node. No pruner or RocksDB metrics thread is started and all write APIs
+    /// will fail.
+    pub fn open_readonly<P: AsRef<Path> + Clone>(db_root_path: P) -> Result<Self> {
+        let path = db_root_path.as_ref().join("libradb");
+        let instant = Instant::now();
+        let db = DB::open_readonly(path.clone(), Self::column_families())?;
+
+        info!(
+            "Opened LibraDB at {:?} in read-only mode in {} ms",
+            path,
+            instant.elapsed().as_millis()
+        );
+
+        Ok(Self::new_with_db(db, false /* pruner_enabled */, None))
+    }
+
     // ================================== Public API ==================================
     /// Returns the account state corresponding to the given version and account address with proof
     /// based on `ledger_version`
@@ -304,13 +383,18 @@ impl LibraDB {
             .version())
     }
 
-    /// Returns ledger infos reflecting epoch bumps starting with the given epoch.
+    /// Returns ledger infos reflecting epoch bumps starting with `start_epoch` and ending before
+    /// `end_epoch`.
     pub fn get_epoch_change_ledger_infos(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
     ) -> Result<Vec<LedgerInfoWithSignatures>> {
-        self.ledger_store
-            .get_epoch_change_ledger_infos(start_epoch, self.get_latest_version()?)
+        self.ledger_store.get_epoch_change_ledger_infos(
+            start_epoch,
+            end_epoch,
+            self.get_latest_version()?,
+        )
     }
 
     /// Persist transactions. Called by the executor module when either syncing nodes or committing
@@ -382,7 +466,9 @@ impl LibraDB {
                 .expect("Counters should be bumped with transactions being saved.")
                 .bump_op_counters();
 
-            self.pruner.wake(last_version);
+            if let Some(pruner) = self.pruner.as_ref() {
+                pruner.wake(last_version);
+            }
         }
 
         Ok(())
@@ -456,6 +542,14 @@ impl LibraDB {
         AccumulatorConsistencyProof,
     )> {
         error_if_too_many_requested(request_items.len() as u64, MAX_REQUEST_ITEMS)?;
+        for request_item in &request_items {
+            if let RequestItem::GetAccountStates { addresses } = request_item {
+                error_if_too_many_requested(
+                    addresses.len() as u64,
+                    MAX_ACCOUNT_STATES_PER_REQUEST,
+                )?;
+            }
+        }
 
         // Get the latest ledger info and signatures
         let ledger_info_with_sigs = self.ledger_store.get_latest_ledger_info()?;
@@ -531,6 +625,30 @@ impl LibraDB {
                         txn_list_with_proof,
                     })
                 }
+                RequestItem::GetAccountStateByVersion { address, version } => {
+                    Ok(ResponseItem::GetAccountState {
+                        account_state_with_proof: self.get_account_state_with_proof(
+                            address,
+                            version,
+                            ledger_version,
+                        )?,
+                    })
+                }
+                RequestItem::GetAccountStates { addresses } => {
+                    let account_states_with_proof = addresses
+                        .into_iter()
+                        .map(|address| {
+                            self.get_account_state_with_proof(
+                                address,
+                                ledger_version,
+                                ledger_version,
+                            )
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    Ok(ResponseItem::GetAccountStates {
+                        account_states_with_proof,
+                    })
+                }
             })
             .collect::<Result<Vec<_>>>()?;
 
@@ -542,8 +660,11 @@ impl LibraDB {
             ledger_info.epoch()
         };
         let validator_change_proof = if client_epoch < current_epoch {
-            self.ledger_store
-                .get_epoch_change_ledger_infos(client_epoch, ledger_info.version())?
+            self.ledger_store.get_epoch_change_ledger_infos(
+                client_epoch,
+                current_epoch,
+                ledger_info.version(),
+            )?
         } else {
             Vec::new()
         };
@@ -575,20 +696,103 @@ impl LibraDB {
             .get_account_state_with_proof_by_version(address, version)
     }
 
+    /// Gets an account state by account address, out of the ledger state whose state Merkle tree
+    /// root hash is `root_hash`. Fails with `JellyfishMerkleError::UnknownRoot` if no such state is
+    /// known, or it has been pruned.
+    ///
+    /// This is used by verify-only consumers holding a trusted root hash.
+    pub fn get_account_state_with_proof_by_root(
+        &self,
+        address: AccountAddress,
+        root_hash: HashValue,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        self.state_store
+            .get_account_state_with_proof_by_root(address, root_hash)
+    }
+
+    /// Gets the state Merkle tree node identified by `node_key`, or `None` if it does not exist.
+    ///
+    /// This is used by offline tools (db-tool) to inspect the state tree.
+    pub fn get_state_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
+        self.state_store.get_node_option(node_key)
+    }
+
+    /// Gets the version since which the state Merkle tree node identified by `node_key` is stale,
+    /// or `None` if it is not known to be stale.
+    ///
+    /// This is used by offline tools (db-tool) to inspect the state tree.
+    pub fn get_retirement_info(&self, node_key: &NodeKey) -> Result<Option<Version>> {
+        self.state_store.get_retirement_info(node_key)
+    }
+
+    /// Populates the index of stale state Merkle tree nodes by node key from the stale node index,
+    /// for DBs created before that index existed. Returns the number of entries written.
+    ///
+    /// This is used by offline tools (db-tool) to maintain the DB.
+    pub fn backfill_retirement_index(&self) -> Result<usize> {
+        self.state_store.backfill_retirement_index()
+    }
+
+    /// Sets whether the state Merkle tree nodes read are checked against the checksums stored with
+    /// them, failing with `LibraDbError::NodeChecksumMismatch` on corrupted ones. On by default.
+    pub fn set_verify_node_checksums(&mut self, verify_node_checksums: bool) {
+        self.state_store
+            .set_verify_node_checksums(verify_node_checksums);
+    }
+
+    /// Checks every state Merkle tree node against the checksum stored with it, failing on the
+    /// first corrupted one. Returns the number of nodes checked and of nodes written before
+    /// checksums were introduced, which can't be.
+    ///
+    /// This is used by offline tools (db-tool) to maintain the DB.
+    pub fn verify_state_node_checksums(&self) -> Result<(usize, usize)> {
+        self.state_store.verify_all_node_checksums()
+    }
+
+    /// Returns the full statistics RocksDB keeps on the DB, e.g. the compactions of each level, as
+    /// text.
+    ///
+    /// This is used by the debug interface.
+    pub fn get_rocksdb_stats(&self) -> Result<String> {
+        self.db.get_rocksdb_stats()
+    }
+
+    /// Gets the latest version and its `TransactionInfo`, or `None` if the DB is empty. Unlike the
+    /// latest ledger info, this also covers transactions synced but not yet certified.
+    pub fn get_latest_transaction_info_option(&self) -> Result<Option<(Version, TransactionInfo)>> {
+        self.ledger_store.get_latest_transaction_info_option()
+    }
+
     /// Gets information needed from storage during the startup of the executor or state
-    /// synchronizer module.
+    /// synchronizer module, or `None` if the DB is not bootstrapped with the genesis yet.
+    ///
+    /// Everything returned is read at the versions of the latest ledger info and the latest
+    /// transaction as seen together, so a commit racing with this call can't make the DB look
+    /// like it is syncing ahead of the ledger info.
     ///
     /// This is used by the libra core (executor, state synchronizer) internally.
     pub fn get_startup_info(&self) -> Result<Option<StartupInfo>> {
-        // Get the latest ledger info. Return None if not bootstrapped.
-        let ledger_info_with_sigs = match self.ledger_store.get_latest_ledger_info_option() {
-            Some(x) => x,
-            None => return Ok(None),
+        // Get the latest ledger info together with the latest version, retrying if a commit
+        // replaced the former in between. Return None if not bootstrapped.
+        let (ledger_info, latest_version, txn_info) = loop {
+            let ledger_info_with_sigs = match self.ledger_store.get_latest_ledger_info_option() {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+            let (latest_version, txn_info) = self.ledger_store.get_latest_transaction_info()?;
+            if self.ledger_store.get_latest_ledger_info_option().as_ref()
+                == Some(&ledger_info_with_sigs)
+            {
+                break (
+                    ledger_info_with_sigs.ledger_info().clone(),
+                    latest_version,
+                    txn_info,
+                );
+            }
         };
-        let ledger_info = ledger_info_with_sigs.ledger_info().clone();
+        let latest_validator_set = self.ledger_store.get_validator_set_after(&ledger_info)?;
 
         let latest_tree_state = {
-            let (latest_version, txn_info) = self.ledger_store.get_latest_transaction_info()?;
             let account_state_root_hash = txn_info.state_root_hash();
             let ledger_frozen_subtree_hashes = self
                 .ledger_store
@@ -617,6 +821,7 @@ impl LibraDB {
                     committed_account_state_root_hash,
                 ),
                 synced_tree_state: Some(latest_tree_state),
+                latest_validator_set,
             }
         } else {
             // The version of the latest ledger info matches other data. So the storage is not in sync mode.
@@ -624,6 +829,7 @@ impl LibraDB {
                 ledger_info,
                 committed_tree_state: latest_tree_state,
                 synced_tree_state: None,
+                latest_validator_set,
             }
         };
 
@@ -371,9 +371,47 @@ fn verify_committed_transactions(
     Ok(())
 }
 
+fn test_get_startup_info_across_epochs_impl(
+    input: Vec<(Vec<TransactionToCommit>, LedgerInfoWithSignatures)>,
+) -> Result<()> {
+    let tmp_dir = TempPath::new();
+    let db = db_with_mock_genesis(&tmp_dir)?;
+
+    let mut validator_set = GENESIS_INFO.1.ledger_info().next_validator_set().cloned();
+    let mut cur_ver = 0;
+    for (txns_to_commit, ledger_info_with_sigs) in input.iter() {
+        db.save_transactions(
+            &txns_to_commit,
+            cur_ver + 1, /* first_version */
+            &Some(ledger_info_with_sigs.clone()),
+        )?;
+        cur_ver += txns_to_commit.len() as u64;
+        if let Some(next_validator_set) = ledger_info_with_sigs.ledger_info().next_validator_set() {
+            validator_set = Some(next_validator_set.clone());
+        }
+
+        let startup_info = db.get_startup_info()?.unwrap();
+        assert_eq!(
+            startup_info.ledger_info,
+            *ledger_info_with_sigs.ledger_info()
+        );
+        assert_eq!(startup_info.committed_tree_state.version, cur_ver);
+        assert_eq!(startup_info.synced_tree_state, None);
+        assert_eq!(startup_info.latest_version(), cur_ver);
+        assert_eq!(Some(startup_info.latest_validator_set), validator_set);
+    }
+
+    Ok(())
+}
+
 proptest! {
     #![proptest_config(ProptestConfig::with_cases(10))]
 
+    #[test]
+    fn test_get_startup_info_across_epochs(input in arb_blocks_to_commit()) {
+        test_get_startup_info_across_epochs_impl(input).unwrap();
+    }
+
     #[test]
     fn test_save_blocks(input in arb_blocks_to_commit()) {
         test_save_blocks_impl(input).unwrap();
@@ -412,6 +450,43 @@ fn test_bootstrap() {
     );
 }
 
+#[test]
+fn test_get_startup_info() {
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir);
+    // Not bootstrapped with the genesis yet.
+    assert_eq!(db.get_startup_info().unwrap(), None);
+
+    let genesis_txn_info = GENESIS_INFO.0.clone();
+    let genesis_ledger_info_with_sigs = GENESIS_INFO.1.clone();
+    let genesis_txn = GENESIS_INFO.2.clone();
+    db.save_transactions(
+        &[genesis_txn],
+        0, /* first_version */
+        &Some(genesis_ledger_info_with_sigs.clone()),
+    )
+    .unwrap();
+
+    let startup_info = db.get_startup_info().unwrap().unwrap();
+    assert_eq!(
+        startup_info.ledger_info,
+        *genesis_ledger_info_with_sigs.ledger_info()
+    );
+    assert_eq!(startup_info.committed_tree_state.version, 0);
+    assert_eq!(
+        startup_info.committed_tree_state.account_state_root_hash,
+        genesis_txn_info.state_root_hash()
+    );
+    assert_eq!(startup_info.synced_tree_state, None);
+    assert_eq!(startup_info.latest_version(), 0);
+    assert_eq!(
+        Some(&startup_info.latest_validator_set),
+        genesis_ledger_info_with_sigs
+            .ledger_info()
+            .next_validator_set()
+    );
+}
+
 rusty_fork_test! {
 #[test]
 fn test_committed_txns_counter() {
@@ -479,6 +554,14 @@ fn test_too_many_requested() {
             ]
         )
         .is_err());
+    assert!(db
+        .update_to_latest_ledger(
+            0,
+            vec![RequestItem::GetAccountStates {
+                addresses: vec![AccountAddress::random(); 1001],
+            }]
+        )
+        .is_err());
     assert!(db.get_transactions(0, 1001 /* limit */, 0, true).is_err());
     assert!(db
         .get_events_by_query_path(
@@ -6,8 +6,12 @@
 
 use crate::{
     schema::{
-        jellyfish_merkle_node::JellyfishMerkleNodeSchema, stale_node_index::StaleNodeIndexSchema,
+        jellyfish_merkle_node::JellyfishMerkleNodeSchema,
+        jellyfish_merkle_node_checksum::JellyfishMerkleNodeChecksumSchema,
+        root_node_key_by_hash::RootNodeKeyByHashSchema, stale_node_index::StaleNodeIndexSchema,
+        stale_node_index_by_node_key::StaleNodeIndexByNodeKeySchema,
     },
+    state_store::StateStore,
     OP_COUNTER,
 };
 use failure::prelude::*;
@@ -23,7 +27,7 @@ use std::{
 };
 
 use failure::_core::sync::atomic::Ordering;
-use jellyfish_merkle::StaleNodeIndex;
+use jellyfish_merkle::{node_type::NodeKey, StaleNodeIndex};
 #[cfg(test)]
 use std::thread::sleep;
 use std::{
@@ -132,6 +136,8 @@ enum Command {
 
 struct Worker {
     db: Arc<DB>,
+    /// Used to check that the nodes whose stale node index entries are being purged are pruned.
+    state_store: StateStore,
     command_receiver: Receiver<Command>,
     target_least_readable_version: Version,
     /// Keeps a record of the pruning progress. If this equals to version `V`, we know versions
@@ -154,6 +160,7 @@ impl Worker {
         least_readable_version: Arc<AtomicU64>,
     ) -> Self {
         Self {
+            state_store: StateStore::new(Arc::clone(&db)),
             db,
             command_receiver,
             least_readable_version,
@@ -258,6 +265,7 @@ impl Worker {
 
             if least_readable_version - self.index_min_nonpurged_version + 1 > MIN_VERSIONS {
                 let new_min_non_purged_version = least_readable_version + 1;
+                self.ensure_pruned(least_readable_version)?;
                 self.db.range_delete::<StaleNodeIndexSchema, Version>(
                     &self.index_min_nonpurged_version,
                     &new_min_non_purged_version, // end is exclusive
@@ -269,6 +277,32 @@ impl Worker {
 
         Ok(())
     }
+
+    /// Defensive check before purging the stale node index up to `version`: all the nodes stale
+    /// since `version`, the last ones pruned, must be gone. Otherwise purging would leave them in
+    /// the DB with nothing pointing at them anymore.
+    fn ensure_pruned(&self, version: Version) -> Result<()> {
+        let mut iter = self
+            .db
+            .iter::<StaleNodeIndexSchema>(ReadOptions::default())?;
+        iter.seek(&version)?;
+        for res in iter {
+            let (index, _) = res?;
+            if index.stale_since_version != version {
+                break;
+            }
+            if let Some(stale_since_version) =
+                self.state_store.get_retirement_info(&index.node_key)?
+            {
+                bail!(
+                    "Node {:?} stale since version {} is not pruned, not purging the index.",
+                    index.node_key,
+                    stale_since_version,
+                );
+            }
+        }
+        Ok(())
+    }
 }
 
 struct StaleNodeIndicesByVersionIterator<'a> {
@@ -350,12 +384,36 @@ pub fn prune_state(
         let mut batch = SchemaBatch::new();
         indices
             .into_iter()
-            .map(|index| batch.delete::<JellyfishMerkleNodeSchema>(&index.node_key))
+            .map(|index| {
+                delete_root_node_key_by_hash(&db, &mut batch, &index.node_key)?;
+                batch.delete::<JellyfishMerkleNodeSchema>(&index.node_key)?;
+                batch.delete::<JellyfishMerkleNodeChecksumSchema>(&index.node_key)?;
+                batch.delete::<StaleNodeIndexByNodeKeySchema>(&index.node_key)
+            })
             .collect::<Result<_>>()?;
         db.write_schemas(batch)?;
         Ok(new_least_readable_version)
     }
 }
 
+/// If `node_key` is a root node, deletes the entry of the root hash index pointing at it. An entry
+/// pointing at a newer root node with the same hash is left alone, since that root stays readable.
+fn delete_root_node_key_by_hash(
+    db: &DB,
+    batch: &mut SchemaBatch,
+    node_key: &NodeKey,
+) -> Result<()> {
+    if node_key.nibble_path().num_nibbles() != 0 {
+        return Ok(());
+    }
+    if let Some(root) = db.get::<JellyfishMerkleNodeSchema>(node_key)? {
+        let root_hash = root.hash();
+        if db.get::<RootNodeKeyByHashSchema>(&root_hash)?.as_ref() == Some(node_key) {
+            batch.delete::<RootNodeKeyByHashSchema>(&root_hash)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test;
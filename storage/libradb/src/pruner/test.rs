@@ -161,3 +161,108 @@ fn test_worker_quit_eagerly() {
         verify_state_in_store(state_store, address, Some(&value2), 2);
     }
 }
+
+#[test]
+fn test_ensure_pruned_before_purging_index() {
+    let address = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let value0 = AccountStateBlob::from(vec![0x01]);
+    let value1 = AccountStateBlob::from(vec![0x02]);
+    let value2 = AccountStateBlob::from(vec![0x03]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir).db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+
+    put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value0)],
+        0, /* version */
+    );
+    put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value1)],
+        1, /* version */
+    );
+    put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value2)],
+        2, /* version */
+    );
+
+    let (_command_sender, command_receiver) = channel();
+    let worker = Worker::new(
+        Arc::clone(&db),
+        command_receiver,
+        Arc::new(AtomicU64::new(0)), /* progress */
+    );
+    // The nodes stale since version 1 are still there, the index must not be purged.
+    assert!(worker.ensure_pruned(1).is_err());
+
+    prune_state(
+        Arc::clone(&db),
+        0,   /* least_readable_version */
+        1,   /* target_least_readable_version */
+        100, /* max_versions */
+    )
+    .unwrap();
+    worker.ensure_pruned(1).unwrap();
+    // No node is stale since version 3 (yet).
+    worker.ensure_pruned(3).unwrap();
+    assert!(worker.ensure_pruned(2).is_err());
+}
+
+#[test]
+fn test_pruner_deletes_root_node_key_by_hash() {
+    let address = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let value0 = AccountStateBlob::from(vec![0x01]);
+    let value1 = AccountStateBlob::from(vec![0x02]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir).db;
+    let state_store = &StateStore::new(Arc::clone(&db));
+    let pruner = Pruner::new(
+        Arc::clone(&db),
+        0, /* num_historical_versions_to_keep */
+    );
+
+    let root0 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value0.clone())],
+        0, /* version */
+    );
+    let root1 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value1)],
+        1, /* version */
+    );
+    // Same state as version 0, so the index entry of `root0` now points at the root of version 2.
+    let root2 = put_account_state_set(
+        &db,
+        state_store,
+        vec![(address, value0.clone())],
+        2, /* version */
+    );
+    assert_eq!(root2, root0);
+    assert!(db.get::<RootNodeKeyByHashSchema>(&root1).unwrap().is_some());
+
+    // Prune till version=2.
+    {
+        pruner.wake_and_wait(2 /* latest_version */).unwrap();
+        // The entry of root1 is gone with its root node.
+        assert!(db.get::<RootNodeKeyByHashSchema>(&root1).unwrap().is_none());
+        assert!(state_store
+            .get_account_state_with_proof_by_root(address, root1)
+            .is_err());
+        // The entry of root0 points at the root of version 2, which is still there.
+        assert!(db.get::<RootNodeKeyByHashSchema>(&root0).unwrap().is_some());
+        let (value, _proof) = state_store
+            .get_account_state_with_proof_by_root(address, root0)
+            .unwrap();
+        assert_eq!(value, Some(value0));
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module provides `RocksdbMetricsCollector` which manages a thread publishing the internal
+//! statistics of RocksDB as metrics in the background.
+
+use libra_logger::prelude::*;
+use schemadb::DB;
+use std::{
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+
+/// The `RocksdbMetricsCollector` is meant to be part of a `LibraDB` instance. It periodically
+/// calls `DB::update_rocksdb_metrics` on a worker thread.
+///
+/// It creates the worker thread on construction and joins it on destruction.
+pub(crate) struct RocksdbMetricsCollector {
+    /// The worker thread handle, created upon construction and joined upon destruction. It only
+    /// becomes `None` after joined in `drop()`.
+    worker_thread: Option<JoinHandle<()>>,
+    /// Tells the worker thread to quit.
+    quit_sender: Mutex<Sender<()>>,
+}
+
+impl RocksdbMetricsCollector {
+    /// Creates a worker thread that updates the RocksDB metrics of `db` every `interval`.
+    pub fn new(db: Arc<DB>, interval: Duration) -> Self {
+        let (quit_sender, quit_receiver) = channel();
+
+        let worker_thread = std::thread::Builder::new()
+            .name("libradb_rocksdb_metrics".into())
+            .spawn(move || loop {
+                if let Err(e) = db.update_rocksdb_metrics() {
+                    warn!("Failed updating RocksDB metrics, ignored. Err: {}", e);
+                }
+                match quit_receiver.recv_timeout(interval) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(()) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            })
+            .expect("Creating RocksDB metrics thread should succeed.");
+
+        Self {
+            worker_thread: Some(worker_thread),
+            quit_sender: Mutex::new(quit_sender),
+        }
+    }
+}
+
+impl Drop for RocksdbMetricsCollector {
+    fn drop(&mut self) {
+        self.quit_sender
+            .lock()
+            .expect("Locking quit_sender should not fail.")
+            .send(())
+            .expect("Receiver should not destruct.");
+        self.worker_thread
+            .take()
+            .expect("Worker thread must exist.")
+            .join()
+            .expect("Worker thread should join peacefully.");
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines physical storage schema for the checksums of the nodes in the state
+//! Jellyfish Merkle tree, used to detect nodes silently corrupted on disk. Entries are written in
+//! the same batch as their nodes in
+//! [`JellyfishMerkleNodeSchema`](crate::schema::jellyfish_merkle_node::JellyfishMerkleNodeSchema)
+//! and deleted together with them when they are pruned. Nodes written before checksums were
+//! introduced have none.
+//!
+//! The checksum is the CRC32 of the serialized node.
+//!
+//! ```text
+//! |<--key--->|<--value-->|
+//! | node_key | checksum  |
+//! ```
+
+use crate::schema::{ensure_slice_len_eq, JELLYFISH_MERKLE_NODE_CHECKSUM_CF_NAME};
+use byteorder::{BigEndian, ReadBytesExt};
+use failure::prelude::*;
+use jellyfish_merkle::node_type::{Node, NodeKey};
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+};
+use std::mem::size_of;
+
+define_schema!(
+    JellyfishMerkleNodeChecksumSchema,
+    NodeKey,
+    u32, // checksum
+    JELLYFISH_MERKLE_NODE_CHECKSUM_CF_NAME
+);
+
+impl KeyCodec<JellyfishMerkleNodeChecksumSchema> for NodeKey {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        self.encode()
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        Self::decode(data)
+    }
+}
+
+impl ValueCodec<JellyfishMerkleNodeChecksumSchema> for u32 {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+        Ok((&data[..]).read_u32::<BigEndian>()?)
+    }
+}
+
+/// Computes the checksum stored for `node`.
+pub(crate) fn node_checksum(node: &Node) -> Result<u32> {
+    Ok(crc32fast::hash(&node.encode()?))
+}
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,16 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use proptest::prelude::*;
+use schemadb::schema::assert_encode_decode;
+
+proptest! {
+    #[test]
+    fn test_encode_decode(
+        node_key in any::<NodeKey>(),
+        checksum in any::<u32>(),
+    ) {
+        assert_encode_decode::<JellyfishMerkleNodeChecksumSchema>(&node_key, &checksum);
+    }
+}
@@ -11,9 +11,12 @@ pub(crate) mod event;
 pub(crate) mod event_accumulator;
 pub(crate) mod event_by_key;
 pub(crate) mod jellyfish_merkle_node;
+pub(crate) mod jellyfish_merkle_node_checksum;
 pub(crate) mod ledger_counters;
 pub(crate) mod ledger_info;
+pub(crate) mod root_node_key_by_hash;
 pub(crate) mod stale_node_index;
+pub(crate) mod stale_node_index_by_node_key;
 pub(crate) mod transaction;
 pub(crate) mod transaction_accumulator;
 pub(crate) mod transaction_by_account;
@@ -28,8 +31,13 @@ pub(super) const EVENT_ACCUMULATOR_CF_NAME: ColumnFamilyName = "event_accumulato
 pub(super) const EVENT_BY_KEY_CF_NAME: ColumnFamilyName = "event_by_key";
 pub(super) const EVENT_CF_NAME: ColumnFamilyName = "event";
 pub(super) const JELLYFISH_MERKLE_NODE_CF_NAME: ColumnFamilyName = "jellyfish_merkle_node";
+pub(super) const JELLYFISH_MERKLE_NODE_CHECKSUM_CF_NAME: ColumnFamilyName =
+    "jellyfish_merkle_node_checksum";
 pub(super) const LEDGER_COUNTERS_CF_NAME: ColumnFamilyName = "ledger_counters";
+pub(super) const ROOT_NODE_KEY_BY_HASH_CF_NAME: ColumnFamilyName = "root_node_key_by_hash";
 pub(super) const STALE_NODE_INDEX_CF_NAME: ColumnFamilyName = "stale_node_index";
+pub(super) const STALE_NODE_INDEX_BY_NODE_KEY_CF_NAME: ColumnFamilyName =
+    "stale_node_index_by_node_key";
 pub(super) const TRANSACTION_CF_NAME: ColumnFamilyName = "transaction";
 pub(super) const TRANSACTION_ACCUMULATOR_CF_NAME: ColumnFamilyName = "transaction_accumulator";
 pub(super) const TRANSACTION_BY_ACCOUNT_CF_NAME: ColumnFamilyName = "transaction_by_account";
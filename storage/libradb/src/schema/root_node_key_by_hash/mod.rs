@@ -0,0 +1,51 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines physical storage schema for an index of the root nodes of the state
+//! Jellyfish Merkle tree by root hash, so that the tree can be read given a trusted root hash
+//! instead of a version. Entries are written in the same batch as the root nodes, and deleted by
+//! the pruner together with the root nodes they point to.
+//!
+//! ```text
+//! |<---key---->|<--value-->|
+//! | root_hash  | node_key  |
+//! ```
+
+use crate::schema::ROOT_NODE_KEY_BY_HASH_CF_NAME;
+use failure::prelude::*;
+use jellyfish_merkle::node_type::NodeKey;
+use libra_crypto::HashValue;
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+};
+
+define_schema!(
+    RootNodeKeyByHashSchema,
+    HashValue,
+    NodeKey,
+    ROOT_NODE_KEY_BY_HASH_CF_NAME
+);
+
+impl KeyCodec<RootNodeKeyByHashSchema> for HashValue {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        Ok(self.to_vec())
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        Self::from_slice(data)
+    }
+}
+
+impl ValueCodec<RootNodeKeyByHashSchema> for NodeKey {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        self.encode()
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        Self::decode(data)
+    }
+}
+
+#[cfg(test)]
+mod test;
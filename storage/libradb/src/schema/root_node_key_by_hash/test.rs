@@ -0,0 +1,16 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use proptest::prelude::*;
+use schemadb::schema::assert_encode_decode;
+
+proptest! {
+    #[test]
+    fn test_encode_decode(
+        root_hash in any::<HashValue>(),
+        node_key in any::<NodeKey>(),
+    ) {
+        assert_encode_decode::<RootNodeKeyByHashSchema>(&root_hash, &node_key);
+    }
+}
@@ -0,0 +1,55 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module defines physical storage schema for a secondary index of the stale node index,
+//! looking up the version since which a state Jellyfish Merkle tree node is stale by the node's own
+//! key. Entries are written in the same batch as their counterparts in
+//! [`StaleNodeIndexSchema`](crate::schema::stale_node_index::StaleNodeIndexSchema) and deleted
+//! together with the nodes when they are pruned.
+//!
+//! ```text
+//! |<--key--->|<-------value-------->|
+//! | node_key | stale_since_version  |
+//! ```
+
+use crate::schema::{ensure_slice_len_eq, STALE_NODE_INDEX_BY_NODE_KEY_CF_NAME};
+use byteorder::{BigEndian, ReadBytesExt};
+use failure::prelude::*;
+use jellyfish_merkle::node_type::NodeKey;
+use libra_types::transaction::Version;
+use schemadb::{
+    define_schema,
+    schema::{KeyCodec, ValueCodec},
+};
+use std::mem::size_of;
+
+define_schema!(
+    StaleNodeIndexByNodeKeySchema,
+    NodeKey,
+    Version, // stale_since_version
+    STALE_NODE_INDEX_BY_NODE_KEY_CF_NAME
+);
+
+impl KeyCodec<StaleNodeIndexByNodeKeySchema> for NodeKey {
+    fn encode_key(&self) -> Result<Vec<u8>> {
+        self.encode()
+    }
+
+    fn decode_key(data: &[u8]) -> Result<Self> {
+        Self::decode(data)
+    }
+}
+
+impl ValueCodec<StaleNodeIndexByNodeKeySchema> for Version {
+    fn encode_value(&self) -> Result<Vec<u8>> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+
+    fn decode_value(data: &[u8]) -> Result<Self> {
+        ensure_slice_len_eq(data, size_of::<Self>())?;
+        Ok((&data[..]).read_u64::<BigEndian>()?)
+    }
+}
+
+#[cfg(test)]
+mod test;
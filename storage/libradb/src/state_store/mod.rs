@@ -8,10 +8,16 @@ mod state_store_test;
 
 use crate::{
     change_set::ChangeSet,
+    errors::LibraDbError,
     ledger_counters::LedgerCounter,
     schema::{
-        jellyfish_merkle_node::JellyfishMerkleNodeSchema, stale_node_index::StaleNodeIndexSchema,
+        jellyfish_merkle_node::JellyfishMerkleNodeSchema,
+        jellyfish_merkle_node_checksum::{node_checksum, JellyfishMerkleNodeChecksumSchema},
+        root_node_key_by_hash::RootNodeKeyByHashSchema,
+        stale_node_index::StaleNodeIndexSchema,
+        stale_node_index_by_node_key::StaleNodeIndexByNodeKeySchema,
     },
+    OP_COUNTER,
 };
 use failure::prelude::*;
 use jellyfish_merkle::{
@@ -23,16 +29,65 @@ use libra_types::{
     account_address::AccountAddress, account_state_blob::AccountStateBlob,
     proof::SparseMerkleProof, transaction::Version,
 };
-use schemadb::DB;
+use schemadb::{ReadOptions, SchemaBatch, DB};
 use std::{collections::HashMap, sync::Arc};
 
 pub(crate) struct StateStore {
     db: Arc<DB>,
+    // whether nodes read are checked against their stored checksums
+    verify_node_checksums: bool,
 }
 
 impl StateStore {
     pub fn new(db: Arc<DB>) -> Self {
-        Self { db }
+        Self {
+            db,
+            verify_node_checksums: true,
+        }
+    }
+
+    /// Sets whether the nodes read are checked against their stored checksums, see
+    /// `verify_node_checksum`.
+    pub fn set_verify_node_checksums(&mut self, verify_node_checksums: bool) {
+        self.verify_node_checksums = verify_node_checksums;
+    }
+
+    /// Checks `node`, read under `node_key`, against its stored checksum. Fails with
+    /// `LibraDbError::NodeChecksumMismatch` if they don't match. Returns whether the node has a
+    /// checksum, which nodes written before checksums were introduced don't.
+    fn verify_node_checksum(&self, node_key: &NodeKey, node: &Node) -> Result<bool> {
+        let stored = match self.db.get::<JellyfishMerkleNodeChecksumSchema>(node_key)? {
+            Some(stored) => stored,
+            None => return Ok(false),
+        };
+        let computed = node_checksum(node)?;
+        if computed != stored {
+            OP_COUNTER.inc("node_checksum_mismatches");
+            return Err(
+                LibraDbError::NodeChecksumMismatch(node_key.clone(), stored, computed).into(),
+            );
+        }
+        Ok(true)
+    }
+
+    /// Checks every node of the state Merkle tree against its stored checksum, failing on the
+    /// first mismatch. Returns the number of nodes checked and of nodes without a checksum.
+    pub fn verify_all_node_checksums(&self) -> Result<(usize, usize)> {
+        let mut iter = self
+            .db
+            .iter::<JellyfishMerkleNodeSchema>(ReadOptions::default())?;
+        iter.seek_to_first();
+        let mut num_verified = 0;
+        let mut num_without_checksum = 0;
+        for res in iter {
+            let (node_key, node) = res?;
+            if self.verify_node_checksum(&node_key, &node)? {
+                num_verified += 1;
+            } else {
+                num_without_checksum += 1;
+            }
+        }
+        Ok((num_verified, num_without_checksum))
     }
 
     /// Get the account state blob given account address and root hash of state Merkle tree
@@ -46,6 +101,63 @@ impl StateStore {
         Ok((blob, proof))
     }
 
+    /// Get the account state blob given account address and root hash of state Merkle tree.
+    /// Fails with `JellyfishMerkleError::UnknownRoot` if the tree is not known or was pruned.
+    pub fn get_account_state_with_proof_by_root(
+        &self,
+        address: AccountAddress,
+        root_hash: HashValue,
+    ) -> Result<(Option<AccountStateBlob>, SparseMerkleProof)> {
+        JellyfishMerkleTree::new(self).get_with_proof_by_root(root_hash, address.hash())
+    }
+
+    /// Returns the version since which the node identified by `node_key` is stale, i.e. replaced
+    /// by a newer node. Returns `None` if the node is still part of the latest tree, has been
+    /// pruned, or became stale before the index was backfilled.
+    pub fn get_retirement_info(&self, node_key: &NodeKey) -> Result<Option<Version>> {
+        self.db.get::<StaleNodeIndexByNodeKeySchema>(node_key)
+    }
+
+    /// Writes the entries of the index by node key for all the nodes in the stale node index that
+    /// have not been pruned yet, to populate the index of a DB created before it existed. Returns
+    /// the number of entries written.
+    pub fn backfill_retirement_index(&self) -> Result<usize> {
+        const BATCH_SIZE: usize = 10000;
+
+        let mut iter = self
+            .db
+            .iter::<StaleNodeIndexSchema>(ReadOptions::default())?;
+        iter.seek_to_first();
+        let mut batch = SchemaBatch::new();
+        let mut batch_len = 0;
+        let mut num_written = 0;
+        for res in iter {
+            let (index, _) = res?;
+            // The stale node index is only purged lazily, so its oldest entries may refer to nodes
+            // already pruned.
+            if self
+                .db
+                .get::<JellyfishMerkleNodeSchema>(&index.node_key)?
+                .is_none()
+            {
+                continue;
+            }
+            batch.put::<StaleNodeIndexByNodeKeySchema>(
+                &index.node_key,
+                &index.stale_since_version,
+            )?;
+            batch_len += 1;
+            if batch_len == BATCH_SIZE {
+                self.db
+                    .write_schemas(std::mem::replace(&mut batch, SchemaBatch::new()))?;
+                num_written += batch_len;
+                batch_len = 0;
+            }
+        }
+        self.db.write_schemas(batch)?;
+        Ok(num_written + batch_len)
+    }
+
     /// Put the results generated by `account_state_sets` to `batch` and return the result root
     /// hashes for each write set.
     pub fn put_account_state_sets(
@@ -78,7 +190,11 @@ impl StateStore {
         tree_update_batch
             .node_batch
             .iter()
-            .map(|(node_key, node)| cs.batch.put::<JellyfishMerkleNodeSchema>(node_key, node))
+            .map(|(node_key, node)| {
+                cs.batch.put::<JellyfishMerkleNodeSchema>(node_key, node)?;
+                cs.batch
+                    .put::<JellyfishMerkleNodeChecksumSchema>(node_key, &node_checksum(node)?)
+            })
             .collect::<Result<Vec<()>>>()?;
 
         cs.counter_bumps.bump(
@@ -92,7 +208,18 @@ impl StateStore {
         tree_update_batch
             .stale_node_index_batch
             .iter()
-            .map(|row| cs.batch.put::<StaleNodeIndexSchema>(row, &()))
+            .map(|row| {
+                cs.batch.put::<StaleNodeIndexSchema>(row, &())?;
+                cs.batch
+                    .put::<StaleNodeIndexByNodeKeySchema>(&row.node_key, &row.stale_since_version)
+            })
+            .collect::<Result<Vec<()>>>()?;
+        tree_update_batch
+            .root_node_keys
+            .iter()
+            .map(|(root_hash, node_key)| {
+                cs.batch.put::<RootNodeKeyByHashSchema>(root_hash, node_key)
+            })
             .collect::<Result<Vec<()>>>()?;
 
         Ok(new_root_hash_vec)
@@ -101,10 +228,20 @@ impl StateStore {
 
 impl TreeReader for StateStore {
     fn get_node_option(&self, node_key: &NodeKey) -> Result<Option<Node>> {
-        Ok(self.db.get::<JellyfishMerkleNodeSchema>(node_key)?)
+        let node = self.db.get::<JellyfishMerkleNodeSchema>(node_key)?;
+        if let Some(node) = &node {
+            if self.verify_node_checksums {
+                self.verify_node_checksum(node_key, node)?;
+            }
+        }
+        Ok(node)
     }
 
     fn get_rightmost_leaf(&self) -> Result<Option<(NodeKey, LeafNode)>> {
         unimplemented!();
     }
+
+    fn get_root_node_key_by_hash(&self, root_hash: HashValue) -> Result<Option<NodeKey>> {
+        self.db.get::<RootNodeKeyByHashSchema>(&root_hash)
+    }
 }
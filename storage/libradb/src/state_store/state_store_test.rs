@@ -3,6 +3,7 @@
 
 use super::*;
 use crate::{pruner, LibraDB};
+use jellyfish_merkle::JellyfishMerkleError;
 use libra_crypto::hash::CryptoHash;
 use libra_tools::tempdir::TempPath;
 use libra_types::{
@@ -77,6 +78,35 @@ fn verify_state_in_store(
     proof.verify(root, address.hash(), value.as_ref()).unwrap();
 }
 
+/// Checks that the index by node key has exactly one entry for each node in the stale node index
+/// that is not pruned yet, and returns the number of them.
+fn verify_retirement_index(store: &StateStore) -> usize {
+    let mut iter = store
+        .db
+        .iter::<StaleNodeIndexSchema>(ReadOptions::default())
+        .unwrap();
+    iter.seek_to_first();
+    let mut num_stale_nodes = 0;
+    for res in iter {
+        let (index, _) = res.unwrap();
+        let retirement_info = store.get_retirement_info(&index.node_key).unwrap();
+        if store.get_node_option(&index.node_key).unwrap().is_some() {
+            assert_eq!(retirement_info, Some(index.stale_since_version));
+            num_stale_nodes += 1;
+        } else {
+            assert_eq!(retirement_info, None);
+        }
+    }
+
+    let mut iter = store
+        .db
+        .iter::<StaleNodeIndexByNodeKeySchema>(ReadOptions::default())
+        .unwrap();
+    iter.seek_to_first();
+    assert_eq!(iter.count(), num_stale_nodes);
+    num_stale_nodes
+}
+
 #[test]
 fn test_empty_store() {
     let tmp_dir = TempPath::new();
@@ -183,6 +213,9 @@ fn test_retired_records() {
         1, /* expected_blobs_retired */
     );
 
+    // Every retired node is indexed by its key.
+    assert_eq!(verify_retirement_index(store), 4);
+
     // Verify.
     // Prune with limit=0, nothing is gone.
     {
@@ -208,6 +241,8 @@ fn test_retired_records() {
         verify_state_in_store(store, address1, Some(&value1), 1, root1);
         verify_state_in_store(store, address2, Some(&value2_update), 1, root1);
         verify_state_in_store(store, address3, Some(&value3), 1, root1);
+        // The nodes retired at version 1 are pruned and gone from the index by node key too.
+        assert_eq!(verify_retirement_index(store), 2);
     }
     // Prune till version=2.
     {
@@ -224,5 +259,213 @@ fn test_retired_records() {
         verify_state_in_store(store, address1, Some(&value1), 2, root2);
         verify_state_in_store(store, address2, Some(&value2_update), 2, root2);
         verify_state_in_store(store, address3, Some(&value3_update), 2, root2);
+        assert_eq!(verify_retirement_index(store), 0);
+    }
+}
+
+#[test]
+fn test_get_by_root_after_pruning() {
+    let address1 = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let address2 = AccountAddress::new([2u8; ADDRESS_LENGTH]);
+    let value1 = AccountStateBlob::from(vec![0x01]);
+    let value2 = AccountStateBlob::from(vec![0x02]);
+    let value2_update = AccountStateBlob::from(vec![0x12]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir);
+    let store = &db.state_store;
+
+    let root0 = put_account_state_set(
+        store,
+        vec![(address1, value1.clone()), (address2, value2.clone())],
+        0, /* version */
+        3, /* expected_nodes_created */
+        0, /* expected_nodes_retired */
+        0, /* expected_blobs_retired */
+    );
+    let root1 = put_account_state_set(
+        store,
+        vec![(address2, value2_update.clone())],
+        1, /* version */
+        2, /* expected_nodes_created */
+        2, /* expected_nodes_retired */
+        1, /* expected_blobs_retired */
+    );
+
+    let (value, proof) = store
+        .get_account_state_with_proof_by_root(address2, root0)
+        .unwrap();
+    assert_eq!(value, Some(value2));
+    proof
+        .verify(root0, address2.hash(), value.as_ref())
+        .unwrap();
+
+    prune_stale_indices(
+        store, 0,   /* least_readable_version */
+        1,   /* target_least_readable_version */
+        100, /* limit */
+    );
+    // root0 is pruned, root1 is still there.
+    let err = store
+        .get_account_state_with_proof_by_root(address2, root0)
+        .unwrap_err();
+    assert_eq!(
+        err.downcast::<JellyfishMerkleError>().unwrap(),
+        JellyfishMerkleError::UnknownRoot(root0)
+    );
+    for (address, expected_value) in &[(address1, value1), (address2, value2_update)] {
+        let (value, proof) = store
+            .get_account_state_with_proof_by_root(*address, root1)
+            .unwrap();
+        assert_eq!(value.as_ref(), Some(expected_value));
+        proof.verify(root1, address.hash(), value.as_ref()).unwrap();
+    }
+}
+
+#[test]
+fn test_backfill_retirement_index() {
+    let address1 = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let address2 = AccountAddress::new([2u8; ADDRESS_LENGTH]);
+    let value1 = AccountStateBlob::from(vec![0x01]);
+    let value1_update = AccountStateBlob::from(vec![0x11]);
+    let value2 = AccountStateBlob::from(vec![0x02]);
+    let value2_update = AccountStateBlob::from(vec![0x12]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir);
+    let store = &db.state_store;
+
+    put_account_state_set(
+        store,
+        vec![(address1, value1), (address2, value2)],
+        0, /* version */
+        3, /* expected_nodes_created */
+        0, /* expected_nodes_retired */
+        0, /* expected_blobs_retired */
+    );
+    put_account_state_set(
+        store,
+        vec![(address1, value1_update)],
+        1, /* version */
+        2, /* expected_nodes_created */
+        2, /* expected_nodes_retired */
+        1, /* expected_blobs_retired */
+    );
+    put_account_state_set(
+        store,
+        vec![(address2, value2_update)],
+        2, /* version */
+        2, /* expected_nodes_created */
+        2, /* expected_nodes_retired */
+        1, /* expected_blobs_retired */
+    );
+    // Prune the nodes retired at version 1, leaving their entries in the stale node index as the
+    // pruner does until it purges the index.
+    prune_stale_indices(
+        store, 0,   /* least_readable_version */
+        1,   /* target_least_readable_version */
+        100, /* limit */
+    );
+
+    // Wipe the index by node key, as if the DB was created before the index existed.
+    let mut batch = SchemaBatch::new();
+    let mut iter = store
+        .db
+        .iter::<StaleNodeIndexByNodeKeySchema>(ReadOptions::default())
+        .unwrap();
+    iter.seek_to_first();
+    for res in iter {
+        batch
+            .delete::<StaleNodeIndexByNodeKeySchema>(&res.unwrap().0)
+            .unwrap();
+    }
+    store.db.write_schemas(batch).unwrap();
+
+    // Only the nodes not pruned yet are backfilled.
+    assert_eq!(store.backfill_retirement_index().unwrap(), 2);
+    assert_eq!(verify_retirement_index(store), 2);
+}
+
+#[test]
+fn test_node_checksum_mismatch() {
+    let address = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let value = AccountStateBlob::from(vec![0x01, 0x02, 0x03]);
+
+    let tmp_dir = TempPath::new();
+    let mut db = LibraDB::new(&tmp_dir);
+    put_account_state_set(
+        &db.state_store,
+        vec![(address, value.clone())],
+        0, /* version */
+        1, /* expected_nodes_created */
+        0, /* expected_nodes_retired */
+        0, /* expected_blobs_retired */
+    );
+    assert_eq!(db.state_store.verify_all_node_checksums().unwrap(), (1, 0));
+
+    // Flip a byte of the blob in the only node, the root leaf, which still decodes.
+    let node_key = NodeKey::new_empty_path(0);
+    let mut bytes = db
+        .state_store
+        .get_node(&node_key)
+        .unwrap()
+        .encode()
+        .unwrap();
+    *bytes.last_mut().unwrap() ^= 0xff;
+    let corrupted_node = Node::decode(&bytes).unwrap();
+    db.state_store
+        .db
+        .put::<JellyfishMerkleNodeSchema>(&node_key, &corrupted_node)
+        .unwrap();
+
+    let mismatches_before = OP_COUNTER.counter("node_checksum_mismatches").get();
+    let err = db
+        .state_store
+        .get_account_state_with_proof_by_version(address, 0)
+        .unwrap_err();
+    match err.downcast::<LibraDbError>() {
+        Ok(LibraDbError::NodeChecksumMismatch(corrupted_node_key, stored, computed)) => {
+            assert_eq!(corrupted_node_key, node_key);
+            assert_ne!(stored, computed);
+        }
+        other => panic!("Unexpected result {:?}.", other),
     }
+    assert!(OP_COUNTER.counter("node_checksum_mismatches").get() > mismatches_before);
+    assert!(db.state_store.verify_all_node_checksums().is_err());
+
+    // Without verification, the corrupted blob is silently returned.
+    db.set_verify_node_checksums(false);
+    let (blob, _proof) = db
+        .state_store
+        .get_account_state_with_proof_by_version(address, 0)
+        .unwrap();
+    assert!(blob.is_some());
+    assert_ne!(blob, Some(value));
+}
+
+#[test]
+fn test_nodes_without_checksum_are_not_verified() {
+    let address = AccountAddress::new([1u8; ADDRESS_LENGTH]);
+    let value = AccountStateBlob::from(vec![0x01]);
+
+    let tmp_dir = TempPath::new();
+    let db = LibraDB::new(&tmp_dir);
+    let store = &db.state_store;
+    let root = put_account_state_set(
+        store,
+        vec![(address, value.clone())],
+        0, /* version */
+        1, /* expected_nodes_created */
+        0, /* expected_nodes_retired */
+        0, /* expected_blobs_retired */
+    );
+
+    // As if the node was written before checksums were introduced.
+    let mut batch = SchemaBatch::new();
+    batch
+        .delete::<JellyfishMerkleNodeChecksumSchema>(&NodeKey::new_empty_path(0))
+        .unwrap();
+    store.db.write_schemas(batch).unwrap();
+    verify_state_in_store(store, address, Some(&value), 0, root);
+    assert_eq!(store.verify_all_node_checksums().unwrap(), (0, 1));
 }
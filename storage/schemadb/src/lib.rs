@@ -11,15 +11,19 @@
 //! [`define_schema!`] macro to define the schema name, the types of key and value, and name of the
 //! column family.
 
+#[macro_use]
+extern crate prometheus;
+
 #[macro_use]
 pub mod schema;
 
 use crate::schema::{KeyCodec, Schema, SeekKeyCodec, ValueCodec};
 use failure::prelude::*;
 use lazy_static::lazy_static;
-use libra_metrics::OpMetrics;
+use libra_metrics::{IntGaugeVec, OpMetrics};
 use rocksdb::{
-    rocksdb_options::ColumnFamilyDescriptor, CFHandle, DBOptions, Writable, WriteOptions,
+    rocksdb_options::ColumnFamilyDescriptor, CFHandle, DBOptions, DBStatisticsTickerType, Writable,
+    WriteOptions,
 };
 use std::{
     collections::{BTreeMap, HashMap},
@@ -30,8 +34,34 @@ use std::{
 
 lazy_static! {
     static ref OP_COUNTER: OpMetrics = OpMetrics::new_and_registered("schemadb");
+
+    /// RocksDB properties of each column family, see `DB::update_rocksdb_metrics`
+    static ref ROCKSDB_CF_PROPERTIES: IntGaugeVec = register_int_gauge_vec!(
+        "libra_schemadb_rocksdb_cf_properties",
+        "RocksDB properties of each column family",
+        &["cf_name", "property"]
+    )
+    .unwrap();
+
+    /// RocksDB properties of the whole DB, see `DB::update_rocksdb_metrics`
+    static ref ROCKSDB_PROPERTIES: IntGaugeVec = register_int_gauge_vec!(
+        "libra_schemadb_rocksdb_properties",
+        "RocksDB properties of the whole DB",
+        &["property"]
+    )
+    .unwrap();
 }
 
+/// Integer RocksDB properties published for each column family, with their `property` label.
+const ROCKSDB_CF_INT_PROPERTIES: &[(&str, &str)] = &[
+    ("rocksdb.estimate-num-keys", "estimate_num_keys"),
+    (
+        "rocksdb.estimate-pending-compaction-bytes",
+        "estimate_pending_compaction_bytes",
+    ),
+    ("rocksdb.total-sst-files-size", "total_sst_files_size"),
+];
+
 /// Type alias to `rocksdb::ColumnFamilyOptions`. See [`rocksdb doc`](https://github.com/pingcap/rust-rocksdb/blob/master/src/rocksdb_options.rs)
 pub type ColumnFamilyOptions = rocksdb::ColumnFamilyOptions;
 /// Type alias to `rocksdb::ReadOptions`. See [`rocksdb doc`](https://github.com/pingcap/rust-rocksdb/blob/master/src/rocksdb_options.rs)
@@ -186,10 +216,28 @@ impl DB {
         // For now we set the max total WAL size to be 1G. This config can be useful when column
         // families are updated at non-uniform frequencies.
         db_opts.set_max_total_wal_size(1 << 30);
+        // Needed for the block cache hits and misses published by `update_rocksdb_metrics`.
+        db_opts.enable_statistics(true);
 
-        // If db exists, just open it with all cfs.
+        // If db exists, open it with the cfs it has and create the missing ones, e.g. those added
+        // by a newer version of the schema.
         if db_exists(path.as_ref()) {
-            return DB::open_cf(db_opts, &path, cf_opts_map.into_iter().collect());
+            let existing_cf_names = rocksdb::DB::list_column_families(
+                &db_opts,
+                path.as_ref().to_str().ok_or_else(|| {
+                    format_err!("Path {:?} can not be converted to string.", path.as_ref())
+                })?,
+            )
+            .map_err(convert_rocksdb_err)?;
+            let (existing_cfs, missing_cfs): (Vec<_>, Vec<_>) = cf_opts_map
+                .into_iter()
+                .partition(|(cf_name, _)| existing_cf_names.iter().any(|name| name == cf_name));
+            let mut db = DB::open_cf(db_opts, &path, existing_cfs)?;
+            missing_cfs
+                .into_iter()
+                .map(|cfd| db.create_cf(cfd))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(db);
         }
 
         // If db doesn't exist, create a db first with all column families.
@@ -209,6 +257,31 @@ impl DB {
         Ok(db)
     }
 
+    /// Opens an existing db at `path` in read-only mode with all the column families provided.
+    /// Writes through the returned handle fail, and a live writer of the same db is not affected.
+    pub fn open_readonly<P: AsRef<Path>>(
+        path: P,
+        cf_opts_map: ColumnFamilyOptionsMap,
+    ) -> Result<Self> {
+        ensure!(
+            db_exists(path.as_ref()),
+            "DB at {:?} does not exist.",
+            path.as_ref()
+        );
+        let cfds: Vec<_> = cf_opts_map.into_iter().collect();
+        let inner = rocksdb::DB::open_cf_for_read_only(
+            DBOptions::new(),
+            path.as_ref().to_str().ok_or_else(|| {
+                format_err!("Path {:?} can not be converted to string.", path.as_ref())
+            })?,
+            cfds,
+            false, /* error_if_log_file_exist */
+        )
+        .map_err(convert_rocksdb_err)?;
+
+        Ok(DB { inner })
+    }
+
     fn open_cf<'a, P, T>(opts: DBOptions, path: P, cfds: Vec<T>) -> Result<DB>
     where
         P: AsRef<Path>,
@@ -349,6 +422,64 @@ impl DB {
         Ok(cf_sizes)
     }
 
+    /// Publishes RocksDB properties as gauges: the estimated number of keys, the estimated bytes
+    /// pending compaction, the total size and the number of SST files of each column family, as
+    /// well as the number of running compactions and the block cache hits and misses of the DB.
+    pub fn update_rocksdb_metrics(&self) -> Result<()> {
+        for cf_name in self.inner.cf_names() {
+            let cf_handle = self.get_cf_handle(cf_name)?;
+            for &(property, label) in ROCKSDB_CF_INT_PROPERTIES {
+                if let Some(value) = self.inner.get_property_int_cf(cf_handle, property) {
+                    ROCKSDB_CF_PROPERTIES
+                        .with_label_values(&[cf_name, label])
+                        .set(value as i64);
+                }
+            }
+            ROCKSDB_CF_PROPERTIES
+                .with_label_values(&[cf_name, "num_sst_files"])
+                .set(self.get_num_sst_files_cf(cf_handle)? as i64);
+        }
+
+        if let Some(value) = self
+            .inner
+            .get_property_int("rocksdb.num-running-compactions")
+        {
+            ROCKSDB_PROPERTIES
+                .with_label_values(&["num_running_compactions"])
+                .set(value as i64);
+        }
+        for &(ticker, label) in &[
+            (DBStatisticsTickerType::BlockCacheHit, "block_cache_hit"),
+            (DBStatisticsTickerType::BlockCacheMiss, "block_cache_miss"),
+        ] {
+            ROCKSDB_PROPERTIES
+                .with_label_values(&[label])
+                .set(self.inner.get_statistics_ticker_count(ticker) as i64);
+        }
+        Ok(())
+    }
+
+    /// Number of SST files of a column family, summed over all levels.
+    fn get_num_sst_files_cf(&self, cf_handle: &CFHandle) -> Result<u64> {
+        let mut num_files = 0;
+        for level in 0.. {
+            let property = format!("rocksdb.num-files-at-level{}", level);
+            match self.inner.get_property_value_cf(cf_handle, &property) {
+                Some(value) => num_files += value.trim().parse::<u64>()?,
+                None => break,
+            }
+        }
+        Ok(num_files)
+    }
+
+    /// Returns the full statistics RocksDB keeps on the DB, e.g. the compactions of each level, as
+    /// text.
+    pub fn get_rocksdb_stats(&self) -> Result<String> {
+        self.inner
+            .get_property_value("rocksdb.stats")
+            .ok_or_else(|| format_err!("Unable to get RocksDB stats."))
+    }
+
     /// Flushes all memtable data. If `sync` is true, the flush will wait until it's done. This is
     /// only used for testing `get_approximate_sizes_cf` in unit tests.
     pub fn flush_all(&self, sync: bool) -> Result<()> {
@@ -71,8 +71,8 @@ impl ValueCodec<TestSchema2> for TestField {
     }
 }
 
-fn open_db(dir: &libra_tools::tempdir::TempPath) -> DB {
-    let cf_opts_map: ColumnFamilyOptionsMap = [
+fn get_cf_opts_map() -> ColumnFamilyOptionsMap {
+    [
         (DEFAULT_CF_NAME, ColumnFamilyOptions::default()),
         (
             TestSchema1::COLUMN_FAMILY_NAME,
@@ -85,8 +85,11 @@ fn open_db(dir: &libra_tools::tempdir::TempPath) -> DB {
     ]
     .iter()
     .cloned()
-    .collect();
-    DB::open(&dir.path(), cf_opts_map).expect("Failed to open DB.")
+    .collect()
+}
+
+fn open_db(dir: &libra_tools::tempdir::TempPath) -> DB {
+    DB::open(&dir.path(), get_cf_opts_map()).expect("Failed to open DB.")
 }
 
 struct TestDB {
@@ -301,6 +304,49 @@ fn test_reopen() {
     }
 }
 
+#[test]
+fn test_reopen_with_new_column_family() {
+    let tmpdir = libra_tools::tempdir::TempPath::new();
+    {
+        let mut cf_opts_map = get_cf_opts_map();
+        cf_opts_map.remove(TestSchema2::COLUMN_FAMILY_NAME);
+        let db = DB::open(&tmpdir.path(), cf_opts_map).expect("Failed to open DB.");
+        db.put::<TestSchema1>(&TestField(0), &TestField(0)).unwrap();
+    }
+    {
+        // The column family missing from the existing DB is created on open.
+        let db = open_db(&tmpdir);
+        assert_eq!(
+            db.get::<TestSchema1>(&TestField(0)).unwrap(),
+            Some(TestField(0)),
+        );
+        db.put::<TestSchema2>(&TestField(1), &TestField(1)).unwrap();
+        assert_eq!(
+            db.get::<TestSchema2>(&TestField(1)).unwrap(),
+            Some(TestField(1)),
+        );
+    }
+}
+
+#[test]
+fn test_open_readonly() {
+    let tmpdir = libra_tools::tempdir::TempPath::new();
+    assert!(DB::open_readonly(&tmpdir.path(), get_cf_opts_map()).is_err());
+
+    let db = open_db(&tmpdir);
+    db.put::<TestSchema1>(&TestField(0), &TestField(0)).unwrap();
+
+    let readonly_db =
+        DB::open_readonly(&tmpdir.path(), get_cf_opts_map()).expect("Failed to open DB.");
+    assert_eq!(
+        readonly_db.get::<TestSchema1>(&TestField(0)).unwrap(),
+        Some(TestField(0)),
+    );
+    assert!(readonly_db
+        .put::<TestSchema1>(&TestField(1), &TestField(1))
+        .is_err());
+}
+
 #[test]
 fn test_report_size() {
     let db = TestDB::new();
@@ -323,3 +369,42 @@ fn test_report_size() {
     assert!(*cf_sizes.get("TestCF2").unwrap() > 0);
     assert_eq!(*cf_sizes.get("default").unwrap(), 0);
 }
+
+fn get_cf_property_gauge(cf_name: &str, property: &str) -> Option<i64> {
+    prometheus::gather()
+        .iter()
+        .filter(|family| family.get_name() == "libra_schemadb_rocksdb_cf_properties")
+        .flat_map(|family| family.get_metric())
+        .find(|metric| {
+            let labels: Vec<_> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            labels.contains(&("cf_name", cf_name)) && labels.contains(&("property", property))
+        })
+        .map(|metric| metric.get_gauge().get_value() as i64)
+}
+
+#[test]
+fn test_update_rocksdb_metrics() {
+    let db = TestDB::new();
+    db.update_rocksdb_metrics().unwrap();
+    assert_eq!(get_cf_property_gauge("TestCF1", "num_sst_files"), Some(0));
+    assert_eq!(
+        get_cf_property_gauge("TestCF1", "total_sst_files_size"),
+        Some(0)
+    );
+
+    for i in 0..1000 {
+        db.put::<TestSchema1>(&TestField(i), &TestField(i)).unwrap();
+    }
+    db.flush_all(/* sync = */ true).unwrap();
+    db.update_rocksdb_metrics().unwrap();
+
+    assert!(get_cf_property_gauge("TestCF1", "estimate_num_keys").unwrap() > 0);
+    assert!(get_cf_property_gauge("TestCF1", "num_sst_files").unwrap() > 0);
+    assert!(get_cf_property_gauge("TestCF1", "total_sst_files_size").unwrap() > 0);
+    assert_eq!(get_cf_property_gauge("TestCF2", "num_sst_files"), Some(0));
+    assert!(db.get_rocksdb_stats().unwrap().contains("Compaction Stats"));
+}
@@ -15,7 +15,7 @@ use futures_01::future::Future as Future01;
 use grpcio::{ChannelBuilder, Environment};
 use libra_types::{
     account_address::AccountAddress,
-    account_state_blob::AccountStateBlob,
+    account_state_blob::{AccountStateBlob, AccountStateWithProof},
     crypto_proxies::{LedgerInfoWithSignatures, ValidatorChangeEventWithProof},
     get_with_proof::{
         RequestItem, ResponseItem, UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse,
@@ -213,15 +213,17 @@ impl StorageRead for StorageReadServiceClient {
     fn get_epoch_change_ledger_infos(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
     ) -> Result<Vec<LedgerInfoWithSignatures>> {
-        block_on(self.get_epoch_change_ledger_infos_async(start_epoch))
+        block_on(self.get_epoch_change_ledger_infos_async(start_epoch, end_epoch))
     }
 
     fn get_epoch_change_ledger_infos_async(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>> {
-        let proto_req = GetEpochChangeLedgerInfosRequest::new(start_epoch);
+        let proto_req = GetEpochChangeLedgerInfosRequest::new(start_epoch, end_epoch);
         convert_grpc_response(
             self.client()
                 .get_epoch_change_ledger_infos_async(&proto_req.into()),
@@ -323,6 +325,45 @@ pub trait StorageRead: Send + Sync {
         >,
     >;
 
+    /// Gets the latest states of `addresses`, in the same order, all proven against the returned
+    /// ledger info. An account that doesn't exist has no blob and a proof of its non-inclusion.
+    /// Served by [`LibraDB::update_to_latest_ledger`], which caps the number of addresses.
+    ///
+    /// [`LibraDB::update_to_latest_ledger`]:
+    /// ../libradb/struct.LibraDB.html#method.update_to_latest_ledger
+    fn get_account_states(
+        &self,
+        addresses: Vec<AccountAddress>,
+    ) -> Result<(Vec<AccountStateWithProof>, LedgerInfoWithSignatures)> {
+        block_on(self.get_account_states_async(addresses))
+    }
+
+    /// See [`get_account_states`](StorageRead::get_account_states).
+    fn get_account_states_async(
+        &self,
+        addresses: Vec<AccountAddress>,
+    ) -> Pin<
+        Box<
+            dyn Future<Output = Result<(Vec<AccountStateWithProof>, LedgerInfoWithSignatures)>>
+                + Send,
+        >,
+    > {
+        self.update_to_latest_ledger_async(0, vec![RequestItem::GetAccountStates { addresses }])
+            .map(|resp| {
+                let (mut response_items, ledger_info_with_sigs, _, _) = resp?;
+                ensure!(
+                    response_items.len() == 1,
+                    "Expected 1 response item, got {}.",
+                    response_items.len()
+                );
+                let account_states_with_proof = response_items
+                    .remove(0)
+                    .into_get_account_states_response()?;
+                Ok((account_states_with_proof, ledger_info_with_sigs))
+            })
+            .boxed()
+    }
+
     /// See [`LibraDB::get_transactions`].
     ///
     /// [`LibraDB::get_transactions`]: ../libradb/struct.LibraDB.html#method.get_transactions
@@ -386,6 +427,7 @@ pub trait StorageRead: Send + Sync {
     fn get_epoch_change_ledger_infos(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
     ) -> Result<Vec<LedgerInfoWithSignatures>>;
 
     /// See [`LibraDB::get_epoch_change_ledger_infos`].
@@ -395,6 +437,7 @@ pub trait StorageRead: Send + Sync {
     fn get_epoch_change_ledger_infos_async(
         &self,
         start_epoch: u64,
+        end_epoch: u64,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>>;
 }
 
@@ -425,6 +468,8 @@ pub trait StorageWrite: Send + Sync {
     ) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 }
 
+/// Keeps the gRPC error as is, so that callers can tell e.g. an unreachable storage service apart
+/// from other failures by downcasting to `grpcio::Error`.
 fn convert_grpc_err(e: grpcio::Error) -> Error {
-    format_err!("grpc error: {}", e)
+    e.into()
 }
@@ -34,6 +34,7 @@ use libra_types::{
     ledger_info::LedgerInfo,
     proof::SparseMerkleProof,
     transaction::{TransactionListWithProof, TransactionToCommit, Version},
+    validator_set::ValidatorSet,
 };
 #[cfg(any(test, feature = "fuzzing"))]
 use proptest_derive::Arbitrary;
@@ -362,6 +363,18 @@ pub struct StartupInfo {
     pub ledger_info: LedgerInfo,
     pub committed_tree_state: TreeState,
     pub synced_tree_state: Option<TreeState>,
+    pub latest_validator_set: ValidatorSet,
+}
+
+impl StartupInfo {
+    /// The latest version in storage, which is ahead of the latest ledger info while a sync is
+    /// incomplete.
+    pub fn latest_version(&self) -> Version {
+        self.synced_tree_state
+            .as_ref()
+            .unwrap_or(&self.committed_tree_state)
+            .version
+    }
 }
 
 impl TryFrom<crate::proto::storage::StartupInfo> for StartupInfo {
@@ -380,11 +393,16 @@ impl TryFrom<crate::proto::storage::StartupInfo> for StartupInfo {
             .synced_tree_state
             .map(TreeState::try_from)
             .transpose()?;
+        let latest_validator_set = proto
+            .latest_validator_set
+            .ok_or_else(|| format_err!("Missing latest_validator_set"))?
+            .try_into()?;
 
         Ok(Self {
             ledger_info,
             committed_tree_state,
             synced_tree_state,
+            latest_validator_set,
         })
     }
 }
@@ -394,11 +412,13 @@ impl From<StartupInfo> for crate::proto::storage::StartupInfo {
         let ledger_info = Some(info.ledger_info.into());
         let committed_tree_state = Some(info.committed_tree_state.into());
         let synced_tree_state = info.synced_tree_state.map(Into::into);
+        let latest_validator_set = Some(info.latest_validator_set.into());
 
         Self {
             ledger_info,
             committed_tree_state,
             synced_tree_state,
+            latest_validator_set,
         }
     }
 }
@@ -433,12 +453,16 @@ impl From<GetStartupInfoResponse> for crate::proto::storage::GetStartupInfoRespo
 #[cfg_attr(any(test, feature = "fuzzing"), derive(Arbitrary))]
 pub struct GetEpochChangeLedgerInfosRequest {
     pub start_epoch: u64,
+    pub end_epoch: u64,
 }
 
 impl GetEpochChangeLedgerInfosRequest {
     /// Constructor.
-    pub fn new(start_epoch: u64) -> Self {
-        Self { start_epoch }
+    pub fn new(start_epoch: u64, end_epoch: u64) -> Self {
+        Self {
+            start_epoch,
+            end_epoch,
+        }
     }
 }
 
@@ -450,6 +474,7 @@ impl TryFrom<crate::proto::storage::GetEpochChangeLedgerInfosRequest>
     fn try_from(proto: crate::proto::storage::GetEpochChangeLedgerInfosRequest) -> Result<Self> {
         Ok(Self {
             start_epoch: proto.start_epoch,
+            end_epoch: proto.end_epoch.unwrap_or_else(u64::max_value),
         })
     }
 }
@@ -460,6 +485,7 @@ impl From<GetEpochChangeLedgerInfosRequest>
     fn from(request: GetEpochChangeLedgerInfosRequest) -> Self {
         Self {
             start_epoch: request.start_epoch,
+            end_epoch: Some(request.end_epoch),
         }
     }
 }
@@ -32,4 +32,21 @@ proptest! {
     fn test_get_startup_info_response(res in any::<GetStartupInfoResponse>()) {
         assert_protobuf_encode_decode::<crate::proto::storage::GetStartupInfoResponse, GetStartupInfoResponse>(&res);
     }
+
+    #[test]
+    fn test_get_epoch_change_ledger_infos_request(req in any::<GetEpochChangeLedgerInfosRequest>()) {
+        assert_protobuf_encode_decode::<crate::proto::storage::GetEpochChangeLedgerInfosRequest, GetEpochChangeLedgerInfosRequest>(&req);
+    }
+}
+
+#[test]
+fn test_get_epoch_change_ledger_infos_request_without_end_epoch() {
+    let proto = crate::proto::storage::GetEpochChangeLedgerInfosRequest {
+        start_epoch: 2,
+        end_epoch: None,
+    };
+    assert_eq!(
+        GetEpochChangeLedgerInfosRequest::try_from(proto).unwrap(),
+        GetEpochChangeLedgerInfosRequest::new(2, u64::max_value())
+    );
 }
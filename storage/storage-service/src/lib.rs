@@ -10,6 +10,7 @@
 #[cfg(feature = "fuzzing")]
 pub mod mocks;
 
+use debug_interface::node_debug_service::DbStatsProvider;
 use failure::prelude::*;
 use grpc_helpers::{provide_grpc_response, spawn_service_thread_with_drop_closure, ServerHandle};
 use libra_config::config::NodeConfig;
@@ -21,7 +22,8 @@ use std::{
     convert::TryFrom,
     ops::Deref,
     path::Path,
-    sync::{mpsc, Arc, Mutex},
+    sync::{mpsc, Arc, Mutex, Weak},
+    time::Duration,
 };
 use storage_proto::proto::storage::{
     create_storage, GetAccountStateWithProofByVersionRequest,
@@ -33,8 +35,22 @@ use storage_proto::proto::storage::{
 
 /// Starts storage service according to config.
 pub fn start_storage_service(config: &NodeConfig) -> ServerHandle {
-    let (storage_service, shutdown_receiver) = StorageService::new(&config.get_storage_dir());
-    spawn_service_thread_with_drop_closure(
+    start_storage_service_with_db_stats(config).0
+}
+
+/// Starts storage service according to config, also returning a provider of the statistics of
+/// its DB for the debug interface.
+pub fn start_storage_service_with_db_stats(
+    config: &NodeConfig,
+) -> (ServerHandle, Arc<dyn DbStatsProvider>) {
+    let mut db = LibraDB::new_with_metrics_interval(
+        &config.get_storage_dir(),
+        Duration::from_millis(config.storage.rocksdb_metrics_interval_ms),
+    );
+    db.set_verify_node_checksums(config.storage.verify_node_checksums);
+    let (storage_service, shutdown_receiver) = StorageService::new_with_db(db);
+    let db_stats_provider = storage_service.db_stats_provider();
+    let handle = spawn_service_thread_with_drop_closure(
         create_storage(storage_service),
         config.storage.address.clone(),
         config.storage.port,
@@ -45,7 +61,8 @@ pub fn start_storage_service(config: &NodeConfig) -> ServerHandle {
                 .recv()
                 .expect("Failed to receive on shutdown channel when storage service was dropped")
         },
-    )
+    );
+    (handle, db_stats_provider)
 }
 
 /// The implementation of the storage [GRPC](http://grpc.io) service.
@@ -73,8 +90,7 @@ struct LibraDBWrapper {
 }
 
 impl LibraDBWrapper {
-    pub fn new<P: AsRef<Path>>(path: &P) -> (Self, mpsc::Receiver<()>) {
-        let db = LibraDB::new(path);
+    pub fn new(db: LibraDB) -> (Self, mpsc::Receiver<()>) {
         let (shutdown_sender, shutdown_receiver) = mpsc::channel();
         (
             Self {
@@ -126,7 +142,23 @@ impl StorageService {
     ///    // LibraDB instance is guaranteed to be properly dropped at this point.
     /// ```
     pub fn new<P: AsRef<Path>>(path: &P) -> (Self, mpsc::Receiver<()>) {
-        let (db_wrapper, shutdown_receiver) = LibraDBWrapper::new(path);
+        Self::new_with_db(LibraDB::new(path))
+    }
+
+    /// Same as `new`, with the internal statistics of RocksDB published as metrics every
+    /// `rocksdb_metrics_interval`.
+    pub fn new_with_metrics_interval<P: AsRef<Path>>(
+        path: &P,
+        rocksdb_metrics_interval: Duration,
+    ) -> (Self, mpsc::Receiver<()>) {
+        Self::new_with_db(LibraDB::new_with_metrics_interval(
+            path,
+            rocksdb_metrics_interval,
+        ))
+    }
+
+    fn new_with_db(db: LibraDB) -> (Self, mpsc::Receiver<()>) {
+        let (db_wrapper, shutdown_receiver) = LibraDBWrapper::new(db);
         (
             Self {
                 db: Arc::new(db_wrapper),
@@ -134,6 +166,24 @@ impl StorageService {
             shutdown_receiver,
         )
     }
+
+    /// Returns a provider of the statistics of the DB for the debug interface. It doesn't keep
+    /// the DB open, so the service can still be shut down while the debug interface is up.
+    pub fn db_stats_provider(&self) -> Arc<dyn DbStatsProvider> {
+        Arc::new(StorageDbStats(Arc::downgrade(&self.db)))
+    }
+}
+
+/// Serves the `GetDbStats` debug command, see `StorageService::db_stats_provider`.
+struct StorageDbStats(Weak<LibraDBWrapper>);
+
+impl DbStatsProvider for StorageDbStats {
+    fn get_db_stats(&self) -> Result<String> {
+        self.0
+            .upgrade()
+            .ok_or_else(|| format_err!("Storage service is shut down."))?
+            .get_rocksdb_stats()
+    }
 }
 
 impl StorageService {
@@ -224,7 +274,7 @@ impl StorageService {
         let rust_req = storage_proto::GetEpochChangeLedgerInfosRequest::try_from(req)?;
         let ledger_infos = self
             .db
-            .get_epoch_change_ledger_infos(rust_req.start_epoch)?;
+            .get_epoch_change_ledger_infos(rust_req.start_epoch, rust_req.end_epoch)?;
         let rust_resp = storage_proto::GetEpochChangeLedgerInfosResponse::new(ledger_infos);
         Ok(rust_resp.into())
     }
@@ -28,10 +28,13 @@ impl StorageNode {
     pub fn run(&self) -> Result<()> {
         info!("Starting storage node");
 
-        let _handle = storage_service::start_storage_service(&self.node_config);
+        let (_handle, db_stats_provider) =
+            storage_service::start_storage_service_with_db_stats(&self.node_config);
 
         // Start Debug interface
-        let debug_service = create_node_debug_interface(NodeDebugService::new());
+        let debug_service = create_node_debug_interface(
+            NodeDebugService::new().with_db_stats_provider(db_stats_provider),
+        );
         let _debug_handle = spawn_service_thread(
             debug_service,
             self.node_config.storage.address.clone(),
@@ -16,10 +16,10 @@ use libra_types::{
     proof::SparseMerkleProof,
     proto::types::{
         request_item::RequestedItems, response_item::ResponseItems, AccountStateWithProof,
-        GetAccountStateResponse, GetTransactionsResponse,
-        LedgerInfoWithSignatures as ProtoLedgerInfoWithSignatures, RequestItem as ProtoRequestItem,
-        ResponseItem as ProtoResponseItem, TransactionListWithProof, UpdateToLatestLedgerRequest,
-        UpdateToLatestLedgerResponse,
+        GetAccountStateResponse, GetAccountTransactionBySequenceNumberResponse,
+        GetTransactionsResponse, LedgerInfoWithSignatures as ProtoLedgerInfoWithSignatures,
+        RequestItem as ProtoRequestItem, ResponseItem as ProtoResponseItem,
+        TransactionListWithProof, UpdateToLatestLedgerRequest, UpdateToLatestLedgerResponse,
     },
     test_helpers::transaction_test_helpers::get_test_signed_txn,
     transaction::{Transaction, Version},
@@ -142,6 +142,7 @@ impl StorageRead for MockStorageReadClient {
     fn get_epoch_change_ledger_infos(
         &self,
         _start_epoch: u64,
+        _end_epoch: u64,
     ) -> Result<Vec<LedgerInfoWithSignatures>> {
         unimplemented!()
     }
@@ -149,6 +150,7 @@ impl StorageRead for MockStorageReadClient {
     fn get_epoch_change_ledger_infos_async(
         &self,
         _start_epoch: u64,
+        _end_epoch: u64,
     ) -> Pin<Box<dyn Future<Output = Result<Vec<LedgerInfoWithSignatures>>> + Send>> {
         unimplemented!()
     }
@@ -221,7 +223,12 @@ fn get_mock_response_item(request_item: &ProtoRequestItem) -> Result<ProtoRespon
                 response_item.response_items = Some(ResponseItems::GetAccountStateResponse(resp));
             }
             RequestedItems::GetAccountTransactionBySequenceNumberRequest(_request) => {
-                unimplemented!();
+                // Nothing is ever committed in the mock.
+                response_item.response_items = Some(
+                    ResponseItems::GetAccountTransactionBySequenceNumberResponse(
+                        GetAccountTransactionBySequenceNumberResponse::default(),
+                    ),
+                );
             }
             RequestedItems::GetEventsByEventAccessPathRequest(_request) => {
                 unimplemented!();
@@ -239,6 +246,12 @@ fn get_mock_response_item(request_item: &ProtoRequestItem) -> Result<ProtoRespon
 
                 response_item.response_items = Some(ResponseItems::GetTransactionsResponse(resp));
             }
+            RequestedItems::GetAccountStateByVersionRequest(_request) => {
+                unimplemented!();
+            }
+            RequestedItems::GetAccountStatesRequest(_request) => {
+                unimplemented!();
+            }
         }
     }
     Ok(response_item)
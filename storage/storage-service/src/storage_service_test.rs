@@ -5,7 +5,10 @@ use super::*;
 use grpcio::EnvBuilder;
 use itertools::zip_eq;
 use libra_config::config::NodeConfigHelpers;
-use libra_types::get_with_proof::{RequestItem, ResponseItem};
+use libra_types::{
+    account_address::AccountAddress,
+    get_with_proof::{RequestItem, ResponseItem},
+};
 use libradb::mock_genesis::db_with_mock_genesis;
 #[cfg(test)]
 use libradb::test_helper::arb_blocks_to_commit;
@@ -107,4 +110,45 @@ proptest! {
             prop_assert_eq!(ledger_info_with_sigs, &response_ledger_info_with_sigs);
          }
     }
+
+    #[test]
+    fn test_get_account_states(blocks in arb_blocks_to_commit().no_shrink()) {
+        let(_tmp_dir, _server_handler, read_client, write_client) =
+            start_test_storage_with_read_write_client(/* need_to_use_genesis = */ true);
+
+        let mut version = 0;
+        let mut account_states = HashMap::new();
+        for (txns_to_commit, ledger_info_with_sigs) in &blocks {
+            write_client
+                .save_transactions(txns_to_commit.clone(),
+                                   version + 1, /* first_version */
+                                   Some(ledger_info_with_sigs.clone()),
+                ).unwrap();
+            version += txns_to_commit.len() as u64;
+            txns_to_commit
+                .iter()
+                .for_each(|txn_to_commit|
+                          account_states.extend(txn_to_commit
+                                                .account_states()
+                                                .clone())
+                );
+        }
+
+        // Interleave accounts that exist with ones that don't.
+        let addresses = account_states
+            .keys()
+            .flat_map(|address| vec![*address, AccountAddress::random()])
+            .collect::<Vec<_>>();
+        let (account_states_with_proof, ledger_info_with_sigs) =
+            read_client.get_account_states(addresses.clone()).unwrap();
+        prop_assert_eq!(&blocks.last().unwrap().1, &ledger_info_with_sigs);
+        for (address, account_state_with_proof) in zip_eq(addresses, account_states_with_proof) {
+            prop_assert_eq!(account_states.get(&address), account_state_with_proof.blob.as_ref());
+            prop_assert!(account_state_with_proof.verify(
+                ledger_info_with_sigs.ledger_info(),
+                version,
+                address,
+            ).is_ok())
+        }
+    }
 }
@@ -61,8 +61,11 @@ mod compiled_module;
 mod consensus_proposal;
 mod inbound_rpc_protocol;
 mod inner_signed_transaction;
+mod jellyfish_node;
+mod jellyfish_node_key;
 mod signed_transaction;
 mod sparse_merkle_proof;
+mod sparse_merkle_proof_verify;
 mod vm_value;
 
 lazy_static! {
@@ -73,6 +76,9 @@ lazy_static! {
             Box::new(signed_transaction::SignedTransactionTarget::default()),
             Box::new(inner_signed_transaction::SignedTransactionTarget::default()),
             Box::new(sparse_merkle_proof::SparseMerkleProofTarget::default()),
+            Box::new(sparse_merkle_proof_verify::SparseMerkleProofVerifyTarget::default()),
+            Box::new(jellyfish_node::JellyfishNodeTarget::default()),
+            Box::new(jellyfish_node_key::JellyfishNodeKeyTarget::default()),
             Box::new(accumulator_merkle_proof::AccumulatorProofTarget::default()),
             Box::new(vm_value::ValueTarget::default()),
             Box::new(consensus_proposal::ConsensusProposal::default()),
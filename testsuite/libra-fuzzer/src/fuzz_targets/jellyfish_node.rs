@@ -0,0 +1,33 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use jellyfish_merkle::node_type::Node;
+use libra_proptest_helpers::ValueGenerator;
+use proptest::prelude::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct JellyfishNodeTarget;
+
+impl FuzzTargetImpl for JellyfishNodeTarget {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "Jellyfish Merkle tree Node (custom deserializer)"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        let value = gen.generate(any::<Node>());
+        Some(value.encode().expect("serialization should work"))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        // Errors are OK -- the fuzzer cares about panics and OOMs. A decoded node is hashed too,
+        // as is done to any node read from storage.
+        if let Ok(node) = Node::decode(data) {
+            let _ = node.hash();
+        }
+    }
+}
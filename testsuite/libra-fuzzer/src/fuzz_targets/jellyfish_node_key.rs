@@ -0,0 +1,30 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use jellyfish_merkle::node_type::NodeKey;
+use libra_proptest_helpers::ValueGenerator;
+use proptest::prelude::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct JellyfishNodeKeyTarget;
+
+impl FuzzTargetImpl for JellyfishNodeKeyTarget {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "Jellyfish Merkle tree NodeKey (custom deserializer)"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        let value = gen.generate(any::<NodeKey>());
+        Some(value.encode().expect("serialization should work"))
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        // Errors are OK -- the fuzzer cares about panics and OOMs.
+        let _ = NodeKey::decode(data);
+    }
+}
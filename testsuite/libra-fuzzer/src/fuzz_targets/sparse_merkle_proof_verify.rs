@@ -0,0 +1,60 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::FuzzTargetImpl;
+use libra_crypto::HashValue;
+use libra_proptest_helpers::ValueGenerator;
+use libra_prost_ext::MessageExt;
+use libra_types::{
+    account_state_blob::AccountStateBlob, proof::SparseMerkleProof,
+    proto::types::SparseMerkleProof as ProtoSparseMerkleProof,
+};
+use proptest::prelude::*;
+use prost::Message;
+use std::convert::TryFrom;
+
+/// The input is the expected root hash and the element key, followed by the proof.
+#[derive(Clone, Debug, Default)]
+pub struct SparseMerkleProofVerifyTarget;
+
+impl FuzzTargetImpl for SparseMerkleProofVerifyTarget {
+    fn name(&self) -> &'static str {
+        module_name!()
+    }
+
+    fn description(&self) -> &'static str {
+        "SparseMerkleProof (protobuf) verified against a root hash and key"
+    }
+
+    fn generate(&self, _idx: usize, gen: &mut ValueGenerator) -> Option<Vec<u8>> {
+        let (root_hash, element_key, proof) =
+            gen.generate(any::<(HashValue, HashValue, SparseMerkleProof)>());
+        let proof: ProtoSparseMerkleProof = proof.into();
+        let mut out = root_hash.to_vec();
+        out.extend(element_key.to_vec());
+        out.extend(proof.to_vec().expect("failed to convert to bytes"));
+        Some(out)
+    }
+
+    fn fuzz(&self, data: &[u8]) {
+        if data.len() < 2 * HashValue::LENGTH {
+            return;
+        }
+        let (hashes, proof) = data.split_at(2 * HashValue::LENGTH);
+        let (root_hash, element_key) = hashes.split_at(HashValue::LENGTH);
+        let root_hash = HashValue::from_slice(root_hash).expect("length checked above");
+        let element_key = HashValue::from_slice(element_key).expect("length checked above");
+        // Errors are OK -- the fuzzer cares about panics and OOMs.
+        if let Ok(Ok(proof)) =
+            ProtoSparseMerkleProof::decode(proof).map(SparseMerkleProof::try_from)
+        {
+            // Both as a non-inclusion and an inclusion proof.
+            let _ = proof.verify(root_hash, element_key, None);
+            let _ = proof.verify(
+                root_hash,
+                element_key,
+                Some(&AccountStateBlob::from(vec![])),
+            );
+        }
+    }
+}
@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 #![allow(unused_mut)]
 use cli::{
-    client_proxy::ClientProxy, AccountAddress, CryptoHash, TransactionArgument, TransactionPayload,
+    client_proxy::ClientProxy, views::BalanceView, AccountAddress, CryptoHash, TransactionArgument,
+    TransactionPayload,
 };
 use libra_config::config::{NodeConfig, RoleType};
 use libra_crypto::{ed25519::*, test_utils::KeyPair, SigningKey};
@@ -12,9 +13,13 @@ use libra_tools::tempdir::TempPath;
 use num_traits::cast::FromPrimitive;
 use rust_decimal::Decimal;
 use std::fs;
-use std::str::FromStr;
 use std::{thread, time};
 
+/// The balance of `view` in libras.
+fn libras(view: BalanceView) -> Option<Decimal> {
+    Some(Decimal::new(view.balance as i64, 6))
+}
+
 struct TestEnvironment {
     validator_swarm: LibraSwarm,
     full_node_swarm: Option<LibraSwarm>,
@@ -158,7 +163,7 @@ fn test_smoke_script(mut client_proxy: ClientProxy) {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     client_proxy.create_next_account(false).unwrap();
     client_proxy.mint_coins(&["mintb", "1", "1"], true).unwrap();
@@ -167,11 +172,11 @@ fn test_smoke_script(mut client_proxy: ClientProxy) {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(7.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(4.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     client_proxy.create_next_account(false).unwrap();
     client_proxy
@@ -179,7 +184,7 @@ fn test_smoke_script(mut client_proxy: ClientProxy) {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(15.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "2"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "2"]).unwrap())
     );
 }
 
@@ -192,7 +197,7 @@ fn test_execute_custom_module_and_script() {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(50.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
 
     let recipient_address = client_proxy.create_next_account(false).unwrap().address;
@@ -227,11 +232,11 @@ fn test_execute_custom_module_and_script() {
 
     assert_eq!(
         Decimal::from_f64(49.999_990),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(1.000_010),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
 }
 
@@ -278,11 +283,11 @@ fn test_concurrent_transfers_single_node() {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(79.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(21.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
 }
 
@@ -307,11 +312,11 @@ fn test_basic_restartability() {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     let peer_to_restart = 0;
     // restart node
@@ -322,22 +327,22 @@ fn test_basic_restartability() {
         .is_ok());
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     client_proxy
         .transfer_coins(&["tb", "0", "1", "10"], true)
         .unwrap();
     assert_eq!(
         Decimal::from_f64(80.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(20.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
 }
 
@@ -352,11 +357,11 @@ fn test_startup_sync_state() {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     let peer_to_stop = 0;
     env.validator_swarm.kill_node(peer_to_stop);
@@ -387,8 +392,7 @@ fn test_startup_sync_state() {
             panic!("Sync failed to complete");
         }
         let expected_balance = Decimal::from_f64(90.0);
-        let actual_balance =
-            Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok();
+        let actual_balance = libras(client_proxy.get_balance(&["b", "0"]).unwrap());
         if actual_balance == expected_balance {
             break;
         }
@@ -397,22 +401,22 @@ fn test_startup_sync_state() {
     }
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     client_proxy
         .transfer_coins(&["tb", "0", "1", "10"], true)
         .unwrap();
     assert_eq!(
         Decimal::from_f64(80.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(20.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
 }
 
@@ -433,22 +437,22 @@ fn test_basic_state_synchronization() {
         .unwrap();
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     let node_to_restart = 0;
     env.validator_swarm.kill_node(node_to_restart);
     // All these are executed while one node is down
     assert_eq!(
         Decimal::from_f64(90.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&client_proxy.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy.get_balance(&["b", "1"]).unwrap())
     );
     for _ in 0..5 {
         client_proxy
@@ -470,11 +474,11 @@ fn test_basic_state_synchronization() {
     client_proxy2.set_accounts(client_proxy.copy_all_accounts());
     assert_eq!(
         Decimal::from_f64(85.0),
-        Decimal::from_str(&client_proxy2.get_balance(&["b", "0"]).unwrap()).ok()
+        libras(client_proxy2.get_balance(&["b", "0"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(15.0),
-        Decimal::from_str(&client_proxy2.get_balance(&["b", "1"]).unwrap()).ok()
+        libras(client_proxy2.get_balance(&["b", "1"]).unwrap())
     );
 }
 
@@ -617,7 +621,7 @@ fn test_full_node_basic_flow() {
     assert!(mint_result.is_ok());
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&full_node_client.get_balance(&["b", "3"]).unwrap()).ok()
+        libras(full_node_client.get_balance(&["b", "3"]).unwrap())
     );
     let sequence = full_node_client
         .get_sequence_number(&["sequence", sender_account, "true"])
@@ -628,7 +632,7 @@ fn test_full_node_basic_flow() {
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&validator_ac_client.get_balance(&["b", "3"]).unwrap()).ok()
+        libras(validator_ac_client.get_balance(&["b", "3"]).unwrap())
     );
 
     // reset sequence number for sender account
@@ -657,11 +661,11 @@ fn test_full_node_basic_flow() {
 
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&validator_ac_client.get_balance(&["b", "4"]).unwrap()).ok()
+        libras(validator_ac_client.get_balance(&["b", "4"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(10.0),
-        Decimal::from_str(&full_node_client.get_balance(&["b", "4"]).unwrap()).ok()
+        libras(full_node_client.get_balance(&["b", "4"]).unwrap())
     );
 
     // minting again on validator doesn't cause error since client sequence has been updated
@@ -676,11 +680,11 @@ fn test_full_node_basic_flow() {
 
     assert_eq!(
         Decimal::from_f64(0.0),
-        Decimal::from_str(&full_node_client.get_balance(&["b", "3"]).unwrap()).ok()
+        libras(full_node_client.get_balance(&["b", "3"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(30.0),
-        Decimal::from_str(&validator_ac_client.get_balance(&["b", "4"]).unwrap()).ok()
+        libras(validator_ac_client.get_balance(&["b", "4"]).unwrap())
     );
 
     let sequence = validator_ac_client
@@ -689,10 +693,10 @@ fn test_full_node_basic_flow() {
     full_node_client_2.wait_for_transaction(account3, sequence);
     assert_eq!(
         Decimal::from_f64(0.0),
-        Decimal::from_str(&full_node_client_2.get_balance(&["b", "3"]).unwrap()).ok()
+        libras(full_node_client_2.get_balance(&["b", "3"]).unwrap())
     );
     assert_eq!(
         Decimal::from_f64(30.0),
-        Decimal::from_str(&full_node_client_2.get_balance(&["b", "4"]).unwrap()).ok()
+        libras(full_node_client_2.get_balance(&["b", "4"]).unwrap())
     );
 }
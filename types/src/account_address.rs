@@ -6,8 +6,8 @@ use bytes::Bytes;
 use failure::prelude::*;
 use hex;
 use libra_crypto::{
-    hash::{CryptoHash, CryptoHasher},
-    HashValue, VerifyingKey,
+    hash::{short_hex, CryptoHash, CryptoHasher},
+    HashValue, ShortStr, VerifyingKey,
 };
 use libra_crypto_derive::CryptoHasher;
 #[cfg(any(test, feature = "fuzzing"))]
@@ -18,8 +18,6 @@ use std::{convert::TryFrom, fmt, str::FromStr};
 
 pub const ADDRESS_LENGTH: usize = 32;
 
-const SHORT_STRING_LENGTH: usize = 4;
-
 const LIBRA_NETWORK_ID_SHORT: &str = "lb";
 
 /// A struct that represents an account address.
@@ -41,11 +39,6 @@ impl AccountAddress {
         AccountAddress::new(buf)
     }
 
-    // Helpful in log messages
-    pub fn short_str(&self) -> String {
-        hex::encode(&self.0[0..SHORT_STRING_LENGTH]).to_string()
-    }
-
     pub fn to_vec(&self) -> Vec<u8> {
         self.0.to_vec()
     }
@@ -92,6 +85,12 @@ impl AsRef<[u8]> for AccountAddress {
     }
 }
 
+impl ShortStr for AccountAddress {
+    fn short_str(&self) -> String {
+        short_hex(&self.0)
+    }
+}
+
 impl fmt::Display for AccountAddress {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
         // Forward to the LowerHex impl with a "0x" prepended (the # flag).
@@ -0,0 +1,154 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+use crate::{
+    byte_array::ByteArray,
+    event::{EventHandle, EventKey},
+};
+
+fn account_resource(balance: u64, sent_events_count: u64) -> Vec<u8> {
+    lcs::to_bytes(&AccountResource::new(
+        balance,
+        sent_events_count, /* sequence_number */
+        ByteArray::new(vec![1; 32]),
+        false,
+        false,
+        EventHandle::new(EventKey::new([2; 32]), sent_events_count),
+        EventHandle::new(EventKey::new([3; 32]), 0),
+    ))
+    .unwrap()
+}
+
+fn account_state(entries: Vec<(Vec<u8>, Vec<u8>)>) -> BTreeMap<Vec<u8>, Vec<u8>> {
+    entries.into_iter().collect()
+}
+
+#[test]
+fn test_identical_states() {
+    let state = account_state(vec![
+        (account_resource_path(), account_resource(100, 1)),
+        (b"unknown".to_vec(), vec![1, 2, 3]),
+    ]);
+    assert!(AccountStateDiff::new(&state, &state).is_empty());
+}
+
+#[test]
+fn test_nested_field_changes() {
+    let old = account_state(vec![(account_resource_path(), account_resource(100, 1))]);
+    let new = account_state(vec![(account_resource_path(), account_resource(90, 2))]);
+
+    let diff = AccountStateDiff::new(&old, &new);
+    assert_eq!(diff.len(), 1);
+    let fields = match &diff.entries[0] {
+        ResourceDiff::Changed {
+            change: ResourceChange::Fields(fields),
+            ..
+        } => fields,
+        entry => panic!("Unexpected diff entry: {:?}", entry),
+    };
+    let changed: Vec<_> = fields
+        .iter()
+        .map(|change| (change.field.as_str(), &change.old, &change.new))
+        .collect();
+    assert_eq!(
+        changed,
+        vec![
+            ("balance", &ResourceValue::U64(100), &ResourceValue::U64(90)),
+            (
+                "sent_events.count",
+                &ResourceValue::U64(1),
+                &ResourceValue::U64(2)
+            ),
+            (
+                "sequence_number",
+                &ResourceValue::U64(1),
+                &ResourceValue::U64(2)
+            ),
+        ]
+    );
+    assert_eq!(
+        diff.to_string(),
+        "~ LibraAccount.T\n    balance: 100 -> 90\n    sent_events.count: 1 -> 2\n    \
+         sequence_number: 1 -> 2\n"
+    );
+}
+
+#[test]
+fn test_unknown_resource_diffs_bytes() {
+    let path = b"unknown".to_vec();
+    let old = account_state(vec![(path.clone(), vec![0xab, 0xcd])]);
+    let new = account_state(vec![(path.clone(), vec![0xab, 0xef])]);
+
+    let diff = AccountStateDiff::new(&old, &new);
+    assert_eq!(
+        diff.entries,
+        vec![ResourceDiff::Changed {
+            path: path.clone(),
+            change: ResourceChange::Bytes {
+                old: vec![0xab, 0xcd],
+                new: vec![0xab, 0xef],
+            },
+        }]
+    );
+    assert_eq!(
+        diff.to_string(),
+        format!("~ 0x{}: 0xabcd -> 0xabef\n", hex::encode(&path))
+    );
+}
+
+#[test]
+fn test_undecodable_account_resource_diffs_bytes() {
+    let old = account_state(vec![(account_resource_path(), vec![1])]);
+    let new = account_state(vec![(account_resource_path(), account_resource(100, 1))]);
+
+    match &AccountStateDiff::new(&old, &new).entries[..] {
+        [ResourceDiff::Changed {
+            change: ResourceChange::Bytes { .. },
+            ..
+        }] => (),
+        entries => panic!("Unexpected diff entries: {:?}", entries),
+    }
+}
+
+#[test]
+fn test_added_and_removed_resources() {
+    let removed = b"removed".to_vec();
+    let added = b"added".to_vec();
+    let old = account_state(vec![(removed.clone(), vec![1])]);
+    let new = account_state(vec![
+        (added.clone(), vec![2]),
+        (account_resource_path(), account_resource(100, 0)),
+    ]);
+
+    let diff = AccountStateDiff::new(&old, &new);
+    let mut expected = vec![
+        ResourceDiff::Added {
+            path: added,
+            value: vec![2],
+        },
+        ResourceDiff::Added {
+            path: account_resource_path(),
+            value: account_resource(100, 0),
+        },
+        ResourceDiff::Removed {
+            path: removed,
+            value: vec![1],
+        },
+    ];
+    expected.sort_by(|a, b| a.path().cmp(b.path()));
+    assert_eq!(diff.entries, expected);
+}
+
+#[test]
+fn test_from_blobs_missing_account() {
+    let state = account_state(vec![(account_resource_path(), account_resource(100, 0))]);
+    let blob = AccountStateBlob::try_from(&state).unwrap();
+
+    let diff = AccountStateDiff::from_blobs(None, Some(&blob)).unwrap();
+    assert_eq!(diff.len(), 1);
+    assert!(diff.to_string().starts_with("+ LibraAccount.T: { "));
+    assert!(AccountStateDiff::from_blobs(Some(&blob), Some(&blob))
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,313 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structural diff of the state of one account between two versions.
+//!
+//! Resources whose layout is known are decoded into [`ResourceValue`]s and compared field by
+//! field, so that a change shows up as e.g. `sent_events.count: 3 -> 4`. Any other resource is
+//! compared as raw bytes.
+
+use crate::{
+    account_config::{account_resource_path, AccountResource},
+    account_state_blob::AccountStateBlob,
+};
+use failure::prelude::*;
+use std::{collections::BTreeMap, convert::TryFrom, fmt};
+
+/// A decoded resource, or a field of one.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResourceValue {
+    Bool(bool),
+    U64(u64),
+    Bytes(Vec<u8>),
+    /// Named fields in declaration order.
+    Struct(Vec<(String, ResourceValue)>),
+}
+
+impl fmt::Display for ResourceValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResourceValue::Bool(value) => write!(f, "{}", value),
+            ResourceValue::U64(value) => write!(f, "{}", value),
+            ResourceValue::Bytes(bytes) => write!(f, "0x{}", hex::encode(bytes)),
+            ResourceValue::Struct(fields) => {
+                write!(f, "{{ ")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, " }}")
+            }
+        }
+    }
+}
+
+impl From<&AccountResource> for ResourceValue {
+    fn from(resource: &AccountResource) -> Self {
+        let event_handle = |handle: &crate::event::EventHandle| {
+            ResourceValue::Struct(vec![
+                ("count".to_string(), ResourceValue::U64(handle.count())),
+                (
+                    "key".to_string(),
+                    ResourceValue::Bytes(handle.key().to_vec()),
+                ),
+            ])
+        };
+        ResourceValue::Struct(vec![
+            (
+                "authentication_key".to_string(),
+                ResourceValue::Bytes(resource.authentication_key().as_bytes().to_vec()),
+            ),
+            (
+                "balance".to_string(),
+                ResourceValue::U64(resource.balance()),
+            ),
+            (
+                "delegated_key_rotation_capability".to_string(),
+                ResourceValue::Bool(resource.delegated_key_rotation_capability()),
+            ),
+            (
+                "delegated_withdrawal_capability".to_string(),
+                ResourceValue::Bool(resource.delegated_withdrawal_capability()),
+            ),
+            (
+                "received_events".to_string(),
+                event_handle(resource.received_events()),
+            ),
+            (
+                "sent_events".to_string(),
+                event_handle(resource.sent_events()),
+            ),
+            (
+                "sequence_number".to_string(),
+                ResourceValue::U64(resource.sequence_number()),
+            ),
+        ])
+    }
+}
+
+/// Decodes the resource stored under `path` if its layout is known.
+pub fn decode_resource(path: &[u8], bytes: &[u8]) -> Option<ResourceValue> {
+    if path == account_resource_path().as_slice() {
+        lcs::from_bytes::<AccountResource>(bytes)
+            .ok()
+            .map(|resource| ResourceValue::from(&resource))
+    } else {
+        None
+    }
+}
+
+/// A human readable name for the resource stored under `path`.
+pub fn resource_name(path: &[u8]) -> String {
+    if path == account_resource_path().as_slice() {
+        "LibraAccount.T".to_string()
+    } else {
+        format!("0x{}", hex::encode(path))
+    }
+}
+
+/// A field whose value differs between the two sides of a diff. `field` is the dot separated path
+/// to it from the root of the resource, empty if the whole resource differs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: ResourceValue,
+    pub new: ResourceValue,
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
+}
+
+/// How a resource present at both versions changed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResourceChange {
+    /// The resource was decoded on both sides and these fields differ.
+    Fields(Vec<FieldChange>),
+    /// The resource layout is unknown, so only the raw bytes can be compared.
+    Bytes { old: Vec<u8>, new: Vec<u8> },
+}
+
+/// The difference of a single resource of an account.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResourceDiff {
+    Added {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Removed {
+        path: Vec<u8>,
+        value: Vec<u8>,
+    },
+    Changed {
+        path: Vec<u8>,
+        change: ResourceChange,
+    },
+}
+
+impl ResourceDiff {
+    pub fn path(&self) -> &[u8] {
+        match self {
+            ResourceDiff::Added { path, .. }
+            | ResourceDiff::Removed { path, .. }
+            | ResourceDiff::Changed { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for ResourceDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_value(path: &[u8], value: &[u8]) -> String {
+            decode_resource(path, value)
+                .map_or_else(|| format!("0x{}", hex::encode(value)), |v| v.to_string())
+        }
+        let name = resource_name(self.path());
+        match self {
+            ResourceDiff::Added { path, value } => {
+                write!(f, "+ {}: {}", name, fmt_value(path, value))
+            }
+            ResourceDiff::Removed { path, value } => {
+                write!(f, "- {}: {}", name, fmt_value(path, value))
+            }
+            ResourceDiff::Changed {
+                change: ResourceChange::Fields(fields),
+                ..
+            } => {
+                write!(f, "~ {}", name)?;
+                for field in fields {
+                    write!(f, "\n    {}", field)?;
+                }
+                Ok(())
+            }
+            ResourceDiff::Changed {
+                change: ResourceChange::Bytes { old, new },
+                ..
+            } => write!(
+                f,
+                "~ {}: 0x{} -> 0x{}",
+                name,
+                hex::encode(old),
+                hex::encode(new)
+            ),
+        }
+    }
+}
+
+/// The resources that differ between two states of an account, ordered by path.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AccountStateDiff {
+    pub entries: Vec<ResourceDiff>,
+}
+
+impl AccountStateDiff {
+    /// Diffs two account states given as maps from resource path to resource bytes.
+    pub fn new(old: &BTreeMap<Vec<u8>, Vec<u8>>, new: &BTreeMap<Vec<u8>, Vec<u8>>) -> Self {
+        let mut paths: Vec<_> = old.keys().chain(new.keys()).collect();
+        paths.sort();
+        paths.dedup();
+        let entries = paths
+            .into_iter()
+            .filter_map(|path| match (old.get(path), new.get(path)) {
+                (None, Some(value)) => Some(ResourceDiff::Added {
+                    path: path.clone(),
+                    value: value.clone(),
+                }),
+                (Some(value), None) => Some(ResourceDiff::Removed {
+                    path: path.clone(),
+                    value: value.clone(),
+                }),
+                (Some(old_value), Some(new_value)) if old_value != new_value => {
+                    Some(ResourceDiff::Changed {
+                        path: path.clone(),
+                        change: diff_resource(path, old_value, new_value),
+                    })
+                }
+                _ => None,
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// Diffs two account state blobs. A missing blob is an account that does not exist.
+    pub fn from_blobs(
+        old: Option<&AccountStateBlob>,
+        new: Option<&AccountStateBlob>,
+    ) -> Result<Self> {
+        fn to_map(blob: Option<&AccountStateBlob>) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+            blob.map_or_else(|| Ok(BTreeMap::new()), BTreeMap::try_from)
+        }
+        Ok(Self::new(&to_map(old)?, &to_map(new)?))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+impl fmt::Display for AccountStateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+fn diff_resource(path: &[u8], old: &[u8], new: &[u8]) -> ResourceChange {
+    match (decode_resource(path, old), decode_resource(path, new)) {
+        (Some(old_value), Some(new_value)) => {
+            let mut changes = vec![];
+            diff_values("", &old_value, &new_value, &mut changes);
+            ResourceChange::Fields(changes)
+        }
+        _ => ResourceChange::Bytes {
+            old: old.to_vec(),
+            new: new.to_vec(),
+        },
+    }
+}
+
+/// Collects the leaf fields that differ, descending into structs with the same fields on both
+/// sides.
+fn diff_values(
+    field: &str,
+    old: &ResourceValue,
+    new: &ResourceValue,
+    changes: &mut Vec<FieldChange>,
+) {
+    match (old, new) {
+        (ResourceValue::Struct(old_fields), ResourceValue::Struct(new_fields))
+            if old_fields.len() == new_fields.len()
+                && old_fields
+                    .iter()
+                    .zip(new_fields)
+                    .all(|((old_name, _), (new_name, _))| old_name == new_name) =>
+        {
+            for ((name, old_field), (_, new_field)) in old_fields.iter().zip(new_fields) {
+                let path = if field.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", field, name)
+                };
+                diff_values(&path, old_field, new_field, changes);
+            }
+        }
+        _ if old != new => changes.push(FieldChange {
+            field: field.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod account_state_diff_test;
@@ -4,11 +4,14 @@
 use crate::account_address::AccountAddress;
 use failure::prelude::*;
 use hex;
-use libra_crypto::HashValue;
+use libra_crypto::{
+    hash::{decode_full_hex, short_hex},
+    HashValue, ShortStr,
+};
 #[cfg(feature = "fuzzing")]
 use proptest_derive::Arbitrary;
 use serde::{de, ser, Deserialize, Serialize};
-use std::{convert::TryFrom, fmt};
+use std::{convert::TryFrom, fmt, str::FromStr};
 
 /// Size of an event key.
 pub const EVENT_KEY_LENGTH: usize = 32;
@@ -154,9 +157,29 @@ impl fmt::LowerHex for EventKey {
     }
 }
 
+impl ShortStr for EventKey {
+    fn short_str(&self) -> String {
+        short_hex(&self.0)
+    }
+}
+
+/// Will print the shortened key of `ShortStr`, or the full key with `{:#}`, both with `0x`. The
+/// address and salt the key is derived from can't be told from it.
 impl fmt::Display for EventKey {
     fn fmt(&self, f: &mut fmt::Formatter) -> std::fmt::Result {
-        // Forward to the LowerHex impl with a "0x" prepended (the # flag).
-        write!(f, "{:#x}", self)
+        if f.alternate() {
+            write!(f, "0x{:x}", self)
+        } else {
+            write!(f, "{}", self.short_str())
+        }
+    }
+}
+
+impl FromStr for EventKey {
+    type Err = failure::Error;
+
+    /// Parses the full hex form of a key, with or without `0x`.
+    fn from_str(s: &str) -> Result<Self> {
+        EventKey::try_from(decode_full_hex(s, EVENT_KEY_LENGTH)?.as_slice())
     }
 }
@@ -10,7 +10,8 @@ use crate::{
     ledger_info::{LedgerInfo, LedgerInfoWithSignatures},
     proof::AccumulatorConsistencyProof,
     proto::types::{
-        GetAccountStateRequest, GetAccountStateResponse,
+        GetAccountStateByVersionRequest, GetAccountStateRequest, GetAccountStateResponse,
+        GetAccountStatesRequest, GetAccountStatesResponse,
         GetAccountTransactionBySequenceNumberRequest,
         GetAccountTransactionBySequenceNumberResponse, GetEventsByEventAccessPathRequest,
         GetEventsByEventAccessPathResponse, GetTransactionsRequest, GetTransactionsResponse,
@@ -227,6 +228,41 @@ fn verify_response_item(
                 account_state_with_proof,
             },
         ) => account_state_with_proof.verify(ledger_info, ledger_info.version(), *address),
+        // GetAccountStateByVersion
+        (
+            RequestItem::GetAccountStateByVersion { address, version },
+            ResponseItem::GetAccountState {
+                account_state_with_proof,
+            },
+        ) => {
+            ensure!(
+                *version <= ledger_info.version(),
+                "Requested version {} is newer than the latest ledger info version {}.",
+                version,
+                ledger_info.version(),
+            );
+            account_state_with_proof.verify(ledger_info, *version, *address)
+        }
+        // GetAccountStates
+        (
+            RequestItem::GetAccountStates { addresses },
+            ResponseItem::GetAccountStates {
+                account_states_with_proof,
+            },
+        ) => {
+            ensure!(
+                addresses.len() == account_states_with_proof.len(),
+                "Number of requested accounts ({}) does not match that of account states ({}).",
+                addresses.len(),
+                account_states_with_proof.len(),
+            );
+            itertools::zip_eq(addresses, account_states_with_proof)
+                .map(|(address, account_state_with_proof)| {
+                    account_state_with_proof.verify(ledger_info, ledger_info.version(), *address)
+                })
+                .collect::<Result<Vec<_>>>()?;
+            Ok(())
+        }
         // GetAccountTransactionBySequenceNumber
         (
             RequestItem::GetAccountTransactionBySequenceNumber {
@@ -451,6 +487,13 @@ pub enum RequestItem {
         limit: u64,
         fetch_events: bool,
     },
+    GetAccountStateByVersion {
+        address: AccountAddress,
+        version: Version,
+    },
+    GetAccountStates {
+        addresses: Vec<AccountAddress>,
+    },
 }
 
 impl TryFrom<crate::proto::types::RequestItem> for RequestItem {
@@ -506,6 +549,19 @@ impl TryFrom<crate::proto::types::RequestItem> for RequestItem {
                     fetch_events,
                 }
             }
+            GetAccountStateByVersionRequest(request) => {
+                let address = AccountAddress::try_from(request.address)?;
+                let version = request.version;
+                RequestItem::GetAccountStateByVersion { address, version }
+            }
+            GetAccountStatesRequest(request) => {
+                let addresses = request
+                    .addresses
+                    .into_iter()
+                    .map(AccountAddress::try_from)
+                    .collect::<Result<Vec<_>>>()?;
+                RequestItem::GetAccountStates { addresses }
+            }
         };
 
         Ok(request)
@@ -555,6 +611,17 @@ impl From<RequestItem> for crate::proto::types::RequestItem {
                 limit,
                 fetch_events,
             }),
+            RequestItem::GetAccountStateByVersion { address, version } => {
+                RequestedItems::GetAccountStateByVersionRequest(GetAccountStateByVersionRequest {
+                    address: address.into(),
+                    version,
+                })
+            }
+            RequestItem::GetAccountStates { addresses } => {
+                RequestedItems::GetAccountStatesRequest(GetAccountStatesRequest {
+                    addresses: addresses.into_iter().map(Into::into).collect(),
+                })
+            }
         };
 
         Self {
@@ -583,6 +650,9 @@ pub enum ResponseItem {
     GetTransactions {
         txn_list_with_proof: TransactionListWithProof,
     },
+    GetAccountStates {
+        account_states_with_proof: Vec<AccountStateWithProof>,
+    },
 }
 
 impl ResponseItem {
@@ -627,6 +697,15 @@ impl ResponseItem {
             _ => bail!("Not ResponseItem::GetTransactions."),
         }
     }
+
+    pub fn into_get_account_states_response(self) -> Result<Vec<AccountStateWithProof>> {
+        match self {
+            ResponseItem::GetAccountStates {
+                account_states_with_proof,
+            } => Ok(account_states_with_proof),
+            _ => bail!("Not ResponseItem::GetAccountStates."),
+        }
+    }
 }
 
 impl TryFrom<crate::proto::types::ResponseItem> for ResponseItem {
@@ -690,6 +769,17 @@ impl TryFrom<crate::proto::types::ResponseItem> for ResponseItem {
                     txn_list_with_proof,
                 }
             }
+            GetAccountStatesResponse(response) => {
+                let account_states_with_proof = response
+                    .account_states_with_proof
+                    .into_iter()
+                    .map(TryFrom::try_from)
+                    .collect::<Result<Vec<_>>>()?;
+
+                ResponseItem::GetAccountStates {
+                    account_states_with_proof,
+                }
+            }
         };
 
         Ok(response)
@@ -730,6 +820,14 @@ impl From<ResponseItem> for crate::proto::types::ResponseItem {
             } => ResponseItems::GetTransactionsResponse(GetTransactionsResponse {
                 txn_list_with_proof: Some(txn_list_with_proof.into()),
             }),
+            ResponseItem::GetAccountStates {
+                account_states_with_proof,
+            } => ResponseItems::GetAccountStatesResponse(GetAccountStatesResponse {
+                account_states_with_proof: account_states_with_proof
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            }),
         };
 
         Self {
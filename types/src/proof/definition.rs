@@ -461,6 +461,12 @@ where
         Self::new(vec![], vec![])
     }
 
+    /// Returns the siblings on the left of the path from the first leaf to the root, near the
+    /// root first.
+    pub fn left_siblings(&self) -> &[HashValue] {
+        &self.left_siblings
+    }
+
     /// Verifies the proof is correct. The verifier needs to have `expected_root_hash`, the index
     /// of the first leaf and all of the leaves in possession.
     pub fn verify(
@@ -1003,6 +1009,11 @@ impl TransactionListProof {
         Self::new(AccumulatorRangeProof::new_empty(), vec![])
     }
 
+    /// Returns the accumulator range proof of the `TransactionInfo` objects.
+    pub fn ledger_info_to_transaction_infos_proof(&self) -> &TransactionAccumulatorRangeProof {
+        &self.ledger_info_to_transaction_infos_proof
+    }
+
     /// Returns the list of `TransactionInfo` objects.
     pub fn transaction_infos(&self) -> &[TransactionInfo] {
         &self.transaction_infos
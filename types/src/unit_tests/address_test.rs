@@ -4,10 +4,19 @@
 use crate::account_address::{AccountAddress, ADDRESS_LENGTH};
 use bech32::Bech32;
 use hex::FromHex;
-use libra_crypto::{hash::CryptoHash, HashValue};
+use libra_crypto::{hash::CryptoHash, HashValue, ShortStr};
 use proptest::prelude::*;
 use std::convert::{AsRef, TryFrom};
 
+#[test]
+fn test_address_short_str() {
+    let mut bytes = [0u8; ADDRESS_LENGTH];
+    bytes[..5].copy_from_slice(&[0xca, 0x84, 0x31, 0x79, 0xa3]);
+    let address = AccountAddress::new(bytes);
+    assert_eq!(address.short_str(), "0xca843179");
+    assert_eq!(address.to_string(), format!("0x{}", hex::encode(&bytes)));
+}
+
 #[test]
 fn test_address_bytes() {
     let hex = Vec::from_hex("ca843279e3427144cead5e4d5999a3d0ccf92b8e124793820837625638742903")
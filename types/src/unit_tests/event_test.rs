@@ -0,0 +1,21 @@
+// Copyright (c) The Libra Core Contributors
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::event::{EventKey, EVENT_KEY_LENGTH};
+use libra_crypto::ShortStr;
+
+#[test]
+fn test_event_key_display_and_from_str() {
+    let mut bytes = [0u8; EVENT_KEY_LENGTH];
+    bytes[..5].copy_from_slice(&[0x01, 0x23, 0x45, 0x67, 0x89]);
+    let key = EventKey::new(bytes);
+    let full = format!("0x{}", hex::encode(&bytes));
+    assert_eq!(key.to_string(), "0x01234567");
+    assert_eq!(key.short_str(), "0x01234567");
+    assert_eq!(format!("{:#}", key), full);
+
+    assert_eq!(full.parse::<EventKey>().unwrap(), key);
+    assert_eq!(full[2..].parse::<EventKey>().unwrap(), key);
+    // the abbreviated form is ambiguous
+    assert!(key.to_string().parse::<EventKey>().is_err());
+}
@@ -7,6 +7,7 @@ mod block_metadata_test;
 mod canonical_serialization_examples;
 mod code_debug_fmt_test;
 mod contract_event_proto_conversion_test;
+mod event_test;
 mod get_with_proof_proto_conversion_test;
 mod identifier_test;
 mod language_storage_test;
@@ -1,8 +1,14 @@
 // Copyright (c) The Libra Core Contributors
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::test_helpers::assert_canonical_encode_decode;
-use crate::write_set::WriteSet;
+use crate::{
+    access_path::AccessPath,
+    account_address::AccountAddress,
+    identifier::Identifier,
+    language_storage::{ModuleId, StructTag},
+    test_helpers::assert_canonical_encode_decode,
+    write_set::{WriteOp, WriteSet, WriteSetBuilder, WriteSetDiffEntry, WriteSetMut},
+};
 use proptest::prelude::*;
 
 proptest! {
@@ -10,4 +16,154 @@ proptest! {
     fn write_set_roundtrip_canonical_serialization(write_set in any::<WriteSet>()) {
         assert_canonical_encode_decode(write_set);
     }
+
+    #[test]
+    fn write_set_diff_with_itself_is_empty(write_set in any::<WriteSet>()) {
+        prop_assert!(write_set.diff(&write_set).is_empty());
+    }
+}
+
+fn test_tag(name: &str) -> StructTag {
+    StructTag {
+        address: AccountAddress::default(),
+        module: Identifier::new("M").unwrap(),
+        name: Identifier::new(name).unwrap(),
+        type_params: vec![],
+    }
+}
+
+fn path(byte: u8) -> AccessPath {
+    AccessPath::new(AccountAddress::new([byte; 32]), vec![byte])
+}
+
+fn write_set(writes: Vec<(AccessPath, WriteOp)>) -> WriteSet {
+    WriteSetMut::new(writes).freeze().unwrap()
+}
+
+#[test]
+fn test_builder_is_order_independent() {
+    let address = AccountAddress::random();
+    let module_id = ModuleId::new(address, Identifier::new("M").unwrap());
+
+    let ws1 = WriteSetBuilder::new()
+        .set_resource(address, &test_tag("A"), vec![1])
+        .delete_resource(address, &test_tag("B"))
+        .set_module(&module_id, vec![2])
+        .build()
+        .unwrap();
+    let ws2 = WriteSetBuilder::new()
+        .set_module(&module_id, vec![2])
+        .delete_resource(address, &test_tag("B"))
+        .set_resource(address, &test_tag("A"), vec![1])
+        .build()
+        .unwrap();
+    assert_eq!(ws1, ws2);
+    assert_eq!(ws1.len(), 3);
+
+    let paths: Vec<_> = ws1.iter().map(|(path, _)| path.clone()).collect();
+    let mut sorted = paths.clone();
+    sorted.sort();
+    assert_eq!(paths, sorted);
+}
+
+#[test]
+fn test_builder_last_write_wins() {
+    let ws = WriteSetBuilder::new()
+        .set(path(1), vec![1])
+        .delete(path(1))
+        .build()
+        .unwrap();
+    assert_eq!(ws.len(), 1);
+    assert_eq!(ws.get(&path(1)), Some(&WriteOp::Deletion));
+}
+
+#[test]
+fn test_get() {
+    let ws = write_set(vec![
+        (path(1), WriteOp::Value(vec![1])),
+        (path(2), WriteOp::Deletion),
+        (path(1), WriteOp::Value(vec![2])),
+    ]);
+    assert_eq!(ws.get(&path(1)), Some(&WriteOp::Value(vec![2])));
+    assert_eq!(ws.get(&path(2)), Some(&WriteOp::Deletion));
+    assert_eq!(ws.get(&path(3)), None);
+}
+
+#[test]
+fn test_iter_by_address() {
+    let address = AccountAddress::new([1; 32]);
+    let other_path = AccessPath::new(address, vec![2]);
+    let ws = write_set(vec![
+        (path(2), WriteOp::Deletion),
+        (path(1), WriteOp::Value(vec![1])),
+        (other_path.clone(), WriteOp::Deletion),
+    ]);
+    let grouped = ws.iter_by_address();
+    assert_eq!(grouped.len(), 2);
+    let paths: Vec<_> = grouped[&address].iter().map(|(path, _)| path).collect();
+    assert_eq!(paths, vec![&path(1), &other_path]);
+}
+
+#[test]
+fn test_diff() {
+    let left = write_set(vec![
+        (path(1), WriteOp::Value(vec![1])),
+        (path(2), WriteOp::Value(vec![2])),
+        (path(3), WriteOp::Deletion),
+        (path(4), WriteOp::Deletion),
+        (path(5), WriteOp::Value(vec![5])),
+    ]);
+    let right = write_set(vec![
+        (path(6), WriteOp::Deletion),
+        (path(5), WriteOp::Value(vec![5])),
+        (path(4), WriteOp::Deletion),
+        (path(2), WriteOp::Value(vec![3])),
+        (path(1), WriteOp::Deletion),
+    ]);
+
+    let diff = left.diff(&right);
+    assert_eq!(
+        diff.entries,
+        vec![
+            WriteSetDiffEntry {
+                access_path: path(1),
+                left: Some(WriteOp::Value(vec![1])),
+                right: Some(WriteOp::Deletion),
+            },
+            WriteSetDiffEntry {
+                access_path: path(2),
+                left: Some(WriteOp::Value(vec![2])),
+                right: Some(WriteOp::Value(vec![3])),
+            },
+            // Deleting a path is not the same as not writing it.
+            WriteSetDiffEntry {
+                access_path: path(3),
+                left: Some(WriteOp::Deletion),
+                right: None,
+            },
+            WriteSetDiffEntry {
+                access_path: path(6),
+                left: None,
+                right: Some(WriteOp::Deletion),
+            },
+        ]
+    );
+
+    let reverse = right.diff(&left);
+    assert_eq!(reverse.len(), diff.len());
+    for (entry, reversed) in diff.iter().zip(reverse.iter()) {
+        assert_eq!(entry.access_path, reversed.access_path);
+        assert_eq!(entry.left, reversed.right);
+        assert_eq!(entry.right, reversed.left);
+    }
+}
+
+#[test]
+fn test_diff_uses_last_write() {
+    let left = write_set(vec![
+        (path(1), WriteOp::Deletion),
+        (path(1), WriteOp::Value(vec![1])),
+    ]);
+    let right = write_set(vec![(path(1), WriteOp::Value(vec![1]))]);
+    assert!(left.diff(&right).is_empty());
 }
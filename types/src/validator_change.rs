@@ -38,11 +38,24 @@ impl ValidatorChangeEventWithProof<Ed25519Signature> {
         epoch: u64,
         validator_verifier: &ValidatorVerifier,
     ) -> Result<LedgerInfoWithSignatures<Ed25519Signature>> {
+        Ok(self.verify_and_ratchet(epoch, validator_verifier)?.0)
+    }
+
+    /// Same as `verify`, but also returns the validator verifier of the epoch started by the
+    /// returned LedgerInfo, so that the next part of a paginated proof can be verified against it.
+    pub fn verify_and_ratchet(
+        &self,
+        epoch: u64,
+        validator_verifier: &ValidatorVerifier,
+    ) -> Result<(
+        LedgerInfoWithSignatures<Ed25519Signature>,
+        ValidatorVerifier,
+    )> {
         ensure!(
             !self.ledger_info_with_sigs.is_empty(),
             "Empty ValidatorChangeEventWithProof"
         );
-        self.ledger_info_with_sigs.iter().try_fold(
+        let (_, next_verifier) = self.ledger_info_with_sigs.iter().try_fold(
             (epoch, validator_verifier.clone()),
             |(epoch, validator_verifier), ledger_info| {
                 ensure!(
@@ -57,7 +70,10 @@ impl ValidatorChangeEventWithProof<Ed25519Signature> {
                     .ok_or_else(|| format_err!("LedgerInfo doesn't carry ValidatorSet"))
             },
         )?;
-        Ok(self.ledger_info_with_sigs.last().unwrap().clone())
+        Ok((
+            self.ledger_info_with_sigs.last().unwrap().clone(),
+            next_verifier,
+        ))
     }
 }
 
@@ -106,6 +122,14 @@ fn verify_validator_set_change_proof() {
     let proof_2 = ValidatorChangeEventWithProof::new(valid_ledger_info[2..5].to_vec());
     assert!(proof_2.verify(all_epoch[2], &validator_verifier[2]).is_ok());
 
+    // Test the proof can be verified piece by piece
+    let (ledger_info, next_verifier) = proof_2
+        .verify_and_ratchet(all_epoch[2], &validator_verifier[2])
+        .unwrap();
+    assert_eq!(ledger_info, valid_ledger_info[4]);
+    let rest = ValidatorChangeEventWithProof::new(valid_ledger_info[5..].to_vec());
+    assert!(rest.verify(all_epoch[5], &next_verifier).is_ok());
+
     // Test empty proof will fail verification
     let proof_3 = ValidatorChangeEventWithProof::new(vec![]);
     assert!(proof_3
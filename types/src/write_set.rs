@@ -4,9 +4,14 @@
 //! For each transaction the VM executes, the VM will output a `WriteSet` that contains each access
 //! path it updates. For each access path, the VM can either give its new value or delete it.
 
-use crate::access_path::AccessPath;
+use crate::{
+    access_path::{AccessPath, Accesses},
+    account_address::AccountAddress,
+    language_storage::{ModuleId, StructTag},
+};
 use failure::prelude::*;
 use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt};
 
 #[derive(Clone, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum WriteOp {
@@ -33,6 +38,15 @@ impl std::fmt::Debug for WriteOp {
     }
 }
 
+impl fmt::Display for WriteOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WriteOp::Value(value) => write!(f, "Value({})", hex::encode(value)),
+            WriteOp::Deletion => write!(f, "Deletion"),
+        }
+    }
+}
+
 /// `WriteSet` contains all access paths that one transaction modifies. Each of them is a `WriteOp`
 /// where `Value(val)` means that serialized representation should be updated to `val`, and
 /// `Deletion` means that we are going to delete this access path.
@@ -59,6 +73,177 @@ impl WriteSet {
     pub fn into_mut(self) -> WriteSetMut {
         self.0
     }
+
+    /// Returns the `WriteOp` this write set applies to `access_path`, or `None` if the path is not
+    /// written at all. If the path is written more than once, the last write wins.
+    pub fn get(&self, access_path: &AccessPath) -> Option<&WriteOp> {
+        self.iter()
+            .rev()
+            .find(|(path, _)| path == access_path)
+            .map(|(_, write_op)| write_op)
+    }
+
+    /// Groups the writes by account address, keeping the original order within each account.
+    pub fn iter_by_address(&self) -> BTreeMap<AccountAddress, Vec<&(AccessPath, WriteOp)>> {
+        let mut grouped: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for write in self {
+            grouped.entry(write.0.address).or_default().push(write);
+        }
+        grouped
+    }
+
+    /// Structurally compares the effective writes of `self` against `other`, ignoring the order
+    /// in which they appear. A `Deletion` and the absence of a write are different.
+    pub fn diff(&self, other: &WriteSet) -> WriteSetDiff {
+        let mut left_writes = self.effective_writes();
+        let mut entries = vec![];
+        for (access_path, right) in other.effective_writes() {
+            let left = left_writes.remove(access_path);
+            if left != Some(right) {
+                entries.push(WriteSetDiffEntry {
+                    access_path: access_path.clone(),
+                    left: left.cloned(),
+                    right: Some(right.clone()),
+                });
+            }
+        }
+        for (access_path, left) in left_writes {
+            entries.push(WriteSetDiffEntry {
+                access_path: access_path.clone(),
+                left: Some(left.clone()),
+                right: None,
+            });
+        }
+        entries.sort_by(|a, b| a.access_path.cmp(&b.access_path));
+        WriteSetDiff { entries }
+    }
+
+    fn effective_writes(&self) -> BTreeMap<&AccessPath, &WriteOp> {
+        self.iter()
+            .map(|(access_path, write_op)| (access_path, write_op))
+            .collect()
+    }
+}
+
+impl fmt::Display for WriteSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (address, writes) in self.iter_by_address() {
+            writeln!(f, "{:x}:", address)?;
+            for (access_path, write_op) in writes {
+                writeln!(f, "  {}: {}", access_path, write_op)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The difference between two write sets, as returned by `WriteSet::diff`. Entries are sorted by
+/// access path.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct WriteSetDiff {
+    pub entries: Vec<WriteSetDiffEntry>,
+}
+
+impl WriteSetDiff {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    pub fn iter(&self) -> ::std::slice::Iter<'_, WriteSetDiffEntry> {
+        self.entries.iter()
+    }
+}
+
+impl fmt::Display for WriteSetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{}", entry)?;
+        }
+        Ok(())
+    }
+}
+
+/// An access path whose effective write differs between the left (`self`) and the right (`other`)
+/// write set of a `WriteSet::diff`. `None` means the side does not write the path at all.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WriteSetDiffEntry {
+    pub access_path: AccessPath,
+    pub left: Option<WriteOp>,
+    pub right: Option<WriteOp>,
+}
+
+impl fmt::Display for WriteSetDiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn fmt_side(write_op: &Option<WriteOp>) -> String {
+            write_op
+                .as_ref()
+                .map_or_else(|| "<absent>".to_string(), WriteOp::to_string)
+        }
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.access_path,
+            fmt_side(&self.left),
+            fmt_side(&self.right)
+        )
+    }
+}
+
+/// Builds a `WriteSet` with at most one write per access path, ordered by access path so that the
+/// result does not depend on the order of the calls. Writing a path again replaces the earlier
+/// write.
+#[derive(Clone, Debug, Default)]
+pub struct WriteSetBuilder {
+    writes: BTreeMap<AccessPath, WriteOp>,
+}
+
+impl WriteSetBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, access_path: AccessPath, value: Vec<u8>) -> Self {
+        self.writes.insert(access_path, WriteOp::Value(value));
+        self
+    }
+
+    pub fn delete(mut self, access_path: AccessPath) -> Self {
+        self.writes.insert(access_path, WriteOp::Deletion);
+        self
+    }
+
+    /// Sets the resource of type `tag` published under `address`.
+    pub fn set_resource(self, address: AccountAddress, tag: &StructTag, value: Vec<u8>) -> Self {
+        self.set(Self::resource_access_path(address, tag), value)
+    }
+
+    /// Deletes the resource of type `tag` published under `address`.
+    pub fn delete_resource(self, address: AccountAddress, tag: &StructTag) -> Self {
+        self.delete(Self::resource_access_path(address, tag))
+    }
+
+    /// Sets the code of module `module_id`.
+    pub fn set_module(self, module_id: &ModuleId, code: Vec<u8>) -> Self {
+        self.set(AccessPath::code_access_path(module_id), code)
+    }
+
+    pub fn build(self) -> Result<WriteSet> {
+        self.writes.into_iter().collect::<WriteSetMut>().freeze()
+    }
+
+    fn resource_access_path(address: AccountAddress, tag: &StructTag) -> AccessPath {
+        AccessPath::new(
+            address,
+            AccessPath::resource_access_vec(tag, &Accesses::empty()),
+        )
+    }
 }
 
 /// A mutable version of `WriteSet`.